@@ -1,10 +1,8 @@
-use ssz::TreeHash;
 use state_processing::per_block_processing::validate_attestation_without_signature;
 use std::collections::{HashMap, HashSet};
+use types::custody;
 use types::*;
 
-const PHASE_0_CUSTODY_BIT: bool = false;
-
 /// Provides the functionality to:
 ///
 ///  - Recieve a `FreeAttestation` and aggregate it into an `Attestation` (or create a new if it
@@ -104,11 +102,8 @@ impl AttestationAggregator {
             invalid_outcome!(Message::BadShard);
         }
 
-        let signable_message = AttestationDataAndCustodyBit {
-            data: free_attestation.data.clone(),
-            custody_bit: PHASE_0_CUSTODY_BIT,
-        }
-        .hash_tree_root();
+        let custody_bit = custody::attester_custody_bit(&free_attestation.data);
+        let signable_message = custody::signing_message(&free_attestation.data, custody_bit);
 
         let validator_record = match state
             .validator_registry
@@ -153,6 +148,14 @@ impl AttestationAggregator {
         }
     }
 
+    /// Returns the attestation aggregated from free attestations with the given `data`, if any.
+    pub fn get_attestation(&self, data: &AttestationData) -> Option<&Attestation> {
+        let custody_bit = custody::attester_custody_bit(data);
+        let signable_message = custody::signing_message(data, custody_bit);
+
+        self.store.get(&signable_message)
+    }
+
     /// Returns all known attestations which are:
     ///
     /// - Valid for the given state
@@ -0,0 +1,189 @@
+use ssz::TreeHash;
+use state_processing::per_block_processing::validate_attestation_without_signature;
+use std::collections::{HashMap, HashSet};
+use types::*;
+
+/// Holds attestations received from the network until they can be included in a block.
+///
+/// Attestations are grouped by their `AttestationData`. An attestation whose participants are all
+/// covered by a stored attestation with the same data is redundant and is not stored.
+#[derive(Default)]
+pub struct AttestationPool {
+    attestations: HashMap<Hash256, Vec<Attestation>>,
+}
+
+impl AttestationPool {
+    /// Adds an attestation to the pool, replacing any stored attestations it covers.
+    ///
+    /// Returns `false` if the attestation is redundant.
+    pub fn insert(&mut self, attestation: Attestation) -> bool {
+        let data_root = Hash256::from_slice(&attestation.data.hash_tree_root()[..]);
+        let stored = self.attestations.entry(data_root).or_insert_with(Vec::new);
+
+        if stored.iter().any(|existing| {
+            covers(
+                &existing.aggregation_bitfield,
+                &attestation.aggregation_bitfield,
+            )
+        }) {
+            return false;
+        }
+
+        stored.retain(|existing| {
+            !covers(
+                &attestation.aggregation_bitfield,
+                &existing.aggregation_bitfield,
+            )
+        });
+        stored.push(attestation);
+        true
+    }
+
+    /// Removes attestations which are too old to be included in a block at or after `slot`.
+    pub fn prune(&mut self, slot: Slot, spec: &ChainSpec) {
+        self.attestations.retain(|_, stored| {
+            stored.first().map_or(false, |attestation| {
+                attestation.data.slot + spec.slots_per_epoch > slot
+            })
+        });
+    }
+
+    /// Returns the attestation with the most participants for each `AttestationData` which is
+    /// valid for inclusion in a block on `state` and not already in `state`.
+    ///
+    /// Attestations are pooled having only been validated for propagation, so the inclusion
+    /// delay, justified checkpoint and crosslink of each are checked here against `state`.
+    pub fn get_attestations_for_state(
+        &self,
+        state: &BeaconState,
+        spec: &ChainSpec,
+    ) -> Vec<Attestation> {
+        let known_attestation_data: HashSet<&AttestationData> = state
+            .previous_epoch_attestations
+            .iter()
+            .chain(state.current_epoch_attestations.iter())
+            .map(|attestation| &attestation.data)
+            .collect();
+
+        self.attestations
+            .values()
+            .filter_map(|stored| {
+                stored
+                    .iter()
+                    .max_by_key(|attestation| attestation.aggregation_bitfield.num_set_bits())
+            })
+            .filter(|attestation| {
+                !known_attestation_data.contains(&attestation.data)
+                    && validate_attestation_without_signature(state, attestation, spec).is_ok()
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the attestation with the most participants for each `AttestationData` with a slot
+    /// at or after `start_slot`, newest first, up to `max` attestations.
+    pub fn get_recent_attestations(&self, start_slot: Slot, max: usize) -> Vec<Attestation> {
+        let mut attestations: Vec<Attestation> = self
+            .attestations
+            .values()
+            .filter_map(|stored| {
+                stored
+                    .iter()
+                    .max_by_key(|attestation| attestation.aggregation_bitfield.num_set_bits())
+            })
+            .filter(|attestation| attestation.data.slot >= start_slot)
+            .cloned()
+            .collect();
+        attestations.sort_by_key(|attestation| std::cmp::Reverse(attestation.data.slot));
+        attestations.truncate(max);
+        attestations
+    }
+
+    /// Returns the number of stored attestations.
+    pub fn len(&self) -> usize {
+        self.attestations.values().map(Vec::len).sum()
+    }
+
+    /// Returns `true` if there are no stored attestations.
+    pub fn is_empty(&self) -> bool {
+        self.attestations.is_empty()
+    }
+}
+
+/// Returns `true` if every bit set in `other` is also set in `bitfield`.
+fn covers(bitfield: &Bitfield, other: &Bitfield) -> bool {
+    (0..other.len())
+        .filter(|i| other.get(*i).unwrap_or(false))
+        .all(|i| bitfield.get(i).unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+
+    fn attestation_with_bits(bits: &[usize]) -> Attestation {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let mut attestation = Attestation::random_for_test(&mut rng);
+        attestation.data.slot = Slot::new(100);
+        attestation.aggregation_bitfield = Bitfield::new();
+        for bit in bits {
+            attestation.aggregation_bitfield.set(*bit, true);
+        }
+        attestation
+    }
+
+    #[test]
+    fn ignores_redundant_attestations() {
+        let mut pool = AttestationPool::default();
+
+        assert!(pool.insert(attestation_with_bits(&[0, 1])));
+        assert!(!pool.insert(attestation_with_bits(&[1])));
+        assert!(!pool.insert(attestation_with_bits(&[0, 1])));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn replaces_covered_attestations() {
+        let mut pool = AttestationPool::default();
+
+        assert!(pool.insert(attestation_with_bits(&[0])));
+        assert!(pool.insert(attestation_with_bits(&[1])));
+        assert_eq!(pool.len(), 2);
+
+        assert!(pool.insert(attestation_with_bits(&[0, 1, 2])));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn prunes_old_attestations() {
+        let spec = ChainSpec::few_validators();
+        let mut pool = AttestationPool::default();
+        pool.insert(attestation_with_bits(&[0]));
+
+        pool.prune(Slot::new(100 + spec.slots_per_epoch - 1), &spec);
+        assert_eq!(pool.len(), 1);
+
+        pool.prune(Slot::new(100 + spec.slots_per_epoch), &spec);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn returns_recent_attestations_newest_first() {
+        let mut pool = AttestationPool::default();
+        for slot in 98..102 {
+            let mut attestation = attestation_with_bits(&[0]);
+            attestation.data.slot = Slot::new(slot);
+            pool.insert(attestation);
+        }
+        pool.insert(attestation_with_bits(&[0, 1]));
+
+        let recent = pool.get_recent_attestations(Slot::new(99), 2);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].data.slot, Slot::new(101));
+        assert_eq!(recent[1].data.slot, Slot::new(100));
+        assert_eq!(recent[1].aggregation_bitfield.num_set_bits(), 2);
+
+        assert_eq!(pool.get_recent_attestations(Slot::new(99), 10).len(), 3);
+    }
+}
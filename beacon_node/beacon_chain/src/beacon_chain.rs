@@ -1,22 +1,61 @@
-use crate::attestation_aggregator::{AttestationAggregator, Outcome as AggregationOutcome};
+use crate::attestation_aggregator::{
+    AttestationAggregator, Message as AggregationMessage, Outcome as AggregationOutcome,
+};
+use crate::attestation_pool::AttestationPool;
 use crate::checkpoint::CheckPoint;
+use crate::checkpoint_sync::{CheckpointSyncError, TrustedCheckpoint};
+use crate::duties_reader::{DutiesReader, ProposerDuties};
+use crate::epoch_boundary_state_cache::EpochBoundaryStateCache;
 use crate::errors::{BeaconChainError as Error, BlockProductionError};
+use crate::future_block_queue::FutureBlockQueue;
+use crate::head_info::HeadInfo;
+use crate::iter::AncestorIter;
+use crate::metrics;
+use crate::slow_epoch::{SlowEpoch, SlowEpochConfig, MAX_SLOW_EPOCH_DUMPS};
+use crate::trace_id::TraceId;
+use crate::validator_pubkey_index::ValidatorPubkeyIndex;
 use db::{
-    stores::{BeaconBlockStore, BeaconStateStore},
+    stores::{BeaconBlockStore, BeaconStateStore, ValidatorStore},
     ClientDB, DBError,
 };
 use fork_choice::{ForkChoice, ForkChoiceError};
-use log::{debug, trace};
-use parking_lot::{RwLock, RwLockReadGuard};
+use log::{debug, info, trace};
+use merkle_proof::IncrementalMerkleTree;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
 use slot_clock::SlotClock;
-use ssz::ssz_encode;
+use ssz::TreeHash;
+use state_processing::per_block_processing::{
+    errors::AttestationValidationError, get_deposit_leaf, validate_attestation_for_gossip,
+};
 use state_processing::{
-    per_block_processing, per_block_processing_without_verifying_block_signature,
-    per_slot_processing, BlockProcessingError, SlotProcessingError,
+    per_block_processing_with_verified_block_signature,
+    per_block_processing_with_verified_deposits,
+    per_block_processing_without_verifying_block_signature, per_slot_processing,
+    per_slot_processing_with_timings, verify_block_signatures, BlockProcessingError,
+    SlotProcessingError, VerifiedDeposits,
 };
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use types::*;
 
+/// The default maximum number of skipped slots between a block and its parent.
+pub const DEFAULT_MAX_SKIP_SLOTS: u64 = 700;
+/// The default percentage of the balance of a slot's committees below which the votes for a late
+/// head block allow a local proposer to re-org it out.
+pub const DEFAULT_PROPOSER_RE_ORG_THRESHOLD: u64 = 20;
+/// The maximum number of epochs through which the state of an attestation's target is advanced
+/// to validate it.
+///
+/// Attestations are received from any peer, so this bounds the epoch processing a single message
+/// may cause.
+pub const MAX_ATTESTATION_STATE_ADVANCE_EPOCHS: u64 = 2;
+
+/// Receives each aggregated attestation as it is updated, returning `false` once it has
+/// unsubscribed. See `BeaconChain::subscribe_attestations`.
+pub type AttestationSubscriber = Box<dyn Fn(&Attestation) -> bool + Send>;
+
 #[derive(Debug, PartialEq)]
 pub enum ValidBlock {
     /// The block was successfully processed.
@@ -31,6 +70,9 @@ pub enum InvalidBlock {
     StateRootMismatch,
     /// The blocks parent_root is unknown.
     ParentUnknown,
+    /// There are more skipped slots between the block and its parent than permitted, each of
+    /// which would require a state transition to be processed.
+    TooManySkippedSlots { parent_slot: Slot, block_slot: Slot },
     /// There was an error whilst advancing the parent state to the present slot. This condition
     /// should not occur, it likely represents an internal error.
     SlotProcessingError(SlotProcessingError),
@@ -42,6 +84,9 @@ pub enum InvalidBlock {
 pub enum BlockProcessingOutcome {
     /// The block was successfully validated.
     ValidBlock(ValidBlock),
+    /// The block is for the slot after the present slot and has been queued until that slot
+    /// starts. See `BeaconChain::process_future_blocks`.
+    QueuedFutureBlock,
     /// The block was not successfully validated.
     InvalidBlock(InvalidBlock),
 }
@@ -51,16 +96,67 @@ pub struct BeaconChain<T: ClientDB + Sized, U: SlotClock, F: ForkChoice> {
     pub state_store: Arc<BeaconStateStore<T>>,
     pub slot_clock: U,
     pub attestation_aggregator: RwLock<AttestationAggregator>,
+    /// Called with each aggregated attestation as it is updated.
+    attestation_subscribers: Mutex<Vec<AttestationSubscriber>>,
+    /// Attestations received from the network, awaiting inclusion in a block.
+    attestation_pool: RwLock<AttestationPool>,
     pub deposits_for_inclusion: RwLock<Vec<Deposit>>,
+    /// Deposits whose proof-of-possession was checked when they were received.
+    verified_deposits: RwLock<VerifiedDeposits>,
+    /// The deposit tree leaves of the deposits of the genesis state and imported blocks, ordered
+    /// by deposit index, as persisted in the block store.
+    deposit_leaves: RwLock<Vec<Hash256>>,
+    /// The registry index of each validator public key, updated as deposits are processed.
+    validator_pubkeys: RwLock<ValidatorPubkeyIndex<T>>,
     pub exits_for_inclusion: RwLock<Vec<VoluntaryExit>>,
     pub transfers_for_inclusion: RwLock<Vec<Transfer>>,
     pub proposer_slashings_for_inclusion: RwLock<Vec<ProposerSlashing>>,
     pub attester_slashings_for_inclusion: RwLock<Vec<AttesterSlashing>>,
+    /// Blocks which arrived shortly before their slot.
+    future_blocks: RwLock<FutureBlockQueue>,
     canonical_head: RwLock<CheckPoint>,
     finalized_head: RwLock<CheckPoint>,
+    /// The values of the head most often read, held only to copy them.
+    head_info: RwLock<HeadInfo>,
+    /// The root and slot of the most recent block to be imported.
+    last_processed_block: RwLock<Option<(Hash256, Slot)>>,
+    /// The root of the most recent block to be imported after its slot had ended.
+    last_late_block: RwLock<Option<Hash256>>,
+    /// The root and slot of the block boosted in fork choice, the first imported in its own slot.
+    proposer_boost: RwLock<Option<(Hash256, Slot)>>,
+    /// The slot of the oldest block held and the root of its parent, unless every block back to
+    /// genesis is held.
+    oldest_block_parent: RwLock<Option<(Slot, Hash256)>>,
+    /// The roots of the canonical blocks at or before finalized epoch start slots, found by
+    /// `block_root_at_slot` beyond the roots held in the head state. They never change, so each
+    /// is found only once.
+    finalized_roots: RwLock<BTreeMap<Slot, Hash256>>,
+    /// The root of the block the chain was started from, under which backfill progress is stored.
+    anchor_block_root: Hash256,
     pub state: RwLock<BeaconState>,
+    /// The duties of the current epoch of `state`, rebuilt when its epoch or shuffling changes.
+    duties: RwLock<Option<Arc<DutiesReader>>>,
+    /// The states at the start of recent epochs, against which attestations on forks are
+    /// validated.
+    epoch_boundary_states: Mutex<EpochBoundaryStateCache>,
     pub spec: ChainSpec,
+    /// The maximum number of skipped slots between an imported block and its parent, if limited.
+    pub max_skip_slots: Option<u64>,
+    /// The percentage of the balance of a slot's committees below which the votes for a late head
+    /// block allow a local proposer to build on its parent instead, if enabled.
+    pub proposer_re_org_threshold: Option<u64>,
+    /// The reporting of epoch transitions which exceed their time budget.
+    pub slow_epoch: SlowEpochConfig,
+    /// The number of pre-states of slow epoch transitions written or being written.
+    slow_epoch_dumps: AtomicUsize,
     pub fork_choice: RwLock<F>,
+    /// The root of the genesis block, used to identify the chain this node is following.
+    pub genesis_block_root: Hash256,
+    /// The `hash_tree_root` of the genesis validator registry.
+    pub genesis_validators_root: Hash256,
+    /// The genesis time of the chain, kept apart from the state so it may be read without waiting
+    /// for blocks being imported.
+    pub genesis_time: u64,
 }
 
 impl<T, U, F> BeaconChain<T, U, F>
@@ -70,55 +166,179 @@ where
     F: ForkChoice,
 {
     /// Instantiate a new Beacon Chain, from genesis.
+    ///
+    /// `genesis_deposits` are the deposits from which the validators of `genesis_state` were
+    /// created, recorded so that the deposit tree may be served, see `finalized_deposit_tree`.
     pub fn from_genesis(
         state_store: Arc<BeaconStateStore<T>>,
         block_store: Arc<BeaconBlockStore<T>>,
+        validator_store: Arc<ValidatorStore<T>>,
         slot_clock: U,
-        mut genesis_state: BeaconState,
+        genesis_state: BeaconState,
         genesis_block: BeaconBlock,
+        genesis_deposits: &[Deposit],
         spec: ChainSpec,
         fork_choice: F,
     ) -> Result<Self, Error> {
-        let state_root = genesis_state.canonical_root();
-        state_store.put(&state_root, &ssz_encode(&genesis_state)[..])?;
+        let genesis_block_root = genesis_block.block_header().canonical_root();
+        let genesis_validators_root =
+            Hash256::from_slice(&genesis_state.validator_registry.hash_tree_root()[..]);
+
+        let chain = Self::from_anchor(
+            state_store,
+            block_store,
+            validator_store,
+            slot_clock,
+            genesis_state,
+            genesis_block,
+            genesis_block_root,
+            genesis_validators_root,
+            spec,
+            fork_choice,
+        )?;
+        chain.record_deposit_leaves(genesis_deposits)?;
+        Ok(chain)
+    }
+
+    /// Instantiate a new Beacon Chain from a trusted finalized checkpoint of the chain starting at
+    /// `genesis_state`, rather than from genesis.
+    ///
+    /// The blocks before the checkpoint are not known, so the chain is synced forwards from it.
+    pub fn from_checkpoint(
+        state_store: Arc<BeaconStateStore<T>>,
+        block_store: Arc<BeaconBlockStore<T>>,
+        validator_store: Arc<ValidatorStore<T>>,
+        slot_clock: U,
+        checkpoint: TrustedCheckpoint,
+        genesis_state: &BeaconState,
+        genesis_block: &BeaconBlock,
+        spec: ChainSpec,
+        fork_choice: F,
+    ) -> Result<Self, CheckpointSyncError> {
+        checkpoint.verify()?;
+        checkpoint.verify_genesis(genesis_state)?;
 
-        let block_root = genesis_block.block_header().canonical_root();
-        block_store.put(&block_root, &ssz_encode(&genesis_block)[..])?;
+        let genesis_block_root = genesis_block.block_header().canonical_root();
+        let genesis_validators_root =
+            Hash256::from_slice(&genesis_state.validator_registry.hash_tree_root()[..]);
+
+        Self::from_anchor(
+            state_store,
+            block_store,
+            validator_store,
+            slot_clock,
+            checkpoint.state,
+            checkpoint.block,
+            genesis_block_root,
+            genesis_validators_root,
+            spec,
+            fork_choice,
+        )
+        .map_err(CheckpointSyncError::BeaconChainError)
+    }
+
+    /// Instantiate a new Beacon Chain with `anchor_block` as its finalized and canonical head.
+    fn from_anchor(
+        state_store: Arc<BeaconStateStore<T>>,
+        block_store: Arc<BeaconBlockStore<T>>,
+        validator_store: Arc<ValidatorStore<T>>,
+        slot_clock: U,
+        mut anchor_state: BeaconState,
+        anchor_block: BeaconBlock,
+        genesis_block_root: Hash256,
+        genesis_validators_root: Hash256,
+        spec: ChainSpec,
+        mut fork_choice: F,
+    ) -> Result<Self, Error> {
+        let state_root = anchor_state.canonical_root();
+        state_store.put_state(&state_root, &anchor_state)?;
+
+        let block_root = anchor_block.block_header().canonical_root();
+        block_store.put_block(&block_root, &anchor_block)?;
+        fork_choice.add_anchor(&anchor_block, &block_root, &anchor_state, &spec)?;
 
         let finalized_head = RwLock::new(CheckPoint::new(
-            genesis_block.clone(),
+            anchor_block.clone(),
             block_root,
-            genesis_state.clone(),
+            anchor_state.clone(),
             state_root,
         ));
         let canonical_head = RwLock::new(CheckPoint::new(
-            genesis_block.clone(),
+            anchor_block.clone(),
             block_root,
-            genesis_state.clone(),
+            anchor_state.clone(),
             state_root,
         ));
+        let head_info = HeadInfo {
+            slot: anchor_block.slot,
+            block_root,
+            finalized_epoch: anchor_state.finalized_epoch,
+            finalized_root: anchor_state.finalized_root,
+            present_slot: anchor_state.slot,
+            fork_version: anchor_state
+                .fork
+                .get_fork_version(anchor_state.current_epoch(&spec)),
+        };
+        let genesis_time = anchor_state.genesis_time;
         let attestation_aggregator = RwLock::new(AttestationAggregator::new());
-
-        genesis_state.build_epoch_cache(RelativeEpoch::Previous, &spec)?;
-        genesis_state.build_epoch_cache(RelativeEpoch::Current, &spec)?;
-        genesis_state.build_epoch_cache(RelativeEpoch::NextWithoutRegistryChange, &spec)?;
-        genesis_state.build_epoch_cache(RelativeEpoch::NextWithRegistryChange, &spec)?;
+        let validator_pubkeys =
+            ValidatorPubkeyIndex::load(validator_store, &anchor_state.validator_registry)?;
+
+        anchor_state.build_epoch_cache(RelativeEpoch::Previous, &spec)?;
+        anchor_state.build_epoch_cache(RelativeEpoch::Current, &spec)?;
+        anchor_state.build_epoch_cache(RelativeEpoch::NextWithoutRegistryChange, &spec)?;
+        anchor_state.build_epoch_cache(RelativeEpoch::NextWithRegistryChange, &spec)?;
+
+        // the blocks before a checkpoint may be backfilled, continuing from those backfilled
+        // before a restart from the same checkpoint
+        let (oldest_slot, oldest_parent) = match block_store.get_oldest_block(&block_root)? {
+            Some(oldest_block) => oldest_block,
+            None => (anchor_block.slot, anchor_block.previous_block_root),
+        };
+        let oldest_block_parent = if oldest_slot == spec.genesis_slot {
+            None
+        } else {
+            Some((oldest_slot, oldest_parent))
+        };
+        let deposit_leaves = block_store.get_deposit_leaves()?;
 
         Ok(Self {
             block_store,
             state_store,
             slot_clock,
             attestation_aggregator,
+            attestation_subscribers: Mutex::new(vec![]),
+            attestation_pool: RwLock::new(AttestationPool::default()),
             deposits_for_inclusion: RwLock::new(vec![]),
+            verified_deposits: RwLock::new(VerifiedDeposits::default()),
+            deposit_leaves: RwLock::new(deposit_leaves),
+            validator_pubkeys: RwLock::new(validator_pubkeys),
             exits_for_inclusion: RwLock::new(vec![]),
             transfers_for_inclusion: RwLock::new(vec![]),
             proposer_slashings_for_inclusion: RwLock::new(vec![]),
             attester_slashings_for_inclusion: RwLock::new(vec![]),
-            state: RwLock::new(genesis_state),
+            future_blocks: RwLock::new(FutureBlockQueue::default()),
+            state: RwLock::new(anchor_state),
+            duties: RwLock::new(None),
+            epoch_boundary_states: Mutex::new(EpochBoundaryStateCache::default()),
             finalized_head,
             canonical_head,
+            head_info: RwLock::new(head_info),
+            last_processed_block: RwLock::new(None),
+            last_late_block: RwLock::new(None),
+            proposer_boost: RwLock::new(None),
+            oldest_block_parent: RwLock::new(oldest_block_parent),
+            finalized_roots: RwLock::new(BTreeMap::new()),
+            anchor_block_root: block_root,
             spec,
+            max_skip_slots: Some(DEFAULT_MAX_SKIP_SLOTS),
+            proposer_re_org_threshold: None,
+            slow_epoch: SlowEpochConfig::default(),
+            slow_epoch_dumps: AtomicUsize::new(0),
             fork_choice: RwLock::new(fork_choice),
+            genesis_block_root,
+            genesis_validators_root,
+            genesis_time,
         })
     }
 
@@ -134,13 +354,58 @@ where
             "Updating canonical head with block at slot: {}",
             new_beacon_block.slot
         );
-        let mut head = self.canonical_head.write();
+        let mut head = match self.canonical_head.try_write() {
+            Some(head) => head,
+            None => {
+                let started = Instant::now();
+                let head = self.canonical_head.write();
+                metrics::record_wait(
+                    &metrics::HEAD_WRITE_WAITS,
+                    &metrics::HEAD_WRITE_WAIT_MICROS,
+                    started,
+                );
+                head
+            }
+        };
         head.update(
             new_beacon_block,
             new_beacon_block_root,
             new_beacon_state,
             new_beacon_state_root,
         );
+        drop(head);
+        self.update_head_info();
+    }
+
+    /// Caches the values of the head most often read. See `head_info`.
+    fn update_head_info(&self) {
+        let (slot, block_root) = {
+            let head = self.head();
+            (head.beacon_block.slot, head.beacon_block_root)
+        };
+        let (finalized_epoch, finalized_root, present_slot, fork_version) = {
+            let state = self.state.read();
+            (
+                state.finalized_epoch,
+                state.finalized_root,
+                state.slot,
+                state.fork.get_fork_version(state.current_epoch(&self.spec)),
+            )
+        };
+        *self.head_info.write() = HeadInfo {
+            slot,
+            block_root,
+            finalized_epoch,
+            finalized_root,
+            present_slot,
+            fork_version,
+        };
+    }
+
+    /// Returns the slot and root of the head block and the finalized checkpoint of the present
+    /// state, without waiting for blocks being imported.
+    pub fn head_info(&self) -> HeadInfo {
+        *self.head_info.read()
     }
 
     /// Returns a read-lock guarded `CheckPoint` struct for reading the head (as chosen by the
@@ -149,8 +414,28 @@ where
     /// It is important to note that the `beacon_state` returned may not match the present slot. It
     /// is the state as it was when the head block was recieved, which could be some slots prior to
     /// now.
+    ///
+    /// Reads which wait for the lock are counted in `metrics::HEAD_READ_WAITS`.
     pub fn head(&self) -> RwLockReadGuard<CheckPoint> {
-        self.canonical_head.read()
+        if let Some(head) = self.canonical_head.try_read() {
+            return head;
+        }
+        let started = Instant::now();
+        let head = self.canonical_head.read();
+        metrics::record_wait(
+            &metrics::HEAD_READ_WAITS,
+            &metrics::HEAD_READ_WAIT_MICROS,
+            started,
+        );
+        head
+    }
+
+    /// Returns the head, or `None` if it is being updated.
+    ///
+    /// Never blocks, so may be used where the caller could already hold the lock, e.g. in a panic
+    /// hook.
+    pub fn try_head(&self) -> Option<RwLockReadGuard<CheckPoint>> {
+        self.canonical_head.try_read()
     }
 
     /// Update the justified head to some new values.
@@ -176,6 +461,188 @@ where
         self.finalized_head.read()
     }
 
+    /// Returns the finalized head, or `None` if it is being updated. Never blocks.
+    pub fn try_finalized_head(&self) -> Option<RwLockReadGuard<CheckPoint>> {
+        self.finalized_head.try_read()
+    }
+
+    /// Returns the root and slot of the most recent block to be imported, if any, or `None` if
+    /// it is being updated. Never blocks.
+    pub fn last_processed_block(&self) -> Option<(Hash256, Slot)> {
+        self.last_processed_block
+            .try_read()
+            .and_then(|last_processed_block| *last_processed_block)
+    }
+
+    /// Returns the slot of the oldest block held and the root of its parent, the next block to be
+    /// backfilled, or `None` if every block back to genesis is held.
+    pub fn oldest_block_parent(&self) -> Option<(Slot, Hash256)> {
+        *self.oldest_block_parent.read()
+    }
+
+    /// Stores historical blocks, in slot order, which precede the oldest block held, as when the
+    /// chain was started from a checkpoint.
+    ///
+    /// The blocks cannot be processed without the states before them, so they are trusted only
+    /// through the hash chain from the oldest block held: each block must be the parent of the
+    /// next, and the last the parent of the oldest block. Returns the number of blocks stored.
+    pub fn import_historical_blocks(&self, blocks: &[BeaconBlock]) -> Result<usize, Error> {
+        let mut oldest_block_parent = self.oldest_block_parent.write();
+        let (mut child_slot, mut expected_root) = match *oldest_block_parent {
+            Some(oldest_block_parent) => oldest_block_parent,
+            None => return Ok(0),
+        };
+
+        let mut roots = Vec::with_capacity(blocks.len());
+        for block in blocks.iter().rev() {
+            let block_root = block.canonical_root();
+            if block_root != expected_root || block.slot >= child_slot {
+                return Err(Error::HistoricalBlockNotLinked { slot: block.slot });
+            }
+            roots.push(block_root);
+            child_slot = block.slot;
+            expected_root = block.previous_block_root;
+        }
+
+        for (block, block_root) in blocks.iter().rev().zip(roots) {
+            self.block_store.put_block(&block_root, block)?;
+        }
+
+        if let Some(oldest) = blocks.first() {
+            self.block_store.put_oldest_block(
+                &self.anchor_block_root,
+                oldest.slot,
+                &oldest.previous_block_root,
+            )?;
+            *oldest_block_parent = if oldest.slot == self.spec.genesis_slot {
+                None
+            } else {
+                Some((oldest.slot, oldest.previous_block_root))
+            };
+        }
+        Ok(blocks.len())
+    }
+
+    /// Returns an iterator over the roots and slots of the block with `block_root` and each of its
+    /// ancestors, newest first.
+    pub fn ancestor_iter(&self, block_root: Hash256) -> AncestorIter<T> {
+        AncestorIter::new(self.block_store.clone(), block_root)
+    }
+
+    /// Returns the roots and slots of the canonical blocks with a slot in
+    /// `[start_slot, start_slot + count)`, ordered by slot.
+    ///
+    /// Skipped slots are omitted, so fewer than `count` roots may be returned.
+    ///
+    /// The blocks of the range are found with the slot index, and only the canonical blocks
+    /// among them are read, so the cost is bounded by the range rather than its distance from the
+    /// head.
+    pub fn get_block_roots(&self, start_slot: Slot, count: u64) -> Vec<(Hash256, Slot)> {
+        let head_slot = self.head().beacon_block.slot;
+        if count == 0 || start_slot > head_slot {
+            return vec![];
+        }
+        let count = std::cmp::min(count, (head_slot - start_slot).as_u64() + 1);
+
+        let indexed = match self.block_store.get_block_roots_by_range(start_slot, count) {
+            Ok(indexed) => indexed,
+            Err(_) => return vec![],
+        };
+        // the canonical block at or before the end of the range, which the blocks of other forks
+        // indexed in the range are not ancestors of
+        let mut expected_root = match self.block_root_at_slot(start_slot + count - 1) {
+            Some(root) => root,
+            None => return vec![],
+        };
+
+        let mut roots = vec![];
+        for (root, slot) in indexed.into_iter().rev() {
+            if root != expected_root {
+                continue;
+            }
+            expected_root = match self.block_store.get_deserialized(&root) {
+                Ok(Some(block)) => block.previous_block_root,
+                _ => break,
+            };
+            roots.push((root, slot));
+        }
+        roots.reverse();
+        roots
+    }
+
+    /// Returns the root of the canonical block at `slot`, or of the latest canonical block before
+    /// it if the slot was skipped.
+    ///
+    /// Returns `None` if `slot` is after the head, or if the block is older than both the roots
+    /// held in the head state and the oldest block held.
+    ///
+    /// Beyond the roots held in the head state, a finalized slot is found by walking back from
+    /// the nearest finalized epoch start slot found before, or the finalized block, recording the
+    /// roots at the epoch start slots passed. Each finalized block is so read at most once.
+    pub fn block_root_at_slot(&self, slot: Slot) -> Option<Hash256> {
+        let head_root = {
+            let head = self.head();
+            if slot > head.beacon_block.slot {
+                return None;
+            }
+            if slot == head.beacon_block.slot {
+                return Some(head.beacon_block_root);
+            }
+            if let Ok(root) = head.beacon_state.get_block_root(slot, &self.spec) {
+                return Some(*root);
+            }
+            head.beacon_block_root
+        };
+
+        let (finalized_root, finalized_slot) = {
+            let finalized_head = self.finalized_head();
+            (
+                finalized_head.beacon_block_root,
+                finalized_head.beacon_block.slot,
+            )
+        };
+        if slot > finalized_slot {
+            // the chain has not finalized for longer than the roots held in the head state
+            return self
+                .ancestor_iter(head_root)
+                .find(|(_, ancestor_slot)| *ancestor_slot <= slot)
+                .map(|(root, _)| root);
+        }
+
+        // walk back from the nearest known root at or after the slot, whose own slot may be
+        // earlier than the epoch start slot it was recorded at
+        let (start_root, mut later_slot) = match self.finalized_roots.read().range(slot..).next() {
+            Some((&epoch_slot, &root)) if epoch_slot == slot => return Some(root),
+            Some((&epoch_slot, &root)) => (root, epoch_slot + 1),
+            None => (finalized_root, finalized_slot + 1),
+        };
+
+        let slots_per_epoch = self.spec.slots_per_epoch;
+        let mut found = vec![];
+        let mut result = None;
+        for (root, ancestor_slot) in self.ancestor_iter(start_root) {
+            // the block is canonical at each slot up to that of the block after it
+            let mut epoch_slot = ancestor_slot
+                .epoch(slots_per_epoch)
+                .start_slot(slots_per_epoch);
+            if epoch_slot < ancestor_slot {
+                epoch_slot += slots_per_epoch;
+            }
+            while epoch_slot < later_slot {
+                found.push((epoch_slot, root));
+                epoch_slot += slots_per_epoch;
+            }
+            if ancestor_slot <= slot {
+                result = Some(root);
+                break;
+            }
+            later_slot = ancestor_slot;
+        }
+
+        self.finalized_roots.write().extend(found);
+        result
+    }
+
     /// Advance the `self.state` `BeaconState` to the supplied slot.
     ///
     /// This will perform per_slot and per_epoch processing as required.
@@ -192,28 +659,68 @@ where
         let latest_block_header = self.head().beacon_block.block_header();
 
         for _ in state_slot.as_u64()..slot.as_u64() {
-            per_slot_processing(&mut *self.state.write(), &latest_block_header, &self.spec)?;
+            let slow_epoch = self.process_slot(&mut *self.state.write(), &latest_block_header)?;
+            // reported once the state is unlocked, as the report may write to disk
+            if let Some(slow_epoch) = slow_epoch {
+                slow_epoch.report(&self.slow_epoch);
+            }
         }
+        // epoch processing may have advanced the finalized checkpoint
+        self.update_head_info();
+        // no attestation may target an epoch before the previous epoch
+        self.epoch_boundary_states
+            .lock()
+            .prune(slot.epoch(self.spec.slots_per_epoch));
 
         Ok(())
     }
 
+    /// Advances `state` of the canonical chain by one slot with `per_slot_processing`, returning
+    /// the epoch transition, if performed, when it exceeds the budget of `self.slow_epoch`.
+    ///
+    /// Only the transitions of the canonical chain are measured, as those of forks and
+    /// attestations are driven by our peers.
+    fn process_slot(
+        &self,
+        state: &mut BeaconState,
+        latest_block_header: &BeaconBlockHeader,
+    ) -> Result<Option<SlowEpoch>, SlotProcessingError> {
+        let dump = self.slow_epoch.dump_dir.is_some()
+            && (state.slot + 1) % self.spec.slots_per_epoch == 0
+            && self.slow_epoch_dumps.load(Ordering::Relaxed) < MAX_SLOW_EPOCH_DUMPS;
+        let pre_state = if dump { Some(state.clone()) } else { None };
+
+        match per_slot_processing_with_timings(state, latest_block_header, &self.spec)? {
+            Some(timings) if timings.total() > self.slow_epoch.budget => {
+                if pre_state.is_some() {
+                    self.slow_epoch_dumps.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(Some(SlowEpoch::new(timings, state, pre_state, &self.spec)))
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Returns the validator index (if any) for the given public key.
     ///
     /// Information is retrieved from the present `beacon_state.validator_registry`.
     pub fn validator_index(&self, pubkey: &PublicKey) -> Option<usize> {
-        for (i, validator) in self
-            .head()
-            .beacon_state
-            .validator_registry
-            .iter()
-            .enumerate()
-        {
-            if validator.pubkey == *pubkey {
-                return Some(i);
-            }
-        }
-        None
+        self.validator_index_in_registry(&self.head().beacon_state.validator_registry, pubkey)
+    }
+
+    /// Returns the index (if any) of the given public key in `registry`, which must be the
+    /// registry of some state of this chain.
+    ///
+    /// Unlike `validator_index`, does not lock the head, so may be called while it is held.
+    pub fn validator_index_in_registry(
+        &self,
+        registry: &[Validator],
+        pubkey: &PublicKey,
+    ) -> Option<usize> {
+        self.validator_pubkeys
+            .read()
+            .get(pubkey)
+            .filter(|&index| registry.get(index).map_or(false, |v| v.pubkey == *pubkey))
     }
 
     /// Reads the slot clock, returns `None` if the slot is unavailable.
@@ -244,22 +751,18 @@ where
     /// Returns the block proposer for a given slot.
     ///
     /// Information is read from the present `beacon_state` shuffling, so only information from the
-    /// present and prior epoch is available.
+    /// present epoch is available.
     pub fn block_proposer(&self, slot: Slot) -> Result<usize, BeaconStateError> {
         trace!("BeaconChain::block_proposer: slot: {}", slot);
-        let index = self.state.read().get_beacon_proposer_index(
-            slot,
-            RelativeEpoch::Current,
-            &self.spec,
-        )?;
-
-        Ok(index)
+        self.duties_reader()?
+            .block_proposer(slot)
+            .ok_or(BeaconStateError::SlotOutOfBounds)
     }
 
     /// Returns the attestation slot and shard for a given validator index.
     ///
-    /// Information is read from the current state, so only information from the present and prior
-    /// epoch is available.
+    /// Information is read from the current state, so only information from the present epoch is
+    /// available.
     pub fn validator_attestion_slot_and_shard(
         &self,
         validator_index: usize,
@@ -268,15 +771,40 @@ where
             "BeaconChain::validator_attestion_slot_and_shard: validator_index: {}",
             validator_index
         );
-        if let Some(attestation_duty) = self
-            .state
-            .read()
-            .get_attestation_duties(validator_index, &self.spec)?
-        {
-            Ok(Some((attestation_duty.slot, attestation_duty.shard)))
-        } else {
-            Ok(None)
+        Ok(self
+            .duties_reader()?
+            .attestation_duty(validator_index)
+            .map(|duty| (duty.slot, duty.shard)))
+    }
+
+    /// Returns the duties of the current epoch of the present `beacon_state`.
+    ///
+    /// The duties are read from the state once per epoch, so they may be queried concurrently
+    /// without holding a lock on the state.
+    pub fn duties_reader(&self) -> Result<Arc<DutiesReader>, BeaconStateError> {
+        let state = self.state.read();
+
+        if let Some(duties) = self.duties.read().as_ref() {
+            if duties.is_current_for(&state, &self.spec) {
+                return Ok(duties.clone());
+            }
         }
+
+        let duties = Arc::new(DutiesReader::from_state(&state, &self.spec)?);
+        *self.duties.write() = Some(duties.clone());
+
+        Ok(duties)
+    }
+
+    /// Returns the proposers of `epoch`, which must be the previous or current epoch of the head
+    /// state.
+    pub fn proposer_duties(&self, epoch: Epoch) -> Result<ProposerDuties, BeaconStateError> {
+        ProposerDuties::from_state(
+            &self.state.read(),
+            epoch,
+            self.genesis_block_root,
+            &self.spec,
+        )
     }
 
     /// Produce an `AttestationData` that is valid for the present `slot` and given `shard`.
@@ -330,6 +858,13 @@ where
             return Ok(aggregation_outcome);
         }
 
+        match aggregation_outcome.message {
+            AggregationMessage::Aggregated | AggregationMessage::NewAttestationCreated => {
+                self.notify_attestation_subscribers(&free_attestation.data)
+            }
+            _ => {}
+        }
+
         // valid attestation, proceed with fork-choice logic
         self.fork_choice.write().add_attestation(
             free_attestation.validator_index,
@@ -339,11 +874,175 @@ where
         Ok(aggregation_outcome)
     }
 
+    /// Validates an `Attestation` received from the network and adds it to the attestation pool,
+    /// for inclusion in a produced block.
+    ///
+    /// An attestation on the current chain is validated against the current state. One whose
+    /// target is on a fork is validated against the state of its target, advanced to the present
+    /// slot, if the target block is known. Only the conditions for propagating it are checked,
+    /// see `validate_attestation_for_gossip`, as it cannot yet be included in a block; the pool
+    /// checks the rest when producing one, see `AttestationPool::get_attestations_for_state`.
+    ///
+    /// Returns `Ok(false)` if the attestation is valid but adds no participants to those already
+    /// in the pool.
+    pub fn process_attestation(
+        &self,
+        attestation: Attestation,
+    ) -> Result<bool, AttestationValidationError> {
+        let state = self.state.read();
+        match self.fork_state_for_attestation(&state, &attestation.data) {
+            Some(fork_state) => {
+                validate_attestation_for_gossip(&fork_state, &attestation, &self.spec)?
+            }
+            None => validate_attestation_for_gossip(&state, &attestation, &self.spec)?,
+        }
+
+        let mut pool = self.attestation_pool.write();
+        pool.prune(state.slot, &self.spec);
+        Ok(pool.insert(attestation))
+    }
+
+    /// Returns the aggregate attestations of the attestation pool with a slot at or after
+    /// `start_slot`, newest first, up to `max` attestations, so that peers which have just synced
+    /// may include them in blocks.
+    pub fn get_pool_attestations(&self, start_slot: Slot, max: usize) -> Vec<Attestation> {
+        self.attestation_pool
+            .read()
+            .get_recent_attestations(start_slot, max)
+    }
+
+    /// Returns the state of the target of `data` advanced to the slot of `state`, if the target
+    /// is known and is not on the chain of `state`.
+    ///
+    /// Only attestations of the current or previous epoch of `state` may be included in a block,
+    /// so no state is built for any other.
+    fn fork_state_for_attestation(
+        &self,
+        state: &BeaconState,
+        data: &AttestationData,
+    ) -> Option<BeaconState> {
+        let epoch = data.slot.epoch(self.spec.slots_per_epoch);
+        if epoch != state.current_epoch(&self.spec) && epoch != state.previous_epoch(&self.spec) {
+            return None;
+        }
+        let start_slot = epoch.start_slot(self.spec.slots_per_epoch);
+        if state.slot > start_slot
+            && state.get_block_root(start_slot, &self.spec).ok() == Some(&data.target_root)
+        {
+            return None;
+        }
+
+        let mut fork_state = (*self.epoch_boundary_state(data.target_root, epoch).ok()?).clone();
+        let header = self
+            .block_store
+            .get_deserialized(&data.target_root)
+            .ok()??
+            .block_header();
+        for _ in fork_state.slot.as_u64()..state.slot.as_u64() {
+            per_slot_processing(&mut fork_state, &header, &self.spec).ok()?;
+        }
+        Some(fork_state)
+    }
+
+    /// Returns the state at the start of `epoch` on the chain of `boundary_root`, the root of the
+    /// latest block at or before the first slot of `epoch`, with the committees of `epoch`
+    /// built.
+    ///
+    /// Each attestation of an epoch targets its boundary block, so states are cached and reused
+    /// for the other attestations of the epoch on that chain.
+    ///
+    /// Returns an error if the state of the boundary block is more than
+    /// `MAX_ATTESTATION_STATE_ADVANCE_EPOCHS` epochs, or more than the `max_skip_slots` a block
+    /// may skip, before the start of `epoch`.
+    pub fn epoch_boundary_state(
+        &self,
+        boundary_root: Hash256,
+        epoch: Epoch,
+    ) -> Result<Arc<BeaconState>, Error> {
+        if let Some(state) = self.epoch_boundary_states.lock().get(boundary_root, epoch) {
+            return Ok(state);
+        }
+
+        let block = self
+            .block_store
+            .get_deserialized(&boundary_root)?
+            .ok_or_else(|| Error::MissingBeaconBlock(boundary_root))?;
+        let start_slot = epoch.start_slot(self.spec.slots_per_epoch);
+        if block.slot > start_slot {
+            return Err(Error::NotEpochBoundary {
+                block_root: boundary_root,
+                epoch,
+            });
+        }
+        let mut max_advance = MAX_ATTESTATION_STATE_ADVANCE_EPOCHS * self.spec.slots_per_epoch;
+        // an attestation may not cost more slot processing than a block
+        if let Some(max_skip_slots) = self.max_skip_slots {
+            max_advance = std::cmp::min(max_advance, max_skip_slots);
+        }
+        if block.slot + max_advance < start_slot {
+            return Err(Error::StateAdvanceTooFar {
+                from: block.slot,
+                to: start_slot,
+            });
+        }
+        let mut state = self
+            .state_store
+            .get_deserialized(&block.state_root)?
+            .ok_or_else(|| Error::MissingBeaconState(block.state_root))?;
+
+        let header = block.block_header();
+        for _ in state.slot.as_u64()..start_slot.as_u64() {
+            per_slot_processing(&mut state, &header, &self.spec)?;
+        }
+        state.build_epoch_cache(RelativeEpoch::Current, &self.spec)?;
+
+        let state = Arc::new(state);
+        self.epoch_boundary_states
+            .lock()
+            .insert(boundary_root, epoch, state.clone());
+        Ok(state)
+    }
+
+    /// Calls `subscriber` with each aggregated attestation whenever a free attestation is added
+    /// to it, until it returns `false`.
+    ///
+    /// The subscriber is called whilst the attestation is being processed, so must not block.
+    pub fn subscribe_attestations(&self, subscriber: AttestationSubscriber) {
+        self.attestation_subscribers.lock().push(subscriber);
+    }
+
+    /// Sends the aggregated attestation with the given `data` to all subscribers, dropping those
+    /// which have unsubscribed.
+    fn notify_attestation_subscribers(&self, data: &AttestationData) {
+        let mut subscribers = self.attestation_subscribers.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        if let Some(attestation) = self.attestation_aggregator.read().get_attestation(data) {
+            subscribers.retain(|subscriber| subscriber(attestation));
+        }
+    }
+
     /// Accept some deposit and queue it for inclusion in an appropriate block.
     pub fn receive_deposit_for_inclusion(&self, deposit: Deposit) {
         // TODO: deposits are not checked for validity; check them.
         //
         // https://github.com/sigp/lighthouse/issues/276
+        //
+        // The proof-of-possession is checked here so that it need not be checked again when a
+        // block containing the deposit is processed.
+        {
+            let state = self.state.read();
+            let epoch = state.slot.epoch(self.spec.slots_per_epoch);
+            if !self
+                .verified_deposits
+                .write()
+                .verify(&deposit, epoch, &state.fork, &self.spec)
+            {
+                debug!("Received deposit with invalid proof-of-possession");
+            }
+        }
         self.deposits_for_inclusion.write().push(deposit);
     }
 
@@ -377,6 +1076,48 @@ where
         for i in indices_to_delete {
             deposits_for_inclusion.remove(i);
         }
+
+        let verified_deposits = &mut self.verified_deposits.write();
+        for included in included_deposits {
+            verified_deposits.remove(included);
+        }
+    }
+
+    /// Records and persists the deposit tree leaves of the genesis deposits or of deposits
+    /// included in an imported block.
+    ///
+    /// Deposits are recorded in index order. Deposits which are already recorded, or which do not
+    /// follow the last recorded deposit, are ignored.
+    fn record_deposit_leaves(&self, deposits: &[Deposit]) -> Result<(), Error> {
+        let mut leaves = self.deposit_leaves.write();
+        for deposit in deposits {
+            if deposit.index == leaves.len() as u64 {
+                let leaf = get_deposit_leaf(deposit);
+                self.block_store.put_deposit_leaf(deposit.index, &leaf)?;
+                leaves.push(leaf);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the deposit tree containing the deposits of the finalized state, from which a
+    /// snapshot can be served to peers so they can validate later deposits without replaying the
+    /// eth1 deposit logs.
+    ///
+    /// Returns `None` if any of those deposits are unknown, e.g. because the chain was started
+    /// from a checkpoint after them.
+    pub fn finalized_deposit_tree(&self) -> Option<IncrementalMerkleTree> {
+        let deposit_count = self.finalized_head().beacon_state.deposit_index as usize;
+        let leaves = self.deposit_leaves.read();
+        if leaves.len() < deposit_count {
+            return None;
+        }
+
+        let mut tree = IncrementalMerkleTree::new(self.spec.deposit_contract_tree_depth as usize);
+        for leaf in &leaves[..deposit_count] {
+            tree.push(*leaf);
+        }
+        Some(tree)
     }
 
     /// Accept some exit and queue it for inclusion in an appropriate block.
@@ -557,15 +1298,170 @@ where
 
     /// Accept some block and attempt to add it to block DAG.
     ///
-    /// Will accept blocks from prior slots, however it will reject any block from a future slot.
+    /// Will accept blocks from prior slots. A block from the slot after the present slot is
+    /// queued until its slot starts, any block further in the future is rejected.
     pub fn process_block(&self, block: BeaconBlock) -> Result<BlockProcessingOutcome, Error> {
-        debug!("Processing block with slot {}...", block.slot);
+        self.process_block_with_trace_id(block, TraceId::next())
+    }
+
+    /// As `process_block`, including `trace_id` in the logs so the block can be followed from
+    /// the subsystem which received it.
+    pub fn process_block_with_trace_id(
+        &self,
+        block: BeaconBlock,
+        trace_id: TraceId,
+    ) -> Result<BlockProcessingOutcome, Error> {
+        debug!(
+            "Processing block with slot {}, trace_id: {}",
+            block.slot, trace_id
+        );
+
+        let outcome = self.apply_block(block, true);
+
+        debug!(
+            "Processed block, trace_id: {}, outcome: {:?}",
+            trace_id, outcome
+        );
+        outcome
+    }
+
+    /// Imports a segment of blocks, each the parent of the next, such as a batch downloaded by
+    /// sync.
+    ///
+    /// The proposer signatures of the blocks of each epoch are verified together across the rayon
+    /// pool, then the blocks are imported one at a time without verifying them again. If any
+    /// signature of an epoch is invalid, or its blocks are not each the parent of the next, its
+    /// blocks are imported verifying each signature, so the invalid block is identified.
+    ///
+    /// Only the proposer signature is verified ahead of import. The other signatures of each block,
+    /// its RANDAO reveal and those of its attestations, slashings, deposits, exits and transfers,
+    /// are verified as the block is imported, as by `process_block`.
+    ///
+    /// Returns the outcome of each block processed, in order, stopping after the first block
+    /// which is not imported.
+    pub fn process_chain_segment(
+        &self,
+        blocks: Vec<BeaconBlock>,
+        trace_id: TraceId,
+    ) -> Vec<Result<BlockProcessingOutcome, Error>> {
+        debug!(
+            "Processing chain segment of {} blocks, trace_id: {}",
+            blocks.len(),
+            trace_id
+        );
+
+        let mut outcomes = Vec::with_capacity(blocks.len());
+        let mut blocks = blocks.into_iter().peekable();
+        while let Some(epoch) = blocks
+            .peek()
+            .map(|block| block.slot.epoch(self.spec.slots_per_epoch))
+        {
+            let mut chunk = vec![];
+            while let Some(block) = blocks.peek() {
+                if block.slot.epoch(self.spec.slots_per_epoch) != epoch {
+                    break;
+                }
+                chunk.extend(blocks.next());
+            }
+
+            let verified = self.verify_chunk_signatures(&chunk);
+            for block in chunk {
+                let outcome = self.apply_block(block, !verified);
+                let imported = match outcome {
+                    Ok(BlockProcessingOutcome::ValidBlock(_)) => true,
+                    _ => false,
+                };
+                outcomes.push(outcome);
+                if !imported {
+                    debug!(
+                        "Chain segment stopped, trace_id: {}, outcome: {:?}",
+                        trace_id,
+                        outcomes.last()
+                    );
+                    return outcomes;
+                }
+            }
+        }
+
+        debug!("Processed chain segment, trace_id: {}", trace_id);
+        outcomes
+    }
 
+    /// Returns `true` if the proposer signatures of `chunk`, blocks of a single epoch each the
+    /// parent of the next, are all valid.
+    ///
+    /// The proposers are read from the parent state of the first block, transitioned to its slot,
+    /// which are only those of the later blocks if each block is the parent of the next. Returns
+    /// `false` if the blocks are not linked or that state cannot be produced, leaving
+    /// `apply_block` to verify or reject each block.
+    fn verify_chunk_signatures(&self, chunk: &[BeaconBlock]) -> bool {
+        let first = match chunk.first() {
+            Some(first) => first,
+            None => return true,
+        };
+        let linked = chunk
+            .windows(2)
+            .all(|pair| pair[1].previous_block_root == pair[0].block_header().canonical_root());
+        if !linked {
+            return false;
+        }
+        if first.slot > self.present_slot() {
+            return false;
+        }
+        let parent_block = match self
+            .block_store
+            .get_deserialized(&first.previous_block_root)
+        {
+            Ok(Some(parent_block)) => parent_block,
+            _ => return false,
+        };
+        // the skipped slots are limited as by `apply_block`, before any are processed
+        if let Some(max_skip_slots) = self.max_skip_slots {
+            if first.slot.as_u64() > parent_block.slot.as_u64() + 1 + max_skip_slots {
+                return false;
+            }
+        }
+        let mut state = match self.state_store.get_deserialized(&parent_block.state_root) {
+            Ok(Some(state)) => state,
+            _ => return false,
+        };
+
+        let previous_block_header = parent_block.block_header();
+        for _ in state.slot.as_u64()..first.slot.as_u64() {
+            if per_slot_processing(&mut state, &previous_block_header, &self.spec).is_err() {
+                return false;
+            }
+        }
+        if state
+            .build_epoch_cache(RelativeEpoch::Current, &self.spec)
+            .is_err()
+        {
+            return false;
+        }
+
+        verify_block_signatures(&state, chunk, &self.spec).is_ok()
+    }
+
+    /// Validates a block and, if valid, stores it and updates the head.
+    ///
+    /// The proposer signature is only verified if `verify_signature`, having otherwise been
+    /// verified already.
+    fn apply_block(
+        &self,
+        block: BeaconBlock,
+        verify_signature: bool,
+    ) -> Result<BlockProcessingOutcome, Error> {
         let block_root = block.block_header().canonical_root();
 
         let present_slot = self.present_slot();
 
         if block.slot > present_slot {
+            if FutureBlockQueue::is_queueable(block.slot, present_slot)
+                && self.future_blocks.write().insert(block_root, block)
+            {
+                debug!("Queued block for future slot.");
+                return Ok(BlockProcessingOutcome::QueuedFutureBlock);
+            }
             return Ok(BlockProcessingOutcome::InvalidBlock(
                 InvalidBlock::FutureSlot,
             ));
@@ -583,6 +1479,23 @@ where
             }
         };
 
+        // Refuse to process more skipped slots than permitted, so that a block claiming a slot far
+        // beyond its parent cannot cheaply exhaust the CPU.
+        if let Some(max_skip_slots) = self.max_skip_slots {
+            let skipped_slots = block
+                .slot
+                .as_u64()
+                .saturating_sub(parent_block.slot.as_u64() + 1);
+            if skipped_slots > max_skip_slots {
+                return Ok(BlockProcessingOutcome::InvalidBlock(
+                    InvalidBlock::TooManySkippedSlots {
+                        parent_slot: parent_block.slot,
+                        block_slot: block.slot,
+                    },
+                ));
+            }
+        }
+
         // Load the parent blocks state from the database, returning an error if it is not found.
         // It is an error because if know the parent block we should also know the parent state.
         let parent_state_root = parent_block.state_root;
@@ -594,20 +1507,41 @@ where
         // TODO: check the block proposer signature BEFORE doing a state transition. This will
         // significantly lower exposure surface to DoS attacks.
 
-        // Transition the parent state to the present slot.
+        // Transition the parent state to the slot of the block.
         let mut state = parent_state;
         let previous_block_header = parent_block.block_header();
-        for _ in state.slot.as_u64()..present_slot.as_u64() {
-            if let Err(e) = per_slot_processing(&mut state, &previous_block_header, &self.spec) {
-                return Ok(BlockProcessingOutcome::InvalidBlock(
-                    InvalidBlock::SlotProcessingError(e),
-                ));
+        for _ in state.slot.as_u64()..block.slot.as_u64() {
+            match self.process_slot(&mut state, &previous_block_header) {
+                Ok(Some(slow_epoch)) => slow_epoch.report(&self.slow_epoch),
+                Ok(None) => {}
+                Err(e) => {
+                    return Ok(BlockProcessingOutcome::InvalidBlock(
+                        InvalidBlock::SlotProcessingError(e),
+                    ))
+                }
             }
         }
 
         // Apply the received block to its parent state (which has been transitioned into this
         // slot).
-        if let Err(e) = per_block_processing(&mut state, &block, &self.spec) {
+        let verified_deposits = self.verified_deposits.read();
+        let processed = if verify_signature {
+            per_block_processing_with_verified_deposits(
+                &mut state,
+                &block,
+                &verified_deposits,
+                &self.spec,
+            )
+        } else {
+            per_block_processing_with_verified_block_signature(
+                &mut state,
+                &block,
+                &verified_deposits,
+                &self.spec,
+            )
+        };
+        drop(verified_deposits);
+        if let Err(e) = processed {
             return Ok(BlockProcessingOutcome::InvalidBlock(
                 InvalidBlock::PerBlockProcessingError(e),
             ));
@@ -622,8 +1556,18 @@ where
         }
 
         // Store the block and state.
-        self.block_store.put(&block_root, &ssz_encode(&block)[..])?;
-        self.state_store.put(&state_root, &ssz_encode(&state)[..])?;
+        self.block_store.put_block(&block_root, &block)?;
+        self.state_store.put_state(&state_root, &state)?;
+        *self.last_processed_block.write() = Some((block_root, block.slot));
+        if block.slot < present_slot {
+            *self.last_late_block.write() = Some(block_root);
+        }
+        let boosted = self.boost_if_timely(block_root, block.slot, present_slot);
+
+        self.record_deposit_leaves(&block.body.deposits)?;
+        self.validator_pubkeys
+            .write()
+            .update(&state.validator_registry)?;
 
         // Update the inclusion queues so they aren't re-submitted.
         self.set_deposits_as_included(&block.body.deposits[..]);
@@ -632,10 +1576,15 @@ where
         self.set_proposer_slashings_as_included(&block.body.proposer_slashings[..]);
         self.set_attester_slashings_as_included(&block.body.attester_slashings[..]);
 
-        // run the fork_choice add_block logic
+        // run the fork_choice add_block logic, before the block may be boosted
         self.fork_choice
             .write()
             .add_block(&block, &block_root, &self.spec)?;
+        if boosted {
+            self.fork_choice
+                .write()
+                .set_proposer_boost(Some(block_root));
+        }
 
         // If the parent block was the parent_block, automatically update the canonical head.
         //
@@ -643,13 +1592,31 @@ where
         // run instead.
         if self.head().beacon_block_root == parent_block_root {
             self.update_canonical_head(block.clone(), block_root, state.clone(), state_root);
-            // Update the local state variable.
+            // Update the local state variable, which may be from a prior slot if the block was
+            // delayed.
             *self.state.write() = state;
+            self.advance_state(present_slot)?;
+        } else if boosted {
+            // a timely block on a fork, such as one built on the parent of a late head, replaces
+            // the head if the boost outweighs the votes for it
+            self.fork_choice()?;
         }
 
         Ok(BlockProcessingOutcome::ValidBlock(ValidBlock::Processed))
     }
 
+    /// Processes any queued future blocks whose slot has started, returning the outcome for each.
+    ///
+    /// Should be called at the start of each slot.
+    pub fn process_future_blocks(&self) -> Vec<Result<BlockProcessingOutcome, Error>> {
+        let ready = self.future_blocks.write().drain_ready(self.present_slot());
+
+        ready
+            .into_iter()
+            .map(|block| self.process_block(block))
+            .collect()
+    }
+
     /// Produce a new block at the present slot.
     ///
     /// The produced block will not be inherently valid, it must be signed by a block producer.
@@ -658,17 +1625,34 @@ where
         &self,
         randao_reveal: Signature,
     ) -> Result<(BeaconBlock, BeaconState), BlockProductionError> {
-        debug!("Producing block at slot {}...", self.state.read().slot);
+        let slot = self.state.read().slot;
+        debug!("Producing block at slot {}...", slot);
 
-        let mut state = self.state.read().clone();
+        let mut state = match self.re_org_state(slot) {
+            Some(state) => state,
+            None => self.state.read().clone(),
+        };
 
         trace!("Finding attestations for new block...");
 
-        let attestations = self
+        let mut attestations = self
             .attestation_aggregator
             .read()
             .get_attestations_for_state(&state, &self.spec);
 
+        // attestations from the network are only included for data we have not aggregated
+        // ourselves
+        let pooled_attestations = self
+            .attestation_pool
+            .read()
+            .get_attestations_for_state(&state, &self.spec);
+        for attestation in pooled_attestations {
+            if !attestations.iter().any(|a| a.data == attestation.data) {
+                attestations.push(attestation);
+            }
+        }
+        attestations.truncate(self.spec.max_attestations as usize);
+
         trace!(
             "Inserting {} attestation(s) into new block.",
             attestations.len()
@@ -710,8 +1694,128 @@ where
         Ok((block, state))
     }
 
+    /// Returns the root of the parent of the head, if a block at `slot` should be built upon it
+    /// rather than the head.
+    ///
+    /// The head is only re-orged out if re-orgs are enabled and it is a block of the previous slot
+    /// which arrived after that slot had ended, with the votes of less than the threshold
+    /// percentage of the balance of a slot's committees. Its parent must be of the slot before it,
+    /// and `slot` may not be the first of an epoch, so that no more than one block is re-orged out
+    /// and the shuffling is unchanged. Once imported in its slot, the block built on the parent is
+    /// boosted in fork choice above the late head, as the threshold is below the boost.
+    pub fn proposer_re_org_parent(&self, slot: Slot) -> Option<Hash256> {
+        let threshold = self.proposer_re_org_threshold?;
+        let slots_per_epoch = self.spec.slots_per_epoch;
+
+        let (head_root, head_slot, parent_root) = {
+            let head = self.head();
+            (
+                head.beacon_block_root,
+                head.beacon_block.slot,
+                head.beacon_block.previous_block_root,
+            )
+        };
+        if head_slot + 1 != slot
+            || slot == slot.epoch(slots_per_epoch).start_slot(slots_per_epoch)
+            || *self.last_late_block.read() != Some(head_root)
+        {
+            return None;
+        }
+
+        let parent = self.block_store.get_deserialized(&parent_root).ok()??;
+        if parent.slot + 1 != head_slot || parent.slot < self.finalized_head().beacon_block.slot {
+            return None;
+        }
+
+        // the head lock is not held while reading the fork choice, which may hold its lock while
+        // waiting to update the head
+        let voters = self.fork_choice.read().latest_voters(&head_root)?;
+
+        let head = self.head();
+        if head.beacon_block_root != head_root {
+            return None;
+        }
+        let state = &head.beacon_state;
+        let head_weight = voters.iter().try_fold(0, |weight, &validator_index| {
+            state
+                .get_effective_balance(validator_index as usize, &self.spec)
+                .map(|balance| weight + balance)
+        });
+        let active_validator_indices =
+            state.get_active_validator_indices(slot.epoch(slots_per_epoch));
+        let committee_weight = state
+            .get_total_balance(&active_validator_indices, &self.spec)
+            .map(|balance| balance / slots_per_epoch);
+
+        match (head_weight, committee_weight) {
+            (Ok(head_weight), Ok(committee_weight))
+                if head_weight * 100 < committee_weight * threshold =>
+            {
+                info!(
+                    "Re-orging out late head block {} at slot {} with {} of {} committee weight",
+                    head_root, head_slot, head_weight, committee_weight
+                );
+                Some(parent_root)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the state at `slot` of the parent of the head, if a block at `slot` should be built
+    /// upon it, as by `proposer_re_org_parent`.
+    fn re_org_state(&self, slot: Slot) -> Option<BeaconState> {
+        let parent_root = self.proposer_re_org_parent(slot)?;
+        let parent = self.block_store.get_deserialized(&parent_root).ok()??;
+        let mut state = self
+            .state_store
+            .get_deserialized(&parent.state_root)
+            .ok()??;
+
+        let header = parent.block_header();
+        for _ in state.slot.as_u64()..slot.as_u64() {
+            per_slot_processing(&mut state, &header, &self.spec).ok()?;
+        }
+        state
+            .build_epoch_cache(RelativeEpoch::Previous, &self.spec)
+            .ok()?;
+        state
+            .build_epoch_cache(RelativeEpoch::Current, &self.spec)
+            .ok()?;
+        Some(state)
+    }
+
+    /// Returns true if the block `block_root` at `block_slot` is to be boosted in fork choice, as
+    /// the first block imported during its own slot, clearing the boost of an earlier slot.
+    fn boost_if_timely(&self, block_root: Hash256, block_slot: Slot, present_slot: Slot) -> bool {
+        let mut proposer_boost = self.proposer_boost.write();
+        if let Some((_, boost_slot)) = *proposer_boost {
+            if boost_slot == present_slot {
+                return false;
+            }
+            *proposer_boost = None;
+            self.fork_choice.write().set_proposer_boost(None);
+        }
+        if block_slot != present_slot {
+            return false;
+        }
+        *proposer_boost = Some((block_root, block_slot));
+        true
+    }
+
+    /// Clears the proposer boost of fork choice once the slot of the boosted block has ended.
+    fn expire_proposer_boost(&self, present_slot: Slot) {
+        let mut proposer_boost = self.proposer_boost.write();
+        if let Some((_, boost_slot)) = *proposer_boost {
+            if boost_slot < present_slot {
+                *proposer_boost = None;
+                self.fork_choice.write().set_proposer_boost(None);
+            }
+        }
+    }
+
     // TODO: Left this as is, modify later
     pub fn fork_choice(&self) -> Result<(), Error> {
+        self.expire_proposer_boost(self.present_slot());
         let present_head = self.finalized_head().beacon_block_root;
 
         let new_head = self
@@ -732,7 +1836,12 @@ where
                 .ok_or_else(|| Error::MissingBeaconState(block.state_root))?;
             let state_root = state.canonical_root();
 
-            self.update_canonical_head(block, block_root, state, state_root);
+            let present_slot = self.present_slot();
+            self.update_canonical_head(block, block_root, state.clone(), state_root);
+
+            // Produce blocks on the new head, rather than on the branch it replaced.
+            *self.state.write() = state;
+            self.advance_state(present_slot)?;
         }
 
         Ok(())
@@ -0,0 +1,232 @@
+//! Starting the chain from a trusted finalized state and block, rather than from genesis.
+//!
+//! The state and block are read SSZ encoded, from files or from the URLs of a trusted node. The
+//! chain is then synced forwards from the checkpoint, skipping the blocks before it.
+
+use crate::errors::BeaconChainError;
+use ssz::{Decodable, DecodeError, TreeHash};
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use types::{BeaconBlock, BeaconState, Hash256, Slot};
+
+#[derive(Debug)]
+pub enum CheckpointSyncError {
+    /// A file could not be read.
+    Io { path: PathBuf, error: io::Error },
+    /// A URL could not be downloaded.
+    Download { url: String, error: String },
+    /// A file or download could not be decoded.
+    DecodeError { source: String, error: DecodeError },
+    /// The block is not at the slot of the state.
+    SlotMismatch { block_slot: Slot, state_slot: Slot },
+    /// The block does not commit to the state.
+    StateRootMismatch {
+        block_state_root: Hash256,
+        state_root: Hash256,
+    },
+    /// The state is of a chain with another genesis to ours.
+    GenesisMismatch { found: u64, expected: u64 },
+    /// The state is of a chain with other genesis validators to ours.
+    GenesisValidatorsMismatch { validator_index: usize },
+    /// The chain could not be instantiated from the checkpoint.
+    BeaconChainError(BeaconChainError),
+}
+
+/// A finalized state and the block it follows, trusted to be on the canonical chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedCheckpoint {
+    pub block: BeaconBlock,
+    pub state: BeaconState,
+}
+
+impl TrustedCheckpoint {
+    /// Reads the SSZ encoded state and block from the given sources, checking that the block
+    /// commits to the state.
+    ///
+    /// Each source is either an `http://` or `https://` URL, which is downloaded, or the path of a
+    /// file.
+    pub fn load(state_source: &str, block_source: &str) -> Result<Self, CheckpointSyncError> {
+        let checkpoint = TrustedCheckpoint {
+            block: read_ssz(block_source)?,
+            state: read_ssz(state_source)?,
+        };
+        checkpoint.verify()?;
+        Ok(checkpoint)
+    }
+
+    /// Checks that the block is at the slot of the state, and that its state root is the root of
+    /// the state.
+    pub fn verify(&self) -> Result<(), CheckpointSyncError> {
+        if self.block.slot != self.state.slot {
+            return Err(CheckpointSyncError::SlotMismatch {
+                block_slot: self.block.slot,
+                state_slot: self.state.slot,
+            });
+        }
+
+        let state_root = Hash256::from_slice(&self.state.hash_tree_root()[..]);
+        if self.block.state_root != state_root {
+            return Err(CheckpointSyncError::StateRootMismatch {
+                block_state_root: self.block.state_root,
+                state_root,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the state is of the chain starting at `genesis_state`.
+    ///
+    /// The state does not commit to the root of the genesis validators, so instead the genesis
+    /// validators must lead its registry, with the keys and withdrawal credentials they never
+    /// change.
+    pub fn verify_genesis(&self, genesis_state: &BeaconState) -> Result<(), CheckpointSyncError> {
+        if self.state.genesis_time != genesis_state.genesis_time {
+            return Err(CheckpointSyncError::GenesisMismatch {
+                found: self.state.genesis_time,
+                expected: genesis_state.genesis_time,
+            });
+        }
+
+        let registry = &self.state.validator_registry;
+        for (validator_index, genesis_validator) in
+            genesis_state.validator_registry.iter().enumerate()
+        {
+            let matches = registry.get(validator_index).map_or(false, |validator| {
+                validator.pubkey == genesis_validator.pubkey
+                    && validator.withdrawal_credentials == genesis_validator.withdrawal_credentials
+            });
+            if !matches {
+                return Err(CheckpointSyncError::GenesisValidatorsMismatch { validator_index });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `source` is a URL to be downloaded, rather than the path of a file.
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Reads and decodes the SSZ encoded file or download at `source`.
+fn read_ssz<T: Decodable>(source: &str) -> Result<T, CheckpointSyncError> {
+    let bytes = if is_url(source) {
+        download(source)?
+    } else {
+        fs::read(source).map_err(|error| CheckpointSyncError::Io {
+            path: PathBuf::from(source),
+            error,
+        })?
+    };
+    T::ssz_decode(&bytes, 0)
+        .map(|(item, _)| item)
+        .map_err(|error| CheckpointSyncError::DecodeError {
+            source: source.to_string(),
+            error,
+        })
+}
+
+/// Downloads the body of `url`, which must respond with a success status.
+fn download(url: &str) -> Result<Vec<u8>, CheckpointSyncError> {
+    let download_error = |error: String| CheckpointSyncError::Download {
+        url: url.to_string(),
+        error,
+    };
+    let mut response = reqwest::get(url).map_err(|e| download_error(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(download_error(format!("status {}", response.status())));
+    }
+    let mut bytes = vec![];
+    response
+        .read_to_end(&mut bytes)
+        .map_err(|e| download_error(e.to_string()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::TestingBeaconStateBuilder;
+    use types::ChainSpec;
+
+    fn checkpoint(spec: &ChainSpec) -> TrustedCheckpoint {
+        let mut builder = TestingBeaconStateBuilder::from_deterministic_keypairs(8, spec);
+        builder.teleport_to_slot(spec.genesis_slot + spec.slots_per_epoch * 3, spec);
+        let (state, _keypairs) = builder.build();
+
+        let mut block = BeaconBlock::empty(spec);
+        block.slot = state.slot;
+        block.state_root = Hash256::from_slice(&state.hash_tree_root()[..]);
+
+        TrustedCheckpoint { block, state }
+    }
+
+    #[test]
+    fn verifies_checkpoint() {
+        let spec = ChainSpec::few_validators();
+        let checkpoint = checkpoint(&spec);
+        assert!(checkpoint.verify().is_ok());
+        assert!(checkpoint.verify_genesis(&checkpoint.state).is_ok());
+
+        let mut other_genesis = checkpoint.state.clone();
+        other_genesis.genesis_time += 1;
+        match checkpoint.verify_genesis(&other_genesis) {
+            Err(CheckpointSyncError::GenesisMismatch { .. }) => (),
+            other => panic!("expected a genesis mismatch, got {:?}", other),
+        }
+
+        let mut other_genesis = checkpoint.state.clone();
+        other_genesis.validator_registry[3].withdrawal_credentials = Hash256::from_low_u64_be(1);
+        match checkpoint.verify_genesis(&other_genesis) {
+            Err(CheckpointSyncError::GenesisValidatorsMismatch { validator_index: 3 }) => (),
+            other => panic!("expected a genesis validators mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn loads_checkpoint_from_files() {
+        let spec = ChainSpec::few_validators();
+        let checkpoint = checkpoint(&spec);
+
+        let dir = std::env::temp_dir().join(format!("checkpoint_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let state_path = dir.join("state.ssz");
+        let block_path = dir.join("block.ssz");
+        fs::write(&state_path, ssz::ssz_encode(&checkpoint.state)).unwrap();
+        fs::write(&block_path, ssz::ssz_encode(&checkpoint.block)).unwrap();
+
+        let loaded =
+            TrustedCheckpoint::load(state_path.to_str().unwrap(), block_path.to_str().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), checkpoint);
+    }
+
+    #[test]
+    fn distinguishes_urls_from_paths() {
+        assert!(is_url("http://localhost:5052/checkpoint/state"));
+        assert!(is_url("https://example.com/state.ssz"));
+        assert!(!is_url("/tmp/state.ssz"));
+        assert!(!is_url("state.ssz"));
+    }
+
+    #[test]
+    fn rejects_block_of_other_state() {
+        let spec = ChainSpec::few_validators();
+
+        let mut checkpoint = checkpoint(&spec);
+        checkpoint.block.state_root = Hash256::zero();
+        match checkpoint.verify() {
+            Err(CheckpointSyncError::StateRootMismatch { .. }) => (),
+            other => panic!("expected a state root mismatch, got {:?}", other),
+        }
+
+        let mut checkpoint = self::checkpoint(&spec);
+        checkpoint.block.slot += 1;
+        match checkpoint.verify() {
+            Err(CheckpointSyncError::SlotMismatch { .. }) => (),
+            other => panic!("expected a slot mismatch, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use types::{
+    AttestationDuty, BeaconState, BeaconStateError, ChainSpec, Epoch, Hash256, PublicKey,
+    RelativeEpoch, Slot,
+};
+
+/// The block proposal and attestation duties of all validators in an epoch.
+///
+/// Extracted from a state once per epoch so that duties may be read by many validators at once
+/// without cloning the state or holding a lock on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DutiesReader {
+    epoch: Epoch,
+    /// The seed the committees of `epoch` were shuffled with.
+    seed: Hash256,
+    active_validator_indices: Vec<usize>,
+    /// The index of the proposer of each slot of `epoch`.
+    proposers: Vec<usize>,
+    /// The attestation duty of each validator in `epoch`, by validator index.
+    attestation_duties: Vec<Option<AttestationDuty>>,
+    slots_per_epoch: u64,
+}
+
+impl DutiesReader {
+    /// Reads the duties of the current epoch of `state`.
+    ///
+    /// Note: the current epoch cache of `state` must be initialized.
+    pub fn from_state(state: &BeaconState, spec: &ChainSpec) -> Result<Self, BeaconStateError> {
+        let epoch = state.current_epoch(spec);
+
+        let proposers = epoch
+            .slot_iter(spec.slots_per_epoch)
+            .map(|slot| state.get_beacon_proposer_index(slot, RelativeEpoch::Current, spec))
+            .collect::<Result<_, _>>()?;
+
+        let attestation_duties = (0..state.validator_registry.len())
+            .map(|index| state.get_attestation_duties(index, spec).map(Clone::clone))
+            .collect::<Result<_, _>>()?;
+
+        Ok(DutiesReader {
+            epoch,
+            seed: state.current_shuffling_seed,
+            active_validator_indices: state
+                .get_cached_active_validator_indices(RelativeEpoch::Current, spec)?
+                .to_vec(),
+            proposers,
+            attestation_duties,
+            slots_per_epoch: spec.slots_per_epoch,
+        })
+    }
+
+    /// Returns `true` if the duties were read from a state in the same epoch and with the same
+    /// shuffling as `state`.
+    pub fn is_current_for(&self, state: &BeaconState, spec: &ChainSpec) -> bool {
+        self.epoch == state.current_epoch(spec) && self.seed == state.current_shuffling_seed
+    }
+
+    /// The epoch the duties are for.
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    /// The seed the committees of the epoch were shuffled with.
+    pub fn seed(&self) -> Hash256 {
+        self.seed
+    }
+
+    /// The indices of the validators active in the epoch.
+    pub fn active_validator_indices(&self) -> &[usize] {
+        &self.active_validator_indices
+    }
+
+    /// Returns the index of the proposer of `slot`, or `None` if `slot` is not in the epoch.
+    pub fn block_proposer(&self, slot: Slot) -> Option<usize> {
+        if slot.epoch(self.slots_per_epoch) != self.epoch {
+            return None;
+        }
+        let index = (slot - self.epoch.start_slot(self.slots_per_epoch)).as_usize();
+        self.proposers.get(index).cloned()
+    }
+
+    /// Returns the first slot of the epoch in which `validator_index` proposes a block, if any.
+    pub fn proposal_slot(&self, validator_index: usize) -> Option<Slot> {
+        self.proposers
+            .iter()
+            .position(|&proposer| proposer == validator_index)
+            .map(|index| self.epoch.start_slot(self.slots_per_epoch) + index as u64)
+    }
+
+    /// Returns the attestation duty of `validator_index` in the epoch, if it has one.
+    pub fn attestation_duty(&self, validator_index: usize) -> Option<&AttestationDuty> {
+        self.attestation_duties
+            .get(validator_index)
+            .and_then(Option::as_ref)
+    }
+}
+
+/// The proposer of each slot of an epoch, for predicting upcoming proposals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProposerDuties {
+    pub epoch: Epoch,
+    /// The root of the block at the last slot before `epoch`, whose state the proposers were
+    /// selected from. The duties must be read again if this block is re-orged out.
+    pub dependent_root: Hash256,
+    /// The slot, validator index and public key of each proposer, in slot order.
+    pub proposers: Vec<(Slot, usize, PublicKey)>,
+}
+
+impl ProposerDuties {
+    /// Reads the proposers of `epoch` from `state`, which must be the previous or current epoch
+    /// of the state as the shuffling of the next epoch is not yet known.
+    ///
+    /// `genesis_block_root` is the dependent root of the genesis epoch.
+    ///
+    /// Note: the epoch cache of `state` for `epoch` must be initialized.
+    pub fn from_state(
+        state: &BeaconState,
+        epoch: Epoch,
+        genesis_block_root: Hash256,
+        spec: &ChainSpec,
+    ) -> Result<Self, BeaconStateError> {
+        let relative_epoch = RelativeEpoch::from_epoch(state.current_epoch(spec), epoch)
+            .map_err(BeaconStateError::RelativeEpochError)?;
+
+        let start_slot = epoch.start_slot(spec.slots_per_epoch);
+        let dependent_root = if start_slot == spec.genesis_slot {
+            genesis_block_root
+        } else {
+            *state.get_block_root(start_slot - 1, spec)?
+        };
+
+        let proposers = epoch
+            .slot_iter(spec.slots_per_epoch)
+            .map(|slot| {
+                let index = state.get_beacon_proposer_index(slot, relative_epoch, spec)?;
+                let validator = state
+                    .validator_registry
+                    .get(index)
+                    .ok_or(BeaconStateError::UnknownValidator)?;
+                Ok((slot, index, validator.pubkey.clone()))
+            })
+            .collect::<Result<_, BeaconStateError>>()?;
+
+        Ok(ProposerDuties {
+            epoch,
+            dependent_root,
+            proposers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::TestingBeaconStateBuilder;
+
+    #[test]
+    fn matches_state() {
+        let spec = ChainSpec::few_validators();
+        let mut builder = TestingBeaconStateBuilder::from_deterministic_keypairs(32, &spec);
+        builder.teleport_to_slot(spec.genesis_slot + spec.slots_per_epoch * 3, &spec);
+        builder.build_caches(&spec).unwrap();
+        let (state, _keypairs) = builder.build();
+
+        let duties = DutiesReader::from_state(&state, &spec).unwrap();
+        assert!(duties.is_current_for(&state, &spec));
+        assert_eq!(duties.epoch(), state.current_epoch(&spec));
+
+        for slot in duties.epoch().slot_iter(spec.slots_per_epoch) {
+            let proposer = state
+                .get_beacon_proposer_index(slot, RelativeEpoch::Current, &spec)
+                .unwrap();
+            assert_eq!(duties.block_proposer(slot), Some(proposer));
+            assert!(duties.proposal_slot(proposer).unwrap() <= slot);
+        }
+        assert_eq!(
+            duties.block_proposer(state.slot - spec.slots_per_epoch),
+            None
+        );
+
+        for index in 0..state.validator_registry.len() {
+            assert_eq!(
+                duties.attestation_duty(index),
+                state.get_attestation_duties(index, &spec).unwrap().as_ref()
+            );
+        }
+        assert_eq!(
+            duties.attestation_duty(state.validator_registry.len()),
+            None
+        );
+    }
+
+    #[test]
+    fn proposer_duties() {
+        let spec = ChainSpec::few_validators();
+        let mut builder = TestingBeaconStateBuilder::from_deterministic_keypairs(32, &spec);
+        builder.teleport_to_slot(spec.genesis_slot + spec.slots_per_epoch * 3, &spec);
+        builder.build_caches(&spec).unwrap();
+        let (state, _keypairs) = builder.build();
+
+        let duties = DutiesReader::from_state(&state, &spec).unwrap();
+        let epoch = state.current_epoch(&spec);
+        let proposer_duties =
+            ProposerDuties::from_state(&state, epoch, Hash256::zero(), &spec).unwrap();
+        assert_eq!(proposer_duties.epoch, epoch);
+        assert_eq!(
+            proposer_duties.dependent_root,
+            *state
+                .get_block_root(epoch.start_slot(spec.slots_per_epoch) - 1, &spec)
+                .unwrap()
+        );
+        assert_eq!(proposer_duties.proposers.len() as u64, spec.slots_per_epoch);
+        for (slot, index, pubkey) in &proposer_duties.proposers {
+            assert_eq!(duties.block_proposer(*slot), Some(*index));
+            assert_eq!(*pubkey, state.validator_registry[*index].pubkey);
+        }
+
+        // the shuffling of the next epoch is not yet known
+        assert!(ProposerDuties::from_state(&state, epoch + 1, Hash256::zero(), &spec).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use types::{BeaconState, Epoch, Hash256};
+
+/// The maximum number of epoch-boundary states held at once.
+pub const MAX_EPOCH_BOUNDARY_STATES: usize = 16;
+
+/// Holds the states at the start of recent epochs, keyed by the root of the epoch boundary block
+/// and the epoch, with the committee caches of the epoch built.
+///
+/// Every attestation of an epoch targets its boundary block, so a state is reused for each
+/// attestation of the epoch on that chain. States of a fork are held alongside those of the
+/// canonical chain, so attestations on either may be validated.
+///
+/// Entries are never evicted while they are of the current or previous epoch and fewer than
+/// `MAX_EPOCH_BOUNDARY_STATES` are held. Beyond that, the least recently used is evicted first.
+#[derive(Default)]
+pub struct EpochBoundaryStateCache {
+    states: HashMap<(Hash256, Epoch), Entry>,
+    /// Incremented on each access, to order entries by use.
+    clock: u64,
+}
+
+struct Entry {
+    state: Arc<BeaconState>,
+    last_used: u64,
+}
+
+impl EpochBoundaryStateCache {
+    /// Returns the state at the start of `epoch` on the chain of `boundary_root`, if held.
+    pub fn get(&mut self, boundary_root: Hash256, epoch: Epoch) -> Option<Arc<BeaconState>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.states.get_mut(&(boundary_root, epoch)).map(|entry| {
+            entry.last_used = clock;
+            entry.state.clone()
+        })
+    }
+
+    /// Adds the state at the start of `epoch` on the chain of `boundary_root`, evicting the least
+    /// recently used state if the cache is full.
+    pub fn insert(&mut self, boundary_root: Hash256, epoch: Epoch, state: Arc<BeaconState>) {
+        self.clock += 1;
+        if self.states.len() >= MAX_EPOCH_BOUNDARY_STATES
+            && !self.states.contains_key(&(boundary_root, epoch))
+        {
+            let oldest = self
+                .states
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            if let Some(key) = oldest {
+                self.states.remove(&key);
+            }
+        }
+        self.states.insert(
+            (boundary_root, epoch),
+            Entry {
+                state,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Removes the states of epochs before the previous epoch of `current_epoch`, which no
+    /// attestation may target.
+    pub fn prune(&mut self, current_epoch: Epoch) {
+        let previous_epoch = current_epoch.saturating_sub(1u64);
+        self.states.retain(|(_, epoch), _| *epoch >= previous_epoch);
+    }
+
+    /// Returns the number of states held.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns `true` if no states are held.
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::TestingBeaconStateBuilder;
+    use types::ChainSpec;
+
+    fn state() -> Arc<BeaconState> {
+        let spec = ChainSpec::few_validators();
+        let (state, _keypairs) =
+            TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec).build();
+        Arc::new(state)
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let state = state();
+        let mut cache = EpochBoundaryStateCache::default();
+        let epoch = Epoch::new(1);
+
+        for i in 0..MAX_EPOCH_BOUNDARY_STATES as u64 {
+            cache.insert(Hash256::from_low_u64_be(i), epoch, state.clone());
+        }
+        // Keep the first state in use, so the second is the least recently used.
+        assert!(cache.get(Hash256::from_low_u64_be(0), epoch).is_some());
+
+        cache.insert(Hash256::from_low_u64_be(100), epoch, state.clone());
+        assert_eq!(cache.len(), MAX_EPOCH_BOUNDARY_STATES);
+        assert!(cache.get(Hash256::from_low_u64_be(0), epoch).is_some());
+        assert!(cache.get(Hash256::from_low_u64_be(1), epoch).is_none());
+        assert!(cache.get(Hash256::from_low_u64_be(100), epoch).is_some());
+    }
+
+    #[test]
+    fn prunes_old_epochs() {
+        let state = state();
+        let mut cache = EpochBoundaryStateCache::default();
+        let root = Hash256::from_low_u64_be(1);
+
+        for epoch in 0..4 {
+            cache.insert(root, Epoch::new(epoch), state.clone());
+        }
+        cache.prune(Epoch::new(3));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(root, Epoch::new(1)).is_none());
+        assert!(cache.get(root, Epoch::new(2)).is_some());
+        assert!(cache.get(root, Epoch::new(3)).is_some());
+    }
+}
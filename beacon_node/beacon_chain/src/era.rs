@@ -0,0 +1,345 @@
+//! Export and import of the canonical chain as flat files of SSZ encoded blocks.
+//!
+//! Each file holds the canonical blocks of a range of epochs, ordered by slot, and optionally the
+//! state following its last block. The files can be distributed out-of-band and imported by a
+//! fresh node, which is much faster than downloading the same blocks from peers.
+
+use crate::beacon_chain::{BeaconChain, BlockProcessingOutcome};
+use crate::errors::BeaconChainError;
+use db::{ClientDB, DBError};
+use fork_choice::ForkChoice;
+use log::{debug, info};
+use slot_clock::SlotClock;
+use ssz::{ssz_encode, Decodable, DecodeError, Encodable, SszStream};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use types::{BeaconBlock, BeaconState, Epoch, Hash256, Slot};
+
+/// The version of the file format, written at the start of each file.
+const ERA_VERSION: u32 = 1;
+/// The extension of exported files.
+const ERA_EXTENSION: &str = "era";
+
+#[derive(Debug)]
+pub enum EraError {
+    Io(io::Error),
+    DBError(String),
+    BeaconChainError(BeaconChainError),
+    /// A file could not be decoded.
+    DecodeError {
+        path: PathBuf,
+        error: DecodeError,
+    },
+    /// A file was written with an unsupported version of the format.
+    UnsupportedVersion {
+        path: PathBuf,
+        version: u32,
+    },
+    MissingBeaconBlock(Hash256),
+    MissingBeaconState(Hash256),
+    /// A block in a file could not be imported.
+    InvalidBlock {
+        slot: Slot,
+        outcome: String,
+    },
+    /// The state following the blocks of a file does not match the state in the file.
+    StateMismatch {
+        path: PathBuf,
+    },
+}
+
+impl From<io::Error> for EraError {
+    fn from(e: io::Error) -> EraError {
+        EraError::Io(e)
+    }
+}
+
+impl From<DBError> for EraError {
+    fn from(e: DBError) -> EraError {
+        EraError::DBError(e.message)
+    }
+}
+
+impl From<BeaconChainError> for EraError {
+    fn from(e: BeaconChainError) -> EraError {
+        EraError::BeaconChainError(e)
+    }
+}
+
+/// Parameters for exporting the chain.
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    /// The number of epochs of blocks in each file.
+    pub epochs_per_file: u64,
+    /// The state following the last block is included in every `states_every`th file. States are
+    /// not included if zero.
+    pub states_every: u64,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            epochs_per_file: 64,
+            states_every: 8,
+        }
+    }
+}
+
+/// The number of blocks and files imported.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportSummary {
+    pub files: usize,
+    pub blocks_imported: usize,
+    /// Blocks which were already known and so were not processed.
+    pub blocks_skipped: usize,
+}
+
+/// The contents of a single file.
+#[derive(Debug, PartialEq)]
+pub struct EraFile {
+    /// The first epoch of the range.
+    pub start_epoch: Epoch,
+    /// The canonical blocks of the range, ordered by slot.
+    pub blocks: Vec<BeaconBlock>,
+    /// The state following the last block, if included.
+    pub state: Option<BeaconState>,
+}
+
+impl Encodable for EraFile {
+    fn ssz_append(&self, s: &mut SszStream) {
+        s.append(&ERA_VERSION);
+        s.append(&self.start_epoch);
+        s.append_vec(&self.blocks);
+        s.append(&self.state.is_some());
+        if let Some(state) = &self.state {
+            s.append(state);
+        }
+    }
+}
+
+impl EraFile {
+    /// Decodes a file, returning the version it was written with if it is not supported.
+    fn from_ssz(bytes: &[u8]) -> Result<Result<Self, u32>, DecodeError> {
+        let (version, i) = u32::ssz_decode(bytes, 0)?;
+        if version != ERA_VERSION {
+            return Ok(Err(version));
+        }
+        let (start_epoch, i) = Epoch::ssz_decode(bytes, i)?;
+        let (blocks, i) = Vec::ssz_decode(bytes, i)?;
+        let (has_state, i) = bool::ssz_decode(bytes, i)?;
+        let state = if has_state {
+            Some(BeaconState::ssz_decode(bytes, i)?.0)
+        } else {
+            None
+        };
+
+        Ok(Ok(EraFile {
+            start_epoch,
+            blocks,
+            state,
+        }))
+    }
+
+    /// Reads and decodes the file at `path`.
+    pub fn read(path: &Path) -> Result<Self, EraError> {
+        let bytes = fs::read(path)?;
+        match EraFile::from_ssz(&bytes) {
+            Ok(Ok(file)) => Ok(file),
+            Ok(Err(version)) => Err(EraError::UnsupportedVersion {
+                path: path.to_path_buf(),
+                version,
+            }),
+            Err(error) => Err(EraError::DecodeError {
+                path: path.to_path_buf(),
+                error,
+            }),
+        }
+    }
+}
+
+/// Writes the canonical chain, excluding the genesis block, to files in `dir`.
+///
+/// Files are named by the range of epochs they cover, so that sorting their names orders them by
+/// epoch. Returns the paths of the files written.
+pub fn export<T, U, F>(
+    chain: &BeaconChain<T, U, F>,
+    dir: &Path,
+    config: &ExportConfig,
+) -> Result<Vec<PathBuf>, EraError>
+where
+    T: ClientDB,
+    U: SlotClock,
+    F: ForkChoice,
+{
+    let slots_per_epoch = chain.spec.slots_per_epoch;
+    let epochs_per_file = std::cmp::max(config.epochs_per_file, 1);
+    let genesis_slot = chain.spec.genesis_slot;
+
+    let head_root = chain.head().beacon_block_root;
+    let mut roots: Vec<(Hash256, Slot)> = chain
+        .ancestor_iter(head_root)
+        .take_while(|(_, slot)| *slot > genesis_slot)
+        .collect();
+    roots.reverse();
+
+    fs::create_dir_all(dir)?;
+
+    let mut paths = vec![];
+    let mut roots = roots.into_iter().peekable();
+    while let Some((_, first_slot)) = roots.peek().cloned() {
+        let file_index = first_slot.epoch(slots_per_epoch).as_u64() / epochs_per_file;
+        let start_epoch = Epoch::new(file_index * epochs_per_file);
+        let end_epoch = start_epoch + epochs_per_file;
+
+        let mut blocks = vec![];
+        while let Some((root, slot)) = roots.peek().cloned() {
+            if slot.epoch(slots_per_epoch) >= end_epoch {
+                break;
+            }
+            roots.next();
+            let block = chain
+                .block_store
+                .get_deserialized(&root)?
+                .ok_or_else(|| EraError::MissingBeaconBlock(root))?;
+            blocks.push(block);
+        }
+
+        let state = match blocks.last() {
+            Some(block) if config.states_every > 0 && file_index % config.states_every == 0 => {
+                Some(
+                    chain
+                        .state_store
+                        .get_deserialized(&block.state_root)?
+                        .ok_or_else(|| EraError::MissingBeaconState(block.state_root))?,
+                )
+            }
+            _ => None,
+        };
+
+        let path = dir.join(format!(
+            "{:010}-{:010}.{}",
+            start_epoch.as_u64(),
+            end_epoch.as_u64(),
+            ERA_EXTENSION
+        ));
+        let file = EraFile {
+            start_epoch,
+            blocks,
+            state,
+        };
+        fs::write(&path, ssz_encode(&file))?;
+        debug!(
+            "Exported {} blocks to {}",
+            file.blocks.len(),
+            path.display()
+        );
+        paths.push(path);
+    }
+
+    info!(
+        "Exported chain to {} files in {}",
+        paths.len(),
+        dir.display()
+    );
+    Ok(paths)
+}
+
+/// Imports the blocks of each file in `dir`, in the order of their names.
+///
+/// Blocks which are already known are skipped, so an interrupted import may be resumed. Where a
+/// file includes a state, it is checked against the state following the imported blocks.
+pub fn import<T, U, F>(chain: &BeaconChain<T, U, F>, dir: &Path) -> Result<ImportSummary, EraError>
+where
+    T: ClientDB,
+    U: SlotClock,
+    F: ForkChoice,
+{
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == ERA_EXTENSION))
+        .collect();
+    paths.sort();
+
+    let mut summary = ImportSummary::default();
+    for path in paths {
+        let file = EraFile::read(&path)?;
+
+        for block in file.blocks {
+            let block_root = block.block_header().canonical_root();
+            if chain.block_store.block_exists(&block_root)? {
+                summary.blocks_skipped += 1;
+                continue;
+            }
+
+            let slot = block.slot;
+            match chain.process_block(block)? {
+                BlockProcessingOutcome::ValidBlock(_) => summary.blocks_imported += 1,
+                outcome => {
+                    return Err(EraError::InvalidBlock {
+                        slot,
+                        outcome: format!("{:?}", outcome),
+                    });
+                }
+            }
+        }
+
+        if let Some(state) = file.state {
+            if chain.head().beacon_state_root != state.canonical_root() {
+                return Err(EraError::StateMismatch { path });
+            }
+        }
+
+        debug!("Imported {}", path.display());
+        summary.files += 1;
+    }
+
+    info!(
+        "Imported {} blocks from {} files in {}",
+        summary.blocks_imported,
+        summary.files,
+        dir.display()
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+
+    #[test]
+    fn round_trip_encoding() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+
+        let file = EraFile {
+            start_epoch: Epoch::new(64),
+            blocks: vec![
+                BeaconBlock::random_for_test(&mut rng),
+                BeaconBlock::random_for_test(&mut rng),
+            ],
+            state: Some(BeaconState::random_for_test(&mut rng)),
+        };
+        let decoded = EraFile::from_ssz(&ssz_encode(&file)).unwrap().unwrap();
+        assert_eq!(decoded, file);
+
+        let file = EraFile {
+            state: None,
+            ..file
+        };
+        let decoded = EraFile::from_ssz(&ssz_encode(&file)).unwrap().unwrap();
+        assert_eq!(decoded, file);
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let mut bytes = ssz_encode(&EraFile {
+            start_epoch: Epoch::new(0),
+            blocks: vec![],
+            state: None,
+        });
+        bytes[..4].copy_from_slice(&ssz_encode(&(ERA_VERSION + 1)));
+
+        assert_eq!(EraFile::from_ssz(&bytes), Ok(Err(ERA_VERSION + 1)));
+    }
+}
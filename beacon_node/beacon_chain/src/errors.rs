@@ -1,5 +1,6 @@
+use db::stores::ValidatorStoreError;
 use fork_choice::ForkChoiceError;
-use state_processing::BlockProcessingError;
+use state_processing::{BlockProcessingError, SlotProcessingError};
 use types::*;
 
 macro_rules! easy_from_to {
@@ -22,6 +23,22 @@ pub enum BeaconChainError {
     ForkChoiceError(ForkChoiceError),
     MissingBeaconBlock(Hash256),
     MissingBeaconState(Hash256),
+    /// A historical block is not the parent of the block after it.
+    HistoricalBlockNotLinked {
+        slot: Slot,
+    },
+    /// The block is after the start of the epoch, so cannot be its boundary block.
+    NotEpochBoundary {
+        block_root: Hash256,
+        epoch: Epoch,
+    },
+    SlotProcessingError(SlotProcessingError),
+    /// Reaching the slot would advance a state through more slots than permitted.
+    StateAdvanceTooFar {
+        from: Slot,
+        to: Slot,
+    },
+    ValidatorStoreError(ValidatorStoreError),
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,3 +48,5 @@ pub enum BlockProductionError {
 }
 
 easy_from_to!(BlockProcessingError, BlockProductionError);
+easy_from_to!(SlotProcessingError, BeaconChainError);
+easy_from_to!(ValidatorStoreError, BeaconChainError);
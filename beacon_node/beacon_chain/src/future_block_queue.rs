@@ -0,0 +1,127 @@
+use types::{BeaconBlock, Hash256, Slot};
+
+/// The maximum number of blocks that may be held in the queue at once.
+pub const MAX_QUEUED_FUTURE_BLOCKS: usize = 64;
+/// The maximum number of slots ahead of the present slot that a block may be queued for.
+pub const MAX_FUTURE_SLOTS: u64 = 1;
+/// The number of slots after its own slot that a queued block is dropped if it has not been
+/// processed.
+pub const FUTURE_BLOCK_EXPIRY_SLOTS: u64 = 2;
+
+/// Holds blocks which arrived slightly before their slot (e.g., due to clock skew between nodes)
+/// until their slot starts, rather than rejecting them outright.
+///
+/// The queue is bounded by `MAX_QUEUED_FUTURE_BLOCKS` and blocks which are not drained within
+/// `FUTURE_BLOCK_EXPIRY_SLOTS` of their slot are discarded.
+#[derive(Default)]
+pub struct FutureBlockQueue {
+    blocks: Vec<(Hash256, BeaconBlock)>,
+}
+
+impl FutureBlockQueue {
+    /// Returns `true` if a block at `slot` is near enough to `present_slot` to be queued.
+    pub fn is_queueable(slot: Slot, present_slot: Slot) -> bool {
+        slot > present_slot && slot <= present_slot + MAX_FUTURE_SLOTS
+    }
+
+    /// Adds a block to the queue.
+    ///
+    /// Returns `false` if the queue is full or the block is already queued.
+    pub fn insert(&mut self, block_root: Hash256, block: BeaconBlock) -> bool {
+        if self.blocks.len() >= MAX_QUEUED_FUTURE_BLOCKS
+            || self.blocks.iter().any(|(root, _)| *root == block_root)
+        {
+            return false;
+        }
+        self.blocks.push((block_root, block));
+        true
+    }
+
+    /// Removes and returns all blocks with a slot less than or equal to `present_slot`, ordered
+    /// by slot. Expired blocks are discarded.
+    pub fn drain_ready(&mut self, present_slot: Slot) -> Vec<BeaconBlock> {
+        self.blocks
+            .retain(|(_, block)| block.slot + FUTURE_BLOCK_EXPIRY_SLOTS >= present_slot);
+
+        let (mut ready, pending): (Vec<_>, Vec<_>) = self
+            .blocks
+            .drain(..)
+            .partition(|(_, block)| block.slot <= present_slot);
+        self.blocks = pending;
+
+        ready.sort_by_key(|(_, block)| block.slot);
+        ready.into_iter().map(|(_, block)| block).collect()
+    }
+
+    /// Returns the number of queued blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns `true` if there are no queued blocks.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::ChainSpec;
+
+    fn block_at(slot: u64) -> (Hash256, BeaconBlock) {
+        let mut block = BeaconBlock::empty(&ChainSpec::few_validators());
+        block.slot = Slot::new(slot);
+        (Hash256::from_low_u64_be(slot), block)
+    }
+
+    #[test]
+    fn only_near_future_is_queueable() {
+        let present = Slot::new(10);
+
+        assert!(!FutureBlockQueue::is_queueable(Slot::new(10), present));
+        assert!(FutureBlockQueue::is_queueable(Slot::new(11), present));
+        assert!(!FutureBlockQueue::is_queueable(Slot::new(12), present));
+    }
+
+    #[test]
+    fn drains_blocks_when_their_slot_starts() {
+        let mut queue = FutureBlockQueue::default();
+        let (root, block) = block_at(11);
+        assert!(queue.insert(root, block));
+
+        assert!(queue.drain_ready(Slot::new(10)).is_empty());
+        assert_eq!(queue.len(), 1);
+
+        let ready = queue.drain_ready(Slot::new(11));
+        assert_eq!(ready.len(), 1);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicates_and_overflow() {
+        let mut queue = FutureBlockQueue::default();
+        let (root, block) = block_at(11);
+        assert!(queue.insert(root, block.clone()));
+        assert!(!queue.insert(root, block));
+
+        for i in 1..MAX_QUEUED_FUTURE_BLOCKS as u64 {
+            let (_, block) = block_at(11);
+            assert!(queue.insert(Hash256::from_low_u64_be(100 + i), block));
+        }
+        let (_, block) = block_at(11);
+        assert!(!queue.insert(Hash256::from_low_u64_be(1_000), block));
+    }
+
+    #[test]
+    fn discards_expired_blocks() {
+        let mut queue = FutureBlockQueue::default();
+        let (root, block) = block_at(11);
+        queue.insert(root, block);
+
+        assert!(queue
+            .drain_ready(Slot::new(11 + FUTURE_BLOCK_EXPIRY_SLOTS + 1))
+            .is_empty());
+        assert!(queue.is_empty());
+    }
+}
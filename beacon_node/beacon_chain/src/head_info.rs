@@ -0,0 +1,21 @@
+use types::{Epoch, Hash256, Slot};
+
+/// The values of the head most often read, such as for HELLO messages and API status.
+///
+/// Cached apart from the head and state, whose locks are held whilst blocks are imported, so they
+/// may be read without waiting for an import to complete.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadInfo {
+    /// The slot of the head block.
+    pub slot: Slot,
+    /// The root of the head block.
+    pub block_root: Hash256,
+    /// The finalized epoch of the present state.
+    pub finalized_epoch: Epoch,
+    /// The finalized root of the present state.
+    pub finalized_root: Hash256,
+    /// The slot of the present state.
+    pub present_slot: Slot,
+    /// The fork version of the current epoch of the present state.
+    pub fork_version: [u8; 4],
+}
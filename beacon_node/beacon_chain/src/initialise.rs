@@ -2,34 +2,92 @@
 // Note: A new version of ClientTypes may need to be implemented for the lighthouse
 // testnet. These are examples. Also. there is code duplication which can/should be cleaned up.
 
+use crate::checkpoint_sync::{CheckpointSyncError, TrustedCheckpoint};
+use crate::slow_epoch::SlowEpochConfig;
 use crate::BeaconChain;
-use db::stores::{BeaconBlockStore, BeaconStateStore};
-use db::{DiskDB, MemoryDB};
+use db::stores::{
+    BeaconBlockStore, BeaconStateStore, MetadataStore, MetadataStoreError, ValidatorStore,
+};
+use db::{ClientDB, MemoryStore};
 use fork_choice::BitwiseLMDGhost;
+use log::info;
 use slot_clock::SystemTimeSlotClock;
 use ssz::TreeHash;
+use std::fmt;
 use std::path::PathBuf;
 use std::sync::Arc;
 use types::test_utils::TestingBeaconStateBuilder;
 use types::{BeaconBlock, ChainSpec, Hash256};
 
+#[derive(Debug)]
+pub enum InitialiseError {
+    /// The database cannot be used with this binary and configuration.
+    MetadataStoreError(MetadataStoreError),
+    /// The chain cannot be started from the given checkpoint.
+    CheckpointSyncError(CheckpointSyncError),
+}
+
+impl From<MetadataStoreError> for InitialiseError {
+    fn from(e: MetadataStoreError) -> InitialiseError {
+        InitialiseError::MetadataStoreError(e)
+    }
+}
+
+impl From<CheckpointSyncError> for InitialiseError {
+    fn from(e: CheckpointSyncError) -> InitialiseError {
+        InitialiseError::CheckpointSyncError(e)
+    }
+}
+
+impl fmt::Display for InitialiseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitialiseError::MetadataStoreError(e) => write!(f, "{}", e),
+            InitialiseError::CheckpointSyncError(e) => {
+                write!(f, "unable to start from the checkpoint: {:?}", e)
+            }
+        }
+    }
+}
+
 //TODO: Correct this for prod
 //TODO: Account for historical db
-pub fn initialise_beacon_chain(
+/// Initialisation of a beacon chain persisted in `db`, an on-disk database opened with every
+/// column of `COLUMNS`.
+///
+/// If `genesis_time` is given, it replaces the genesis time of the generated genesis state.
+/// Blocks are refused if they skip more than `max_skip_slots` slots, if given. Late head blocks
+/// are re-orged out by local proposers below the `proposer_re_org_threshold`, if given. Slow epoch
+/// transitions are reported as by `slow_epoch`. If a trusted `checkpoint` is given, the chain
+/// starts from it rather than from genesis.
+pub fn initialise_beacon_chain<T: ClientDB>(
     spec: &ChainSpec,
-    db_name: Option<&PathBuf>,
-) -> Arc<BeaconChain<DiskDB, SystemTimeSlotClock, BitwiseLMDGhost<DiskDB>>> {
-    // set up the db
-    let db = Arc::new(DiskDB::open(
-        db_name.expect("Database directory must be included"),
-        None,
-    ));
+    db: Arc<T>,
+    genesis_time: Option<u64>,
+    max_skip_slots: Option<u64>,
+    proposer_re_org_threshold: Option<u64>,
+    slow_epoch: SlowEpochConfig,
+    checkpoint: Option<TrustedCheckpoint>,
+) -> Result<Arc<BeaconChain<T, SystemTimeSlotClock, BitwiseLMDGhost<T>>>, InitialiseError> {
+    // refuse a database written by an incompatible binary or for another chain
+    MetadataStore::new(db.clone()).check_compatibility(spec.genesis_fork_version)?;
 
     let block_store = Arc::new(BeaconBlockStore::new(db.clone()));
+    // blocks stored before the slot index existed are indexed, for serving them by range
+    let indexed = block_store
+        .backfill_slot_index()
+        .map_err(MetadataStoreError::from)?;
+    if indexed > 0 {
+        info!("Indexed the slots of {} stored blocks", indexed);
+    }
     let state_store = Arc::new(BeaconStateStore::new(db.clone()));
+    let validator_store = Arc::new(ValidatorStore::new(db.clone()));
 
     let state_builder = TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec);
-    let (genesis_state, _keypairs) = state_builder.build();
+    let (mut genesis_state, _keypairs) = state_builder.build();
+    if let Some(genesis_time) = genesis_time {
+        genesis_state.genesis_time = genesis_time;
+    }
 
     let mut genesis_block = BeaconBlock::empty(&spec);
     genesis_block.state_root = Hash256::from_slice(&genesis_state.hash_tree_root());
@@ -40,33 +98,61 @@ pub fn initialise_beacon_chain(
     // Choose the fork choice
     let fork_choice = BitwiseLMDGhost::new(block_store.clone(), state_store.clone());
 
-    // Genesis chain
-    //TODO: Handle error correctly
-    Arc::new(
-        BeaconChain::from_genesis(
+    let mut beacon_chain = match checkpoint {
+        // Checkpoint chain
+        Some(checkpoint) => BeaconChain::from_checkpoint(
             state_store.clone(),
             block_store.clone(),
+            validator_store,
+            slot_clock,
+            checkpoint,
+            &genesis_state,
+            &genesis_block,
+            spec.clone(),
+            fork_choice,
+        )?,
+        // Genesis chain
+        //TODO: Handle error correctly
+        None => BeaconChain::from_genesis(
+            state_store.clone(),
+            block_store.clone(),
+            validator_store,
             slot_clock,
             genesis_state,
             genesis_block,
+            &[],
             spec.clone(),
             fork_choice,
         )
         .expect("Terminate if beacon chain generation fails"),
-    )
+    };
+    beacon_chain.max_skip_slots = max_skip_slots;
+    beacon_chain.proposer_re_org_threshold = proposer_re_org_threshold;
+    beacon_chain.slow_epoch = slow_epoch;
+    Ok(Arc::new(beacon_chain))
 }
 
-/// Initialisation of a test beacon chain, uses an in memory db with fixed genesis time.
+/// Initialisation of a test beacon chain, uses an in memory db with fixed genesis time, unless
+/// `genesis_time` is given. Starts from the `checkpoint`, if given.
 pub fn initialise_test_beacon_chain(
     spec: &ChainSpec,
     _db_name: Option<&PathBuf>,
-) -> Arc<BeaconChain<MemoryDB, SystemTimeSlotClock, BitwiseLMDGhost<MemoryDB>>> {
-    let db = Arc::new(MemoryDB::open());
+    genesis_time: Option<u64>,
+    max_skip_slots: Option<u64>,
+    proposer_re_org_threshold: Option<u64>,
+    slow_epoch: SlowEpochConfig,
+    checkpoint: Option<TrustedCheckpoint>,
+) -> Arc<BeaconChain<MemoryStore, SystemTimeSlotClock, BitwiseLMDGhost<MemoryStore>>> {
+    let db = Arc::new(MemoryStore::open());
     let block_store = Arc::new(BeaconBlockStore::new(db.clone()));
     let state_store = Arc::new(BeaconStateStore::new(db.clone()));
+    let validator_store = Arc::new(ValidatorStore::new(db.clone()));
 
     let state_builder = TestingBeaconStateBuilder::from_deterministic_keypairs(8, spec);
-    let (genesis_state, _keypairs) = state_builder.build();
+    let (mut genesis_state, _keypairs) = state_builder.build();
+    if let Some(genesis_time) = genesis_time {
+        genesis_state.genesis_time = genesis_time;
+    }
 
     let mut genesis_block = BeaconBlock::empty(spec);
     genesis_block.state_root = Hash256::from_slice(&genesis_state.hash_tree_root());
@@ -77,18 +163,37 @@ pub fn initialise_test_beacon_chain(
     // Choose the fork choice
     let fork_choice = BitwiseLMDGhost::new(block_store.clone(), state_store.clone());
 
-    // Genesis chain
     //TODO: Handle error correctly
-    Arc::new(
-        BeaconChain::from_genesis(
+    let mut beacon_chain = match checkpoint {
+        // Checkpoint chain
+        Some(checkpoint) => BeaconChain::from_checkpoint(
+            state_store.clone(),
+            block_store.clone(),
+            validator_store,
+            slot_clock,
+            checkpoint,
+            &genesis_state,
+            &genesis_block,
+            spec.clone(),
+            fork_choice,
+        )
+        .expect("Terminate if beacon chain generation fails"),
+        // Genesis chain
+        None => BeaconChain::from_genesis(
             state_store.clone(),
             block_store.clone(),
+            validator_store,
             slot_clock,
             genesis_state,
             genesis_block,
+            &[],
             spec.clone(),
             fork_choice,
         )
         .expect("Terminate if beacon chain generation fails"),
-    )
+    };
+    beacon_chain.max_skip_slots = max_skip_slots;
+    beacon_chain.proposer_re_org_threshold = proposer_re_org_threshold;
+    beacon_chain.slow_epoch = slow_epoch;
+    Arc::new(beacon_chain)
 }
@@ -0,0 +1,38 @@
+use db::{stores::BeaconBlockStore, ClientDB};
+use std::sync::Arc;
+use types::{Hash256, Slot};
+
+/// Iterates backwards through the ancestors of some block, yielding `(block_root, slot)` for the
+/// block itself and then each of its ancestors down to genesis.
+///
+/// Iteration stops early if an ancestor is not present in the block store.
+pub struct AncestorIter<T: ClientDB> {
+    block_store: Arc<BeaconBlockStore<T>>,
+    next_root: Option<Hash256>,
+}
+
+impl<T: ClientDB> AncestorIter<T> {
+    /// Returns an iterator starting at (and including) `block_root`.
+    pub fn new(block_store: Arc<BeaconBlockStore<T>>, block_root: Hash256) -> Self {
+        Self {
+            block_store,
+            next_root: Some(block_root),
+        }
+    }
+}
+
+impl<T: ClientDB> Iterator for AncestorIter<T> {
+    type Item = (Hash256, Slot);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block_root = self.next_root.take()?;
+        let block = self.block_store.get_deserialized(&block_root).ok()??;
+
+        // The genesis block has a zero parent root.
+        if !block.previous_block_root.is_zero() {
+            self.next_root = Some(block.previous_block_root);
+        }
+
+        Some((block_root, block.slot))
+    }
+}
@@ -1,14 +1,39 @@
 mod attestation_aggregator;
+mod attestation_pool;
 mod beacon_chain;
 mod checkpoint;
+pub mod checkpoint_sync;
+mod duties_reader;
+mod epoch_boundary_state_cache;
+pub mod era;
 mod errors;
+mod future_block_queue;
+mod head_info;
 pub mod initialise;
+mod iter;
+pub mod metrics;
+pub mod slow_epoch;
+mod trace_id;
+pub mod validator_performance;
+mod validator_pubkey_index;
 
-pub use self::beacon_chain::{BeaconChain, BlockProcessingOutcome, InvalidBlock, ValidBlock};
+pub use self::attestation_pool::AttestationPool;
+pub use self::beacon_chain::{
+    AttestationSubscriber, BeaconChain, BlockProcessingOutcome, InvalidBlock, ValidBlock,
+    DEFAULT_MAX_SKIP_SLOTS, DEFAULT_PROPOSER_RE_ORG_THRESHOLD,
+};
 pub use self::checkpoint::CheckPoint;
+pub use self::duties_reader::{DutiesReader, ProposerDuties};
 pub use self::errors::BeaconChainError;
+pub use self::future_block_queue::FutureBlockQueue;
+pub use self::head_info::HeadInfo;
+pub use self::iter::AncestorIter;
+pub use self::slow_epoch::{SlowEpochConfig, DEFAULT_EPOCH_PROCESSING_BUDGET};
+pub use self::trace_id::TraceId;
 pub use db;
 pub use fork_choice;
+pub use merkle_proof::IncrementalMerkleTree;
 pub use parking_lot;
 pub use slot_clock;
+pub use state_processing::per_block_processing::errors::AttestationValidationError;
 pub use types;
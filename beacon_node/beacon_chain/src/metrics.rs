@@ -0,0 +1,55 @@
+//! Simple counters used to monitor contention on the locks of the beacon chain.
+//!
+//! Counters are global and only ever increase. Their values are exported by `gather`. The
+//! `Counter` type is shared by the counters of the other beacon node crates.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// A monotonically increasing counter.
+pub struct Counter(AtomicUsize);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Counter(AtomicUsize::new(0))
+    }
+
+    /// Increments the counter by one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Increments the counter by `n`.
+    pub fn inc_by(&self, n: usize) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of the counter.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The number of reads of the head which waited for the lock to be released.
+pub static HEAD_READ_WAITS: Counter = Counter::new();
+/// The total time spent by reads of the head waiting for the lock, in microseconds.
+pub static HEAD_READ_WAIT_MICROS: Counter = Counter::new();
+/// The number of updates of the head which waited for the lock to be released.
+pub static HEAD_WRITE_WAITS: Counter = Counter::new();
+/// The total time spent by updates of the head waiting for the lock, in microseconds.
+pub static HEAD_WRITE_WAIT_MICROS: Counter = Counter::new();
+
+/// Records a wait for a lock which began at `started` in the `waits` and `wait_micros` counters.
+pub fn record_wait(waits: &Counter, wait_micros: &Counter, started: Instant) {
+    waits.inc();
+    wait_micros.inc_by(started.elapsed().as_micros() as usize);
+}
+
+/// Returns the name and value of every counter, for export.
+pub fn gather() -> Vec<(&'static str, usize)> {
+    vec![
+        ("head_read_waits", HEAD_READ_WAITS.get()),
+        ("head_read_wait_micros", HEAD_READ_WAIT_MICROS.get()),
+        ("head_write_waits", HEAD_WRITE_WAITS.get()),
+        ("head_write_wait_micros", HEAD_WRITE_WAIT_MICROS.get()),
+    ]
+}
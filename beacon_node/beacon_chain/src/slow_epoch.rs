@@ -0,0 +1,140 @@
+//! Diagnostics for epoch transitions which take longer than expected.
+//!
+//! Per-epoch processing which exceeds its budget is logged with a breakdown of its stages and the
+//! size of the validator set, and its pre-state may be written to a file for offline profiling.
+
+use log::{info, warn};
+use ssz::ssz_encode;
+use state_processing::EpochProcessingTimings;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use types::{BeaconState, ChainSpec, Epoch};
+
+/// The default time per-epoch processing may take before it is reported.
+pub const DEFAULT_EPOCH_PROCESSING_BUDGET: Duration = Duration::from_secs(1);
+/// The maximum number of pre-states written to the dump directory in the life of the node, so
+/// that a run of slow epochs cannot fill the disk.
+pub const MAX_SLOW_EPOCH_DUMPS: usize = 4;
+
+/// Configures the reporting of slow epoch transitions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowEpochConfig {
+    /// Epoch transitions taking longer than this are reported.
+    pub budget: Duration,
+    /// The directory to which the pre-state of a slow epoch transition is written, if any.
+    ///
+    /// The state is cloned before every epoch transition of the canonical chain whilst set and
+    /// fewer than `MAX_SLOW_EPOCH_DUMPS` have been written, so that it is available should the
+    /// transition prove slow.
+    pub dump_dir: Option<PathBuf>,
+}
+
+impl Default for SlowEpochConfig {
+    fn default() -> Self {
+        SlowEpochConfig {
+            budget: DEFAULT_EPOCH_PROCESSING_BUDGET,
+            dump_dir: None,
+        }
+    }
+}
+
+/// An epoch transition which exceeded its budget, to be reported once any lock on its state is
+/// released.
+pub(crate) struct SlowEpoch {
+    epoch: Epoch,
+    validators: usize,
+    active_validators: usize,
+    timings: EpochProcessingTimings,
+    /// The state before the transition, if it is to be written to the dump directory.
+    pre_state: Option<BeaconState>,
+}
+
+impl SlowEpoch {
+    /// Records the transition to `post_state`, which took `timings`.
+    pub fn new(
+        timings: EpochProcessingTimings,
+        post_state: &BeaconState,
+        pre_state: Option<BeaconState>,
+        spec: &ChainSpec,
+    ) -> Self {
+        SlowEpoch {
+            epoch: post_state.previous_epoch(spec),
+            validators: post_state.validator_registry.len(),
+            active_validators: post_state
+                .get_active_validator_indices(post_state.current_epoch(spec))
+                .len(),
+            timings,
+            pre_state,
+        }
+    }
+
+    /// Logs the transition with a breakdown of its stages, writing its pre-state to the dump
+    /// directory if held.
+    pub fn report(&self, config: &SlowEpochConfig) {
+        let timings = &self.timings;
+        warn!(
+            "Processing epoch {} took {:?}, exceeding the budget of {:?}, with {} validators of \
+             which {} active: caches {:?}, validator statuses {:?}, justification {:?}, \
+             crosslinks {:?}, rewards {:?}, registry {:?}, slashings and exits {:?}, final \
+             updates {:?}",
+            self.epoch,
+            timings.total(),
+            config.budget,
+            self.validators,
+            self.active_validators,
+            timings.caches,
+            timings.validator_statuses,
+            timings.justification,
+            timings.crosslinks,
+            timings.rewards,
+            timings.registry,
+            timings.slashings_and_exits,
+            timings.final_updates,
+        );
+
+        if let (Some(dir), Some(pre_state)) = (&config.dump_dir, &self.pre_state) {
+            match dump_state(dir, pre_state) {
+                Ok(path) => info!(
+                    "Wrote the pre-state of slow epoch {} to {:?}",
+                    self.epoch, path
+                ),
+                Err(e) => warn!(
+                    "Unable to write the pre-state of slow epoch {}: {}",
+                    self.epoch, e
+                ),
+            }
+        }
+    }
+}
+
+/// Writes the SSZ encoding of `state` to a file in `dir` named by its slot, returning its path.
+fn dump_state(dir: &Path, state: &BeaconState) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("slow_epoch_pre_state_{}.ssz", state.slot));
+    fs::write(&path, ssz_encode(state))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssz::Decodable;
+    use types::test_utils::TestingBeaconStateBuilder;
+
+    #[test]
+    fn dumps_decodable_pre_state() {
+        let spec = ChainSpec::few_validators();
+        let (state, _keypairs) =
+            TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec).build();
+
+        let dir = std::env::temp_dir().join(format!("slow_epoch_test_{}", std::process::id()));
+        let path = dump_state(&dir, &state).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (decoded, _) = BeaconState::ssz_decode(&bytes, 0).unwrap();
+        assert_eq!(decoded, state);
+    }
+}
@@ -0,0 +1,24 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_TRACE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifies an object (or the network message carrying it) as it passes from the network,
+/// through sync and into the beacon chain.
+///
+/// Included in the logs of each subsystem so the handling of a single object can be followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(usize);
+
+impl TraceId {
+    /// Returns a new, unique, `TraceId`.
+    pub fn next() -> Self {
+        TraceId(NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:x}", self.0)
+    }
+}
@@ -0,0 +1,138 @@
+//! Reports of how well a validator attested in recent epochs, computed from the stored blocks and
+//! states of the canonical chain.
+
+use crate::beacon_chain::BeaconChain;
+use crate::errors::BeaconChainError as Error;
+use db::ClientDB;
+use fork_choice::ForkChoice;
+use slot_clock::SlotClock;
+use state_processing::per_epoch_processing::validator_statuses::ValidatorStatuses;
+use state_processing::per_slot_processing;
+use types::{BeaconBlockHeader, BeaconState, ChainSpec, Epoch, RelativeEpoch};
+
+/// The attestation performance of a validator in a single epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochPerformance {
+    pub epoch: Epoch,
+    /// The validator was active, so was expected to attest.
+    pub active: bool,
+    /// An attestation by the validator was included, which requires a correct source.
+    pub source_correct: bool,
+    /// The included attestation voted for the canonical epoch boundary block.
+    pub target_correct: bool,
+    /// The included attestation voted for the canonical block at its slot.
+    pub head_correct: bool,
+    /// The number of slots between the attestation and its inclusion, if included.
+    pub inclusion_delay: Option<u64>,
+    /// The change in the validator's balance, in Gwei, when the rewards and penalties of the
+    /// epoch were applied. Includes any rewards for crosslinks and for proposing blocks which
+    /// included attestations.
+    pub balance_change: i64,
+}
+
+/// Returns the performance of the validator at `validator_index` in up to `epochs` epochs, newest
+/// first.
+///
+/// Attestations may be included until the end of the epoch following theirs, so the latest epoch
+/// reported is two before the epoch of the head block.
+pub fn validator_performance<T, U, F>(
+    chain: &BeaconChain<T, U, F>,
+    validator_index: usize,
+    epochs: u64,
+) -> Result<Vec<EpochPerformance>, Error>
+where
+    T: ClientDB,
+    U: SlotClock,
+    F: ForkChoice,
+{
+    let spec = &chain.spec;
+    let (head_root, head_epoch) = {
+        let head = chain.head();
+        (
+            head.beacon_block_root,
+            head.beacon_block.slot.epoch(spec.slots_per_epoch),
+        )
+    };
+
+    let mut ancestors = chain.ancestor_iter(head_root).peekable();
+    let mut performance = vec![];
+    for i in 0..epochs {
+        let epoch = match head_epoch.as_u64().checked_sub(2 + i).map(Epoch::new) {
+            Some(epoch) if epoch >= spec.genesis_epoch => epoch,
+            _ => break,
+        };
+        // the state at the last slot of the following epoch, before the transition which rewards
+        // the attestations of `epoch`
+        let slot = Epoch::new(epoch.as_u64() + 2).start_slot(spec.slots_per_epoch) - 1;
+
+        while ancestors
+            .peek()
+            .map_or(false, |(_, block_slot)| *block_slot > slot)
+        {
+            ancestors.next();
+        }
+        let block_root = match ancestors.peek() {
+            Some((block_root, _)) => *block_root,
+            None => break,
+        };
+
+        let block = chain
+            .block_store
+            .get_deserialized(&block_root)?
+            .ok_or_else(|| Error::MissingBeaconBlock(block_root))?;
+        let mut state = chain
+            .state_store
+            .get_deserialized(&block.state_root)?
+            .ok_or_else(|| Error::MissingBeaconState(block.state_root))?;
+
+        let header = block.block_header();
+        for _ in state.slot.as_u64()..slot.as_u64() {
+            per_slot_processing(&mut state, &header, spec)?;
+        }
+        performance.push(epoch_performance(state, &header, validator_index, spec)?);
+    }
+
+    Ok(performance)
+}
+
+/// Computes the performance of a validator in the previous epoch of `state`, which must be at the
+/// last slot of its epoch.
+fn epoch_performance(
+    mut state: BeaconState,
+    header: &BeaconBlockHeader,
+    validator_index: usize,
+    spec: &ChainSpec,
+) -> Result<EpochPerformance, Error> {
+    let mut performance = EpochPerformance {
+        epoch: state.previous_epoch(spec),
+        active: false,
+        source_correct: false,
+        target_correct: false,
+        head_correct: false,
+        inclusion_delay: None,
+        balance_change: 0,
+    };
+    // the validator had not yet been deposited
+    if validator_index >= state.validator_registry.len() {
+        return Ok(performance);
+    }
+
+    state.build_epoch_cache(RelativeEpoch::Previous, spec)?;
+    state.build_epoch_cache(RelativeEpoch::Current, spec)?;
+    let mut statuses = ValidatorStatuses::new(&state, spec)?;
+    statuses.process_attestations(&state, spec)?;
+    let status = &statuses.statuses[validator_index];
+
+    performance.active = status.is_active_in_previous_epoch;
+    performance.source_correct = status.is_previous_epoch_attester;
+    performance.target_correct = status.is_previous_epoch_boundary_attester;
+    performance.head_correct = status.is_previous_epoch_head_attester;
+    performance.inclusion_delay = status.inclusion_info.map(|info| info.distance.as_u64());
+
+    let balance_before = state.validator_balances[validator_index];
+    per_slot_processing(&mut state, header, spec)?;
+    let balance_after = state.validator_balances[validator_index];
+    performance.balance_change = balance_after as i64 - balance_before as i64;
+
+    Ok(performance)
+}
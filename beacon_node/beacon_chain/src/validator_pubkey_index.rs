@@ -0,0 +1,222 @@
+use db::stores::{ValidatorStore, ValidatorStoreError};
+use db::ClientDB;
+use ssz::{ssz_encode, Decodable};
+use std::collections::HashMap;
+use std::sync::Arc;
+use types::{PublicKey, Validator};
+
+/// A map from validator public keys to their index in the validator registry.
+///
+/// Validators are only ever appended to the registry, in deposit order, so each index is assigned
+/// a public key once. The public key of each index is persisted in the `ValidatorStore`, so the map
+/// is restored on startup rather than rebuilt by scanning a state. The number of keys persisted is
+/// stored with them.
+pub struct ValidatorPubkeyIndex<T: ClientDB> {
+    store: Arc<ValidatorStore<T>>,
+    indices: HashMap<PublicKey, usize>,
+    /// The number of registry entries in the map. Not `indices.len()`, as duplicate public keys
+    /// may be added during testing.
+    len: usize,
+}
+
+impl<T: ClientDB> ValidatorPubkeyIndex<T> {
+    /// Loads the public keys persisted in `store`, then adds any further validators in `registry`.
+    ///
+    /// Persisted keys also in `registry` are compared in their compressed encoding and the decoded
+    /// registry keys are indexed, so only the persisted keys beyond `registry` are decompressed.
+    /// The persisted keys are deleted if they do not match `registry`, e.g. if the database was
+    /// written by a different chain, or if any cannot be read.
+    pub fn load(
+        store: Arc<ValidatorStore<T>>,
+        registry: &[Validator],
+    ) -> Result<Self, ValidatorStoreError> {
+        let count = match store.get_public_key_count() {
+            Ok(Some(count)) => count,
+            // keys written without a count, or with an unreadable one, are counted so none are
+            // left behind; the count is rewritten once the registry is added
+            Ok(None) | Err(ValidatorStoreError::DecodeError) => {
+                let mut count = 0;
+                while store.get_public_key_bytes_by_index(count)?.is_some() {
+                    count += 1;
+                }
+                count
+            }
+            Err(e) => return Err(e),
+        };
+        let mut persisted = Vec::with_capacity(count);
+        for index in 0..count {
+            let pubkey = match (
+                store.get_public_key_bytes_by_index(index)?,
+                registry.get(index),
+            ) {
+                (Some(ref bytes), Some(validator)) if *bytes == ssz_encode(&validator.pubkey) => {
+                    Some(validator.pubkey.clone())
+                }
+                (Some(_), Some(_)) | (None, _) => None,
+                (Some(bytes), None) => PublicKey::ssz_decode(&bytes, 0)
+                    .ok()
+                    .map(|(pubkey, _)| pubkey),
+            };
+            match pubkey {
+                Some(pubkey) => persisted.push(pubkey),
+                None => break,
+            }
+        }
+
+        let mut pubkey_index = ValidatorPubkeyIndex {
+            store,
+            indices: HashMap::new(),
+            len: 0,
+        };
+
+        if persisted.len() == count {
+            for pubkey in persisted {
+                pubkey_index.insert(pubkey);
+            }
+        } else {
+            for index in 0..count {
+                pubkey_index.store.delete_public_key_by_index(index)?;
+            }
+            pubkey_index.store.put_public_key_count(0)?;
+        }
+
+        pubkey_index.update(registry)?;
+        Ok(pubkey_index)
+    }
+
+    /// Adds and persists the validators in `registry` which are not yet in the map.
+    ///
+    /// The number of keys persisted is written after the keys themselves, so an interrupted
+    /// update leaves a consistent prefix.
+    pub fn update(&mut self, registry: &[Validator]) -> Result<(), ValidatorStoreError> {
+        if registry.len() <= self.len {
+            return Ok(());
+        }
+        for validator in registry.iter().skip(self.len) {
+            self.store
+                .put_public_key_by_index(self.len, &validator.pubkey)?;
+            self.insert(validator.pubkey.clone());
+        }
+        self.store.put_public_key_count(self.len)
+    }
+
+    /// Returns the registry index of the validator with `pubkey`, if known.
+    pub fn get(&self, pubkey: &PublicKey) -> Option<usize> {
+        self.indices.get(pubkey).cloned()
+    }
+
+    /// Returns the number of registry entries in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no registry entries are in the map.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn insert(&mut self, pubkey: PublicKey) {
+        // the first index of a duplicated key is kept, matching a scan of the registry
+        self.indices.entry(pubkey).or_insert(self.len);
+        self.len += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::MemoryStore;
+    use types::Keypair;
+
+    fn registry(count: usize) -> Vec<Validator> {
+        (0..count)
+            .map(|_| Validator {
+                pubkey: Keypair::random().pk,
+                ..Validator::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn indexes_registry() {
+        let store = Arc::new(ValidatorStore::new(Arc::new(MemoryStore::open())));
+        let mut registry = registry(4);
+
+        let mut pubkey_index = ValidatorPubkeyIndex::load(store, &registry[..2]).unwrap();
+        assert_eq!(pubkey_index.len(), 2);
+        assert_eq!(pubkey_index.get(&registry[2].pubkey), None);
+
+        pubkey_index.update(&registry).unwrap();
+        for (i, validator) in registry.iter().enumerate() {
+            assert_eq!(pubkey_index.get(&validator.pubkey), Some(i));
+        }
+
+        registry.push(registry[0].clone());
+        pubkey_index.update(&registry).unwrap();
+        assert_eq!(pubkey_index.len(), 5);
+        assert_eq!(pubkey_index.get(&registry[0].pubkey), Some(0));
+    }
+
+    #[test]
+    fn restores_persisted_keys() {
+        let store = Arc::new(ValidatorStore::new(Arc::new(MemoryStore::open())));
+        let registry = registry(4);
+
+        ValidatorPubkeyIndex::load(store.clone(), &registry).unwrap();
+
+        let pubkey_index = ValidatorPubkeyIndex::load(store, &registry[..1]).unwrap();
+        assert_eq!(pubkey_index.len(), 4);
+        assert_eq!(pubkey_index.get(&registry[3].pubkey), Some(3));
+    }
+
+    #[test]
+    fn discards_keys_of_another_chain() {
+        let store = Arc::new(ValidatorStore::new(Arc::new(MemoryStore::open())));
+
+        ValidatorPubkeyIndex::load(store.clone(), &registry(4)).unwrap();
+
+        let other_registry = registry(2);
+        let pubkey_index = ValidatorPubkeyIndex::load(store.clone(), &other_registry).unwrap();
+        assert_eq!(pubkey_index.len(), 2);
+        assert_eq!(pubkey_index.get(&other_registry[1].pubkey), Some(1));
+
+        // keys of the other chain beyond its registry must not be restored
+        let pubkey_index = ValidatorPubkeyIndex::load(store, &[]).unwrap();
+        assert_eq!(pubkey_index.len(), 2);
+    }
+
+    #[test]
+    fn rebuilds_unreadable_keys() {
+        let store = Arc::new(ValidatorStore::new(Arc::new(MemoryStore::open())));
+        let registry = registry(4);
+
+        ValidatorPubkeyIndex::load(store.clone(), &registry).unwrap();
+        store.delete_public_key_by_index(1).unwrap();
+
+        let pubkey_index = ValidatorPubkeyIndex::load(store.clone(), &registry[..3]).unwrap();
+        assert_eq!(pubkey_index.len(), 3);
+        assert_eq!(pubkey_index.get(&registry[3].pubkey), None);
+        assert_eq!(store.get_public_key_count(), Ok(Some(3)));
+    }
+
+    #[test]
+    fn adopts_keys_persisted_without_a_count() {
+        let store = Arc::new(ValidatorStore::new(Arc::new(MemoryStore::open())));
+        let registry = registry(3);
+
+        for (i, validator) in registry.iter().enumerate() {
+            store.put_public_key_by_index(i, &validator.pubkey).unwrap();
+        }
+
+        let pubkey_index = ValidatorPubkeyIndex::load(store.clone(), &registry[..2]).unwrap();
+        assert_eq!(pubkey_index.len(), 3);
+        assert_eq!(pubkey_index.get(&registry[2].pubkey), Some(2));
+        assert_eq!(store.get_public_key_count(), Ok(Some(3)));
+
+        // keys of another chain written without a count are deleted, not left behind
+        let other_registry = registry(1);
+        let pubkey_index = ValidatorPubkeyIndex::load(store.clone(), &other_registry).unwrap();
+        assert_eq!(pubkey_index.len(), 1);
+        assert_eq!(store.get_public_key_by_index(2), Ok(None));
+    }
+}
@@ -1,9 +1,9 @@
 use super::ValidatorHarness;
-use beacon_chain::{BeaconChain, BlockProcessingOutcome};
+use beacon_chain::{BeaconChain, BlockProcessingOutcome, DEFAULT_MAX_SKIP_SLOTS};
 pub use beacon_chain::{BeaconChainError, CheckPoint};
 use db::{
-    stores::{BeaconBlockStore, BeaconStateStore},
-    MemoryDB,
+    stores::{BeaconBlockStore, BeaconStateStore, ValidatorStore},
+    MemoryStore,
 };
 use fork_choice::BitwiseLMDGhost;
 use log::debug;
@@ -22,10 +22,10 @@ use types::{test_utils::TestingBeaconStateBuilder, *};
 /// This test harness is useful for testing validator and internal state transition logic. It
 /// is not useful for testing that multiple beacon nodes can reach consensus.
 pub struct BeaconChainHarness {
-    pub db: Arc<MemoryDB>,
-    pub beacon_chain: Arc<BeaconChain<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>>,
-    pub block_store: Arc<BeaconBlockStore<MemoryDB>>,
-    pub state_store: Arc<BeaconStateStore<MemoryDB>>,
+    pub db: Arc<MemoryStore>,
+    pub beacon_chain: Arc<BeaconChain<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>>,
+    pub block_store: Arc<BeaconBlockStore<MemoryStore>>,
+    pub state_store: Arc<BeaconStateStore<MemoryStore>>,
     pub validators: Vec<ValidatorHarness>,
     pub spec: Arc<ChainSpec>,
 }
@@ -36,9 +36,19 @@ impl BeaconChainHarness {
     /// - A keypair, `BlockProducer` and `Attester` for each validator.
     /// - A new BeaconChain struct where the given validators are in the genesis.
     pub fn new(spec: ChainSpec, validator_count: usize) -> Self {
-        let db = Arc::new(MemoryDB::open());
+        Self::with_max_skip_slots(spec, validator_count, Some(DEFAULT_MAX_SKIP_SLOTS))
+    }
+
+    /// As `new`, with blocks refused if they skip more than `max_skip_slots` slots.
+    pub fn with_max_skip_slots(
+        spec: ChainSpec,
+        validator_count: usize,
+        max_skip_slots: Option<u64>,
+    ) -> Self {
+        let db = Arc::new(MemoryStore::open());
         let block_store = Arc::new(BeaconBlockStore::new(db.clone()));
         let state_store = Arc::new(BeaconStateStore::new(db.clone()));
+        let validator_store = Arc::new(ValidatorStore::new(db.clone()));
         let slot_clock = TestingSlotClock::new(spec.genesis_slot.as_u64());
         let fork_choice = BitwiseLMDGhost::new(block_store.clone(), state_store.clone());
 
@@ -50,18 +60,20 @@ impl BeaconChainHarness {
         genesis_block.state_root = Hash256::from_slice(&genesis_state.hash_tree_root());
 
         // Create the Beacon Chain
-        let beacon_chain = Arc::new(
-            BeaconChain::from_genesis(
-                state_store.clone(),
-                block_store.clone(),
-                slot_clock,
-                genesis_state,
-                genesis_block,
-                spec.clone(),
-                fork_choice,
-            )
-            .unwrap(),
-        );
+        let mut beacon_chain = BeaconChain::from_genesis(
+            state_store.clone(),
+            block_store.clone(),
+            validator_store,
+            slot_clock,
+            genesis_state,
+            genesis_block,
+            &[],
+            spec.clone(),
+            fork_choice,
+        )
+        .unwrap();
+        beacon_chain.max_skip_slots = max_skip_slots;
+        let beacon_chain = Arc::new(beacon_chain);
 
         let spec = Arc::new(spec);
 
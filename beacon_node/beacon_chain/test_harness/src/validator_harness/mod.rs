@@ -7,7 +7,7 @@ use attester::{Attester, Error as AttestationPollError};
 use beacon_chain::BeaconChain;
 use block_proposer::PollOutcome as BlockPollOutcome;
 use block_proposer::{BlockProducer, Error as BlockPollError};
-use db::MemoryDB;
+use db::MemoryStore;
 use direct_beacon_node::DirectBeaconNode;
 use direct_duties::DirectDuties;
 use fork_choice::BitwiseLMDGhost;
@@ -30,15 +30,15 @@ pub enum AttestationProduceError {
 
 type TestingBlockProducer = BlockProducer<
     TestingSlotClock,
-    DirectBeaconNode<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>,
-    DirectDuties<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>,
+    DirectBeaconNode<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>,
+    DirectDuties<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>,
     LocalSigner,
 >;
 
 type TestingAttester = Attester<
     TestingSlotClock,
-    DirectBeaconNode<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>,
-    DirectDuties<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>,
+    DirectBeaconNode<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>,
+    DirectDuties<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>,
     LocalSigner,
 >;
 
@@ -51,9 +51,10 @@ pub struct ValidatorHarness {
     pub block_producer: TestingBlockProducer,
     pub attester: TestingAttester,
     pub spec: Arc<ChainSpec>,
-    pub epoch_map: Arc<DirectDuties<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>>,
+    pub epoch_map: Arc<DirectDuties<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>>,
     pub keypair: Keypair,
-    pub beacon_node: Arc<DirectBeaconNode<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>>,
+    pub beacon_node:
+        Arc<DirectBeaconNode<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>>,
     pub slot_clock: Arc<TestingSlotClock>,
     pub signer: Arc<LocalSigner>,
 }
@@ -65,7 +66,7 @@ impl ValidatorHarness {
     /// A `BlockProducer` and `Attester` is created..
     pub fn new(
         keypair: Keypair,
-        beacon_chain: Arc<BeaconChain<MemoryDB, TestingSlotClock, BitwiseLMDGhost<MemoryDB>>>,
+        beacon_chain: Arc<BeaconChain<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>>>,
         spec: Arc<ChainSpec>,
     ) -> Self {
         let slot_clock = Arc::new(TestingSlotClock::new(spec.genesis_slot.as_u64()));
@@ -1,7 +1,22 @@
+use beacon_chain::checkpoint_sync::TrustedCheckpoint;
+use beacon_chain::validator_performance::validator_performance;
+use beacon_chain::{
+    BeaconChain, BeaconChainError, BlockProcessingOutcome, IncrementalMerkleTree, InvalidBlock,
+    TraceId, ValidBlock,
+};
+use db::stores::{BeaconBlockStore, BeaconStateStore, ValidatorStore};
+use db::MemoryStore;
 use env_logger::{Builder, Env};
+use fork_choice::BitwiseLMDGhost;
 use log::debug;
+use slot_clock::TestingSlotClock;
+use ssz::{SignedRoot, TreeHash};
+use state_processing::per_block_processing::errors::{BlockInvalid, BlockProcessingError};
+use state_processing::per_block_processing::get_deposit_leaf;
+use std::sync::Arc;
 use test_harness::BeaconChainHarness;
-use types::ChainSpec;
+use types::test_utils::{TestingBeaconStateBuilder, TestingDepositBuilder};
+use types::{BeaconBlock, ChainSpec, Deposit, Domain, Epoch, Hash256, Slot};
 
 #[test]
 fn it_can_build_on_genesis_block() {
@@ -42,3 +57,468 @@ fn it_can_produce_past_first_epoch_boundary() {
 
     assert_eq!(dump.len() as u64, blocks + 1); // + 1 for genesis block.
 }
+
+#[test]
+#[ignore]
+fn it_reports_validator_performance() {
+    let spec = ChainSpec::few_validators();
+    let validator_count = 8;
+
+    let mut harness = BeaconChainHarness::new(spec, validator_count);
+
+    for _ in 0..harness.spec.slots_per_epoch * 4 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+
+    let performance =
+        validator_performance(&harness.beacon_chain, 0, 8).expect("Performance failed.");
+
+    // only the epochs whose attestations can no longer be included are reported
+    assert_eq!(performance.len(), 3);
+    for epoch in performance {
+        assert!(epoch.active);
+        assert!(epoch.source_correct);
+        assert!(epoch.target_correct);
+        assert!(epoch.head_correct);
+        assert!(epoch.inclusion_delay.is_some());
+    }
+}
+
+/// Starts a second chain in `db` from the head of the chain of `harness`, as a trusted checkpoint.
+fn checkpoint_chain(
+    harness: &BeaconChainHarness,
+    db: Arc<MemoryStore>,
+) -> BeaconChain<MemoryStore, TestingSlotClock, BitwiseLMDGhost<MemoryStore>> {
+    let checkpoint = {
+        let head = harness.beacon_chain.head();
+        TrustedCheckpoint {
+            block: head.beacon_block.clone(),
+            state: head.beacon_state.clone(),
+        }
+    };
+    let genesis_block = harness
+        .block_store
+        .get_deserialized(&harness.beacon_chain.genesis_block_root)
+        .unwrap()
+        .unwrap();
+    let genesis_state = harness
+        .state_store
+        .get_deserialized(&genesis_block.state_root)
+        .unwrap()
+        .unwrap();
+
+    let block_store = Arc::new(BeaconBlockStore::new(db.clone()));
+    let state_store = Arc::new(BeaconStateStore::new(db.clone()));
+    let slot_clock = TestingSlotClock::new(checkpoint.block.slot.as_u64());
+    let fork_choice = BitwiseLMDGhost::new(block_store.clone(), state_store.clone());
+    BeaconChain::from_checkpoint(
+        state_store,
+        block_store,
+        Arc::new(ValidatorStore::new(db)),
+        slot_clock,
+        checkpoint,
+        &genesis_state,
+        &genesis_block,
+        (*harness.spec).clone(),
+        fork_choice,
+    )
+    .unwrap()
+}
+
+#[test]
+fn it_imports_blocks_after_a_checkpoint() {
+    let spec = ChainSpec::few_validators();
+    let mut harness = BeaconChainHarness::new(spec, 8);
+    for _ in 0..3 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+
+    // a second node, started from the head of the first
+    let chain = checkpoint_chain(&harness, Arc::new(MemoryStore::open()));
+
+    harness.advance_chain_with_block();
+    harness.run_fork_choice();
+    let (block, block_root) = {
+        let head = harness.beacon_chain.head();
+        (head.beacon_block.clone(), head.beacon_block_root)
+    };
+    chain.slot_clock.set_slot(block.slot.as_u64());
+    chain.advance_state(block.slot).unwrap();
+
+    assert_eq!(
+        chain.process_block(block),
+        Ok(BlockProcessingOutcome::ValidBlock(ValidBlock::Processed))
+    );
+    chain.fork_choice().unwrap();
+    assert_eq!(chain.head().beacon_block_root, block_root);
+}
+
+#[test]
+fn it_backfills_historical_blocks_across_restarts() {
+    let spec = ChainSpec::few_validators();
+    let mut harness = BeaconChainHarness::new(spec, 8);
+    for _ in 0..3 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+
+    // the blocks before the head, in slot order
+    let mut history: Vec<BeaconBlock> = harness
+        .chain_dump()
+        .unwrap()
+        .into_iter()
+        .skip(1)
+        .map(|checkpoint| checkpoint.beacon_block)
+        .collect();
+    history.reverse();
+    let (genesis_block, blocks) = history.split_first().unwrap();
+    let head = harness.beacon_chain.head().beacon_block.clone();
+
+    let db = Arc::new(MemoryStore::open());
+    let chain = checkpoint_chain(&harness, db.clone());
+    assert_eq!(
+        chain.oldest_block_parent(),
+        Some((head.slot, head.previous_block_root))
+    );
+
+    // blocks which do not lead to the oldest block held are refused
+    assert!(chain.import_historical_blocks(&blocks[..1]).is_err());
+    assert_eq!(
+        chain.import_historical_blocks(blocks).unwrap(),
+        blocks.len()
+    );
+    let oldest_block_parent = Some((blocks[0].slot, blocks[0].previous_block_root));
+    assert_eq!(chain.oldest_block_parent(), oldest_block_parent);
+    assert!(chain
+        .block_store
+        .exists(&blocks[0].canonical_root())
+        .unwrap());
+
+    // a restart from the same checkpoint continues from the blocks already backfilled
+    let chain = checkpoint_chain(&harness, db);
+    assert_eq!(chain.oldest_block_parent(), oldest_block_parent);
+    assert_eq!(
+        chain
+            .import_historical_blocks(&[genesis_block.clone()])
+            .unwrap(),
+        1
+    );
+    assert_eq!(chain.oldest_block_parent(), None);
+}
+
+#[test]
+fn it_serves_the_deposit_tree_of_genesis_deposits_across_restarts() {
+    let spec = ChainSpec::few_validators();
+    let db = Arc::new(MemoryStore::open());
+
+    let (mut genesis_state, keypairs) =
+        TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec).build();
+    let deposits: Vec<Deposit> = keypairs[..2]
+        .iter()
+        .enumerate()
+        .map(|(index, keypair)| {
+            let mut builder = TestingDepositBuilder::new(keypair.pk.clone(), 32_000_000_000);
+            builder.set_index(index as u64);
+            builder.build()
+        })
+        .collect();
+    genesis_state.deposit_index = deposits.len() as u64;
+
+    let mut expected = IncrementalMerkleTree::new(spec.deposit_contract_tree_depth as usize);
+    for deposit in &deposits {
+        expected.push(get_deposit_leaf(deposit));
+    }
+
+    let start = |genesis_deposits: &[Deposit]| {
+        let mut genesis_block = BeaconBlock::empty(&spec);
+        genesis_block.state_root = Hash256::from_slice(&genesis_state.hash_tree_root());
+        let block_store = Arc::new(BeaconBlockStore::new(db.clone()));
+        let state_store = Arc::new(BeaconStateStore::new(db.clone()));
+        let fork_choice = BitwiseLMDGhost::new(block_store.clone(), state_store.clone());
+        BeaconChain::from_genesis(
+            state_store,
+            block_store,
+            Arc::new(ValidatorStore::new(db.clone())),
+            TestingSlotClock::new(spec.genesis_slot.as_u64()),
+            genesis_state.clone(),
+            genesis_block,
+            genesis_deposits,
+            spec.clone(),
+            fork_choice,
+        )
+        .unwrap()
+    };
+
+    let tree = start(&deposits).finalized_deposit_tree().unwrap();
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.root(), expected.root());
+
+    // the leaves are read back from the database after a restart
+    let tree = start(&[]).finalized_deposit_tree().unwrap();
+    assert_eq!(tree.root(), expected.root());
+}
+
+/// Returns the blocks of the chain of `harness` after genesis, in slot order.
+fn blocks_after_genesis(harness: &BeaconChainHarness) -> Vec<BeaconBlock> {
+    let mut blocks: Vec<BeaconBlock> = harness
+        .chain_dump()
+        .unwrap()
+        .into_iter()
+        .map(|checkpoint| checkpoint.beacon_block)
+        .collect();
+    blocks.reverse();
+    blocks.split_off(1)
+}
+
+#[test]
+fn it_returns_the_canonical_block_roots_of_a_range() {
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    for _ in 0..4 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+    let blocks = blocks_after_genesis(&harness);
+    let roots_of = |blocks: &[BeaconBlock]| -> Vec<(Hash256, Slot)> {
+        blocks
+            .iter()
+            .map(|block| (block.canonical_root(), block.slot))
+            .collect()
+    };
+
+    // a block of another fork is indexed by its slot, but is not canonical
+    let mut fork = blocks[1].clone();
+    fork.state_root = Hash256::from_low_u64_be(1);
+    harness
+        .block_store
+        .put_block(&fork.canonical_root(), &fork)
+        .unwrap();
+
+    let chain = &harness.beacon_chain;
+    assert_eq!(
+        chain.get_block_roots(blocks[1].slot, 2),
+        roots_of(&blocks[1..3])
+    );
+    // the range ends at the head
+    assert_eq!(
+        chain.get_block_roots(blocks[2].slot, 100),
+        roots_of(&blocks[2..])
+    );
+    assert!(chain.get_block_roots(blocks[3].slot + 1, 10).is_empty());
+    assert!(chain.get_block_roots(blocks[0].slot, 0).is_empty());
+}
+
+/// Returns a harness sharing the genesis of a chain of `blocks.len()` blocks, with its clock at
+/// the slot of the last block.
+fn segment_harness(blocks: &[BeaconBlock]) -> BeaconChainHarness {
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    while harness.beacon_chain.present_slot() < blocks.last().unwrap().slot {
+        harness.increment_beacon_chain_slot();
+    }
+    harness
+}
+
+#[test]
+fn it_imports_a_chain_segment() {
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    for _ in 0..3 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+    let blocks = blocks_after_genesis(&harness);
+
+    let other = segment_harness(&blocks);
+    let outcomes = other
+        .beacon_chain
+        .process_chain_segment(blocks.clone(), TraceId::next());
+
+    assert_eq!(outcomes.len(), blocks.len());
+    for outcome in outcomes {
+        assert_eq!(
+            outcome.unwrap(),
+            BlockProcessingOutcome::ValidBlock(ValidBlock::Processed)
+        );
+    }
+    for block in &blocks {
+        assert!(other.block_store.exists(&block.canonical_root()).unwrap());
+    }
+}
+
+#[test]
+fn it_verifies_every_signature_of_a_chain_segment() {
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    for _ in 0..3 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+    let mut blocks = blocks_after_genesis(&harness);
+
+    // the second block carries a RANDAO reveal of another validator, but is signed by its
+    // proposer, so only verifying the RANDAO reveal finds it invalid
+    let proposer = harness.beacon_chain.block_proposer(blocks[1].slot).unwrap();
+    let epoch = blocks[1].slot.epoch(harness.spec.slots_per_epoch);
+    blocks[1].body.randao_reveal = harness
+        .validator_sign(
+            (proposer + 1) % 8,
+            &epoch.hash_tree_root()[..],
+            epoch,
+            Domain::Randao,
+        )
+        .unwrap();
+    blocks[1].signature = harness
+        .validator_sign(
+            proposer,
+            &blocks[1].signed_root()[..],
+            epoch,
+            Domain::BeaconBlock,
+        )
+        .unwrap();
+
+    let other = segment_harness(&blocks);
+    let outcomes = other
+        .beacon_chain
+        .process_chain_segment(blocks.clone(), TraceId::next());
+
+    // importing stops at the invalid block
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(
+        outcomes[0].as_ref().unwrap(),
+        &BlockProcessingOutcome::ValidBlock(ValidBlock::Processed)
+    );
+    assert_eq!(
+        outcomes[1].as_ref().unwrap(),
+        &BlockProcessingOutcome::InvalidBlock(InvalidBlock::PerBlockProcessingError(
+            BlockProcessingError::Invalid(BlockInvalid::BadRandaoSignature)
+        ))
+    );
+}
+
+#[test]
+fn it_rejects_a_chain_segment_which_is_not_linked() {
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    for _ in 0..3 {
+        harness.advance_chain_with_block();
+    }
+    harness.run_fork_choice();
+    let blocks = blocks_after_genesis(&harness);
+
+    // the third block does not follow the first
+    let segment = vec![blocks[0].clone(), blocks[2].clone()];
+    let other = segment_harness(&blocks);
+    let outcomes = other
+        .beacon_chain
+        .process_chain_segment(segment, TraceId::next());
+
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(
+        outcomes[1].as_ref().unwrap(),
+        &BlockProcessingOutcome::InvalidBlock(InvalidBlock::ParentUnknown)
+    );
+}
+
+#[test]
+fn it_imports_a_block_received_after_its_slot() {
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    harness.increment_beacon_chain_slot();
+    let block = harness.produce_block();
+
+    // the block is delayed by two slots
+    harness.increment_beacon_chain_slot();
+    let present_slot = harness.increment_beacon_chain_slot();
+
+    assert_eq!(
+        harness.beacon_chain.process_block(block.clone()).unwrap(),
+        BlockProcessingOutcome::ValidBlock(ValidBlock::Processed)
+    );
+    assert_eq!(
+        harness.beacon_chain.head().beacon_block_root,
+        block.canonical_root()
+    );
+    // the state built on is that of the present slot
+    assert_eq!(harness.beacon_chain.state.read().slot, present_slot);
+}
+
+#[test]
+fn it_builds_on_the_head_chosen_by_fork_choice() {
+    let mut other = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    for _ in 0..3 {
+        other.advance_chain_with_block();
+    }
+    other.run_fork_choice();
+    let blocks = blocks_after_genesis(&other);
+
+    // a fork of a single block, which skips the first slot
+    let mut harness = BeaconChainHarness::new(ChainSpec::few_validators(), 8);
+    harness.increment_beacon_chain_slot();
+    harness.increment_beacon_chain_slot();
+    let fork = harness.produce_block();
+    harness.beacon_chain.process_block(fork.clone()).unwrap();
+    assert_eq!(
+        harness.beacon_chain.head().beacon_block_root,
+        fork.canonical_root()
+    );
+
+    // the longer chain, whose blocks carry attestations to it, becomes the head
+    harness.increment_beacon_chain_slot();
+    for block in &blocks {
+        assert_eq!(
+            harness.beacon_chain.process_block(block.clone()).unwrap(),
+            BlockProcessingOutcome::ValidBlock(ValidBlock::Processed)
+        );
+    }
+    harness.run_fork_choice();
+    let head_root = blocks.last().unwrap().canonical_root();
+    assert_eq!(harness.beacon_chain.head().beacon_block_root, head_root);
+
+    // the next block is built on the new head, rather than on the fork
+    harness.increment_beacon_chain_slot();
+    assert_eq!(harness.produce_block().previous_block_root, head_root);
+}
+
+#[test]
+fn it_refuses_blocks_skipping_too_many_slots() {
+    let mut harness =
+        BeaconChainHarness::with_max_skip_slots(ChainSpec::few_validators(), 8, Some(2));
+    let genesis_slot = harness.spec.genesis_slot;
+
+    // a block may skip up to the maximum
+    for _ in 0..3 {
+        harness.increment_beacon_chain_slot();
+    }
+    let block = harness.produce_block();
+    assert_eq!(
+        harness.beacon_chain.process_block(block.clone()).unwrap(),
+        BlockProcessingOutcome::ValidBlock(ValidBlock::Processed)
+    );
+
+    for _ in 0..4 {
+        harness.increment_beacon_chain_slot();
+    }
+    let late_block = harness.produce_block();
+    assert_eq!(
+        harness
+            .beacon_chain
+            .process_block(late_block.clone())
+            .unwrap(),
+        BlockProcessingOutcome::InvalidBlock(InvalidBlock::TooManySkippedSlots {
+            parent_slot: genesis_slot + 3,
+            block_slot: late_block.slot,
+        })
+    );
+    assert!(!harness
+        .block_store
+        .exists(&late_block.canonical_root())
+        .unwrap());
+
+    // nor is a state advanced further to verify an attestation
+    let genesis_block_root = harness.beacon_chain.genesis_block_root;
+    let epoch = Epoch::new(1) + genesis_slot.epoch(harness.spec.slots_per_epoch);
+    match harness
+        .beacon_chain
+        .epoch_boundary_state(genesis_block_root, epoch)
+    {
+        Err(BeaconChainError::StateAdvanceTooFar { from, .. }) => assert_eq!(from, genesis_slot),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
@@ -1,15 +1,24 @@
+use beacon_chain::{SlowEpochConfig, DEFAULT_MAX_SKIP_SLOTS, DEFAULT_PROPOSER_RE_ORG_THRESHOLD};
 use clap::ArgMatches;
 use db::DBType;
-use fork_choice::ForkChoiceAlgorithm;
-use network::NetworkConfig;
-use slog::error;
+use fork_choice::{ForkChoiceAlgorithm, PROPOSER_SCORE_BOOST};
+use network::{NetworkConfig, SyncConfig};
+use slog::{error, info, warn};
 use std::fs;
+use std::io;
 use std::net::SocketAddr;
 use std::net::{IpAddr, Ipv4Addr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use types::multiaddr::Protocol;
 use types::multiaddr::ToMultiaddr;
-use types::ChainSpec;
+use types::{ChainSpec, Multiaddr};
+
+/// The file within the datadir holding the private key which identifies the node to its peers.
+const NODE_KEY_FILENAME: &str = "node_key";
+
+/// The file within the datadir holding a genesis time given relative to the time of first start.
+const GENESIS_TIME_FILENAME: &str = "genesis_time";
 
 /// Stores the client configuration for this Lighthouse instance.
 #[derive(Debug, Clone)]
@@ -17,10 +26,30 @@ pub struct ClientConfig {
     pub data_dir: PathBuf,
     pub spec: ChainSpec,
     pub net_conf: network::NetworkConfig,
+    pub sync_conf: SyncConfig,
     pub fork_choice: ForkChoiceAlgorithm,
     pub db_type: DBType,
     pub db_name: PathBuf,
+    /// The backend of an existing database in the datadir to copy into a database of `db_type`
+    /// on startup.
+    pub migrate_db_from: Option<DBType>,
     pub rpc_conf: rpc::RPCConfig,
+    /// A directory of exported chain files to import before joining the network.
+    pub import_era_dir: Option<PathBuf>,
+    /// The file or http(s) URL of an SSZ encoded finalized state to start the chain from, rather
+    /// than genesis.
+    pub checkpoint_state: Option<String>,
+    /// The file or http(s) URL of the SSZ encoded block of `checkpoint_state`.
+    pub checkpoint_block: Option<String>,
+    /// Replaces the genesis time of the genesis state, so that devnet nodes can agree on a genesis.
+    pub genesis_time: Option<u64>,
+    /// The maximum number of skipped slots between an imported block and its parent, if limited.
+    pub max_skip_slots: Option<u64>,
+    /// The percentage of a slot's committee weight below which a late head block is re-orged out
+    /// by a local proposer, if enabled.
+    pub proposer_re_org_threshold: Option<u64>,
+    /// The reporting of epoch transitions which exceed their time budget.
+    pub slow_epoch: SlowEpochConfig,
     //pub ipc_conf:
 }
 
@@ -35,20 +64,30 @@ impl Default for ClientConfig {
             .unwrap_or_else(|_| panic!("Unable to create {:?}", &data_dir));
 
         let default_spec = ChainSpec::lighthouse_testnet();
-        let default_net_conf = NetworkConfig::new(default_spec.boot_nodes.clone());
+        let mut default_net_conf = NetworkConfig::new(default_spec.boot_nodes.clone());
+        default_net_conf.key_path = Some(data_dir.join(NODE_KEY_FILENAME));
 
         Self {
             data_dir: data_dir.clone(),
             // default to foundation for chain specs
             spec: default_spec,
             net_conf: default_net_conf,
+            sync_conf: SyncConfig::default(),
             // default to bitwise LMD Ghost
             fork_choice: ForkChoiceAlgorithm::BitwiseLMDGhost,
             // default to memory db for now
             db_type: DBType::Memory,
             // default db name for disk-based dbs
             db_name: data_dir.join("chain.db"),
+            migrate_db_from: None,
             rpc_conf: rpc::RPCConfig::default(),
+            import_era_dir: None,
+            checkpoint_state: None,
+            checkpoint_block: None,
+            genesis_time: None,
+            max_skip_slots: Some(DEFAULT_MAX_SKIP_SLOTS),
+            proposer_re_org_threshold: None,
+            slow_epoch: SlowEpochConfig::default(),
         }
     }
 }
@@ -88,12 +127,439 @@ impl ClientConfig {
             }
         }
 
+        if let Some(address_str) = args.value_of("discovery-address") {
+            match address_str.parse::<IpAddr>() {
+                Ok(address) => config.net_conf.discovery_address = Some(address),
+                Err(_) => {
+                    error!(log, "Invalid discovery address"; "address" => address_str);
+                    return Err("Invalid discovery address");
+                }
+            }
+        }
+
+        if args.is_present("upnp") {
+            config.net_conf.upnp_enabled = true;
+        }
+
+        if args.is_present("mdns") {
+            config.net_conf.mdns_enabled = true;
+        }
+
+        if let Some(boot_nodes_str) = args.value_of("boot-nodes") {
+            let mut boot_nodes = vec![];
+            for boot_node in boot_nodes_str.split(',') {
+                match boot_node.parse::<Multiaddr>() {
+                    Ok(multiaddr) => boot_nodes.push(multiaddr),
+                    Err(_) => {
+                        error!(log, "Invalid boot node address"; "address" => boot_node);
+                        return Err("Invalid boot node address");
+                    }
+                }
+            }
+            config.net_conf.boot_nodes = boot_nodes;
+        }
+
+        if let Some(trusted_peers_str) = args.value_of("trusted-peers") {
+            let mut trusted_peers = vec![];
+            for trusted_peer in trusted_peers_str.split(',') {
+                let has_peer_id = |multiaddr: &Multiaddr| {
+                    multiaddr.iter().any(|protocol| match protocol {
+                        Protocol::P2p(_) => true,
+                        _ => false,
+                    })
+                };
+                match trusted_peer.parse::<Multiaddr>() {
+                    Ok(ref multiaddr) if has_peer_id(multiaddr) => {
+                        trusted_peers.push(multiaddr.clone())
+                    }
+                    _ => {
+                        error!(log, "Invalid trusted peer address, expected a multiaddr ending in /p2p/<peer id>"; "address" => trusted_peer);
+                        return Err("Invalid trusted peer address");
+                    }
+                }
+            }
+            config.net_conf.trusted_peers = trusted_peers;
+        }
+
+        // Per-peer upload limits, supplied in megabytes
+        if let Some(limit_str) = args.value_of("peer-hourly-upload-limit") {
+            match limit_str
+                .parse::<u64>()
+                .ok()
+                .and_then(|limit| limit.checked_mul(1_000_000))
+            {
+                Some(limit) => config.net_conf.peer_hourly_upload_limit = Some(limit),
+                None => {
+                    error!(log, "Invalid hourly upload limit"; "limit" => limit_str);
+                    return Err("Invalid hourly upload limit");
+                }
+            }
+        }
+
+        if let Some(limit_str) = args.value_of("peer-daily-upload-limit") {
+            match limit_str
+                .parse::<u64>()
+                .ok()
+                .and_then(|limit| limit.checked_mul(1_000_000))
+            {
+                Some(limit) => config.net_conf.peer_daily_upload_limit = Some(limit),
+                None => {
+                    error!(log, "Invalid daily upload limit"; "limit" => limit_str);
+                    return Err("Invalid daily upload limit");
+                }
+            }
+        }
+
+        if let Some(duration_str) = args.value_of("peer-ban-duration") {
+            if let Ok(seconds) = duration_str.parse::<u64>() {
+                config.net_conf.peer_ban_duration = Duration::from_secs(seconds);
+            } else {
+                error!(log, "Invalid peer ban duration"; "duration" => duration_str);
+                return Err("Invalid peer ban duration");
+            }
+        }
+
+        if let Some(count_str) = args.value_of("target-peers") {
+            match count_str.parse::<usize>() {
+                Ok(count) if count > 0 => config.net_conf.target_peers = count,
+                _ => {
+                    error!(log, "Invalid target peer count"; "count" => count_str);
+                    return Err("Invalid target peer count");
+                }
+            }
+        }
+
+        if let Some(count_str) = args.value_of("max-requests-per-peer") {
+            match count_str.parse::<usize>() {
+                Ok(count) if count > 0 => config.net_conf.max_requests_per_peer = count,
+                _ => {
+                    error!(log, "Invalid maximum requests per peer"; "count" => count_str);
+                    return Err("Invalid maximum requests per peer");
+                }
+            }
+        }
+
+        if args.is_present("snappy-compression") {
+            config.net_conf.snappy_compression = true;
+        }
+
+        if let Some(size_str) = args.value_of("max-gossip-size") {
+            match size_str.parse::<usize>() {
+                Ok(size) if size > 0 => config.net_conf.max_gossip_size = size,
+                _ => {
+                    error!(log, "Invalid maximum gossip message size"; "size" => size_str);
+                    return Err("Invalid maximum gossip message size");
+                }
+            }
+        }
+
+        if let Some(size_str) = args.value_of("max-rpc-size") {
+            match size_str.parse::<usize>() {
+                Ok(size) if size > 0 => config.net_conf.max_rpc_size = size,
+                _ => {
+                    error!(log, "Invalid maximum RPC message size"; "size" => size_str);
+                    return Err("Invalid maximum RPC message size");
+                }
+            }
+        }
+
+        // Global upload limit, supplied in megabits per second
+        if let Some(mbps_str) = args.value_of("max-upload-mbps") {
+            match mbps_str.parse::<f64>() {
+                Ok(mbps) if mbps > 0.0 => {
+                    config.net_conf.max_upload_bytes_per_second =
+                        Some((mbps * 1_000_000.0 / 8.0) as u64);
+                }
+                _ => {
+                    error!(log, "Invalid maximum upload rate"; "mbps" => mbps_str);
+                    return Err("Invalid maximum upload rate");
+                }
+            }
+        }
+
+        // Gossip topics which are not subscribed to
+        if let Some(topics) = args.values_of("disable-gossip-topic") {
+            for topic in topics {
+                if let Err(e) = config.net_conf.disable_topic(topic) {
+                    error!(log, "Invalid gossip topic"; "error" => e);
+                    return Err("Invalid gossip topic");
+                }
+                info!(log, "Gossip topic disabled"; "topic" => topic);
+            }
+        }
+
+        if args.is_present("subscribe-all-subnets") {
+            if let Err(e) = config.net_conf.enable_all_subnets() {
+                error!(log, "Cannot subscribe to all subnets"; "error" => e);
+                return Err("Cannot subscribe to all subnets");
+            }
+        }
+
+        /* Sync related arguments */
+
+        // A file of sync parameters, each of which may be overridden by the arguments below
+        if let Some(sync_config_file) = args.value_of("sync-config") {
+            match SyncConfig::from_file(Path::new(sync_config_file)) {
+                Ok(sync_conf) => config.sync_conf = sync_conf,
+                Err(e) => {
+                    error!(log, "Invalid sync config file"; "error" => e);
+                    return Err("Invalid sync config file");
+                }
+            }
+        }
+
+        if let Some(tolerance_str) = args.value_of("slot-import-tolerance") {
+            if let Ok(tolerance) = tolerance_str.parse::<u64>() {
+                config.sync_conf.slot_import_tolerance = tolerance;
+            } else {
+                error!(log, "Invalid slot import tolerance"; "tolerance" => tolerance_str);
+                return Err("Invalid slot import tolerance");
+            }
+        }
+
+        if let Some(hysteresis_str) = args.value_of("slot-import-hysteresis") {
+            if let Ok(hysteresis) = hysteresis_str.parse::<u64>() {
+                config.sync_conf.slot_import_hysteresis = hysteresis;
+            } else {
+                error!(log, "Invalid slot import hysteresis"; "hysteresis" => hysteresis_str);
+                return Err("Invalid slot import hysteresis");
+            }
+        }
+
+        if let Some(epochs_str) = args.value_of("epochs-per-batch") {
+            match epochs_str.parse::<u64>() {
+                Ok(epochs) if epochs > 0 => config.sync_conf.epochs_per_batch = epochs,
+                _ => {
+                    error!(log, "Invalid epochs per batch"; "epochs" => epochs_str);
+                    return Err("Invalid epochs per batch");
+                }
+            }
+        }
+
+        if let Some(batches_str) = args.value_of("max-pending-batches") {
+            match batches_str.parse::<usize>() {
+                Ok(batches) if batches > 0 => config.sync_conf.max_pending_batches = batches,
+                _ => {
+                    error!(log, "Invalid max pending batches"; "batches" => batches_str);
+                    return Err("Invalid max pending batches");
+                }
+            }
+        }
+
+        if let Some(timeout_str) = args.value_of("batch-timeout") {
+            match timeout_str.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => {
+                    config.sync_conf.batch_timeout = Duration::from_secs(seconds)
+                }
+                _ => {
+                    error!(log, "Invalid batch timeout"; "timeout" => timeout_str);
+                    return Err("Invalid batch timeout");
+                }
+            }
+        }
+
+        if let Some(tolerance_str) = args.value_of("synced-tolerance") {
+            if let Ok(tolerance) = tolerance_str.parse::<u64>() {
+                config.sync_conf.synced_tolerance = tolerance;
+            } else {
+                error!(log, "Invalid synced tolerance"; "tolerance" => tolerance_str);
+                return Err("Invalid synced tolerance");
+            }
+        }
+
+        if let Some(failures_str) = args.value_of("max-batch-import-failures") {
+            match failures_str.parse::<u32>() {
+                Ok(failures) if failures > 0 => {
+                    config.sync_conf.max_batch_import_failures = failures
+                }
+                _ => {
+                    error!(log, "Invalid max batch import failures"; "failures" => failures_str);
+                    return Err("Invalid max batch import failures");
+                }
+            }
+        }
+
+        if let Err(e) = config.sync_conf.validate() {
+            error!(log, "Invalid sync config"; "error" => e);
+            return Err(e);
+        }
+
+        /* Chain spec related arguments */
+
+        // Spec constant overrides, only permitted for experiments
+        if let Some(overrides) = args.values_of("spec-override") {
+            if !args.is_present("unsafe-experimental") {
+                error!(log, "Spec overrides require --unsafe-experimental");
+                return Err("Spec overrides require --unsafe-experimental");
+            }
+            for spec_override in overrides {
+                let mut split = spec_override.splitn(2, '=');
+                let (name, value) = match (split.next(), split.next()) {
+                    (Some(name), Some(value)) => (name, value),
+                    _ => {
+                        error!(log, "Invalid spec override, expected NAME=VALUE"; "override" => spec_override);
+                        return Err("Invalid spec override");
+                    }
+                };
+                if let Err(e) = config.spec.apply_override(name, value) {
+                    error!(log, "Invalid spec override"; "error" => e);
+                    return Err("Invalid spec override");
+                }
+                warn!(log, "Overriding spec constant"; "name" => name, "value" => value);
+            }
+        }
+
+        // Limit on the slots skipped by imported blocks
+        if let Some(max_skip_slots) = args.value_of("max-skip-slots") {
+            if max_skip_slots == "none" {
+                config.max_skip_slots = None;
+            } else if let Ok(max_skip_slots) = max_skip_slots.parse::<u64>() {
+                config.max_skip_slots = Some(max_skip_slots);
+            } else {
+                error!(log, "Invalid max skip slots"; "max_skip_slots" => max_skip_slots);
+                return Err("Invalid max skip slots");
+            }
+        }
+
+        // Re-orging out late head blocks when proposing
+        if args.is_present("proposer-re-org") {
+            config.proposer_re_org_threshold = Some(DEFAULT_PROPOSER_RE_ORG_THRESHOLD);
+        }
+
+        if let Some(threshold) = args.value_of("proposer-re-org-threshold") {
+            match threshold.parse::<u64>() {
+                // a re-org block must outweigh the late head with its proposer boost alone
+                Ok(threshold) if threshold < PROPOSER_SCORE_BOOST => {
+                    config.proposer_re_org_threshold = Some(threshold);
+                }
+                _ => {
+                    error!(log, "Invalid proposer re-org threshold"; "threshold" => threshold);
+                    return Err("Invalid proposer re-org threshold");
+                }
+            }
+        }
+
+        // Diagnostics of slow epoch transitions
+        if let Some(budget_str) = args.value_of("epoch-processing-budget") {
+            if let Ok(millis) = budget_str.parse::<u64>() {
+                config.slow_epoch.budget = Duration::from_millis(millis);
+            } else {
+                error!(log, "Invalid epoch processing budget"; "budget" => budget_str);
+                return Err("Invalid epoch processing budget");
+            }
+        }
+
+        if let Some(dir) = args.value_of("slow-epoch-dump-dir") {
+            config.slow_epoch.dump_dir = Some(PathBuf::from(dir));
+        }
+
         /* Filesystem related arguments */
 
         // Custom datadir
         if let Some(dir) = args.value_of("datadir") {
             config.data_dir = PathBuf::from(dir.to_string());
+            config.db_name = config.data_dir.join("chain.db");
+        };
+
+        // Genesis time override, for coordinating the genesis of a devnet
+        if let Some(genesis_time_str) = args.value_of("genesis-time") {
+            let genesis_time = match parse_genesis_time(genesis_time_str) {
+                Some(genesis_time) => genesis_time,
+                None => {
+                    error!(log, "Invalid genesis time"; "genesis_time" => genesis_time_str);
+                    return Err("Invalid genesis time");
+                }
+            };
+            match genesis_time {
+                GenesisTime::Absolute(genesis_time) => {
+                    warn!(log, "Overriding genesis time"; "genesis_time" => genesis_time);
+                    config.genesis_time = Some(genesis_time);
+                }
+                GenesisTime::FromNow(_) => {
+                    match resolve_in_dir(genesis_time, &config.data_dir, unix_now()) {
+                        Ok(genesis_time) => {
+                            // a relative time is resolved by each node, so other machines must be
+                            // given the absolute time to agree on the genesis
+                            warn!(
+                                log,
+                                "Overriding genesis time, pass it to every other node as --genesis-time";
+                                "genesis_time" => genesis_time
+                            );
+                            config.genesis_time = Some(genesis_time);
+                        }
+                        Err(e) => {
+                            error!(log, "Unable to store genesis time"; "error" => format!("{}", e));
+                            return Err("Unable to store genesis time");
+                        }
+                    }
+                }
+            }
+        }
+
+        // The database backend, which may need to be enabled at build time
+        if let Some(name) = args.value_of("db-backend") {
+            match DBType::from_name(name) {
+                Some(db_type) if db_type.is_available() => config.db_type = db_type,
+                Some(_) => {
+                    error!(log, "Database backend not supported by this build"; "backend" => name);
+                    return Err("Database backend not supported by this build");
+                }
+                None => {
+                    error!(log, "Invalid database backend"; "backend" => name);
+                    return Err("Invalid database backend");
+                }
+            }
+        }
+
+        // An existing database of another backend to migrate from
+        if let Some(name) = args.value_of("migrate-db-from") {
+            match DBType::from_name(name) {
+                Some(from)
+                    if from.is_available()
+                        && from != config.db_type
+                        && from != DBType::Memory
+                        && config.db_type != DBType::Memory =>
+                {
+                    config.migrate_db_from = Some(from)
+                }
+                _ => {
+                    error!(
+                        log,
+                        "Invalid database migration";
+                        "from" => name,
+                        "to" => format!("{:?}", config.db_type),
+                    );
+                    return Err("Invalid database migration");
+                }
+            }
+        }
+
+        // The node key, kept in the datadir unless a path is given
+        config.net_conf.key_path = match args.value_of("node-key") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(config.data_dir.join(NODE_KEY_FILENAME)),
         };
+        if args.is_present("new-identity") {
+            config.net_conf.new_identity = true;
+        }
+
+        // Peer events are audited to a separate file
+        if let Some(path) = args.value_of("peer-audit-log") {
+            config.net_conf.audit_log = Some(PathBuf::from(path));
+        }
+
+        // Exported chain files to import on startup
+        if let Some(dir) = args.value_of("import-era") {
+            config.import_era_dir = Some(PathBuf::from(dir));
+        }
+
+        if let Some(source) = args.value_of("checkpoint-state") {
+            config.checkpoint_state = Some(source.to_string());
+        }
+
+        if let Some(source) = args.value_of("checkpoint-block") {
+            config.checkpoint_block = Some(source.to_string());
+        }
 
         /* RPC related arguments */
 
@@ -119,6 +585,112 @@ impl ClientConfig {
             }
         }
 
+        if let Some(dir) = args.value_of("rpc-export-dir") {
+            config.rpc_conf.export_dir = Some(PathBuf::from(dir));
+        }
+
+        if args.is_present("sync-gated-publishing") {
+            config.rpc_conf.publish_while_syncing = false;
+        }
+
         Ok(config)
     }
 }
+
+/// A genesis time given on the command line.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum GenesisTime {
+    /// A unix timestamp in seconds.
+    Absolute(u64),
+    /// A number of seconds after the time it is resolved.
+    FromNow(u64),
+}
+
+impl GenesisTime {
+    /// The unix timestamp of the genesis, where `now` is the current unix timestamp.
+    fn resolve(self, now: u64) -> Option<u64> {
+        match self {
+            GenesisTime::Absolute(genesis_time) => Some(genesis_time),
+            GenesisTime::FromNow(delay) => now.checked_add(delay),
+        }
+    }
+}
+
+/// Parses a genesis time, given either as a unix timestamp in seconds, as `now`, or as `now+N`
+/// for `N` seconds from now.
+fn parse_genesis_time(genesis_time: &str) -> Option<GenesisTime> {
+    let mut split = genesis_time.splitn(2, '+');
+    match (split.next(), split.next()) {
+        (Some("now"), None) => Some(GenesisTime::FromNow(0)),
+        (Some("now"), Some(delay)) => delay.parse::<u64>().ok().map(GenesisTime::FromNow),
+        (Some(timestamp), None) => timestamp.parse::<u64>().ok().map(GenesisTime::Absolute),
+        _ => None,
+    }
+}
+
+/// Resolves a genesis time given as by `--genesis-time` to a unix timestamp, for distributing to
+/// every node of a devnet. Returns `None` if it is invalid.
+pub fn resolve_genesis_time(genesis_time: &str) -> Option<u64> {
+    parse_genesis_time(genesis_time)?.resolve(unix_now())
+}
+
+/// Resolves `genesis_time` once per datadir, so that a restarted node keeps the genesis time
+/// resolved when it first started.
+fn resolve_in_dir(genesis_time: GenesisTime, data_dir: &Path, now: u64) -> io::Result<u64> {
+    let path = data_dir.join(GENESIS_TIME_FILENAME);
+    if path.exists() {
+        return fs::read_to_string(&path)?
+            .trim()
+            .parse::<u64>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+    let resolved = genesis_time
+        .resolve(now)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "genesis time overflows"))?;
+    fs::create_dir_all(data_dir)?;
+    fs::write(&path, resolved.to_string())?;
+    Ok(resolved)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genesis_times_are_parsed() {
+        assert_eq!(
+            parse_genesis_time("1560000000"),
+            Some(GenesisTime::Absolute(1_560_000_000))
+        );
+        assert_eq!(parse_genesis_time("now"), Some(GenesisTime::FromNow(0)));
+        assert_eq!(parse_genesis_time("now+60"), Some(GenesisTime::FromNow(60)));
+        assert_eq!(parse_genesis_time("now+"), None);
+        assert_eq!(parse_genesis_time("now+-1"), None);
+        assert_eq!(parse_genesis_time("1560000000+60"), None);
+        assert_eq!(parse_genesis_time("tomorrow"), None);
+
+        assert_eq!(GenesisTime::FromNow(60).resolve(1000), Some(1060));
+        assert_eq!(GenesisTime::Absolute(5).resolve(1000), Some(5));
+        assert_eq!(GenesisTime::FromNow(1).resolve(u64::max_value()), None);
+    }
+
+    #[test]
+    fn a_relative_genesis_time_is_kept_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("genesis_time_test_{}", std::process::id()));
+
+        let first = resolve_in_dir(GenesisTime::FromNow(60), &dir, 1000).unwrap();
+        assert_eq!(first, 1060);
+        // restarted later, the node keeps the genesis time it first resolved
+        let restarted = resolve_in_dir(GenesisTime::FromNow(60), &dir, 2000).unwrap();
+        assert_eq!(restarted, first);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
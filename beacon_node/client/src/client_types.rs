@@ -1,8 +1,9 @@
 use crate::ClientConfig;
 use beacon_chain::{
-    db::{ClientDB, DiskDB, MemoryDB},
+    checkpoint_sync::TrustedCheckpoint,
+    db::{stores::COLUMNS, ClientDB, DiskStore, MemoryStore},
     fork_choice::BitwiseLMDGhost,
-    initialise,
+    initialise::{self, InitialiseError},
     slot_clock::{SlotClock, SystemTimeSlotClock},
     BeaconChain,
 };
@@ -15,35 +16,87 @@ pub trait ClientTypes {
     type SlotClock: SlotClock + 'static;
     type ForkChoice: ForkChoice + 'static;
 
+    /// Returns an error if the database cannot be used with this binary and configuration, or the
+    /// chain cannot be started from the trusted `checkpoint`, if given.
     fn initialise_beacon_chain(
         config: &ClientConfig,
-    ) -> Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>>;
+        checkpoint: Option<TrustedCheckpoint>,
+    ) -> Result<Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>>, InitialiseError>;
 }
 
 pub struct StandardClientType;
 
 impl ClientTypes for StandardClientType {
-    type DB = DiskDB;
+    type DB = DiskStore;
     type SlotClock = SystemTimeSlotClock;
-    type ForkChoice = BitwiseLMDGhost<DiskDB>;
+    type ForkChoice = BitwiseLMDGhost<DiskStore>;
 
     fn initialise_beacon_chain(
         config: &ClientConfig,
-    ) -> Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>> {
-        initialise::initialise_beacon_chain(&config.spec, Some(&config.db_name))
+        checkpoint: Option<TrustedCheckpoint>,
+    ) -> Result<Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>>, InitialiseError>
+    {
+        let db = Arc::new(DiskStore::open(&config.db_name, Some(&COLUMNS[..])));
+        initialise::initialise_beacon_chain(
+            &config.spec,
+            db,
+            config.genesis_time,
+            config.max_skip_slots,
+            config.proposer_re_org_threshold,
+            config.slow_epoch.clone(),
+            checkpoint,
+        )
+    }
+}
+
+/// A client persisting the chain with redb rather than RocksDB.
+#[cfg(feature = "redb")]
+pub struct RedbClientType;
+
+#[cfg(feature = "redb")]
+impl ClientTypes for RedbClientType {
+    type DB = beacon_chain::db::RedbStore;
+    type SlotClock = SystemTimeSlotClock;
+    type ForkChoice = BitwiseLMDGhost<Self::DB>;
+
+    fn initialise_beacon_chain(
+        config: &ClientConfig,
+        checkpoint: Option<TrustedCheckpoint>,
+    ) -> Result<Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>>, InitialiseError>
+    {
+        let db = Arc::new(Self::DB::open(&config.db_name, &COLUMNS[..]));
+        initialise::initialise_beacon_chain(
+            &config.spec,
+            db,
+            config.genesis_time,
+            config.max_skip_slots,
+            config.proposer_re_org_threshold,
+            config.slow_epoch.clone(),
+            checkpoint,
+        )
     }
 }
 
 pub struct TestingClientType;
 
 impl ClientTypes for TestingClientType {
-    type DB = MemoryDB;
+    type DB = MemoryStore;
     type SlotClock = SystemTimeSlotClock;
-    type ForkChoice = BitwiseLMDGhost<MemoryDB>;
+    type ForkChoice = BitwiseLMDGhost<MemoryStore>;
 
     fn initialise_beacon_chain(
         config: &ClientConfig,
-    ) -> Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>> {
-        initialise::initialise_test_beacon_chain(&config.spec, None)
+        checkpoint: Option<TrustedCheckpoint>,
+    ) -> Result<Arc<BeaconChain<Self::DB, Self::SlotClock, Self::ForkChoice>>, InitialiseError>
+    {
+        Ok(initialise::initialise_test_beacon_chain(
+            &config.spec,
+            None,
+            config.genesis_time,
+            config.max_skip_slots,
+            config.proposer_re_org_threshold,
+            config.slow_epoch.clone(),
+            checkpoint,
+        ))
     }
 }
@@ -0,0 +1,189 @@
+use beacon_chain::{db::ClientDB, fork_choice::ForkChoice, slot_clock::SlotClock, BeaconChain};
+use logging::LogBuffer;
+use std::fmt::Write;
+use std::fs;
+use std::panic::{self, PanicInfo};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use types::ChainSpec;
+
+/// The number of recent log records included in a crash report.
+pub const CRASH_REPORT_LOG_RECORDS: usize = 256;
+
+/// Describes the state of the chain, for inclusion in a crash report.
+type ChainSummary = Box<Fn() -> String + Send + Sync>;
+
+/// Writes a report to the datadir when the node panics, containing the version, the chain spec,
+/// the head, finalized and last processed blocks, recent log records and recent warnings and
+/// errors.
+///
+/// The report is written by a panic hook, so nothing it reads may block: locks which are held are
+/// skipped rather than waited for.
+#[derive(Clone)]
+pub struct CrashReporter {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    dir: PathBuf,
+    spec: ChainSpec,
+    log_buffer: LogBuffer,
+    error_logs: LogBuffer,
+    chain_summary: Mutex<Option<ChainSummary>>,
+}
+
+impl CrashReporter {
+    /// Returns a reporter writing to `dir`, including the records held by `log_buffer` and
+    /// `error_logs`.
+    pub fn new(
+        dir: PathBuf,
+        spec: ChainSpec,
+        log_buffer: LogBuffer,
+        error_logs: LogBuffer,
+    ) -> Self {
+        CrashReporter {
+            inner: Arc::new(Inner {
+                dir,
+                spec,
+                log_buffer,
+                error_logs,
+                chain_summary: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Installs the panic hook. The previous hook is still called after the report is written,
+    /// so the panic is printed as before.
+    pub fn install(&self) {
+        let reporter = self.clone();
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            match reporter.write_report(info) {
+                Ok(path) => eprintln!("Crash report written to {}", path.display()),
+                Err(e) => eprintln!("Unable to write crash report: {:?}", e),
+            }
+            previous_hook(info);
+        }));
+    }
+
+    /// Includes the state of `beacon_chain` in any later report.
+    pub fn watch_chain<T, U, F>(&self, beacon_chain: Arc<BeaconChain<T, U, F>>)
+    where
+        T: ClientDB + 'static,
+        U: SlotClock + 'static,
+        F: ForkChoice + 'static,
+    {
+        let summary: ChainSummary = Box::new(move || summarize_chain(&beacon_chain));
+        if let Ok(mut chain_summary) = self.inner.chain_summary.lock() {
+            *chain_summary = Some(summary);
+        }
+    }
+
+    /// Writes a report of the panic, returning the path of the report.
+    fn write_report(&self, info: &PanicInfo) -> std::io::Result<PathBuf> {
+        let inner = &self.inner;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let mut report = String::new();
+        let _ = writeln!(report, "Lighthouse crash report");
+        let _ = writeln!(report, "version: {}", version::version());
+        let _ = writeln!(report, "time: {}", timestamp);
+        let _ = writeln!(
+            report,
+            "thread: {}",
+            thread::current().name().unwrap_or("<unnamed>")
+        );
+        let _ = writeln!(report, "panic: {}", info);
+
+        let _ = writeln!(report, "\n[chain]");
+        let chain_summary = match inner.chain_summary.try_lock() {
+            Ok(chain_summary) => match chain_summary.as_ref() {
+                Some(summarize) => summarize(),
+                None => "not yet initialised\n".to_string(),
+            },
+            Err(_) => "unavailable\n".to_string(),
+        };
+        report.push_str(&chain_summary);
+
+        let _ = writeln!(report, "\n[spec]\n{:#?}", inner.spec);
+
+        let _ = writeln!(report, "\n[recent warnings and errors]");
+        write_log_entries(&mut report, &inner.error_logs);
+        let _ = writeln!(report, "\n[recent logs]");
+        write_log_entries(&mut report, &inner.log_buffer);
+
+        let _ = writeln!(report, "\n[backtrace]\n{:?}", backtrace::Backtrace::new());
+
+        fs::create_dir_all(&inner.dir)?;
+        let path = inner.dir.join(format!("crash-{}.txt", timestamp));
+        fs::write(&path, report)?;
+        Ok(path)
+    }
+}
+
+/// Appends the records held by `log_buffer` to the report, one per line.
+fn write_log_entries(report: &mut String, log_buffer: &LogBuffer) {
+    match log_buffer.try_entries() {
+        Some(entries) => {
+            for entry in entries {
+                let _ = writeln!(report, "{}", entry);
+            }
+        }
+        None => {
+            let _ = writeln!(report, "unavailable");
+        }
+    }
+}
+
+/// Describes the head, finalized and last processed blocks of `beacon_chain`, skipping those
+/// which are locked.
+fn summarize_chain<T, U, F>(beacon_chain: &BeaconChain<T, U, F>) -> String
+where
+    T: ClientDB,
+    U: SlotClock,
+    F: ForkChoice,
+{
+    let mut summary = String::new();
+    match beacon_chain.try_head() {
+        Some(head) => {
+            let _ = writeln!(
+                summary,
+                "head: {:?} at slot {}",
+                head.beacon_block_root, head.beacon_block.slot
+            );
+        }
+        None => {
+            let _ = writeln!(summary, "head: unavailable");
+        }
+    }
+    match beacon_chain.try_finalized_head() {
+        Some(finalized) => {
+            let _ = writeln!(
+                summary,
+                "finalized: {:?} at epoch {}",
+                finalized.beacon_block_root,
+                finalized
+                    .beacon_block
+                    .slot
+                    .epoch(beacon_chain.spec.slots_per_epoch)
+            );
+        }
+        None => {
+            let _ = writeln!(summary, "finalized: unavailable");
+        }
+    }
+    match beacon_chain.last_processed_block() {
+        Some((root, slot)) => {
+            let _ = writeln!(summary, "last processed block: {:?} at slot {}", root, slot);
+        }
+        None => {
+            let _ = writeln!(summary, "last processed block: none");
+        }
+    }
+    summary
+}
@@ -2,18 +2,25 @@ extern crate slog;
 
 mod client_config;
 pub mod client_types;
+pub mod crash_report;
 pub mod error;
+mod migrate;
 pub mod notifier;
 
-use beacon_chain::BeaconChain;
-pub use client_config::ClientConfig;
+use beacon_chain::{checkpoint_sync::TrustedCheckpoint, era, BeaconChain};
+pub use client_config::{resolve_genesis_time, ClientConfig};
 pub use client_types::ClientTypes;
+pub use crash_report::CrashReporter;
 use exit_future::Signal;
+use futures::{Future, Stream};
+use logging::LogBuffer;
 use network::Service as NetworkService;
-use slog::o;
+use slog::{debug, info, o, warn};
 use std::marker::PhantomData;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::TaskExecutor;
+use tokio::timer::Interval;
 
 /// Main beacon node client service. This provides the connection and initialisation of the clients
 /// sub-services in multiple threads.
@@ -40,13 +47,63 @@ impl<TClientType: ClientTypes> Client<TClientType> {
     /// Generate an instance of the client. Spawn and link all internal sub-processes.
     pub fn new(
         config: ClientConfig,
+        error_logs: LogBuffer,
         log: slog::Logger,
         executor: &TaskExecutor,
     ) -> error::Result<Self> {
         let (exit_signal, exit) = exit_future::signal();
 
+        // start from a trusted checkpoint rather than genesis, if given
+        let checkpoint = match (&config.checkpoint_state, &config.checkpoint_block) {
+            (Some(state_source), Some(block_source)) => {
+                let checkpoint = TrustedCheckpoint::load(state_source, block_source)
+                    .map_err(|e| format!("Unable to load the checkpoint: {:?}", e))?;
+                info!(
+                    log,
+                    "Starting from checkpoint";
+                    "slot" => checkpoint.state.slot.as_u64(),
+                    "finalized_epoch" => checkpoint.state.finalized_epoch.as_u64(),
+                );
+                Some(checkpoint)
+            }
+            _ => None,
+        };
+
+        // copy the database of another backend, before the chain is loaded from it
+        if let Some(from) = config.migrate_db_from {
+            migrate::migrate_database(&config, from, &log)?;
+        }
+
         // generate a beacon chain
-        let beacon_chain = TClientType::initialise_beacon_chain(&config);
+        let beacon_chain =
+            TClientType::initialise_beacon_chain(&config, checkpoint).map_err(|e| {
+                format!(
+                    "Unable to initialise the beacon chain with the database at {}: {}",
+                    config.db_name.display(),
+                    e
+                )
+            })?;
+
+        info!(
+            log,
+            "Beacon chain initialised";
+            "genesis_block_root" => format!("{:?}", beacon_chain.genesis_block_root),
+            "genesis_validators_root" => format!("{:?}", beacon_chain.genesis_validators_root),
+        );
+
+        // seed the chain from exported files, which is much faster than syncing from peers
+        if let Some(dir) = &config.import_era_dir {
+            info!(log, "Importing chain files"; "directory" => format!("{}", dir.display()));
+            let summary = era::import(&*beacon_chain, dir)
+                .map_err(|e| format!("Unable to import chain files: {:?}", e))?;
+            info!(
+                log,
+                "Imported chain files";
+                "files" => summary.files,
+                "blocks_imported" => summary.blocks_imported,
+                "blocks_skipped" => summary.blocks_skipped,
+            );
+        }
 
         // Start the network service, libp2p and syncing threads
         // TODO: Add beacon_chain reference to network parameters
@@ -55,13 +112,53 @@ impl<TClientType: ClientTypes> Client<TClientType> {
         let (network, _network_send) = NetworkService::new(
             beacon_chain.clone(),
             network_config,
+            &config.sync_conf,
             executor,
             network_logger,
         )?;
 
+        // process blocks that arrived shortly before their slot, once their slot starts
+        let future_block_processor = {
+            let beacon_chain = beacon_chain.clone();
+            let log = log.new(o!("Service" => "FutureBlocks"));
+            let timer_log = log.clone();
+            let mut last_slot = beacon_chain.present_slot();
+
+            Interval::new(Instant::now(), Duration::from_secs(1))
+                .map_err(move |e| debug!(timer_log, "Timer error {}", e))
+                .for_each(move |_| {
+                    let present_slot = beacon_chain.present_slot();
+                    if present_slot > last_slot {
+                        last_slot = present_slot;
+                        for outcome in beacon_chain.process_future_blocks() {
+                            match outcome {
+                                Ok(outcome) => debug!(
+                                    log,
+                                    "Processed queued future block";
+                                    "outcome" => format!("{:?}", outcome)
+                                ),
+                                Err(e) => warn!(
+                                    log,
+                                    "Error processing queued future block";
+                                    "error" => format!("{:?}", e)
+                                ),
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+        };
+        executor.spawn(exit.clone().until(future_block_processor).map(|_| ()));
+
         // spawn the RPC server
         if config.rpc_conf.enabled {
-            rpc::start_server(&config.rpc_conf, &log);
+            rpc::start_server(
+                &config.rpc_conf,
+                beacon_chain.clone(),
+                network.clone(),
+                error_logs,
+                &log,
+            );
         }
 
         Ok(Client {
@@ -74,4 +171,11 @@ impl<TClientType: ClientTypes> Client<TClientType> {
             phantom: PhantomData,
         })
     }
+
+    /// Returns the beacon chain of the client.
+    pub fn beacon_chain(
+        &self,
+    ) -> Arc<BeaconChain<TClientType::DB, TClientType::SlotClock, TClientType::ForkChoice>> {
+        self.beacon_chain.clone()
+    }
 }
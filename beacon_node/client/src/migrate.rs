@@ -0,0 +1,71 @@
+use crate::ClientConfig;
+use db::migrate::{is_migrated, migrate};
+use db::stores::COLUMNS;
+use db::{ClientDB, DBError, DBType, DiskStore};
+use slog::info;
+use std::time::Instant;
+
+/// Copies `from` into `to`, returning the number of pairs copied, or `None` if `to` was already
+/// filled by a migration, so that the flag may be left set after migrating.
+#[cfg_attr(not(feature = "redb"), allow(dead_code))]
+fn copy_once<F: ClientDB, T: ClientDB>(from: &F, to: &T) -> Result<Option<usize>, DBError> {
+    if is_migrated(to)? {
+        return Ok(None);
+    }
+    migrate(from, to).map(Some)
+}
+
+/// Copies the database of the `from` backend in the data directory into a database of the
+/// configured backend, so that a node may switch backends without syncing again.
+///
+/// The source database is left in place, to be removed once the node runs from the copy.
+pub fn migrate_database(
+    config: &ClientConfig,
+    from: DBType,
+    log: &slog::Logger,
+) -> Result<(), String> {
+    let path = &config.db_name;
+    info!(
+        log,
+        "Migrating database";
+        "from" => format!("{:?}", from),
+        "to" => format!("{:?}", config.db_type),
+        "path" => format!("{}", path.display()),
+    );
+    let started = Instant::now();
+
+    let copied = match (from, config.db_type) {
+        #[cfg(feature = "redb")]
+        (DBType::RocksDB, DBType::Redb) => copy_once(
+            &DiskStore::open(path, Some(&COLUMNS[..])),
+            &db::RedbStore::open(path, &COLUMNS[..]),
+        ),
+        #[cfg(feature = "redb")]
+        (DBType::Redb, DBType::RocksDB) => copy_once(
+            &db::RedbStore::open(path, &COLUMNS[..]),
+            &DiskStore::open(path, Some(&COLUMNS[..])),
+        ),
+        (from, to) => {
+            return Err(format!(
+                "Unable to migrate a {:?} database to a {:?} database",
+                from, to
+            ))
+        }
+    }
+    .map_err(|e| format!("Unable to migrate the database: {}", e.message))?;
+
+    let copied = match copied {
+        Some(copied) => copied,
+        None => {
+            info!(log, "Database already migrated");
+            return Ok(());
+        }
+    };
+    info!(
+        log,
+        "Database migrated";
+        "entries" => copied,
+        "duration_ms" => started.elapsed().as_millis() as u64,
+    );
+    Ok(())
+}
@@ -1,7 +1,7 @@
 extern crate rocksdb;
 
 use super::rocksdb::Error as RocksError;
-use super::rocksdb::{Options, DB};
+use super::rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
 use super::{ClientDB, DBError, DBValue};
 use std::fs;
 use std::path::Path;
@@ -28,6 +28,7 @@ impl DiskDB {
          */
         let mut options = Options::default();
         options.create_if_missing(true);
+        options.create_missing_column_families(true);
 
         // TODO: ensure that columns are created (and remove
         // the dead_code allow)
@@ -45,7 +46,7 @@ impl DiskDB {
             None => DB::open(&options, db_path),
             Some(columns) => DB::open_cf(&options, db_path, columns),
         }
-        .expect("Unable to open local database");;
+        .expect("Unable to open local database");
 
         Self { db }
     }
@@ -132,15 +133,94 @@ impl ClientDB for DiskDB {
             }
         }
     }
+
+    /// Return all key-value pairs in some column with a key in the range `[start, end)`.
+    ///
+    /// Performs a single forward iterator scan from `start`.
+    fn get_range(
+        &self,
+        col: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, DBValue)>, DBError> {
+        match self.db.cf_handle(col) {
+            None => Err(DBError {
+                message: "Unknown column".to_string(),
+            }),
+            Some(handle) => Ok(self
+                .db
+                .iterator_cf(handle, IteratorMode::From(start, Direction::Forward))?
+                .take_while(|(key, _)| &key[..] < end)
+                .map(|(key, val)| (key.to_vec(), val.to_vec()))
+                .collect()),
+        }
+    }
+
+    /// Set the values of many keys of some column in a single atomic write.
+    fn put_batch(&self, col: &str, pairs: &[(Vec<u8>, DBValue)]) -> Result<(), DBError> {
+        match self.db.cf_handle(col) {
+            None => Err(DBError {
+                message: "Unknown column".to_string(),
+            }),
+            Some(handle) => {
+                let mut batch = WriteBatch::default();
+                for (key, val) in pairs {
+                    batch.put_cf(handle, key, val)?;
+                }
+                self.db.write(batch)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Return every key in some column, scanning the whole column.
+    fn keys(&self, col: &str) -> Result<Vec<Vec<u8>>, DBError> {
+        match self.db.cf_handle(col) {
+            None => Err(DBError {
+                message: "Unknown column".to_string(),
+            }),
+            Some(handle) => Ok(self
+                .db
+                .iterator_cf(handle, IteratorMode::Start)?
+                .map(|(key, _)| key.to_vec())
+                .collect()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::super::ClientDB;
+    use super::super::stores::{BLOCKS_DB_COLUMN, COLUMNS, STATES_DB_COLUMN};
+    use super::super::{test_db_dir, ClientDB};
     use super::*;
     use std::sync::Arc;
     use std::{env, fs, thread};
 
+    #[test]
+    fn lists_keys_of_a_column_in_order() {
+        let path = test_db_dir("disk_db_keys");
+        let db = DiskDB::open(&path, Some(&COLUMNS[..]));
+        db.put(BLOCKS_DB_COLUMN, &[3], &[30]).unwrap();
+        db.put(BLOCKS_DB_COLUMN, &[1], &[10]).unwrap();
+        db.put_batch(
+            BLOCKS_DB_COLUMN,
+            &[(vec![2], vec![20]), (vec![4], vec![40])],
+        )
+        .unwrap();
+        db.put(STATES_DB_COLUMN, &[5], &[50]).unwrap();
+
+        assert_eq!(
+            db.keys(BLOCKS_DB_COLUMN).unwrap(),
+            vec![vec![1], vec![2], vec![3], vec![4]]
+        );
+        assert_eq!(db.get(BLOCKS_DB_COLUMN, &[4]).unwrap(), Some(vec![40]));
+        assert_eq!(db.keys(STATES_DB_COLUMN).unwrap(), vec![vec![5]]);
+        assert!(db.keys("ColumnX").is_err());
+
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
     #[test]
     #[ignore]
     fn test_rocksdb_can_use_db() {
@@ -1,9 +1,14 @@
-extern crate blake2_rfc as blake2;
 extern crate bls;
+#[cfg(feature = "redb")]
+extern crate redb;
 extern crate rocksdb;
 
 mod disk_db;
 mod memory_db;
+pub mod migrate;
+#[cfg(feature = "redb")]
+mod redb_db;
+mod store;
 pub mod stores;
 mod traits;
 
@@ -11,11 +16,46 @@ use self::stores::COLUMNS;
 
 pub use self::disk_db::DiskDB;
 pub use self::memory_db::MemoryDB;
+#[cfg(feature = "redb")]
+pub use self::redb_db::RedbDB;
+#[cfg(feature = "redb")]
+pub use self::store::RedbStore;
+pub use self::store::{DiskStore, MemoryStore, Store, StoreItem};
 pub use self::traits::{ClientDB, DBError, DBValue};
 
+/// Returns an empty directory in which a test named `name` may open an on-disk database.
+#[cfg(test)]
+fn test_db_dir(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("lighthouse_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
+
 /// Currently available database options
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DBType {
     Memory,
     RocksDB,
+    /// Only available when built with the `redb` feature.
+    Redb,
+}
+
+impl DBType {
+    /// Returns the database type named on the command line, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "memory" => Some(DBType::Memory),
+            "rocksdb" => Some(DBType::RocksDB),
+            "redb" => Some(DBType::Redb),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this binary was built with support for the database type.
+    pub fn is_available(self) -> bool {
+        match self {
+            DBType::Memory | DBType::RocksDB => true,
+            DBType::Redb => cfg!(feature = "redb"),
+        }
+    }
 }
@@ -1,10 +1,9 @@
-use super::blake2::blake2b::blake2b;
 use super::COLUMNS;
 use super::{ClientDB, DBError, DBValue};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 use std::sync::RwLock;
 
-type DBHashMap = HashMap<Vec<u8>, Vec<u8>>;
+type DBMap = BTreeMap<Vec<u8>, Vec<u8>>;
 type ColumnHashSet = HashSet<String>;
 
 /// An in-memory database implementing the ClientDB trait.
@@ -12,7 +11,7 @@ type ColumnHashSet = HashSet<String>;
 /// It is not particularily optimized, it exists for ease and speed of testing. It's not expected
 /// this DB would be used outside of tests.
 pub struct MemoryDB {
-    db: RwLock<DBHashMap>,
+    db: RwLock<DBMap>,
     known_columns: RwLock<ColumnHashSet>,
 }
 
@@ -22,7 +21,7 @@ impl MemoryDB {
     /// All columns must be supplied initially, you will get an error if you try to access a column
     /// that was not declared here. This condition is enforced artificially to simulate RocksDB.
     pub fn open() -> Self {
-        let db: DBHashMap = HashMap::new();
+        let db: DBMap = BTreeMap::new();
         let mut known_columns: ColumnHashSet = HashSet::new();
         for col in &COLUMNS {
             known_columns.insert(col.to_string());
@@ -33,9 +32,16 @@ impl MemoryDB {
         }
     }
 
-    /// Hashes a key and a column name in order to get a unique key for the supplied column.
+    /// Prefixes a key with a column name in order to get a unique key for the supplied column.
+    ///
+    /// The column name is followed by a zero byte so that keys within a column keep their order,
+    /// allowing range queries.
     fn get_key_for_col(col: &str, key: &[u8]) -> Vec<u8> {
-        blake2b(32, col.as_bytes(), key).as_bytes().to_vec()
+        let mut column_key = Vec::with_capacity(col.len() + 1 + key.len());
+        column_key.extend_from_slice(col.as_bytes());
+        column_key.push(0);
+        column_key.extend_from_slice(key);
+        column_key
     }
 }
 
@@ -105,6 +111,55 @@ impl ClientDB for MemoryDB {
             })
         }
     }
+
+    /// Return all key-value pairs in some column with a key in the range `[start, end)`.
+    fn get_range(
+        &self,
+        col: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, DBValue)>, DBError> {
+        // Panic if the DB locks are poisoned.
+        let db = self.db.read().unwrap();
+        let known_columns = self.known_columns.read().unwrap();
+
+        if known_columns.contains(&col.to_string()) {
+            let prefix_len = col.len() + 1;
+            let start = MemoryDB::get_key_for_col(col, start);
+            let end = MemoryDB::get_key_for_col(col, end);
+            if start >= end {
+                return Ok(vec![]);
+            }
+            Ok(db
+                .range(start..end)
+                .map(|(key, val)| (key[prefix_len..].to_vec(), val.clone()))
+                .collect())
+        } else {
+            Err(DBError {
+                message: "Unknown column".to_string(),
+            })
+        }
+    }
+
+    /// Return every key in some column.
+    fn keys(&self, col: &str) -> Result<Vec<Vec<u8>>, DBError> {
+        // Panic if the DB locks are poisoned.
+        let db = self.db.read().unwrap();
+        let known_columns = self.known_columns.read().unwrap();
+
+        if known_columns.contains(&col.to_string()) {
+            let prefix = MemoryDB::get_key_for_col(col, &[]);
+            Ok(db
+                .range(prefix.clone()..)
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, _)| key[prefix.len()..].to_vec())
+                .collect())
+        } else {
+            Err(DBError {
+                message: "Unknown column".to_string(),
+            })
+        }
+    }
 }
 
 #[cfg(test)]
@@ -194,6 +249,25 @@ mod tests {
         assert_eq!(false, db.exists(col_b, "dogs".as_bytes()).unwrap());
     }
 
+    #[test]
+    fn test_memorydb_get_range() {
+        let col_a: &str = BLOCKS_DB_COLUMN;
+        let col_b: &str = VALIDATOR_DB_COLUMN;
+
+        let db = MemoryDB::open();
+
+        for key in &[1_u8, 2, 3, 4] {
+            db.put(col_a, &[*key], &[*key]).unwrap();
+        }
+        db.put(col_b, &[2], &[42]).unwrap();
+
+        let range = db.get_range(col_a, &[2], &[4]).unwrap();
+        assert_eq!(range, vec![(vec![2], vec![2]), (vec![3], vec![3])]);
+
+        assert!(db.get_range(col_a, &[4], &[2]).unwrap().is_empty());
+        assert!(db.get_range("ColumnX", &[0], &[9]).is_err());
+    }
+
     #[test]
     fn test_memorydb_threading() {
         let col_name: &str = BLOCKS_DB_COLUMN;
@@ -0,0 +1,118 @@
+use super::stores::{COLUMNS, METADATA_DB_COLUMN};
+use super::{ClientDB, DBError};
+
+/// The key of the metadata column recording that a database was filled by `migrate`.
+const MIGRATED_KEY: &[u8] = b"migrated";
+/// The number of bytes of values copied in each batch written by `migrate`.
+const MIGRATION_BATCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// Copies every key-value pair of every column from one database to another, such as from a
+/// RocksDB database to a redb database, returning the number of pairs copied.
+///
+/// Values are written in batches of about `MIGRATION_BATCH_BYTES`, so that the largest values,
+/// the states, are not all held in memory at once. Pairs already in `to` are overwritten. Once
+/// every pair is copied, `to` is marked as migrated, see `is_migrated`.
+pub fn migrate<F: ClientDB, T: ClientDB>(from: &F, to: &T) -> Result<usize, DBError> {
+    let mut copied = 0;
+    for col in COLUMNS.iter() {
+        let mut batch = vec![];
+        let mut batch_bytes = 0;
+        for key in from.keys(col)? {
+            let value = from.get(col, &key)?.ok_or_else(|| DBError {
+                message: format!("Key removed from column {} during migration", col),
+            })?;
+            batch_bytes += value.len();
+            batch.push((key, value));
+            if batch_bytes >= MIGRATION_BATCH_BYTES {
+                to.put_batch(col, &batch)?;
+                copied += batch.len();
+                batch.clear();
+                batch_bytes = 0;
+            }
+        }
+        to.put_batch(col, &batch)?;
+        copied += batch.len();
+    }
+    to.put_batch(METADATA_DB_COLUMN, &[(MIGRATED_KEY.to_vec(), vec![1])])?;
+    Ok(copied)
+}
+
+/// Returns `true` if `db` was filled by a completed `migrate`, so need not be migrated again.
+pub fn is_migrated<T: ClientDB>(db: &T) -> Result<bool, DBError> {
+    db.exists(METADATA_DB_COLUMN, MIGRATED_KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stores::{BLOCKS_DB_COLUMN, METADATA_DB_COLUMN, STATES_DB_COLUMN};
+    use super::super::{test_db_dir, DiskDB, MemoryDB};
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn copies_every_column() {
+        let from = MemoryDB::open();
+        from.put(BLOCKS_DB_COLUMN, &[1], &[10]).unwrap();
+        from.put(BLOCKS_DB_COLUMN, &[2], &[20]).unwrap();
+        from.put(METADATA_DB_COLUMN, b"schema_version", &[3])
+            .unwrap();
+
+        let to = MemoryDB::open();
+        to.put(BLOCKS_DB_COLUMN, &[2], &[0]).unwrap();
+
+        assert!(!is_migrated(&to).unwrap());
+        assert_eq!(migrate(&from, &to).unwrap(), 3);
+        assert!(is_migrated(&to).unwrap());
+        assert_eq!(to.keys(BLOCKS_DB_COLUMN).unwrap(), vec![vec![1], vec![2]]);
+        assert_eq!(to.get(BLOCKS_DB_COLUMN, &[2]).unwrap(), Some(vec![20]));
+        assert_eq!(
+            to.get(METADATA_DB_COLUMN, b"schema_version").unwrap(),
+            Some(vec![3])
+        );
+    }
+
+    #[test]
+    fn copies_a_rocksdb_database() {
+        let path = test_db_dir("migrate_rocksdb");
+        let from = DiskDB::open(&path, Some(&COLUMNS[..]));
+        from.put(BLOCKS_DB_COLUMN, &[1], &[10]).unwrap();
+        from.put(STATES_DB_COLUMN, &[1], &vec![7; MIGRATION_BATCH_BYTES])
+            .unwrap();
+        from.put(STATES_DB_COLUMN, &[2], &[20]).unwrap();
+
+        let to = MemoryDB::open();
+        assert_eq!(migrate(&from, &to).unwrap(), 3);
+        assert_eq!(
+            to.keys(STATES_DB_COLUMN).unwrap(),
+            from.keys(STATES_DB_COLUMN).unwrap()
+        );
+        assert_eq!(to.get(STATES_DB_COLUMN, &[2]).unwrap(), Some(vec![20]));
+        assert!(is_migrated(&to).unwrap());
+
+        drop(from);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "redb")]
+    fn copies_a_rocksdb_database_into_redb() {
+        let path = test_db_dir("migrate_redb");
+        let from = DiskDB::open(&path, Some(&COLUMNS[..]));
+        from.put(BLOCKS_DB_COLUMN, &[1], &[10]).unwrap();
+        from.put(BLOCKS_DB_COLUMN, &[2], &[20]).unwrap();
+
+        {
+            let to = super::super::RedbDB::open(&path, &COLUMNS[..]);
+            assert!(!is_migrated(&to).unwrap());
+            assert_eq!(migrate(&from, &to).unwrap(), 2);
+        }
+
+        // the copy is found on the next start
+        let to = super::super::RedbDB::open(&path, &COLUMNS[..]);
+        assert!(is_migrated(&to).unwrap());
+        assert_eq!(to.keys(BLOCKS_DB_COLUMN).unwrap(), vec![vec![1], vec![2]]);
+
+        drop(from);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
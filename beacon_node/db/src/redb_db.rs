@@ -0,0 +1,194 @@
+use super::redb::{Database, Durability, Error, ReadableTable, TableDefinition};
+use super::{ClientDB, DBError, DBValue};
+use std::fs;
+use std::path::Path;
+
+/// An on-disk database which implements the ClientDB trait.
+///
+/// This implementation uses redb, a pure Rust store, for platforms on which RocksDB is difficult
+/// to build or run. Each column is a table of the single database file.
+///
+/// Single writes are committed without waiting for the file to be synced, as RocksDB commits
+/// without syncing its log, so that importing a block is not an fsync per key. They become
+/// durable, in order, with the next batch or on a clean shutdown.
+pub struct RedbDB {
+    db: Database,
+}
+
+impl RedbDB {
+    /// Open the redb database, creating a table for each of the supplied columns.
+    ///
+    /// The redb database will be contained in a file titled "database.redb" in the supplied path,
+    /// so that it sits next to, not in, the "database" directory in which `DiskDB` keeps a RocksDB
+    /// database opened with the same path.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database is unable to be created.
+    pub fn open(path: &Path, columns: &[&str]) -> Self {
+        fs::create_dir_all(&path).unwrap_or_else(|_| panic!("Unable to create {:?}", &path));
+        let db =
+            Database::create(path.join("database.redb")).expect("Unable to open local database");
+
+        // tables must exist before they are read
+        let txn = db.begin_write().expect("Unable to create database tables");
+        for col in columns {
+            txn.open_table(table(col))
+                .expect("Unable to create database tables");
+        }
+        txn.commit().expect("Unable to create database tables");
+
+        Self { db }
+    }
+}
+
+/// Returns the definition of the table holding a column.
+fn table(col: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(col)
+}
+
+/// Converts any redb error into a `DBError`.
+fn db_error<E: Into<Error>>(e: E) -> DBError {
+    DBError {
+        message: e.into().to_string(),
+    }
+}
+
+impl ClientDB for RedbDB {
+    /// Get the value for some key on some column.
+    fn get(&self, col: &str, key: &[u8]) -> Result<Option<DBValue>, DBError> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(table(col)).map_err(db_error)?;
+        let value = table.get(key).map_err(db_error)?;
+        Ok(value.map(|value| value.value().to_vec()))
+    }
+
+    /// Set some value for some key on some column, committing without syncing.
+    fn put(&self, col: &str, key: &[u8], val: &[u8]) -> Result<(), DBError> {
+        let mut txn = self.db.begin_write().map_err(db_error)?;
+        txn.set_durability(Durability::Eventual);
+        {
+            let mut table = txn.open_table(table(col)).map_err(db_error)?;
+            table.insert(key, val).map_err(db_error)?;
+        }
+        txn.commit().map_err(db_error)
+    }
+
+    /// Return true if some key exists in some column.
+    fn exists(&self, col: &str, key: &[u8]) -> Result<bool, DBError> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(table(col)).map_err(db_error)?;
+        let exists = table.get(key).map_err(db_error)?.is_some();
+        Ok(exists)
+    }
+
+    /// Delete the value for some key on some column, committing without syncing.
+    fn delete(&self, col: &str, key: &[u8]) -> Result<(), DBError> {
+        let mut txn = self.db.begin_write().map_err(db_error)?;
+        txn.set_durability(Durability::Eventual);
+        {
+            let mut table = txn.open_table(table(col)).map_err(db_error)?;
+            table.remove(key).map_err(db_error)?;
+        }
+        txn.commit().map_err(db_error)
+    }
+
+    /// Return all key-value pairs in some column with a key in the range `[start, end)`.
+    fn get_range(
+        &self,
+        col: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, DBValue)>, DBError> {
+        if start >= end {
+            return Ok(vec![]);
+        }
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(table(col)).map_err(db_error)?;
+        let mut pairs = vec![];
+        for entry in table.range(start..end).map_err(db_error)? {
+            let (key, val) = entry.map_err(db_error)?;
+            pairs.push((key.value().to_vec(), val.value().to_vec()));
+        }
+        Ok(pairs)
+    }
+
+    /// Set the values of many keys of some column in a single transaction, synced on commit.
+    fn put_batch(&self, col: &str, pairs: &[(Vec<u8>, DBValue)]) -> Result<(), DBError> {
+        let txn = self.db.begin_write().map_err(db_error)?;
+        {
+            let mut table = txn.open_table(table(col)).map_err(db_error)?;
+            for (key, val) in pairs {
+                table.insert(&key[..], &val[..]).map_err(db_error)?;
+            }
+        }
+        txn.commit().map_err(db_error)
+    }
+
+    /// Return every key in some column.
+    fn keys(&self, col: &str) -> Result<Vec<Vec<u8>>, DBError> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(table(col)).map_err(db_error)?;
+        let mut keys = vec![];
+        for entry in table.iter().map_err(db_error)? {
+            let (key, _) = entry.map_err(db_error)?;
+            keys.push(key.value().to_vec());
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stores::{BLOCKS_DB_COLUMN, COLUMNS, STATES_DB_COLUMN};
+    use super::super::test_db_dir;
+    use super::*;
+
+    #[test]
+    fn reads_back_writes() {
+        let path = test_db_dir("redb_db_writes");
+        let db = RedbDB::open(&path, &COLUMNS[..]);
+
+        db.put(BLOCKS_DB_COLUMN, &[3], &[30]).unwrap();
+        db.put(BLOCKS_DB_COLUMN, &[1], &[10]).unwrap();
+        db.put_batch(
+            BLOCKS_DB_COLUMN,
+            &[(vec![2], vec![20]), (vec![4], vec![40])],
+        )
+        .unwrap();
+        db.put(STATES_DB_COLUMN, &[3], &[31]).unwrap();
+        db.delete(BLOCKS_DB_COLUMN, &[4]).unwrap();
+
+        assert_eq!(db.get(BLOCKS_DB_COLUMN, &[3]).unwrap(), Some(vec![30]));
+        assert_eq!(db.get(STATES_DB_COLUMN, &[3]).unwrap(), Some(vec![31]));
+        assert!(db.exists(BLOCKS_DB_COLUMN, &[2]).unwrap());
+        assert!(!db.exists(BLOCKS_DB_COLUMN, &[4]).unwrap());
+        assert_eq!(
+            db.keys(BLOCKS_DB_COLUMN).unwrap(),
+            vec![vec![1], vec![2], vec![3]]
+        );
+        assert_eq!(
+            db.get_range(BLOCKS_DB_COLUMN, &[2], &[4]).unwrap(),
+            vec![(vec![2], vec![20]), (vec![3], vec![30])]
+        );
+        assert!(db.get("ColumnX", &[1]).is_err());
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn persists_next_to_a_rocksdb_database() {
+        let path = test_db_dir("redb_db_persists");
+        {
+            let db = RedbDB::open(&path, &COLUMNS[..]);
+            db.put(BLOCKS_DB_COLUMN, &[1], &[10]).unwrap();
+        }
+        assert!(path.join("database.redb").is_file());
+        assert!(!path.join("database").exists());
+
+        let db = RedbDB::open(&path, &COLUMNS[..]);
+        assert_eq!(db.get(BLOCKS_DB_COLUMN, &[1]).unwrap(), Some(vec![10]));
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
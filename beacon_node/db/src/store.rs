@@ -0,0 +1,100 @@
+use super::stores::{BLOCKS_DB_COLUMN, STATES_DB_COLUMN};
+use super::{ClientDB, DBError, DiskDB, MemoryDB};
+use ssz::{ssz_encode, Decodable, Encodable};
+use types::{BeaconBlock, BeaconState, Hash256};
+
+/// The on-disk `Store`, backed by RocksDB.
+///
+/// `Store` is implemented for every `ClientDB` rather than by separate types, so the stores of
+/// production and tests differ only in the backend beneath them.
+pub type DiskStore = DiskDB;
+
+/// The in-memory `Store`, used by the beacon chain and sync tests.
+pub type MemoryStore = MemoryDB;
+
+/// The on-disk `Store` backed by redb, for platforms on which RocksDB is troublesome.
+#[cfg(feature = "redb")]
+pub type RedbStore = super::RedbDB;
+
+/// An item which may be held in a `Store`, SSZ encoded and keyed by its root.
+pub trait StoreItem: Encodable + Decodable {
+    /// The column in which items of this type are held.
+    fn db_column() -> &'static str;
+}
+
+impl StoreItem for BeaconBlock {
+    fn db_column() -> &'static str {
+        BLOCKS_DB_COLUMN
+    }
+}
+
+impl StoreItem for BeaconState {
+    fn db_column() -> &'static str {
+        STATES_DB_COLUMN
+    }
+}
+
+/// A database of items keyed by their root.
+///
+/// Implemented for every `ClientDB`, so that the `MemoryStore` used by tests runs exactly the
+/// encoding, decoding and column selection of the `DiskStore` used in production.
+pub trait Store: ClientDB {
+    /// Stores an item under `key`, replacing any existing item.
+    fn put_item<I: StoreItem>(&self, key: &Hash256, item: &I) -> Result<(), DBError> {
+        self.put(I::db_column(), key.as_bytes(), &ssz_encode(item))
+    }
+
+    /// Returns the item stored under `key`, if any.
+    fn get_item<I: StoreItem>(&self, key: &Hash256) -> Result<Option<I>, DBError> {
+        match self.get(I::db_column(), key.as_bytes())? {
+            None => Ok(None),
+            Some(ssz) => {
+                let (item, _) = I::ssz_decode(&ssz, 0).map_err(|e| DBError {
+                    message: format!("Bad SSZ in column {}: {:?}", I::db_column(), e),
+                })?;
+                Ok(Some(item))
+            }
+        }
+    }
+
+    /// Returns `true` if an item is stored under `key`.
+    fn item_exists<I: StoreItem>(&self, key: &Hash256) -> Result<bool, DBError> {
+        self.exists(I::db_column(), key.as_bytes())
+    }
+}
+
+impl<T: ClientDB> Store for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+
+    #[test]
+    fn round_trips_items() {
+        let store = MemoryStore::open();
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+
+        let block = BeaconBlock::random_for_test(&mut rng);
+        let root = block.canonical_root();
+
+        assert!(!store.item_exists::<BeaconBlock>(&root).unwrap());
+        assert_eq!(store.get_item::<BeaconBlock>(&root).unwrap(), None);
+
+        store.put_item(&root, &block).unwrap();
+        assert!(store.item_exists::<BeaconBlock>(&root).unwrap());
+        assert!(!store.item_exists::<BeaconState>(&root).unwrap());
+        assert_eq!(store.get_item::<BeaconBlock>(&root).unwrap(), Some(block));
+    }
+
+    #[test]
+    fn rejects_bad_ssz() {
+        let store = MemoryStore::open();
+        let root = Hash256::from([0xAA; 32]);
+
+        store
+            .put(BLOCKS_DB_COLUMN, root.as_bytes(), &[1, 2, 3])
+            .unwrap();
+        assert!(store.get_item::<BeaconBlock>(&root).is_err());
+    }
+}
@@ -1,9 +1,16 @@
 use super::BLOCKS_DB_COLUMN as DB_COLUMN;
+use super::BLOCK_SLOTS_DB_COLUMN as SLOTS_DB_COLUMN;
+use super::METADATA_DB_COLUMN;
 use super::{ClientDB, DBError};
-use ssz::Decodable;
+use crate::Store;
 use std::sync::Arc;
 use types::{BeaconBlock, Hash256, Slot};
 
+const OLDEST_BLOCK_KEY_PREFIX: &[u8] = b"oldest_block";
+const DEPOSIT_LEAF_KEY_PREFIX: &[u8] = b"deposit_leaf";
+/// Present once every block stored before the slot index existed has been indexed.
+const SLOT_INDEX_COMPLETE_KEY: &[u8] = b"block_slots_indexed";
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum BeaconBlockAtSlotError {
     UnknownBeaconBlock(Hash256),
@@ -26,18 +33,152 @@ impl<T: ClientDB> BeaconBlockStore<T> {
         Self { db }
     }
 
+    /// Stores a block, indexing it by its slot so that it may be found by
+    /// `get_block_roots_by_range`.
+    pub fn put_block(&self, hash: &Hash256, block: &BeaconBlock) -> Result<(), DBError> {
+        self.db.put_item(hash, block)?;
+        self.db
+            .put(SLOTS_DB_COLUMN, &slot_index_key(block.slot, hash), &[])
+    }
+
+    /// Returns the roots of all stored blocks with a slot in `[start_slot, start_slot + count)`,
+    /// ordered by slot.
+    ///
+    /// Uses a single scan over the slot index. All blocks stored with `put_block` are returned,
+    /// including those which are not part of the canonical chain.
+    pub fn get_block_roots_by_range(
+        &self,
+        start_slot: Slot,
+        count: u64,
+    ) -> Result<Vec<(Hash256, Slot)>, DBError> {
+        let start = slot_index_key(start_slot, &Hash256::zero());
+        let end = slot_index_key(start_slot + count, &Hash256::zero());
+
+        Ok(self
+            .db
+            .get_range(SLOTS_DB_COLUMN, &start, &end)?
+            .into_iter()
+            .map(|(key, _)| {
+                let mut slot_bytes = [0; 8];
+                slot_bytes.copy_from_slice(&key[0..8]);
+                (
+                    Hash256::from_slice(&key[8..]),
+                    Slot::from(u64::from_be_bytes(slot_bytes)),
+                )
+            })
+            .collect())
+    }
+
+    /// Indexes the slot of each block stored before the slot index existed, once per database,
+    /// returning the number of blocks indexed.
+    ///
+    /// Must be called before `get_block_roots_by_range` is relied upon for a database which may
+    /// predate the index.
+    pub fn backfill_slot_index(&self) -> Result<usize, DBError> {
+        if self
+            .db
+            .exists(METADATA_DB_COLUMN, SLOT_INDEX_COMPLETE_KEY)?
+        {
+            return Ok(0);
+        }
+
+        let mut index = vec![];
+        for key in self.db.keys(DB_COLUMN)? {
+            if key.len() != 32 {
+                continue;
+            }
+            let hash = Hash256::from_slice(&key);
+            if let Some(block) = self.get_deserialized(&hash)? {
+                index.push((slot_index_key(block.slot, &hash), vec![]));
+            }
+        }
+        self.db.put_batch(SLOTS_DB_COLUMN, &index)?;
+        self.db
+            .put(METADATA_DB_COLUMN, SLOT_INDEX_COMPLETE_KEY, &[])?;
+        Ok(index.len())
+    }
+
+    /// Returns `true` if the block with root `hash` is stored.
+    pub fn block_exists(&self, hash: &Hash256) -> Result<bool, DBError> {
+        self.db.item_exists::<BeaconBlock>(hash)
+    }
+
     pub fn get_deserialized(&self, hash: &Hash256) -> Result<Option<BeaconBlock>, DBError> {
-        match self.get(&hash)? {
-            None => Ok(None),
-            Some(ssz) => {
-                let (block, _) = BeaconBlock::ssz_decode(&ssz, 0).map_err(|_| DBError {
-                    message: "Bad BeaconBlock SSZ.".to_string(),
-                })?;
-                Ok(Some(block))
+        self.db.get_item(hash)
+    }
+
+    /// Records the slot and parent root of the oldest block held of the chain started from
+    /// `anchor_root`, every block between it and the anchor being held, so that blocks backfilled
+    /// before a restart are not downloaded again.
+    pub fn put_oldest_block(
+        &self,
+        anchor_root: &Hash256,
+        slot: Slot,
+        parent_root: &Hash256,
+    ) -> Result<(), DBError> {
+        self.db.put(
+            METADATA_DB_COLUMN,
+            &oldest_block_key(anchor_root),
+            &slot_index_key(slot, parent_root),
+        )
+    }
+
+    /// Returns the slot and parent root of the oldest block held of the chain started from
+    /// `anchor_root`, if recorded by `put_oldest_block`.
+    pub fn get_oldest_block(
+        &self,
+        anchor_root: &Hash256,
+    ) -> Result<Option<(Slot, Hash256)>, DBError> {
+        match self
+            .db
+            .get(METADATA_DB_COLUMN, &oldest_block_key(anchor_root))?
+        {
+            Some(bytes) if bytes.len() == 8 + 32 => {
+                let mut slot_bytes = [0; 8];
+                slot_bytes.copy_from_slice(&bytes[0..8]);
+                Ok(Some((
+                    Slot::from(u64::from_be_bytes(slot_bytes)),
+                    Hash256::from_slice(&bytes[8..]),
+                )))
             }
+            Some(_) => Err(DBError {
+                message: "Bad oldest block record.".to_string(),
+            }),
+            None => Ok(None),
         }
     }
 
+    /// Records the deposit tree leaf of the deposit with `index`, as the deposits of the chain are
+    /// included in the genesis state or imported blocks.
+    pub fn put_deposit_leaf(&self, index: u64, leaf: &Hash256) -> Result<(), DBError> {
+        self.db.put(
+            METADATA_DB_COLUMN,
+            &deposit_leaf_key(index),
+            leaf.as_bytes(),
+        )
+    }
+
+    /// Returns the deposit tree leaves recorded by `put_deposit_leaf`, ordered by deposit index.
+    pub fn get_deposit_leaves(&self) -> Result<Vec<Hash256>, DBError> {
+        self.db
+            .get_range(
+                METADATA_DB_COLUMN,
+                &deposit_leaf_key(0),
+                &deposit_leaf_key(u64::max_value()),
+            )?
+            .into_iter()
+            .map(|(_, leaf)| {
+                if leaf.len() == 32 {
+                    Ok(Hash256::from_slice(&leaf))
+                } else {
+                    Err(DBError {
+                        message: "Bad deposit leaf record.".to_string(),
+                    })
+                }
+            })
+            .collect()
+    }
+
     /// Retrieve the block at a slot given a "head_hash" and a slot.
     ///
     /// A "head_hash" must be a block hash with a slot number greater than or equal to the desired
@@ -74,6 +215,28 @@ impl<T: ClientDB> BeaconBlockStore<T> {
     }
 }
 
+/// Returns the slot index key for a block, the big-endian slot followed by the block root, so that
+/// keys are ordered by slot.
+fn slot_index_key(slot: Slot, hash: &Hash256) -> Vec<u8> {
+    let mut key = slot.as_u64().to_be_bytes().to_vec();
+    key.extend_from_slice(hash.as_bytes());
+    key
+}
+
+/// Returns the key of the deposit tree leaf of the deposit with `index`, ordered by index.
+fn deposit_leaf_key(index: u64) -> Vec<u8> {
+    let mut key = DEPOSIT_LEAF_KEY_PREFIX.to_vec();
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+/// Returns the key of the oldest block held of the chain started from `anchor_root`.
+fn oldest_block_key(anchor_root: &Hash256) -> Vec<u8> {
+    let mut key = OLDEST_BLOCK_KEY_PREFIX.to_vec();
+    key.extend_from_slice(anchor_root.as_bytes());
+    key
+}
+
 impl From<DBError> for BeaconBlockAtSlotError {
     fn from(e: DBError) -> Self {
         BeaconBlockAtSlotError::DBError(e.message)
@@ -111,6 +274,99 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    #[test]
+    fn blocks_by_range() {
+        let db = Arc::new(MemoryDB::open());
+        let bs = BeaconBlockStore::new(db.clone());
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+
+        let mut roots = vec![];
+        for slot in (0..10_u64).rev() {
+            let mut block = BeaconBlock::random_for_test(&mut rng);
+            block.slot = Slot::from(slot);
+            let block_root = block.canonical_root();
+            bs.put_block(&block_root, &block).unwrap();
+            roots.push((block_root, block.slot));
+        }
+        roots.reverse();
+
+        let range = bs.get_block_roots_by_range(Slot::from(3_u64), 4).unwrap();
+        assert_eq!(range, roots[3..7].to_vec());
+
+        assert!(bs
+            .get_block_roots_by_range(Slot::from(20_u64), 4)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn backfills_slot_index_of_unindexed_blocks() {
+        let db = Arc::new(MemoryDB::open());
+        let bs = BeaconBlockStore::new(db.clone());
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+
+        // blocks stored before the slot index existed
+        let mut roots = vec![];
+        for slot in 0..5_u64 {
+            let mut block = BeaconBlock::random_for_test(&mut rng);
+            block.slot = Slot::from(slot);
+            let block_root = block.canonical_root();
+            bs.put(&block_root, &ssz_encode(&block)).unwrap();
+            roots.push((block_root, block.slot));
+        }
+        assert!(bs
+            .get_block_roots_by_range(Slot::from(0_u64), 5)
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(bs.backfill_slot_index().unwrap(), 5);
+        assert_eq!(
+            bs.get_block_roots_by_range(Slot::from(0_u64), 5).unwrap(),
+            roots
+        );
+
+        // only done once
+        assert_eq!(bs.backfill_slot_index().unwrap(), 0);
+    }
+
+    #[test]
+    fn records_oldest_block_per_anchor() {
+        let bs = BeaconBlockStore::new(Arc::new(MemoryDB::open()));
+        let anchor_root = Hash256::from_low_u64_be(1);
+        let parent_root = Hash256::from_low_u64_be(2);
+
+        assert_eq!(bs.get_oldest_block(&anchor_root).unwrap(), None);
+        bs.put_oldest_block(&anchor_root, Slot::from(5_u64), &parent_root)
+            .unwrap();
+        assert_eq!(
+            bs.get_oldest_block(&anchor_root).unwrap(),
+            Some((Slot::from(5_u64), parent_root))
+        );
+        assert_eq!(bs.get_oldest_block(&parent_root).unwrap(), None);
+    }
+
+    #[test]
+    fn records_deposit_leaves_in_index_order() {
+        let bs = BeaconBlockStore::new(Arc::new(MemoryDB::open()));
+        assert!(bs.get_deposit_leaves().unwrap().is_empty());
+
+        for index in &[256, 0, 1] {
+            bs.put_deposit_leaf(*index, &Hash256::from_low_u64_be(*index + 1))
+                .unwrap();
+        }
+        bs.put_oldest_block(&Hash256::zero(), Slot::from(5_u64), &Hash256::zero())
+            .unwrap();
+
+        assert_eq!(
+            bs.get_deposit_leaves().unwrap(),
+            vec![
+                Hash256::from_low_u64_be(1),
+                Hash256::from_low_u64_be(2),
+                Hash256::from_low_u64_be(257),
+            ]
+        );
+    }
+
     #[test]
     fn test_invalid_block_at_slot() {
         let db = Arc::new(MemoryDB::open());
@@ -1,6 +1,6 @@
 use super::STATES_DB_COLUMN as DB_COLUMN;
 use super::{ClientDB, DBError};
-use ssz::Decodable;
+use crate::Store;
 use std::sync::Arc;
 use types::{BeaconState, Hash256};
 
@@ -19,16 +19,12 @@ impl<T: ClientDB> BeaconStateStore<T> {
         Self { db }
     }
 
+    pub fn put_state(&self, hash: &Hash256, state: &BeaconState) -> Result<(), DBError> {
+        self.db.put_item(hash, state)
+    }
+
     pub fn get_deserialized(&self, hash: &Hash256) -> Result<Option<BeaconState>, DBError> {
-        match self.get(&hash)? {
-            None => Ok(None),
-            Some(ssz) => {
-                let (state, _) = BeaconState::ssz_decode(&ssz, 0).map_err(|_| DBError {
-                    message: "Bad State SSZ.".to_string(),
-                })?;
-                Ok(Some(state))
-            }
-        }
+        self.db.get_item(hash)
     }
 }
 
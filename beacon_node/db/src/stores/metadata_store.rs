@@ -0,0 +1,189 @@
+use super::METADATA_DB_COLUMN as DB_COLUMN;
+use super::{ClientDB, DBError};
+use ssz::{ssz_encode, Decodable};
+use std::fmt;
+use std::sync::Arc;
+
+/// The layout of the data written by this binary. Increment when a change is made that the
+/// previous binary could not read, or that this binary could not read from the previous layout.
+pub const SCHEMA_VERSION: u64 = 1;
+/// The oldest schema version this binary can read.
+pub const MIN_SCHEMA_VERSION: u64 = 1;
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+const GENESIS_FORK_VERSION_KEY: &[u8] = b"genesis_fork_version";
+
+#[derive(Debug, PartialEq)]
+pub enum MetadataStoreError {
+    DBError(String),
+    DecodeError,
+    /// The database was written with a schema this binary cannot read.
+    IncompatibleSchema {
+        found: u64,
+    },
+    /// The database holds a chain with a different genesis fork to the one configured.
+    IncompatibleFork {
+        found: u32,
+        expected: u32,
+    },
+}
+
+impl From<DBError> for MetadataStoreError {
+    fn from(error: DBError) -> Self {
+        MetadataStoreError::DBError(error.message)
+    }
+}
+
+impl fmt::Display for MetadataStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetadataStoreError::DBError(message) => write!(f, "database error: {}", message),
+            MetadataStoreError::DecodeError => write!(f, "unable to decode database metadata"),
+            MetadataStoreError::IncompatibleSchema { found } if *found > SCHEMA_VERSION => write!(
+                f,
+                "the database schema version {} is newer than the latest supported, {}. \
+                 Upgrade this binary, or remove the database directory to resync",
+                found, SCHEMA_VERSION
+            ),
+            MetadataStoreError::IncompatibleSchema { found } => write!(
+                f,
+                "the database schema version {} is older than the oldest supported, {}. \
+                 Migrate the database with the binary that wrote it, or remove the database \
+                 directory to resync",
+                found, MIN_SCHEMA_VERSION
+            ),
+            MetadataStoreError::IncompatibleFork { found, expected } => write!(
+                f,
+                "the database holds a chain with genesis fork version {}, but {} is configured. \
+                 Use the database directory of this chain, or remove the database directory to \
+                 resync",
+                found, expected
+            ),
+        }
+    }
+}
+
+/// Stores information about the database itself, rather than the chain.
+pub struct MetadataStore<T>
+where
+    T: ClientDB,
+{
+    db: Arc<T>,
+}
+
+impl<T: ClientDB> MetadataStore<T> {
+    pub fn new(db: Arc<T>) -> Self {
+        Self { db }
+    }
+
+    pub fn get_schema_version(&self) -> Result<Option<u64>, MetadataStoreError> {
+        self.get_u64(SCHEMA_VERSION_KEY)
+    }
+
+    pub fn put_schema_version(&self, version: u64) -> Result<(), MetadataStoreError> {
+        self.put_u64(SCHEMA_VERSION_KEY, version)
+    }
+
+    pub fn get_genesis_fork_version(&self) -> Result<Option<u32>, MetadataStoreError> {
+        Ok(self.get_u64(GENESIS_FORK_VERSION_KEY)?.map(|v| v as u32))
+    }
+
+    pub fn put_genesis_fork_version(&self, version: u32) -> Result<(), MetadataStoreError> {
+        self.put_u64(GENESIS_FORK_VERSION_KEY, u64::from(version))
+    }
+
+    /// Returns an error if the database cannot be safely used by this binary for a chain with
+    /// `genesis_fork_version`.
+    ///
+    /// A database without metadata is either new or predates it, in which case it has the first
+    /// schema version. Its metadata is written.
+    pub fn check_compatibility(&self, genesis_fork_version: u32) -> Result<(), MetadataStoreError> {
+        match self.get_schema_version()? {
+            Some(found) if found < MIN_SCHEMA_VERSION || found > SCHEMA_VERSION => {
+                return Err(MetadataStoreError::IncompatibleSchema { found });
+            }
+            Some(_) => {}
+            None => self.put_schema_version(SCHEMA_VERSION)?,
+        }
+
+        match self.get_genesis_fork_version()? {
+            Some(found) if found != genesis_fork_version => {
+                Err(MetadataStoreError::IncompatibleFork {
+                    found,
+                    expected: genesis_fork_version,
+                })
+            }
+            Some(_) => Ok(()),
+            None => self.put_genesis_fork_version(genesis_fork_version),
+        }
+    }
+
+    fn get_u64(&self, key: &[u8]) -> Result<Option<u64>, MetadataStoreError> {
+        match self.db.get(DB_COLUMN, key)? {
+            Some(bytes) => {
+                let (value, _) =
+                    u64::ssz_decode(&bytes, 0).map_err(|_| MetadataStoreError::DecodeError)?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn put_u64(&self, key: &[u8], value: u64) -> Result<(), MetadataStoreError> {
+        self.db
+            .put(DB_COLUMN, key, &ssz_encode(&value))
+            .map_err(MetadataStoreError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::MemoryDB;
+    use super::*;
+
+    #[test]
+    fn writes_metadata_to_new_database() {
+        let store = MetadataStore::new(Arc::new(MemoryDB::open()));
+
+        store.check_compatibility(7).unwrap();
+        assert_eq!(store.get_schema_version(), Ok(Some(SCHEMA_VERSION)));
+        assert_eq!(store.get_genesis_fork_version(), Ok(Some(7)));
+
+        store.check_compatibility(7).unwrap();
+    }
+
+    #[test]
+    fn rejects_unsupported_schema() {
+        let store = MetadataStore::new(Arc::new(MemoryDB::open()));
+
+        store.put_schema_version(SCHEMA_VERSION + 1).unwrap();
+        assert_eq!(
+            store.check_compatibility(0),
+            Err(MetadataStoreError::IncompatibleSchema {
+                found: SCHEMA_VERSION + 1
+            })
+        );
+
+        store.put_schema_version(MIN_SCHEMA_VERSION - 1).unwrap();
+        assert_eq!(
+            store.check_compatibility(0),
+            Err(MetadataStoreError::IncompatibleSchema {
+                found: MIN_SCHEMA_VERSION - 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_other_fork() {
+        let store = MetadataStore::new(Arc::new(MemoryDB::open()));
+
+        store.check_compatibility(1).unwrap();
+        assert_eq!(
+            store.check_compatibility(2),
+            Err(MetadataStoreError::IncompatibleFork {
+                found: 1,
+                expected: 2
+            })
+        );
+    }
+}
@@ -4,11 +4,15 @@ use super::{ClientDB, DBError};
 mod macros;
 mod beacon_block_store;
 mod beacon_state_store;
+mod metadata_store;
 mod pow_chain_store;
 mod validator_store;
 
 pub use self::beacon_block_store::{BeaconBlockAtSlotError, BeaconBlockStore};
 pub use self::beacon_state_store::BeaconStateStore;
+pub use self::metadata_store::{
+    MetadataStore, MetadataStoreError, MIN_SCHEMA_VERSION, SCHEMA_VERSION,
+};
 pub use self::pow_chain_store::PoWChainStore;
 pub use self::validator_store::{ValidatorStore, ValidatorStoreError};
 
@@ -16,10 +20,14 @@ pub const BLOCKS_DB_COLUMN: &str = "blocks";
 pub const STATES_DB_COLUMN: &str = "states";
 pub const POW_CHAIN_DB_COLUMN: &str = "powchain";
 pub const VALIDATOR_DB_COLUMN: &str = "validator";
+pub const BLOCK_SLOTS_DB_COLUMN: &str = "block_slots";
+pub const METADATA_DB_COLUMN: &str = "metadata";
 
-pub const COLUMNS: [&str; 4] = [
+pub const COLUMNS: [&str; 6] = [
     BLOCKS_DB_COLUMN,
     STATES_DB_COLUMN,
     POW_CHAIN_DB_COLUMN,
     VALIDATOR_DB_COLUMN,
+    BLOCK_SLOTS_DB_COLUMN,
+    METADATA_DB_COLUMN,
 ];
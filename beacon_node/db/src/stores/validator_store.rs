@@ -24,6 +24,9 @@ enum KeyPrefixes {
     PublicKey,
 }
 
+/// The key of the number of public keys stored.
+const PUBLIC_KEY_COUNT_KEY: &[u8] = b"pubkey_count";
+
 pub struct ValidatorStore<T>
 where
     T: ClientDB,
@@ -61,6 +64,13 @@ impl<T: ClientDB> ValidatorStore<T> {
             .map_err(ValidatorStoreError::from)
     }
 
+    pub fn delete_public_key_by_index(&self, index: usize) -> Result<(), ValidatorStoreError> {
+        let key = self.get_db_key_for_index(&KeyPrefixes::PublicKey, index);
+        self.db
+            .delete(DB_COLUMN, &key[..])
+            .map_err(ValidatorStoreError::from)
+    }
+
     pub fn get_public_key_by_index(
         &self,
         index: usize,
@@ -75,6 +85,40 @@ impl<T: ClientDB> ValidatorStore<T> {
             },
         }
     }
+
+    /// Returns the SSZ encoding of the public key at `index`, without decoding it.
+    ///
+    /// Decoding decompresses the key, so comparing the encoding with a known key is much cheaper.
+    pub fn get_public_key_bytes_by_index(
+        &self,
+        index: usize,
+    ) -> Result<Option<Vec<u8>>, ValidatorStoreError> {
+        let key = self.get_db_key_for_index(&KeyPrefixes::PublicKey, index);
+        self.db
+            .get(DB_COLUMN, &key[..])
+            .map_err(ValidatorStoreError::from)
+    }
+
+    /// Returns the number of public keys stored, those of indices `0..count`.
+    pub fn get_public_key_count(&self) -> Result<Option<usize>, ValidatorStoreError> {
+        match self.db.get(DB_COLUMN, PUBLIC_KEY_COUNT_KEY)? {
+            None => Ok(None),
+            Some(val) => match u64::ssz_decode(&val, 0) {
+                Ok((count, _)) => Ok(Some(count as usize)),
+                Err(_) => Err(ValidatorStoreError::DecodeError),
+            },
+        }
+    }
+
+    pub fn put_public_key_count(&self, count: usize) -> Result<(), ValidatorStoreError> {
+        self.db
+            .put(
+                DB_COLUMN,
+                PUBLIC_KEY_COUNT_KEY,
+                &ssz_encode(&(count as u64))[..],
+            )
+            .map_err(ValidatorStoreError::from)
+    }
 }
 
 #[cfg(test)]
@@ -179,6 +223,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_public_key_bytes_by_index() {
+        let db = Arc::new(MemoryDB::open());
+        let store = ValidatorStore::new(db.clone());
+
+        let public_key = Keypair::random().pk;
+
+        store.put_public_key_by_index(7, &public_key).unwrap();
+        assert_eq!(
+            store.get_public_key_bytes_by_index(7),
+            Ok(Some(ssz_encode(&public_key)))
+        );
+        assert_eq!(store.get_public_key_bytes_by_index(8), Ok(None));
+    }
+
+    #[test]
+    fn test_public_key_count() {
+        let db = Arc::new(MemoryDB::open());
+        let store = ValidatorStore::new(db);
+
+        assert_eq!(store.get_public_key_count(), Ok(None));
+        store.put_public_key_count(42).unwrap();
+        assert_eq!(store.get_public_key_count(), Ok(Some(42)));
+    }
+
     #[test]
     fn test_validator_store_put_get() {
         let db = Arc::new(MemoryDB::open());
@@ -25,4 +25,25 @@ pub trait ClientDB: Sync + Send {
     fn exists(&self, col: &str, key: &[u8]) -> Result<bool, DBError>;
 
     fn delete(&self, col: &str, key: &[u8]) -> Result<(), DBError>;
+
+    /// Returns all key-value pairs in some column with a key in the range `[start, end)`, ordered
+    /// by key.
+    fn get_range(
+        &self,
+        col: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, DBValue)>, DBError>;
+
+    /// Returns every key in some column, ordered by key.
+    fn keys(&self, col: &str) -> Result<Vec<Vec<u8>>, DBError>;
+
+    /// Sets the values of many keys of some column, which backends may write at once rather than
+    /// key by key.
+    fn put_batch(&self, col: &str, pairs: &[(Vec<u8>, DBValue)]) -> Result<(), DBError> {
+        for (key, val) in pairs {
+            self.put(col, key, val)?;
+        }
+        Ok(())
+    }
 }
@@ -1,19 +1,33 @@
-use crate::rpc::{RPCEvent, RPCMessage, Rpc};
+use crate::discovery::Discovery;
+use crate::encoding::Encoding;
+use crate::rpc::{self, InvalidRPC, RPCEvent, RPCMessage, Rpc};
 use crate::NetworkConfig;
 use futures::prelude::*;
 use libp2p::{
     core::{
-        swarm::{NetworkBehaviourAction, NetworkBehaviourEventProcess},
+        swarm::{toggle::Toggle, NetworkBehaviourAction, NetworkBehaviourEventProcess},
         PublicKey,
     },
-    gossipsub::{Gossipsub, GossipsubEvent},
+    gossipsub::{Gossipsub, GossipsubEvent, MessageId},
     identify::{protocol::IdentifyInfo, Identify, IdentifyEvent},
+    kad::KademliaOut,
+    mdns::{Mdns, MdnsEvent},
     ping::{Ping, PingEvent},
     tokio_io::{AsyncRead, AsyncWrite},
-    NetworkBehaviour, PeerId,
+    Multiaddr, NetworkBehaviour, PeerId,
 };
-use slog::{debug, o};
-use types::Topic;
+use slog::{debug, info, o, trace, warn};
+use ssz::{ssz_encode, Decodable, DecodeError, Encodable, SszStream};
+use types::{Attestation, BeaconBlock, Topic, TopicBuilder};
+
+/// The gossipsub topic on which new beacon blocks are propagated.
+pub const BEACON_BLOCK_TOPIC: &str = "beacon_block";
+/// The gossipsub topic on which attestations are propagated.
+pub const BEACON_ATTESTATION_TOPIC: &str = "beacon_attestation";
+/// All gossipsub topics known to the beacon node.
+pub const GOSSIP_TOPICS: &[&str] = &[BEACON_BLOCK_TOPIC, BEACON_ATTESTATION_TOPIC];
+/// The default maximum size of a decompressed gossipsub message.
+pub const DEFAULT_MAX_GOSSIP_SIZE: usize = 1_048_576; // 1M
 
 /// Builds the network behaviour for the libp2p Swarm.
 /// Implements gossipsub message routing.
@@ -22,9 +36,12 @@ use types::Topic;
 pub struct Behaviour<TSubstream: AsyncRead + AsyncWrite> {
     /// The routing pub-sub mechanism for eth2.
     gossipsub: Gossipsub<TSubstream>,
-    // TODO: Add Kademlia for peer discovery
     /// The events generated by this behaviour to be consumed in the swarm poll.
     serenity_rpc: Rpc<TSubstream>,
+    /// Discovers new peers when searched for.
+    discovery: Discovery<TSubstream>,
+    /// Discovers peers on the local network, if enabled.
+    mdns: Toggle<Mdns<TSubstream>>,
     /// Allows discovery of IP addresses for peers on the network.
     identify: Identify<TSubstream>,
     /// Keep regular connection to peers and disconnect if absent.
@@ -33,6 +50,16 @@ pub struct Behaviour<TSubstream: AsyncRead + AsyncWrite> {
     ping: Ping<TSubstream>,
     #[behaviour(ignore)]
     events: Vec<BehaviourEvent>,
+    /// This node's PeerId, which is never reported as discovered.
+    #[behaviour(ignore)]
+    local_peer_id: PeerId,
+    /// The encoding of gossipsub messages, which determines the topics subscribed and published
+    /// to.
+    #[behaviour(ignore)]
+    gossip_encoding: Encoding,
+    /// The maximum size of a decompressed gossipsub message.
+    #[behaviour(ignore)]
+    max_gossip_size: usize,
     /// Logger for behaviour actions.
     #[behaviour(ignore)]
     log: slog::Logger,
@@ -44,11 +71,43 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<GossipsubE
 {
     fn inject_event(&mut self, event: GossipsubEvent) {
         match event {
-            GossipsubEvent::Message(message) => {
-                let gs_message = String::from_utf8_lossy(&message.data);
-                // TODO: Remove this type - debug only
-                self.events
-                    .push(BehaviourEvent::Message(gs_message.to_string()))
+            // the message is forwarded to our other peers only once validated, see `propagate`
+            GossipsubEvent::Message(propagation_source, id, gs_message) => {
+                let topics = gs_message
+                    .topics
+                    .iter()
+                    .map(|topic| topic.as_str().to_string())
+                    .collect();
+
+                // the encoding of a message is given by the topic it was published on
+                let encoding = gs_message
+                    .topics
+                    .first()
+                    .map(|topic| Encoding::from_suffix(topic.as_str().as_bytes()))
+                    .unwrap_or(self.gossip_encoding);
+                let decoded = encoding
+                    .decode(gs_message.data, self.max_gossip_size)
+                    .map_err(rpc::DecodeError::from)
+                    .and_then(|data| {
+                        PubsubMessage::ssz_decode(&data, 0).map_err(rpc::DecodeError::from)
+                    });
+
+                match decoded {
+                    Ok((message, _index)) => {
+                        trace!(self.log, "Received gossip message"; "topics" => format!("{:?}", topics));
+                        self.events.push(BehaviourEvent::GossipMessage {
+                            id,
+                            source: propagation_source,
+                            topics,
+                            message,
+                        })
+                    }
+                    Err(error) => self.events.push(BehaviourEvent::InvalidGossip {
+                        source: propagation_source,
+                        topics,
+                        error,
+                    }),
+                }
             }
             _ => {}
         }
@@ -63,9 +122,15 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<RPCMessage
             RPCMessage::PeerDialed(peer_id) => {
                 self.events.push(BehaviourEvent::PeerDialed(peer_id))
             }
-            RPCMessage::RPC(peer_id, rpc_event) => {
-                self.events.push(BehaviourEvent::RPC(peer_id, rpc_event))
+            RPCMessage::PeerDisconnected(peer_id) => {
+                self.events.push(BehaviourEvent::PeerDisconnected(peer_id))
             }
+            RPCMessage::RPC(peer_id, rpc_event, len) => self
+                .events
+                .push(BehaviourEvent::RPC(peer_id, rpc_event, len)),
+            RPCMessage::InvalidRPC(peer_id, invalid) => self
+                .events
+                .push(BehaviourEvent::InvalidRPC(peer_id, invalid)),
         }
     }
 }
@@ -85,6 +150,10 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<IdentifyEv
                     );
                     info.listen_addrs.truncate(20);
                 }
+                for address in &info.listen_addrs {
+                    self.discovery
+                        .add_connected_address(&peer_id, address.clone());
+                }
                 self.events.push(BehaviourEvent::Identified(peer_id, info));
             }
             IdentifyEvent::Error { .. } => {}
@@ -93,6 +162,41 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<IdentifyEv
     }
 }
 
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<KademliaOut>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: KademliaOut) {
+        if let KademliaOut::Discovered {
+            peer_id, addresses, ..
+        } = event
+        {
+            self.peer_discovered(peer_id, addresses);
+        }
+    }
+}
+
+impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<MdnsEvent>
+    for Behaviour<TSubstream>
+{
+    fn inject_event(&mut self, event: MdnsEvent) {
+        match event {
+            MdnsEvent::Discovered(peers) => {
+                for (peer_id, address) in peers {
+                    trace!(
+                        self.log,
+                        "Discovered local peer";
+                        "peer" => format!("{:?}", peer_id),
+                        "address" => format!("{}", address)
+                    );
+                    self.peer_discovered(peer_id, vec![address]);
+                }
+            }
+            // peers which go quiet are disconnected by the ping protocol.
+            MdnsEvent::Expired(_) => {}
+        }
+    }
+}
+
 impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<PingEvent>
     for Behaviour<TSubstream>
 {
@@ -107,9 +211,26 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
         let identify_config = net_conf.identify_config.clone();
         let behaviour_log = log.new(o!());
 
+        let mdns = if net_conf.mdns_enabled {
+            match Mdns::new() {
+                Ok(mdns) => {
+                    info!(log, "Discovering peers on the local network with mDNS");
+                    Some(mdns)
+                }
+                Err(e) => {
+                    warn!(log, "Unable to start mDNS discovery"; "error" => format!("{:?}", e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         Behaviour {
-            gossipsub: Gossipsub::new(local_peer_id, net_conf.gs_config.clone()),
-            serenity_rpc: Rpc::new(log),
+            gossipsub: Gossipsub::new(local_peer_id.clone(), net_conf.gs_config.clone()),
+            serenity_rpc: Rpc::new(net_conf.snappy_compression, net_conf.max_rpc_size, log),
+            discovery: Discovery::new(local_peer_id.clone(), log),
+            mdns: Toggle::from(mdns),
             identify: Identify::new(
                 identify_config.version,
                 identify_config.user_agent,
@@ -117,10 +238,25 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
             ),
             ping: Ping::new(),
             events: Vec::new(),
+            local_peer_id,
+            gossip_encoding: if net_conf.snappy_compression {
+                Encoding::SSZSnappy
+            } else {
+                Encoding::SSZ
+            },
+            max_gossip_size: net_conf.max_gossip_size,
             log: behaviour_log,
         }
     }
 
+    /// Reports the addresses of a peer found by discovery, other than this node.
+    fn peer_discovered(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+        if peer_id != self.local_peer_id && !addresses.is_empty() {
+            self.events
+                .push(BehaviourEvent::PeerDiscovered(peer_id, addresses));
+        }
+    }
+
     /// Consumes the events list when polled.
     fn poll<TBehaviourIn>(
         &mut self,
@@ -135,9 +271,39 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
 
 /// Implements the combined behaviour for the libp2p service.
 impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
-    /// Subscribes to a gossipsub topic.
+    /// Starts a search for new peers, which are reported as they are discovered.
+    pub fn find_peers(&mut self) {
+        self.discovery.find_peers();
+    }
+
+    /// Subscribes to a gossipsub topic, in each encoding of `Encoding::topics`. Returns `true`
+    /// if any of the encoded topics is newly subscribed to.
     pub fn subscribe(&mut self, topic: Topic) -> bool {
-        self.gossipsub.subscribe(topic)
+        let mut subscribed = false;
+        for topic in self.gossip_encoding.topics(topic.name()) {
+            subscribed |= self.gossipsub.subscribe(TopicBuilder::new(topic).build());
+        }
+        subscribed
+    }
+
+    /// Publishes an SSZ encoded message on the given gossipsub topics, in each encoding of
+    /// `Encoding::topics`.
+    pub fn publish(&mut self, topics: Vec<Topic>, message: Vec<u8>) {
+        for topic in topics {
+            for encoded_topic in self.gossip_encoding.topics(topic.name()) {
+                let encoding = Encoding::from_suffix(encoded_topic.as_bytes());
+                self.gossipsub.publish(
+                    TopicBuilder::new(encoded_topic).build(),
+                    encoding.encode(message.clone()),
+                );
+            }
+        }
+    }
+
+    /// Forwards a received gossipsub message to our other peers on its topics, once it has been
+    /// validated. `source` is the peer the message was received from.
+    pub fn propagate(&mut self, id: &MessageId, source: &PeerId) {
+        self.gossipsub.propagate_message(id, source);
     }
 
     /// Sends an RPC Request/Response via the RPC protocol.
@@ -148,9 +314,75 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
 
 /// The types of events than can be obtained from polling the behaviour.
 pub enum BehaviourEvent {
-    RPC(PeerId, RPCEvent),
+    /// An RPC message received from a peer, with its length on the wire.
+    RPC(PeerId, RPCEvent, usize),
+    InvalidRPC(PeerId, InvalidRPC),
     PeerDialed(PeerId),
+    PeerDisconnected(PeerId),
     Identified(PeerId, IdentifyInfo),
-    // TODO: This is a stub at the moment
-    Message(String),
+    /// A peer, and the addresses it listens on, found by discovery.
+    PeerDiscovered(PeerId, Vec<Multiaddr>),
+    /// A gossipsub message has been received from `source`. It is not forwarded unless
+    /// `Behaviour::propagate` is called with its `id`.
+    GossipMessage {
+        id: MessageId,
+        source: PeerId,
+        topics: Vec<String>,
+        message: PubsubMessage,
+    },
+    /// A gossipsub message has been received which could not be decoded.
+    InvalidGossip {
+        source: PeerId,
+        topics: Vec<String>,
+        error: rpc::DecodeError,
+    },
+}
+
+/// Messages that are passed to and from the pubsub (Gossipsub) behaviour.
+#[derive(Debug, Clone)]
+pub enum PubsubMessage {
+    /// A newly proposed beacon block.
+    Block(BeaconBlock),
+    /// An attestation, possibly aggregated.
+    Attestation(Attestation),
+}
+
+//TODO: Correctly encode/decode enums. Prefixing with integer for now.
+impl Encodable for PubsubMessage {
+    fn ssz_append(&self, s: &mut SszStream) {
+        match self {
+            PubsubMessage::Block(block) => {
+                s.append(&0u32);
+                s.append(block);
+            }
+            PubsubMessage::Attestation(attestation) => {
+                s.append(&1u32);
+                s.append(attestation);
+            }
+        }
+    }
+}
+
+impl Decodable for PubsubMessage {
+    fn ssz_decode(bytes: &[u8], index: usize) -> Result<(Self, usize), DecodeError> {
+        let (id, index) = u32::ssz_decode(bytes, index)?;
+        match id {
+            0 => {
+                let (block, index) = BeaconBlock::ssz_decode(bytes, index)?;
+                Ok((PubsubMessage::Block(block), index))
+            }
+            1 => {
+                let (attestation, index) = Attestation::ssz_decode(bytes, index)?;
+                Ok((PubsubMessage::Attestation(attestation), index))
+            }
+            _ => Err(DecodeError::Invalid),
+        }
+    }
+}
+
+impl PubsubMessage {
+    /// Returns the SSZ encoding of the message, as published on gossipsub.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        ssz_encode(self)
+    }
 }
@@ -1,5 +1,10 @@
+use crate::behaviour::{BEACON_ATTESTATION_TOPIC, DEFAULT_MAX_GOSSIP_SIZE, GOSSIP_TOPICS};
+use crate::rpc::DEFAULT_MAX_RPC_SIZE;
 use crate::Multiaddr;
 use libp2p::gossipsub::{GossipsubConfig, GossipsubConfigBuilder};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Clone, Debug)]
 /// Network configuration for lighthouse.
@@ -9,16 +14,70 @@ pub struct Config {
     pub listen_addresses: Vec<Multiaddr>,
     /// Listen port UDP/TCP.
     pub listen_port: u16,
-    /// Gossipsub configuration parameters.
+    /// The address advertised to peers through identify and discovery, in addition to those
+    /// listened on. Set when this node is reached through an address it cannot listen on, e.g.
+    /// from behind a NAT.
+    pub discovery_address: Option<IpAddr>,
+    /// Whether to map the listen port on a UPnP gateway, or a NAT-PMP gateway if none is found, and
+    /// advertise the external address.
+    pub upnp_enabled: bool,
+    /// Whether to discover peers on the local network with mDNS, for devnets on a single LAN.
+    /// Peers discovered this way are dialed like any other, so this should not be enabled on
+    /// public networks.
+    pub mdns_enabled: bool,
+    /// Gossipsub configuration parameters. Received messages are only forwarded once validated,
+    /// see `Service::propagate`, so manual propagation should remain enabled.
     pub gs_config: GossipsubConfig,
     /// Configuration parameters for node identification protocol.
     pub identify_config: IdentifyConfig,
     /// List of nodes to initially connect to.
     pub boot_nodes: Vec<Multiaddr>,
+    /// Addresses of peers which are always kept connected and never banned. Each must include
+    /// the `/p2p/` peer id of the peer.
+    pub trusted_peers: Vec<Multiaddr>,
     /// Client version
     pub client_version: String,
-    /// List of topics to subscribe to as strings
+    /// List of topics to subscribe to as strings. All known topics are subscribed to by default.
     pub topics: Vec<String>,
+    /// Whether to stay subscribed to every attestation subnet, whatever validators are attached,
+    /// e.g. for aggregating, research or bootstrap nodes. The queues and caches handling gossip
+    /// are enlarged for the extra traffic.
+    pub subscribe_all_subnets: bool,
+    /// The maximum bytes of block range responses served to a single peer per hour, if any.
+    pub peer_hourly_upload_limit: Option<u64>,
+    /// The maximum bytes of block range responses served to a single peer per day, if any.
+    pub peer_daily_upload_limit: Option<u64>,
+    /// The maximum bytes per second uploaded by RPC messages and locally published gossip, if any.
+    ///
+    /// Locally published messages take priority over RPC responses when the limit is reached.
+    pub max_upload_bytes_per_second: Option<u64>,
+    /// The duration a misbehaving peer is banned for.
+    pub peer_ban_duration: Duration,
+    /// The maximum number of our RPC requests awaiting a response from a single peer. Further
+    /// requests are queued until earlier ones complete or time out.
+    pub max_requests_per_peer: usize,
+    /// Whether RPC and gossipsub payloads are compressed with snappy.
+    ///
+    /// RPC compression is negotiated with each peer. Compressed gossipsub messages are published
+    /// on separate topics, so a node which compresses also publishes and receives on the
+    /// uncompressed topics.
+    pub snappy_compression: bool,
+    /// The maximum size of a decompressed gossipsub message. The decompressed size of a snappy
+    /// message is checked before it is decompressed, so compression bombs are rejected cheaply
+    /// and their sender penalized.
+    pub max_gossip_size: usize,
+    /// The maximum size of an RPC message, both as received and once decompressed.
+    pub max_rpc_size: usize,
+    /// The number of peers to maintain connections to. Further peers are dialed when below the
+    /// target and the lowest-scored peers are disconnected when above it.
+    pub target_peers: usize,
+    /// The file holding the private key which identifies this node, generated if it does not
+    /// exist. If `None`, a new key is generated on each start.
+    pub key_path: Option<PathBuf>,
+    /// Whether to replace the key at `key_path` with a newly generated one.
+    pub new_identity: bool,
+    /// The file to which peer connections, disconnections and bans are appended, if any.
+    pub audit_log: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -29,11 +88,31 @@ impl Default for Config {
                 .parse()
                 .expect("is a correct multi-address")],
             listen_port: 9000,
-            gs_config: GossipsubConfigBuilder::new().build(),
+            discovery_address: None,
+            upnp_enabled: false,
+            mdns_enabled: false,
+            gs_config: GossipsubConfigBuilder::new().manual_propagation().build(),
             identify_config: IdentifyConfig::default(),
             boot_nodes: Vec::new(),
+            trusted_peers: Vec::new(),
             client_version: version::version(),
-            topics: vec![String::from("beacon_chain")],
+            topics: GOSSIP_TOPICS
+                .iter()
+                .map(|topic| topic.to_string())
+                .collect(),
+            subscribe_all_subnets: false,
+            peer_hourly_upload_limit: None,
+            peer_daily_upload_limit: None,
+            max_upload_bytes_per_second: None,
+            peer_ban_duration: Duration::from_secs(30 * 60),
+            max_requests_per_peer: 4,
+            snappy_compression: false,
+            max_gossip_size: DEFAULT_MAX_GOSSIP_SIZE,
+            max_rpc_size: DEFAULT_MAX_RPC_SIZE,
+            target_peers: 50,
+            key_path: None,
+            new_identity: false,
+            audit_log: None,
         }
     }
 }
@@ -45,6 +124,35 @@ impl Config {
 
         conf
     }
+
+    /// Stops subscribing to a gossipsub topic, e.g. for a relay-only node which does not process
+    /// attestations.
+    ///
+    /// Returns an error if the topic is unknown or already disabled.
+    pub fn disable_topic(&mut self, topic: &str) -> Result<(), String> {
+        if !GOSSIP_TOPICS.contains(&topic) {
+            return Err(format!("Unknown gossip topic: {}", topic));
+        }
+        if !self.topics.iter().any(|t| t == topic) {
+            return Err(format!("Gossip topic already disabled: {}", topic));
+        }
+        self.topics.retain(|t| t != topic);
+        Ok(())
+    }
+
+    /// Subscribes to every attestation subnet, see `subscribe_all_subnets`.
+    ///
+    /// Returns an error if the attestation topic has been disabled.
+    pub fn enable_all_subnets(&mut self) -> Result<(), String> {
+        if !self.topics.iter().any(|t| t == BEACON_ATTESTATION_TOPIC) {
+            return Err(format!(
+                "Gossip topic {} is disabled, so subnets cannot be subscribed to",
+                BEACON_ATTESTATION_TOPIC
+            ));
+        }
+        self.subscribe_all_subnets = true;
+        Ok(())
+    }
 }
 
 /// The configuration parameters for the Identify protocol
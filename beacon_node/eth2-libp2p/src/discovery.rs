@@ -0,0 +1,117 @@
+use futures::prelude::*;
+use libp2p::core::protocols_handler::ProtocolsHandler;
+use libp2p::core::swarm::{
+    ConnectedPoint, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+};
+use libp2p::kad::{Kademlia, KademliaOut};
+use libp2p::{Multiaddr, PeerId};
+use slog::{debug, o, trace};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Discovers new peers with a Kademlia random walk.
+///
+/// Discovered peers are only reported. Which of them are dialed, and when to search for more, is
+/// decided by the network service, which tracks the connected peers.
+pub struct Discovery<TSubstream> {
+    /// The Kademlia behaviour used to discover new peers.
+    discovery: Kademlia<TSubstream>,
+    /// Logger for the discovery behaviour.
+    log: slog::Logger,
+}
+
+impl<TSubstream> Discovery<TSubstream> {
+    pub fn new(local_peer_id: PeerId, log: &slog::Logger) -> Self {
+        let log = log.new(o!("Service" => "Libp2p-Discovery"));
+        Discovery {
+            discovery: Kademlia::new(local_peer_id),
+            log,
+        }
+    }
+
+    /// Records an address a connected peer is listening on, making the peer available to
+    /// discovery queries.
+    pub fn add_connected_address(&mut self, peer_id: &PeerId, address: Multiaddr) {
+        self.discovery.add_connected_address(peer_id, address);
+    }
+
+    /// Starts a query for the peers closest to a random peer id.
+    pub fn find_peers(&mut self) {
+        debug!(self.log, "Searching for peers");
+        self.discovery.find_node(PeerId::random());
+    }
+}
+
+// Handles the Kademlia protocol with the underlying behaviour.
+impl<TSubstream> NetworkBehaviour for Discovery<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    type ProtocolsHandler = <Kademlia<TSubstream> as NetworkBehaviour>::ProtocolsHandler;
+    type OutEvent = KademliaOut;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        NetworkBehaviour::new_handler(&mut self.discovery)
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.discovery.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        NetworkBehaviour::inject_connected(&mut self.discovery, peer_id, endpoint)
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        NetworkBehaviour::inject_disconnected(&mut self.discovery, peer_id, endpoint)
+    }
+
+    fn inject_replaced(&mut self, peer_id: PeerId, closed: ConnectedPoint, opened: ConnectedPoint) {
+        NetworkBehaviour::inject_replaced(&mut self.discovery, peer_id, closed, opened)
+    }
+
+    fn inject_node_event(
+        &mut self,
+        peer_id: PeerId,
+        event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        NetworkBehaviour::inject_node_event(&mut self.discovery, peer_id, event)
+    }
+
+    fn poll(
+        &mut self,
+        params: &mut PollParameters<'_>,
+    ) -> Async<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        match self.discovery.poll(params) {
+            Async::Ready(NetworkBehaviourAction::GenerateEvent(event)) => {
+                match &event {
+                    KademliaOut::Discovered {
+                        peer_id, addresses, ..
+                    } => {
+                        trace!(
+                            self.log,
+                            "Discovered peer";
+                            "peer" => format!("{:?}", peer_id),
+                            "addresses" => format!("{:?}", addresses)
+                        );
+                    }
+                    KademliaOut::FindNodeResult { closer_peers, .. } => {
+                        debug!(
+                            self.log,
+                            "Peer search completed";
+                            "peers_found" => closer_peers.len()
+                        );
+                    }
+                    _ => {}
+                }
+                Async::Ready(NetworkBehaviourAction::GenerateEvent(event))
+            }
+            Async::Ready(action) => Async::Ready(action),
+            Async::NotReady => Async::NotReady,
+        }
+    }
+}
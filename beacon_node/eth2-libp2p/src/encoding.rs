@@ -0,0 +1,224 @@
+use std::io::{self, Read, Write};
+
+/// The reason a payload could not be decoded.
+#[derive(Debug)]
+pub enum EncodingError {
+    /// The payload would decompress to `len` bytes, more than `max_len`. It is rejected before
+    /// being decompressed, so compression bombs are cheap to reject.
+    TooLong { len: usize, max_len: usize },
+    /// The payload is not validly compressed.
+    Invalid(io::Error),
+}
+
+/// The suffix of protocol ids and gossipsub topics whose payloads are snappy compressed.
+pub const SNAPPY_SUFFIX: &str = "/ssz_snappy";
+
+/// The encoding of RPC and gossipsub payloads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Raw SSZ.
+    SSZ,
+    /// SSZ compressed with the snappy frame format.
+    SSZSnappy,
+}
+
+impl Encoding {
+    /// Returns the encoding of payloads on a protocol id or gossipsub topic.
+    pub fn from_suffix(name: &[u8]) -> Self {
+        if name.ends_with(SNAPPY_SUFFIX.as_bytes()) {
+            Encoding::SSZSnappy
+        } else {
+            Encoding::SSZ
+        }
+    }
+
+    /// Returns the gossipsub topic on which payloads of this encoding are published.
+    pub fn topic(self, topic: &str) -> String {
+        match self {
+            Encoding::SSZ => topic.to_string(),
+            Encoding::SSZSnappy => format!("{}{}", topic, SNAPPY_SUFFIX),
+        }
+    }
+
+    /// Returns the gossipsub topics subscribed and published to by a node of this encoding.
+    ///
+    /// A node which compresses also uses the uncompressed topic, so that it remains connected to
+    /// nodes which do not compress rather than splitting the network by encoding.
+    pub fn topics(self, topic: &str) -> Vec<String> {
+        match self {
+            Encoding::SSZ => vec![Encoding::SSZ.topic(topic)],
+            Encoding::SSZSnappy => {
+                vec![Encoding::SSZSnappy.topic(topic), Encoding::SSZ.topic(topic)]
+            }
+        }
+    }
+
+    /// Encodes SSZ bytes.
+    pub fn encode(self, bytes: Vec<u8>) -> Vec<u8> {
+        match self {
+            Encoding::SSZ => bytes,
+            Encoding::SSZSnappy => {
+                let mut writer = snap::Writer::new(Vec::with_capacity(bytes.len()));
+                writer
+                    .write_all(&bytes)
+                    .and_then(|()| writer.flush())
+                    .expect("writing to a vec cannot fail");
+                writer.into_inner().expect("writer has been flushed")
+            }
+        }
+    }
+
+    /// Decodes bytes into SSZ, failing if the decoded length would exceed `max_len`.
+    ///
+    /// The decoded length of a snappy payload is read from its chunk headers and checked before
+    /// anything is decompressed.
+    pub fn decode(self, bytes: Vec<u8>, max_len: usize) -> Result<Vec<u8>, EncodingError> {
+        match self {
+            Encoding::SSZ => Ok(bytes),
+            Encoding::SSZSnappy => {
+                let len = decompressed_len(&bytes).map_err(EncodingError::Invalid)?;
+                if len > max_len {
+                    return Err(EncodingError::TooLong { len, max_len });
+                }
+                // the headers are verified as the chunks are decompressed, so the length is
+                // limited again in case they understate it
+                let mut decoded = Vec::with_capacity(len);
+                snap::Reader::new(&bytes[..])
+                    .take(max_len as u64 + 1)
+                    .read_to_end(&mut decoded)
+                    .map_err(EncodingError::Invalid)?;
+                if decoded.len() > max_len {
+                    return Err(EncodingError::TooLong {
+                        len: decoded.len(),
+                        max_len,
+                    });
+                }
+                Ok(decoded)
+            }
+        }
+    }
+}
+
+/// Returns the length of a stream of snappy frames once decompressed, read from the header of
+/// each chunk and the length preamble of each compressed chunk.
+fn decompressed_len(mut bytes: &[u8]) -> io::Result<usize> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+    let mut len: usize = 0;
+    while !bytes.is_empty() {
+        if bytes.len() < 4 {
+            return Err(invalid("truncated chunk header"));
+        }
+        let chunk_type = bytes[0];
+        let chunk_len = bytes[1] as usize | (bytes[2] as usize) << 8 | (bytes[3] as usize) << 16;
+        let chunk = bytes
+            .get(4..4 + chunk_len)
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        let chunk_decompressed_len = match chunk_type {
+            // compressed data, following a checksum
+            0x00 => {
+                let data = chunk.get(4..).ok_or_else(|| invalid("truncated chunk"))?;
+                snap::decompress_len(data).map_err(|e| invalid(&e.to_string()))?
+            }
+            // uncompressed data, following a checksum
+            0x01 => chunk_len.saturating_sub(4),
+            0x02..=0x7f => return Err(invalid("reserved unskippable chunk")),
+            // the stream identifier, padding and skippable chunks
+            _ => 0,
+        };
+        len = len.saturating_add(chunk_decompressed_len);
+        bytes = &bytes[4 + chunk_len..];
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssz::{ssz_encode, Decodable};
+    use types::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+    use types::{BeaconBlock, BeaconState};
+
+    const MAX_LEN: usize = 1 << 24;
+
+    #[test]
+    fn round_trip_beacon_state() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let state = BeaconState::random_for_test(&mut rng);
+        let ssz = ssz_encode(&state);
+
+        for encoding in &[Encoding::SSZ, Encoding::SSZSnappy] {
+            let encoded = encoding.encode(ssz.clone());
+            let decoded = encoding.decode(encoded, MAX_LEN).unwrap();
+            assert_eq!(decoded, ssz);
+            assert_eq!(BeaconState::ssz_decode(&decoded, 0).unwrap().0, state);
+        }
+    }
+
+    #[test]
+    fn round_trip_block_batch() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let blocks: Vec<BeaconBlock> = (0..64)
+            .map(|_| BeaconBlock::random_for_test(&mut rng))
+            .collect();
+        let ssz = ssz_encode(&blocks);
+
+        let encoded = Encoding::SSZSnappy.encode(ssz.clone());
+        let decoded = Encoding::SSZSnappy.decode(encoded, MAX_LEN).unwrap();
+        let (decoded_blocks, _) = Vec::<BeaconBlock>::ssz_decode(&decoded, 0).unwrap();
+        assert_eq!(decoded_blocks, blocks);
+    }
+
+    #[test]
+    fn rejects_oversized_payload() {
+        let encoded = Encoding::SSZSnappy.encode(vec![0; 1024]);
+        assert!(Encoding::SSZSnappy.decode(encoded.clone(), 1024).is_ok());
+        assert!(Encoding::SSZSnappy.decode(encoded, 1023).is_err());
+    }
+
+    #[test]
+    fn reads_decompressed_len_from_headers() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let ssz = ssz_encode(&BeaconState::random_for_test(&mut rng));
+        let encoded = Encoding::SSZSnappy.encode(ssz.clone());
+        assert_eq!(decompressed_len(&encoded).unwrap(), ssz.len());
+    }
+
+    #[test]
+    fn rejects_compression_bomb() {
+        let encoded = Encoding::SSZSnappy.encode(vec![0; 1 << 22]);
+        assert!(encoded.len() < 1 << 20);
+        match Encoding::SSZSnappy.decode(encoded, 1 << 20) {
+            Err(EncodingError::TooLong { len, max_len }) => {
+                assert_eq!(len, 1 << 22);
+                assert_eq!(max_len, 1 << 20);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_chunk() {
+        let mut encoded = Encoding::SSZSnappy.encode(vec![0; 1024]);
+        encoded.pop();
+        match Encoding::SSZSnappy.decode(encoded, MAX_LEN) {
+            Err(EncodingError::Invalid(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn encoding_from_suffix() {
+        let topic = Encoding::SSZSnappy.topic("beacon_block");
+        assert_eq!(Encoding::from_suffix(topic.as_bytes()), Encoding::SSZSnappy);
+        assert_eq!(Encoding::from_suffix(b"beacon_block"), Encoding::SSZ);
+    }
+
+    #[test]
+    fn compressing_nodes_also_use_the_uncompressed_topic() {
+        assert_eq!(Encoding::SSZ.topics("beacon_block"), vec!["beacon_block"]);
+        assert_eq!(
+            Encoding::SSZSnappy.topics("beacon_block"),
+            vec!["beacon_block/ssz_snappy", "beacon_block"]
+        );
+    }
+}
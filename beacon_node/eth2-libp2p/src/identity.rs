@@ -0,0 +1,117 @@
+use crate::error;
+use libp2p::secio::SecioKeyPair;
+use slog::{info, warn};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// The length of a secp256k1 private key, as stored in a key file.
+const PRIVATE_KEY_LENGTH: usize = 32;
+
+/// Returns the keypair which identifies this node to its peers.
+///
+/// The private key is read from `key_path` if the file exists. Otherwise, or if `new_identity` is
+/// set, a new key is generated and written to `key_path`, so that the node keeps its `PeerId`
+/// across restarts. Without a `key_path` the keypair is generated for this run only.
+pub fn load_keypair(
+    key_path: Option<&Path>,
+    new_identity: bool,
+    log: &slog::Logger,
+) -> error::Result<SecioKeyPair> {
+    let key_path = match key_path {
+        Some(key_path) => key_path,
+        None => return Ok(generate_keypair()?.1),
+    };
+
+    if key_path.exists() && !new_identity {
+        let bytes = fs::read(key_path)
+            .map_err(|e| format!("Unable to read node key {:?}: {:?}", key_path, e))?;
+        if bytes.len() != PRIVATE_KEY_LENGTH {
+            return Err(format!("Invalid node key length in {:?}", key_path).into());
+        }
+        let keypair = SecioKeyPair::secp256k1_raw_key(&bytes)
+            .map_err(|e| format!("Invalid node key in {:?}: {:?}", key_path, e))?;
+        info!(log, "Loaded node key"; "path" => format!("{:?}", key_path));
+        return Ok(keypair);
+    }
+
+    if new_identity && key_path.exists() {
+        warn!(log, "Replacing node key with a new identity"; "path" => format!("{:?}", key_path));
+    }
+    let (bytes, keypair) = generate_keypair()?;
+    write_key(key_path, &bytes)
+        .map_err(|e| format!("Unable to write node key {:?}: {:?}", key_path, e))?;
+    info!(log, "Generated new node key"; "path" => format!("{:?}", key_path));
+    Ok(keypair)
+}
+
+/// Generates a secp256k1 keypair, returning the private key along with it.
+fn generate_keypair() -> error::Result<([u8; PRIVATE_KEY_LENGTH], SecioKeyPair)> {
+    // A random 32 bytes is almost certainly a valid key, but retry in case it is not.
+    for _ in 0..8 {
+        let bytes: [u8; PRIVATE_KEY_LENGTH] = rand::random();
+        if let Ok(keypair) = SecioKeyPair::secp256k1_raw_key(&bytes) {
+            return Ok((bytes, keypair));
+        }
+    }
+    Err("Unable to generate a node key".into())
+}
+
+/// Writes the private key to `path`, readable only by its owner where supported. The file is
+/// restricted before the key is written, whether it is created or replaced.
+fn write_key(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        // the mode only applies to a file created, so a replaced key file is restricted too
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        write_all(file, bytes)
+    }
+    #[cfg(not(unix))]
+    {
+        write_all(options.open(path)?, bytes)
+    }
+}
+
+fn write_all(mut file: fs::File, bytes: &[u8]) -> std::io::Result<()> {
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+
+    #[test]
+    fn keys_are_written_and_read_back() {
+        let dir = std::env::temp_dir().join(format!("identity_test_{}", std::process::id()));
+        let key_path = dir.join("key");
+        let log = slog::Logger::root(slog::Discard, o!());
+
+        let generated = load_keypair(Some(&key_path), false, &log).unwrap();
+        let loaded = load_keypair(Some(&key_path), false, &log).unwrap();
+        assert_eq!(loaded.to_peer_id(), generated.to_peer_id());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // a new identity replaces the key
+        let replaced = load_keypair(Some(&key_path), true, &log).unwrap();
+        assert_ne!(replaced.to_peer_id(), generated.to_peer_id());
+        let loaded = load_keypair(Some(&key_path), false, &log).unwrap();
+        assert_eq!(loaded.to_peer_id(), replaced.to_peer_id());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
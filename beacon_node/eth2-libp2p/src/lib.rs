@@ -4,13 +4,18 @@
 /// This crate builds and manages the libp2p services required by the beacon node.
 pub mod behaviour;
 mod config;
+mod discovery;
+pub mod encoding;
 pub mod error;
+mod identity;
+mod nat;
 pub mod rpc;
 mod service;
 
+pub use behaviour::{PubsubMessage, BEACON_ATTESTATION_TOPIC, BEACON_BLOCK_TOPIC, GOSSIP_TOPICS};
 pub use config::Config as NetworkConfig;
 pub use libp2p::{
-    gossipsub::{GossipsubConfig, GossipsubConfigBuilder},
+    gossipsub::{GossipsubConfig, GossipsubConfigBuilder, MessageId},
     PeerId,
 };
 pub use rpc::{HelloMessage, RPCEvent};
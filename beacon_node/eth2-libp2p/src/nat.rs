@@ -0,0 +1,315 @@
+use igd::{PortMappingProtocol, SearchOptions};
+use slog::{debug, info, warn};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// The duration of a port mapping, after which the gateway removes it.
+const MAPPING_LEASE: Duration = Duration::from_secs(60 * 60);
+/// How often the port mapping is renewed, well within its lease.
+const MAPPING_RENEWAL_INTERVAL: Duration = Duration::from_secs(20 * 60);
+/// The description of the port mapping shown by the gateway.
+const MAPPING_DESCRIPTION: &str = "lighthouse";
+/// The port a NAT-PMP gateway listens on.
+const NAT_PMP_PORT: u16 = 5351;
+/// The time a NAT-PMP request is awaited before being sent again.
+const NAT_PMP_TIMEOUT: Duration = Duration::from_millis(500);
+/// The number of times a NAT-PMP request is sent before the gateway is given up on.
+const NAT_PMP_ATTEMPTS: usize = 3;
+
+/// The mapping of the listen port on the gateway, renewed on its own thread until dropped.
+pub struct PortMapping {
+    /// The external address of the mapping, sent each time it changes.
+    pub addresses: Receiver<SocketAddrV4>,
+    /// Dropped to stop the mapping thread, which then removes the mapping.
+    _stop: Sender<()>,
+}
+
+/// Maps `port` on a UPnP gateway to the same port on this host, or on a NAT-PMP gateway if no
+/// UPnP gateway is found, renewing the mapping until the returned `PortMapping` is dropped.
+///
+/// Searching for a gateway may take several seconds, so it is done on its own thread. The
+/// external address of the mapping is sent each time the mapping is made and the external
+/// address has changed. Nothing is sent if no gateway is found.
+pub fn map_port(port: u16, log: slog::Logger) -> PortMapping {
+    let (sender, receiver) = channel();
+    let (stop_sender, stop) = channel::<()>();
+
+    let spawned = thread::Builder::new()
+        .name("port_mapping".to_string())
+        .spawn(move || {
+            let mut gateway = None;
+            let mut mapped_address = None;
+            loop {
+                match add_port_mapping(port, gateway) {
+                    Ok((mapped_by, external_address)) => {
+                        gateway = Some(mapped_by);
+                        if mapped_address != Some(external_address) {
+                            info!(
+                                log,
+                                "Port mapped";
+                                "gateway" => format!("{:?}", mapped_by),
+                                "external_address" => format!("{}", external_address)
+                            );
+                            mapped_address = Some(external_address);
+                            if sender.send(external_address).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        if mapped_address.is_some() {
+                            warn!(log, "Unable to renew port mapping"; "error" => e);
+                        } else {
+                            debug!(log, "Port mapping unavailable"; "error" => e);
+                            return;
+                        }
+                    }
+                }
+                match stop.recv_timeout(MAPPING_RENEWAL_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // the network service has stopped
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            if let Some(gateway) = gateway {
+                match remove_port_mapping(port, gateway) {
+                    Ok(()) => debug!(log, "Port mapping removed"),
+                    Err(e) => debug!(log, "Unable to remove port mapping"; "error" => e),
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        warn!(log, "Unable to start port mapping thread"; "error" => format!("{:?}", e));
+    }
+
+    PortMapping {
+        addresses: receiver,
+        _stop: stop_sender,
+    }
+}
+
+/// The kind of gateway a port is mapped on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Gateway {
+    Upnp,
+    NatPmp,
+}
+
+/// Maps the TCP `port` on `gateway`, or on whichever gateway is found if not yet known, returning
+/// the gateway and the external address.
+fn add_port_mapping(
+    port: u16,
+    gateway: Option<Gateway>,
+) -> Result<(Gateway, SocketAddrV4), String> {
+    match gateway {
+        Some(Gateway::Upnp) => add_upnp_mapping(port).map(|address| (Gateway::Upnp, address)),
+        Some(Gateway::NatPmp) => {
+            add_nat_pmp_mapping(port).map(|address| (Gateway::NatPmp, address))
+        }
+        None => add_upnp_mapping(port)
+            .map(|address| (Gateway::Upnp, address))
+            .or_else(|upnp_error| {
+                add_nat_pmp_mapping(port)
+                    .map(|address| (Gateway::NatPmp, address))
+                    .map_err(|nat_pmp_error| format!("{}, {}", upnp_error, nat_pmp_error))
+            }),
+    }
+}
+
+/// Removes the mapping of the TCP `port` from `gateway`.
+fn remove_port_mapping(port: u16, gateway: Gateway) -> Result<(), String> {
+    match gateway {
+        Gateway::Upnp => igd::search_gateway(SearchOptions::default())
+            .map_err(|e| format!("No UPnP gateway found: {}", e))?
+            .remove_port(PortMappingProtocol::TCP, port)
+            .map_err(|e| format!("Unable to unmap port {}: {}", port, e)),
+        Gateway::NatPmp => {
+            let gateway = default_gateway()?;
+            nat_pmp_request(gateway, &map_request(port, 0)).map(|_| ())
+        }
+    }
+}
+
+/// Finds the UPnP gateway and maps the TCP `port`, returning the external address.
+fn add_upnp_mapping(port: u16) -> Result<SocketAddrV4, String> {
+    let gateway = igd::search_gateway(SearchOptions::default())
+        .map_err(|e| format!("No UPnP gateway found: {}", e))?;
+    let local_ip = local_ip_towards(*gateway.addr.ip())?;
+    gateway
+        .add_port(
+            PortMappingProtocol::TCP,
+            port,
+            SocketAddrV4::new(local_ip, port),
+            MAPPING_LEASE.as_secs() as u32,
+            MAPPING_DESCRIPTION,
+        )
+        .map_err(|e| format!("Unable to map port {}: {}", port, e))?;
+    let external_ip = gateway
+        .get_external_ip()
+        .map_err(|e| format!("Unable to get external ip: {}", e))?;
+    Ok(SocketAddrV4::new(external_ip, port))
+}
+
+/// Maps the TCP `port` on the NAT-PMP gateway of the default route, returning the external
+/// address.
+fn add_nat_pmp_mapping(port: u16) -> Result<SocketAddrV4, String> {
+    let gateway = default_gateway()?;
+    let response = nat_pmp_request(gateway, &[0, 0])?;
+    let external_ip = parse_external_address_response(&response)?;
+    let lifetime = MAPPING_LEASE.as_secs() as u32;
+    let response = nat_pmp_request(gateway, &map_request(port, lifetime))?;
+    let external_port = parse_map_response(&response)?;
+    Ok(SocketAddrV4::new(external_ip, external_port))
+}
+
+/// Sends a NAT-PMP request to `gateway`, returning the response.
+fn nat_pmp_request(gateway: Ipv4Addr, request: &[u8]) -> Result<Vec<u8>, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("{:?}", e))?;
+    socket
+        .connect((gateway, NAT_PMP_PORT))
+        .map_err(|e| format!("{:?}", e))?;
+    socket
+        .set_read_timeout(Some(NAT_PMP_TIMEOUT))
+        .map_err(|e| format!("{:?}", e))?;
+
+    let mut response = [0; 16];
+    for _ in 0..NAT_PMP_ATTEMPTS {
+        socket.send(request).map_err(|e| format!("{:?}", e))?;
+        match socket.recv(&mut response) {
+            Ok(len) => return Ok(response[..len].to_vec()),
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            }
+            Err(e) => return Err(format!("No NAT-PMP gateway found: {:?}", e)),
+        }
+    }
+    Err("No NAT-PMP gateway found".to_string())
+}
+
+/// Returns a NAT-PMP request mapping the TCP `port` to the same external port for `lifetime`
+/// seconds, or removing the mapping if `lifetime` is zero.
+fn map_request(port: u16, lifetime: u32) -> Vec<u8> {
+    let mut request = vec![0, 2, 0, 0];
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+/// Returns the external address of a NAT-PMP external address response.
+fn parse_external_address_response(response: &[u8]) -> Result<Ipv4Addr, String> {
+    check_nat_pmp_response(response, 128, 12)?;
+    Ok(Ipv4Addr::new(
+        response[8],
+        response[9],
+        response[10],
+        response[11],
+    ))
+}
+
+/// Returns the external port of a NAT-PMP TCP mapping response.
+fn parse_map_response(response: &[u8]) -> Result<u16, String> {
+    check_nat_pmp_response(response, 130, 16)?;
+    Ok(u16::from_be_bytes([response[10], response[11]]))
+}
+
+fn check_nat_pmp_response(response: &[u8], opcode: u8, len: usize) -> Result<(), String> {
+    if response.len() < len || response[0] != 0 || response[1] != opcode {
+        return Err("Invalid NAT-PMP response".to_string());
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        code => Err(format!("NAT-PMP request refused with code {}", code)),
+    }
+}
+
+/// Returns the gateway of the default IPv4 route, where NAT-PMP requests are sent.
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Result<Ipv4Addr, String> {
+    let routes = std::fs::read_to_string("/proc/net/route").map_err(|e| format!("{:?}", e))?;
+    parse_default_gateway(&routes).ok_or_else(|| "No default gateway".to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Result<Ipv4Addr, String> {
+    Err("The default gateway is only found on Linux".to_string())
+}
+
+/// Returns the gateway of the default route in the contents of `/proc/net/route`, in which
+/// addresses are hexadecimal in the byte order of the host.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    routes.lines().skip(1).find_map(|route| {
+        let fields: Vec<&str> = route.split_whitespace().collect();
+        match (fields.get(1), fields.get(2)) {
+            (Some(&"00000000"), Some(gateway)) => u32::from_str_radix(gateway, 16)
+                .ok()
+                .map(|gateway| Ipv4Addr::from(gateway.to_ne_bytes())),
+            _ => None,
+        }
+    })
+}
+
+/// Returns the local address from which `gateway` is reached.
+fn local_ip_towards(gateway: Ipv4Addr) -> Result<Ipv4Addr, String> {
+    // connecting a UDP socket sends nothing, but selects the local interface
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("{:?}", e))?;
+    socket
+        .connect((gateway, 1900))
+        .map_err(|e| format!("{:?}", e))?;
+    match socket.local_addr().map_err(|e| format!("{:?}", e))? {
+        SocketAddr::V4(address) => Ok(*address.ip()),
+        SocketAddr::V6(_) => Err("No IPv4 address towards the gateway".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nat_pmp_map_requests_are_encoded() {
+        assert_eq!(
+            map_request(9000, 3600),
+            vec![0, 2, 0, 0, 0x23, 0x28, 0x23, 0x28, 0, 0, 0x0e, 0x10]
+        );
+    }
+
+    #[test]
+    fn nat_pmp_responses_are_decoded() {
+        let external_address = [0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7];
+        assert_eq!(
+            parse_external_address_response(&external_address),
+            Ok(Ipv4Addr::new(203, 0, 113, 7))
+        );
+
+        let map = [
+            0, 130, 0, 0, 0, 0, 0, 1, 0x23, 0x28, 0x23, 0x29, 0, 0, 0x0e, 0x10,
+        ];
+        assert_eq!(parse_map_response(&map), Ok(9001));
+
+        // refused, the wrong opcode, and truncated
+        let refused = [0, 130, 0, 2, 0, 0, 0, 1, 0x23, 0x28, 0, 0, 0, 0, 0, 0];
+        assert!(parse_map_response(&refused).is_err());
+        assert!(parse_map_response(&external_address).is_err());
+        assert!(parse_external_address_response(&external_address[..8]).is_err());
+    }
+
+    #[test]
+    fn the_default_gateway_is_found_in_the_routing_table() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                      eth0\t0002A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+                      eth0\t00000000\t0102A8C0\t0003\t0\t0\t0\t00000000\n";
+        let expected = if cfg!(target_endian = "little") {
+            Ipv4Addr::new(192, 168, 2, 1)
+        } else {
+            Ipv4Addr::new(1, 2, 168, 192)
+        };
+        assert_eq!(parse_default_gateway(routes), Some(expected));
+        assert_eq!(parse_default_gateway("Iface\tDestination\tGateway\n"), None);
+    }
+}
@@ -1,14 +1,37 @@
 /// Available RPC methods types and ids.
 use ssz_derive::{Decode, Encode};
-use types::{BeaconBlockBody, BeaconBlockHeader, Epoch, Hash256, Slot};
+use types::{Attestation, BeaconBlockBody, BeaconBlockHeader, Bitfield, Epoch, Hash256, Slot};
 
-#[derive(Debug)]
+/// The number of attestation subnets a peer may advertise in its `MetaData`.
+pub const ATTESTATION_SUBNET_COUNT: usize = 64;
+
+/// The codes of `RPCResponse::Error`, describing why a request was not served.
+pub mod error_codes {
+    /// A block range request asked for no blocks.
+    pub const ZERO_COUNT: u64 = 1;
+    /// A block range request asked for too many slots to be skipped between blocks.
+    pub const STEP_TOO_LARGE: u64 = 2;
+    /// A requested block root is not on the chain of the responder.
+    pub const UNKNOWN_ROOT: u64 = 3;
+    /// The requester exceeded its request rate or upload limit, or the responder is too busy.
+    pub const RESOURCE_UNAVAILABLE: u64 = 4;
+    /// The request method is not supported by the responder.
+    pub const UNSUPPORTED: u64 = 5;
+    /// The request could not be served due to an error of the responder.
+    pub const SERVER_ERROR: u64 = 6;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 /// Available Serenity Libp2p RPC methods
 pub enum RPCMethod {
     /// Initialise handshake between connecting peers.
     Hello,
     /// Terminate a connection providing a reason.
     Goodbye,
+    /// Checks that a peer is alive, measuring the round-trip time.
+    Ping,
+    /// Requests the metadata of a peer, such as the attestation subnets it subscribes to.
+    Metadata,
     /// Requests a number of beacon block roots.
     BeaconBlockRoots,
     /// Requests a number of beacon block headers.
@@ -17,6 +40,10 @@ pub enum RPCMethod {
     BeaconBlockBodies,
     /// Requests values for a merkle proof for the current blocks state root.
     BeaconChainState, // Note: experimental, not complete.
+    /// Requests the recent aggregate attestations held by a peer.
+    BeaconAttestations,
+    /// A response to any request which could not be served.
+    Error,
     /// Unknown method received.
     Unknown,
 }
@@ -26,10 +53,14 @@ impl From<u16> for RPCMethod {
         match method_id {
             0 => RPCMethod::Hello,
             1 => RPCMethod::Goodbye,
+            2 => RPCMethod::Ping,
+            3 => RPCMethod::Metadata,
             10 => RPCMethod::BeaconBlockRoots,
             11 => RPCMethod::BeaconBlockHeaders,
             12 => RPCMethod::BeaconBlockBodies,
             13 => RPCMethod::BeaconChainState,
+            14 => RPCMethod::BeaconAttestations,
+            255 => RPCMethod::Error,
 
             _ => RPCMethod::Unknown,
         }
@@ -41,32 +72,126 @@ impl Into<u16> for RPCMethod {
         match self {
             RPCMethod::Hello => 0,
             RPCMethod::Goodbye => 1,
+            RPCMethod::Ping => 2,
+            RPCMethod::Metadata => 3,
             RPCMethod::BeaconBlockRoots => 10,
             RPCMethod::BeaconBlockHeaders => 11,
             RPCMethod::BeaconBlockBodies => 12,
             RPCMethod::BeaconChainState => 13,
+            RPCMethod::BeaconAttestations => 14,
+            RPCMethod::Error => 255,
             _ => 0,
         }
     }
 }
 
+/// The reason given by a peer for terminating a connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GoodbyeReason {
+    /// The client is shutting down.
+    ClientShutdown,
+    /// The peer is on a different network or chain.
+    IrrelevantNetwork,
+    /// The peer has misbehaved.
+    Fault,
+    /// The client has more peers than it wants.
+    TooManyPeers,
+    /// A reason code we do not know of.
+    Unknown(u64),
+}
+
+impl From<u64> for GoodbyeReason {
+    fn from(code: u64) -> Self {
+        match code {
+            1 => GoodbyeReason::ClientShutdown,
+            2 => GoodbyeReason::IrrelevantNetwork,
+            3 => GoodbyeReason::Fault,
+            129 => GoodbyeReason::TooManyPeers,
+            code => GoodbyeReason::Unknown(code),
+        }
+    }
+}
+
+impl Into<u64> for GoodbyeReason {
+    fn into(self) -> u64 {
+        match self {
+            GoodbyeReason::ClientShutdown => 1,
+            GoodbyeReason::IrrelevantNetwork => 2,
+            GoodbyeReason::Fault => 3,
+            GoodbyeReason::TooManyPeers => 129,
+            GoodbyeReason::Unknown(code) => code,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum RPCRequest {
     Hello(HelloMessage),
-    Goodbye(u64),
+    Goodbye(GoodbyeReason),
+    Ping(Ping),
+    Metadata,
     BeaconBlockRoots(BeaconBlockRootsRequest),
     BeaconBlockHeaders(BeaconBlockHeadersRequest),
     BeaconBlockBodies(BeaconBlockBodiesRequest),
     BeaconChainState(BeaconChainStateRequest),
+    BeaconAttestations(BeaconAttestationsRequest),
+}
+
+impl RPCRequest {
+    /// Returns the method of the request.
+    pub fn method(&self) -> RPCMethod {
+        match self {
+            RPCRequest::Hello(_) => RPCMethod::Hello,
+            RPCRequest::Goodbye(_) => RPCMethod::Goodbye,
+            RPCRequest::Ping(_) => RPCMethod::Ping,
+            RPCRequest::Metadata => RPCMethod::Metadata,
+            RPCRequest::BeaconBlockRoots(_) => RPCMethod::BeaconBlockRoots,
+            RPCRequest::BeaconBlockHeaders(_) => RPCMethod::BeaconBlockHeaders,
+            RPCRequest::BeaconBlockBodies(_) => RPCMethod::BeaconBlockBodies,
+            RPCRequest::BeaconChainState(_) => RPCMethod::BeaconChainState,
+            RPCRequest::BeaconAttestations(_) => RPCMethod::BeaconAttestations,
+        }
+    }
+
+    /// Returns the method id of the request.
+    pub fn method_id(&self) -> u16 {
+        self.method().into()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum RPCResponse {
     Hello(HelloMessage),
+    Ping(Ping),
+    Metadata(MetaData),
     BeaconBlockRoots(BeaconBlockRootsResponse),
     BeaconBlockHeaders(BeaconBlockHeadersResponse),
     BeaconBlockBodies(BeaconBlockBodiesResponse),
     BeaconChainState(BeaconChainStateResponse),
+    BeaconAttestations(BeaconAttestationsResponse),
+    /// The request could not be served, for the reason given by one of `error_codes`.
+    Error {
+        code: u64,
+        message: String,
+    },
+}
+
+impl RPCResponse {
+    /// Returns the method of the request this responds to, or `RPCMethod::Error` for an error
+    /// response, which may respond to a request of any method.
+    pub fn method(&self) -> RPCMethod {
+        match self {
+            RPCResponse::Hello(_) => RPCMethod::Hello,
+            RPCResponse::Ping(_) => RPCMethod::Ping,
+            RPCResponse::Metadata(_) => RPCMethod::Metadata,
+            RPCResponse::BeaconBlockRoots(_) => RPCMethod::BeaconBlockRoots,
+            RPCResponse::BeaconBlockHeaders(_) => RPCMethod::BeaconBlockHeaders,
+            RPCResponse::BeaconBlockBodies(_) => RPCMethod::BeaconBlockBodies,
+            RPCResponse::BeaconChainState(_) => RPCMethod::BeaconChainState,
+            RPCResponse::BeaconAttestations(_) => RPCMethod::BeaconAttestations,
+            RPCResponse::Error { .. } => RPCMethod::Error,
+        }
+    }
 }
 
 /* Request/Response data structures for RPC methods */
@@ -76,6 +201,8 @@ pub enum RPCResponse {
 pub struct HelloMessage {
     /// The network ID of the peer.
     pub network_id: u8,
+    /// The fork digest of the peer, identifying its genesis and current fork.
+    pub fork_digest: [u8; 4],
     /// The peers last finalized root.
     pub latest_finalized_root: Hash256,
     /// The peers last finalized epoch.
@@ -86,63 +213,86 @@ pub struct HelloMessage {
     pub best_slot: Slot,
 }
 
+/// A PING request, or the PONG response echoing it.
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq)]
+pub struct Ping {
+    /// An arbitrary value chosen by the requester, which the response must echo.
+    pub data: u64,
+}
+
+/// The METADATA response, describing the sender.
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+pub struct MetaData {
+    /// Incremented whenever the rest of the metadata changes.
+    pub seq_number: u64,
+    /// The attestation subnets the peer subscribes to, one bit per subnet.
+    pub attnets: Bitfield,
+}
+
+impl MetaData {
+    /// Returns `true` if the peer subscribes to the attestation `subnet`.
+    pub fn has_subnet(&self, subnet: usize) -> bool {
+        self.attnets.get(subnet).unwrap_or(false)
+    }
+}
+
 /// Request a number of beacon block roots from a peer.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BeaconBlockRootsRequest {
     /// The starting slot of the requested blocks.
-    start_slot: Slot,
+    pub start_slot: Slot,
     /// The number of blocks from the start slot.
-    count: u64, // this must be less than 32768. //TODO: Enforce this in the lower layers
+    pub count: u64, // this must be less than 32768. //TODO: Enforce this in the lower layers
 }
 
 /// Response containing a number of beacon block roots from a peer.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BeaconBlockRootsResponse {
     /// List of requested blocks and associated slots.
-    roots: Vec<BlockRootSlot>,
+    pub roots: Vec<BlockRootSlot>,
 }
 
 /// Contains a block root and associated slot.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BlockRootSlot {
     /// The block root.
-    block_root: Hash256,
+    pub block_root: Hash256,
     /// The block slot.
-    slot: Slot,
+    pub slot: Slot,
 }
 
 /// Request a number of beacon block headers from a peer.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BeaconBlockHeadersRequest {
     /// The starting header hash of the requested headers.
-    start_root: Hash256,
+    pub start_root: Hash256,
     /// The starting slot of the requested headers.
-    start_slot: Slot,
+    pub start_slot: Slot,
     /// The maximum number of headers than can be returned.
-    max_headers: u64,
+    pub max_headers: u64,
     /// The maximum number of slots to skip between blocks.
-    skip_slots: u64,
+    pub skip_slots: u64,
 }
 
 /// Response containing requested block headers.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BeaconBlockHeadersResponse {
     /// The list of requested beacon block headers.
-    headers: Vec<BeaconBlockHeader>,
+    pub headers: Vec<BeaconBlockHeader>,
 }
 
 /// Request a number of beacon block bodies from a peer.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BeaconBlockBodiesRequest {
     /// The list of beacon block bodies being requested.
-    block_roots: Hash256,
+    pub block_roots: Vec<Hash256>,
 }
 
 /// Response containing the list of requested beacon block bodies.
 #[derive(Encode, Decode, Clone, Debug)]
 pub struct BeaconBlockBodiesResponse {
     /// The list of beacon block bodies being requested.
-    block_bodies: Vec<BeaconBlockBody>,
+    pub block_bodies: Vec<BeaconBlockBody>,
 }
 
 /// Request values for tree hashes which yield a blocks `state_root`.
@@ -159,3 +309,19 @@ pub struct BeaconChainStateResponse {
     /// The values corresponding the to the requested tree hashes.
     values: bool, //TBD - stubbed with encodeable bool
 }
+
+/// Request the aggregate attestations held by a peer for inclusion in blocks.
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct BeaconAttestationsRequest {
+    /// The earliest slot of the requested attestations.
+    pub start_slot: Slot,
+    /// The maximum number of attestations that can be returned.
+    pub max_attestations: u64,
+}
+
+/// Response containing the requested aggregate attestations, newest first.
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct BeaconAttestationsResponse {
+    /// The attestations with the most participants for each `AttestationData` held.
+    pub attestations: Vec<Attestation>,
+}
@@ -1,7 +1,7 @@
 /// RPC Protocol over libp2p.
 ///
 /// This is purpose built for Ethereum 2.0 serenity and the protocol listens on
-/// `/eth/serenity/rpc/1.0.0`
+/// `/eth/serenity/rpc/1.0.0`, or `/eth/serenity/rpc/1.0.0/ssz_snappy` for snappy compressed messages.
 mod methods;
 mod protocol;
 
@@ -11,8 +11,15 @@ use libp2p::core::swarm::{
     ConnectedPoint, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
 };
 use libp2p::{Multiaddr, PeerId};
-pub use methods::{HelloMessage, RPCMethod, RPCRequest, RPCResponse};
-pub use protocol::{RPCEvent, RPCProtocol};
+pub use methods::{
+    error_codes, BeaconAttestationsRequest, BeaconAttestationsResponse, BeaconBlockBodiesRequest,
+    BeaconBlockBodiesResponse, BeaconBlockHeadersRequest, BeaconBlockHeadersResponse,
+    BeaconBlockRootsRequest, BeaconBlockRootsResponse, BlockRootSlot, GoodbyeReason, HelloMessage,
+    MetaData, Ping, RPCMethod, RPCRequest, RPCResponse, ATTESTATION_SUBNET_COUNT,
+};
+pub use protocol::{
+    DecodeError, InvalidRPC, OutboundRPC, RPCEvent, RPCProtocol, DEFAULT_MAX_RPC_SIZE,
+};
 use slog::o;
 use std::marker::PhantomData;
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -22,7 +29,11 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 pub struct Rpc<TSubstream> {
     /// Queue of events to processed.
-    events: Vec<NetworkBehaviourAction<RPCEvent, RPCMessage>>,
+    events: Vec<NetworkBehaviourAction<OutboundRPC, RPCMessage>>,
+    /// Whether messages are snappy compressed when the peer supports it.
+    snappy: bool,
+    /// The maximum size of a received message, both as received and once decompressed.
+    max_size: usize,
     /// Pins the generic substream.
     marker: PhantomData<TSubstream>,
     /// Slog logger for RPC behaviour.
@@ -30,10 +41,12 @@ pub struct Rpc<TSubstream> {
 }
 
 impl<TSubstream> Rpc<TSubstream> {
-    pub fn new(log: &slog::Logger) -> Self {
+    pub fn new(snappy: bool, max_size: usize, log: &slog::Logger) -> Self {
         let log = log.new(o!("Service" => "Libp2p-RPC"));
         Rpc {
             events: Vec::new(),
+            snappy,
+            max_size,
             marker: PhantomData,
             log,
         }
@@ -43,7 +56,10 @@ impl<TSubstream> Rpc<TSubstream> {
     pub fn send_rpc(&mut self, peer_id: PeerId, rpc_event: RPCEvent) {
         self.events.push(NetworkBehaviourAction::SendEvent {
             peer_id,
-            event: rpc_event,
+            event: OutboundRPC {
+                event: rpc_event,
+                snappy: self.snappy,
+            },
         });
     }
 }
@@ -52,11 +68,11 @@ impl<TSubstream> NetworkBehaviour for Rpc<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
 {
-    type ProtocolsHandler = OneShotHandler<TSubstream, RPCProtocol, RPCEvent, OneShotEvent>;
+    type ProtocolsHandler = OneShotHandler<TSubstream, RPCProtocol, OutboundRPC, OneShotEvent>;
     type OutEvent = RPCMessage;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        Default::default()
+        OneShotHandler::new(RPCProtocol::new(self.snappy, self.max_size))
     }
 
     fn addresses_of_peer(&mut self, _peer_id: &PeerId) -> Vec<Multiaddr> {
@@ -72,7 +88,11 @@ where
         }
     }
 
-    fn inject_disconnected(&mut self, _: &PeerId, _: ConnectedPoint) {}
+    fn inject_disconnected(&mut self, peer_id: &PeerId, _: ConnectedPoint) {
+        self.events.push(NetworkBehaviourAction::GenerateEvent(
+            RPCMessage::PeerDisconnected(peer_id.clone()),
+        ));
+    }
 
     fn inject_node_event(
         &mut self,
@@ -81,14 +101,20 @@ where
     ) {
         // ignore successful send events
         let event = match event {
-            OneShotEvent::Rx(event) => event,
+            OneShotEvent::Rx(event, len) => (event, len),
+            OneShotEvent::Invalid(invalid) => {
+                self.events.push(NetworkBehaviourAction::GenerateEvent(
+                    RPCMessage::InvalidRPC(source, invalid),
+                ));
+                return;
+            }
             OneShotEvent::Sent => return,
         };
 
         // send the event to the user
         self.events
             .push(NetworkBehaviourAction::GenerateEvent(RPCMessage::RPC(
-                source, event,
+                source, event.0, event.1,
             )));
     }
 
@@ -110,23 +136,33 @@ where
 
 /// Messages sent to the user from the RPC protocol.
 pub enum RPCMessage {
-    RPC(PeerId, RPCEvent),
+    /// An RPC message received from a peer, with its length on the wire.
+    RPC(PeerId, RPCEvent, usize),
     PeerDialed(PeerId),
+    /// The connection to a peer has closed.
+    PeerDisconnected(PeerId),
+    /// A peer sent an RPC message which could not be decoded.
+    InvalidRPC(PeerId, InvalidRPC),
 }
 
 /// Transmission between the `OneShotHandler` and the `RPCEvent`.
 #[derive(Debug)]
 pub enum OneShotEvent {
-    /// We received an RPC from a remote.
-    Rx(RPCEvent),
+    /// We received an RPC from a remote, of the given length on the wire.
+    Rx(RPCEvent, usize),
+    /// We received an RPC from a remote which could not be decoded.
+    Invalid(InvalidRPC),
     /// We successfully sent an RPC request.
     Sent,
 }
 
-impl From<RPCEvent> for OneShotEvent {
+impl From<Result<(RPCEvent, usize), InvalidRPC>> for OneShotEvent {
     #[inline]
-    fn from(rpc: RPCEvent) -> OneShotEvent {
-        OneShotEvent::Rx(rpc)
+    fn from(rpc: Result<(RPCEvent, usize), InvalidRPC>) -> OneShotEvent {
+        match rpc {
+            Ok((rpc, len)) => OneShotEvent::Rx(rpc, len),
+            Err(invalid) => OneShotEvent::Invalid(invalid),
+        }
     }
 }
 
@@ -1,31 +1,56 @@
 use super::methods::*;
+use crate::encoding::{Encoding, EncodingError};
 use libp2p::core::{upgrade, InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use ssz::{ssz_encode, Decodable, Encodable, SszStream};
 use std::io;
-use std::iter;
+use std::vec;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-/// The maximum bytes that can be sent across the RPC.
-const MAX_READ_SIZE: usize = 4_194_304; // 4M
+/// The default maximum bytes that can be sent across the RPC.
+pub const DEFAULT_MAX_RPC_SIZE: usize = 4_194_304; // 4M
 
-/// Implementation of the `ConnectionUpgrade` for the rpc protocol.
+/// The protocol id of raw SSZ messages.
+const PROTOCOL_ID: &[u8] = b"/eth/serenity/rpc/1.0.0";
+/// The protocol id of snappy compressed SSZ messages.
+const PROTOCOL_ID_SNAPPY: &[u8] = b"/eth/serenity/rpc/1.0.0/ssz_snappy";
 
-#[derive(Debug, Clone)]
-pub struct RPCProtocol;
+/// Returns the protocol ids supported, in order of preference.
+fn protocol_ids(snappy: bool) -> vec::IntoIter<&'static [u8]> {
+    if snappy {
+        vec![PROTOCOL_ID_SNAPPY, PROTOCOL_ID].into_iter()
+    } else {
+        vec![PROTOCOL_ID].into_iter()
+    }
+}
 
-impl UpgradeInfo for RPCProtocol {
-    type Info = &'static [u8];
-    type InfoIter = iter::Once<Self::Info>;
+/// Implementation of the `ConnectionUpgrade` for the rpc protocol.
+#[derive(Debug, Clone)]
+pub struct RPCProtocol {
+    /// Whether snappy compressed messages are accepted.
+    snappy: bool,
+    /// The maximum size of a received message, both as received and once decompressed.
+    max_size: usize,
+}
 
-    #[inline]
-    fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(b"/eth/serenity/rpc/1.0.0")
+impl RPCProtocol {
+    pub fn new(snappy: bool, max_size: usize) -> Self {
+        RPCProtocol { snappy, max_size }
     }
 }
 
 impl Default for RPCProtocol {
     fn default() -> Self {
-        RPCProtocol
+        RPCProtocol::new(false, DEFAULT_MAX_RPC_SIZE)
+    }
+}
+
+impl UpgradeInfo for RPCProtocol {
+    type Info = &'static [u8];
+    type InfoIter = vec::IntoIter<Self::Info>;
+
+    #[inline]
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocol_ids(self.snappy)
     }
 }
 
@@ -44,13 +69,20 @@ pub enum RPCEvent {
     },
 }
 
-impl UpgradeInfo for RPCEvent {
+/// An RPC message to send, compressed with snappy if enabled and supported by the peer.
+#[derive(Debug, Clone)]
+pub struct OutboundRPC {
+    pub event: RPCEvent,
+    pub snappy: bool,
+}
+
+impl UpgradeInfo for OutboundRPC {
     type Info = &'static [u8];
-    type InfoIter = iter::Once<Self::Info>;
+    type InfoIter = vec::IntoIter<Self::Info>;
 
     #[inline]
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(b"/eth/serenity/rpc/1.0.0")
+        protocol_ids(self.snappy)
     }
 }
 
@@ -58,54 +90,117 @@ impl<TSocket> InboundUpgrade<TSocket> for RPCProtocol
 where
     TSocket: AsyncRead + AsyncWrite,
 {
-    type Output = RPCEvent;
+    /// The message received and its length on the wire.
+    type Output = Result<(RPCEvent, usize), InvalidRPC>;
     type Error = DecodeError;
-    type Future =
-        upgrade::ReadOneThen<TSocket, (), fn(Vec<u8>, ()) -> Result<RPCEvent, DecodeError>>;
+    type Future = upgrade::ReadOneThen<
+        TSocket,
+        (Encoding, usize),
+        fn(
+            Vec<u8>,
+            (Encoding, usize),
+        ) -> Result<Result<(RPCEvent, usize), InvalidRPC>, DecodeError>,
+    >;
 
-    fn upgrade_inbound(self, socket: TSocket, _: Self::Info) -> Self::Future {
-        upgrade::read_one_then(socket, MAX_READ_SIZE, (), |packet, ()| Ok(decode(packet)?))
+    fn upgrade_inbound(self, socket: TSocket, protocol: Self::Info) -> Self::Future {
+        // messages which cannot be decoded are reported, rather than failing the upgrade, so the
+        // sending peer can be identified
+        upgrade::read_one_then(
+            socket,
+            self.max_size,
+            (Encoding::from_suffix(protocol), self.max_size),
+            |packet, (encoding, max_size)| {
+                let len = packet.len();
+                Ok(decode(packet, encoding, max_size).map(|event| (event, len)))
+            },
+        )
     }
 }
 
-fn decode(packet: Vec<u8>) -> Result<RPCEvent, DecodeError> {
+/// An inbound RPC message which could not be decoded.
+#[derive(Debug)]
+pub struct InvalidRPC {
+    /// The method id of the message, if the header could be decoded.
+    pub method_id: Option<u16>,
+    /// The reason decoding failed.
+    pub error: DecodeError,
+}
+
+fn decode(packet: Vec<u8>, encoding: Encoding, max_size: usize) -> Result<RPCEvent, InvalidRPC> {
+    let packet = encoding.decode(packet, max_size).map_err(|e| InvalidRPC {
+        method_id: None,
+        error: e.into(),
+    })?;
+
     // decode the header of the rpc
     // request/response
-    let (request, index) = bool::ssz_decode(&packet, 0)?;
-    let (id, index) = u64::ssz_decode(&packet, index)?;
-    let (method_id, index) = u16::ssz_decode(&packet, index)?;
+    let (request, id, method_id, index) = decode_header(&packet).map_err(|error| InvalidRPC {
+        method_id: None,
+        error,
+    })?;
 
+    decode_body(&packet, request, id, method_id, index).map_err(|error| InvalidRPC {
+        method_id: Some(method_id),
+        error,
+    })
+}
+
+fn decode_header(packet: &[u8]) -> Result<(bool, u64, u16, usize), DecodeError> {
+    let (request, index) = bool::ssz_decode(packet, 0)?;
+    let (id, index) = u64::ssz_decode(packet, index)?;
+    let (method_id, index) = u16::ssz_decode(packet, index)?;
+    Ok((request, id, method_id, index))
+}
+
+fn decode_body(
+    packet: &[u8],
+    request: bool,
+    id: u64,
+    method_id: u16,
+    index: usize,
+) -> Result<RPCEvent, DecodeError> {
     if request {
         let body = match RPCMethod::from(method_id) {
             RPCMethod::Hello => {
-                let (hello_body, _index) = HelloMessage::ssz_decode(&packet, index)?;
+                let (hello_body, _index) = HelloMessage::ssz_decode(packet, index)?;
                 RPCRequest::Hello(hello_body)
             }
             RPCMethod::Goodbye => {
-                let (goodbye_code, _index) = u64::ssz_decode(&packet, index)?;
-                RPCRequest::Goodbye(goodbye_code)
+                let (goodbye_code, _index) = u64::ssz_decode(packet, index)?;
+                RPCRequest::Goodbye(GoodbyeReason::from(goodbye_code))
+            }
+            RPCMethod::Ping => {
+                let (ping, _index) = Ping::ssz_decode(packet, index)?;
+                RPCRequest::Ping(ping)
             }
+            RPCMethod::Metadata => RPCRequest::Metadata,
             RPCMethod::BeaconBlockRoots => {
                 let (block_roots_request, _index) =
-                    BeaconBlockRootsRequest::ssz_decode(&packet, index)?;
+                    BeaconBlockRootsRequest::ssz_decode(packet, index)?;
                 RPCRequest::BeaconBlockRoots(block_roots_request)
             }
             RPCMethod::BeaconBlockHeaders => {
                 let (block_headers_request, _index) =
-                    BeaconBlockHeadersRequest::ssz_decode(&packet, index)?;
+                    BeaconBlockHeadersRequest::ssz_decode(packet, index)?;
                 RPCRequest::BeaconBlockHeaders(block_headers_request)
             }
             RPCMethod::BeaconBlockBodies => {
                 let (block_bodies_request, _index) =
-                    BeaconBlockBodiesRequest::ssz_decode(&packet, index)?;
+                    BeaconBlockBodiesRequest::ssz_decode(packet, index)?;
                 RPCRequest::BeaconBlockBodies(block_bodies_request)
             }
             RPCMethod::BeaconChainState => {
                 let (chain_state_request, _index) =
-                    BeaconChainStateRequest::ssz_decode(&packet, index)?;
+                    BeaconChainStateRequest::ssz_decode(packet, index)?;
                 RPCRequest::BeaconChainState(chain_state_request)
             }
-            RPCMethod::Unknown => return Err(DecodeError::UnknownRPCMethod),
+            RPCMethod::BeaconAttestations => {
+                let (attestations_request, _index) =
+                    BeaconAttestationsRequest::ssz_decode(packet, index)?;
+                RPCRequest::BeaconAttestations(attestations_request)
+            }
+            // errors are only sent as responses
+            RPCMethod::Error | RPCMethod::Unknown => return Err(DecodeError::UnknownRPCMethod),
         };
 
         Ok(RPCEvent::Request {
@@ -118,26 +213,46 @@ fn decode(packet: Vec<u8>) -> Result<RPCEvent, DecodeError> {
     else {
         let result = match RPCMethod::from(method_id) {
             RPCMethod::Hello => {
-                let (body, _index) = HelloMessage::ssz_decode(&packet, index)?;
+                let (body, _index) = HelloMessage::ssz_decode(packet, index)?;
                 RPCResponse::Hello(body)
             }
             RPCMethod::Goodbye => unreachable!("Should never receive a goodbye response"),
+            RPCMethod::Ping => {
+                let (body, _index) = Ping::ssz_decode(packet, index)?;
+                RPCResponse::Ping(body)
+            }
+            RPCMethod::Metadata => {
+                let (body, _index) = MetaData::ssz_decode(packet, index)?;
+                RPCResponse::Metadata(body)
+            }
             RPCMethod::BeaconBlockRoots => {
-                let (body, _index) = BeaconBlockRootsResponse::ssz_decode(&packet, index)?;
+                let (body, _index) = BeaconBlockRootsResponse::ssz_decode(packet, index)?;
                 RPCResponse::BeaconBlockRoots(body)
             }
             RPCMethod::BeaconBlockHeaders => {
-                let (body, _index) = BeaconBlockHeadersResponse::ssz_decode(&packet, index)?;
+                let (body, _index) = BeaconBlockHeadersResponse::ssz_decode(packet, index)?;
                 RPCResponse::BeaconBlockHeaders(body)
             }
             RPCMethod::BeaconBlockBodies => {
-                let (body, _index) = BeaconBlockBodiesResponse::ssz_decode(&packet, index)?;
+                let (body, _index) = BeaconBlockBodiesResponse::ssz_decode(packet, index)?;
                 RPCResponse::BeaconBlockBodies(body)
             }
             RPCMethod::BeaconChainState => {
-                let (body, _index) = BeaconChainStateResponse::ssz_decode(&packet, index)?;
+                let (body, _index) = BeaconChainStateResponse::ssz_decode(packet, index)?;
                 RPCResponse::BeaconChainState(body)
             }
+            RPCMethod::BeaconAttestations => {
+                let (body, _index) = BeaconAttestationsResponse::ssz_decode(packet, index)?;
+                RPCResponse::BeaconAttestations(body)
+            }
+            RPCMethod::Error => {
+                let (code, index) = u64::ssz_decode(packet, index)?;
+                let (message, _index) = <Vec<u8>>::ssz_decode(packet, index)?;
+                RPCResponse::Error {
+                    code,
+                    message: String::from_utf8_lossy(&message).into_owned(),
+                }
+            }
             RPCMethod::Unknown => return Err(DecodeError::UnknownRPCMethod),
         };
         Ok(RPCEvent::Response {
@@ -148,7 +263,7 @@ fn decode(packet: Vec<u8>) -> Result<RPCEvent, DecodeError> {
     }
 }
 
-impl<TSocket> OutboundUpgrade<TSocket> for RPCEvent
+impl<TSocket> OutboundUpgrade<TSocket> for OutboundRPC
 where
     TSocket: AsyncWrite,
 {
@@ -157,8 +272,8 @@ where
     type Future = upgrade::WriteOne<TSocket>;
 
     #[inline]
-    fn upgrade_outbound(self, socket: TSocket, _: Self::Info) -> Self::Future {
-        let bytes = ssz_encode(&self);
+    fn upgrade_outbound(self, socket: TSocket, protocol: Self::Info) -> Self::Future {
+        let bytes = Encoding::from_suffix(protocol).encode(ssz_encode(&self.event));
         upgrade::write_one(socket, bytes)
     }
 }
@@ -178,9 +293,15 @@ impl Encodable for RPCEvent {
                     RPCRequest::Hello(body) => {
                         s.append(body);
                     }
-                    RPCRequest::Goodbye(body) => {
+                    RPCRequest::Goodbye(reason) => {
+                        let code: u64 = (*reason).into();
+                        s.append(&code);
+                    }
+                    RPCRequest::Ping(body) => {
                         s.append(body);
                     }
+                    // the request has no body
+                    RPCRequest::Metadata => {}
                     RPCRequest::BeaconBlockRoots(body) => {
                         s.append(body);
                     }
@@ -193,6 +314,9 @@ impl Encodable for RPCEvent {
                     RPCRequest::BeaconChainState(body) => {
                         s.append(body);
                     }
+                    RPCRequest::BeaconAttestations(body) => {
+                        s.append(body);
+                    }
                 }
             }
             RPCEvent::Response {
@@ -207,6 +331,12 @@ impl Encodable for RPCEvent {
                     RPCResponse::Hello(response) => {
                         s.append(response);
                     }
+                    RPCResponse::Ping(response) => {
+                        s.append(response);
+                    }
+                    RPCResponse::Metadata(response) => {
+                        s.append(response);
+                    }
                     RPCResponse::BeaconBlockRoots(response) => {
                         s.append(response);
                     }
@@ -219,6 +349,13 @@ impl Encodable for RPCEvent {
                     RPCResponse::BeaconChainState(response) => {
                         s.append(response);
                     }
+                    RPCResponse::BeaconAttestations(response) => {
+                        s.append(response);
+                    }
+                    RPCResponse::Error { code, message } => {
+                        s.append(code);
+                        s.append(&message.as_bytes().to_vec());
+                    }
                 }
             }
         }
@@ -229,9 +366,28 @@ impl Encodable for RPCEvent {
 pub enum DecodeError {
     ReadError(upgrade::ReadOneError),
     SSZDecodeError(ssz::DecodeError),
+    /// A snappy compressed message could not be decompressed.
+    DecompressionError(String),
+    /// A snappy compressed message would decompress to `len` bytes, more than `max_len`.
+    DecompressedTooLong {
+        len: usize,
+        max_len: usize,
+    },
     UnknownRPCMethod,
 }
 
+impl From<EncodingError> for DecodeError {
+    #[inline]
+    fn from(err: EncodingError) -> Self {
+        match err {
+            EncodingError::TooLong { len, max_len } => {
+                DecodeError::DecompressedTooLong { len, max_len }
+            }
+            EncodingError::Invalid(e) => DecodeError::DecompressionError(e.to_string()),
+        }
+    }
+}
+
 impl From<upgrade::ReadOneError> for DecodeError {
     #[inline]
     fn from(err: upgrade::ReadOneError) -> Self {
@@ -245,3 +401,209 @@ impl From<ssz::DecodeError> for DecodeError {
         DecodeError::SSZDecodeError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{Epoch, Hash256, Slot};
+
+    /// Decodes the hex of a fixture, ignoring whitespace and `#` comments.
+    fn fixture(hex: &str) -> Vec<u8> {
+        let digits: Vec<u8> = hex
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").bytes())
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect();
+        assert_eq!(digits.len() % 2, 0, "fixture has an odd number of digits");
+        digits
+            .chunks(2)
+            .map(|pair| {
+                u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16)
+                    .expect("fixture is not hex")
+            })
+            .collect()
+    }
+
+    /// Decodes a fixture as a raw SSZ frame, checking it re-encodes to the same bytes.
+    fn decode_fixture(hex: &str) -> RPCEvent {
+        let packet = fixture(hex);
+        let event = decode(packet.clone(), Encoding::SSZ, DEFAULT_MAX_RPC_SIZE)
+            .expect("fixture should decode");
+        assert_eq!(ssz_encode(&event), packet, "fixture does not re-encode");
+        event
+    }
+
+    #[test]
+    fn decodes_hello_request() {
+        match decode_fixture(include_str!("../../fixtures/rpc/hello_request.hex")) {
+            RPCEvent::Request {
+                id: 1,
+                method_id: 0,
+                body: RPCRequest::Hello(hello),
+            } => {
+                assert_eq!(hello.network_id, 2);
+                assert_eq!(hello.fork_digest, [0xde, 0xad, 0xbe, 0xef]);
+                assert_eq!(hello.latest_finalized_root, Hash256::repeat_byte(0x11));
+                assert_eq!(hello.latest_finalized_epoch, Epoch::new(3));
+                assert_eq!(hello.best_root, Hash256::repeat_byte(0x22));
+                assert_eq!(hello.best_slot, Slot::new(100));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_hello_response() {
+        match decode_fixture(include_str!("../../fixtures/rpc/hello_response.hex")) {
+            RPCEvent::Response {
+                id: 1,
+                method_id: 0,
+                result: RPCResponse::Hello(hello),
+            } => {
+                assert_eq!(hello.network_id, 2);
+                assert_eq!(hello.fork_digest, [0xde, 0xad, 0xbe, 0xef]);
+                assert!(hello.latest_finalized_root.is_zero());
+                assert_eq!(hello.latest_finalized_epoch, Epoch::new(0));
+                assert_eq!(hello.best_root, Hash256::repeat_byte(0x33));
+                assert_eq!(hello.best_slot, Slot::new(258));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_goodbye_request() {
+        match decode_fixture(include_str!("../../fixtures/rpc/goodbye_request.hex")) {
+            RPCEvent::Request {
+                id: 2,
+                method_id: 1,
+                body: RPCRequest::Goodbye(reason),
+            } => assert_eq!(reason, GoodbyeReason::Fault),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_block_roots_request() {
+        match decode_fixture(include_str!("../../fixtures/rpc/block_roots_request.hex")) {
+            RPCEvent::Request {
+                id: 7,
+                method_id: 10,
+                body: RPCRequest::BeaconBlockRoots(request),
+            } => {
+                assert_eq!(request.start_slot, Slot::new(64));
+                assert_eq!(request.count, 3);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_block_headers_request() {
+        match decode_fixture(include_str!("../../fixtures/rpc/block_headers_request.hex")) {
+            RPCEvent::Request {
+                id: 8,
+                method_id: 11,
+                body: RPCRequest::BeaconBlockHeaders(request),
+            } => {
+                assert_eq!(request.start_root, Hash256::repeat_byte(0xaa));
+                assert_eq!(request.start_slot, Slot::new(64));
+                assert_eq!(request.max_headers, 2);
+                assert_eq!(request.skip_slots, 1);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_block_roots_response() {
+        match decode_fixture(include_str!("../../fixtures/rpc/block_roots_response.hex")) {
+            RPCEvent::Response {
+                id: 7,
+                method_id: 10,
+                result: RPCResponse::BeaconBlockRoots(response),
+            } => {
+                let roots: Vec<(Hash256, Slot)> = response
+                    .roots
+                    .iter()
+                    .map(|root| (root.block_root, root.slot))
+                    .collect();
+                assert_eq!(
+                    roots,
+                    vec![
+                        (Hash256::repeat_byte(0xaa), Slot::new(64)),
+                        (Hash256::repeat_byte(0xbb), Slot::new(66)),
+                    ]
+                );
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn decodes_error_response() {
+        match decode_fixture(include_str!("../../fixtures/rpc/error_response.hex")) {
+            RPCEvent::Response {
+                id: 9,
+                method_id: 255,
+                result: RPCResponse::Error { code, message },
+            } => {
+                assert_eq!(code, error_codes::STEP_TOO_LARGE);
+                assert_eq!(message, "step too large");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn every_fixture_round_trips() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/rpc");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(false, |ext| ext == "hex") {
+                decode_fixture(&std::fs::read_to_string(&path).unwrap());
+                count += 1;
+            }
+        }
+        assert!(count > 0, "no fixtures found");
+    }
+
+    #[test]
+    fn rejects_truncated_frame() {
+        let packet = fixture(include_str!(
+            "../../fixtures/rpc/invalid/truncated_block_roots_response.hex"
+        ));
+        match decode(packet, Encoding::SSZ, DEFAULT_MAX_RPC_SIZE) {
+            Err(InvalidRPC {
+                method_id: Some(10),
+                error: DecodeError::SSZDecodeError(ssz::DecodeError::TooShort),
+            }) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn decodes_snappy_frames() {
+        let packet = fixture(include_str!("../../fixtures/rpc/hello_request.hex"));
+        let compressed = Encoding::SSZSnappy.encode(packet.clone());
+        let event = decode(compressed, Encoding::SSZSnappy, DEFAULT_MAX_RPC_SIZE)
+            .expect("frame should decode");
+        assert_eq!(ssz_encode(&event), packet);
+    }
+
+    #[test]
+    fn rejects_compression_bomb() {
+        let compressed = Encoding::SSZSnappy.encode(vec![0; DEFAULT_MAX_RPC_SIZE + 1]);
+        match decode(compressed, Encoding::SSZSnappy, DEFAULT_MAX_RPC_SIZE) {
+            Err(InvalidRPC {
+                method_id: None,
+                error: DecodeError::DecompressedTooLong { len, max_len },
+            }) => {
+                assert_eq!(len, DEFAULT_MAX_RPC_SIZE + 1);
+                assert_eq!(max_len, DEFAULT_MAX_RPC_SIZE);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
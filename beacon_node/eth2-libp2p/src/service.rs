@@ -1,8 +1,10 @@
-use crate::behaviour::{Behaviour, BehaviourEvent};
+use crate::behaviour::{Behaviour, BehaviourEvent, PubsubMessage};
 use crate::error;
+use crate::identity;
 use crate::multiaddr::Protocol;
-use crate::rpc::RPCEvent;
-use crate::NetworkConfig;
+use crate::nat;
+use crate::rpc::{DecodeError, GoodbyeReason, InvalidRPC, RPCEvent, RPCMethod, RPCRequest};
+use crate::{Multiaddr, NetworkConfig};
 use futures::prelude::*;
 use futures::Stream;
 use libp2p::core::{
@@ -11,13 +13,20 @@ use libp2p::core::{
     transport::boxed::Boxed,
     upgrade::{InboundUpgradeExt, OutboundUpgradeExt},
 };
+use libp2p::gossipsub::MessageId;
 use libp2p::identify::protocol::IdentifyInfo;
 use libp2p::{core, secio, PeerId, Swarm, Transport};
 use slog::{debug, info, trace, warn};
+use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
-use std::time::Duration;
+use std::net::{SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use types::multiaddr::ToMultiaddr;
 use types::TopicBuilder;
 
+/// The time a peer sent a Goodbye is given to disconnect, before the connection is closed.
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// The configuration and state of the libp2p components for the beacon node.
 pub struct Service {
     /// The libp2p Swarm handler.
@@ -25,6 +34,17 @@ pub struct Service {
     pub swarm: Swarm<Boxed<(PeerId, StreamMuxerBox), Error>, Behaviour<Substream<StreamMuxerBox>>>,
     /// This node's PeerId.
     local_peer_id: PeerId,
+    /// Banned peers and the time their ban expires.
+    banned_peers: HashMap<PeerId, Instant>,
+    /// Peers sent a Goodbye and the time their connection is closed if they remain connected.
+    disconnecting_peers: HashMap<PeerId, Instant>,
+    /// The gossipsub topics subscribed to.
+    subscribed_topics: Vec<String>,
+    /// The mapping of the listen port on a UPnP or NAT-PMP gateway, if enabled. It is removed
+    /// when the service is dropped.
+    port_mapping: Option<nat::PortMapping>,
+    /// The external address of the mapped port currently advertised.
+    mapped_address: Option<SocketAddrV4>,
     /// The libp2p logger handle.
     pub log: slog::Logger,
 }
@@ -35,8 +55,11 @@ impl Service {
 
         // TODO: Currently using secp256k1 key pairs. Wire protocol specifies RSA. Waiting for this
         // PR to be merged to generate RSA keys: https://github.com/briansmith/ring/pull/733
-        // TODO: Save and recover node key from disk
-        let local_private_key = secio::SecioKeyPair::secp256k1_generated().unwrap();
+        let local_private_key = identity::load_keypair(
+            config.key_path.as_ref().map(|p| p.as_path()),
+            config.new_identity,
+            &log,
+        )?;
 
         let local_public_key = local_private_key.to_public_key();
         let local_peer_id = local_private_key.to_peer_id();
@@ -62,9 +85,25 @@ impl Service {
                 Err(err) => warn!(log, "Cannot listen on: {} : {:?}", address, err),
             };
         }
-        // connect to boot nodes - these are currently stored as multiaddrs
-        // Once we have discovery, can set to peerId
-        for bootnode in config.boot_nodes {
+        // advertise the addresses this node is reachable at from outside a NAT
+        if let Some(ip) = config.discovery_address {
+            match SocketAddr::new(ip, config.listen_port).to_multiaddr() {
+                Ok(address) => {
+                    info!(log, "Advertising address: {}", address);
+                    Swarm::add_external_address(&mut swarm, address);
+                }
+                Err(e) => warn!(log, "Invalid discovery address: {} : {:?}", ip, e),
+            }
+        }
+        let port_mapping = if config.upnp_enabled {
+            Some(nat::map_port(config.listen_port, log.clone()))
+        } else {
+            None
+        };
+
+        // connect to trusted peers and boot nodes - once identified, their addresses seed peer
+        // discovery
+        for bootnode in config.trusted_peers.into_iter().chain(config.boot_nodes) {
             match Swarm::dial_addr(&mut swarm, bootnode.clone()) {
                 Ok(()) => debug!(log, "Dialing bootnode: {}", bootnode),
                 Err(err) => debug!(
@@ -89,37 +128,191 @@ impl Service {
 
         Ok(Service {
             local_peer_id,
+            banned_peers: HashMap::new(),
+            disconnecting_peers: HashMap::new(),
+            subscribed_topics,
+            port_mapping,
+            mapped_address: None,
             swarm,
             log,
         })
     }
 }
 
+impl Service {
+    /// Returns the gossipsub topics subscribed to.
+    pub fn subscribed_topics(&self) -> &[String] {
+        &self.subscribed_topics
+    }
+
+    /// Sends a Goodbye to a peer, requesting that it disconnects. The connection is closed if the
+    /// peer has not disconnected after `GOODBYE_TIMEOUT`.
+    pub fn disconnect_peer(&mut self, peer_id: PeerId, reason: GoodbyeReason) {
+        self.disconnecting_peers
+            .entry(peer_id.clone())
+            .or_insert_with(|| Instant::now() + GOODBYE_TIMEOUT);
+        self.swarm.send_rpc(
+            peer_id,
+            RPCEvent::Request {
+                id: 0,
+                method_id: RPCMethod::Goodbye.into(),
+                body: RPCRequest::Goodbye(reason),
+            },
+        );
+    }
+
+    /// Forwards a received gossipsub message, which has been validated, to our other peers.
+    pub fn propagate(&mut self, id: &MessageId, source: &PeerId) {
+        self.swarm.propagate(id, source);
+    }
+
+    /// Starts a search for new peers, reported as `Libp2pEvent::PeerDiscovered`.
+    pub fn find_peers(&mut self) {
+        self.swarm.find_peers();
+    }
+
+    /// Dials an address, e.g. one a previously connected peer listens on.
+    pub fn dial(&mut self, address: Multiaddr) {
+        if let Err(err) = Swarm::dial_addr(&mut self.swarm, address.clone()) {
+            debug!(self.log, "Could not dial: {} error: {:?}", address, err);
+        }
+    }
+
+    /// Disconnects from a peer and refuses connections from it until `duration` has elapsed.
+    pub fn ban_peer(&mut self, peer_id: PeerId, duration: Duration) {
+        Swarm::ban_peer_id(&mut self.swarm, peer_id.clone());
+        self.banned_peers.insert(peer_id, Instant::now() + duration);
+    }
+
+    /// Lifts the bans which have expired.
+    fn unban_expired_peers(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<PeerId> = self
+            .banned_peers
+            .iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        for peer_id in expired {
+            debug!(self.log, "Peer ban expired: {:?}", peer_id);
+            self.banned_peers.remove(&peer_id);
+            Swarm::unban_peer_id(&mut self.swarm, peer_id);
+        }
+    }
+
+    /// Closes the connections of peers which have not disconnected since being sent a Goodbye.
+    fn close_disconnecting_peers(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<PeerId> = self
+            .disconnecting_peers
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        for peer_id in expired {
+            debug!(self.log, "Closing connection after goodbye: {:?}", peer_id);
+            self.disconnecting_peers.remove(&peer_id);
+            // banning closes the connection. The peer may reconnect unless it is actually banned.
+            Swarm::ban_peer_id(&mut self.swarm, peer_id.clone());
+            if !self.banned_peers.contains_key(&peer_id) {
+                Swarm::unban_peer_id(&mut self.swarm, peer_id);
+            }
+        }
+    }
+
+    /// Advertises the external addresses of any new UPnP or NAT-PMP port mappings.
+    ///
+    /// Mappings are made rarely, so they are collected whenever the service is next polled rather
+    /// than waking it.
+    fn advertise_mapped_addresses(&mut self) {
+        let addresses: Vec<SocketAddrV4> = match &self.port_mapping {
+            Some(port_mapping) => port_mapping.addresses.try_iter().collect(),
+            None => return,
+        };
+        for address in addresses {
+            match SocketAddr::V4(address).to_multiaddr() {
+                Ok(multiaddr) => {
+                    // the swarm cannot withdraw an external address once added
+                    if let Some(previous) = self.mapped_address.replace(address) {
+                        warn!(
+                            self.log,
+                            "Mapped address changed, the previous address remains advertised until restart";
+                            "previous" => format!("{}", previous),
+                        );
+                    }
+                    info!(self.log, "Advertising address: {}", multiaddr);
+                    Swarm::add_external_address(&mut self.swarm, multiaddr);
+                }
+                Err(e) => warn!(self.log, "Invalid mapped address: {} : {:?}", address, e),
+            }
+        }
+    }
+}
+
 impl Stream for Service {
     type Item = Libp2pEvent;
     type Error = crate::error::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.unban_expired_peers();
+        self.close_disconnecting_peers();
+        self.advertise_mapped_addresses();
+
         loop {
             // TODO: Currently only gossipsub events passed here.
             // Build a type for more generic events
             match self.swarm.poll() {
                 //Behaviour events
                 Ok(Async::Ready(Some(event))) => match event {
-                    // TODO: Stub here for debugging
-                    BehaviourEvent::Message(m) => {
-                        debug!(self.log, "Message received: {}", m);
-                        return Ok(Async::Ready(Some(Libp2pEvent::Message(m))));
+                    BehaviourEvent::GossipMessage {
+                        id,
+                        source,
+                        topics,
+                        message,
+                    } => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::PubsubMessage {
+                            id,
+                            source,
+                            topics,
+                            message,
+                        })));
+                    }
+                    BehaviourEvent::InvalidGossip {
+                        source,
+                        topics,
+                        error,
+                    } => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::InvalidGossip {
+                            source,
+                            topics,
+                            error,
+                        })));
+                    }
+                    BehaviourEvent::RPC(peer_id, event, len) => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::RPC(peer_id, event, len))));
                     }
-                    BehaviourEvent::RPC(peer_id, event) => {
-                        return Ok(Async::Ready(Some(Libp2pEvent::RPC(peer_id, event))));
+                    BehaviourEvent::InvalidRPC(peer_id, invalid) => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::InvalidRPC(
+                            peer_id, invalid,
+                        ))));
                     }
                     BehaviourEvent::PeerDialed(peer_id) => {
                         return Ok(Async::Ready(Some(Libp2pEvent::PeerDialed(peer_id))));
                     }
+                    BehaviourEvent::PeerDisconnected(peer_id) => {
+                        self.disconnecting_peers.remove(&peer_id);
+                        return Ok(Async::Ready(Some(Libp2pEvent::PeerDisconnected(peer_id))));
+                    }
                     BehaviourEvent::Identified(peer_id, info) => {
                         return Ok(Async::Ready(Some(Libp2pEvent::Identified(peer_id, info))));
                     }
+                    BehaviourEvent::PeerDiscovered(peer_id, addresses) => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::PeerDiscovered(
+                            peer_id, addresses,
+                        ))));
+                    }
                 },
                 Ok(Async::Ready(None)) => unreachable!("Swarm stream shouldn't end"),
                 Ok(Async::NotReady) => break,
@@ -167,12 +360,31 @@ fn build_transport(
 
 /// Events that can be obtained from polling the Libp2p Service.
 pub enum Libp2pEvent {
-    /// An RPC response request has been received on the swarm.
-    RPC(PeerId, RPCEvent),
+    /// An RPC response request has been received on the swarm, of the given length on the
+    /// wire.
+    RPC(PeerId, RPCEvent, usize),
+    /// A peer sent an RPC message which could not be decoded.
+    InvalidRPC(PeerId, InvalidRPC),
     /// Initiated the connection to a new peer.
     PeerDialed(PeerId),
+    /// The connection to a peer has closed.
+    PeerDisconnected(PeerId),
     /// Received information about a peer on the network.
     Identified(PeerId, IdentifyInfo),
-    // TODO: Pub-sub testing only.
-    Message(String),
+    /// Discovered a peer and the addresses it listens on.
+    PeerDiscovered(PeerId, Vec<Multiaddr>),
+    /// Received a gossipsub message from `source`, which is forwarded to our other peers only
+    /// once passed to `Service::propagate`.
+    PubsubMessage {
+        id: MessageId,
+        source: PeerId,
+        topics: Vec<String>,
+        message: PubsubMessage,
+    },
+    /// Received a gossipsub message which could not be decoded.
+    InvalidGossip {
+        source: PeerId,
+        topics: Vec<String>,
+        error: DecodeError,
+    },
 }
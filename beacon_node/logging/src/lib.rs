@@ -0,0 +1,149 @@
+/// Logging utilities shared by the beacon node services.
+use slog::{Drain, Key, Level, Never, OwnedKVList, Record, KV};
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A log record held by a `LogBuffer`.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// The time the record was logged, in seconds since the unix epoch.
+    pub timestamp: u64,
+    pub level: Level,
+    /// The message of the record, followed by its key-value pairs.
+    pub message: String,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.timestamp,
+            self.level.as_short_str(),
+            self.message
+        )
+    }
+}
+
+/// A `slog` drain which keeps the most recent records of at least a minimum level in memory, so
+/// they can be retrieved without access to the log output.
+///
+/// Clones share the same records, so a clone may be given to the logger and another kept to read
+/// the records.
+#[derive(Clone)]
+pub struct LogBuffer {
+    entries: Arc<Mutex<VecDeque<LogEntry>>>,
+    capacity: usize,
+    min_level: Level,
+}
+
+impl LogBuffer {
+    /// Returns a buffer holding up to `capacity` records of `min_level` or more severe, evicting
+    /// the oldest when full.
+    pub fn new(capacity: usize, min_level: Level) -> Self {
+        LogBuffer {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            min_level,
+        }
+    }
+
+    /// Returns the records held, oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().cloned().collect()
+    }
+
+    /// Returns the records held, oldest first, or `None` if they are being written. Never blocks,
+    /// so may be used where the caller could hold the lock, e.g. in a panic hook.
+    pub fn try_entries(&self) -> Option<Vec<LogEntry>> {
+        let entries = self.entries.try_lock().ok()?;
+        Some(entries.iter().cloned().collect())
+    }
+
+    fn push(&self, entry: LogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+impl Drain for LogBuffer {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+        if !record.level().is_at_least(self.min_level) {
+            return Ok(());
+        }
+
+        let mut message = format!("{}", record.msg());
+        let mut serializer = LineSerializer(&mut message);
+        // formatting into a string cannot fail
+        let _ = record.kv().serialize(record, &mut serializer);
+        let _ = values.serialize(record, &mut serializer);
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        self.push(LogEntry {
+            timestamp,
+            level: record.level(),
+            message,
+        });
+        Ok(())
+    }
+}
+
+/// Appends the key-value pairs of a record to a line, as `, key: value`.
+struct LineSerializer<'a>(&'a mut String);
+
+impl<'a> slog::Serializer for LineSerializer<'a> {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        write!(self.0, ", {}: {}", key, val).map_err(slog::Error::Fmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{error, info, o, warn, Logger};
+
+    #[test]
+    fn keeps_recent_records_of_min_level() {
+        let buffer = LogBuffer::new(2, Level::Warning);
+        let log = Logger::root(buffer.clone().fuse(), o!("service" => "test"));
+
+        warn!(log, "first"; "count" => 1);
+        info!(log, "ignored");
+        error!(log, "second");
+        warn!(log, "third");
+
+        let entries = buffer.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level, Level::Error);
+        assert_eq!(entries[0].message, "second, service: test");
+        assert_eq!(entries[1].message, "third, service: test");
+    }
+
+    #[test]
+    fn formats_key_values() {
+        let buffer = LogBuffer::new(4, Level::Info);
+        let log = Logger::root(buffer.clone().fuse(), o!());
+
+        info!(log, "Peer connected"; "peer" => "a", "count" => 3);
+
+        let message = &buffer.entries()[0].message;
+        assert!(message.starts_with("Peer connected"));
+        assert!(message.contains("peer: a"));
+        assert!(message.contains("count: 3"));
+    }
+}
@@ -0,0 +1,222 @@
+//! An append-only log of peer connection events, kept apart from the general logs so that
+//! eclipse attempts or storms of bans can be analysed after the fact.
+//!
+//! Each event is written as a single line of JSON. Events are written by a thread of their own,
+//! so that a storm of bans does not hold up the network service.
+use crate::error;
+use crate::peer_manager::PeerManager;
+use beacon_chain::parking_lot::RwLock;
+use eth2_libp2p::rpc::GoodbyeReason;
+use eth2_libp2p::PeerId;
+use serde_derive::Serialize;
+use slog::warn;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of events which may await writing. Events beyond it are dropped.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// An event in the connection to a peer.
+#[derive(Debug, Clone, Copy)]
+pub enum PeerEvent {
+    /// We dialed the peer.
+    Dialed,
+    /// The peer identified itself on a new connection, inbound or outbound.
+    Identified,
+    /// The connection to the peer closed.
+    Disconnected,
+    /// We are disconnecting from the peer, for the reason given.
+    Goodbye(GoodbyeReason),
+    /// We banned the peer, refusing its connections for the duration, for the reason given.
+    Banned(Duration, &'static str),
+}
+
+/// A single line of the log.
+#[derive(Serialize)]
+struct Record {
+    /// Milliseconds since the unix epoch.
+    timestamp_ms: u64,
+    event: &'static str,
+    peer_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ban_secs: Option<u64>,
+    /// The score of the peer when the event occurred, if the peer manager knows of it.
+    score: Option<i64>,
+}
+
+/// Appends peer events to a file, if one is configured. Otherwise events are discarded.
+///
+/// Dropping the log waits for the events already recorded to be written.
+pub struct AuditLog {
+    /// Sends events to the thread writing the file, if one is configured.
+    writer: Option<(SyncSender<Record>, JoinHandle<()>)>,
+    peer_manager: Arc<RwLock<PeerManager>>,
+    log: slog::Logger,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` for appending, creating it if it does not exist.
+    pub fn open(
+        path: Option<&Path>,
+        peer_manager: Arc<RwLock<PeerManager>>,
+        log: slog::Logger,
+    ) -> error::Result<Self> {
+        let writer = match path {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("Unable to open peer audit log {:?}: {:?}", path, e))?;
+                let (send, receive) = sync_channel(QUEUE_CAPACITY);
+                let writer_log = log.clone();
+                let handle = thread::Builder::new()
+                    .name("peer_audit_log".to_string())
+                    .spawn(move || write_records(file, receive, writer_log))
+                    .map_err(|e| format!("Unable to start the peer audit log: {:?}", e))?;
+                Some((send, handle))
+            }
+            None => None,
+        };
+
+        Ok(AuditLog {
+            writer,
+            peer_manager,
+            log,
+        })
+    }
+
+    /// Queues an event to be appended, along with the current score of the peer. The event is
+    /// dropped if too many are already queued.
+    pub fn record(&mut self, peer_id: &PeerId, event: PeerEvent) {
+        let send = match self.writer.as_ref() {
+            Some((send, _)) => send,
+            None => return,
+        };
+
+        let (name, reason, ban_secs) = match event {
+            PeerEvent::Dialed => ("dialed", None, None),
+            PeerEvent::Identified => ("identified", None, None),
+            PeerEvent::Disconnected => ("disconnected", None, None),
+            PeerEvent::Goodbye(reason) => ("goodbye", Some(format!("{:?}", reason)), None),
+            PeerEvent::Banned(duration, reason) => {
+                ("banned", Some(reason.to_string()), Some(duration.as_secs()))
+            }
+        };
+        let record = Record {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis() as u64)
+                .unwrap_or(0),
+            event: name,
+            peer_id: peer_id.to_base58(),
+            reason,
+            ban_secs,
+            score: self
+                .peer_manager
+                .read()
+                .peer(peer_id)
+                .map(|info| info.score),
+        };
+
+        match send.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!(self.log, "Peer audit log is behind, event dropped"; "event" => name)
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!(self.log, "Peer audit log has stopped, event dropped"; "event" => name)
+            }
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        if let Some((send, handle)) = self.writer.take() {
+            // the writer stops once the events queued are written and the sender is dropped
+            drop(send);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Writes each record received to `file` until the sender is dropped, flushing whenever no more
+/// are queued.
+fn write_records(file: File, receive: Receiver<Record>, log: slog::Logger) {
+    let mut file = BufWriter::new(file);
+    while let Ok(record) = receive.recv() {
+        for record in Some(record).into_iter().chain(receive.try_iter()) {
+            let result = serde_json::to_string(&record)
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|mut line| {
+                    line.push('\n');
+                    file.write_all(line.as_bytes())
+                        .map_err(|e| format!("{:?}", e))
+                });
+            if let Err(e) = result {
+                warn!(log, "Unable to write to the peer audit log"; "error" => e);
+            }
+        }
+        if let Err(e) = file.flush() {
+            warn!(log, "Unable to write to the peer audit log"; "error" => format!("{:?}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+    use std::fs;
+
+    #[test]
+    fn events_are_written_as_lines_of_json() {
+        let path = std::env::temp_dir().join(format!("audit_log_test_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let log = slog::Logger::root(slog::Discard, o!());
+        let peer_manager = Arc::new(RwLock::new(PeerManager::new(
+            Duration::from_secs(60),
+            log.clone(),
+        )));
+        let peer_id = PeerId::random();
+        peer_manager.write().penalize(&peer_id, 10, "test");
+
+        let mut audit_log = AuditLog::open(Some(&path), peer_manager, log).unwrap();
+        audit_log.record(&peer_id, PeerEvent::Dialed);
+        audit_log.record(&peer_id, PeerEvent::Goodbye(GoodbyeReason::Fault));
+        audit_log.record(
+            &peer_id,
+            PeerEvent::Banned(Duration::from_secs(60), "invalid block"),
+        );
+        // waits for the events to be written
+        drop(audit_log);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            assert_eq!(line["peer_id"], peer_id.to_base58());
+            assert_eq!(line["score"], -10);
+        }
+        assert_eq!(lines[0]["event"], "dialed");
+        assert!(lines[0].get("reason").is_none());
+        assert_eq!(lines[1]["event"], "goodbye");
+        assert_eq!(lines[1]["reason"], "Fault");
+        assert_eq!(lines[2]["event"], "banned");
+        assert_eq!(lines[2]["reason"], "invalid block");
+        assert_eq!(lines[2]["ban_secs"], 60);
+    }
+}
@@ -0,0 +1,171 @@
+use eth2_libp2p::rpc::RPCMethod;
+use eth2_libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Returns `true` if serving the RPC method may require significant upload bandwidth, in which
+/// case it counts towards the per-peer bandwidth caps.
+pub fn is_expensive_method(method_id: u16) -> bool {
+    match RPCMethod::from(method_id) {
+        RPCMethod::BeaconBlockRoots
+        | RPCMethod::BeaconBlockHeaders
+        | RPCMethod::BeaconBlockBodies => true,
+        _ => false,
+    }
+}
+
+/// A count of bytes served within a fixed-length window of time.
+struct Window {
+    start: Instant,
+    length: Duration,
+    bytes: u64,
+}
+
+impl Window {
+    fn new(length: Duration) -> Self {
+        Window {
+            start: Instant::now(),
+            length,
+            bytes: 0,
+        }
+    }
+
+    /// Starts a new window if the current one has elapsed.
+    fn roll(&mut self, now: Instant) {
+        if now.duration_since(self.start) >= self.length {
+            self.start = now;
+            self.bytes = 0;
+        }
+    }
+}
+
+/// The bytes served to a single peer.
+struct PeerBandwidth {
+    /// Total bytes served for each RPC method id, since the peer was first seen.
+    by_method: HashMap<u16, u64>,
+    /// Bytes served for expensive methods in the current hour.
+    hour: Window,
+    /// Bytes served for expensive methods in the current day.
+    day: Window,
+    /// The time the peer was last served.
+    last_served: Instant,
+}
+
+impl Default for PeerBandwidth {
+    fn default() -> Self {
+        PeerBandwidth {
+            by_method: HashMap::new(),
+            hour: Window::new(HOUR),
+            day: Window::new(DAY),
+            last_served: Instant::now(),
+        }
+    }
+}
+
+/// Accounts for the bytes of RPC responses served to each peer and enforces per-peer caps on the
+/// bandwidth used by expensive methods, so a single peer cannot saturate our upload.
+pub struct BandwidthTracker {
+    peers: HashMap<PeerId, PeerBandwidth>,
+    /// The maximum bytes of expensive responses served to a peer per hour, if any.
+    hourly_limit: Option<u64>,
+    /// The maximum bytes of expensive responses served to a peer per day, if any.
+    daily_limit: Option<u64>,
+}
+
+impl BandwidthTracker {
+    pub fn new(hourly_limit: Option<u64>, daily_limit: Option<u64>) -> Self {
+        BandwidthTracker {
+            peers: HashMap::new(),
+            hourly_limit,
+            daily_limit,
+        }
+    }
+
+    /// Returns `true` if the peer is within its bandwidth caps and may be served an expensive
+    /// method.
+    pub fn can_serve(&mut self, peer_id: &PeerId) -> bool {
+        let peer = match self.peers.get_mut(peer_id) {
+            Some(peer) => peer,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        peer.hour.roll(now);
+        peer.day.roll(now);
+
+        let within = |limit: Option<u64>, bytes: u64| limit.map_or(true, |limit| bytes < limit);
+        within(self.hourly_limit, peer.hour.bytes) && within(self.daily_limit, peer.day.bytes)
+    }
+
+    /// Records `bytes` served to a peer for some RPC method.
+    pub fn record(&mut self, peer_id: &PeerId, method_id: u16, bytes: u64) {
+        let peer = self.peers.entry(peer_id.clone()).or_default();
+        let now = Instant::now();
+        peer.last_served = now;
+
+        *peer.by_method.entry(method_id).or_insert(0) += bytes;
+
+        if is_expensive_method(method_id) {
+            peer.hour.roll(now);
+            peer.day.roll(now);
+            peer.hour.bytes += bytes;
+            peer.day.bytes += bytes;
+        }
+    }
+
+    /// Forgets the peers not served for a day. Their caps no longer constrain them, as their
+    /// windows have elapsed, so this only discards their totals by method.
+    ///
+    /// Peers are not forgotten on disconnecting, so that reconnecting does not lift their caps.
+    pub fn prune(&mut self, now: Instant) {
+        self.peers
+            .retain(|_, peer| now.duration_since(peer.last_served) < DAY);
+    }
+
+    /// Returns the total bytes served to a peer for each RPC method id.
+    pub fn served_by_method(&self, peer_id: &PeerId) -> Option<&HashMap<u16, u64>> {
+        self.peers.get(peer_id).map(|peer| &peer.by_method)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: u16 = 0;
+    const BLOCK_BODIES: u16 = 12;
+
+    #[test]
+    fn caps_expensive_methods_only() {
+        let mut tracker = BandwidthTracker::new(Some(100), None);
+        let peer_id = PeerId::random();
+        tracker.record(&peer_id, HELLO, 1_000);
+        assert!(tracker.can_serve(&peer_id));
+
+        tracker.record(&peer_id, BLOCK_BODIES, 100);
+        assert!(!tracker.can_serve(&peer_id));
+        assert!(tracker.can_serve(&PeerId::random()));
+
+        let served = tracker.served_by_method(&peer_id).unwrap();
+        assert_eq!(served[&HELLO], 1_000);
+        assert_eq!(served[&BLOCK_BODIES], 100);
+    }
+
+    #[test]
+    fn prunes_peers_not_served_for_a_day() {
+        let mut tracker = BandwidthTracker::new(Some(100), None);
+        let idle = PeerId::random();
+        tracker.record(&idle, BLOCK_BODIES, 100);
+
+        let now = Instant::now();
+        tracker.prune(now);
+        assert!(tracker.served_by_method(&idle).is_some());
+
+        tracker.prune(now + DAY);
+        assert!(tracker.served_by_method(&idle).is_none());
+        assert!(tracker.can_serve(&idle));
+    }
+}
@@ -4,22 +4,91 @@ use beacon_chain::{
     fork_choice::ForkChoice,
     parking_lot::RwLockReadGuard,
     slot_clock::SlotClock,
-    types::{BeaconState, ChainSpec},
-    CheckPoint,
+    types::{
+        Attestation, BeaconBlock, BeaconBlockBody, BeaconBlockHeader, BeaconState, ChainSpec,
+        Hash256, Slot,
+    },
+    AttestationValidationError, BeaconChainError, BlockProcessingOutcome, CheckPoint, HeadInfo,
+    TraceId,
 };
 
-/// The network's API to the beacon chain.
-pub trait BeaconChain: Send + Sync {
+/// The parts of the beacon chain used by sync, so that sync may be driven by a mock chain in
+/// tests.
+pub trait BeaconChainRead: Send + Sync {
     fn get_spec(&self) -> &ChainSpec;
 
     fn get_state(&self) -> RwLockReadGuard<BeaconState>;
 
+    /// Returns the values of the head most often read, without waiting for block imports.
+    fn head_info(&self) -> HeadInfo;
+
+    fn genesis_block_root(&self) -> Hash256;
+
+    fn genesis_validators_root(&self) -> Hash256;
+
+    fn is_block_known(&self, block_root: &Hash256) -> bool;
+
+    /// Returns the slot of the present state, beyond which blocks may not yet be imported, without
+    /// waiting for block imports.
+    fn present_slot(&self) -> Slot;
+
+    /// Returns the slot of the wall clock, if it is after genesis.
+    fn read_slot_clock(&self) -> Option<Slot>;
+
+    /// Returns the root of the canonical block at `slot`, or of the latest before it, if known.
+    fn block_root_at_slot(&self, slot: Slot) -> Option<Hash256>;
+
+    fn process_block(
+        &self,
+        block: BeaconBlock,
+        trace_id: TraceId,
+    ) -> Result<BlockProcessingOutcome, BeaconChainError>;
+
+    /// Imports blocks, each the parent of the next, returning the outcome of each processed and
+    /// stopping after the first which is not imported.
+    fn process_chain_segment(
+        &self,
+        blocks: Vec<BeaconBlock>,
+        trace_id: TraceId,
+    ) -> Vec<Result<BlockProcessingOutcome, BeaconChainError>>;
+
+    /// Returns the slot of the oldest block held and the root of its parent, or `None` if every
+    /// block back to genesis is held.
+    fn oldest_block_parent(&self) -> Option<(Slot, Hash256)>;
+
+    /// Stores historical blocks, in slot order, the last being the parent of the oldest block
+    /// held, returning the number stored.
+    fn import_historical_blocks(&self, blocks: &[BeaconBlock]) -> Result<usize, BeaconChainError>;
+}
+
+/// The network's API to the beacon chain.
+pub trait BeaconChain: BeaconChainRead {
     fn head(&self) -> RwLockReadGuard<CheckPoint>;
 
     fn finalized_head(&self) -> RwLockReadGuard<CheckPoint>;
+
+    fn get_block_roots(&self, start_slot: Slot, count: u64) -> Vec<(Hash256, Slot)>;
+
+    fn get_block_headers(
+        &self,
+        start_slot: Slot,
+        max_headers: u64,
+        skip_slots: u64,
+    ) -> Vec<BeaconBlockHeader>;
+
+    fn get_block_bodies(&self, block_roots: &[Hash256]) -> Vec<BeaconBlockBody>;
+
+    fn process_attestation(
+        &self,
+        attestation: Attestation,
+    ) -> Result<bool, AttestationValidationError>;
+
+    /// Returns the aggregate attestations held for inclusion in blocks with a slot at or after
+    /// `start_slot`, newest first, up to `max` attestations.
+    fn get_pool_attestations(&self, start_slot: Slot, max: usize) -> Vec<Attestation>;
 }
 
-impl<T, U, F> BeaconChain for RawBeaconChain<T, U, F>
+impl<T, U, F> BeaconChainRead for RawBeaconChain<T, U, F>
 where
     T: ClientDB + Sized,
     U: SlotClock,
@@ -33,6 +102,65 @@ where
         self.state.read()
     }
 
+    fn head_info(&self) -> HeadInfo {
+        self.head_info()
+    }
+
+    fn genesis_block_root(&self) -> Hash256 {
+        self.genesis_block_root
+    }
+
+    fn genesis_validators_root(&self) -> Hash256 {
+        self.genesis_validators_root
+    }
+
+    fn is_block_known(&self, block_root: &Hash256) -> bool {
+        self.block_store.block_exists(block_root).unwrap_or(false)
+    }
+
+    fn present_slot(&self) -> Slot {
+        self.head_info().present_slot
+    }
+
+    fn read_slot_clock(&self) -> Option<Slot> {
+        self.read_slot_clock()
+    }
+
+    fn block_root_at_slot(&self, slot: Slot) -> Option<Hash256> {
+        self.block_root_at_slot(slot)
+    }
+
+    fn process_block(
+        &self,
+        block: BeaconBlock,
+        trace_id: TraceId,
+    ) -> Result<BlockProcessingOutcome, BeaconChainError> {
+        self.process_block_with_trace_id(block, trace_id)
+    }
+
+    fn process_chain_segment(
+        &self,
+        blocks: Vec<BeaconBlock>,
+        trace_id: TraceId,
+    ) -> Vec<Result<BlockProcessingOutcome, BeaconChainError>> {
+        self.process_chain_segment(blocks, trace_id)
+    }
+
+    fn oldest_block_parent(&self) -> Option<(Slot, Hash256)> {
+        self.oldest_block_parent()
+    }
+
+    fn import_historical_blocks(&self, blocks: &[BeaconBlock]) -> Result<usize, BeaconChainError> {
+        self.import_historical_blocks(blocks)
+    }
+}
+
+impl<T, U, F> BeaconChain for RawBeaconChain<T, U, F>
+where
+    T: ClientDB + Sized,
+    U: SlotClock,
+    F: ForkChoice,
+{
     fn head(&self) -> RwLockReadGuard<CheckPoint> {
         self.head()
     }
@@ -40,4 +168,52 @@ where
     fn finalized_head(&self) -> RwLockReadGuard<CheckPoint> {
         self.finalized_head()
     }
+
+    fn get_block_roots(&self, start_slot: Slot, count: u64) -> Vec<(Hash256, Slot)> {
+        self.get_block_roots(start_slot, count)
+    }
+
+    fn get_block_headers(
+        &self,
+        start_slot: Slot,
+        max_headers: u64,
+        skip_slots: u64,
+    ) -> Vec<BeaconBlockHeader> {
+        // headers are counted in blocks rather than slots, so consider all slots up to the head
+        let head_slot = self.head().beacon_block.slot;
+        if start_slot > head_slot {
+            return vec![];
+        }
+        let step = skip_slots.saturating_add(1);
+        let count = std::cmp::min(
+            (head_slot - start_slot).as_u64() + 1,
+            max_headers.saturating_mul(step),
+        );
+        self.get_block_roots(start_slot, count)
+            .into_iter()
+            .step_by(step as usize)
+            .take(max_headers as usize)
+            .filter_map(|(root, _)| self.block_store.get_deserialized(&root).ok()?)
+            .map(|block| block.block_header())
+            .collect()
+    }
+
+    fn get_block_bodies(&self, block_roots: &[Hash256]) -> Vec<BeaconBlockBody> {
+        block_roots
+            .iter()
+            .filter_map(|root| self.block_store.get_deserialized(root).ok()?)
+            .map(|block| block.body)
+            .collect()
+    }
+
+    fn process_attestation(
+        &self,
+        attestation: Attestation,
+    ) -> Result<bool, AttestationValidationError> {
+        self.process_attestation(attestation)
+    }
+
+    fn get_pool_attestations(&self, start_slot: Slot, max: usize) -> Vec<Attestation> {
+        self.get_pool_attestations(start_slot, max)
+    }
 }
@@ -0,0 +1,464 @@
+use crate::peer_manager::PeerManager;
+use eth2_libp2p::multiaddr::Protocol;
+use eth2_libp2p::{Multiaddr, PeerId};
+use slog::debug;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// The interval at which the number of connected peers is checked against the target.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// The delay before an address is dialed again. Doubled after each further attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+/// The maximum delay before an address is dialed again.
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+/// The maximum number of addresses remembered for a single peer.
+const MAX_ADDRESSES_PER_PEER: usize = 8;
+/// The number of failed dials of each of its addresses after which a peer is forgotten.
+const MAX_DIAL_ATTEMPTS: u32 = 8;
+/// The maximum number of discovered peers whose addresses are remembered.
+const MAX_KNOWN_PEERS: usize = 1_000;
+
+/// The dial attempts made to an address since a connection was last made through it.
+struct Backoff {
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// Maintains a target number of connected peers.
+///
+/// When above the target, the lowest-scored peers are disconnected. When below, the addresses of
+/// known peers which are not connected are dialed, backing off exponentially from addresses which
+/// do not yield a connection. A peer none of whose addresses has yielded a connection after
+/// `MAX_DIAL_ATTEMPTS` dials is forgotten, and peers discovered once `MAX_KNOWN_PEERS` are known
+/// are ignored.
+///
+/// Trusted peers are never disconnected to reach the target, and are redialed whenever they are
+/// not connected, regardless of the target.
+pub struct ConnectionManager {
+    target_peers: usize,
+    /// The configured address of each trusted peer.
+    trusted: HashMap<PeerId, Multiaddr>,
+    /// The peers currently connected.
+    connected: HashSet<PeerId>,
+    /// The addresses each known peer listens on.
+    addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    /// The dial backoff of each address which has been dialed.
+    backoff: HashMap<Multiaddr, Backoff>,
+    /// The `ConnectionManager` logger.
+    log: slog::Logger,
+}
+
+impl ConnectionManager {
+    pub fn new(target_peers: usize, log: slog::Logger) -> Self {
+        ConnectionManager {
+            target_peers,
+            trusted: HashMap::new(),
+            connected: HashSet::new(),
+            addresses: HashMap::new(),
+            backoff: HashMap::new(),
+            log,
+        }
+    }
+
+    /// Adds a peer which is always kept connected, dialed at `address`.
+    pub fn add_trusted_peer(&mut self, peer_id: PeerId, address: Multiaddr) {
+        self.trusted.insert(peer_id, address);
+    }
+
+    /// Records a connection to a peer. The backoff of its addresses is reset when the peer
+    /// newly connects, not each time a connected peer is identified again.
+    pub fn peer_connected(&mut self, peer_id: PeerId) {
+        if self.connected.contains(&peer_id) {
+            return;
+        }
+        if let Some(addresses) = self.addresses.get(&peer_id) {
+            for address in addresses {
+                self.backoff.remove(address);
+            }
+        }
+        if let Some(address) = self.trusted.get(&peer_id) {
+            self.backoff.remove(address);
+        }
+        self.connected.insert(peer_id);
+    }
+
+    /// Records that the connection to a peer has closed.
+    pub fn peer_disconnected(&mut self, peer_id: &PeerId) {
+        self.connected.remove(peer_id);
+    }
+
+    /// Returns `true` if the peer is connected.
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        self.connected.contains(peer_id)
+    }
+
+    /// Returns `true` if fewer peers than the target are connected, so more should be searched
+    /// for.
+    pub fn needs_peers(&self) -> bool {
+        self.connected.len() < self.target_peers
+    }
+
+    /// Returns the peers currently connected.
+    pub fn connected_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.connected.iter()
+    }
+
+    /// Returns the addresses a peer is known to listen on.
+    pub fn addresses(&self, peer_id: &PeerId) -> &[Multiaddr] {
+        self.addresses
+            .get(peer_id)
+            .map(|addresses| addresses.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Records the addresses a peer listens on, as identified by the peer itself, replacing
+    /// those previously known.
+    ///
+    /// The peer is connected, so it is recorded even when `MAX_KNOWN_PEERS` are known.
+    pub fn set_addresses(&mut self, peer_id: &PeerId, mut addresses: Vec<Multiaddr>) {
+        addresses.truncate(MAX_ADDRESSES_PER_PEER);
+        if let Some(replaced) = self.addresses.remove(peer_id) {
+            for address in replaced {
+                if !addresses.contains(&address) {
+                    self.backoff.remove(&address);
+                }
+            }
+        }
+        if !addresses.is_empty() {
+            self.addresses.insert(peer_id.clone(), addresses);
+        }
+    }
+
+    /// Records addresses a peer was discovered at, in addition to those already known.
+    ///
+    /// A newly discovered peer is ignored if `MAX_KNOWN_PEERS` are already known.
+    pub fn add_addresses(&mut self, peer_id: &PeerId, addresses: Vec<Multiaddr>) {
+        if addresses.is_empty()
+            || (!self.addresses.contains_key(peer_id) && self.addresses.len() >= MAX_KNOWN_PEERS)
+        {
+            return;
+        }
+        let known = self
+            .addresses
+            .entry(peer_id.clone())
+            .or_insert_with(Vec::new);
+        for address in addresses {
+            if known.len() >= MAX_ADDRESSES_PER_PEER {
+                break;
+            }
+            if !known.contains(&address) {
+                known.push(address);
+            }
+        }
+    }
+
+    /// Returns the connected peers to disconnect to return to the target peer count, lowest
+    /// scored first. Trusted peers are never returned.
+    pub fn peers_to_prune(&self, peer_manager: &PeerManager) -> Vec<PeerId> {
+        if self.connected.len() <= self.target_peers {
+            return vec![];
+        }
+
+        let mut peers: Vec<(i64, &PeerId)> = self
+            .connected
+            .iter()
+            .filter(|peer_id| !self.trusted.contains_key(peer_id))
+            .map(|peer_id| {
+                let score = peer_manager.peer(peer_id).map_or(0, |info| info.score);
+                (score, peer_id)
+            })
+            .collect();
+        peers.sort_by_key(|(score, _)| *score);
+
+        let excess = self.connected.len() - self.target_peers;
+        debug!(
+            self.log,
+            "Above target peer count";
+            "connected" => self.connected.len(),
+            "target" => self.target_peers,
+        );
+        peers
+            .into_iter()
+            .take(excess)
+            .map(|(_, peer_id)| peer_id.clone())
+            .collect()
+    }
+
+    /// Returns addresses of disconnected peers to dial to reach the target peer count, recording
+    /// an attempt against each. At most one address of each peer is returned, and banned peers
+    /// and addresses which are backing off are skipped.
+    ///
+    /// The addresses of disconnected trusted peers are returned whenever they are not backing off.
+    pub fn addresses_to_dial(&mut self, peer_manager: &PeerManager) -> Vec<Multiaddr> {
+        let now = Instant::now();
+        self.forget_unreachable(now);
+
+        let backoff = &self.backoff;
+        let connected = &self.connected;
+        let mut to_dial: Vec<Multiaddr> = self
+            .trusted
+            .iter()
+            .filter(|(peer_id, address)| {
+                !connected.contains(peer_id)
+                    && backoff
+                        .get(*address)
+                        .map_or(true, |backoff| backoff.next_attempt <= now)
+            })
+            .map(|(_, address)| address.clone())
+            .collect();
+        let trusted_count = to_dial.len();
+
+        let deficit = self.target_peers.saturating_sub(self.connected.len());
+        for (peer_id, addresses) in &self.addresses {
+            if to_dial.len() - trusted_count >= deficit {
+                break;
+            }
+            if self.trusted.contains_key(peer_id) {
+                continue;
+            }
+            let banned = peer_manager
+                .peer(peer_id)
+                .and_then(|info| info.banned_until)
+                .map_or(false, |until| until > now);
+            if banned || self.connected.contains(peer_id) {
+                continue;
+            }
+
+            let address = addresses.iter().find(|address| {
+                backoff
+                    .get(*address)
+                    .map_or(true, |backoff| backoff.next_attempt <= now)
+            });
+            if let Some(address) = address {
+                to_dial.push(address.clone());
+            }
+        }
+
+        for address in &to_dial {
+            let backoff = self.backoff.entry(address.clone()).or_insert(Backoff {
+                attempts: 0,
+                next_attempt: now,
+            });
+            let delay = INITIAL_BACKOFF * 2u32.pow(cmp::min(backoff.attempts, 16));
+            backoff.attempts += 1;
+            backoff.next_attempt = now + cmp::min(delay, MAX_BACKOFF);
+        }
+
+        if !to_dial.is_empty() {
+            debug!(
+                self.log,
+                "Dialing known peers";
+                "connected" => self.connected.len(),
+                "target" => self.target_peers,
+                "dialing" => to_dial.len(),
+                "trusted" => trusted_count,
+            );
+        }
+        to_dial
+    }
+
+    /// Forgets the disconnected peers, other than trusted peers, whose every address has been
+    /// dialed `MAX_DIAL_ATTEMPTS` times without a connection, along with the backoff of those
+    /// addresses.
+    fn forget_unreachable(&mut self, now: Instant) {
+        let backoff = &self.backoff;
+        let unreachable: Vec<PeerId> = self
+            .addresses
+            .iter()
+            .filter(|(peer_id, addresses)| {
+                !self.trusted.contains_key(*peer_id)
+                    && !self.connected.contains(*peer_id)
+                    && addresses.iter().all(|address| {
+                        backoff.get(address).map_or(false, |backoff| {
+                            backoff.attempts >= MAX_DIAL_ATTEMPTS && backoff.next_attempt <= now
+                        })
+                    })
+            })
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        for peer_id in unreachable {
+            debug!(self.log, "Forgetting unreachable peer: {:?}", peer_id);
+            if let Some(addresses) = self.addresses.remove(&peer_id) {
+                for address in addresses {
+                    self.backoff.remove(&address);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the peer id given by the `/p2p/` component of `address`, if any.
+pub fn peer_id_of(address: &Multiaddr) -> Option<PeerId> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(multihash) => PeerId::from_multihash(multihash).ok(),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+
+    fn logger() -> slog::Logger {
+        slog::Logger::root(slog::Discard, o!())
+    }
+
+    fn address(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()
+    }
+
+    fn peer_manager() -> PeerManager {
+        PeerManager::new(Duration::from_secs(60), logger())
+    }
+
+    #[test]
+    fn lowest_scored_peers_are_pruned_above_target() {
+        let mut manager = ConnectionManager::new(2, logger());
+        let mut peer_manager = peer_manager();
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        manager.add_trusted_peer(peers[0].clone(), address(9000));
+        for peer_id in &peers {
+            manager.peer_connected(peer_id.clone());
+        }
+        // the trusted peer has the lowest score, but is never pruned
+        peer_manager.penalize(&peers[0], 50, "test");
+        peer_manager.penalize(&peers[1], 20, "test");
+        peer_manager.penalize(&peers[2], 10, "test");
+
+        assert_eq!(
+            manager.peers_to_prune(&peer_manager),
+            vec![peers[1].clone(), peers[2].clone()]
+        );
+
+        manager.peer_disconnected(&peers[1]);
+        manager.peer_disconnected(&peers[2]);
+        assert!(manager.peers_to_prune(&peer_manager).is_empty());
+    }
+
+    #[test]
+    fn discovered_peers_are_dialed_up_to_the_target() {
+        let mut manager = ConnectionManager::new(2, logger());
+        let mut peer_manager = peer_manager();
+        assert!(manager.needs_peers());
+
+        let banned = PeerId::random();
+        peer_manager.penalize(&banned, 1000, "test");
+        manager.add_addresses(&banned, vec![address(9000)]);
+        for port in 9001..9004 {
+            manager.add_addresses(&PeerId::random(), vec![address(port)]);
+        }
+
+        let to_dial = manager.addresses_to_dial(&peer_manager);
+        assert_eq!(to_dial.len(), 2);
+        assert!(!to_dial.contains(&address(9000)));
+
+        manager.peer_connected(PeerId::random());
+        manager.peer_connected(PeerId::random());
+        assert!(!manager.needs_peers());
+        assert!(manager.addresses_to_dial(&peer_manager).is_empty());
+    }
+
+    #[test]
+    fn discovered_addresses_are_merged_and_capped() {
+        let mut manager = ConnectionManager::new(2, logger());
+        let peer_id = PeerId::random();
+        manager.add_addresses(&peer_id, vec![address(9000)]);
+        manager.add_addresses(&peer_id, vec![address(9000), address(9001)]);
+        assert_eq!(manager.addresses(&peer_id), &[address(9000), address(9001)]);
+
+        manager.add_addresses(&peer_id, (9002..9020).map(address).collect());
+        assert_eq!(manager.addresses(&peer_id).len(), MAX_ADDRESSES_PER_PEER);
+    }
+
+    #[test]
+    fn backoff_is_only_reset_by_a_new_connection() {
+        let mut manager = ConnectionManager::new(1, logger());
+        let peer_manager = peer_manager();
+        let peer_id = PeerId::random();
+        manager.add_addresses(&peer_id, vec![address(9000)]);
+
+        assert_eq!(
+            manager.addresses_to_dial(&peer_manager),
+            vec![address(9000)]
+        );
+        assert!(manager.addresses_to_dial(&peer_manager).is_empty());
+
+        // learning the address again does not reset its backoff
+        manager.set_addresses(&peer_id, vec![address(9000)]);
+        assert!(manager.addresses_to_dial(&peer_manager).is_empty());
+
+        // a connection does, so the address is dialed as soon as the peer disconnects
+        manager.peer_connected(peer_id.clone());
+        manager.peer_disconnected(&peer_id);
+        assert_eq!(
+            manager.addresses_to_dial(&peer_manager),
+            vec![address(9000)]
+        );
+    }
+
+    #[test]
+    fn unreachable_peers_are_forgotten() {
+        let mut manager = ConnectionManager::new(1, logger());
+        let peer_manager = peer_manager();
+        let peer_id = PeerId::random();
+        manager.add_addresses(&peer_id, vec![address(9000)]);
+
+        for _ in 0..MAX_DIAL_ATTEMPTS {
+            assert_eq!(
+                manager.addresses_to_dial(&peer_manager),
+                vec![address(9000)]
+            );
+            // the backoff elapses without a connection
+            manager
+                .backoff
+                .get_mut(&address(9000))
+                .unwrap()
+                .next_attempt = Instant::now();
+        }
+
+        assert!(manager.addresses_to_dial(&peer_manager).is_empty());
+        assert!(manager.addresses(&peer_id).is_empty());
+        assert!(manager.backoff.is_empty());
+    }
+
+    #[test]
+    fn discovered_peers_are_capped() {
+        let mut manager = ConnectionManager::new(1, logger());
+        let peers: Vec<PeerId> = (0..=MAX_KNOWN_PEERS).map(|_| PeerId::random()).collect();
+        for peer_id in &peers {
+            manager.add_addresses(peer_id, vec![address(9000)]);
+        }
+
+        assert_eq!(manager.addresses.len(), MAX_KNOWN_PEERS);
+        assert!(manager.addresses(&peers[MAX_KNOWN_PEERS]).is_empty());
+        // known peers may still be given more addresses
+        manager.add_addresses(&peers[0], vec![address(9001)]);
+        assert_eq!(manager.addresses(&peers[0]).len(), 2);
+    }
+
+    #[test]
+    fn trusted_peers_are_redialed_regardless_of_the_target() {
+        let mut manager = ConnectionManager::new(1, logger());
+        let peer_manager = peer_manager();
+        let trusted = PeerId::random();
+        manager.add_trusted_peer(trusted.clone(), address(9000));
+        manager.peer_connected(PeerId::random());
+
+        assert_eq!(
+            manager.addresses_to_dial(&peer_manager),
+            vec![address(9000)]
+        );
+        // backing off
+        assert!(manager.addresses_to_dial(&peer_manager).is_empty());
+
+        manager.peer_connected(trusted.clone());
+        manager.peer_disconnected(&trusted);
+        assert_eq!(
+            manager.addresses_to_dial(&peer_manager),
+            vec![address(9000)]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use types::Slot;
+
+/// The number of slots after its own slot that an attestation is accepted from gossip.
+pub const ATTESTATION_PROPAGATION_SLOT_RANGE: u64 = 32;
+
+/// The reason a gossip message was rejected before being processed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GossipError {
+    /// The attestation is more than `ATTESTATION_PROPAGATION_SLOT_RANGE` slots old.
+    AttestationTooOld,
+    /// The attestation is for a slot which has yet to start.
+    AttestationFromFuture,
+    /// The block is not later than our finalized block, so cannot join our chain.
+    BlockFinalized,
+}
+
+/// Validates the slot of an attestation received over gossip, which must be within
+/// `ATTESTATION_PROPAGATION_SLOT_RANGE` slots before `present_slot` and not after it.
+pub fn validate_gossip_attestation(
+    attestation_slot: Slot,
+    present_slot: Slot,
+) -> Result<(), GossipError> {
+    if attestation_slot + ATTESTATION_PROPAGATION_SLOT_RANGE < present_slot {
+        return Err(GossipError::AttestationTooOld);
+    }
+    if attestation_slot > present_slot {
+        return Err(GossipError::AttestationFromFuture);
+    }
+    Ok(())
+}
+
+/// Validates the slot of a block received over gossip, which must be later than the slot of
+/// our finalized block.
+pub fn validate_gossip_block(block_slot: Slot, finalized_slot: Slot) -> Result<(), GossipError> {
+    if block_slot <= finalized_slot {
+        return Err(GossipError::BlockFinalized);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attestations_are_accepted_from_the_propagation_range() {
+        let present_slot = Slot::new(100);
+        let oldest = present_slot - ATTESTATION_PROPAGATION_SLOT_RANGE;
+
+        assert_eq!(
+            validate_gossip_attestation(present_slot, present_slot),
+            Ok(())
+        );
+        assert_eq!(validate_gossip_attestation(oldest, present_slot), Ok(()));
+        assert_eq!(
+            validate_gossip_attestation(oldest - 1, present_slot),
+            Err(GossipError::AttestationTooOld)
+        );
+        assert_eq!(
+            validate_gossip_attestation(present_slot + 1, present_slot),
+            Err(GossipError::AttestationFromFuture)
+        );
+    }
+
+    #[test]
+    fn blocks_must_follow_the_finalized_block() {
+        let finalized_slot = Slot::new(64);
+
+        assert_eq!(
+            validate_gossip_block(finalized_slot + 1, finalized_slot),
+            Ok(())
+        );
+        assert_eq!(
+            validate_gossip_block(finalized_slot, finalized_slot),
+            Err(GossipError::BlockFinalized)
+        );
+        assert_eq!(
+            validate_gossip_block(finalized_slot - 1, finalized_slot),
+            Err(GossipError::BlockFinalized)
+        );
+    }
+}
@@ -1,9 +1,23 @@
 /// This crate provides the network server for Lighthouse.
+mod audit_log;
+mod bandwidth;
 pub mod beacon_chain;
+mod connection_manager;
 pub mod error;
+mod gossip_validation;
 mod message_handler;
+pub mod metrics;
+mod outbound_queue;
+pub mod peer_manager;
+mod rate_limiter;
+mod request_validation;
+mod seen_cache;
 mod service;
 pub mod sync;
+mod throttle;
 
 pub use eth2_libp2p::NetworkConfig;
-pub use service::Service;
+pub use message_handler::{HandlerMessage, MessageHandler};
+pub use outbound_queue::{OutboundBacklog, OutboundQueue};
+pub use service::{NetworkMessage, OutgoingMessage, PeerSnapshot, Service};
+pub use sync::SyncConfig;
@@ -1,23 +1,62 @@
+use crate::bandwidth::BandwidthTracker;
 use crate::beacon_chain::BeaconChain;
-use crate::error;
+use crate::gossip_validation::{validate_gossip_attestation, validate_gossip_block};
+use crate::metrics;
+use crate::outbound_queue::{OutboundBacklog, Priority};
+use crate::peer_manager::{
+    PeerAction, PeerManager, BAD_HELLO_PENALTY, HELLO_TIMEOUT_PENALTY, INVALID_ATTESTATION_PENALTY,
+    INVALID_REQUEST_PENALTY, MISMATCHED_RESPONSE_PENALTY, RATE_LIMIT_PENALTY,
+    REQUEST_TIMEOUT_PENALTY,
+};
+use crate::rate_limiter::RateLimiter;
+use crate::request_validation::{
+    validate_block_bodies_request, validate_block_headers_request, validate_block_roots_request,
+    RangeRequestError,
+};
+use crate::seen_cache::{SeenCache, SEEN_CACHE_CAPACITY};
 use crate::service::{NetworkMessage, OutgoingMessage};
-use crate::sync::SimpleSync;
-use crossbeam_channel::{unbounded as channel, Sender};
+use crate::sync::{SimpleSync, StopReason, SyncConfig, SyncStatus, MAX_REQUESTED_ATTESTATIONS};
+use crate::NetworkConfig;
+use beacon_chain::parking_lot::RwLock;
+use beacon_chain::TraceId;
 use eth2_libp2p::{
-    rpc::{RPCMethod, RPCRequest, RPCResponse},
-    HelloMessage, PeerId, RPCEvent,
+    rpc::{
+        error_codes, BeaconAttestationsRequest, BeaconAttestationsResponse,
+        BeaconBlockBodiesRequest, BeaconBlockBodiesResponse, BeaconBlockHeadersRequest,
+        BeaconBlockHeadersResponse, BeaconBlockRootsRequest, BeaconBlockRootsResponse,
+        BlockRootSlot, GoodbyeReason, MetaData, Ping, RPCMethod, RPCRequest, RPCResponse,
+        ATTESTATION_SUBNET_COUNT,
+    },
+    HelloMessage, PeerId, PubsubMessage, RPCEvent, BEACON_ATTESTATION_TOPIC,
 };
 use futures::future;
-use slog::warn;
-use slog::{debug, trace};
-use std::collections::HashMap;
+use futures::prelude::*;
+use futures::sync::{mpsc, oneshot};
+use slog::{debug, error, trace, warn};
+use ssz::{ssz_encode, TreeHash};
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::SyncSender;
 use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
+use tokio::runtime::current_thread;
+use tokio::timer::Interval;
+use types::{Bitfield, Hash256};
 
 /// Timeout for RPC requests.
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
-/// Timeout before banning a peer for non-identification.
+/// Timeout before disconnecting a peer for non-identification.
 const HELLO_TIMEOUT: Duration = Duration::from_secs(30);
+/// The interval at which requests and HELLOs are checked for timeouts, whether or not messages are
+/// being received.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of messages which may be queued for the handler before the network service stops
+/// reading from the swarm.
+const HANDLER_QUEUE_CAPACITY: usize = 256;
+/// The factor by which the handler queue and the cache of seen gossip are enlarged when
+/// subscribed to every attestation subnet.
+const ALL_SUBNETS_CAPACITY_FACTOR: usize = 4;
 
 /// Handles messages received from the network and client and organises syncing.
 pub struct MessageHandler {
@@ -25,67 +64,244 @@ pub struct MessageHandler {
     chain: Arc<BeaconChain>,
     /// The syncing framework.
     sync: SimpleSync,
+    /// Tracks the behaviour of peers, shared with the network service.
+    peer_manager: Arc<RwLock<PeerManager>>,
     /// The network channel to relay messages to the Network service.
-    network_send: crossbeam_channel::Sender<NetworkMessage>,
-    /// A mapping of peers and the RPC id we have sent an RPC request to.
-    requests: HashMap<(PeerId, u64), Instant>,
+    network_send: mpsc::UnboundedSender<NetworkMessage>,
+    /// A mapping of peers and the RPC id we have sent an RPC request to, to the method of the
+    /// request and when it was sent.
+    requests: HashMap<(PeerId, u64), (RPCMethod, Instant)>,
+    /// Requests still queued by the network service, with the number given to each by
+    /// `OutboundBacklog::next_request`. Their timeouts start once they have been sent.
+    unsent_requests: HashMap<(PeerId, u64), usize>,
     /// A counter of request id for each peer.
     request_ids: HashMap<PeerId, u64>,
+    /// Requests waiting to be sent to each peer once it has fewer than `max_requests_per_peer`
+    /// requests in flight.
+    pending_requests: HashMap<PeerId, VecDeque<RPCRequest>>,
+    /// The maximum number of our requests awaiting a response from a single peer.
+    max_requests_per_peer: usize,
+    /// Peers we have dialed which have not yet completed the HELLO handshake.
+    pending_hellos: HashMap<PeerId, Instant>,
+    /// The request id of the PING awaiting a response from each peer.
+    pending_pings: HashMap<PeerId, u64>,
+    /// Accounts for the bytes served to each peer.
+    bandwidth: BandwidthTracker,
+    /// Limits the rate of requests from each peer.
+    rate_limiter: RateLimiter,
+    /// The roots of recently received gossip messages.
+    seen_gossip: SeenCache,
+    /// Our metadata, sent to peers which request it.
+    metadata: MetaData,
+    /// The lengths of the network service's queues of outgoing RPC messages.
+    outbound_backlog: OutboundBacklog,
     /// The `MessageHandler` logger.
     log: slog::Logger,
 }
 
 /// Types of messages the handler can receive.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum HandlerMessage {
     /// We have initiated a connection to a new peer.
     PeerDialed(PeerId),
     /// Peer has disconnected,
     PeerDisconnected(PeerId),
-    /// An RPC response/request has been received.
-    RPC(PeerId, RPCEvent),
-    /// A block has been imported.
-    BlockImported(), //TODO: This comes from pub-sub - decide its contents
+    /// An RPC response/request has been received, of the given length on the wire, identified by
+    /// a `TraceId` assigned when it was received.
+    RPC(PeerId, RPCEvent, usize, TraceId),
+    /// A message has been received which could not be decoded.
+    DecodeError {
+        peer_id: PeerId,
+        /// The RPC method or gossipsub topic of the message.
+        message_type: String,
+        error_kind: String,
+    },
+    /// A snappy compressed message has been received which would decompress to more than the
+    /// maximum message size, and was rejected before being decompressed.
+    OversizedMessage {
+        peer_id: PeerId,
+        /// The RPC method or gossipsub topic of the message.
+        message_type: String,
+        len: usize,
+        max_len: usize,
+    },
+    /// A gossipsub message has been received, identified by a `TraceId` assigned when it was
+    /// received.
+    PubsubMessage(PeerId, PubsubMessage, TraceId),
+    /// An operator has requested that syncing be paused.
+    PauseSync,
+    /// An operator has requested that syncing be resumed.
+    ResumeSync,
+    /// Reply with the progress of syncing, including whether we consider ourselves synced.
+    GetSyncStatus(SyncSender<SyncStatus>),
+    /// Sent by the network service at a regular interval, on which peers are pinged.
+    Heartbeat,
+    /// The peers connected when the handler was restarted, which are sent a HELLO again as the
+    /// new handler knows nothing of them.
+    ConnectedPeers(Vec<PeerId>),
+    /// The node is shutting down. Messages already received are handled, peers are sent a
+    /// GOODBYE and the handler stops.
+    Shutdown,
 }
 
 impl MessageHandler {
-    /// Initializes and runs the MessageHandler.
+    /// Initializes the MessageHandler and spawns it on a thread of its own, as handling a message
+    /// may run blocking chain work, such as importing a block, which must not hold up the tasks
+    /// of the executor.
+    ///
+    /// Returns the bounded channel on which the handler receives messages, and a receiver which
+    /// completes once the handler has stopped: after a `HandlerMessage::Shutdown`, or once every
+    /// sender of the channel is dropped. Senders wait for capacity whilst the handler is behind.
+    ///
+    /// If handling a message panics, the handler is replaced by a new one, which continues with
+    /// the next message on the channel.
     pub fn spawn(
         beacon_chain: Arc<BeaconChain>,
-        network_send: crossbeam_channel::Sender<NetworkMessage>,
-        executor: &tokio::runtime::TaskExecutor,
+        network_send: mpsc::UnboundedSender<NetworkMessage>,
+        peer_manager: Arc<RwLock<PeerManager>>,
+        outbound_backlog: OutboundBacklog,
+        network_config: &NetworkConfig,
+        sync_config: &SyncConfig,
         log: slog::Logger,
-    ) -> error::Result<Sender<HandlerMessage>> {
+    ) -> (mpsc::Sender<HandlerMessage>, oneshot::Receiver<()>) {
         debug!(log, "Service starting");
 
-        let (handler_send, handler_recv) = channel();
+        let (handler_send, mut handler_recv) =
+            mpsc::channel(capacity(HANDLER_QUEUE_CAPACITY, network_config));
+        let (stopped_send, stopped) = oneshot::channel();
+        let network_config = network_config.clone();
+        let sync_config = sync_config.clone();
+        let handler_log = log.clone();
+        let new_handler = move || {
+            MessageHandler::new(
+                beacon_chain.clone(),
+                network_send.clone(),
+                peer_manager.clone(),
+                outbound_backlog.clone(),
+                &network_config,
+                &sync_config,
+                handler_log.clone(),
+            )
+        };
+
+        let mut handler = new_handler();
+        let mut shutting_down = false;
+        let mut expiry = Interval::new_interval(EXPIRY_INTERVAL);
+        let receive_loop = future::poll_fn(move || -> Result<Async<()>, ()> {
+            loop {
+                // timeouts are checked on a timer, as no messages may arrive whilst waiting for
+                // a response
+                if let Ok(Async::Ready(Some(_))) = expiry.poll() {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| handler.maintain()));
+                    if result.is_err() {
+                        error!(log, "Message handler panicked, restarting");
+                        metrics::MESSAGE_HANDLER_RESTARTS.inc();
+                        handler = restart(&new_handler, &log);
+                    }
+                    continue;
+                }
 
-        // Initialise sync and begin processing in thread
-        // generate the Message handler
-        let sync = SimpleSync::new(beacon_chain.clone(), &log);
+                let message = match handler_recv.poll() {
+                    Ok(Async::Ready(Some(HandlerMessage::Shutdown))) => {
+                        shutting_down = true;
+                        continue;
+                    }
+                    Ok(Async::Ready(Some(message))) => message,
+                    // once shutting down, the handler stops when no messages remain queued
+                    Ok(Async::NotReady) if shutting_down => {
+                        handler.shutdown();
+                        return Ok(Async::Ready(()));
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(None)) | Err(()) => {
+                        debug!(log, "Network message handler terminated.");
+                        return Ok(Async::Ready(()));
+                    }
+                };
 
-        let mut handler = MessageHandler {
+                // the state of a handler which panicked may be inconsistent, so it is discarded
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| handler.handle_message(message)));
+                if result.is_err() {
+                    error!(log, "Message handler panicked, restarting");
+                    metrics::MESSAGE_HANDLER_RESTARTS.inc();
+                    handler = restart(&new_handler, &log);
+                }
+            }
+        });
+
+        thread::Builder::new()
+            .name("message_handler".to_string())
+            .spawn(move || {
+                // the runtime drives the expiry timer of the handler
+                let _ = current_thread::block_on_all(receive_loop);
+                // the network service may not be waiting for the handler to stop
+                let _ = stopped_send.send(());
+            })
+            .expect("Unable to spawn the message handler thread");
+
+        (handler_send, stopped)
+    }
+
+    fn new(
+        beacon_chain: Arc<BeaconChain>,
+        network_send: mpsc::UnboundedSender<NetworkMessage>,
+        peer_manager: Arc<RwLock<PeerManager>>,
+        outbound_backlog: OutboundBacklog,
+        network_config: &NetworkConfig,
+        sync_config: &SyncConfig,
+        log: slog::Logger,
+    ) -> Self {
+        // Initialise sync
+        let sync = SimpleSync::new(beacon_chain.clone(), sync_config, &log);
+
+        MessageHandler {
             // TODO: The handler may not need a chain, perhaps only sync?
             chain: beacon_chain.clone(),
             sync,
+            peer_manager,
             network_send,
             requests: HashMap::new(),
+            unsent_requests: HashMap::new(),
             request_ids: HashMap::new(),
+            pending_requests: HashMap::new(),
+            max_requests_per_peer: network_config.max_requests_per_peer,
+            pending_hellos: HashMap::new(),
+            pending_pings: HashMap::new(),
+            bandwidth: BandwidthTracker::new(
+                network_config.peer_hourly_upload_limit,
+                network_config.peer_daily_upload_limit,
+            ),
+            rate_limiter: RateLimiter::default(),
+            seen_gossip: SeenCache::new(capacity(SEEN_CACHE_CAPACITY, network_config)),
+            metadata: local_metadata(network_config),
+            outbound_backlog,
 
-            log: log.clone(),
-        };
+            log,
+        }
+    }
 
-        // spawn handler task
-        // TODO: Handle manual termination of thread
-        executor.spawn(future::poll_fn(move || -> Result<_, _> {
-            loop {
-                handler.handle_message(handler_recv.recv().map_err(|_| {
-                    debug!(log, "Network message handler terminated.");
-                })?);
-            }
-        }));
+    /// Says GOODBYE to each peer, once the messages received before the shutdown are handled.
+    fn shutdown(&mut self) {
+        let peers = self.sync.known_peers();
+        debug!(self.log, "Message handler shutting down"; "peers" => peers.len());
+        for peer_id in peers {
+            self.network_send
+                .unbounded_send(NetworkMessage::Disconnect(
+                    peer_id,
+                    GoodbyeReason::ClientShutdown,
+                ))
+                .unwrap_or_else(|_| {
+                    warn!(self.log, "Could not send GOODBYE to the network service")
+                });
+        }
+    }
 
-        Ok(handler_send)
+    /// Sends a HELLO request to a peer, awaiting its response.
+    fn greet(&mut self, peer_id: PeerId) {
+        self.pending_hellos.insert(peer_id.clone(), Instant::now());
+        let id = self.generate_request_id(&peer_id, RPCMethod::Hello);
+        self.send_hello(peer_id, id, true);
     }
 
     /// Handle all messages incoming from the network service.
@@ -93,53 +309,566 @@ impl MessageHandler {
         match message {
             // we have initiated a connection to a peer
             HandlerMessage::PeerDialed(peer_id) => {
-                let id = self.generate_request_id(&peer_id);
-                self.send_hello(peer_id, id, true);
+                self.peer_manager.write().peer_connected(&peer_id);
+                self.greet(peer_id);
+            }
+            // the handler has restarted, and the peers it knew of are greeted again
+            HandlerMessage::ConnectedPeers(peers) => {
+                debug!(self.log, "Greeting connected peers after restart"; "peers" => peers.len());
+                for peer_id in peers {
+                    self.peer_manager.write().peer_connected(&peer_id);
+                    self.greet(peer_id);
+                }
+            }
+            // the connection to a peer has closed
+            HandlerMessage::PeerDisconnected(peer_id) => {
+                match self.sync.peer_summary(&peer_id) {
+                    Some(summary) => debug!(
+                        self.log,
+                        "Forgetting disconnected peer: {:?}", peer_id;
+                        "last_seen_slot" => format!("{:?}", summary.last_seen_slot),
+                        "blocks_served" => summary.blocks_served,
+                        "bytes_received" => summary.bytes_received,
+                        "bytes_sent" => summary.bytes_sent,
+                        "errors" => summary.errors,
+                    ),
+                    None => debug!(self.log, "Forgetting disconnected peer: {:?}", peer_id),
+                }
+                self.forget_peer(&peer_id);
+                self.peer_manager.write().peer_disconnected(&peer_id);
+                self.request_ids.remove(&peer_id);
+                // a batch being downloaded from the peer is requested from another
+                self.request_batches();
             }
             // we have received an RPC message request/response
-            HandlerMessage::RPC(peer_id, rpc_event) => {
-                self.handle_rpc_message(peer_id, rpc_event);
+            HandlerMessage::RPC(peer_id, rpc_event, len, trace_id) => {
+                self.handle_rpc_message(peer_id, rpc_event, len, trace_id);
+            }
+            HandlerMessage::PubsubMessage(peer_id, message, trace_id) => {
+                self.handle_gossip(peer_id, message, trace_id);
+            }
+            HandlerMessage::DecodeError {
+                peer_id,
+                message_type,
+                error_kind,
+            } => {
+                metrics::RPC_DECODE_ERRORS.inc();
+                self.peer_manager
+                    .write()
+                    .record_decode_error(&peer_id, &message_type, &error_kind);
+            }
+            HandlerMessage::OversizedMessage {
+                peer_id,
+                message_type,
+                len,
+                max_len,
+            } => {
+                metrics::RPC_DECODE_ERRORS.inc();
+                metrics::OVERSIZED_MESSAGES.inc();
+                debug!(
+                    self.log,
+                    "Rejected oversized compressed message. Peer: {:?}", peer_id;
+                    "message_type" => &message_type,
+                    "len" => len,
+                    "max_len" => max_len,
+                );
+                self.peer_manager
+                    .write()
+                    .record_oversized_message(&peer_id, &message_type);
+            }
+            HandlerMessage::GetSyncStatus(reply) => {
+                // the requester may have given up waiting
+                let _ = reply.send(self.sync.sync_status());
+            }
+            HandlerMessage::PauseSync => {
+                debug!(self.log, "Pausing sync");
+                self.sync.stop(StopReason::Paused);
+            }
+            HandlerMessage::ResumeSync => {
+                debug!(self.log, "Resuming sync");
+                self.sync.resume();
+                self.request_batches();
+            }
+            HandlerMessage::Heartbeat => {
+                self.ping_peers();
+                // held gossip blocks whose slot has started are imported
+                let penalties = self.sync.process_pending_blocks(TraceId::next());
+                self.penalize_all(penalties);
+                self.sync.log_progress();
+                self.bandwidth.prune(Instant::now());
+                self.peer_manager.write().prune();
+                // the clock may have run ahead of our head whilst no blocks were received
+                self.sync.check_clock();
+                // batches which have timed out are requested again
+                self.request_batches();
+            }
+            // handled by the receive loop of `spawn`
+            HandlerMessage::Shutdown => {}
+        }
+
+        self.maintain();
+    }
+
+    /// Expires requests and HELLOs which have timed out, acting on the resulting penalties, and
+    /// sends requests which were waiting for capacity.
+    fn maintain(&mut self) {
+        self.expire_requests();
+        self.expire_hellos();
+        self.refresh_hellos();
+        self.apply_peer_actions();
+        self.dispatch_pending_requests();
+    }
+
+    /* Peer management */
+
+    /// Removes requests which have not been responded to within `REQUEST_TIMEOUT` of being sent,
+    /// penalizing the peers they were sent to. Time spent in the network service's queue is not
+    /// counted against the peer, and PINGs are accounted for by `ping_peers` instead. Sync is
+    /// told of its requests which expired, as their responses are no longer accepted.
+    fn expire_requests(&mut self) {
+        let outbound_backlog = &self.outbound_backlog;
+        let requests = &mut self.requests;
+        self.unsent_requests.retain(|key, number| {
+            if !outbound_backlog.is_sent(*number) {
+                return requests.contains_key(key);
+            }
+            if let Some((_, sent)) = requests.get_mut(key) {
+                *sent = Instant::now();
+            }
+            false
+        });
+
+        let unsent_requests = &self.unsent_requests;
+        let expired: Vec<(PeerId, u64)> = self
+            .requests
+            .iter()
+            .filter(|(key, (_, sent))| {
+                !unsent_requests.contains_key(*key) && sent.elapsed() > REQUEST_TIMEOUT
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for (peer_id, id) in expired {
+            let method = self
+                .requests
+                .remove(&(peer_id.clone(), id))
+                .map(|(method, _)| method);
+            debug!(
+                self.log,
+                "RPC request {} timed out. Peer: {:?}", id, peer_id
+            );
+            if method != Some(RPCMethod::Ping) {
+                self.peer_manager.write().penalize(
+                    &peer_id,
+                    REQUEST_TIMEOUT_PENALTY,
+                    "request timed out",
+                );
+            }
+            if method.map_or(false, is_sync_method) {
+                self.sync.on_request_failed(&peer_id);
+            }
+        }
+    }
+
+    /// Disconnects peers which have not completed the HELLO handshake within `HELLO_TIMEOUT`,
+    /// penalizing them.
+    fn expire_hellos(&mut self) {
+        let expired: Vec<PeerId> = self
+            .pending_hellos
+            .iter()
+            .filter(|(_, dialed)| dialed.elapsed() > HELLO_TIMEOUT)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        for peer_id in expired {
+            self.pending_hellos.remove(&peer_id);
+            debug!(
+                self.log,
+                "Peer did not identify itself in time. Peer: {:?}", peer_id
+            );
+            let mut peer_manager = self.peer_manager.write();
+            peer_manager.disconnect(&peer_id, GoodbyeReason::Fault);
+            peer_manager.penalize(&peer_id, HELLO_TIMEOUT_PENALTY, "no HELLO");
+        }
+    }
+
+    /// Sends a HELLO to each known peer if our finalized epoch has advanced, so the sync info
+    /// peers hold about us stays current. Peers doing the same update `known_peers` through
+    /// `handle_hello_request`.
+    fn refresh_hellos(&mut self) {
+        if !self.sync.update_finalized_epoch() {
+            return;
+        }
+
+        for peer_id in self.sync.known_peers() {
+            let id = self.generate_request_id(&peer_id, RPCMethod::Hello);
+            self.send_hello(peer_id, id, true);
+        }
+    }
+
+    /// Sends a PING to each known peer, recording a missed PING against peers which have not
+    /// responded to the previous one. The missed PING replaces the request's timeout. Peers with
+    /// `max_requests_per_peer` requests in flight are not sent another.
+    fn ping_peers(&mut self) {
+        for peer_id in self.sync.known_peers() {
+            if let Some(id) = self.pending_pings.remove(&peer_id) {
+                metrics::PINGS_MISSED.inc();
+                self.peer_manager.write().record_missed_ping(&peer_id);
+                self.requests.remove(&(peer_id.clone(), id));
+            }
+            if self.requests_in_flight(&peer_id) >= self.max_requests_per_peer {
+                continue;
+            }
+
+            let id = self.generate_request_id(&peer_id, RPCMethod::Ping);
+            self.pending_pings.insert(peer_id.clone(), id);
+            self.send_rpc(
+                peer_id,
+                RPCEvent::Request {
+                    id,
+                    method_id: RPCMethod::Ping.into(),
+                    body: RPCRequest::Ping(Ping { data: id }),
+                },
+            );
+        }
+    }
+
+    /// Disconnects or bans peers as decided by the peer manager.
+    fn apply_peer_actions(&mut self) {
+        let actions = self.peer_manager.write().drain_actions();
+
+        for (peer_id, action) in actions {
+            self.forget_peer(&peer_id);
+
+            let message = match action {
+                PeerAction::Disconnect(reason) => NetworkMessage::Disconnect(peer_id, reason),
+                PeerAction::Ban(duration, reason) => NetworkMessage::Ban(peer_id, duration, reason),
+            };
+            self.network_send
+                .unbounded_send(message)
+                .unwrap_or_else(|_| {
+                    warn!(
+                        self.log,
+                        "Could not send peer action to the network service"
+                    )
+                });
+        }
+    }
+
+    /// Forgets all state held about a peer which is no longer connected.
+    fn forget_peer(&mut self, peer_id: &PeerId) {
+        self.sync.remove_peer(peer_id);
+        self.pending_hellos.remove(peer_id);
+        self.pending_pings.remove(peer_id);
+        self.pending_requests.remove(peer_id);
+        self.rate_limiter.remove_peer(peer_id);
+        self.requests
+            .retain(|(request_peer, _), _| request_peer != peer_id);
+        self.unsent_requests
+            .retain(|(request_peer, _), _| request_peer != peer_id);
+    }
+
+    /* Gossip - Related functionality */
+
+    /// Handle a message received over gossipsub, telling the network service whether to forward
+    /// it to our other peers.
+    ///
+    /// Only blocks which are imported and attestations which are valid against our state are
+    /// forwarded, so that invalid messages do not travel beyond the peer that sent them.
+    fn handle_gossip(&mut self, peer_id: PeerId, message: PubsubMessage, trace_id: TraceId) {
+        let message = if self.process_gossip(peer_id, message, trace_id) {
+            NetworkMessage::Propagate(trace_id)
+        } else {
+            NetworkMessage::Discard(trace_id)
+        };
+        self.network_send
+            .unbounded_send(message)
+            .unwrap_or_else(|_| {
+                warn!(
+                    self.log,
+                    "Could not send gossip validation result to the network service"
+                )
+            });
+    }
+
+    /// Processes a message received over gossipsub, returning `true` if it is valid.
+    ///
+    /// Messages which have already been received from another peer are dropped, as are
+    /// attestations and blocks too old to be of use and attestations for a slot yet to start,
+    /// see `gossip_validation`. The peer is not penalized for a stale message, which may only
+    /// have been delayed.
+    fn process_gossip(
+        &mut self,
+        peer_id: PeerId,
+        message: PubsubMessage,
+        trace_id: TraceId,
+    ) -> bool {
+        let root = match &message {
+            PubsubMessage::Block(block) => block.hash_tree_root(),
+            PubsubMessage::Attestation(attestation) => attestation.hash_tree_root(),
+        };
+        if self.seen_gossip.observe(Hash256::from_slice(&root)) {
+            trace!(
+                self.log,
+                "Dropping duplicate gossip message. Peer: {:?}", peer_id;
+                "trace_id" => format!("{}", trace_id),
+            );
+            metrics::GOSSIP_MESSAGES_DUPLICATE.inc();
+            return false;
+        }
+        metrics::GOSSIP_MESSAGES_NEW.inc();
+
+        let validation = match &message {
+            PubsubMessage::Block(block) => {
+                validate_gossip_block(block.slot, self.chain.finalized_head().beacon_block.slot)
+            }
+            PubsubMessage::Attestation(attestation) => {
+                validate_gossip_attestation(attestation.data.slot, self.chain.present_slot())
+            }
+        };
+        if let Err(e) = validation {
+            debug!(
+                self.log,
+                "Dropping untimely gossip message. Peer: {:?}", peer_id;
+                "error" => format!("{:?}", e),
+                "trace_id" => format!("{}", trace_id),
+            );
+            metrics::GOSSIP_MESSAGES_STALE.inc();
+            return false;
+        }
+
+        match message {
+            PubsubMessage::Block(block) => {
+                let block_root = block.canonical_root();
+                // held blocks imported with this one may have been sent by other peers
+                let penalties = self.sync.on_gossip_block(&peer_id, block, trace_id);
+                self.penalize_all(penalties);
+                // the block may have started a parent lookup
+                self.request_batches();
+                // a block held for its parent or slot is not yet known to be valid
+                self.chain.is_block_known(&block_root)
+            }
+            PubsubMessage::Attestation(attestation) => {
+                // attestations may be invalid only because our state differs from the peer's, so
+                // the peer is not penalized
+                match self.chain.process_attestation(attestation) {
+                    Ok(added) => {
+                        trace!(
+                            self.log,
+                            "Gossip attestation processed. Peer: {:?}", peer_id;
+                            "added_to_pool" => added,
+                            "trace_id" => format!("{}", trace_id),
+                        );
+                        true
+                    }
+                    Err(e) => {
+                        debug!(
+                            self.log,
+                            "Gossip attestation rejected. Peer: {:?}", peer_id;
+                            "error" => format!("{:?}", e),
+                            "trace_id" => format!("{}", trace_id),
+                        );
+                        false
+                    }
+                }
             }
-            //TODO: Handle all messages
-            _ => {}
         }
     }
 
     /* RPC - Related functionality */
 
-    /// Handle RPC messages
-    fn handle_rpc_message(&mut self, peer_id: PeerId, rpc_message: RPCEvent) {
+    /// Handle RPC messages, of `len` bytes on the wire.
+    fn handle_rpc_message(
+        &mut self,
+        peer_id: PeerId,
+        rpc_message: RPCEvent,
+        len: usize,
+        trace_id: TraceId,
+    ) {
+        if let RPCEvent::Response { .. } = rpc_message {
+            self.sync.on_bytes_received(&peer_id, len as u64);
+        }
         match rpc_message {
             RPCEvent::Request { id, body, .. // TODO: Clean up RPC Message types, have a cleaner type by this point.
             } => self.handle_rpc_request(peer_id, id, body),
-            RPCEvent::Response { id, result, .. } => self.handle_rpc_response(peer_id, id, result),
+            RPCEvent::Response { id, result, .. } => {
+                self.handle_rpc_response(peer_id, id, result, trace_id)
+            }
         }
     }
 
     /// A new RPC request has been received from the network.
+    ///
+    /// Requests in excess of the peer's request rate are refused and the peer penalized.
     fn handle_rpc_request(&mut self, peer_id: PeerId, id: u64, request: RPCRequest) {
+        if !self.rate_limiter.allows(&peer_id, request.method_id()) {
+            debug!(
+                self.log,
+                "Refusing rate limited request. Peer: {:?}", peer_id;
+                "method_id" => request.method_id(),
+            );
+            metrics::RPC_REQUESTS_RATE_LIMITED.inc();
+            self.peer_manager
+                .write()
+                .penalize(&peer_id, RATE_LIMIT_PENALTY, "rate limited");
+            self.send_rpc_error(
+                peer_id,
+                id,
+                error_codes::RESOURCE_UNAVAILABLE,
+                "rate limited",
+            );
+            return;
+        }
+
         match request {
             RPCRequest::Hello(hello_message) => {
                 self.handle_hello_request(peer_id, id, hello_message)
             }
-            // TODO: Handle all requests
-            _ => {}
+            RPCRequest::BeaconBlockRoots(request) => {
+                self.handle_beacon_block_roots_request(peer_id, id, request)
+            }
+            RPCRequest::BeaconBlockHeaders(request) => {
+                self.handle_beacon_block_headers_request(peer_id, id, request)
+            }
+            RPCRequest::BeaconBlockBodies(request) => {
+                self.handle_beacon_block_bodies_request(peer_id, id, request)
+            }
+            RPCRequest::BeaconAttestations(request) => {
+                self.handle_beacon_attestations_request(peer_id, id, request)
+            }
+            RPCRequest::Goodbye(reason) => self.handle_goodbye(peer_id, reason),
+            RPCRequest::Metadata => {
+                let metadata = self.metadata.clone();
+                self.send_rpc_response(
+                    peer_id,
+                    RPCEvent::Response {
+                        id,
+                        method_id: RPCMethod::Metadata.into(),
+                        result: RPCResponse::Metadata(metadata),
+                    },
+                )
+            }
+            RPCRequest::Ping(ping) => self.send_rpc_response(
+                peer_id,
+                RPCEvent::Response {
+                    id,
+                    method_id: RPCMethod::Ping.into(),
+                    result: RPCResponse::Ping(ping),
+                },
+            ),
+            RPCRequest::BeaconChainState(_) => self.send_rpc_error(
+                peer_id,
+                id,
+                error_codes::UNSUPPORTED,
+                "BeaconChainState is not supported",
+            ),
         }
     }
 
     /// An RPC response has been received from the network.
     // we match on id and ignore responses past the timeout.
-    fn handle_rpc_response(&mut self, peer_id: PeerId, id: u64, response: RPCResponse) {
+    fn handle_rpc_response(
+        &mut self,
+        peer_id: PeerId,
+        id: u64,
+        response: RPCResponse,
+        trace_id: TraceId,
+    ) {
         // if response id is related to a request, ignore (likely RPC timeout)
-        if self.requests.remove(&(peer_id.clone(), id)).is_none() {
-            debug!(self.log, "Unrecognized response from peer: {:?}", peer_id);
+        let (method, sent) = match self.requests.remove(&(peer_id.clone(), id)) {
+            Some(request) => request,
+            None => {
+                debug!(self.log, "Unrecognized response from peer: {:?}", peer_id);
+                return;
+            }
+        };
+        // an error may respond to any request, other responses must be of the method requested
+        let response_method = response.method();
+        if response_method != method && response_method != RPCMethod::Error {
+            debug!(
+                self.log,
+                "Mismatched response to RPC request {} from peer: {:?}", id, peer_id;
+                "request" => format!("{:?}", method),
+                "response" => format!("{:?}", response_method),
+            );
+            metrics::RPC_RESPONSES_MISMATCHED.inc();
+            self.peer_manager.write().penalize(
+                &peer_id,
+                MISMATCHED_RESPONSE_PENALTY,
+                "mismatched response",
+            );
+            if is_sync_method(method) {
+                self.sync.on_request_failed(&peer_id);
+                self.request_batches();
+            }
             return;
         }
         match response {
+            RPCResponse::Ping(ping) => self.handle_pong(peer_id, id, ping, sent),
+            RPCResponse::Metadata(metadata) => {
+                trace!(
+                    self.log,
+                    "Metadata response received from peer: {:?}", peer_id;
+                    "seq_number" => metadata.seq_number,
+                );
+                self.peer_manager
+                    .write()
+                    .record_metadata(&peer_id, metadata);
+            }
             RPCResponse::Hello(hello_message) => {
                 debug!(self.log, "Hello response received from peer: {:?}", peer_id);
                 self.validate_hello(peer_id, hello_message);
             }
+            RPCResponse::BeaconBlockRoots(response) => {
+                match self.sync.on_beacon_block_roots_response(&peer_id, response) {
+                    Some(request) => {
+                        self.send_rpc_request(peer_id, RPCRequest::BeaconBlockHeaders(request))
+                    }
+                    None => self.request_batches(),
+                }
+            }
+            RPCResponse::BeaconBlockHeaders(response) => {
+                match self
+                    .sync
+                    .on_beacon_block_headers_response(&peer_id, response)
+                {
+                    Some(request) => {
+                        self.send_rpc_request(peer_id, RPCRequest::BeaconBlockBodies(request))
+                    }
+                    None => self.request_batches(),
+                }
+            }
+            RPCResponse::BeaconAttestations(response) => {
+                self.handle_beacon_attestations_response(peer_id, response)
+            }
+            RPCResponse::Error { code, message } => {
+                debug!(
+                    self.log,
+                    "RPC request {} refused by peer: {:?}", id, peer_id;
+                    "code" => code,
+                    "message" => message,
+                );
+                metrics::RPC_ERROR_RESPONSES.inc();
+                if is_sync_method(method) {
+                    self.sync.on_request_failed(&peer_id);
+                    self.request_batches();
+                }
+            }
+            RPCResponse::BeaconBlockBodies(response) => {
+                debug!(
+                    self.log,
+                    "BeaconBlockBodies response received from peer: {:?}", peer_id;
+                    "bodies" => response.block_bodies.len(),
+                    "trace_id" => format!("{}", trace_id),
+                );
+                let penalties = self
+                    .sync
+                    .on_beacon_block_bodies_response(&peer_id, response, trace_id);
+                // blocks of a batch may be imported once a later response completes the batches
+                // before it, so the peer penalized may not be the responder
+                self.penalize_all(penalties);
+                self.request_batches();
+            }
             // TODO: Handle all responses
             _ => {}
         }
@@ -153,22 +882,426 @@ impl MessageHandler {
         self.validate_hello(peer_id, hello_message);
     }
 
+    /// Handle a PING response, recording the round-trip time of the request sent at `sent`.
+    fn handle_pong(&mut self, peer_id: PeerId, id: u64, ping: Ping, sent: Instant) {
+        if self.pending_pings.get(&peer_id) != Some(&id) || ping.data != id {
+            debug!(
+                self.log,
+                "Unexpected PING response from peer: {:?}", peer_id
+            );
+            return;
+        }
+        self.pending_pings.remove(&peer_id);
+
+        let latency = sent.elapsed();
+        trace!(
+            self.log,
+            "PING response received from peer: {:?}", peer_id;
+            "latency_ms" => latency.as_millis() as u64,
+        );
+        metrics::PING_RESPONSES.inc();
+        metrics::PING_ROUND_TRIP_MILLIS.inc_by(latency.as_millis() as usize);
+        self.peer_manager.write().record_latency(&peer_id, latency);
+        self.sync.update_latency(&peer_id, latency);
+    }
+
+    /// Handle a Goodbye RPC request, forgetting the departing peer.
+    fn handle_goodbye(&mut self, peer_id: PeerId, reason: GoodbyeReason) {
+        debug!(
+            self.log,
+            "Peer said goodbye. Peer: {:?}", peer_id;
+            "reason" => format!("{:?}", reason),
+        );
+        self.forget_peer(&peer_id);
+    }
+
+    /// Handle a BeaconBlockRoots RPC request, responding with the roots of our canonical blocks
+    /// in the requested range.
+    ///
+    /// If we know of no blocks in the range, an empty list of roots is returned.
+    fn handle_beacon_block_roots_request(
+        &mut self,
+        peer_id: PeerId,
+        id: u64,
+        request: BeaconBlockRootsRequest,
+    ) {
+        if self.is_throttled(&peer_id, id, RPCMethod::BeaconBlockRoots) {
+            return;
+        }
+
+        let genesis_slot = self.chain.get_spec().genesis_slot;
+        let request = match validate_block_roots_request(request, genesis_slot) {
+            Ok(request) => request,
+            Err(e) => {
+                self.reject_range_request(peer_id, id, RPCMethod::BeaconBlockRoots, e);
+                return;
+            }
+        };
+
+        let roots: Vec<BlockRootSlot> = self
+            .chain
+            .get_block_roots(request.start_slot, request.count)
+            .into_iter()
+            .map(|(block_root, slot)| BlockRootSlot { block_root, slot })
+            .collect();
+
+        if roots.is_empty() {
+            debug!(
+                self.log,
+                "BeaconBlockRoots request for unknown range. Peer: {:?}", peer_id;
+                "start_slot" => request.start_slot.as_u64(),
+                "count" => request.count,
+            );
+        } else {
+            trace!(
+                self.log,
+                "Serving BeaconBlockRoots. Peer: {:?}", peer_id;
+                "roots" => roots.len(),
+            );
+        }
+
+        self.send_rpc_response(
+            peer_id,
+            RPCEvent::Response {
+                id,
+                method_id: RPCMethod::BeaconBlockRoots.into(),
+                result: RPCResponse::BeaconBlockRoots(BeaconBlockRootsResponse { roots }),
+            },
+        );
+    }
+
+    /// Handle a BeaconBlockHeaders RPC request, responding with the headers of our canonical
+    /// blocks from the requested slot.
+    ///
+    /// If the block at the start slot does not match the requested root, an error is returned.
+    fn handle_beacon_block_headers_request(
+        &mut self,
+        peer_id: PeerId,
+        id: u64,
+        request: BeaconBlockHeadersRequest,
+    ) {
+        if self.is_throttled(&peer_id, id, RPCMethod::BeaconBlockHeaders)
+            || self.is_backlogged(&peer_id, id, RPCMethod::BeaconBlockHeaders)
+        {
+            return;
+        }
+
+        let genesis_slot = self.chain.get_spec().genesis_slot;
+        let request = match validate_block_headers_request(request, genesis_slot) {
+            Ok(request) => request,
+            Err(e) => {
+                self.reject_range_request(peer_id, id, RPCMethod::BeaconBlockHeaders, e);
+                return;
+            }
+        };
+
+        let headers = self.chain.get_block_headers(
+            request.start_slot,
+            request.max_headers,
+            request.skip_slots,
+        );
+
+        if headers.first().map_or(false, |header| {
+            header.canonical_root() != request.start_root
+        }) {
+            debug!(
+                self.log,
+                "BeaconBlockHeaders request does not match our chain. Peer: {:?}", peer_id;
+                "start_slot" => request.start_slot.as_u64(),
+            );
+            self.send_rpc_error(
+                peer_id,
+                id,
+                error_codes::UNKNOWN_ROOT,
+                "start root is not on our chain",
+            );
+            return;
+        }
+
+        trace!(
+            self.log,
+            "Serving BeaconBlockHeaders. Peer: {:?}", peer_id;
+            "headers" => headers.len(),
+        );
+
+        self.send_rpc_response(
+            peer_id,
+            RPCEvent::Response {
+                id,
+                method_id: RPCMethod::BeaconBlockHeaders.into(),
+                result: RPCResponse::BeaconBlockHeaders(BeaconBlockHeadersResponse { headers }),
+            },
+        );
+    }
+
+    /// Handle a BeaconBlockBodies RPC request, responding with the bodies of the requested blocks
+    /// that we know of.
+    fn handle_beacon_block_bodies_request(
+        &mut self,
+        peer_id: PeerId,
+        id: u64,
+        request: BeaconBlockBodiesRequest,
+    ) {
+        if self.is_throttled(&peer_id, id, RPCMethod::BeaconBlockBodies)
+            || self.is_backlogged(&peer_id, id, RPCMethod::BeaconBlockBodies)
+        {
+            return;
+        }
+
+        let request = match validate_block_bodies_request(request) {
+            Ok(request) => request,
+            Err(e) => {
+                self.reject_range_request(peer_id, id, RPCMethod::BeaconBlockBodies, e);
+                return;
+            }
+        };
+
+        let block_bodies = self.chain.get_block_bodies(&request.block_roots);
+
+        trace!(
+            self.log,
+            "Serving BeaconBlockBodies. Peer: {:?}", peer_id;
+            "requested" => request.block_roots.len(),
+            "bodies" => block_bodies.len(),
+        );
+
+        self.send_rpc_response(
+            peer_id,
+            RPCEvent::Response {
+                id,
+                method_id: RPCMethod::BeaconBlockBodies.into(),
+                result: RPCResponse::BeaconBlockBodies(BeaconBlockBodiesResponse { block_bodies }),
+            },
+        );
+    }
+
+    /// Handle a BeaconAttestations request, serving the recent aggregate attestations of the
+    /// attestation pool.
+    fn handle_beacon_attestations_request(
+        &mut self,
+        peer_id: PeerId,
+        id: u64,
+        request: BeaconAttestationsRequest,
+    ) {
+        if self.is_throttled(&peer_id, id, RPCMethod::BeaconAttestations)
+            || self.is_backlogged(&peer_id, id, RPCMethod::BeaconAttestations)
+        {
+            return;
+        }
+
+        let max = std::cmp::min(request.max_attestations, MAX_REQUESTED_ATTESTATIONS);
+        let attestations = self
+            .chain
+            .get_pool_attestations(request.start_slot, max as usize);
+
+        trace!(
+            self.log,
+            "Serving BeaconAttestations. Peer: {:?}", peer_id;
+            "start_slot" => request.start_slot.as_u64(),
+            "attestations" => attestations.len(),
+        );
+
+        self.send_rpc_response(
+            peer_id,
+            RPCEvent::Response {
+                id,
+                method_id: RPCMethod::BeaconAttestations.into(),
+                result: RPCResponse::BeaconAttestations(BeaconAttestationsResponse {
+                    attestations,
+                }),
+            },
+        );
+    }
+
+    /// Handle a BeaconAttestations response, adding the attestations requested to the attestation
+    /// pool.
+    ///
+    /// As with gossip, attestations may be invalid only because our state differs from the
+    /// peer's, for which the peer is not penalized. The peer is penalized for an attestation which
+    /// is invalid on any chain, and the rest of the response is dropped.
+    fn handle_beacon_attestations_response(
+        &mut self,
+        peer_id: PeerId,
+        response: BeaconAttestationsResponse,
+    ) {
+        let received = response.attestations.len();
+        let attestations = self
+            .sync
+            .on_beacon_attestations_response(&peer_id, response);
+        let requested = attestations.len();
+
+        let mut added = 0;
+        for attestation in attestations {
+            match self.chain.process_attestation(attestation) {
+                Ok(true) => added += 1,
+                Ok(false) => {}
+                Err(ref e) if e.is_state_dependent() => {}
+                Err(e) => {
+                    debug!(
+                        self.log,
+                        "Invalid attestation received. Peer: {:?}", peer_id;
+                        "error" => format!("{:?}", e),
+                    );
+                    self.peer_manager.write().penalize(
+                        &peer_id,
+                        INVALID_ATTESTATION_PENALTY,
+                        "invalid attestation",
+                    );
+                    break;
+                }
+            }
+        }
+        metrics::SYNC_ATTESTATIONS_IMPORTED.inc_by(added);
+
+        debug!(
+            self.log,
+            "BeaconAttestations response received from peer: {:?}", peer_id;
+            "received" => received,
+            "requested" => requested,
+            "added_to_pool" => added,
+        );
+    }
+
     /// Validate a HELLO RPC message.
     fn validate_hello(&mut self, peer_id: PeerId, message: HelloMessage) {
+        // the peer has identified itself
+        self.pending_hellos.remove(&peer_id);
+
+        // a peer whose finalized checkpoint goes backwards is penalized and not synced from
+        if !self.peer_manager.write().record_hello(&peer_id, &message) {
+            debug!(
+                self.log,
+                "Peer ignored due to a regressing finalized checkpoint: {:?}", peer_id
+            );
+            return;
+        }
+
         // validate the peer
         if !self.sync.validate_peer(peer_id.clone(), message) {
             debug!(
                 self.log,
                 "Peer dropped due to mismatching HELLO messages: {:?}", peer_id
             );
-            //TODO: block/ban the peer
+            let mut peer_manager = self.peer_manager.write();
+            peer_manager.disconnect(&peer_id, GoodbyeReason::IrrelevantNetwork);
+            peer_manager.penalize(&peer_id, BAD_HELLO_PENALTY, "irrelevant network");
+            return;
+        }
+
+        // learn which attestation subnets the peer subscribes to
+        self.send_rpc_request(peer_id, RPCRequest::Metadata);
+        self.request_batches();
+    }
+
+    /// Requests batches of blocks from idle peers if syncing requires them, the ancestors of
+    /// gossip blocks whose parent is unknown, historical blocks to backfill, and the recent
+    /// attestations of peers once syncing finishes.
+    fn request_batches(&mut self) {
+        for (peer_id, request) in self.sync.next_batches() {
+            self.send_rpc_request(peer_id, RPCRequest::BeaconBlockRoots(request));
+        }
+        for (peer_id, request) in self.sync.next_parent_lookups() {
+            self.send_rpc_request(peer_id, request);
+        }
+        if let Some((peer_id, request)) = self.sync.next_backfill() {
+            self.send_rpc_request(peer_id, RPCRequest::BeaconBlockRoots(request));
+        }
+        if self.sync.wants_attestations() {
+            let subnet_counts = self.subnet_counts();
+            for (peer_id, request) in self.sync.next_attestation_requests(&subnet_counts) {
+                self.send_rpc_request(peer_id, RPCRequest::BeaconAttestations(request));
+            }
+        }
+    }
+
+    /// Returns the number of attestation subnets each peer advertises in its metadata.
+    fn subnet_counts(&self) -> HashMap<PeerId, usize> {
+        let peer_manager = self.peer_manager.read();
+        let mut subnet_counts = HashMap::new();
+        for subnet in 0..ATTESTATION_SUBNET_COUNT {
+            for peer_id in peer_manager.peers_on_subnet(subnet) {
+                *subnet_counts.entry(peer_id).or_insert(0) += 1;
+            }
+        }
+        subnet_counts
+    }
+
+    /// Applies the score penalties of peers which provided invalid blocks.
+    fn penalize_all(&self, penalties: Vec<(PeerId, i64)>) {
+        if penalties.is_empty() {
+            return;
+        }
+        let mut peer_manager = self.peer_manager.write();
+        for (peer_id, penalty) in penalties {
+            peer_manager.penalize(&peer_id, penalty, "invalid block");
         }
     }
 
     /* General RPC helper functions */
 
-    /// Generates a new request id for a peer.
-    fn generate_request_id(&mut self, peer_id: &PeerId) -> u64 {
+    /// Returns `true` if the peer has exceeded its upload limit, in which case the request `id`
+    /// for `method` has been refused.
+    fn is_throttled(&mut self, peer_id: &PeerId, id: u64, method: RPCMethod) -> bool {
+        if self.bandwidth.can_serve(peer_id) {
+            return false;
+        }
+        debug!(
+            self.log,
+            "Peer exceeded its upload limit, {:?} request refused: {:?}", method, peer_id
+        );
+        metrics::RPC_REQUESTS_THROTTLED.inc();
+        self.send_rpc_error(
+            peer_id.clone(),
+            id,
+            error_codes::RESOURCE_UNAVAILABLE,
+            "upload limit exceeded",
+        );
+        true
+    }
+
+    /// Returns `true` if the network service's queue of bulk responses is full, in which case
+    /// the request `id` for `method` has been refused. The peer may retry it with another peer.
+    fn is_backlogged(&mut self, peer_id: &PeerId, id: u64, method: RPCMethod) -> bool {
+        if !self.outbound_backlog.is_full(Priority::Bulk) {
+            return false;
+        }
+        debug!(
+            self.log,
+            "Outbound queue full, {:?} request refused: {:?}", method, peer_id
+        );
+        metrics::RPC_REQUESTS_BACKLOGGED.inc();
+        self.send_rpc_error(
+            peer_id.clone(),
+            id,
+            error_codes::RESOURCE_UNAVAILABLE,
+            "too busy",
+        );
+        true
+    }
+
+    /// Refuses the invalid block range request `id`, penalizing the peer.
+    fn reject_range_request(
+        &mut self,
+        peer_id: PeerId,
+        id: u64,
+        method: RPCMethod,
+        error: RangeRequestError,
+    ) {
+        debug!(
+            self.log,
+            "Invalid {:?} request. Peer: {:?}", method, peer_id;
+            "error" => format!("{:?}", error),
+            "code" => error.code(),
+        );
+        metrics::RPC_REQUESTS_INVALID.inc();
+        self.peer_manager
+            .write()
+            .penalize(&peer_id, INVALID_REQUEST_PENALTY, "invalid request");
+        self.send_rpc_error(peer_id, id, error.code(), &format!("{:?}", error));
+    }
+
+    /// Generates a new request id for a peer, registering a request of `method`.
+    fn generate_request_id(&mut self, peer_id: &PeerId, method: RPCMethod) -> u64 {
         // generate a unique id for the peer
         let id = {
             let borrowed_id = self.request_ids.entry(peer_id.clone()).or_insert_with(|| 0);
@@ -178,10 +1311,11 @@ impl MessageHandler {
             id
         };
         // register RPC request
-        self.requests.insert((peer_id.clone(), id), Instant::now());
+        self.requests
+            .insert((peer_id.clone(), id), (method, Instant::now()));
         debug!(
             self.log,
-            "Hello request registered with peer: {:?}", peer_id
+            "RPC request {} registered with peer: {:?}", id, peer_id
         );
         id
     }
@@ -205,15 +1339,122 @@ impl MessageHandler {
 
         // send the hello request to the network
         trace!(self.log, "Sending HELLO message to peer {:?}", peer_id);
+        if is_request {
+            self.send_rpc(peer_id, rpc_event);
+        } else {
+            self.send_rpc_response(peer_id, rpc_event);
+        }
+    }
+
+    /// Sends an RPC request to a peer, or queues it if the peer already has
+    /// `max_requests_per_peer` requests in flight or the network service's queue is full.
+    fn send_rpc_request(&mut self, peer_id: PeerId, body: RPCRequest) {
+        if self.requests_in_flight(&peer_id) >= self.max_requests_per_peer
+            || self.outbound_backlog.is_full(Priority::Normal)
+        {
+            trace!(
+                self.log,
+                "Queuing RPC request to busy peer: {:?}", peer_id;
+                "method_id" => body.method_id(),
+            );
+            self.pending_requests
+                .entry(peer_id)
+                .or_insert_with(VecDeque::new)
+                .push_back(body);
+            return;
+        }
+        self.dispatch_rpc_request(peer_id, body);
+    }
+
+    /// Sends queued requests to peers which have completed, or timed out on, earlier requests,
+    /// whilst the network service's queue has room for them.
+    fn dispatch_pending_requests(&mut self) {
+        let peers: Vec<PeerId> = self.pending_requests.keys().cloned().collect();
+
+        for peer_id in peers {
+            while self.requests_in_flight(&peer_id) < self.max_requests_per_peer
+                && !self.outbound_backlog.is_full(Priority::Normal)
+            {
+                let body = match self
+                    .pending_requests
+                    .get_mut(&peer_id)
+                    .and_then(VecDeque::pop_front)
+                {
+                    Some(body) => body,
+                    None => break,
+                };
+                self.dispatch_rpc_request(peer_id.clone(), body);
+            }
+            if self
+                .pending_requests
+                .get(&peer_id)
+                .map_or(false, VecDeque::is_empty)
+            {
+                self.pending_requests.remove(&peer_id);
+            }
+        }
+    }
+
+    /// Returns the number of our requests awaiting a response from a peer.
+    fn requests_in_flight(&self, peer_id: &PeerId) -> usize {
+        self.requests
+            .keys()
+            .filter(|(request_peer, _)| request_peer == peer_id)
+            .count()
+    }
+
+    /// Sends an RPC request to a peer, registering a new request id.
+    fn dispatch_rpc_request(&mut self, peer_id: PeerId, body: RPCRequest) {
+        let id = self.generate_request_id(&peer_id, body.method());
+        self.unsent_requests
+            .insert((peer_id.clone(), id), self.outbound_backlog.next_request());
+        let rpc_event = RPCEvent::Request {
+            id,
+            method_id: body.method_id(),
+            body,
+        };
         self.send_rpc(peer_id, rpc_event);
     }
 
+    /// Sends an RPC response to the network server, accounting for the bytes served to the peer.
+    fn send_rpc_response(&mut self, peer_id: PeerId, rpc_event: RPCEvent) {
+        let bytes = ssz_encode(&rpc_event).len() as u64;
+        if let RPCEvent::Response { method_id, .. } = rpc_event {
+            self.bandwidth.record(&peer_id, method_id, bytes);
+            self.sync.on_bytes_sent(&peer_id, bytes);
+            metrics::RPC_BYTES_SERVED.inc_by(bytes as usize);
+        }
+        self.send_encoded_rpc(peer_id, rpc_event, bytes);
+    }
+
+    /// Responds to the request `id` with an error, explaining why it was not served.
+    fn send_rpc_error(&mut self, peer_id: PeerId, id: u64, code: u64, message: &str) {
+        self.send_rpc_response(
+            peer_id,
+            RPCEvent::Response {
+                id,
+                method_id: RPCMethod::Error.into(),
+                result: RPCResponse::Error {
+                    code,
+                    message: message.to_string(),
+                },
+            },
+        );
+    }
+
     /// Sends an RPC request/response to the network server.
     fn send_rpc(&self, peer_id: PeerId, rpc_event: RPCEvent) {
+        let len = ssz_encode(&rpc_event).len() as u64;
+        self.send_encoded_rpc(peer_id, rpc_event, len);
+    }
+
+    /// Sends an RPC request/response whose SSZ encoding is `len` bytes to the network server,
+    /// which uses the length to limit the upload rate without encoding the message again.
+    fn send_encoded_rpc(&self, peer_id: PeerId, rpc_event: RPCEvent, len: u64) {
         self.network_send
-            .send(NetworkMessage::Send(
+            .unbounded_send(NetworkMessage::Send(
                 peer_id,
-                OutgoingMessage::RPC(rpc_event),
+                OutgoingMessage::RPC(rpc_event, len),
             ))
             .unwrap_or_else(|_| {
                 warn!(
@@ -223,3 +1464,68 @@ impl MessageHandler {
             });
     }
 }
+
+/// Returns `true` if requests of `method` are made by sync to download blocks, so that sync is
+/// told when they fail. Other requests have no part in its batches or lookups.
+fn is_sync_method(method: RPCMethod) -> bool {
+    match method {
+        RPCMethod::BeaconBlockRoots
+        | RPCMethod::BeaconBlockHeaders
+        | RPCMethod::BeaconBlockBodies => true,
+        _ => false,
+    }
+}
+
+/// Replaces a handler which panicked, as the state of the old one may be inconsistent, and asks the
+/// network service for the peers still connected, to greet them again.
+fn restart(new_handler: &impl Fn() -> MessageHandler, log: &slog::Logger) -> MessageHandler {
+    let handler = new_handler();
+    handler
+        .network_send
+        .unbounded_send(NetworkMessage::HandlerRestarted)
+        .unwrap_or_else(|_| warn!(log, "Could not request connected peers after restart"));
+    handler
+}
+
+/// Returns `capacity`, enlarged if the node is subscribed to every attestation subnet.
+fn capacity(capacity: usize, config: &NetworkConfig) -> usize {
+    if config.subscribe_all_subnets {
+        capacity * ALL_SUBNETS_CAPACITY_FACTOR
+    } else {
+        capacity
+    }
+}
+
+/// Returns the metadata advertised to peers.
+///
+/// Attestations of all subnets are propagated on a single topic, so every subnet is advertised if
+/// that topic is subscribed to, as it always is with `subscribe_all_subnets`.
+fn local_metadata(config: &NetworkConfig) -> MetaData {
+    let subscribed = config.subscribe_all_subnets
+        || config
+            .topics
+            .iter()
+            .any(|topic| topic == BEACON_ATTESTATION_TOPIC);
+    MetaData {
+        seq_number: 0,
+        attnets: Bitfield::from_elem(ATTESTATION_SUBNET_COUNT, subscribed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_block_requests_are_reported_to_sync() {
+        assert!(is_sync_method(RPCMethod::BeaconBlockRoots));
+        assert!(is_sync_method(RPCMethod::BeaconBlockHeaders));
+        assert!(is_sync_method(RPCMethod::BeaconBlockBodies));
+
+        assert!(!is_sync_method(RPCMethod::Hello));
+        assert!(!is_sync_method(RPCMethod::Ping));
+        assert!(!is_sync_method(RPCMethod::Metadata));
+        assert!(!is_sync_method(RPCMethod::Goodbye));
+        assert!(!is_sync_method(RPCMethod::BeaconAttestations));
+    }
+}
@@ -0,0 +1,120 @@
+//! Simple counters used to monitor the network service.
+//!
+//! Counters are global and only ever increase. Their values are exported by `gather`.
+pub use beacon_chain::metrics::Counter;
+
+/// The number of times the sync state has changed to `Idle`.
+pub static SYNC_STATE_IDLE_TRANSITIONS: Counter = Counter::new();
+/// The number of times the sync state has changed to `Downloading`.
+pub static SYNC_STATE_DOWNLOADING_TRANSITIONS: Counter = Counter::new();
+/// The number of times the sync state has changed to `Stopped`.
+pub static SYNC_STATE_STOPPED_TRANSITIONS: Counter = Counter::new();
+/// The number of batches of blocks downloaded and imported whilst syncing.
+pub static SYNC_BATCHES_IMPORTED: Counter = Counter::new();
+/// The number of batches abandoned to be downloaded again, as their peer failed to provide them
+/// or provided an invalid block.
+pub static SYNC_BATCHES_FAILED: Counter = Counter::new();
+/// The number of chains abandoned as sync targets as a batch of them repeatedly failed to import.
+pub static SYNC_CHAINS_INVALIDATED: Counter = Counter::new();
+/// The number of historical blocks before our oldest block which were downloaded and stored.
+pub static SYNC_BLOCKS_BACKFILLED: Counter = Counter::new();
+/// The number of aggregate attestations requested from peers once syncing finished which were
+/// added to the attestation pool.
+pub static SYNC_ATTESTATIONS_IMPORTED: Counter = Counter::new();
+/// The number of gossip blocks held until their slot starts or their parent is imported.
+pub static SYNC_BLOCKS_PENDING: Counter = Counter::new();
+/// The number of held gossip blocks discarded as they did not become importable in time.
+pub static SYNC_PENDING_BLOCKS_EXPIRED: Counter = Counter::new();
+/// The number of bytes of RPC responses served to peers.
+pub static RPC_BYTES_SERVED: Counter = Counter::new();
+/// The number of RPC requests not served because the peer exceeded its bandwidth cap.
+pub static RPC_REQUESTS_THROTTLED: Counter = Counter::new();
+/// The number of block range requests rejected for having invalid parameters.
+pub static RPC_REQUESTS_INVALID: Counter = Counter::new();
+/// The number of RPC requests dropped because the peer exceeded its request rate.
+pub static RPC_REQUESTS_RATE_LIMITED: Counter = Counter::new();
+/// The number of times sending queued RPC messages was deferred by the global upload limit.
+pub static RPC_SENDS_DEFERRED: Counter = Counter::new();
+/// The number of outgoing RPC messages dropped because the queue of their priority was full.
+pub static RPC_SENDS_DROPPED: Counter = Counter::new();
+/// The number of block range requests dropped because the queue of bulk responses was full.
+pub static RPC_REQUESTS_BACKLOGGED: Counter = Counter::new();
+/// The number of error responses received to our RPC requests.
+pub static RPC_ERROR_RESPONSES: Counter = Counter::new();
+/// The number of RPC messages received which could not be decoded.
+pub static RPC_DECODE_ERRORS: Counter = Counter::new();
+/// The number of compressed messages received which would decompress to more than the maximum
+/// message size.
+pub static OVERSIZED_MESSAGES: Counter = Counter::new();
+/// The number of PING responses received.
+pub static PING_RESPONSES: Counter = Counter::new();
+/// The sum of the round-trip times of PING responses, in milliseconds. Divided by
+/// `PING_RESPONSES`, gives the mean peer latency.
+pub static PING_ROUND_TRIP_MILLIS: Counter = Counter::new();
+/// The number of PINGs which were not responded to before the next was due.
+pub static PINGS_MISSED: Counter = Counter::new();
+/// The number of times the message handler has been restarted after panicking.
+pub static MESSAGE_HANDLER_RESTARTS: Counter = Counter::new();
+/// The number of times the network service stopped reading from the swarm because the message
+/// handler's queue was full.
+pub static HANDLER_BACKPRESSURE: Counter = Counter::new();
+/// The number of responses received whose type did not match the method of our request.
+pub static RPC_RESPONSES_MISMATCHED: Counter = Counter::new();
+/// The number of gossip messages received for the first time, which were validated.
+pub static GOSSIP_MESSAGES_NEW: Counter = Counter::new();
+/// The number of gossip messages dropped as they had already been received. The hit rate of the
+/// cache of seen gossip is this over the sum of it and `GOSSIP_MESSAGES_NEW`.
+pub static GOSSIP_MESSAGES_DUPLICATE: Counter = Counter::new();
+/// The number of gossip messages dropped as they were too old, or too early, to be processed or
+/// relayed.
+pub static GOSSIP_MESSAGES_STALE: Counter = Counter::new();
+
+/// Returns the name and value of every counter, for export.
+pub fn gather() -> Vec<(&'static str, usize)> {
+    vec![
+        (
+            "sync_state_idle_transitions",
+            SYNC_STATE_IDLE_TRANSITIONS.get(),
+        ),
+        (
+            "sync_state_downloading_transitions",
+            SYNC_STATE_DOWNLOADING_TRANSITIONS.get(),
+        ),
+        (
+            "sync_state_stopped_transitions",
+            SYNC_STATE_STOPPED_TRANSITIONS.get(),
+        ),
+        ("sync_batches_imported", SYNC_BATCHES_IMPORTED.get()),
+        ("sync_batches_failed", SYNC_BATCHES_FAILED.get()),
+        ("sync_chains_invalidated", SYNC_CHAINS_INVALIDATED.get()),
+        ("sync_blocks_backfilled", SYNC_BLOCKS_BACKFILLED.get()),
+        (
+            "sync_attestations_imported",
+            SYNC_ATTESTATIONS_IMPORTED.get(),
+        ),
+        ("sync_blocks_pending", SYNC_BLOCKS_PENDING.get()),
+        (
+            "sync_pending_blocks_expired",
+            SYNC_PENDING_BLOCKS_EXPIRED.get(),
+        ),
+        ("rpc_bytes_served", RPC_BYTES_SERVED.get()),
+        ("rpc_requests_throttled", RPC_REQUESTS_THROTTLED.get()),
+        ("rpc_requests_invalid", RPC_REQUESTS_INVALID.get()),
+        ("rpc_requests_rate_limited", RPC_REQUESTS_RATE_LIMITED.get()),
+        ("rpc_sends_deferred", RPC_SENDS_DEFERRED.get()),
+        ("rpc_sends_dropped", RPC_SENDS_DROPPED.get()),
+        ("rpc_requests_backlogged", RPC_REQUESTS_BACKLOGGED.get()),
+        ("rpc_error_responses", RPC_ERROR_RESPONSES.get()),
+        ("rpc_decode_errors", RPC_DECODE_ERRORS.get()),
+        ("oversized_messages", OVERSIZED_MESSAGES.get()),
+        ("ping_responses", PING_RESPONSES.get()),
+        ("ping_round_trip_millis", PING_ROUND_TRIP_MILLIS.get()),
+        ("pings_missed", PINGS_MISSED.get()),
+        ("message_handler_restarts", MESSAGE_HANDLER_RESTARTS.get()),
+        ("handler_backpressure", HANDLER_BACKPRESSURE.get()),
+        ("rpc_responses_mismatched", RPC_RESPONSES_MISMATCHED.get()),
+        ("gossip_messages_new", GOSSIP_MESSAGES_NEW.get()),
+        ("gossip_messages_duplicate", GOSSIP_MESSAGES_DUPLICATE.get()),
+        ("gossip_messages_stale", GOSSIP_MESSAGES_STALE.get()),
+    ]
+}
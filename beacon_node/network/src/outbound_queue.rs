@@ -0,0 +1,317 @@
+use eth2_libp2p::rpc::{RPCRequest, RPCResponse};
+use eth2_libp2p::{PeerId, RPCEvent};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The maximum number of queued RPC responses and requests, other than control messages.
+const NORMAL_QUEUE_CAPACITY: usize = 1024;
+/// The maximum number of queued block header and body responses.
+const BULK_QUEUE_CAPACITY: usize = 64;
+
+/// The priority of an outgoing RPC message. Messages are sent in order of priority, then in the
+/// order they were queued.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    /// Messages which maintain the connection: HELLO, GOODBYE, PING and METADATA. Never dropped.
+    Control = 0,
+    /// Other requests, and responses which are small.
+    Normal = 1,
+    /// Responses carrying batches of blocks or attestations.
+    Bulk = 2,
+}
+
+impl Priority {
+    /// Returns the priority of sending `event`.
+    pub fn of(event: &RPCEvent) -> Self {
+        match event {
+            RPCEvent::Request { body, .. } => match body {
+                RPCRequest::Hello(_)
+                | RPCRequest::Goodbye(_)
+                | RPCRequest::Ping(_)
+                | RPCRequest::Metadata => Priority::Control,
+                _ => Priority::Normal,
+            },
+            RPCEvent::Response { result, .. } => match result {
+                RPCResponse::Hello(_) | RPCResponse::Ping(_) | RPCResponse::Metadata(_) => {
+                    Priority::Control
+                }
+                RPCResponse::BeaconBlockHeaders(_)
+                | RPCResponse::BeaconBlockBodies(_)
+                | RPCResponse::BeaconAttestations(_) => Priority::Bulk,
+                _ => Priority::Normal,
+            },
+        }
+    }
+
+    fn capacity(self) -> usize {
+        match self {
+            Priority::Control => usize::max_value(),
+            Priority::Normal => NORMAL_QUEUE_CAPACITY,
+            Priority::Bulk => BULK_QUEUE_CAPACITY,
+        }
+    }
+}
+
+/// The RPC messages waiting to be sent by the network service, queued separately by priority so
+/// that bulk sync responses do not delay handshakes and pings.
+///
+/// The `Normal` and `Bulk` queues are bounded. Their lengths are shared through an
+/// `OutboundBacklog`, so that the message handler can stop serving requests whose responses
+/// would not fit, and hold back its own requests whilst the `Normal` queue is full.
+#[derive(Default)]
+pub struct OutboundQueue {
+    /// The messages of each priority, with the length of their SSZ encoding.
+    queues: [VecDeque<(PeerId, RPCEvent, u64)>; 3],
+    counters: Arc<Counters>,
+}
+
+/// The state of an `OutboundQueue` shared with its `OutboundBacklog`s.
+#[derive(Default)]
+struct Counters {
+    /// The length of the queue of each priority.
+    lengths: [AtomicUsize; 3],
+    /// The number of `Normal` requests numbered by `OutboundBacklog::next_request`.
+    requests_queued: AtomicUsize,
+    /// The number of `Normal` requests sent, see `OutboundQueue::on_sent`.
+    requests_sent: AtomicUsize,
+}
+
+impl OutboundQueue {
+    /// Returns a handle through which the lengths of the queues may be read from other threads.
+    pub fn backlog(&self) -> OutboundBacklog {
+        OutboundBacklog {
+            counters: self.counters.clone(),
+        }
+    }
+
+    /// Queues `event`, whose SSZ encoding is `len` bytes, behind the others of its priority.
+    ///
+    /// Returns the priority of `event` as an error if its queue is full, in which case it is
+    /// dropped. Requests are never dropped: a request has been registered by the message handler,
+    /// which holds back further requests whilst the `Normal` queue is full.
+    pub fn push(&mut self, peer_id: PeerId, event: RPCEvent, len: u64) -> Result<(), Priority> {
+        let priority = Priority::of(&event);
+        let is_request = match event {
+            RPCEvent::Request { .. } => true,
+            RPCEvent::Response { .. } => false,
+        };
+        if !is_request && self.queues[priority as usize].len() >= priority.capacity() {
+            return Err(priority);
+        }
+        self.queues[priority as usize].push_back((peer_id, event, len));
+        self.update_length(priority);
+        Ok(())
+    }
+
+    /// Returns the next message to send, its length and its priority: the oldest of the highest
+    /// priority.
+    pub fn pop(&mut self) -> Option<(PeerId, RPCEvent, u64, Priority)> {
+        for &priority in &[Priority::Control, Priority::Normal, Priority::Bulk] {
+            if let Some((peer_id, event, len)) = self.queues[priority as usize].pop_front() {
+                self.update_length(priority);
+                return Some((peer_id, event, len, priority));
+            }
+        }
+        None
+    }
+
+    /// Returns a message taken by `pop` to the front of its queue, for a send which was deferred.
+    pub fn push_front(&mut self, peer_id: PeerId, event: RPCEvent, len: u64, priority: Priority) {
+        self.queues[priority as usize].push_front((peer_id, event, len));
+        self.update_length(priority);
+    }
+
+    /// Records that a message taken by `pop` has been sent, so that the message handler may start
+    /// timing out a request from when it was sent rather than queued.
+    pub fn on_sent(&self, event: &RPCEvent, priority: Priority) {
+        if let (RPCEvent::Request { .. }, Priority::Normal) = (event, priority) {
+            self.counters.requests_sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    fn update_length(&self, priority: Priority) {
+        self.counters.lengths[priority as usize]
+            .store(self.queues[priority as usize].len(), Ordering::Relaxed);
+    }
+}
+
+/// A read-only view of the lengths of an `OutboundQueue`.
+#[derive(Clone)]
+pub struct OutboundBacklog {
+    counters: Arc<Counters>,
+}
+
+impl OutboundBacklog {
+    /// Returns the number of messages of `priority` waiting to be sent.
+    pub fn len(&self, priority: Priority) -> usize {
+        self.counters.lengths[priority as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if no further messages of `priority` can be queued.
+    pub fn is_full(&self, priority: Priority) -> bool {
+        self.len(priority) >= priority.capacity()
+    }
+
+    /// Numbers a `Normal` request about to be queued. Such requests are sent in the order they
+    /// are queued, so the number identifies the request to `is_sent`.
+    pub fn next_request(&self) -> usize {
+        self.counters
+            .requests_queued
+            .fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the request numbered `number` by `next_request` has been sent.
+    pub fn is_sent(&self, number: usize) -> bool {
+        number < self.counters.requests_sent.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth2_libp2p::rpc::{BeaconBlockBodiesResponse, BeaconBlockRootsRequest, Ping};
+    use types::Slot;
+
+    fn ping(id: u64) -> RPCEvent {
+        RPCEvent::Request {
+            id,
+            method_id: 0,
+            body: RPCRequest::Ping(Ping { data: id }),
+        }
+    }
+
+    fn roots_request(id: u64) -> RPCEvent {
+        RPCEvent::Request {
+            id,
+            method_id: 0,
+            body: RPCRequest::BeaconBlockRoots(BeaconBlockRootsRequest {
+                start_slot: Slot::new(0),
+                count: 1,
+            }),
+        }
+    }
+
+    fn bodies_response(id: u64) -> RPCEvent {
+        RPCEvent::Response {
+            id,
+            method_id: 0,
+            result: RPCResponse::BeaconBlockBodies(BeaconBlockBodiesResponse {
+                block_bodies: vec![],
+            }),
+        }
+    }
+
+    fn id_of(event: &RPCEvent) -> u64 {
+        match event {
+            RPCEvent::Request { id, .. } | RPCEvent::Response { id, .. } => *id,
+        }
+    }
+
+    fn pop_id(queue: &mut OutboundQueue) -> Option<(u64, Priority)> {
+        queue
+            .pop()
+            .map(|(_, event, _, priority)| (id_of(&event), priority))
+    }
+
+    #[test]
+    fn messages_are_sent_by_priority_then_in_order() {
+        let mut queue = OutboundQueue::default();
+        let peer_id = PeerId::random();
+        queue.push(peer_id.clone(), bodies_response(0), 0).unwrap();
+        queue.push(peer_id.clone(), roots_request(1), 0).unwrap();
+        queue.push(peer_id.clone(), ping(2), 0).unwrap();
+        queue.push(peer_id.clone(), roots_request(3), 0).unwrap();
+
+        assert_eq!(pop_id(&mut queue), Some((2, Priority::Control)));
+        assert_eq!(pop_id(&mut queue), Some((1, Priority::Normal)));
+        assert_eq!(pop_id(&mut queue), Some((3, Priority::Normal)));
+        assert_eq!(pop_id(&mut queue), Some((0, Priority::Bulk)));
+        assert_eq!(pop_id(&mut queue), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn full_queue_drops_responses_but_not_requests() {
+        let mut queue = OutboundQueue::default();
+        let backlog = queue.backlog();
+        let peer_id = PeerId::random();
+        for id in 0..BULK_QUEUE_CAPACITY as u64 {
+            queue.push(peer_id.clone(), bodies_response(id), 0).unwrap();
+        }
+        assert!(backlog.is_full(Priority::Bulk));
+        assert_eq!(
+            queue.push(peer_id.clone(), bodies_response(0), 0),
+            Err(Priority::Bulk)
+        );
+
+        for id in 0..NORMAL_QUEUE_CAPACITY as u64 {
+            queue.push(peer_id.clone(), roots_request(id), 0).unwrap();
+        }
+        assert!(backlog.is_full(Priority::Normal));
+        assert_eq!(queue.push(peer_id.clone(), roots_request(0), 0), Ok(()));
+        assert_eq!(backlog.len(Priority::Normal), NORMAL_QUEUE_CAPACITY + 1);
+
+        for id in 0..2 * NORMAL_QUEUE_CAPACITY as u64 {
+            queue.push(peer_id.clone(), ping(id), 0).unwrap();
+        }
+        assert_eq!(backlog.len(Priority::Control), 2 * NORMAL_QUEUE_CAPACITY);
+    }
+
+    #[test]
+    fn deferred_message_is_sent_first() {
+        let mut queue = OutboundQueue::default();
+        let backlog = queue.backlog();
+        let peer_id = PeerId::random();
+        queue.push(peer_id.clone(), roots_request(0), 0).unwrap();
+        queue.push(peer_id.clone(), roots_request(1), 0).unwrap();
+
+        let (peer_id, event, len, priority) = queue.pop().unwrap();
+        assert_eq!(backlog.len(Priority::Normal), 1);
+        queue.push_front(peer_id, event, len, priority);
+        assert_eq!(backlog.len(Priority::Normal), 2);
+
+        assert_eq!(pop_id(&mut queue), Some((0, Priority::Normal)));
+        assert_eq!(pop_id(&mut queue), Some((1, Priority::Normal)));
+        assert_eq!(backlog.len(Priority::Normal), 0);
+    }
+
+    #[test]
+    fn requests_are_sent_once_popped_and_sent() {
+        let mut queue = OutboundQueue::default();
+        let backlog = queue.backlog();
+        let peer_id = PeerId::random();
+        let first = backlog.next_request();
+        queue.push(peer_id.clone(), roots_request(0), 0).unwrap();
+        let second = backlog.next_request();
+        queue.push(peer_id.clone(), roots_request(1), 0).unwrap();
+        queue.push(peer_id.clone(), ping(2), 0).unwrap();
+        queue.push(peer_id.clone(), bodies_response(3), 0).unwrap();
+        assert!(!backlog.is_sent(first));
+
+        // control messages and responses are not counted
+        let (_, event, _, priority) = queue.pop().unwrap();
+        queue.on_sent(&event, priority);
+        assert!(!backlog.is_sent(first));
+
+        // a deferred request is not sent
+        let (peer_id, event, len, priority) = queue.pop().unwrap();
+        queue.push_front(peer_id, event, len, priority);
+        assert!(!backlog.is_sent(first));
+
+        let (_, event, _, priority) = queue.pop().unwrap();
+        queue.on_sent(&event, priority);
+        assert!(backlog.is_sent(first));
+        assert!(!backlog.is_sent(second));
+
+        let (_, event, _, priority) = queue.pop().unwrap();
+        queue.on_sent(&event, priority);
+        let (_, event, _, priority) = queue.pop().unwrap();
+        queue.on_sent(&event, priority);
+        assert!(backlog.is_sent(second));
+    }
+}
@@ -0,0 +1,695 @@
+use eth2_libp2p::rpc::{GoodbyeReason, MetaData};
+use eth2_libp2p::{HelloMessage, PeerId};
+use slog::{debug, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The number of HELLO messages that are remembered for each peer.
+pub const HELLO_HISTORY_LEN: usize = 8;
+/// The score penalty applied to a peer whose advertised finalized checkpoint regresses.
+pub const FINALIZED_REGRESSION_PENALTY: i64 = 50;
+/// The score penalty applied to a peer for each message it sends which cannot be decoded.
+pub const DECODE_ERROR_PENALTY: i64 = 10;
+/// The score penalty applied to a peer for each compressed message it sends which would
+/// decompress to more than the maximum message size.
+pub const OVERSIZED_MESSAGE_PENALTY: i64 = 50;
+/// The score penalty applied to a peer whose HELLO shows it is on a different chain.
+pub const BAD_HELLO_PENALTY: i64 = 100;
+/// The score penalty applied to a peer for each invalid block it sends.
+pub const INVALID_BLOCK_PENALTY: i64 = 50;
+/// The score penalty applied to a peer for each response of aggregate attestations it sends which
+/// holds an attestation that is invalid on any chain.
+pub const INVALID_ATTESTATION_PENALTY: i64 = 50;
+/// The score penalty applied to a peer for each block it sends which skips more slots after its
+/// parent than we are willing to process.
+pub const TOO_MANY_SKIPPED_SLOTS_PENALTY: i64 = 25;
+/// The score penalty applied to a peer for each request it does not respond to in time.
+pub const REQUEST_TIMEOUT_PENALTY: i64 = 10;
+/// The score penalty applied to a peer for each invalid block range request it sends.
+pub const INVALID_REQUEST_PENALTY: i64 = 10;
+/// The score penalty applied to a peer which does not complete the HELLO handshake in time.
+pub const HELLO_TIMEOUT_PENALTY: i64 = 20;
+/// The score penalty applied to a peer for each request it sends in excess of its request rate.
+pub const RATE_LIMIT_PENALTY: i64 = 5;
+/// The score penalty applied to a peer for each response whose type does not match our request.
+pub const MISMATCHED_RESPONSE_PENALTY: i64 = 50;
+/// Peers which do not respond to this many consecutive PINGs are disconnected.
+pub const MAX_MISSED_PINGS: u32 = 3;
+/// Peers with a score at or below this are disconnected.
+pub const DISCONNECT_SCORE: i64 = -100;
+/// Peers with a score at or below this are banned.
+pub const BAN_SCORE: i64 = -200;
+/// The maximum number of disconnected peers whose score is remembered.
+pub const MAX_DISCONNECTED_PEERS: usize = 1_024;
+
+/// An action to be taken against a peer by the network service.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeerAction {
+    /// Disconnect from the peer, giving a reason. It may reconnect.
+    Disconnect(GoodbyeReason),
+    /// Disconnect from the peer and refuse connections from it for the given duration, giving the
+    /// reason for the penalty which caused the ban.
+    Ban(Duration, &'static str),
+}
+
+/// A HELLO message received from a peer, along with the time it was received.
+#[derive(Debug, Clone)]
+pub struct HelloRecord {
+    pub received: Instant,
+    pub message: HelloMessage,
+}
+
+/// The information we hold about a single peer.
+#[derive(Debug, Clone, Default)]
+pub struct PeerInfo {
+    /// The reputation of the peer. Starts at zero and is decreased by penalties.
+    pub score: i64,
+    /// The most recent HELLO messages received from the peer, oldest first.
+    pub hello_history: VecDeque<HelloRecord>,
+    /// The number of messages from the peer which failed to decode, keyed by the message type (RPC
+    /// method or gossipsub topic) and error kind.
+    pub decode_errors: HashMap<(String, String), u64>,
+    /// The time the peer's ban expires, if it is banned.
+    pub banned_until: Option<Instant>,
+    /// The round-trip time of the most recent PING to the peer.
+    pub latency: Option<Duration>,
+    /// The number of consecutive PINGs the peer has not responded to.
+    pub missed_pings: u32,
+    /// The most recent metadata of the peer, if it has sent any.
+    pub metadata: Option<MetaData>,
+}
+
+/// Keeps track of the behaviour of peers.
+///
+/// Peers are penalized for misbehaviour, lowering their score. A peer whose score falls to
+/// `DISCONNECT_SCORE` is disconnected and one that falls to `BAN_SCORE` is banned for
+/// `ban_duration`, after which its score is reset. The resulting actions are collected until
+/// drained with `drain_actions`.
+///
+/// Trusted peers are scored like any other, but are never disconnected or banned for their score.
+///
+/// The information about a peer is dropped when it disconnects, unless it is banned or has a
+/// negative score. Only the score and ban of such a peer are kept, so that it cannot shed them by
+/// reconnecting, until the later of its ban ending and `ban_duration` after it disconnected. At
+/// most `MAX_DISCONNECTED_PEERS` are remembered, those due to be forgotten soonest being dropped
+/// first.
+pub struct PeerManager {
+    /// The known peers and their information.
+    peers: HashMap<PeerId, PeerInfo>,
+    /// The peers currently connected.
+    connected: HashSet<PeerId>,
+    /// The time each remembered disconnected peer is forgotten.
+    forget_at: HashMap<PeerId, Instant>,
+    /// Peers configured by the operator, which are never banned.
+    trusted: HashSet<PeerId>,
+    /// The duration of a ban.
+    ban_duration: Duration,
+    /// Actions to be taken against peers, oldest first.
+    actions: Vec<(PeerId, PeerAction)>,
+    /// The `PeerManager` logger.
+    log: slog::Logger,
+}
+
+impl PeerManager {
+    pub fn new(ban_duration: Duration, log: slog::Logger) -> Self {
+        PeerManager {
+            peers: HashMap::new(),
+            connected: HashSet::new(),
+            forget_at: HashMap::new(),
+            trusted: HashSet::new(),
+            ban_duration,
+            actions: vec![],
+            log,
+        }
+    }
+
+    /// Marks a peer as trusted, so that it is never disconnected or banned for its score.
+    pub fn add_trusted_peer(&mut self, peer_id: PeerId) {
+        self.trusted.insert(peer_id);
+    }
+
+    /// Returns `true` if the peer is trusted.
+    pub fn is_trusted(&self, peer_id: &PeerId) -> bool {
+        self.trusted.contains(peer_id)
+    }
+
+    /// Records that a peer has connected.
+    pub fn peer_connected(&mut self, peer_id: &PeerId) {
+        self.connected.insert(peer_id.clone());
+        self.forget_at.remove(peer_id);
+    }
+
+    /// Records that a peer has disconnected, dropping the information held about it.
+    pub fn peer_disconnected(&mut self, peer_id: &PeerId) {
+        self.connected.remove(peer_id);
+        self.forget(peer_id.clone(), Instant::now());
+        self.prune();
+    }
+
+    /// Drops the information about disconnected peers which are due to be forgotten, and the
+    /// peers which are due to be forgotten soonest beyond `MAX_DISCONNECTED_PEERS`.
+    ///
+    /// Peers penalized after they disconnected are only dropped from here.
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        let unforgotten: Vec<PeerId> = self
+            .peers
+            .keys()
+            .filter(|peer_id| {
+                !self.connected.contains(*peer_id) && !self.forget_at.contains_key(*peer_id)
+            })
+            .cloned()
+            .collect();
+        for peer_id in unforgotten {
+            self.forget(peer_id, now);
+        }
+
+        let peers = &mut self.peers;
+        self.forget_at.retain(|peer_id, forget_at| {
+            let keep = *forget_at > now;
+            if !keep {
+                peers.remove(peer_id);
+            }
+            keep
+        });
+
+        if self.forget_at.len() > MAX_DISCONNECTED_PEERS {
+            let mut by_time: Vec<(Instant, PeerId)> = self
+                .forget_at
+                .iter()
+                .map(|(peer_id, forget_at)| (*forget_at, peer_id.clone()))
+                .collect();
+            by_time.sort_by_key(|(forget_at, _)| *forget_at);
+            let excess = by_time.len() - MAX_DISCONNECTED_PEERS;
+            for (_, peer_id) in by_time.into_iter().take(excess) {
+                self.forget_at.remove(&peer_id);
+                self.peers.remove(&peer_id);
+            }
+        }
+    }
+
+    /// Drops the information about a disconnected peer, keeping only the score and ban of a peer
+    /// which is banned or has a negative score until it is due to be forgotten.
+    fn forget(&mut self, peer_id: PeerId, now: Instant) {
+        let (score, banned_until) = match self.peers.get(&peer_id) {
+            Some(info) => (info.score, info.banned_until),
+            None => return,
+        };
+        let banned = banned_until.map_or(false, |until| until > now);
+        if score < 0 || banned {
+            let forget_at = std::cmp::max(now + self.ban_duration, banned_until.unwrap_or(now));
+            self.peers.insert(
+                peer_id.clone(),
+                PeerInfo {
+                    score,
+                    banned_until,
+                    ..PeerInfo::default()
+                },
+            );
+            self.forget_at.insert(peer_id, forget_at);
+        } else {
+            self.peers.remove(&peer_id);
+        }
+    }
+
+    /// Records a HELLO message received from a peer.
+    ///
+    /// Returns `false` and penalizes the peer if the advertised finalized checkpoint regresses
+    /// from its previous HELLO, which is impossible for an honest node.
+    pub fn record_hello(&mut self, peer_id: &PeerId, message: &HelloMessage) -> bool {
+        let regressed = {
+            let info = self.peers.entry(peer_id.clone()).or_default();
+
+            let regressed = info
+                .hello_history
+                .back()
+                .map(|previous| finalized_regressed(&previous.message, message))
+                .unwrap_or(false);
+
+            if info.hello_history.len() >= HELLO_HISTORY_LEN {
+                info.hello_history.pop_front();
+            }
+            info.hello_history.push_back(HelloRecord {
+                received: Instant::now(),
+                message: message.clone(),
+            });
+
+            regressed
+        };
+
+        if regressed {
+            warn!(
+                self.log,
+                "Peer finalized checkpoint regressed. Peer: {:?}", peer_id;
+                "epoch" => message.latest_finalized_epoch.as_u64(),
+            );
+            self.penalize(
+                peer_id,
+                FINALIZED_REGRESSION_PENALTY,
+                "finalized checkpoint regressed",
+            );
+        }
+
+        !regressed
+    }
+
+    /// Records a message from a peer which could not be decoded, penalizing the peer.
+    pub fn record_decode_error(&mut self, peer_id: &PeerId, message_type: &str, error_kind: &str) {
+        self.record_undecodable(
+            peer_id,
+            message_type,
+            error_kind,
+            DECODE_ERROR_PENALTY,
+            "undecodable message",
+        );
+    }
+
+    /// Records a compressed message from a peer which would decompress to more than the maximum
+    /// message size, penalizing the peer more heavily than for other undecodable messages.
+    pub fn record_oversized_message(&mut self, peer_id: &PeerId, message_type: &str) {
+        self.record_undecodable(
+            peer_id,
+            message_type,
+            "DecompressedTooLong",
+            OVERSIZED_MESSAGE_PENALTY,
+            "oversized message",
+        );
+    }
+
+    /// Counts an undecodable message from a peer by type and error kind, penalizing the peer.
+    fn record_undecodable(
+        &mut self,
+        peer_id: &PeerId,
+        message_type: &str,
+        error_kind: &str,
+        penalty: i64,
+        reason: &'static str,
+    ) {
+        let count = {
+            let info = self.peers.entry(peer_id.clone()).or_default();
+            let count = info
+                .decode_errors
+                .entry((message_type.to_string(), error_kind.to_string()))
+                .or_insert(0);
+            *count += 1;
+            *count
+        };
+
+        warn!(
+            self.log,
+            "Undecodable message received. Peer: {:?}", peer_id;
+            "message_type" => message_type,
+            "error" => error_kind,
+            "count" => count,
+        );
+        self.penalize(peer_id, penalty, reason);
+    }
+
+    /// Records the metadata of a peer, unless it is older than that already held.
+    pub fn record_metadata(&mut self, peer_id: &PeerId, metadata: MetaData) {
+        let info = self.peers.entry(peer_id.clone()).or_default();
+        if info
+            .metadata
+            .as_ref()
+            .map_or(true, |held| metadata.seq_number >= held.seq_number)
+        {
+            info.metadata = Some(metadata);
+        }
+    }
+
+    /// Returns the unbanned peers which advertise that they subscribe to the attestation
+    /// `subnet`, highest scored first.
+    pub fn peers_on_subnet(&self, subnet: usize) -> Vec<PeerId> {
+        let now = Instant::now();
+        let mut peers: Vec<(&PeerId, &PeerInfo)> = self
+            .peers
+            .iter()
+            .filter(|(_, info)| info.banned_until.map_or(true, |until| until <= now))
+            .filter(|(_, info)| {
+                info.metadata
+                    .as_ref()
+                    .map_or(false, |metadata| metadata.has_subnet(subnet))
+            })
+            .collect();
+        peers.sort_by_key(|(_, info)| -info.score);
+        peers
+            .into_iter()
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect()
+    }
+
+    /// Records the round-trip time of a PING the peer responded to.
+    pub fn record_latency(&mut self, peer_id: &PeerId, latency: Duration) {
+        let info = self.peers.entry(peer_id.clone()).or_default();
+        info.latency = Some(latency);
+        info.missed_pings = 0;
+    }
+
+    /// Records a PING the peer did not respond to, disconnecting the peer once it has missed
+    /// `MAX_MISSED_PINGS` in a row.
+    pub fn record_missed_ping(&mut self, peer_id: &PeerId) {
+        let missed_pings = {
+            let info = self.peers.entry(peer_id.clone()).or_default();
+            info.missed_pings += 1;
+            info.missed_pings
+        };
+
+        debug!(
+            self.log,
+            "Peer missed a PING. Peer: {:?}", peer_id;
+            "missed_pings" => missed_pings,
+        );
+        if missed_pings >= MAX_MISSED_PINGS {
+            warn!(
+                self.log,
+                "Peer is unresponsive. Peer: {:?}", peer_id;
+                "missed_pings" => missed_pings,
+            );
+            self.queue_action(peer_id, PeerAction::Disconnect(GoodbyeReason::Fault));
+        }
+    }
+
+    /// Decreases the score of a peer, disconnecting or banning it if the score falls far enough.
+    ///
+    /// The `reason` for the penalty is given as the reason for a ban it causes.
+    pub fn penalize(&mut self, peer_id: &PeerId, penalty: i64, reason: &'static str) {
+        let now = Instant::now();
+        let info = self.peers.entry(peer_id.clone()).or_default();
+
+        // a banned peer cannot be penalized further
+        if info.banned_until.map_or(false, |until| until > now) {
+            return;
+        }
+
+        let previous_score = info.score;
+        info.score -= penalty;
+        debug!(
+            self.log,
+            "Peer penalized. Peer: {:?}", peer_id;
+            "penalty" => penalty,
+            "reason" => reason,
+            "score" => info.score,
+        );
+
+        let action = if self.trusted.contains(peer_id) {
+            None
+        } else if info.score <= BAN_SCORE {
+            info.banned_until = Some(now + self.ban_duration);
+            Some(PeerAction::Ban(self.ban_duration, reason))
+        } else if info.score <= DISCONNECT_SCORE && previous_score > DISCONNECT_SCORE {
+            Some(PeerAction::Disconnect(GoodbyeReason::Fault))
+        } else {
+            None
+        };
+
+        if let Some(action) = action {
+            warn!(
+                self.log,
+                "Peer score too low. Peer: {:?}", peer_id;
+                "score" => info.score,
+                "action" => format!("{:?}", action),
+            );
+            self.queue_action(peer_id, action);
+        }
+    }
+
+    /// Disconnects a peer without penalizing it.
+    pub fn disconnect(&mut self, peer_id: &PeerId, reason: GoodbyeReason) {
+        self.queue_action(peer_id, PeerAction::Disconnect(reason));
+    }
+
+    /// Queues an action against a peer. A ban supersedes a queued disconnect, otherwise only the
+    /// first action queued against a peer is kept.
+    fn queue_action(&mut self, peer_id: &PeerId, action: PeerAction) {
+        match self
+            .actions
+            .iter_mut()
+            .find(|(queued, _)| queued == peer_id)
+        {
+            Some((_, queued)) => {
+                if let PeerAction::Ban(..) = action {
+                    *queued = action;
+                }
+            }
+            None => self.actions.push((peer_id.clone(), action)),
+        }
+    }
+
+    /// Returns `true` if the peer is currently banned. Trusted peers are never banned.
+    ///
+    /// The score of a peer whose ban has expired is reset.
+    pub fn is_banned(&mut self, peer_id: &PeerId) -> bool {
+        if self.trusted.contains(peer_id) {
+            return false;
+        }
+        let info = match self.peers.get_mut(peer_id) {
+            Some(info) => info,
+            None => return false,
+        };
+
+        match info.banned_until {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                info.banned_until = None;
+                info.score = 0;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Removes and returns the actions to be taken against peers, oldest first.
+    pub fn drain_actions(&mut self) -> Vec<(PeerId, PeerAction)> {
+        std::mem::replace(&mut self.actions, vec![])
+    }
+
+    /// Returns the information held about a peer, if any.
+    pub fn peer(&self, peer_id: &PeerId) -> Option<&PeerInfo> {
+        self.peers.get(peer_id)
+    }
+
+    /// Returns all known peers and their information.
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &PeerInfo)> {
+        self.peers.iter()
+    }
+}
+
+/// Returns `true` if the finalized checkpoint of `current` is behind, or conflicts with, that of
+/// `previous`.
+fn finalized_regressed(previous: &HelloMessage, current: &HelloMessage) -> bool {
+    current.latest_finalized_epoch < previous.latest_finalized_epoch
+        || (current.latest_finalized_epoch == previous.latest_finalized_epoch
+            && current.latest_finalized_root != previous.latest_finalized_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+    use types::{Bitfield, Epoch, Hash256, Slot};
+
+    fn peer_manager() -> PeerManager {
+        let log = slog::Logger::root(slog::Discard, o!());
+        PeerManager::new(Duration::from_secs(60), log)
+    }
+
+    fn metadata(seq_number: u64, subnets: &[usize]) -> MetaData {
+        let mut attnets = Bitfield::from_elem(8, false);
+        for &subnet in subnets {
+            attnets.set(subnet, true);
+        }
+        MetaData {
+            seq_number,
+            attnets,
+        }
+    }
+
+    #[test]
+    fn trusted_peers_are_never_disconnected_or_banned() {
+        let mut peer_manager = peer_manager();
+        let trusted = PeerId::random();
+        let other = PeerId::random();
+        peer_manager.add_trusted_peer(trusted.clone());
+        assert!(peer_manager.is_trusted(&trusted));
+        assert!(!peer_manager.is_trusted(&other));
+
+        for peer_id in &[&trusted, &other] {
+            peer_manager.penalize(peer_id, -DISCONNECT_SCORE, "test");
+            peer_manager.penalize(peer_id, -BAN_SCORE, "test");
+        }
+
+        // the trusted peer is still scored, so its misbehaviour is visible
+        assert_eq!(
+            peer_manager.peer(&trusted).unwrap().score,
+            DISCONNECT_SCORE + BAN_SCORE
+        );
+        assert!(!peer_manager.is_banned(&trusted));
+        assert!(peer_manager.is_banned(&other));
+        let actions = peer_manager.drain_actions();
+        assert_eq!(actions.len(), 1);
+        match &actions[0] {
+            (peer_id, PeerAction::Ban(_, "test")) => assert_eq!(peer_id, &other),
+            action => panic!("unexpected action: {:?}", action),
+        }
+    }
+
+    fn hello(finalized_epoch: u64, finalized_root: u64, best_slot: u64) -> HelloMessage {
+        HelloMessage {
+            network_id: 1,
+            fork_digest: [0; 4],
+            latest_finalized_root: Hash256::from_low_u64_be(finalized_root),
+            latest_finalized_epoch: Epoch::new(finalized_epoch),
+            best_root: Hash256::from_low_u64_be(best_slot),
+            best_slot: Slot::new(best_slot),
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_hello_messages() {
+        let mut peer_manager = peer_manager();
+        let peer_id = PeerId::random();
+
+        for best_slot in 0..HELLO_HISTORY_LEN as u64 + 2 {
+            assert!(peer_manager.record_hello(&peer_id, &hello(0, 0, best_slot)));
+        }
+
+        let history = &peer_manager.peer(&peer_id).unwrap().hello_history;
+        assert_eq!(history.len(), HELLO_HISTORY_LEN);
+        // the two oldest messages were dropped
+        assert_eq!(history.front().unwrap().message.best_slot, Slot::new(2));
+        assert_eq!(
+            history.back().unwrap().message.best_slot,
+            Slot::new(HELLO_HISTORY_LEN as u64 + 1)
+        );
+    }
+
+    #[test]
+    fn penalizes_finalized_checkpoint_regressions() {
+        let mut peer_manager = peer_manager();
+        let peer_id = PeerId::random();
+
+        // the same checkpoint, or a later one, is not a regression
+        assert!(peer_manager.record_hello(&peer_id, &hello(2, 2, 64)));
+        assert!(peer_manager.record_hello(&peer_id, &hello(2, 2, 65)));
+        assert!(peer_manager.record_hello(&peer_id, &hello(3, 3, 96)));
+        assert_eq!(peer_manager.peer(&peer_id).unwrap().score, 0);
+
+        // an earlier epoch
+        assert!(!peer_manager.record_hello(&peer_id, &hello(2, 2, 97)));
+        assert_eq!(
+            peer_manager.peer(&peer_id).unwrap().score,
+            -FINALIZED_REGRESSION_PENALTY
+        );
+
+        // the same epoch with a different root
+        assert!(!peer_manager.record_hello(&peer_id, &hello(2, 4, 98)));
+        assert_eq!(
+            peer_manager.peer(&peer_id).unwrap().score,
+            -2 * FINALIZED_REGRESSION_PENALTY
+        );
+
+        // the regressed messages are still recorded
+        assert_eq!(peer_manager.peer(&peer_id).unwrap().hello_history.len(), 5);
+    }
+
+    #[test]
+    fn forgets_disconnected_peers() {
+        let mut peer_manager = peer_manager();
+        let peers: Vec<PeerId> = (0..2 * MAX_DISCONNECTED_PEERS + 10)
+            .map(|_| PeerId::random())
+            .collect();
+        let (penalized, good) = peers.split_at(MAX_DISCONNECTED_PEERS + 5);
+
+        for peer_id in &peers {
+            peer_manager.peer_connected(peer_id);
+            assert!(peer_manager.record_hello(peer_id, &hello(0, 0, 1)));
+            peer_manager.record_metadata(peer_id, metadata(0, &[1]));
+            peer_manager.record_decode_error(peer_id, "beacon_block", "TooShort");
+        }
+        for peer_id in good {
+            // a penalty which has been made up for
+            peer_manager.peers.get_mut(peer_id).unwrap().score = 0;
+        }
+        assert_eq!(peer_manager.peers().count(), peers.len());
+
+        for peer_id in &peers {
+            peer_manager.peer_disconnected(peer_id);
+        }
+
+        // only the scores of penalized peers are kept, and only so many of them
+        assert_eq!(peer_manager.peers().count(), MAX_DISCONNECTED_PEERS);
+        assert!(good
+            .iter()
+            .all(|peer_id| peer_manager.peer(peer_id).is_none()));
+        let info = peer_manager.peer(penalized.last().unwrap()).unwrap();
+        assert_eq!(info.score, -DECODE_ERROR_PENALTY);
+        assert!(info.hello_history.is_empty());
+        assert!(info.decode_errors.is_empty());
+        assert!(peer_manager.peers_on_subnet(1).is_empty());
+
+        // a peer penalized after it disconnected is forgotten too
+        let late = PeerId::random();
+        peer_manager.penalize(&late, DECODE_ERROR_PENALTY, "test");
+        peer_manager.prune();
+        assert_eq!(peer_manager.peers().count(), MAX_DISCONNECTED_PEERS);
+
+        // a reconnected peer keeps its score
+        let returning = penalized.last().unwrap();
+        peer_manager.peer_connected(returning);
+        peer_manager.prune();
+        assert_eq!(
+            peer_manager.peer(returning).unwrap().score,
+            -DECODE_ERROR_PENALTY
+        );
+    }
+
+    #[test]
+    fn forgets_penalized_peers_after_the_ban_duration() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut peer_manager = PeerManager::new(Duration::from_millis(0), log);
+        let peer_id = PeerId::random();
+
+        peer_manager.peer_connected(&peer_id);
+        peer_manager.penalize(&peer_id, DECODE_ERROR_PENALTY, "test");
+        peer_manager.peer_disconnected(&peer_id);
+
+        assert!(peer_manager.peer(&peer_id).is_none());
+    }
+
+    #[test]
+    fn keeps_the_latest_metadata() {
+        let mut peer_manager = peer_manager();
+        let peer_id = PeerId::random();
+
+        peer_manager.record_metadata(&peer_id, metadata(2, &[1]));
+        peer_manager.record_metadata(&peer_id, metadata(1, &[2]));
+        assert_eq!(peer_manager.peers_on_subnet(1), vec![peer_id.clone()]);
+        assert!(peer_manager.peers_on_subnet(2).is_empty());
+
+        peer_manager.record_metadata(&peer_id, metadata(3, &[2]));
+        assert!(peer_manager.peers_on_subnet(1).is_empty());
+        assert_eq!(peer_manager.peers_on_subnet(2), vec![peer_id]);
+    }
+
+    #[test]
+    fn peers_on_subnet_are_unbanned_and_ordered_by_score() {
+        let mut peer_manager = peer_manager();
+        let (best, worst, banned, elsewhere) = (
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+        );
+        for peer_id in &[&best, &worst, &banned] {
+            peer_manager.record_metadata(peer_id, metadata(0, &[3]));
+        }
+        peer_manager.record_metadata(&elsewhere, metadata(0, &[4]));
+
+        peer_manager.penalize(&worst, DECODE_ERROR_PENALTY, "test");
+        peer_manager.penalize(&banned, -BAN_SCORE, "test");
+
+        assert_eq!(peer_manager.peers_on_subnet(3), vec![best, worst]);
+        // a subnet beyond the bitfield is not advertised
+        assert!(peer_manager.peers_on_subnet(64).is_empty());
+    }
+}
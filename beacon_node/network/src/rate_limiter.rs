@@ -0,0 +1,166 @@
+use eth2_libp2p::rpc::RPCMethod;
+use eth2_libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The number of requests a peer may make of a single RPC method in a burst, and the interval at
+/// which it may make another.
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    max_tokens: u64,
+    replenish_every: Duration,
+}
+
+/// Returns the quota for an RPC method, or `None` if requests for it are not limited.
+fn quota(method: RPCMethod) -> Option<Quota> {
+    let quota = match method {
+        RPCMethod::Hello => Quota {
+            max_tokens: 2,
+            replenish_every: Duration::from_secs(10),
+        },
+        RPCMethod::BeaconBlockRoots
+        | RPCMethod::BeaconBlockHeaders
+        | RPCMethod::BeaconBlockBodies => Quota {
+            max_tokens: 10,
+            replenish_every: Duration::from_secs(1),
+        },
+        RPCMethod::Metadata => Quota {
+            max_tokens: 2,
+            replenish_every: Duration::from_secs(10),
+        },
+        RPCMethod::Ping => Quota {
+            max_tokens: 2,
+            replenish_every: Duration::from_secs(5),
+        },
+        RPCMethod::BeaconChainState | RPCMethod::BeaconAttestations => Quota {
+            max_tokens: 2,
+            replenish_every: Duration::from_secs(10),
+        },
+        RPCMethod::Goodbye | RPCMethod::Error | RPCMethod::Unknown => return None,
+    };
+    Some(quota)
+}
+
+/// The requests a peer may currently make of a single RPC method.
+struct Bucket {
+    tokens: u64,
+    /// The time the last token was added.
+    last_replenish: Instant,
+}
+
+/// Limits the rate of inbound RPC requests using a token bucket for each peer and RPC method.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: HashMap<(PeerId, u16), Bucket>,
+}
+
+impl RateLimiter {
+    /// Returns `true` and consumes a token if the peer may make a request of the RPC method with
+    /// `method_id`.
+    pub fn allows(&mut self, peer_id: &PeerId, method_id: u16) -> bool {
+        self.allows_at(peer_id, method_id, Instant::now())
+    }
+
+    /// As `allows`, for a request made at `now`.
+    fn allows_at(&mut self, peer_id: &PeerId, method_id: u16, now: Instant) -> bool {
+        let quota = match quota(RPCMethod::from(method_id)) {
+            Some(quota) => quota,
+            None => return true,
+        };
+
+        let bucket = self
+            .buckets
+            .entry((peer_id.clone(), method_id))
+            .or_insert_with(|| Bucket {
+                tokens: quota.max_tokens,
+                last_replenish: now,
+            });
+
+        // add a token for each full interval elapsed since the last token was added
+        while bucket.tokens < quota.max_tokens
+            && now.duration_since(bucket.last_replenish) >= quota.replenish_every
+        {
+            bucket.tokens += 1;
+            bucket.last_replenish += quota.replenish_every;
+        }
+        if bucket.tokens == quota.max_tokens {
+            bucket.last_replenish = now;
+        }
+
+        if bucket.tokens == 0 {
+            return false;
+        }
+        bucket.tokens -= 1;
+        true
+    }
+
+    /// Forgets the buckets of a peer which is no longer connected.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.buckets
+            .retain(|(bucket_peer, _), _| bucket_peer != peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: u16 = 0;
+    const GOODBYE: u16 = 1;
+    const BLOCK_ROOTS: u16 = 10;
+
+    #[test]
+    fn refuses_requests_beyond_the_burst() {
+        let mut limiter = RateLimiter::default();
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(limiter.allows_at(&peer_id, BLOCK_ROOTS, now));
+        }
+        assert!(!limiter.allows_at(&peer_id, BLOCK_ROOTS, now));
+
+        // each peer and method has a bucket of its own
+        assert!(limiter.allows_at(&PeerId::random(), BLOCK_ROOTS, now));
+        assert!(limiter.allows_at(&peer_id, HELLO, now));
+        // GOODBYEs are never limited
+        for _ in 0..100 {
+            assert!(limiter.allows_at(&peer_id, GOODBYE, now));
+        }
+    }
+
+    #[test]
+    fn replenishes_a_token_each_interval_up_to_the_burst() {
+        let mut limiter = RateLimiter::default();
+        let peer_id = PeerId::random();
+        let start = Instant::now();
+
+        assert!(limiter.allows_at(&peer_id, HELLO, start));
+        assert!(limiter.allows_at(&peer_id, HELLO, start));
+        assert!(!limiter.allows_at(&peer_id, HELLO, start + Duration::from_secs(9)));
+
+        let later = start + Duration::from_secs(10);
+        assert!(limiter.allows_at(&peer_id, HELLO, later));
+        assert!(!limiter.allows_at(&peer_id, HELLO, later));
+
+        // a long idle period refills no more than the burst
+        let idle = later + Duration::from_secs(1_000);
+        assert!(limiter.allows_at(&peer_id, HELLO, idle));
+        assert!(limiter.allows_at(&peer_id, HELLO, idle));
+        assert!(!limiter.allows_at(&peer_id, HELLO, idle));
+    }
+
+    #[test]
+    fn forgets_removed_peers() {
+        let mut limiter = RateLimiter::default();
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        assert!(limiter.allows_at(&peer_id, HELLO, now));
+        assert!(limiter.allows_at(&peer_id, HELLO, now));
+        assert!(!limiter.allows_at(&peer_id, HELLO, now));
+
+        limiter.remove_peer(&peer_id);
+        assert!(limiter.allows_at(&peer_id, HELLO, now));
+    }
+}
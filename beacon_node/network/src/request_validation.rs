@@ -0,0 +1,199 @@
+use eth2_libp2p::rpc::{
+    error_codes, BeaconBlockBodiesRequest, BeaconBlockHeadersRequest, BeaconBlockRootsRequest,
+};
+use types::Slot;
+
+/// The maximum number of slots of block roots served in response to a single request.
+pub const MAX_BLOCK_ROOTS_PER_REQUEST: u64 = 1_024;
+/// The maximum number of block headers served in response to a single request.
+pub const MAX_HEADERS_PER_REQUEST: u64 = 256;
+/// The maximum number of slots that may be skipped between requested headers.
+pub const MAX_SKIP_SLOTS: u64 = 1_024;
+/// The maximum number of block bodies served in response to a single request.
+pub const MAX_BODIES_PER_REQUEST: usize = 256;
+
+/// The reason an inbound block range request was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeRequestError {
+    /// The request asked for no blocks.
+    ZeroCount,
+    /// The request asked for too many slots to be skipped between blocks.
+    StepTooLarge,
+}
+
+impl RangeRequestError {
+    /// Returns the error code reported to the requesting peer.
+    pub fn code(self) -> u64 {
+        match self {
+            RangeRequestError::ZeroCount => error_codes::ZERO_COUNT,
+            RangeRequestError::StepTooLarge => error_codes::STEP_TOO_LARGE,
+        }
+    }
+}
+
+/// Validates a BeaconBlockRoots request, returning the request which should be served.
+///
+/// The count is capped at `MAX_BLOCK_ROOTS_PER_REQUEST` and a range starting before
+/// `genesis_slot` is clamped to start at it.
+pub fn validate_block_roots_request(
+    request: BeaconBlockRootsRequest,
+    genesis_slot: Slot,
+) -> Result<BeaconBlockRootsRequest, RangeRequestError> {
+    if request.count == 0 {
+        return Err(RangeRequestError::ZeroCount);
+    }
+
+    // slot subtraction saturates at zero
+    let before_genesis = (genesis_slot - request.start_slot).as_u64();
+
+    Ok(BeaconBlockRootsRequest {
+        start_slot: std::cmp::max(request.start_slot, genesis_slot),
+        count: std::cmp::min(
+            request.count.saturating_sub(before_genesis),
+            MAX_BLOCK_ROOTS_PER_REQUEST,
+        ),
+    })
+}
+
+/// Validates a BeaconBlockHeaders request, returning the request which should be served.
+///
+/// The number of headers is capped at `MAX_HEADERS_PER_REQUEST` and the start slot is clamped to
+/// `genesis_slot`.
+pub fn validate_block_headers_request(
+    request: BeaconBlockHeadersRequest,
+    genesis_slot: Slot,
+) -> Result<BeaconBlockHeadersRequest, RangeRequestError> {
+    if request.max_headers == 0 {
+        return Err(RangeRequestError::ZeroCount);
+    }
+    if request.skip_slots > MAX_SKIP_SLOTS {
+        return Err(RangeRequestError::StepTooLarge);
+    }
+
+    Ok(BeaconBlockHeadersRequest {
+        start_slot: std::cmp::max(request.start_slot, genesis_slot),
+        max_headers: std::cmp::min(request.max_headers, MAX_HEADERS_PER_REQUEST),
+        ..request
+    })
+}
+
+/// Validates a BeaconBlockBodies request, returning the request which should be served.
+///
+/// Roots beyond the first `MAX_BODIES_PER_REQUEST` are ignored.
+pub fn validate_block_bodies_request(
+    mut request: BeaconBlockBodiesRequest,
+) -> Result<BeaconBlockBodiesRequest, RangeRequestError> {
+    if request.block_roots.is_empty() {
+        return Err(RangeRequestError::ZeroCount);
+    }
+
+    request.block_roots.truncate(MAX_BODIES_PER_REQUEST);
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::Hash256;
+
+    const GENESIS_SLOT: u64 = 100;
+
+    fn roots_request(start_slot: u64, count: u64) -> BeaconBlockRootsRequest {
+        BeaconBlockRootsRequest {
+            start_slot: Slot::new(start_slot),
+            count,
+        }
+    }
+
+    fn headers_request(
+        start_slot: u64,
+        max_headers: u64,
+        skip_slots: u64,
+    ) -> BeaconBlockHeadersRequest {
+        BeaconBlockHeadersRequest {
+            start_root: Hash256::repeat_byte(1),
+            start_slot: Slot::new(start_slot),
+            max_headers,
+            skip_slots,
+        }
+    }
+
+    #[test]
+    fn block_roots_requests_are_clamped_to_genesis_and_capped() {
+        let genesis_slot = Slot::new(GENESIS_SLOT);
+        assert_eq!(
+            validate_block_roots_request(roots_request(GENESIS_SLOT, 0), genesis_slot).err(),
+            Some(RangeRequestError::ZeroCount)
+        );
+
+        let request = validate_block_roots_request(roots_request(110, 5), genesis_slot).unwrap();
+        assert_eq!((request.start_slot, request.count), (Slot::new(110), 5));
+
+        // the slots before genesis are not counted
+        let request = validate_block_roots_request(roots_request(90, 15), genesis_slot).unwrap();
+        assert_eq!((request.start_slot, request.count), (genesis_slot, 5));
+        let request = validate_block_roots_request(roots_request(0, 10), genesis_slot).unwrap();
+        assert_eq!((request.start_slot, request.count), (genesis_slot, 0));
+
+        let request =
+            validate_block_roots_request(roots_request(110, u64::max_value()), genesis_slot)
+                .unwrap();
+        assert_eq!(request.count, MAX_BLOCK_ROOTS_PER_REQUEST);
+    }
+
+    #[test]
+    fn block_headers_requests_are_clamped_to_genesis_and_capped() {
+        let genesis_slot = Slot::new(GENESIS_SLOT);
+        assert_eq!(
+            validate_block_headers_request(headers_request(110, 0, 0), genesis_slot).err(),
+            Some(RangeRequestError::ZeroCount)
+        );
+        assert_eq!(
+            validate_block_headers_request(
+                headers_request(110, 1, MAX_SKIP_SLOTS + 1),
+                genesis_slot
+            )
+            .err(),
+            Some(RangeRequestError::StepTooLarge)
+        );
+
+        let request = validate_block_headers_request(
+            headers_request(50, 1_000, MAX_SKIP_SLOTS),
+            genesis_slot,
+        )
+        .unwrap();
+        assert_eq!(request.start_root, Hash256::repeat_byte(1));
+        assert_eq!(request.start_slot, genesis_slot);
+        assert_eq!(request.max_headers, MAX_HEADERS_PER_REQUEST);
+        assert_eq!(request.skip_slots, MAX_SKIP_SLOTS);
+    }
+
+    #[test]
+    fn block_bodies_requests_are_truncated() {
+        let empty = BeaconBlockBodiesRequest {
+            block_roots: vec![],
+        };
+        assert_eq!(
+            validate_block_bodies_request(empty).err(),
+            Some(RangeRequestError::ZeroCount)
+        );
+
+        let block_roots: Vec<Hash256> = (0..MAX_BODIES_PER_REQUEST as u64 + 5)
+            .map(Hash256::from_low_u64_be)
+            .collect();
+        let request = validate_block_bodies_request(BeaconBlockBodiesRequest {
+            block_roots: block_roots.clone(),
+        })
+        .unwrap();
+        assert_eq!(request.block_roots, &block_roots[..MAX_BODIES_PER_REQUEST]);
+    }
+
+    #[test]
+    fn errors_are_reported_with_their_codes() {
+        assert_eq!(RangeRequestError::ZeroCount.code(), error_codes::ZERO_COUNT);
+        assert_eq!(
+            RangeRequestError::StepTooLarge.code(),
+            error_codes::STEP_TOO_LARGE
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::{HashMap, VecDeque};
+use types::Hash256;
+
+/// The default number of gossip message roots remembered.
+pub const SEEN_CACHE_CAPACITY: usize = 8_192;
+
+/// Remembers the roots of recently received gossip messages, so that a message delivered again by
+/// other peers is dropped rather than validated against the chain a second time.
+///
+/// Holds at most `capacity` roots, evicting the least recently seen first.
+pub struct SeenCache {
+    capacity: usize,
+    /// Incremented on each observation, to order roots by use.
+    clock: u64,
+    /// The time each root was last seen.
+    last_seen: HashMap<Hash256, u64>,
+    /// Roots in the order they were seen. A root seen again is pushed again, leaving a stale
+    /// entry which is skipped on eviction.
+    order: VecDeque<(Hash256, u64)>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        SeenCache {
+            capacity,
+            clock: 0,
+            last_seen: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Records that a message with `root` has been received, returning `true` if it had been
+    /// received before.
+    pub fn observe(&mut self, root: Hash256) -> bool {
+        self.clock += 1;
+        let seen_before = self.last_seen.insert(root, self.clock).is_some();
+        self.order.push_back((root, self.clock));
+
+        while self.last_seen.len() > self.capacity {
+            match self.order.pop_front() {
+                Some((oldest, seen)) => {
+                    if self.last_seen.get(&oldest) == Some(&seen) {
+                        self.last_seen.remove(&oldest);
+                    }
+                }
+                None => break,
+            }
+        }
+        // drop stale entries, so the queue stays proportional to the capacity
+        if self.order.len() > self.capacity * 2 {
+            let last_seen = &self.last_seen;
+            self.order
+                .retain(|(root, seen)| last_seen.get(root) == Some(seen));
+        }
+
+        seen_before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(i: u64) -> Hash256 {
+        Hash256::from_low_u64_be(i)
+    }
+
+    #[test]
+    fn reports_roots_seen_before() {
+        let mut cache = SeenCache::new(4);
+        assert!(!cache.observe(root(1)));
+        assert!(cache.observe(root(1)));
+        assert!(!cache.observe(root(2)));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen() {
+        let mut cache = SeenCache::new(2);
+        cache.observe(root(1));
+        cache.observe(root(2));
+        // seeing the first root again keeps it over the second
+        cache.observe(root(1));
+        cache.observe(root(3));
+
+        assert_eq!(cache.last_seen.len(), 2);
+        assert!(cache.observe(root(1)));
+        assert!(cache.observe(root(3)));
+        assert!(!cache.observe(root(2)));
+    }
+
+    #[test]
+    fn stale_entries_do_not_accumulate() {
+        let mut cache = SeenCache::new(2);
+        for _ in 0..100 {
+            cache.observe(root(1));
+        }
+        assert_eq!(cache.last_seen.len(), 1);
+        assert!(cache.order.len() <= 4);
+    }
+}
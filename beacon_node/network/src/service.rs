@@ -1,69 +1,283 @@
+use crate::audit_log::{AuditLog, PeerEvent};
 use crate::beacon_chain::BeaconChain;
+use crate::connection_manager::{peer_id_of, ConnectionManager, HEARTBEAT_INTERVAL};
 use crate::error;
 use crate::message_handler::{HandlerMessage, MessageHandler};
+use crate::metrics;
+use crate::outbound_queue::{OutboundQueue, Priority};
+use crate::peer_manager::PeerManager;
+use crate::sync::{SyncConfig, SyncStatus};
+use crate::throttle::UploadThrottle;
 use crate::NetworkConfig;
-use crossbeam_channel::{unbounded as channel, Sender, TryRecvError};
+use beacon_chain::parking_lot::{Mutex, RwLock};
+use beacon_chain::TraceId;
+use eth2_libp2p::rpc::{DecodeError, GoodbyeReason, RPCMethod};
 use eth2_libp2p::RPCEvent;
 use eth2_libp2p::Service as LibP2PService;
-use eth2_libp2p::{Libp2pEvent, PeerId};
+use eth2_libp2p::{
+    HelloMessage, Libp2pEvent, MessageId, Multiaddr, PeerId, PubsubMessage,
+    BEACON_ATTESTATION_TOPIC, BEACON_BLOCK_TOPIC,
+};
 use futures::prelude::*;
-use futures::sync::oneshot;
+use futures::sync::{mpsc, oneshot};
 use futures::Stream;
-use slog::{debug, info, o, trace};
+use slog::{debug, info, o, trace, warn};
+use std::collections::HashMap;
+use std::sync::mpsc::{sync_channel, SyncSender};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::TaskExecutor;
+use tokio::timer::{Delay, Interval};
+use types::{Attestation, BeaconBlock, Topic, TopicBuilder};
+
+/// How often RPC messages held back by the upload limit are retried.
+const THROTTLE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a query of the message handler is waited on before it is abandoned, so that RPC
+/// threads are not held whilst the handler is behind.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a received gossip message may await validation by the message handler before it is
+/// no longer forwarded.
+const GOSSIP_VALIDATION_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the swarm is still polled on exit, for the GOODBYEs sent to peers to be written to
+/// their connections.
+const EXIT_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Service that handles communication between internal services and the eth2_libp2p network service.
 pub struct Service {
     //libp2p_service: Arc<Mutex<LibP2PService>>,
-    libp2p_exit: oneshot::Sender<()>,
-    network_send: crossbeam_channel::Sender<NetworkMessage>,
+    /// Stops the libp2p service once fired or dropped. Taken on shutdown.
+    libp2p_exit: Mutex<Option<oneshot::Sender<()>>>,
+    /// Completes once the message handler has stopped. Taken on shutdown.
+    handler_stopped: Mutex<Option<oneshot::Receiver<()>>>,
+    network_send: mpsc::UnboundedSender<NetworkMessage>,
+    /// The gossipsub topics subscribed to.
+    subscribed_topics: Vec<String>,
     //message_handler: MessageHandler,
-    //message_handler_send: Sender<HandlerMessage>,
+    message_handler_send: mpsc::Sender<HandlerMessage>,
+    /// The `Service` logger.
+    log: slog::Logger,
 }
 
 impl Service {
     pub fn new(
         beacon_chain: Arc<BeaconChain>,
         config: &NetworkConfig,
+        sync_config: &SyncConfig,
         executor: &TaskExecutor,
         log: slog::Logger,
-    ) -> error::Result<(Arc<Self>, Sender<NetworkMessage>)> {
-        // build the network channel
-        let (network_send, network_recv) = channel::<NetworkMessage>();
-        // launch message handler thread
+    ) -> error::Result<(Arc<Self>, mpsc::UnboundedSender<NetworkMessage>)> {
+        // build the network channel. It is unbounded, as the message handler must never wait on
+        // the network service, which may itself be waiting on the handler. The RPC messages it
+        // carries are bounded by the outbound queue.
+        let (network_send, network_recv) = mpsc::unbounded::<NetworkMessage>();
+        let outbound_queue = OutboundQueue::default();
+        let peer_manager = Arc::new(RwLock::new(PeerManager::new(
+            config.peer_ban_duration,
+            log.new(o!("Service" => "PeerManager")),
+        )));
+        // launch message handler
         let message_handler_log = log.new(o!("Service" => "MessageHandler"));
-        let message_handler_send = MessageHandler::spawn(
+        let (message_handler_send, handler_stopped) = MessageHandler::spawn(
             beacon_chain,
             network_send.clone(),
-            executor,
+            peer_manager.clone(),
+            outbound_queue.backlog(),
+            config,
+            sync_config,
             message_handler_log,
-        )?;
+        );
 
         // launch libp2p service
         let libp2p_log = log.new(o!("Service" => "Libp2p"));
         let libp2p_service = LibP2PService::new(config.clone(), libp2p_log)?;
+        let subscribed_topics = libp2p_service.subscribed_topics().to_vec();
+        let upload_throttle = config.max_upload_bytes_per_second.map(UploadThrottle::new);
+        let audit_log = AuditLog::open(
+            config.audit_log.as_ref().map(|path| path.as_path()),
+            peer_manager.clone(),
+            log.clone(),
+        )?;
+        let mut connection_manager = ConnectionManager::new(
+            config.target_peers,
+            log.new(o!("Service" => "ConnectionManager")),
+        );
+        for address in &config.trusted_peers {
+            match peer_id_of(address) {
+                Some(peer_id) => {
+                    info!(log, "Trusted peer: {:?}", peer_id; "address" => format!("{}", address));
+                    peer_manager.write().add_trusted_peer(peer_id.clone());
+                    connection_manager.add_trusted_peer(peer_id, address.clone());
+                }
+                None => {
+                    warn!(log, "Trusted peer address has no peer id, ignoring"; "address" => format!("{}", address))
+                }
+            }
+        }
 
         // TODO: Spawn thread to handle libp2p messages and pass to message handler thread.
         let libp2p_exit = spawn_service(
             libp2p_service,
             network_recv,
-            message_handler_send,
+            outbound_queue,
+            message_handler_send.clone(),
+            upload_throttle,
+            connection_manager,
+            peer_manager,
+            audit_log,
             executor,
-            log,
+            log.clone(),
         )?;
         let network_service = Service {
-            libp2p_exit,
+            libp2p_exit: Mutex::new(Some(libp2p_exit)),
+            handler_stopped: Mutex::new(Some(handler_stopped)),
             network_send: network_send.clone(),
+            subscribed_topics,
+            message_handler_send,
+            log,
         };
 
         Ok((Arc::new(network_service), network_send))
     }
 
+    /// Shuts down the network cleanly: the message handler handles the messages already received
+    /// and says GOODBYE to its peers, then the libp2p service sends the queued messages and stops.
+    ///
+    /// Blocks until the message handler has stopped, so must not be called from a task of the
+    /// executor. Calling this more than once has no effect.
+    pub fn shutdown(&self) {
+        info!(self.log, "Network service shutting down");
+        if let Some(handler_stopped) = self.handler_stopped.lock().take() {
+            self.send_to_handler(HandlerMessage::Shutdown);
+            if handler_stopped.wait().is_err() {
+                warn!(self.log, "Message handler stopped without shutting down");
+            }
+        }
+        if let Some(libp2p_exit) = self.libp2p_exit.lock().take() {
+            // the service may already have stopped
+            let _ = libp2p_exit.send(());
+        }
+    }
+
+    /// Returns a snapshot of a peer, or `None` if it is neither connected nor known, or the
+    /// network service does not reply within `QUERY_TIMEOUT`.
+    ///
+    /// Blocks until the network service replies, so must not be called from a task of the
+    /// executor.
+    pub fn peer_info(&self, peer_id: PeerId) -> Option<PeerSnapshot> {
+        let (reply, snapshot) = sync_channel(1);
+        self.query(NetworkMessage::GetPeerInfo(peer_id, reply));
+        snapshot.recv_timeout(QUERY_TIMEOUT).unwrap_or(None)
+    }
+
+    /// Returns a snapshot of each connected peer, or `None` if the network service has stopped
+    /// or does not reply within `QUERY_TIMEOUT`.
+    ///
+    /// Blocks until the network service replies, so must not be called from a task of the
+    /// executor.
+    pub fn connected_peers(&self) -> Option<Vec<PeerSnapshot>> {
+        let (reply, snapshots) = sync_channel(1);
+        self.query(NetworkMessage::GetConnectedPeers(reply));
+        snapshots.recv_timeout(QUERY_TIMEOUT).ok()
+    }
+
+    /// Returns a snapshot of each peer known to the peer manager, connected or not, or `None` if
+    /// the network service has stopped or does not reply within `QUERY_TIMEOUT`.
+    ///
+    /// Blocks until the network service replies, so must not be called from a task of the
+    /// executor.
+    pub fn known_peers(&self) -> Option<Vec<PeerSnapshot>> {
+        let (reply, snapshots) = sync_channel(1);
+        self.query(NetworkMessage::GetKnownPeers(reply));
+        snapshots.recv_timeout(QUERY_TIMEOUT).ok()
+    }
+
+    /// Returns the progress of syncing, or `None` if the message handler has stopped or does not
+    /// reply within `QUERY_TIMEOUT`.
+    ///
+    /// Blocks until the message handler replies, so must not be called from a task of the
+    /// executor.
+    pub fn sync_status(&self) -> Option<SyncStatus> {
+        let (reply, status) = sync_channel(1);
+        // a full channel means the handler is behind, so would not reply in time
+        self.message_handler_send
+            .clone()
+            .try_send(HandlerMessage::GetSyncStatus(reply))
+            .ok()?;
+        status.recv_timeout(QUERY_TIMEOUT).ok()
+    }
+
+    /// Returns `true` if our head is close enough to the network's head for blocks to be produced
+    /// on it. See `SimpleSync::is_synced`.
+    ///
+    /// Returns `false` if the message handler does not reply within `QUERY_TIMEOUT`. Blocks until
+    /// it replies, so must not be called from a task of the executor.
+    pub fn is_synced(&self) -> bool {
+        self.sync_status().map_or(false, |status| status.is_synced)
+    }
+
+    /// Sends a query to the network service. If the service has stopped, the reply channel is
+    /// dropped and the query answered with nothing.
+    fn query(&self, message: NetworkMessage) {
+        if self.network_send.unbounded_send(message).is_err() {
+            warn!(self.log, "Could not query the network service");
+        }
+    }
+
+    /// Returns the gossipsub topics subscribed to. Messages on other topics are neither received
+    /// nor forwarded.
+    pub fn subscribed_topics(&self) -> &[String] {
+        &self.subscribed_topics
+    }
+
+    /// Pauses syncing. Peers are still handshaken but no new blocks are requested.
+    pub fn pause_sync(&self) {
+        self.send_to_handler(HandlerMessage::PauseSync);
+    }
+
+    /// Resumes syncing after it has been paused or stopped.
+    pub fn resume_sync(&self) {
+        self.send_to_handler(HandlerMessage::ResumeSync);
+    }
+
+    /// Publishes a locally produced message on gossipsub topics.
+    ///
+    /// Published messages are never delayed by the upload limit.
+    pub fn publish(&self, topics: Vec<Topic>, message: Vec<u8>) {
+        self.network_send
+            .unbounded_send(NetworkMessage::Publish { topics, message })
+            .unwrap_or_else(|_| warn!(self.log, "Could not send message to the network service"));
+    }
+
+    /// Publishes a locally produced block on the beacon block topic.
+    pub fn publish_block(&self, block: BeaconBlock) {
+        let topic = TopicBuilder::new(BEACON_BLOCK_TOPIC).build();
+        self.publish(vec![topic], PubsubMessage::Block(block).to_bytes());
+    }
+
+    /// Publishes a locally produced attestation on the beacon attestation topic.
+    pub fn publish_attestation(&self, attestation: Attestation) {
+        let topic = TopicBuilder::new(BEACON_ATTESTATION_TOPIC).build();
+        self.publish(
+            vec![topic],
+            PubsubMessage::Attestation(attestation).to_bytes(),
+        );
+    }
+
+    /// Sends a message to the message handler without waiting, so may be called from a task of
+    /// the executor.
+    ///
+    /// Each clone of the sender may queue one message beyond the capacity of the channel, so these
+    /// occasional messages from the operator are queued even whilst the handler is behind.
+    fn send_to_handler(&self, message: HandlerMessage) {
+        if self.message_handler_send.clone().try_send(message).is_err() {
+            warn!(self.log, "Could not send message to the message handler");
+        }
+    }
+
     // TODO: Testing only
     pub fn send_message(&self) {
         self.network_send
-            .send(NetworkMessage::Send(
+            .unbounded_send(NetworkMessage::Send(
                 PeerId::random(),
                 OutgoingMessage::NotifierTest,
             ))
@@ -73,8 +287,13 @@ impl Service {
 
 fn spawn_service(
     libp2p_service: LibP2PService,
-    network_recv: crossbeam_channel::Receiver<NetworkMessage>,
-    message_handler_send: crossbeam_channel::Sender<HandlerMessage>,
+    network_recv: mpsc::UnboundedReceiver<NetworkMessage>,
+    outbound_queue: OutboundQueue,
+    message_handler_send: mpsc::Sender<HandlerMessage>,
+    upload_throttle: Option<UploadThrottle>,
+    connection_manager: ConnectionManager,
+    peer_manager: Arc<RwLock<PeerManager>>,
+    audit_log: AuditLog,
     executor: &TaskExecutor,
     log: slog::Logger,
 ) -> error::Result<oneshot::Sender<()>> {
@@ -85,11 +304,15 @@ fn spawn_service(
         network_service(
             libp2p_service,
             network_recv,
+            outbound_queue,
             message_handler_send,
+            upload_throttle,
+            connection_manager,
+            peer_manager,
+            audit_log,
+            exit_rx,
             log.clone(),
         )
-        // allow for manual termination
-        .select(exit_rx.then(|_| Ok(())))
         .then(move |_| {
             info!(log.clone(), "Network service shutdown");
             Ok(())
@@ -101,25 +324,116 @@ fn spawn_service(
 
 fn network_service(
     mut libp2p_service: LibP2PService,
-    network_recv: crossbeam_channel::Receiver<NetworkMessage>,
-    message_handler_send: crossbeam_channel::Sender<HandlerMessage>,
+    mut network_recv: mpsc::UnboundedReceiver<NetworkMessage>,
+    mut outbound_queue: OutboundQueue,
+    mut message_handler_send: mpsc::Sender<HandlerMessage>,
+    mut upload_throttle: Option<UploadThrottle>,
+    mut connection_manager: ConnectionManager,
+    peer_manager: Arc<RwLock<PeerManager>>,
+    mut audit_log: AuditLog,
+    mut exit: oneshot::Receiver<()>,
     log: slog::Logger,
 ) -> impl futures::Future<Item = (), Error = eth2_libp2p::error::Error> {
+    let mut throttle_retry = Interval::new_interval(THROTTLE_RETRY_INTERVAL);
+    let mut heartbeat = Interval::new_interval(HEARTBEAT_INTERVAL);
+    // received gossip awaiting validation by the message handler, by the trace id it was passed
+    // to the handler with
+    let mut unvalidated_gossip: HashMap<TraceId, (MessageId, PeerId, Instant)> = HashMap::new();
+    // set on exit, after which the task completes once peers have disconnected or it fires
+    let mut exit_deadline: Option<Delay> = None;
+
     futures::future::poll_fn(move || -> Result<_, eth2_libp2p::error::Error> {
-        // poll the swarm
-        loop {
+        // on exit, only the messages already queued are sent. The exit fires when signalled, or
+        // when the `Service` is dropped. The message handler only stops when shutting down, so
+        // once it has, nothing more is passed on from the swarm either.
+        let exiting = match exit.poll() {
+            Ok(Async::NotReady) => message_handler_send.poll_ready().is_err(),
+            Ok(Async::Ready(())) | Err(_) => true,
+        };
+
+        // maintain the target peer count
+        while let Ok(Async::Ready(Some(_))) = heartbeat.poll() {
+            if exiting {
+                break;
+            }
+            unvalidated_gossip
+                .retain(|_, (_, _, received)| received.elapsed() < GOSSIP_VALIDATION_TIMEOUT);
+            // a handler which is behind misses the heartbeat rather than queueing it
+            if let Ok(Async::Ready(())) = message_handler_send.poll_ready() {
+                message_handler_send
+                    .try_send(HandlerMessage::Heartbeat)
+                    .map_err(|_| "failed to send heartbeat to handler")?;
+            }
+            let (to_prune, to_dial) = {
+                let peer_manager = peer_manager.read();
+                (
+                    connection_manager.peers_to_prune(&peer_manager),
+                    connection_manager.addresses_to_dial(&peer_manager),
+                )
+            };
+            for peer_id in to_prune {
+                debug!(log, "Pruning peer: {:?}", peer_id);
+                audit_log.record(&peer_id, PeerEvent::Goodbye(GoodbyeReason::TooManyPeers));
+                libp2p_service.disconnect_peer(peer_id, GoodbyeReason::TooManyPeers);
+            }
+            for address in to_dial {
+                debug!(log, "Redialing: {}", address);
+                libp2p_service.dial(address);
+            }
+            // discovered peers are dialed on later heartbeats
+            if connection_manager.needs_peers() {
+                libp2p_service.find_peers();
+            }
+        }
+
+        // poll the swarm whilst the message handler has capacity. When it falls behind, messages
+        // are left to back up in libp2p and the task is woken once the handler catches up.
+        while !exiting {
+            match message_handler_send.poll_ready() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => {
+                    metrics::HANDLER_BACKPRESSURE.inc();
+                    break;
+                }
+                Err(_) => break,
+            }
             match libp2p_service.poll() {
                 Ok(Async::Ready(Some(event))) => match event {
-                    Libp2pEvent::RPC(peer_id, rpc_event) => {
-                        trace!(log, "RPC Event: RPC message received: {:?}", rpc_event);
+                    Libp2pEvent::RPC(peer_id, rpc_event, len) => {
+                        // identify the message so it can be followed through sync and the chain
+                        let trace_id = TraceId::next();
+                        trace!(
+                            log,
+                            "RPC Event: RPC message received: {:?}", rpc_event;
+                            "trace_id" => format!("{}", trace_id),
+                        );
                         message_handler_send
-                            .send(HandlerMessage::RPC(peer_id, rpc_event))
+                            .try_send(HandlerMessage::RPC(peer_id, rpc_event, len, trace_id))
                             .map_err(|_| "failed to send rpc to handler")?;
                     }
+                    Libp2pEvent::InvalidRPC(peer_id, invalid) => {
+                        let message_type = match invalid.method_id {
+                            Some(method_id) => format!("rpc/{:?}", RPCMethod::from(method_id)),
+                            None => "rpc/unknown".to_string(),
+                        };
+                        message_handler_send
+                            .try_send(decode_error_message(peer_id, message_type, invalid.error))
+                            .map_err(|_| "failed to send decode error to handler")?;
+                    }
                     Libp2pEvent::PeerDialed(peer_id) => {
                         debug!(log, "Peer Dialed: {:?}", peer_id);
+                        connection_manager.peer_connected(peer_id.clone());
+                        audit_log.record(&peer_id, PeerEvent::Dialed);
                         message_handler_send
-                            .send(HandlerMessage::PeerDialed(peer_id))
+                            .try_send(HandlerMessage::PeerDialed(peer_id))
+                            .map_err(|_| "failed to send rpc to handler")?;
+                    }
+                    Libp2pEvent::PeerDisconnected(peer_id) => {
+                        debug!(log, "Peer Disconnected: {:?}", peer_id);
+                        connection_manager.peer_disconnected(&peer_id);
+                        audit_log.record(&peer_id, PeerEvent::Disconnected);
+                        message_handler_send
+                            .try_send(HandlerMessage::PeerDisconnected(peer_id))
                             .map_err(|_| "failed to send rpc to handler")?;
                     }
                     Libp2pEvent::Identified(peer_id, info) => {
@@ -127,11 +441,42 @@ fn network_service(
                             log,
                             "We have identified peer: {:?} with {:?}", peer_id, info
                         );
+                        // identification happens on every connection, inbound or outbound
+                        connection_manager.set_addresses(&peer_id, info.listen_addrs);
+                        audit_log.record(&peer_id, PeerEvent::Identified);
+                        peer_manager.write().peer_connected(&peer_id);
+                        connection_manager.peer_connected(peer_id);
+                    }
+                    Libp2pEvent::PeerDiscovered(peer_id, addresses) => {
+                        connection_manager.add_addresses(&peer_id, addresses);
+                    }
+                    Libp2pEvent::PubsubMessage {
+                        id,
+                        source,
+                        message,
+                        ..
+                    } => {
+                        let trace_id = TraceId::next();
+                        trace!(
+                            log,
+                            "Gossip message received: {:?}", message;
+                            "trace_id" => format!("{}", trace_id),
+                        );
+                        unvalidated_gossip.insert(trace_id, (id, source.clone(), Instant::now()));
+                        message_handler_send
+                            .try_send(HandlerMessage::PubsubMessage(source, message, trace_id))
+                            .map_err(|_| "failed to send gossip message to handler")?;
+                    }
+                    Libp2pEvent::InvalidGossip {
+                        source,
+                        topics,
+                        error,
+                    } => {
+                        let message_type = format!("gossip/{}", topics.join(","));
+                        message_handler_send
+                            .try_send(decode_error_message(source, message_type, error))
+                            .map_err(|_| "failed to send decode error to handler")?;
                     }
-                    Libp2pEvent::Message(m) => debug!(
-                        libp2p_service.log,
-                        "Network Service: Message received: {}", m
-                    ),
                 },
                 Ok(Async::Ready(None)) => unreachable!("Stream never ends"),
                 Ok(Async::NotReady) => break,
@@ -141,46 +486,276 @@ fn network_service(
         // poll the network channel
         // TODO: refactor - combine poll_fn's?
         loop {
-            match network_recv.try_recv() {
+            match network_recv.poll() {
                 // TODO: Testing message - remove
-                Ok(NetworkMessage::Send(peer_id, outgoing_message)) => {
+                Ok(Async::Ready(Some(NetworkMessage::Send(peer_id, outgoing_message)))) => {
                     match outgoing_message {
-                        OutgoingMessage::RPC(rpc_event) => {
-                            trace!(log, "Sending RPC Event: {:?}", rpc_event);
-                            //TODO: Make swarm private
-                            //TODO: Implement correct peer id topic message handling
-                            libp2p_service.swarm.send_rpc(peer_id, rpc_event);
+                        OutgoingMessage::RPC(rpc_event, len) => {
+                            if let Err(priority) = outbound_queue.push(peer_id, rpc_event, len) {
+                                debug!(
+                                    log,
+                                    "Outbound {:?} RPC queue full, message dropped", priority
+                                );
+                                metrics::RPC_SENDS_DROPPED.inc();
+                            }
                         }
                         OutgoingMessage::NotifierTest => {
                             debug!(log, "Received message from notifier");
                         }
                     };
                 }
-                Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => {
+                Ok(Async::Ready(Some(NetworkMessage::Disconnect(peer_id, reason)))) => {
+                    debug!(log, "Disconnecting peer: {:?}", peer_id; "reason" => format!("{:?}", reason));
+                    audit_log.record(&peer_id, PeerEvent::Goodbye(reason));
+                    libp2p_service.disconnect_peer(peer_id, reason);
+                }
+                Ok(Async::Ready(Some(NetworkMessage::Ban(peer_id, duration, reason)))) => {
+                    info!(
+                        log,
+                        "Banning peer: {:?}", peer_id;
+                        "seconds" => duration.as_secs(),
+                        "reason" => reason,
+                    );
+                    audit_log.record(&peer_id, PeerEvent::Banned(duration, reason));
+                    libp2p_service.ban_peer(peer_id, duration);
+                }
+                Ok(Async::Ready(Some(NetworkMessage::GetPeerInfo(peer_id, reply)))) => {
+                    let snapshot =
+                        peer_snapshot(&peer_id, &connection_manager, &peer_manager.read());
+                    // the requester may have given up waiting
+                    let _ = reply.send(snapshot);
+                }
+                Ok(Async::Ready(Some(NetworkMessage::GetConnectedPeers(reply)))) => {
+                    let snapshots = {
+                        let peer_manager = peer_manager.read();
+                        connection_manager
+                            .connected_peers()
+                            .filter_map(|peer_id| {
+                                peer_snapshot(peer_id, &connection_manager, &peer_manager)
+                            })
+                            .collect()
+                    };
+                    let _ = reply.send(snapshots);
+                }
+                Ok(Async::Ready(Some(NetworkMessage::GetKnownPeers(reply)))) => {
+                    let snapshots = {
+                        let peer_manager = peer_manager.read();
+                        peer_manager
+                            .peers()
+                            .filter_map(|(peer_id, _)| {
+                                peer_snapshot(peer_id, &connection_manager, &peer_manager)
+                            })
+                            .collect()
+                    };
+                    let _ = reply.send(snapshots);
+                }
+                Ok(Async::Ready(Some(NetworkMessage::HandlerRestarted))) => {
+                    let peers = connection_manager.connected_peers().cloned().collect();
+                    // each sender has a slot of its own, so a fresh clone never waits on the
+                    // handler
+                    let _ = message_handler_send
+                        .clone()
+                        .try_send(HandlerMessage::ConnectedPeers(peers));
+                }
+                Ok(Async::Ready(Some(NetworkMessage::Propagate(trace_id)))) => {
+                    if let Some((id, source, _)) = unvalidated_gossip.remove(&trace_id) {
+                        libp2p_service.propagate(&id, &source);
+                    }
+                }
+                Ok(Async::Ready(Some(NetworkMessage::Discard(trace_id)))) => {
+                    unvalidated_gossip.remove(&trace_id);
+                }
+                Ok(Async::Ready(Some(NetworkMessage::Publish { topics, message }))) => {
+                    // locally produced messages are prioritized and never delayed
+                    if let Some(throttle) = upload_throttle.as_mut() {
+                        throttle.force_consume((message.len() * topics.len()) as u64);
+                    }
+                    trace!(log, "Publishing message on topics: {:?}", topics);
+                    libp2p_service.swarm.publish(topics, message);
+                }
+                Ok(Async::NotReady) => break,
+                Ok(Async::Ready(None)) | Err(()) => {
                     return Err(eth2_libp2p::error::Error::from(
                         "Network channel disconnected",
                     ));
                 }
             }
         }
+        // send RPC messages, highest priority first, whilst the upload limit permits. Control
+        // messages are never delayed.
+        while let Some((peer_id, rpc_event, len, priority)) = outbound_queue.pop() {
+            let permitted = upload_throttle.as_mut().map_or(true, |throttle| {
+                if priority == Priority::Control {
+                    throttle.force_consume(len);
+                    true
+                } else {
+                    throttle.try_consume(len)
+                }
+            });
+            if !permitted {
+                metrics::RPC_SENDS_DEFERRED.inc();
+                outbound_queue.push_front(peer_id, rpc_event, len, priority);
+                break;
+            }
+            trace!(log, "Sending RPC Event: {:?}", rpc_event);
+            outbound_queue.on_sent(&rpc_event, priority);
+            //TODO: Make swarm private
+            //TODO: Implement correct peer id topic message handling
+            libp2p_service.swarm.send_rpc(peer_id, rpc_event);
+        }
+        // wake up to retry any messages held back by the upload limit
+        if !outbound_queue.is_empty() {
+            while let Ok(Async::Ready(Some(_))) = throttle_retry.poll() {}
+        }
+        if exiting {
+            // the swarm writes queued messages to the connections only whilst polled, so it is
+            // polled, discarding what is received, until the peers sent a GOODBYE have
+            // disconnected
+            loop {
+                match libp2p_service.poll() {
+                    Ok(Async::Ready(Some(Libp2pEvent::PeerDisconnected(peer_id)))) => {
+                        connection_manager.peer_disconnected(&peer_id);
+                    }
+                    Ok(Async::Ready(Some(_))) => {}
+                    Ok(Async::NotReady) | Ok(Async::Ready(None)) | Err(_) => break,
+                }
+            }
+            let flushed =
+                outbound_queue.is_empty() && connection_manager.connected_peers().next().is_none();
+            let deadline = exit_deadline
+                .get_or_insert_with(|| Delay::new(Instant::now() + EXIT_FLUSH_TIMEOUT));
+            return match deadline.poll() {
+                Ok(Async::NotReady) if !flushed => Ok(Async::NotReady),
+                _ => Ok(Async::Ready(())),
+            };
+        }
         Ok(Async::NotReady)
     })
 }
 
 /// Types of messages that the network service can receive.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum NetworkMessage {
     /// Send a message to libp2p service.
     //TODO: Define typing for messages across the wire
     Send(PeerId, OutgoingMessage),
+    /// Send a Goodbye to a peer, disconnecting from it.
+    Disconnect(PeerId, GoodbyeReason),
+    /// Disconnect from a peer and refuse connections from it for a duration, for the reason
+    /// given.
+    Ban(PeerId, Duration, &'static str),
+    /// Publish a locally produced message on gossipsub topics.
+    Publish {
+        topics: Vec<Topic>,
+        message: Vec<u8>,
+    },
+    /// Forward the received gossip message passed to the message handler with a `TraceId` to our
+    /// other peers, as it has been validated.
+    Propagate(TraceId),
+    /// Forget the received gossip message passed to the message handler with a `TraceId`, as it
+    /// is not to be forwarded.
+    Discard(TraceId),
+    /// Reply with a snapshot of a peer, or `None` if it is neither connected nor known to the
+    /// peer manager.
+    GetPeerInfo(PeerId, SyncSender<Option<PeerSnapshot>>),
+    /// Reply with a snapshot of each connected peer.
+    GetConnectedPeers(SyncSender<Vec<PeerSnapshot>>),
+    /// Reply with a snapshot of each peer known to the peer manager.
+    GetKnownPeers(SyncSender<Vec<PeerSnapshot>>),
+    /// The message handler has been restarted, and is sent the peers which remain connected.
+    HandlerRestarted,
+}
+
+/// A snapshot of what is known of a peer, taken by the network service for reporting elsewhere.
+#[derive(Debug, Clone)]
+pub struct PeerSnapshot {
+    pub peer_id: PeerId,
+    pub connected: bool,
+    pub trusted: bool,
+    pub score: i64,
+    /// The time until the peer's ban expires, if it is banned.
+    pub ban_remaining: Option<Duration>,
+    /// The round-trip time of the most recent PING to the peer.
+    pub latency: Option<Duration>,
+    /// The most recent HELLO messages received from the peer, oldest first, each with the time
+    /// since it was received.
+    pub hello_history: Vec<(HelloMessage, Duration)>,
+    /// The number of messages from the peer which failed to decode, by message type and error
+    /// kind.
+    pub decode_errors: Vec<(String, String, u64)>,
+    /// The addresses the peer is known to listen on.
+    pub addresses: Vec<Multiaddr>,
+}
+
+/// Takes a snapshot of a peer, returning `None` if it is neither connected nor known to the peer
+/// manager.
+fn peer_snapshot(
+    peer_id: &PeerId,
+    connection_manager: &ConnectionManager,
+    peer_manager: &PeerManager,
+) -> Option<PeerSnapshot> {
+    let connected = connection_manager.is_connected(peer_id);
+    let info = peer_manager.peer(peer_id);
+    if !connected && info.is_none() {
+        return None;
+    }
+
+    let now = Instant::now();
+    Some(PeerSnapshot {
+        peer_id: peer_id.clone(),
+        connected,
+        trusted: peer_manager.is_trusted(peer_id),
+        score: info.map_or(0, |info| info.score),
+        ban_remaining: info
+            .and_then(|info| info.banned_until)
+            .filter(|until| *until > now)
+            .map(|until| until.duration_since(now)),
+        latency: info.and_then(|info| info.latency),
+        hello_history: info.map_or_else(Vec::new, |info| {
+            info.hello_history
+                .iter()
+                .map(|record| (record.message.clone(), record.received.elapsed()))
+                .collect()
+        }),
+        decode_errors: info.map_or_else(Vec::new, |info| {
+            info.decode_errors
+                .iter()
+                .map(|((message_type, error_kind), count)| {
+                    (message_type.clone(), error_kind.clone(), *count)
+                })
+                .collect()
+        }),
+        addresses: connection_manager.addresses(peer_id).to_vec(),
+    })
+}
+
+/// Builds the message reporting an undecodable message to the handler, distinguishing messages
+/// too long once decompressed, which may be compression bombs.
+fn decode_error_message(
+    peer_id: PeerId,
+    message_type: String,
+    error: DecodeError,
+) -> HandlerMessage {
+    match error {
+        DecodeError::DecompressedTooLong { len, max_len } => HandlerMessage::OversizedMessage {
+            peer_id,
+            message_type,
+            len,
+            max_len,
+        },
+        error => HandlerMessage::DecodeError {
+            peer_id,
+            message_type,
+            error_kind: format!("{:?}", error),
+        },
+    }
 }
 
 /// Type of outgoing messages that can be sent through the network service.
 #[derive(Debug, Clone)]
 pub enum OutgoingMessage {
-    /// Send an RPC request/response.
-    RPC(RPCEvent),
+    /// Send an RPC request/response, whose SSZ encoding is of the given length.
+    RPC(RPCEvent, u64),
     //TODO: Remove
     NotifierTest,
 }
@@ -0,0 +1,301 @@
+use super::import_queue::{ImportQueue, MAX_QUEUED_HEADERS};
+use eth2_libp2p::rpc::{
+    BeaconBlockBodiesRequest, BeaconBlockBodiesResponse, BeaconBlockHeadersRequest,
+    BeaconBlockHeadersResponse, BeaconBlockRootsRequest, BeaconBlockRootsResponse,
+};
+use eth2_libp2p::PeerId;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use types::{BeaconBlock, Hash256, Slot};
+
+/// The reason a backfill download was abandoned.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BackfillError {
+    /// The peer returned a block root outside the range requested.
+    RootOutOfRange,
+    /// The peer returned headers other than those requested.
+    UnexpectedHeaders,
+    /// The peer did not return the body of every header.
+    MissingBodies,
+    /// The blocks do not form a chain leading to the oldest block held.
+    NonLinearBlocks,
+}
+
+enum DownloadState {
+    AwaitingRoots,
+    /// Awaiting the headers of the blocks with the given roots, in slot order.
+    AwaitingHeaders {
+        roots: Vec<Hash256>,
+    },
+    AwaitingBodies,
+}
+
+/// A range of slots whose blocks are being downloaded from a single peer.
+struct Download {
+    peer_id: PeerId,
+    start_slot: Slot,
+    state: DownloadState,
+    import_queue: ImportQueue,
+    requested: Instant,
+}
+
+/// Downloads the blocks before the oldest block held, back to genesis, as when the chain was
+/// started from a checkpoint, so that we can serve them to peers.
+///
+/// Backfill is of low priority, so a single range of `slots_per_batch` slots is downloaded at a
+/// time, working backwards from the oldest block held. The blocks of a range are only accepted if
+/// each is the parent of the next and the last is the parent of the oldest block held, which was
+/// itself trusted. A range holding only skipped slots moves the search further back, and should
+/// the search reach genesis, the peers which reported those ranges are known to have lied, as
+/// the oldest block held has a parent.
+pub struct BackfillSync {
+    download: Option<Download>,
+    /// The lowest slot searched for the parent of the oldest block held.
+    searched_slot: Option<Slot>,
+    /// The peers which reported the ranges searched to hold only skipped slots.
+    empty_range_peers: HashSet<PeerId>,
+    /// Peers which failed to provide a range, only tried again if no other peer can.
+    failed_peers: HashSet<PeerId>,
+    slots_per_batch: u64,
+    /// The time after which a request awaiting a response is abandoned.
+    timeout: Duration,
+}
+
+impl BackfillSync {
+    pub fn new(slots_per_batch: u64, timeout: Duration) -> Self {
+        BackfillSync {
+            download: None,
+            searched_slot: None,
+            empty_range_peers: HashSet::new(),
+            failed_peers: HashSet::new(),
+            // the headers of a range must fit in the import queue
+            slots_per_batch: std::cmp::min(slots_per_batch, MAX_QUEUED_HEADERS as u64),
+            timeout,
+        }
+    }
+
+    /// Returns `true` if a range is being downloaded from `peer_id`.
+    pub fn is_downloading_from(&self, peer_id: &PeerId) -> bool {
+        self.download
+            .as_ref()
+            .map_or(false, |download| download.peer_id == *peer_id)
+    }
+
+    /// Returns a request for the next range, below the oldest block held at `oldest_slot`, and
+    /// the peer to send it to, unless a range is already being downloaded.
+    ///
+    /// `peers` are the idle peers which may be downloaded from, most preferred first. A download
+    /// not answered within the timeout is abandoned.
+    pub fn next_request(
+        &mut self,
+        oldest_slot: Slot,
+        genesis_slot: Slot,
+        peers: &[PeerId],
+    ) -> Option<(PeerId, BeaconBlockRootsRequest)> {
+        if let Some(download) = &self.download {
+            if download.requested.elapsed() < self.timeout {
+                return None;
+            }
+            self.abandon();
+        }
+
+        if self
+            .searched_slot
+            .map_or(false, |searched_slot| searched_slot <= genesis_slot)
+        {
+            // the search is restarted from the oldest block, without the peers which hid its parent
+            let empty_range_peers = std::mem::replace(&mut self.empty_range_peers, HashSet::new());
+            self.failed_peers.extend(empty_range_peers);
+            self.searched_slot = None;
+        }
+        let end_slot = match self.searched_slot {
+            Some(searched_slot) if searched_slot < oldest_slot => searched_slot,
+            _ => oldest_slot,
+        };
+        if end_slot <= genesis_slot {
+            return None;
+        }
+        let start_slot = std::cmp::max(
+            genesis_slot,
+            Slot::new(end_slot.as_u64().saturating_sub(self.slots_per_batch)),
+        );
+
+        let failed_peers = &self.failed_peers;
+        let peer_id = peers
+            .iter()
+            .min_by_key(|peer_id| failed_peers.contains(peer_id))?
+            .clone();
+
+        self.download = Some(Download {
+            peer_id: peer_id.clone(),
+            start_slot,
+            state: DownloadState::AwaitingRoots,
+            import_queue: ImportQueue::default(),
+            requested: Instant::now(),
+        });
+        Some((
+            peer_id,
+            BeaconBlockRootsRequest {
+                start_slot,
+                count: (end_slot - start_slot).as_u64(),
+            },
+        ))
+    }
+
+    /// Handles the block roots of the range being downloaded from `peer_id`, returning a request
+    /// for their headers.
+    ///
+    /// Returns `Ok(None)` if no range is being downloaded from the peer, or if the range holds
+    /// only skipped slots, in which case the next range is requested by `next_request`.
+    pub fn on_roots_response(
+        &mut self,
+        peer_id: &PeerId,
+        oldest_slot: Slot,
+        mut response: BeaconBlockRootsResponse,
+    ) -> Result<Option<BeaconBlockHeadersRequest>, BackfillError> {
+        let download = match self.downloading(peer_id) {
+            Some(download) => download,
+            None => return Ok(None),
+        };
+        match download.state {
+            DownloadState::AwaitingRoots => {}
+            _ => return Ok(None),
+        }
+        let start_slot = download.start_slot;
+
+        if response
+            .roots
+            .iter()
+            .any(|root_slot| root_slot.slot < start_slot || root_slot.slot >= oldest_slot)
+        {
+            return Err(self.fail(BackfillError::RootOutOfRange));
+        }
+        response.roots.sort_by_key(|root_slot| root_slot.slot);
+
+        let first = match response.roots.first() {
+            Some(first) => first.clone(),
+            None => {
+                self.download = None;
+                self.searched_slot = Some(start_slot);
+                self.empty_range_peers.insert(peer_id.clone());
+                return Ok(None);
+            }
+        };
+        download.state = DownloadState::AwaitingHeaders {
+            roots: response
+                .roots
+                .iter()
+                .map(|root_slot| root_slot.block_root)
+                .collect(),
+        };
+        download.requested = Instant::now();
+        Ok(Some(BeaconBlockHeadersRequest {
+            start_root: first.block_root,
+            start_slot: first.slot,
+            max_headers: response.roots.len() as u64,
+            skip_slots: 0,
+        }))
+    }
+
+    /// Handles the block headers of the range being downloaded from `peer_id`, returning a
+    /// request for their bodies. Returns `Ok(None)` if no range is awaiting headers from the peer.
+    pub fn on_headers_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockHeadersResponse,
+    ) -> Result<Option<BeaconBlockBodiesRequest>, BackfillError> {
+        let download = match self.downloading(peer_id) {
+            Some(download) => download,
+            None => return Ok(None),
+        };
+        let roots = match &download.state {
+            DownloadState::AwaitingHeaders { roots } => roots.clone(),
+            _ => return Ok(None),
+        };
+
+        let block_roots = match download.import_queue.insert_headers(response.headers) {
+            Some(block_roots) if block_roots == roots => block_roots,
+            _ => return Err(self.fail(BackfillError::UnexpectedHeaders)),
+        };
+        download.state = DownloadState::AwaitingBodies;
+        download.requested = Instant::now();
+        Ok(Some(BeaconBlockBodiesRequest { block_roots }))
+    }
+
+    /// Completes the range being downloaded from `peer_id` with the block bodies, returning its
+    /// blocks in slot order once verified to lead to `oldest_parent`, the parent of the oldest
+    /// block held. Returns `Ok(None)` if no range is awaiting bodies from the peer.
+    pub fn on_bodies_response(
+        &mut self,
+        peer_id: &PeerId,
+        oldest_parent: Hash256,
+        response: BeaconBlockBodiesResponse,
+    ) -> Result<Option<Vec<BeaconBlock>>, BackfillError> {
+        let download = match self.downloading(peer_id) {
+            Some(download) => download,
+            None => return Ok(None),
+        };
+        match download.state {
+            DownloadState::AwaitingBodies => {}
+            _ => return Ok(None),
+        }
+
+        let blocks = download.import_queue.complete_blocks(response.block_bodies);
+        if !download.import_queue.is_empty() {
+            return Err(self.fail(BackfillError::MissingBodies));
+        }
+        let linked = blocks.windows(2).all(|pair| {
+            pair[0].slot < pair[1].slot && pair[1].previous_block_root == pair[0].canonical_root()
+        });
+        let leads_to_oldest = blocks
+            .last()
+            .map_or(false, |block| block.canonical_root() == oldest_parent);
+        if !linked || !leads_to_oldest {
+            return Err(self.fail(BackfillError::NonLinearBlocks));
+        }
+
+        self.download = None;
+        self.searched_slot = None;
+        self.empty_range_peers.clear();
+        Ok(Some(blocks))
+    }
+
+    /// Abandons the range being downloaded from a peer which returned an error or disconnected,
+    /// returning `true` if there was such a range.
+    pub fn on_request_failed(&mut self, peer_id: &PeerId) -> bool {
+        if !self.is_downloading_from(peer_id) {
+            return false;
+        }
+        self.abandon();
+        true
+    }
+
+    /// Abandons the range being downloaded without fault of the peer, as when we stop being idle
+    /// and the peer's responses are of range sync instead.
+    pub fn cancel(&mut self) {
+        self.download = None;
+    }
+
+    fn downloading(&mut self, peer_id: &PeerId) -> Option<&mut Download> {
+        self.download
+            .as_mut()
+            .filter(|download| download.peer_id == *peer_id)
+    }
+
+    /// Abandons the range being downloaded for the reason `error`, returning `error`.
+    fn fail(&mut self, error: BackfillError) -> BackfillError {
+        self.abandon();
+        error
+    }
+
+    /// Abandons the range being downloaded, preferring another peer when it is requested again.
+    /// The search restarts from the oldest block held, as the skipped slots reported by the peer
+    /// may be false.
+    fn abandon(&mut self) {
+        if let Some(download) = self.download.take() {
+            self.failed_peers.insert(download.peer_id);
+        }
+        self.searched_slot = None;
+        self.empty_range_peers.clear();
+    }
+}
@@ -0,0 +1,288 @@
+use crate::peer_manager::{BAN_SCORE, INVALID_BLOCK_PENALTY, TOO_MANY_SKIPPED_SLOTS_PENALTY};
+use beacon_chain::{BeaconChainError, BlockProcessingOutcome, InvalidBlock};
+use types::{BeaconBlock, Slot};
+
+/// The result of importing a chain segment, such as a downloaded batch, provided by a peer.
+#[derive(Debug)]
+pub enum BatchProcessingResult {
+    /// Every block of the segment was imported or was already known.
+    Success { imported: usize },
+    /// The segment was only partly imported, `imported` blocks having been imported first.
+    Failed { imported: usize, error: SyncError },
+}
+
+/// The reason a chain segment provided by a peer could not be imported.
+#[derive(Debug)]
+pub enum SyncError {
+    /// A block of the segment is invalid, warranting the given score penalty.
+    InvalidBatch {
+        slot: Slot,
+        penalty: i64,
+        outcome: BlockProcessingOutcome,
+    },
+    /// The slots of the blocks of the segment do not strictly increase, which an honest peer
+    /// never sends.
+    NonLinearSlots { slot: Slot },
+    /// A block of the segment is not the child of the block before it, or the first block is not
+    /// a child of our chain. The peer may simply be on another fork.
+    WrongParent { slot: Slot },
+    /// The beacon chain failed to process a block, which is not the fault of the peer.
+    ExecutionError { slot: Slot, error: BeaconChainError },
+}
+
+/// The action to take against the peer which provided a chain segment that could not be
+/// imported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncPeerAction {
+    /// Decrease the score of the peer by the penalty and download the segment elsewhere.
+    Downscore(i64),
+    /// Ban the peer and download the segment elsewhere.
+    Ban,
+    /// Download the segment from another peer without penalizing this one.
+    RetryElsewhere,
+    /// Take no action, as syncing cannot continue.
+    None,
+}
+
+impl SyncPeerAction {
+    /// Returns the score penalty for the action, a ban being enough to ban a peer with a neutral
+    /// score.
+    pub fn penalty(self) -> i64 {
+        match self {
+            SyncPeerAction::Downscore(penalty) => penalty,
+            SyncPeerAction::Ban => -BAN_SCORE,
+            SyncPeerAction::RetryElsewhere | SyncPeerAction::None => 0,
+        }
+    }
+}
+
+impl SyncError {
+    /// Returns the action to take against the peer which provided the segment.
+    pub fn peer_action(&self) -> SyncPeerAction {
+        match self {
+            SyncError::InvalidBatch { penalty, .. } => SyncPeerAction::Downscore(*penalty),
+            SyncError::NonLinearSlots { .. } => SyncPeerAction::Ban,
+            SyncError::WrongParent { .. } => SyncPeerAction::RetryElsewhere,
+            SyncError::ExecutionError { .. } => SyncPeerAction::None,
+        }
+    }
+
+    /// Returns the slot of the block which could not be imported.
+    pub fn slot(&self) -> Slot {
+        match self {
+            SyncError::InvalidBatch { slot, .. }
+            | SyncError::NonLinearSlots { slot }
+            | SyncError::WrongParent { slot }
+            | SyncError::ExecutionError { slot, .. } => *slot,
+        }
+    }
+}
+
+/// Checks that each block of a segment has a later slot than, and is the child of, the block
+/// before it, so that a malformed segment is rejected before any block is processed.
+pub fn verify_linear(blocks: &[BeaconBlock]) -> Result<(), SyncError> {
+    for pair in blocks.windows(2) {
+        let (parent, child) = (&pair[0], &pair[1]);
+        if child.slot <= parent.slot {
+            return Err(SyncError::NonLinearSlots { slot: child.slot });
+        }
+        if child.previous_block_root != parent.canonical_root() {
+            return Err(SyncError::WrongParent { slot: child.slot });
+        }
+    }
+    Ok(())
+}
+
+/// Classifies the outcome of processing the block at `slot`, returning `None` if the block was
+/// imported or is not invalid but merely unprocessable by us at present.
+pub fn classify_outcome(
+    slot: Slot,
+    outcome: Result<BlockProcessingOutcome, BeaconChainError>,
+) -> Option<SyncError> {
+    match outcome {
+        Ok(BlockProcessingOutcome::ValidBlock(_))
+        | Ok(BlockProcessingOutcome::QueuedFutureBlock) => None,
+        Ok(BlockProcessingOutcome::InvalidBlock(InvalidBlock::ParentUnknown)) => {
+            Some(SyncError::WrongParent { slot })
+        }
+        Ok(outcome) => match penalty(&outcome) {
+            0 => None,
+            penalty => Some(SyncError::InvalidBatch {
+                slot,
+                penalty,
+                outcome,
+            }),
+        },
+        Err(error) => Some(SyncError::ExecutionError { slot, error }),
+    }
+}
+
+/// Returns the score penalty for the peer which sent a block with the given outcome, or zero if
+/// the block is not invalid but merely unprocessable by us at present.
+pub fn penalty(outcome: &BlockProcessingOutcome) -> i64 {
+    match outcome {
+        BlockProcessingOutcome::InvalidBlock(InvalidBlock::StateRootMismatch)
+        | BlockProcessingOutcome::InvalidBlock(InvalidBlock::PerBlockProcessingError(_)) => {
+            INVALID_BLOCK_PENALTY
+        }
+        BlockProcessingOutcome::InvalidBlock(InvalidBlock::TooManySkippedSlots { .. }) => {
+            TOO_MANY_SKIPPED_SLOTS_PENALTY
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use beacon_chain::ValidBlock;
+    use types::{ChainSpec, Hash256};
+
+    /// Returns a chain of `count` blocks, one per slot from `start_slot`.
+    fn linked_blocks(start_slot: u64, count: u64) -> Vec<BeaconBlock> {
+        let spec = ChainSpec::few_validators();
+        let mut parent_root = Hash256::zero();
+        let mut blocks = vec![];
+        for slot in start_slot..start_slot + count {
+            let mut block = BeaconBlock::empty(&spec);
+            block.slot = Slot::new(slot);
+            block.previous_block_root = parent_root;
+            parent_root = block.canonical_root();
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn linear_segments_are_verified() {
+        let blocks = linked_blocks(10, 4);
+        assert!(verify_linear(&blocks).is_ok());
+        assert!(verify_linear(&blocks[..1]).is_ok());
+        assert!(verify_linear(&[]).is_ok());
+
+        // a skipped slot is not a break in the chain
+        let mut skipped = blocks.clone();
+        skipped[3].slot = Slot::new(20);
+        assert!(verify_linear(&skipped).is_ok());
+
+        let mut repeated = blocks.clone();
+        repeated[2].slot = repeated[1].slot;
+        match verify_linear(&repeated) {
+            Err(SyncError::NonLinearSlots { slot }) => assert_eq!(slot, Slot::new(11)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut orphan = blocks.clone();
+        orphan[2].previous_block_root = Hash256::from_low_u64_be(1);
+        match verify_linear(&orphan) {
+            Err(SyncError::WrongParent { slot }) => assert_eq!(slot, Slot::new(12)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn outcomes_are_classified() {
+        let slot = Slot::new(10);
+
+        assert!(classify_outcome(
+            slot,
+            Ok(BlockProcessingOutcome::ValidBlock(ValidBlock::Processed))
+        )
+        .is_none());
+        assert!(classify_outcome(slot, Ok(BlockProcessingOutcome::QueuedFutureBlock)).is_none());
+        // a block from the future is not invalid, we may simply be behind
+        assert!(classify_outcome(
+            slot,
+            Ok(BlockProcessingOutcome::InvalidBlock(
+                InvalidBlock::FutureSlot
+            ))
+        )
+        .is_none());
+
+        match classify_outcome(
+            slot,
+            Ok(BlockProcessingOutcome::InvalidBlock(
+                InvalidBlock::ParentUnknown,
+            )),
+        ) {
+            Some(SyncError::WrongParent { slot: error_slot }) => assert_eq!(error_slot, slot),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match classify_outcome(
+            slot,
+            Ok(BlockProcessingOutcome::InvalidBlock(
+                InvalidBlock::StateRootMismatch,
+            )),
+        ) {
+            Some(SyncError::InvalidBatch { penalty, .. }) => {
+                assert_eq!(penalty, INVALID_BLOCK_PENALTY)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match classify_outcome(
+            slot,
+            Ok(BlockProcessingOutcome::InvalidBlock(
+                InvalidBlock::TooManySkippedSlots {
+                    parent_slot: Slot::new(0),
+                    block_slot: slot,
+                },
+            )),
+        ) {
+            Some(SyncError::InvalidBatch { penalty, .. }) => {
+                assert_eq!(penalty, TOO_MANY_SKIPPED_SLOTS_PENALTY)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match classify_outcome(
+            slot,
+            Err(BeaconChainError::DBError("disk full".to_string())),
+        ) {
+            Some(SyncError::ExecutionError {
+                slot: error_slot, ..
+            }) => {
+                assert_eq!(error_slot, slot)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn peers_are_only_penalised_for_their_own_faults() {
+        let slot = Slot::new(10);
+
+        let invalid = SyncError::InvalidBatch {
+            slot,
+            penalty: INVALID_BLOCK_PENALTY,
+            outcome: BlockProcessingOutcome::InvalidBlock(InvalidBlock::StateRootMismatch),
+        };
+        assert_eq!(
+            invalid.peer_action(),
+            SyncPeerAction::Downscore(INVALID_BLOCK_PENALTY)
+        );
+        assert_eq!(invalid.peer_action().penalty(), INVALID_BLOCK_PENALTY);
+
+        let non_linear = SyncError::NonLinearSlots { slot };
+        assert_eq!(non_linear.peer_action(), SyncPeerAction::Ban);
+        assert_eq!(non_linear.peer_action().penalty(), -BAN_SCORE);
+
+        // the peer may be on another fork
+        let unconnected = SyncError::WrongParent { slot };
+        assert_eq!(unconnected.peer_action(), SyncPeerAction::RetryElsewhere);
+        assert_eq!(unconnected.peer_action().penalty(), 0);
+
+        // the fault is ours
+        let error = SyncError::ExecutionError {
+            slot,
+            error: BeaconChainError::DBError("disk full".to_string()),
+        };
+        assert_eq!(error.peer_action(), SyncPeerAction::None);
+        assert_eq!(error.peer_action().penalty(), 0);
+
+        for error in &[invalid, non_linear, unconnected, error] {
+            assert_eq!(error.slot(), slot);
+        }
+    }
+}
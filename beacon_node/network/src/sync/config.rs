@@ -0,0 +1,229 @@
+use super::import_queue::MAX_QUEUED_HEADERS;
+use serde::Deserializer;
+use serde_derive::Deserialize;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+/// The default number of slots that we can import blocks ahead of us, before going into full
+/// Sync mode.
+pub const DEFAULT_SLOT_IMPORT_TOLERANCE: u64 = 100;
+/// The default number of slots below the import tolerance that the best known peer must fall
+/// within before we stop downloading.
+pub const DEFAULT_SLOT_IMPORT_HYSTERESIS: u64 = 20;
+/// The default number of epochs of blocks requested in a single batch whilst downloading.
+pub const DEFAULT_EPOCHS_PER_BATCH: u64 = 1;
+/// The default number of batches which may be downloading or awaiting import at once.
+pub const DEFAULT_MAX_PENDING_BATCHES: usize = 8;
+/// The default number of slots our head may be behind the network's head whilst we consider
+/// ourselves synced.
+pub const DEFAULT_SYNCED_TOLERANCE: u64 = 8;
+/// The default number of times a batch may fail to import before the chain it was downloaded
+/// from is considered invalid.
+pub const DEFAULT_MAX_BATCH_IMPORT_FAILURES: u32 = 3;
+/// The default time after which an unanswered batch is requested again.
+pub const DEFAULT_BATCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// The default maximum number of gossip blocks held until their slot starts or their parent is
+/// imported.
+pub const DEFAULT_MAX_PENDING_BLOCKS: usize = 64;
+/// The default maximum number of gossip blocks held from a single peer.
+pub const DEFAULT_MAX_PENDING_BLOCKS_PER_PEER: usize = 16;
+/// The default maximum number of slots ahead of the present slot that a gossip block is held for.
+pub const DEFAULT_MAX_FUTURE_SLOTS: u64 = 4;
+/// The default time after which a held gossip block which has not become importable is
+/// discarded.
+pub const DEFAULT_PENDING_BLOCK_TIMEOUT: Duration = Duration::from_secs(120);
+/// The default number of slots whose block roots are requested at once whilst searching for the
+/// ancestors of a gossip block.
+pub const DEFAULT_PARENT_LOOKUP_WINDOW: u64 = 16;
+/// The default maximum number of slots below a gossip block which are searched for an ancestor we
+/// know.
+pub const DEFAULT_MAX_PARENT_LOOKUP_DEPTH: u64 = 64;
+/// The default maximum number of parent lookups in progress at once.
+pub const DEFAULT_MAX_PARENT_LOOKUPS: usize = 4;
+/// The default time after which a parent lookup awaiting a response is abandoned.
+pub const DEFAULT_PARENT_LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration for the syncing of the beacon chain.
+///
+/// May be read from a JSON file by `Config::from_file`, where any field not given takes its
+/// default and durations are given in whole seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Start downloading blocks once a peer is more than this many slots ahead of us.
+    pub slot_import_tolerance: u64,
+    /// Once downloading, only return to idle when no peer is more than
+    /// `slot_import_tolerance - slot_import_hysteresis` slots ahead of us. Prevents the sync state
+    /// flapping when peers hover around the tolerance.
+    pub slot_import_hysteresis: u64,
+    /// The number of epochs of blocks requested from a peer in a single batch.
+    pub epochs_per_batch: u64,
+    /// The maximum number of batches downloading or awaiting import at once. Each peer downloads
+    /// one batch at a time, so this bounds both the peers downloaded from in parallel and the
+    /// blocks held whilst earlier batches complete.
+    pub max_pending_batches: usize,
+    /// The time after which an unanswered batch is abandoned and requested again.
+    #[serde(deserialize_with = "duration_from_secs")]
+    pub batch_timeout: Duration,
+    /// We consider ourselves synced whilst our head is within this many slots of the head reached
+    /// by a majority of the peers of the chain we sync to. Blocks should not be produced
+    /// otherwise.
+    pub synced_tolerance: u64,
+    /// Once a batch has failed to import this many times, each time downloaded from another peer
+    /// where possible, the chain being synced to is considered invalid and another is chosen.
+    pub max_batch_import_failures: u32,
+    /// The maximum number of gossip blocks held until their slot starts or their parent is
+    /// imported.
+    pub max_pending_blocks: usize,
+    /// The maximum number of gossip blocks held from a single peer, so one peer cannot fill the
+    /// pending blocks.
+    pub max_pending_blocks_per_peer: usize,
+    /// The maximum number of slots ahead of the present slot that a gossip block is held for.
+    pub max_future_slots: u64,
+    /// The time after which a held gossip block which has not become importable is discarded.
+    #[serde(deserialize_with = "duration_from_secs")]
+    pub pending_block_timeout: Duration,
+    /// The number of slots whose block roots are requested at once whilst searching for the
+    /// ancestors of a gossip block. The headers of a window are queued at once, so it may not
+    /// exceed `MAX_QUEUED_HEADERS`.
+    pub parent_lookup_window: u64,
+    /// The maximum number of slots below a gossip block which are searched for an ancestor we
+    /// know. Longer distances are left to range sync.
+    pub max_parent_lookup_depth: u64,
+    /// The maximum number of parent lookups in progress at once, each from a different peer.
+    pub max_parent_lookups: usize,
+    /// The time after which a parent lookup awaiting a response is abandoned.
+    #[serde(deserialize_with = "duration_from_secs")]
+    pub parent_lookup_timeout: Duration,
+}
+
+impl Config {
+    /// Reads the configuration from the JSON file at `path`, without validating it.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Unable to open {:?}: {}", path, e))?;
+        serde_json::from_reader(file).map_err(|e| format!("Unable to parse {:?}: {}", path, e))
+    }
+
+    /// Returns an error if the configuration could not be synced with.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.slot_import_hysteresis > self.slot_import_tolerance {
+            return Err("Slot import hysteresis exceeds the slot import tolerance");
+        }
+        if self.epochs_per_batch == 0 {
+            return Err("Invalid epochs per batch");
+        }
+        if self.max_pending_batches == 0 {
+            return Err("Invalid max pending batches");
+        }
+        if self.batch_timeout == Duration::from_secs(0) {
+            return Err("Invalid batch timeout");
+        }
+        if self.max_batch_import_failures == 0 {
+            return Err("Invalid max batch import failures");
+        }
+        if self.max_pending_blocks == 0 || self.max_pending_blocks_per_peer == 0 {
+            return Err("Invalid max pending blocks");
+        }
+        if self.max_pending_blocks_per_peer > self.max_pending_blocks {
+            return Err("Max pending blocks per peer exceeds the max pending blocks");
+        }
+        if self.pending_block_timeout == Duration::from_secs(0) {
+            return Err("Invalid pending block timeout");
+        }
+        if self.parent_lookup_window == 0 || self.parent_lookup_window > MAX_QUEUED_HEADERS as u64 {
+            return Err("Invalid parent lookup window");
+        }
+        if self.max_parent_lookup_depth == 0 || self.max_parent_lookups == 0 {
+            return Err("Invalid parent lookup limits");
+        }
+        if self.parent_lookup_timeout == Duration::from_secs(0) {
+            return Err("Invalid parent lookup timeout");
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            slot_import_tolerance: DEFAULT_SLOT_IMPORT_TOLERANCE,
+            slot_import_hysteresis: DEFAULT_SLOT_IMPORT_HYSTERESIS,
+            epochs_per_batch: DEFAULT_EPOCHS_PER_BATCH,
+            max_pending_batches: DEFAULT_MAX_PENDING_BATCHES,
+            batch_timeout: DEFAULT_BATCH_TIMEOUT,
+            synced_tolerance: DEFAULT_SYNCED_TOLERANCE,
+            max_batch_import_failures: DEFAULT_MAX_BATCH_IMPORT_FAILURES,
+            max_pending_blocks: DEFAULT_MAX_PENDING_BLOCKS,
+            max_pending_blocks_per_peer: DEFAULT_MAX_PENDING_BLOCKS_PER_PEER,
+            max_future_slots: DEFAULT_MAX_FUTURE_SLOTS,
+            pending_block_timeout: DEFAULT_PENDING_BLOCK_TIMEOUT,
+            parent_lookup_window: DEFAULT_PARENT_LOOKUP_WINDOW,
+            max_parent_lookup_depth: DEFAULT_MAX_PARENT_LOOKUP_DEPTH,
+            max_parent_lookups: DEFAULT_MAX_PARENT_LOOKUPS,
+            parent_lookup_timeout: DEFAULT_PARENT_LOOKUP_TIMEOUT,
+        }
+    }
+}
+
+/// Reads a duration given in whole seconds.
+fn duration_from_secs<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    <u64 as serde::Deserialize>::deserialize(deserializer).map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_not_given_take_their_default() {
+        let config: Config =
+            serde_json::from_str(r#"{ "parent_lookup_window": 8, "batch_timeout": 6 }"#).unwrap();
+        assert_eq!(config.parent_lookup_window, 8);
+        assert_eq!(config.batch_timeout, Duration::from_secs(6));
+        assert_eq!(
+            config.max_pending_blocks_per_peer,
+            DEFAULT_MAX_PENDING_BLOCKS_PER_PEER
+        );
+        assert_eq!(config.pending_block_timeout, DEFAULT_PENDING_BLOCK_TIMEOUT);
+        assert!(config.validate().is_ok());
+
+        assert!(serde_json::from_str::<Config>(r#"{ "parent_lookup_windows": 8 }"#).is_err());
+    }
+
+    #[test]
+    fn parent_lookup_window_fits_the_import_queue() {
+        let mut config = Config::default();
+        config.parent_lookup_window = MAX_QUEUED_HEADERS as u64;
+        assert!(config.validate().is_ok());
+
+        config.parent_lookup_window += 1;
+        assert!(config.validate().is_err());
+        config.parent_lookup_window = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_zero_limits_and_timeouts() {
+        let zeroed: [fn(&mut Config); 7] = [
+            |config| config.batch_timeout = Duration::from_secs(0),
+            |config| config.max_pending_blocks = 0,
+            |config| config.max_pending_blocks_per_peer = 0,
+            |config| config.pending_block_timeout = Duration::from_secs(0),
+            |config| config.max_parent_lookup_depth = 0,
+            |config| config.max_parent_lookups = 0,
+            |config| config.parent_lookup_timeout = Duration::from_secs(0),
+        ];
+        for zero in &zeroed {
+            let mut config = Config::default();
+            zero(&mut config);
+            assert!(config.validate().is_err(), "{:?} is accepted", config);
+        }
+
+        let config: Config = serde_json::from_str(r#"{ "pending_block_timeout": 0 }"#).unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use ssz::TreeHash;
+use std::collections::HashMap;
+use types::{BeaconBlock, BeaconBlockBody, BeaconBlockHeader, Hash256};
+
+/// The maximum number of headers that may await their bodies at once. Downloads request no more
+/// headers than this at a time.
+pub const MAX_QUEUED_HEADERS: usize = 1_024;
+
+/// Holds the block headers received whilst syncing until their bodies arrive, at which point the
+/// full blocks are reconstructed for import.
+#[derive(Default)]
+pub struct ImportQueue {
+    /// Headers awaiting a body, keyed by block root.
+    headers: HashMap<Hash256, BeaconBlockHeader>,
+}
+
+impl ImportQueue {
+    /// Adds headers to the queue, returning the block roots of those which were not already
+    /// queued.
+    ///
+    /// Returns `None`, queuing none of the headers, if they would take the queue beyond
+    /// `MAX_QUEUED_HEADERS`. Queuing only some of them would leave blocks missing from the
+    /// download without it noticing.
+    pub fn insert_headers(&mut self, headers: Vec<BeaconBlockHeader>) -> Option<Vec<Hash256>> {
+        let mut new_headers: Vec<(Hash256, BeaconBlockHeader)> = vec![];
+        for header in headers {
+            let block_root = header.canonical_root();
+            let is_new = !self.headers.contains_key(&block_root)
+                && new_headers.iter().all(|(root, _)| *root != block_root);
+            if is_new {
+                new_headers.push((block_root, header));
+            }
+        }
+        if self.headers.len() + new_headers.len() > MAX_QUEUED_HEADERS {
+            return None;
+        }
+
+        let new_roots = new_headers.iter().map(|(root, _)| *root).collect();
+        self.headers.extend(new_headers);
+        Some(new_roots)
+    }
+
+    /// Pairs each body with its queued header, returning the reconstructed blocks ordered by slot.
+    ///
+    /// Bodies which match no queued header are discarded.
+    pub fn complete_blocks(&mut self, bodies: Vec<BeaconBlockBody>) -> Vec<BeaconBlock> {
+        let mut blocks: Vec<BeaconBlock> = bodies
+            .into_iter()
+            .filter_map(|body| {
+                let body_root = Hash256::from_slice(&body.hash_tree_root()[..]);
+                let block_root = *self
+                    .headers
+                    .iter()
+                    .find(|(_, header)| header.block_body_root == body_root)?
+                    .0;
+                let header = self.headers.remove(&block_root)?;
+                Some(header.into_block(body))
+            })
+            .collect();
+
+        blocks.sort_by_key(|block| block.slot);
+        blocks
+    }
+
+    /// Removes all queued headers.
+    pub fn clear(&mut self) {
+        self.headers.clear();
+    }
+
+    /// Returns the number of headers awaiting a body.
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Returns `true` if no headers are awaiting a body.
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+}
@@ -1,9 +1,21 @@
 /// Syncing for lighthouse.
 ///
 /// Stores the various syncing methods for the beacon chain.
+mod backfill;
+mod batch_processing;
+mod config;
+mod import_queue;
+mod parent_lookup;
+mod pending_blocks;
+mod range_sync;
 mod simple_sync;
+mod tests;
 
-pub use simple_sync::SimpleSync;
+pub use batch_processing::{BatchProcessingResult, SyncError, SyncPeerAction};
+pub use config::Config as SyncConfig;
+pub use simple_sync::{
+    PeerSummary, SimpleSync, StopReason, SyncState, SyncStatus, MAX_REQUESTED_ATTESTATIONS,
+};
 
 /// Currently implemented sync methods.
 pub enum SyncMethod {
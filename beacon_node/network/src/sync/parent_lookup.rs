@@ -0,0 +1,382 @@
+use super::import_queue::ImportQueue;
+use super::SyncConfig;
+use eth2_libp2p::rpc::{
+    BeaconBlockBodiesRequest, BeaconBlockBodiesResponse, BeaconBlockHeadersRequest,
+    BeaconBlockHeadersResponse, BeaconBlockRootsRequest, BeaconBlockRootsResponse, RPCRequest,
+};
+use eth2_libp2p::PeerId;
+use std::time::Instant;
+use types::{BeaconBlock, Hash256, Slot};
+
+/// The reason a parent lookup was abandoned.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LookupError {
+    /// The peer's chain does not include the parent it was asked for.
+    ParentNotFound,
+    /// No ancestor we know was found within `max_parent_lookup_depth` slots.
+    DepthExceeded,
+    /// The peer returned headers other than those requested.
+    UnexpectedHeaders,
+    /// The peer did not return the body of every header.
+    MissingBodies,
+    /// The ancestors do not form a chain leading to the blocks already held.
+    NonLinearBlocks,
+}
+
+enum LookupState {
+    /// Block roots below the segment are to be requested.
+    Pending,
+    AwaitingRoots,
+    /// Awaiting the headers of the ancestors with the given roots, in slot order.
+    AwaitingHeaders {
+        roots: Vec<Hash256>,
+        connects: bool,
+    },
+    /// Awaiting the bodies of the queued headers.
+    AwaitingBodies {
+        connects: bool,
+    },
+}
+
+/// A search for the ancestors of a block, from the peer which sent it.
+struct ParentLookup {
+    peer_id: PeerId,
+    /// Blocks whose parent is unknown to us, in slot order, the last being the block the lookup
+    /// started from.
+    segment: Vec<BeaconBlock>,
+    /// The lowest slot whose block root has been requested.
+    lowest_slot: Slot,
+    state: LookupState,
+    /// Headers of the ancestors awaiting their bodies.
+    import_queue: ImportQueue,
+    /// The time the last request of the lookup was sent.
+    requested: Instant,
+}
+
+impl ParentLookup {
+    /// Returns the root of the parent of the oldest block held, which we are looking for.
+    fn missing_root(&self) -> Hash256 {
+        self.segment[0].previous_block_root
+    }
+
+    /// Returns the block roots request for the next `window` slots below the blocks searched so
+    /// far, searching at most `max_depth` slots below the block the lookup started from.
+    fn roots_request(
+        &self,
+        window: u64,
+        max_depth: u64,
+    ) -> Result<BeaconBlockRootsRequest, LookupError> {
+        let top_slot = self.segment[self.segment.len() - 1].slot;
+        let floor = top_slot.as_u64().saturating_sub(max_depth);
+        if self.lowest_slot.as_u64() <= floor {
+            return Err(LookupError::DepthExceeded);
+        }
+        let start = std::cmp::max(self.lowest_slot.as_u64().saturating_sub(window), floor);
+        Ok(BeaconBlockRootsRequest {
+            start_slot: Slot::new(start),
+            count: self.lowest_slot.as_u64() - start,
+        })
+    }
+}
+
+/// Recovers the ancestors of blocks received over gossip whose parent we do not know.
+///
+/// Block roots are requested from the peer which sent the block in windows of
+/// `parent_lookup_window` slots, searching backwards for a block we know. The headers and bodies
+/// of the unknown ancestors are then downloaded and held with the block, and the whole segment
+/// is released for import, oldest first, once it connects to a known block. A lookup which
+/// reaches `max_parent_lookup_depth` slots without connecting is abandoned, long distances being
+/// left to range sync.
+///
+/// Each peer has at most one lookup at a time, so responses are matched to lookups by peer and
+/// one peer cannot exhaust `max_parent_lookups`. A lookup whose missing parent is imported whilst
+/// it is in progress is complete, its segment being returned by `take_connected`.
+pub struct ParentLookups {
+    lookups: Vec<ParentLookup>,
+    /// The segments of lookups whose missing parent was imported by other means, with the peer
+    /// which sent them.
+    connected: Vec<(PeerId, Vec<BeaconBlock>)>,
+    config: SyncConfig,
+}
+
+impl ParentLookups {
+    pub fn new(config: &SyncConfig) -> Self {
+        ParentLookups {
+            lookups: vec![],
+            connected: vec![],
+            config: config.clone(),
+        }
+    }
+
+    /// Returns `true` if a lookup may be started from `block`, sent by `peer_id`.
+    ///
+    /// A lookup may not be started if the peer already has a lookup in progress, too many
+    /// lookups are in progress, or a lookup already holds the block or its parent.
+    pub fn can_start(&self, peer_id: &PeerId, block: &BeaconBlock) -> bool {
+        if self.lookups.len() >= self.config.max_parent_lookups
+            || self.lookups.iter().any(|lookup| lookup.peer_id == *peer_id)
+        {
+            return false;
+        }
+        let block_root = block.canonical_root();
+        let already_held = self.lookups.iter().any(|lookup| {
+            lookup.missing_root() == block.previous_block_root
+                || lookup.segment.iter().any(|held| {
+                    held.previous_block_root == block_root
+                        || held.canonical_root() == block.previous_block_root
+                })
+        });
+        !already_held
+    }
+
+    /// Starts searching for the ancestors of `block`, sent by `peer_id`.
+    ///
+    /// Returns `false` if the block is ignored, as a lookup may not be started from it, see
+    /// `can_start`.
+    pub fn start(&mut self, peer_id: PeerId, block: BeaconBlock) -> bool {
+        if !self.can_start(&peer_id, &block) {
+            return false;
+        }
+
+        self.lookups.push(ParentLookup {
+            peer_id,
+            lowest_slot: block.slot,
+            segment: vec![block],
+            state: LookupState::Pending,
+            import_queue: ImportQueue::default(),
+            requested: Instant::now(),
+        });
+        true
+    }
+
+    /// Returns the block roots requests of lookups which are ready to search the next window.
+    ///
+    /// Lookups which have searched `max_parent_lookup_depth` slots are abandoned and returned
+    /// with their errors. Lookups not answered within `parent_lookup_timeout` are abandoned, the
+    /// request timeout being penalized elsewhere.
+    pub fn next_requests(&mut self) -> (Vec<(PeerId, RPCRequest)>, Vec<(PeerId, LookupError)>) {
+        let mut requests = vec![];
+        let mut failed = vec![];
+        let timeout = self.config.parent_lookup_timeout;
+        self.lookups.retain(|lookup| match lookup.state {
+            LookupState::Pending => true,
+            _ => lookup.requested.elapsed() < timeout,
+        });
+
+        for lookup in &mut self.lookups {
+            if let LookupState::Pending = lookup.state {
+                match lookup.roots_request(
+                    self.config.parent_lookup_window,
+                    self.config.max_parent_lookup_depth,
+                ) {
+                    Ok(request) => {
+                        lookup.lowest_slot = request.start_slot;
+                        lookup.state = LookupState::AwaitingRoots;
+                        lookup.requested = Instant::now();
+                        requests.push((
+                            lookup.peer_id.clone(),
+                            RPCRequest::BeaconBlockRoots(request),
+                        ));
+                    }
+                    Err(e) => failed.push((lookup.peer_id.clone(), e)),
+                }
+            }
+        }
+        for (peer_id, _) in &failed {
+            self.remove_peer(peer_id);
+        }
+        (requests, failed)
+    }
+
+    /// Handles block roots from a peer with a lookup awaiting them, returning a request for the
+    /// headers of the unknown ancestors.
+    ///
+    /// Roots are followed back from the missing parent until one satisfying `is_known` is found.
+    /// Returns `Ok(None)` if the peer has no lookup awaiting roots, if the window holds no
+    /// blocks, in which case the next window is requested by `next_requests`, or if the missing
+    /// parent is now known, in which case the lookup is complete, see `take_connected`.
+    pub fn on_roots_response<F>(
+        &mut self,
+        peer_id: &PeerId,
+        mut response: BeaconBlockRootsResponse,
+        is_known: F,
+    ) -> Result<Option<BeaconBlockHeadersRequest>, LookupError>
+    where
+        F: Fn(&Hash256) -> bool,
+    {
+        let index = match self.lookup_index(peer_id) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let lookup = &mut self.lookups[index];
+        match lookup.state {
+            LookupState::AwaitingRoots => {}
+            _ => return Ok(None),
+        }
+
+        // the roots of the peer's chain below the oldest block held, newest first
+        let top_slot = lookup.segment[0].slot;
+        response.roots.retain(|root_slot| root_slot.slot < top_slot);
+        response.roots.sort_by(|a, b| b.slot.cmp(&a.slot));
+        // every slot of the window was skipped, so the parent is in an earlier window
+        if response.roots.is_empty() {
+            lookup.state = LookupState::Pending;
+            return Ok(None);
+        }
+
+        let missing_root = lookup.missing_root();
+        let start = response
+            .roots
+            .iter()
+            .position(|root_slot| root_slot.block_root == missing_root);
+        let start = match start {
+            Some(start) => start,
+            None => return Err(self.fail(index, LookupError::ParentNotFound)),
+        };
+
+        let mut unknown = vec![];
+        let mut connects = false;
+        for root_slot in &response.roots[start..] {
+            if is_known(&root_slot.block_root) {
+                connects = true;
+                break;
+            }
+            unknown.push(root_slot.clone());
+        }
+        // the missing parent has been imported since the lookup began, so the segment may be
+        // imported as it is
+        if unknown.is_empty() {
+            let lookup = self.lookups.remove(index);
+            self.connected.push((lookup.peer_id, lookup.segment));
+            return Ok(None);
+        }
+        unknown.reverse();
+
+        let first = &unknown[0];
+        let request = BeaconBlockHeadersRequest {
+            start_root: first.block_root,
+            start_slot: first.slot,
+            max_headers: unknown.len() as u64,
+            skip_slots: 0,
+        };
+        lookup.state = LookupState::AwaitingHeaders {
+            roots: unknown
+                .iter()
+                .map(|root_slot| root_slot.block_root)
+                .collect(),
+            connects,
+        };
+        lookup.requested = Instant::now();
+        Ok(Some(request))
+    }
+
+    /// Handles block headers from a peer with a lookup awaiting them, returning a request for
+    /// their bodies. Returns `Ok(None)` if the peer has no lookup awaiting headers.
+    pub fn on_headers_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockHeadersResponse,
+    ) -> Result<Option<BeaconBlockBodiesRequest>, LookupError> {
+        let index = match self.lookup_index(peer_id) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let lookup = &mut self.lookups[index];
+        let (roots, connects) = match &lookup.state {
+            LookupState::AwaitingHeaders { roots, connects } => (roots.clone(), *connects),
+            _ => return Ok(None),
+        };
+
+        let block_roots = match lookup.import_queue.insert_headers(response.headers) {
+            Some(block_roots) if block_roots == roots => block_roots,
+            _ => return Err(self.fail(index, LookupError::UnexpectedHeaders)),
+        };
+        lookup.state = LookupState::AwaitingBodies { connects };
+        lookup.requested = Instant::now();
+        Ok(Some(BeaconBlockBodiesRequest { block_roots }))
+    }
+
+    /// Handles block bodies from a peer with a lookup awaiting them, adding the ancestors to the
+    /// segment.
+    ///
+    /// Returns the segment, oldest block first, if it now connects to a known block, in which
+    /// case the lookup is complete. Otherwise the lookup continues with the next window. Returns
+    /// `Ok(None)` if the peer has no lookup awaiting bodies.
+    pub fn on_bodies_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockBodiesResponse,
+    ) -> Result<Option<Vec<BeaconBlock>>, LookupError> {
+        let index = match self.lookup_index(peer_id) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let lookup = &mut self.lookups[index];
+        let connects = match lookup.state {
+            LookupState::AwaitingBodies { connects } => connects,
+            _ => return Ok(None),
+        };
+
+        let mut ancestors = lookup.import_queue.complete_blocks(response.block_bodies);
+        if !lookup.import_queue.is_empty() {
+            return Err(self.fail(index, LookupError::MissingBodies));
+        }
+        let linked = ancestors.windows(2).all(|pair| {
+            pair[0].slot < pair[1].slot && pair[1].previous_block_root == pair[0].canonical_root()
+        });
+        let leads_to_segment = ancestors.last().map_or(false, |block| {
+            block.canonical_root() == lookup.missing_root()
+        });
+        if !linked || !leads_to_segment {
+            return Err(self.fail(index, LookupError::NonLinearBlocks));
+        }
+
+        ancestors.append(&mut lookup.segment);
+        lookup.segment = ancestors;
+        if connects {
+            return Ok(Some(self.lookups.remove(index).segment));
+        }
+        lookup.state = LookupState::Pending;
+        Ok(None)
+    }
+
+    /// Returns the segments, oldest block first, of the lookups completed as their missing parent
+    /// was imported by other means, with the peer which sent each.
+    pub fn take_connected(&mut self) -> Vec<(PeerId, Vec<BeaconBlock>)> {
+        std::mem::replace(&mut self.connected, vec![])
+    }
+
+    /// Returns `true` if a lookup from `peer_id` is in progress.
+    pub fn has_lookup(&self, peer_id: &PeerId) -> bool {
+        self.lookup_index(peer_id).is_some()
+    }
+
+    /// Abandons the lookup of a peer, returning `true` if it had one.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) -> bool {
+        match self.lookup_index(peer_id) {
+            Some(index) => {
+                self.lookups.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Abandons all lookups.
+    pub fn clear(&mut self) {
+        self.lookups.clear();
+        self.connected.clear();
+    }
+
+    fn lookup_index(&self, peer_id: &PeerId) -> Option<usize> {
+        self.lookups
+            .iter()
+            .position(|lookup| lookup.peer_id == *peer_id)
+    }
+
+    /// Abandons the lookup at `index` for the reason `error`, returning `error`.
+    fn fail(&mut self, index: usize, error: LookupError) -> LookupError {
+        self.lookups.remove(index);
+        error
+    }
+}
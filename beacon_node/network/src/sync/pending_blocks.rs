@@ -0,0 +1,189 @@
+use super::SyncConfig;
+use eth2_libp2p::PeerId;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+use types::{BeaconBlock, Hash256, Slot};
+
+/// A block held until it may be imported, with the peer which sent it.
+pub struct PendingBlock {
+    pub peer_id: PeerId,
+    pub block: BeaconBlock,
+    block_root: Hash256,
+    queued: Instant,
+}
+
+/// Holds gossip blocks which cannot yet be imported, rather than discarding them.
+///
+/// A block may arrive before its slot has started, as the clocks of nodes differ, or before its
+/// parent, as gossip does not preserve order. Such blocks are held, keyed by the slot or parent
+/// root they await, until that slot starts or the parent is imported. The queue is bounded by
+/// `max_pending_blocks`, and by `max_pending_blocks_per_peer` for the blocks of each peer. Blocks
+/// held for longer than `pending_block_timeout` are discarded.
+pub struct PendingBlocks {
+    awaiting_parent: HashMap<Hash256, Vec<PendingBlock>>,
+    awaiting_slot: BTreeMap<Slot, Vec<PendingBlock>>,
+    /// The roots of every block held.
+    roots: HashSet<Hash256>,
+    /// The number of blocks held from each peer.
+    peer_counts: HashMap<PeerId, usize>,
+    config: SyncConfig,
+}
+
+impl PendingBlocks {
+    pub fn new(config: &SyncConfig) -> Self {
+        PendingBlocks {
+            awaiting_parent: HashMap::new(),
+            awaiting_slot: BTreeMap::new(),
+            roots: HashSet::new(),
+            peer_counts: HashMap::new(),
+            config: config.clone(),
+        }
+    }
+
+    /// Holds `block` until its parent is imported.
+    ///
+    /// Returns `false` if the queue or the share of `peer_id` is full, or the block is already
+    /// held.
+    pub fn queue_for_parent(&mut self, peer_id: PeerId, block: BeaconBlock) -> bool {
+        let parent_root = block.previous_block_root;
+        match self.pending(peer_id, block) {
+            Some(pending) => {
+                self.awaiting_parent
+                    .entry(parent_root)
+                    .or_insert_with(Vec::new)
+                    .push(pending);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Holds `block` until its slot starts.
+    ///
+    /// Returns `false` if the block is more than `max_future_slots` ahead of `present_slot`, the
+    /// queue or the share of `peer_id` is full, or the block is already held.
+    pub fn queue_for_slot(
+        &mut self,
+        peer_id: PeerId,
+        block: BeaconBlock,
+        present_slot: Slot,
+    ) -> bool {
+        let slot = block.slot;
+        if slot > present_slot + self.config.max_future_slots {
+            return false;
+        }
+        match self.pending(peer_id, block) {
+            Some(pending) => {
+                self.awaiting_slot
+                    .entry(slot)
+                    .or_insert_with(Vec::new)
+                    .push(pending);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the block with `block_root` is held.
+    pub fn is_pending(&self, block_root: &Hash256) -> bool {
+        self.roots.contains(block_root)
+    }
+
+    /// Returns the number of blocks held.
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Removes and returns the blocks whose slot is at most `present_slot`, ordered by slot.
+    pub fn take_ready(&mut self, present_slot: Slot) -> Vec<PendingBlock> {
+        let later = self.awaiting_slot.split_off(&(present_slot + 1));
+        let ready = std::mem::replace(&mut self.awaiting_slot, later);
+        self.release(ready.into_iter().flat_map(|(_, blocks)| blocks).collect())
+    }
+
+    /// Removes and returns the blocks whose parent satisfies `is_known`, ordered by slot.
+    pub fn take_connected<F>(&mut self, is_known: F) -> Vec<PendingBlock>
+    where
+        F: Fn(&Hash256) -> bool,
+    {
+        let parents: Vec<Hash256> = self
+            .awaiting_parent
+            .keys()
+            .filter(|root| is_known(root))
+            .cloned()
+            .collect();
+        let connected = parents
+            .iter()
+            .filter_map(|root| self.awaiting_parent.remove(root))
+            .flatten()
+            .collect();
+        self.release(connected)
+    }
+
+    /// Discards the blocks held for longer than `pending_block_timeout`, returning the number
+    /// discarded.
+    pub fn expire(&mut self) -> usize {
+        let timeout = self.config.pending_block_timeout;
+        let roots = &mut self.roots;
+        let peer_counts = &mut self.peer_counts;
+        let mut expired = 0;
+        let mut retain = |blocks: &mut Vec<PendingBlock>| {
+            blocks.retain(|pending| {
+                let keep = pending.queued.elapsed() < timeout;
+                if !keep {
+                    roots.remove(&pending.block_root);
+                    forget_peer_block(peer_counts, &pending.peer_id);
+                    expired += 1;
+                }
+                keep
+            });
+            !blocks.is_empty()
+        };
+        self.awaiting_parent.retain(|_, blocks| retain(blocks));
+        self.awaiting_slot.retain(|_, blocks| retain(blocks));
+        expired
+    }
+
+    /// Returns `block` ready to be held, or `None` if the queue or the share of `peer_id` is
+    /// full, or it is already held.
+    fn pending(&mut self, peer_id: PeerId, block: BeaconBlock) -> Option<PendingBlock> {
+        if self.roots.len() >= self.config.max_pending_blocks {
+            return None;
+        }
+        let peer_count = self.peer_counts.get(&peer_id).cloned().unwrap_or(0);
+        if peer_count >= self.config.max_pending_blocks_per_peer {
+            return None;
+        }
+        let block_root = block.canonical_root();
+        if !self.roots.insert(block_root) {
+            return None;
+        }
+        self.peer_counts.insert(peer_id.clone(), peer_count + 1);
+        Some(PendingBlock {
+            peer_id,
+            block,
+            block_root,
+            queued: Instant::now(),
+        })
+    }
+
+    /// Forgets the roots of blocks removed from the queue, returning them ordered by slot.
+    fn release(&mut self, mut blocks: Vec<PendingBlock>) -> Vec<PendingBlock> {
+        for pending in &blocks {
+            self.roots.remove(&pending.block_root);
+            forget_peer_block(&mut self.peer_counts, &pending.peer_id);
+        }
+        blocks.sort_by_key(|pending| pending.block.slot);
+        blocks
+    }
+}
+
+/// Decrements the number of blocks held from `peer_id`, removing peers with none held.
+fn forget_peer_block(peer_counts: &mut HashMap<PeerId, usize>, peer_id: &PeerId) {
+    if let Some(count) = peer_counts.get_mut(peer_id) {
+        *count -= 1;
+        if *count == 0 {
+            peer_counts.remove(peer_id);
+        }
+    }
+}
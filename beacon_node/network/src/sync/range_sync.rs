@@ -0,0 +1,547 @@
+use super::import_queue::{ImportQueue, MAX_QUEUED_HEADERS};
+use super::SyncConfig;
+use eth2_libp2p::rpc::{
+    BeaconBlockBodiesResponse, BeaconBlockHeadersRequest, BeaconBlockHeadersResponse,
+    BeaconBlockRootsRequest, BeaconBlockRootsResponse,
+};
+use eth2_libp2p::PeerId;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Instant;
+use types::{BeaconBlock, Hash256, Slot};
+
+/// The reason a batch was abandoned and must be downloaded again.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BatchError {
+    /// The peer returned a block root outside the range of the batch.
+    RootOutOfRange,
+    /// The peer returned no headers, or more headers than roots.
+    UnexpectedHeaders,
+    /// The peer did not return the body of every header.
+    MissingBodies,
+    /// The blocks of the batch do not form a chain in slot order.
+    NonLinearBlocks,
+}
+
+/// A range of slots whose blocks are downloaded from a single peer.
+struct Batch {
+    /// The number of slots in the range.
+    count: u64,
+    state: BatchState,
+    /// Peers which failed to provide the batch, only tried again if no other peer can.
+    failed_peers: HashSet<PeerId>,
+    /// Peers which provided invalid blocks for the batch, never tried again.
+    invalid_peers: HashSet<PeerId>,
+    /// Peers which reported the range to hold only skipped slots, which is only accepted once
+    /// reported again, by another peer where possible.
+    empty_peers: HashSet<PeerId>,
+    /// The number of times the blocks of the batch failed to import.
+    import_failures: u32,
+}
+
+enum BatchState {
+    /// Waiting for a peer to download the batch from.
+    Pending,
+    /// Being downloaded from a peer.
+    Downloading {
+        peer_id: PeerId,
+        requested: Instant,
+        /// Headers of the batch, awaiting their bodies.
+        import_queue: ImportQueue,
+    },
+    /// Downloaded and verified, waiting for the batches before it to be imported.
+    Downloaded {
+        peer_id: PeerId,
+        blocks: Vec<BeaconBlock>,
+    },
+}
+
+/// A downloaded batch whose blocks are next to be imported.
+pub struct ReadyBatch {
+    /// The peer the blocks were downloaded from.
+    pub peer_id: PeerId,
+    pub start_slot: Slot,
+    pub count: u64,
+    /// The blocks of the range, in slot order.
+    pub blocks: Vec<BeaconBlock>,
+    failed_peers: HashSet<PeerId>,
+    invalid_peers: HashSet<PeerId>,
+    empty_peers: HashSet<PeerId>,
+    import_failures: u32,
+}
+
+/// Downloads the blocks between our head and a target slot from many peers at once.
+///
+/// The range is split into batches of `epochs_per_batch` epochs, aligned to epoch boundaries.
+/// Each peer downloads one batch at a time, so up to `max_pending_batches` batches are downloaded
+/// in parallel. A batch is verified as it is downloaded, but is only released for import once
+/// every batch before it has been, so blocks are imported in slot order. A batch which fails,
+/// times out or whose peer disconnects is downloaded again, preferring another peer. A batch
+/// whose blocks are invalid is never downloaded from the same peer again. A batch reported to
+/// hold only skipped slots is downloaded again, from another peer where possible, as the report
+/// may hide blocks, and is complete once reported twice. A peer whose batch timed out is given no
+/// other batch until it answers or its request fails, so that a late response is not taken for
+/// that of another batch. The end of a batch may also be withheld,
+/// which is only found once the batch after it does not connect to our chain.
+pub struct RangeSync {
+    /// Batches being downloaded or awaiting import, keyed by their first slot.
+    batches: BTreeMap<Slot, Batch>,
+    /// The peers which provided the last two batches released for import, most recent first.
+    ready_peers: (Option<PeerId>, Option<PeerId>),
+    /// Peers whose batch timed out whilst a request to them was outstanding.
+    timed_out_peers: HashSet<PeerId>,
+    /// The first slot not yet covered by a batch.
+    next_slot: Slot,
+    /// The slot to download blocks up to, the best slot of our peers.
+    target_slot: Slot,
+    slots_per_epoch: u64,
+    config: SyncConfig,
+}
+
+impl RangeSync {
+    pub fn new(slots_per_epoch: u64, config: &SyncConfig) -> Self {
+        RangeSync {
+            batches: BTreeMap::new(),
+            ready_peers: (None, None),
+            timed_out_peers: HashSet::new(),
+            next_slot: Slot::new(0),
+            target_slot: Slot::new(0),
+            slots_per_epoch,
+            config: config.clone(),
+        }
+    }
+
+    /// Begins downloading from `start_slot`, discarding any batches of a previous range.
+    pub fn start(&mut self, start_slot: Slot) {
+        self.batches.clear();
+        self.ready_peers = (None, None);
+        self.next_slot = start_slot;
+    }
+
+    /// Discards all batches.
+    pub fn stop(&mut self) {
+        self.batches.clear();
+    }
+
+    /// Sets the slot to download blocks up to. Pending batches beyond a lowered target are
+    /// abandoned, as no peer may be able to serve them.
+    pub fn set_target(&mut self, target_slot: Slot) {
+        self.target_slot = target_slot;
+        while let Some((&start_slot, batch)) = self.batches.iter().next_back() {
+            match batch.state {
+                BatchState::Pending if start_slot > target_slot => {
+                    self.batches.remove(&start_slot);
+                    self.next_slot = start_slot;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Returns the number of batches being downloaded or awaiting import.
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    /// Assigns pending batches to idle peers, returning the requests to send them.
+    ///
+    /// `peers` are the peers which may be downloaded from with their best slots, most preferred
+    /// first. New batches are added up to the target slot whilst fewer than `max_pending_batches`
+    /// exist. Batches which have not downloaded within `batch_timeout` are reassigned, but not
+    /// to the peer they timed out with until its request is answered or fails.
+    pub fn next_requests(
+        &mut self,
+        peers: &[(PeerId, Slot)],
+    ) -> Vec<(PeerId, BeaconBlockRootsRequest)> {
+        self.expire_batches();
+        self.add_batches();
+
+        let mut busy: HashSet<PeerId> = self
+            .batches
+            .values()
+            .filter_map(|batch| match &batch.state {
+                BatchState::Downloading { peer_id, .. } => Some(peer_id.clone()),
+                _ => None,
+            })
+            .chain(self.timed_out_peers.iter().cloned())
+            .collect();
+
+        let mut requests = vec![];
+        for (&start_slot, batch) in self.batches.iter_mut() {
+            match batch.state {
+                BatchState::Pending => {}
+                _ => continue,
+            }
+            let end_slot = start_slot + batch.count - 1;
+            let failed_peers = &batch.failed_peers;
+            let invalid_peers = &batch.invalid_peers;
+            let empty_peers = &batch.empty_peers;
+            let peer_id = match peers
+                .iter()
+                .filter(|(peer_id, best_slot)| {
+                    !busy.contains(peer_id)
+                        && !invalid_peers.contains(peer_id)
+                        && *best_slot >= end_slot
+                })
+                .min_by_key(|(peer_id, _)| {
+                    (
+                        empty_peers.contains(peer_id),
+                        failed_peers.contains(peer_id),
+                    )
+                }) {
+                Some((peer_id, _)) => peer_id.clone(),
+                None => continue,
+            };
+
+            busy.insert(peer_id.clone());
+            batch.state = BatchState::Downloading {
+                peer_id: peer_id.clone(),
+                requested: Instant::now(),
+                import_queue: ImportQueue::default(),
+            };
+            requests.push((
+                peer_id,
+                BeaconBlockRootsRequest {
+                    start_slot,
+                    count: batch.count,
+                },
+            ));
+        }
+        requests
+    }
+
+    /// Handles the block roots of the batch being downloaded from `peer_id`, returning a request
+    /// for their headers.
+    ///
+    /// Returns `Ok(None)` if no batch is being downloaded from the peer, or if the range of the
+    /// batch holds only skipped slots, in which case the batch is complete if reported before and
+    /// is otherwise downloaded again.
+    pub fn on_roots_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockRootsResponse,
+    ) -> Result<Option<BeaconBlockHeadersRequest>, BatchError> {
+        let (start_slot, count) = match self.downloading(peer_id) {
+            Some((start_slot, count, _)) => (start_slot, count),
+            None => {
+                self.timed_out_peers.remove(peer_id);
+                return Ok(None);
+            }
+        };
+        let end_slot = start_slot + count - 1;
+
+        if response
+            .roots
+            .iter()
+            .any(|root_slot| root_slot.slot < start_slot || root_slot.slot > end_slot)
+        {
+            return Err(self.fail(start_slot, BatchError::RootOutOfRange));
+        }
+
+        match response.roots.iter().min_by_key(|root_slot| root_slot.slot) {
+            Some(first) => Ok(Some(BeaconBlockHeadersRequest {
+                start_root: first.block_root,
+                start_slot: first.slot,
+                max_headers: response.roots.len() as u64,
+                skip_slots: 0,
+            })),
+            None => {
+                self.complete_empty(start_slot, peer_id);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Queues the headers of the batch being downloaded from `peer_id`, returning the roots of
+    /// the blocks whose bodies should be requested.
+    ///
+    /// Returns `Ok(None)` if no batch is being downloaded from the peer.
+    pub fn on_headers_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockHeadersResponse,
+    ) -> Result<Option<Vec<Hash256>>, BatchError> {
+        let (start_slot, count, import_queue) = match self.downloading(peer_id) {
+            Some(downloading) => downloading,
+            None => {
+                self.timed_out_peers.remove(peer_id);
+                return Ok(None);
+            }
+        };
+        let block_roots = if response.headers.len() as u64 > count {
+            None
+        } else {
+            import_queue.insert_headers(response.headers)
+        };
+        match block_roots {
+            Some(block_roots) if !block_roots.is_empty() => Ok(Some(block_roots)),
+            _ => Err(self.fail(start_slot, BatchError::UnexpectedHeaders)),
+        }
+    }
+
+    /// Completes the blocks of the batch being downloaded from `peer_id` with their bodies,
+    /// verifying that every header has a body and that the blocks form a chain.
+    ///
+    /// Returns the number of blocks downloaded, zero if no batch is being downloaded from the
+    /// peer.
+    pub fn on_bodies_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockBodiesResponse,
+    ) -> Result<usize, BatchError> {
+        let (start_slot, count, import_queue) = match self.downloading(peer_id) {
+            Some(downloading) => downloading,
+            None => {
+                self.timed_out_peers.remove(peer_id);
+                return Ok(0);
+            }
+        };
+        let end_slot = start_slot + count - 1;
+
+        let blocks = import_queue.complete_blocks(response.block_bodies);
+        if !import_queue.is_empty() {
+            return Err(self.fail(start_slot, BatchError::MissingBodies));
+        }
+        let in_range = blocks
+            .iter()
+            .all(|block| block.slot >= start_slot && block.slot <= end_slot);
+        let linked = blocks.windows(2).all(|pair| {
+            pair[0].slot < pair[1].slot && pair[1].previous_block_root == pair[0].canonical_root()
+        });
+        if !in_range || !linked {
+            return Err(self.fail(start_slot, BatchError::NonLinearBlocks));
+        }
+
+        let downloaded = blocks.len();
+        self.complete(start_slot, blocks);
+        Ok(downloaded)
+    }
+
+    /// Removes and returns the first batch if it has been downloaded, its blocks being the next
+    /// to import.
+    pub fn pop_ready(&mut self) -> Option<ReadyBatch> {
+        let start_slot = match self.batches.iter().next() {
+            Some((&start_slot, batch)) => match batch.state {
+                BatchState::Downloaded { .. } => start_slot,
+                _ => return None,
+            },
+            None => return None,
+        };
+        let batch = self.batches.remove(&start_slot)?;
+        match batch.state {
+            BatchState::Downloaded { peer_id, blocks } => {
+                let previous = self.ready_peers.0.replace(peer_id.clone());
+                self.ready_peers.1 = previous;
+                Some(ReadyBatch {
+                    peer_id,
+                    start_slot,
+                    count: batch.count,
+                    blocks,
+                    failed_peers: batch.failed_peers,
+                    invalid_peers: batch.invalid_peers,
+                    empty_peers: batch.empty_peers,
+                    import_failures: batch.import_failures,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Downloads the slots from `start_slot` up to the batch at `end_slot` again, as its first
+    /// block does not connect to our chain and the peer which provided the batch before it may
+    /// have withheld the blocks at its end. That peer is the least preferred to download from.
+    ///
+    /// Must be called after the batch at `end_slot` is returned by `retry`.
+    pub fn redownload(&mut self, start_slot: Slot, end_slot: Slot) {
+        if start_slot >= end_slot || self.batches.contains_key(&start_slot) {
+            return;
+        }
+        self.batches.insert(
+            start_slot,
+            Batch {
+                count: (end_slot - start_slot).as_u64(),
+                state: BatchState::Pending,
+                failed_peers: self.ready_peers.1.iter().cloned().collect(),
+                invalid_peers: HashSet::new(),
+                empty_peers: HashSet::new(),
+                import_failures: 0,
+            },
+        );
+    }
+
+    /// Returns a batch whose blocks could not be imported, to be downloaded again, preferably
+    /// from a peer other than the one which provided it. If `invalid`, the peer misbehaved and
+    /// the batch is never downloaded from it again. If `invalid_blocks`, the blocks were invalid,
+    /// which counts towards the import failures of the batch.
+    ///
+    /// Returns the number of times the blocks of the batch have been invalid.
+    pub fn retry(&mut self, batch: ReadyBatch, invalid: bool, invalid_blocks: bool) -> u32 {
+        let mut failed_peers = batch.failed_peers;
+        let mut invalid_peers = batch.invalid_peers;
+        if invalid {
+            invalid_peers.insert(batch.peer_id);
+        } else {
+            failed_peers.insert(batch.peer_id);
+        }
+        let import_failures = if invalid_blocks {
+            batch.import_failures + 1
+        } else {
+            batch.import_failures
+        };
+        self.batches.insert(
+            batch.start_slot,
+            Batch {
+                count: batch.count,
+                state: BatchState::Pending,
+                failed_peers,
+                invalid_peers,
+                empty_peers: batch.empty_peers,
+                import_failures,
+            },
+        );
+        import_failures
+    }
+
+    /// Returns the peers which provided invalid blocks for the batch at `start_slot`.
+    pub fn invalid_peers(&self, start_slot: Slot) -> Vec<PeerId> {
+        self.batches.get(&start_slot).map_or(vec![], |batch| {
+            batch.invalid_peers.iter().cloned().collect()
+        })
+    }
+
+    /// Abandons the batch being downloaded from a peer which returned an error, preferring
+    /// another peer when it is requested again. Returns `true` if there was such a batch.
+    pub fn on_request_failed(&mut self, peer_id: &PeerId) -> bool {
+        self.timed_out_peers.remove(peer_id);
+        match self.downloading(peer_id) {
+            Some((start_slot, _, _)) => {
+                self.reset(start_slot, true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Abandons the batch being downloaded from a disconnected peer.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.timed_out_peers.remove(peer_id);
+        if let Some((start_slot, _, _)) = self.downloading(peer_id) {
+            self.reset(start_slot, false);
+        }
+    }
+
+    /// Adds batches up to the target slot, whilst fewer than `max_pending_batches` exist. Batches
+    /// end on an epoch boundary or at the target slot, and span at most `MAX_QUEUED_HEADERS`
+    /// slots so that their headers fit in the import queue.
+    fn add_batches(&mut self) {
+        while self.batches.len() < self.config.max_pending_batches
+            && self.next_slot <= self.target_slot
+        {
+            let start = self.next_slot.as_u64();
+            let epoch_end = (start / self.slots_per_epoch + self.config.epochs_per_batch)
+                * self.slots_per_epoch
+                - 1;
+            let end = std::cmp::min(epoch_end, self.target_slot.as_u64())
+                .min(start + MAX_QUEUED_HEADERS as u64 - 1);
+            let count = end - start + 1;
+
+            self.batches.insert(
+                self.next_slot,
+                Batch {
+                    count,
+                    state: BatchState::Pending,
+                    failed_peers: HashSet::new(),
+                    invalid_peers: HashSet::new(),
+                    empty_peers: HashSet::new(),
+                    import_failures: 0,
+                },
+            );
+            self.next_slot = self.next_slot + count;
+        }
+    }
+
+    /// Reassigns batches which have not downloaded within `batch_timeout`.
+    fn expire_batches(&mut self) {
+        let timeout = self.config.batch_timeout;
+        let expired: Vec<Slot> = self
+            .batches
+            .iter()
+            .filter_map(|(&start_slot, batch)| match batch.state {
+                BatchState::Downloading { requested, .. } if requested.elapsed() >= timeout => {
+                    Some(start_slot)
+                }
+                _ => None,
+            })
+            .collect();
+        for start_slot in expired {
+            if let Some(BatchState::Downloading { peer_id, .. }) =
+                self.batches.get(&start_slot).map(|batch| &batch.state)
+            {
+                self.timed_out_peers.insert(peer_id.clone());
+            }
+            self.reset(start_slot, true);
+        }
+    }
+
+    /// Returns the first slot, the slot count and the queued headers of the batch being
+    /// downloaded from `peer_id`.
+    fn downloading(&mut self, peer_id: &PeerId) -> Option<(Slot, u64, &mut ImportQueue)> {
+        self.batches
+            .iter_mut()
+            .find_map(|(&start_slot, batch)| match &mut batch.state {
+                BatchState::Downloading {
+                    peer_id: batch_peer,
+                    import_queue,
+                    ..
+                } if batch_peer == peer_id => Some((start_slot, batch.count, import_queue)),
+                _ => None,
+            })
+    }
+
+    /// Marks the batch at `start_slot` as downloaded.
+    fn complete(&mut self, start_slot: Slot, blocks: Vec<BeaconBlock>) {
+        if let Some(batch) = self.batches.get_mut(&start_slot) {
+            if let BatchState::Downloading { peer_id, .. } = &batch.state {
+                batch.state = BatchState::Downloaded {
+                    peer_id: peer_id.clone(),
+                    blocks,
+                };
+            }
+        }
+    }
+
+    /// Completes the batch at `start_slot` without blocks if a peer reported its slots skipped
+    /// before `peer_id`, otherwise returning it to pending to be downloaded again.
+    fn complete_empty(&mut self, start_slot: Slot, peer_id: &PeerId) {
+        let confirmed = match self.batches.get_mut(&start_slot) {
+            Some(batch) => !batch.empty_peers.is_empty(),
+            None => return,
+        };
+        if confirmed {
+            self.complete(start_slot, vec![]);
+        } else {
+            self.reset(start_slot, false);
+            if let Some(batch) = self.batches.get_mut(&start_slot) {
+                batch.empty_peers.insert(peer_id.clone());
+            }
+        }
+    }
+
+    /// Abandons the batch at `start_slot` for the reason `error`, returning `error`.
+    fn fail(&mut self, start_slot: Slot, error: BatchError) -> BatchError {
+        self.reset(start_slot, true);
+        error
+    }
+
+    /// Returns the batch at `start_slot` to pending, recording its peer as having failed it if
+    /// `failed`.
+    fn reset(&mut self, start_slot: Slot, failed: bool) {
+        if let Some(batch) = self.batches.get_mut(&start_slot) {
+            if let BatchState::Downloading { peer_id, .. } = &batch.state {
+                if failed {
+                    batch.failed_peers.insert(peer_id.clone());
+                }
+                batch.state = BatchState::Pending;
+            }
+        }
+    }
+}
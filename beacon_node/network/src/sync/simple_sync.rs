@@ -1,13 +1,23 @@
-use crate::beacon_chain::BeaconChain;
-use eth2_libp2p::rpc::HelloMessage;
+use super::backfill::{BackfillError, BackfillSync};
+use super::batch_processing::{self, penalty, BatchProcessingResult, SyncError, SyncPeerAction};
+use super::parent_lookup::{LookupError, ParentLookups};
+use super::pending_blocks::{PendingBlock, PendingBlocks};
+use super::range_sync::{BatchError, RangeSync, ReadyBatch};
+use super::SyncConfig;
+use crate::beacon_chain::{BeaconChain, BeaconChainRead};
+use crate::metrics;
+use beacon_chain::{BlockProcessingOutcome, InvalidBlock, TraceId};
+use eth2_libp2p::rpc::{
+    BeaconAttestationsRequest, BeaconAttestationsResponse, BeaconBlockBodiesRequest,
+    BeaconBlockBodiesResponse, BeaconBlockHeadersRequest, BeaconBlockHeadersResponse,
+    BeaconBlockRootsRequest, BeaconBlockRootsResponse, HelloMessage, RPCRequest,
+};
 use eth2_libp2p::PeerId;
-use slog::{debug, o};
+use slog::{debug, info, o, trace, warn};
 use std::collections::HashMap;
 use std::sync::Arc;
-use types::{Epoch, Hash256, Slot};
-
-/// The number of slots that we can import blocks ahead of us, before going into full Sync mode.
-const SLOT_IMPORT_TOLERANCE: u64 = 100;
+use std::time::{Duration, Instant};
+use types::{Attestation, BeaconBlock, Epoch, Fork, Hash256, Slot};
 
 /// Keeps track of syncing information for known connected peers.
 pub struct PeerSyncInfo {
@@ -15,61 +25,298 @@ pub struct PeerSyncInfo {
     latest_finalized_epoch: Epoch,
     best_root: Hash256,
     best_slot: Slot,
+    /// The round-trip time of the most recent PING to the peer, if it has responded to one.
+    latency: Option<Duration>,
+    /// The number of blocks received from the peer, by sync or gossip.
+    blocks_served: u64,
+    /// The bytes of RPC responses received from the peer.
+    bytes_received: u64,
+    /// The bytes of RPC responses sent to the peer.
+    bytes_sent: u64,
+    /// The number of failed requests to the peer and invalid blocks received from it.
+    errors: u64,
+    /// The highest slot of a block received from the peer and imported. Claims of the peer which
+    /// have not been validated, such as the best slot of its HELLO, are not counted.
+    last_seen_slot: Option<Slot>,
+}
+
+impl PeerSyncInfo {
+    fn new(hello_message: &HelloMessage) -> Self {
+        PeerSyncInfo {
+            latest_finalized_root: hello_message.latest_finalized_root,
+            latest_finalized_epoch: hello_message.latest_finalized_epoch,
+            best_root: hello_message.best_root,
+            best_slot: hello_message.best_slot,
+            latency: None,
+            blocks_served: 0,
+            bytes_received: 0,
+            bytes_sent: 0,
+            errors: 0,
+            last_seen_slot: None,
+        }
+    }
+
+    /// Updates the chain info of the peer from a new HELLO, keeping its statistics.
+    fn update(&mut self, hello_message: &HelloMessage) {
+        self.latest_finalized_root = hello_message.latest_finalized_root;
+        self.latest_finalized_epoch = hello_message.latest_finalized_epoch;
+        self.best_root = hello_message.best_root;
+        self.best_slot = hello_message.best_slot;
+    }
+
+    /// Records the slot of a block received from the peer which has been imported.
+    fn see_slot(&mut self, slot: Slot) {
+        if self.last_seen_slot.map_or(true, |seen| slot > seen) {
+            self.last_seen_slot = Some(slot);
+        }
+    }
+}
+
+/// The sync health of a known peer, for display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerSummary {
+    pub peer_id: PeerId,
+    pub finalized_epoch: Epoch,
+    /// The best slot of the peer, as of its latest HELLO.
+    pub best_slot: Slot,
+    /// The highest slot of a block received from the peer and imported, if any.
+    pub last_seen_slot: Option<Slot>,
+    pub latency: Option<Duration>,
+    /// The number of blocks received from the peer, by sync or gossip.
+    pub blocks_served: u64,
+    /// The bytes of RPC responses received from the peer.
+    pub bytes_received: u64,
+    /// The bytes of RPC responses sent to the peer.
+    pub bytes_sent: u64,
+    /// The number of failed requests to the peer and invalid blocks received from it.
+    pub errors: u64,
+}
+
+/// The interval at which progress is logged whilst downloading.
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of peers asked for their aggregate attestations once syncing finishes.
+const ATTESTATION_REQUEST_PEERS: usize = 2;
+
+/// The maximum number of aggregate attestations requested of each peer.
+pub const MAX_REQUESTED_ATTESTATIONS: u64 = 256;
+/// The time for which the peers which served the blocks of a batch which repeatedly failed to
+/// import are not synced to.
+const INVALID_CHAIN_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// Peers which have finalized up to this many epochs beyond a candidate chain's checkpoint are
+/// counted as following it, as honest peers learn of a new checkpoint at slightly different
+/// times.
+const FINALIZATION_LAG_EPOCHS: u64 = 2;
+
+/// The progress of syncing, for display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncStatus {
+    pub state: SyncState,
+    /// The slot of our latest block.
+    pub current_slot: Slot,
+    /// The present slot according to the wall clock.
+    pub clock_slot: Slot,
+    /// The best slot of the chain being synced to, the network's head as advertised by our
+    /// peers, if any peers are known.
+    pub target_slot: Option<Slot>,
+    /// Whether our head is close enough to the network's head to produce blocks. See
+    /// `SimpleSync::is_synced`.
+    pub is_synced: bool,
+    /// The number of peers on the chain being synced to.
+    pub sync_peers: usize,
+    /// The blocks imported per second since downloading began, zero if not downloading.
+    pub blocks_per_second: f64,
+    /// The estimated time until the target slot is reached, if downloading has made progress.
+    pub eta: Option<Duration>,
+}
+
+/// A chain advertised by our peers, identified by the finalized checkpoint of their HELLO.
+struct CandidateChain {
+    finalized_root: Hash256,
+    finalized_epoch: Epoch,
+    /// The highest best slot reached by a majority of the peers on the chain, no later than the
+    /// wall clock, so that no single peer decides it.
+    quorum_slot: Slot,
+    /// The peers advertising the checkpoint, or one up to `FINALIZATION_LAG_EPOCHS` later.
+    peers: Vec<PeerId>,
 }
 
 /// The current syncing state.
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SyncState {
+    /// We are close enough to our peers that no blocks are being requested.
     Idle,
+    /// We are behind our peers and requesting blocks from them.
     Downloading,
-    Stopped,
+    /// Syncing has been halted. Peers are still handshaken and gossip is still validated, but no
+    /// new blocks are requested until syncing is resumed.
+    Stopped(StopReason),
+}
+
+/// The reason syncing was stopped.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StopReason {
+    /// Syncing was paused by an operator.
+    Paused,
+    /// The beacon chain returned an error from which syncing cannot recover by itself.
+    FatalChainError,
 }
 
 /// Simple Syncing protocol.
+///
+/// Generic over the beacon chain so that it may be driven by a mock chain in tests, the network
+/// chain by default.
 //TODO: Decide for HELLO messages whether its better to keep current in RAM or build on the fly
 //when asked.
-pub struct SimpleSync {
+pub struct SimpleSync<C: ?Sized + BeaconChainRead = BeaconChain> {
     /// A reference to the underlying beacon chain.
-    chain: Arc<BeaconChain>,
+    chain: Arc<C>,
     /// A mapping of Peers to their respective PeerSyncInfo.
     known_peers: HashMap<PeerId, PeerSyncInfo>,
     /// The current state of the syncing protocol.
     state: SyncState,
+    /// The sync configuration, defining when to start and stop downloading.
+    config: SyncConfig,
+    /// Downloads the blocks between our head and the best slot of our peers whilst
+    /// `Downloading`.
+    range_sync: RangeSync,
+    /// Searches for the ancestors of gossip blocks whose parent is unknown whilst `Idle`.
+    parent_lookups: ParentLookups,
+    /// Downloads the blocks before our oldest block whilst `Idle`, if the chain was started from a
+    /// checkpoint.
+    backfill: BackfillSync,
+    /// Gossip blocks awaiting their slot or parent.
+    pending_blocks: PendingBlocks,
+    /// Whether peers have been asked for their aggregate attestations since syncing last
+    /// finished, so that the attestation pool holds recent attestations for produced blocks.
+    attestations_requested: bool,
+    /// The outstanding requests for aggregate attestations, by peer.
+    attestation_requests: HashMap<PeerId, BeaconAttestationsRequest>,
+    /// When downloading last began and our latest slot at the time, to measure progress.
+    download_started: Option<(Instant, Slot)>,
+    /// The number of blocks imported since downloading last began.
+    blocks_downloaded: u64,
+    /// When progress was last logged.
+    last_progress_log: Instant,
+    /// Whether the slot we have synced up to, see `synced_slot`, is more than
+    /// `slot_import_tolerance` slots behind the wall clock.
+    behind_clock: bool,
+    /// The peers which served invalid blocks of a batch which repeatedly failed to import, with
+    /// the time until which they are not synced to.
+    invalid_chain_peers: HashMap<PeerId, Instant>,
     /// The network id, for quick HELLO RPC message lookup.
     network_id: u8,
+    /// The genesis epoch of the chain, for quick HELLO RPC message lookup.
+    genesis_epoch: Epoch,
+    /// The root of our genesis block, used to reject peers on a different chain.
+    genesis_block_root: Hash256,
+    /// The root of our genesis validators, from which our fork digest is computed.
+    genesis_validators_root: Hash256,
     /// The latest epoch of the syncing chain.
     latest_finalized_epoch: Epoch,
     /// The latest block of the syncing chain.
     latest_slot: Slot,
+    /// The last slot of the latest batch imported, which is after `latest_slot` if the slots at
+    /// the end of the batch were skipped.
+    batch_end_slot: Slot,
     /// Sync logger.
     log: slog::Logger,
 }
 
-impl SimpleSync {
-    pub fn new(beacon_chain: Arc<BeaconChain>, log: &slog::Logger) -> Self {
+impl<C: ?Sized + BeaconChainRead> SimpleSync<C> {
+    pub fn new(beacon_chain: Arc<C>, config: &SyncConfig, log: &slog::Logger) -> Self {
         let state = beacon_chain.get_state();
         let sync_logger = log.new(o!("Service"=> "Sync"));
-        SimpleSync {
+        let mut sync = SimpleSync {
             chain: beacon_chain.clone(),
             known_peers: HashMap::new(),
             state: SyncState::Idle,
+            config: config.clone(),
+            range_sync: RangeSync::new(beacon_chain.get_spec().slots_per_epoch, config),
+            parent_lookups: ParentLookups::new(config),
+            backfill: BackfillSync::new(
+                config.epochs_per_batch * beacon_chain.get_spec().slots_per_epoch,
+                config.batch_timeout,
+            ),
+            pending_blocks: PendingBlocks::new(config),
+            attestations_requested: false,
+            attestation_requests: HashMap::new(),
+            download_started: None,
+            blocks_downloaded: 0,
+            last_progress_log: Instant::now(),
+            behind_clock: false,
+            invalid_chain_peers: HashMap::new(),
             network_id: beacon_chain.get_spec().network_id,
+            genesis_epoch: beacon_chain.get_spec().genesis_epoch,
+            genesis_block_root: beacon_chain.genesis_block_root(),
+            genesis_validators_root: beacon_chain.genesis_validators_root(),
             latest_finalized_epoch: state.finalized_epoch,
-            latest_slot: state.slot - 1, //TODO: Build latest block function into Beacon chain and correct this
+            latest_slot: beacon_chain.head_info().slot,
+            batch_end_slot: Slot::new(0),
             log: sync_logger,
+        };
+        drop(state);
+        // after a long downtime we are behind before any peer connects
+        sync.check_clock();
+        sync
+    }
+
+    /// Compares the slot we have synced up to with the wall clock, downloading from peers
+    /// advertising a later head if we have fallen more than `slot_import_tolerance` slots behind.
+    /// See `update_state`.
+    ///
+    /// Should be called periodically, as the clock advances whilst no blocks are received.
+    pub fn check_clock(&mut self) {
+        let clock_slot = match self.chain.read_slot_clock() {
+            Some(slot) => slot,
+            None => return,
+        };
+        // slot subtraction saturates at zero
+        let behind_clock =
+            (clock_slot - self.synced_slot()).as_u64() > self.config.slot_import_tolerance;
+        if behind_clock && !self.behind_clock {
+            info!(
+                self.log,
+                "Head is behind the wall clock";
+                "local_slot" => self.latest_slot.as_u64(),
+                "clock_slot" => clock_slot.as_u64(),
+                "peers" => self.known_peers.len(),
+            );
         }
+        self.behind_clock = behind_clock;
+        self.update_state();
     }
 
-    /// Generates our current state in the form of a HELLO RPC message.
+    /// Returns the slot up to which we hold the blocks of the chain we sync to: that of our latest
+    /// block, or the end of the latest batch imported if the slots since were skipped.
+    ///
+    /// Our latest block alone would fall behind the clock whilst the network skips slots, so we
+    /// would keep downloading from peers with nothing more to serve.
+    fn synced_slot(&self) -> Slot {
+        std::cmp::max(self.latest_slot, self.batch_end_slot)
+    }
+
+    /// Returns the digest of our genesis and the fork of our current epoch, which peers must
+    /// share. Read without waiting for a block being imported.
+    pub fn fork_digest(&self) -> [u8; 4] {
+        Fork::compute_digest(
+            self.chain.head_info().fork_version,
+            self.genesis_validators_root,
+        )
+    }
+
+    /// Generates our current state in the form of a HELLO RPC message, without waiting for a block
+    /// being imported.
     pub fn generate_hello(&self) -> HelloMessage {
-        let state = &self.chain.get_state();
-        //TODO: Paul to verify the logic of these fields.
+        let head_info = self.chain.head_info();
         HelloMessage {
             network_id: self.network_id,
-            latest_finalized_root: state.finalized_root,
-            latest_finalized_epoch: state.finalized_epoch,
-            best_root: Hash256::zero(), //TODO: build correct value as a beacon chain function
-            best_slot: state.slot - 1,
+            fork_digest: Fork::compute_digest(head_info.fork_version, self.genesis_validators_root),
+            latest_finalized_root: self
+                .finalized_root(head_info.finalized_epoch, head_info.finalized_root),
+            latest_finalized_epoch: head_info.finalized_epoch,
+            best_root: head_info.block_root,
+            best_slot: head_info.slot,
         }
     }
 
@@ -78,35 +325,1299 @@ impl SimpleSync {
         if hello_message.network_id != self.network_id {
             return false;
         }
-        // compare latest epoch and finalized root to see if they exist in our chain
-        if hello_message.latest_finalized_epoch <= self.latest_finalized_epoch {
-            // ensure their finalized root is in our chain
-            // TODO: Get the finalized root at hello_message.latest_epoch and ensure they match
-            //if (hello_message.latest_finalized_root == self.chain.get_state() {
-            //    return false;
-            //    }
+        // the peer must share our genesis and fork
+        let fork_digest = self.fork_digest();
+        if hello_message.fork_digest != fork_digest {
+            debug!(
+                self.log,
+                "Peer has a different fork digest. Peer: {:?}", peer_id;
+                "our_digest" => format!("{:?}", fork_digest),
+                "peer_digest" => format!("{:?}", hello_message.fork_digest),
+            );
+            return false;
+        }
+        // a peer that has not finalized beyond genesis must share our genesis block
+        if hello_message.latest_finalized_epoch == self.genesis_epoch
+            && hello_message.latest_finalized_root != self.genesis_block_root
+            && !hello_message.latest_finalized_root.is_zero()
+        {
+            debug!(
+                self.log,
+                "Peer has a different genesis block. Peer: {:?}", peer_id;
+                "our_genesis" => format!("{:?}", self.genesis_block_root),
+                "peer_genesis" => format!("{:?}", hello_message.latest_finalized_root),
+            );
+            return false;
+        }
+        // a peer that has finalized no further than us must have finalized a block of our chain
+        if hello_message.latest_finalized_epoch > self.genesis_epoch
+            && hello_message.latest_finalized_epoch <= self.latest_finalized_epoch
+        {
+            let finalized_slot = hello_message
+                .latest_finalized_epoch
+                .start_slot(self.chain.get_spec().slots_per_epoch);
+            match self.chain.block_root_at_slot(finalized_slot) {
+                Some(our_root) if our_root != hello_message.latest_finalized_root => {
+                    debug!(
+                        self.log,
+                        "Peer has finalized a different chain. Peer: {:?}", peer_id;
+                        "epoch" => hello_message.latest_finalized_epoch.as_u64(),
+                        "our_root" => format!("{:?}", our_root),
+                        "peer_root" => format!("{:?}", hello_message.latest_finalized_root),
+                    );
+                    return false;
+                }
+                // the block is before the history we hold, so cannot be checked
+                _ => {}
+            }
         }
 
         // the client is valid, add it to our list of known_peers and request sync if required
         // update peer list if peer already exists
-        let peer_info = PeerSyncInfo {
-            latest_finalized_root: hello_message.latest_finalized_root,
-            latest_finalized_epoch: hello_message.latest_finalized_epoch,
-            best_root: hello_message.best_root,
-            best_slot: hello_message.best_slot,
+        debug!(self.log, "Handshake successful. Peer: {:?}", peer_id);
+        self.known_peers
+            .entry(peer_id)
+            .and_modify(|info| info.update(&hello_message))
+            .or_insert_with(|| PeerSyncInfo::new(&hello_message));
+
+        self.update_state();
+
+        true
+    }
+
+    /// Records our latest finalized epoch, returning `true` if it has advanced since last checked.
+    ///
+    /// Peers hold our finalized checkpoint from our last HELLO, so they should be sent a new
+    /// HELLO when it advances.
+    pub fn update_finalized_epoch(&mut self) -> bool {
+        let finalized_epoch = self.chain.head_info().finalized_epoch;
+        if finalized_epoch <= self.latest_finalized_epoch {
+            return false;
+        }
+        debug!(
+            self.log,
+            "Finalized epoch advanced";
+            "from" => self.latest_finalized_epoch.as_u64(),
+            "to" => finalized_epoch.as_u64(),
+        );
+        self.latest_finalized_epoch = finalized_epoch;
+        true
+    }
+
+    /// Records the round-trip time of a PING to a known peer, used to prefer responsive peers
+    /// when requesting batches.
+    pub fn update_latency(&mut self, peer_id: &PeerId, latency: Duration) {
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.latency = Some(latency);
+        }
+    }
+
+    /// Returns the peers which have completed the HELLO handshake.
+    pub fn known_peers(&self) -> Vec<PeerId> {
+        self.known_peers.keys().cloned().collect()
+    }
+
+    /// Records the bytes of an RPC response received from a known peer.
+    pub fn on_bytes_received(&mut self, peer_id: &PeerId, bytes: u64) {
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.bytes_received += bytes;
+        }
+    }
+
+    /// Records the bytes of an RPC response sent to a known peer.
+    pub fn on_bytes_sent(&mut self, peer_id: &PeerId, bytes: u64) {
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.bytes_sent += bytes;
+        }
+    }
+
+    /// Returns the sync health of a known peer.
+    pub fn peer_summary(&self, peer_id: &PeerId) -> Option<PeerSummary> {
+        self.known_peers.get(peer_id).map(|info| PeerSummary {
+            peer_id: peer_id.clone(),
+            finalized_epoch: info.latest_finalized_epoch,
+            best_slot: info.best_slot,
+            last_seen_slot: info.last_seen_slot,
+            latency: info.latency,
+            blocks_served: info.blocks_served,
+            bytes_received: info.bytes_received,
+            bytes_sent: info.bytes_sent,
+            errors: info.errors,
+        })
+    }
+
+    /// Returns the sync health of each known peer, highest last seen slot first.
+    pub fn peer_summaries(&self) -> Vec<PeerSummary> {
+        let mut summaries: Vec<PeerSummary> = self
+            .known_peers
+            .keys()
+            .filter_map(|peer_id| self.peer_summary(peer_id))
+            .collect();
+        summaries.sort_by(|a, b| b.last_seen_slot.cmp(&a.last_seen_slot));
+        summaries
+    }
+
+    /// Returns the progress of syncing towards the chain of our peers.
+    ///
+    /// The rate and estimated completion are measured from when downloading last began.
+    pub fn sync_status(&self) -> SyncStatus {
+        let target = self.sync_target();
+        let target_slot = target.as_ref().map(|target| target.quorum_slot);
+        let clock_slot = self.chain.present_slot();
+        let mut status = SyncStatus {
+            state: self.state,
+            current_slot: self.latest_slot,
+            clock_slot,
+            target_slot,
+            is_synced: self.is_synced_to(target.as_ref()),
+            sync_peers: target.map_or(0, |target| target.peers.len()),
+            blocks_per_second: 0.0,
+            eta: None,
         };
 
-        debug!(self.log, "Handshake successful. Peer: {:?}", peer_id);
-        self.known_peers.insert(peer_id, peer_info);
+        let (started, start_slot) = match self.download_started {
+            Some(started) if self.state == SyncState::Downloading => started,
+            _ => return status,
+        };
+        let elapsed_millis = started.elapsed().as_millis();
+        if elapsed_millis > 0 {
+            status.blocks_per_second =
+                self.blocks_downloaded as f64 * 1_000.0 / elapsed_millis as f64;
+        }
+        // slot subtraction saturates at zero
+        let progress = u128::from((self.latest_slot - start_slot).as_u64());
+        if let (Some(target_slot), true) = (target_slot, progress > 0) {
+            let remaining = u128::from((target_slot - self.latest_slot).as_u64());
+            status.eta = Some(Duration::from_millis(
+                (elapsed_millis * remaining / progress) as u64,
+            ));
+        }
+        status
+    }
+
+    /// Returns `true` if our head is within `synced_tolerance` slots of the head reached by a
+    /// majority of the peers of the chain we sync to, in which case blocks may be produced on it.
+    ///
+    /// Heads are compared by the slot of their block, so slots skipped by the whole network do
+    /// not count against us. Without peers there is nothing to sync from, so we are synced.
+    pub fn is_synced(&self) -> bool {
+        self.is_synced_to(self.sync_target().as_ref())
+    }
 
-        // set state to sync
-        if self.state == SyncState::Idle
-            && hello_message.best_slot > self.latest_slot + SLOT_IMPORT_TOLERANCE
+    fn is_synced_to(&self, target: Option<&CandidateChain>) -> bool {
+        // slot subtraction saturates at zero
+        target.map_or(true, |target| {
+            (target.quorum_slot - self.latest_slot).as_u64() <= self.config.synced_tolerance
+        })
+    }
+
+    /// Logs the progress of syncing, at most once per `PROGRESS_LOG_INTERVAL` and only whilst
+    /// downloading.
+    pub fn log_progress(&mut self) {
+        if self.state != SyncState::Downloading
+            || self.last_progress_log.elapsed() < PROGRESS_LOG_INTERVAL
         {
-            self.state = SyncState::Downloading;
-            //TODO: Start requesting blocks from known peers. Ideally in batches
+            return;
         }
+        self.last_progress_log = Instant::now();
 
-        true
+        let status = self.sync_status();
+        info!(
+            self.log,
+            "Syncing";
+            "current_slot" => status.current_slot.as_u64(),
+            "target_slot" => status.target_slot.map_or(0, |slot| slot.as_u64()),
+            "peers" => status.sync_peers,
+            "blocks_per_sec" => format!("{:.1}", status.blocks_per_second),
+            "eta_secs" => status.eta.map_or("unknown".to_string(), |eta| eta.as_secs().to_string()),
+        );
+    }
+
+    /// Returns requests for batches of blocks and the peers to send them to, if we are
+    /// downloading.
+    ///
+    /// The range up to the quorum slot of the sync target is downloaded in batches from every
+    /// peer on the target chain whose best slot covers them, lowest latency peers first. See
+    /// `sync_target` and `RangeSync`.
+    pub fn next_batches(&mut self) -> Vec<(PeerId, BeaconBlockRootsRequest)> {
+        if self.state != SyncState::Downloading {
+            return vec![];
+        }
+        let target = match self.sync_target() {
+            Some(target) => target,
+            None => return vec![],
+        };
+        let target_slot = target.quorum_slot;
+
+        // peers which have not responded to a PING are tried last
+        let mut peers: Vec<(&PeerId, &PeerSyncInfo)> = self
+            .known_peers
+            .iter()
+            .filter(|(peer_id, _)| target.peers.contains(peer_id))
+            .collect();
+        peers.sort_by_key(|(_, info)| (info.latency.is_none(), info.latency));
+        let peers: Vec<(PeerId, Slot)> = peers
+            .into_iter()
+            .map(|(peer_id, info)| (peer_id.clone(), info.best_slot))
+            .collect();
+
+        self.range_sync.set_target(target_slot);
+        let requests = self.range_sync.next_requests(&peers);
+        for (peer_id, request) in &requests {
+            debug!(
+                self.log,
+                "Requesting batch. Peer: {:?}", peer_id;
+                "start_slot" => request.start_slot.as_u64(),
+                "count" => request.count,
+                "target_slot" => target_slot.as_u64(),
+                "target_peers" => target.peers.len(),
+            );
+        }
+        requests
+    }
+
+    /// Returns the chain to sync, the one followed by the most known peers.
+    ///
+    /// Each finalized checkpoint advertised in a HELLO is a candidate chain, followed by the
+    /// peers advertising it and by those which have finalized up to `FINALIZATION_LAG_EPOCHS`
+    /// epochs beyond it, so that peers are not split whilst a new checkpoint is finalized. The
+    /// chain is synced up to its quorum slot, so a single peer claiming a distant head cannot
+    /// decide the target. Ties are broken in favour of the later finalized epoch, then the higher
+    /// quorum slot. Peers which recently served a chain whose blocks repeatedly failed to import
+    /// are ignored, see `invalidate_target`.
+    fn sync_target(&self) -> Option<CandidateChain> {
+        let clock_slot = self.chain.read_slot_clock();
+        let now = Instant::now();
+        let peers: Vec<(&PeerId, &PeerSyncInfo)> = self
+            .known_peers
+            .iter()
+            .filter(|(peer_id, _)| {
+                self.invalid_chain_peers
+                    .get(peer_id)
+                    .map_or(true, |until| *until <= now)
+            })
+            .collect();
+        let mut checkpoints: Vec<(Epoch, Hash256)> = peers
+            .iter()
+            .map(|(_, info)| (info.latest_finalized_epoch, info.latest_finalized_root))
+            .collect();
+        checkpoints.sort();
+        checkpoints.dedup();
+
+        // the finalized root breaks any remaining tie, so the same chain is chosen on each call
+        checkpoints
+            .into_iter()
+            .map(|(finalized_epoch, finalized_root)| {
+                let followers: Vec<&(&PeerId, &PeerSyncInfo)> = peers
+                    .iter()
+                    .filter(|(_, info)| {
+                        if info.latest_finalized_epoch == finalized_epoch {
+                            info.latest_finalized_root == finalized_root
+                        } else {
+                            info.latest_finalized_epoch > finalized_epoch
+                                && info.latest_finalized_epoch
+                                    <= finalized_epoch + FINALIZATION_LAG_EPOCHS
+                        }
+                    })
+                    .collect();
+                // no honest peer has a block after the wall clock
+                let mut best_slots: Vec<Slot> = followers
+                    .iter()
+                    .map(|(_, info)| {
+                        clock_slot.map_or(info.best_slot, |clock_slot| {
+                            std::cmp::min(info.best_slot, clock_slot)
+                        })
+                    })
+                    .collect();
+                CandidateChain {
+                    finalized_root,
+                    finalized_epoch,
+                    quorum_slot: quorum_slot(&mut best_slots),
+                    peers: followers
+                        .into_iter()
+                        .map(|(peer_id, _)| (*peer_id).clone())
+                        .collect(),
+                }
+            })
+            .max_by_key(|chain| {
+                (
+                    chain.peers.len(),
+                    chain.finalized_epoch,
+                    chain.quorum_slot,
+                    chain.finalized_root,
+                )
+            })
+    }
+
+    /// Returns the block roots requests of parent lookups which are ready to search further back,
+    /// if we are idle.
+    pub fn next_parent_lookups(&mut self) -> Vec<(PeerId, RPCRequest)> {
+        if self.state != SyncState::Idle {
+            return vec![];
+        }
+        let (requests, failed) = self.parent_lookups.next_requests();
+        for (peer_id, error) in failed {
+            self.on_lookup_failed(&peer_id, error);
+        }
+        requests
+    }
+
+    /// Returns a request for the blocks before our oldest block and the peer to send it to, if we
+    /// are idle and the chain was started from a checkpoint.
+    ///
+    /// The range is downloaded from a peer on the chain we follow without a parent lookup in
+    /// progress, lowest latency peers first. See `BackfillSync`.
+    pub fn next_backfill(&mut self) -> Option<(PeerId, BeaconBlockRootsRequest)> {
+        if self.state != SyncState::Idle {
+            return None;
+        }
+        let (oldest_slot, _) = self.chain.oldest_block_parent()?;
+        let target = self.sync_target()?;
+
+        let mut peers: Vec<(&PeerId, &PeerSyncInfo)> = self
+            .known_peers
+            .iter()
+            .filter(|(peer_id, _)| {
+                target.peers.contains(peer_id) && !self.parent_lookups.has_lookup(peer_id)
+            })
+            .collect();
+        peers.sort_by_key(|(_, info)| (info.latency.is_none(), info.latency));
+        let peers: Vec<PeerId> = peers
+            .into_iter()
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        let genesis_slot = self.chain.get_spec().genesis_slot;
+        let (peer_id, request) = self
+            .backfill
+            .next_request(oldest_slot, genesis_slot, &peers)?;
+        debug!(
+            self.log,
+            "Requesting historical blocks. Peer: {:?}", peer_id;
+            "start_slot" => request.start_slot.as_u64(),
+            "count" => request.count,
+            "oldest_slot" => oldest_slot.as_u64(),
+        );
+        Some((peer_id, request))
+    }
+
+    /// Returns `true` if the recent aggregate attestations of our peers are yet to be requested,
+    /// see `next_attestation_requests`.
+    pub fn wants_attestations(&self) -> bool {
+        self.state == SyncState::Idle && !self.attestations_requested
+    }
+
+    /// Returns requests for the recent aggregate attestations of the peers of the sync target,
+    /// once each time we become idle, as gossip only provides the attestations made after
+    /// syncing finished.
+    ///
+    /// `subnet_counts` holds the number of attestation subnets each peer advertises. Peers on more
+    /// subnets are preferred, as they hold the aggregates of more committees, then those with the
+    /// lowest latency.
+    pub fn next_attestation_requests(
+        &mut self,
+        subnet_counts: &HashMap<PeerId, usize>,
+    ) -> Vec<(PeerId, BeaconAttestationsRequest)> {
+        if !self.wants_attestations() {
+            return vec![];
+        }
+        let target = match self.sync_target() {
+            Some(target) => target,
+            None => return vec![],
+        };
+
+        let mut peers: Vec<(&PeerId, &PeerSyncInfo)> = self
+            .known_peers
+            .iter()
+            .filter(|(peer_id, _)| target.peers.contains(peer_id))
+            .collect();
+        peers.sort_by_key(|(peer_id, info)| {
+            (
+                std::cmp::Reverse(subnet_counts.get(*peer_id).cloned().unwrap_or(0)),
+                info.latency.is_none(),
+                info.latency,
+            )
+        });
+
+        // attestations older than an epoch cannot be included in a block
+        let slots_per_epoch = self.chain.get_spec().slots_per_epoch;
+        let start_slot = Slot::new(
+            self.chain
+                .present_slot()
+                .as_u64()
+                .saturating_sub(slots_per_epoch),
+        );
+        self.attestations_requested = true;
+        debug!(
+            self.log,
+            "Requesting recent attestations";
+            "peers" => std::cmp::min(peers.len(), ATTESTATION_REQUEST_PEERS),
+            "start_slot" => start_slot.as_u64(),
+        );
+        let requests: Vec<(PeerId, BeaconAttestationsRequest)> = peers
+            .into_iter()
+            .take(ATTESTATION_REQUEST_PEERS)
+            .map(|(peer_id, _)| {
+                (
+                    peer_id.clone(),
+                    BeaconAttestationsRequest {
+                        start_slot,
+                        max_attestations: MAX_REQUESTED_ATTESTATIONS,
+                    },
+                )
+            })
+            .collect();
+        for (peer_id, request) in &requests {
+            self.attestation_requests
+                .insert(peer_id.clone(), request.clone());
+        }
+        requests
+    }
+
+    /// Handles a BeaconAttestations response, returning the attestations to be processed.
+    ///
+    /// Only the attestations requested are returned, so a peer cannot make us verify more than
+    /// `max_attestations` signatures, nor any attestation before the `start_slot` requested.
+    pub fn on_beacon_attestations_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconAttestationsResponse,
+    ) -> Vec<Attestation> {
+        let request = match self.attestation_requests.remove(peer_id) {
+            Some(request) => request,
+            None => return vec![],
+        };
+        let received = response.attestations.len();
+        let attestations: Vec<Attestation> = response
+            .attestations
+            .into_iter()
+            .filter(|attestation| attestation.data.slot >= request.start_slot)
+            .take(request.max_attestations as usize)
+            .collect();
+        if attestations.len() < received {
+            debug!(
+                self.log,
+                "Attestations not requested were ignored. Peer: {:?}", peer_id;
+                "received" => received,
+                "requested" => request.max_attestations,
+            );
+        }
+        attestations
+    }
+
+    /// Handles a BeaconBlockRoots response, returning a request for the headers of those blocks.
+    ///
+    /// Whilst downloading, the roots are of a batch of range sync. Whilst idle, they are of a
+    /// parent lookup or of historical blocks being backfilled. Returns `None` if the peer
+    /// returned no roots or the roots are not of the batch, lookup or range requested.
+    pub fn on_beacon_block_roots_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockRootsResponse,
+    ) -> Option<BeaconBlockHeadersRequest> {
+        if self.backfill.is_downloading_from(peer_id) {
+            let (oldest_slot, _) = self.chain.oldest_block_parent()?;
+            return match self
+                .backfill
+                .on_roots_response(peer_id, oldest_slot, response)
+            {
+                Ok(request) => request,
+                Err(e) => {
+                    self.on_backfill_failed(peer_id, e);
+                    None
+                }
+            };
+        }
+        if self.state == SyncState::Idle {
+            let chain = &self.chain;
+            let result = self
+                .parent_lookups
+                .on_roots_response(peer_id, response, |root| chain.is_block_known(root));
+            // the blocks of a lookup whose parent has since been imported are imported with the
+            // held gossip blocks
+            for (peer_id, segment) in self.parent_lookups.take_connected() {
+                for block in segment {
+                    self.pending_blocks.queue_for_parent(peer_id.clone(), block);
+                }
+            }
+            return match result {
+                Ok(request) => request,
+                Err(e) => {
+                    self.on_lookup_failed(peer_id, e);
+                    None
+                }
+            };
+        }
+        if self.state != SyncState::Downloading {
+            return None;
+        }
+        match self.range_sync.on_roots_response(peer_id, response) {
+            Ok(Some(request)) => {
+                debug!(
+                    self.log,
+                    "Requesting block headers. Peer: {:?}", peer_id;
+                    "start_slot" => request.start_slot.as_u64(),
+                    "count" => request.max_headers,
+                );
+                Some(request)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                self.on_batch_failed(peer_id, e);
+                None
+            }
+        }
+    }
+
+    /// Handles a BeaconBlockHeaders response by queuing the headers, returning a request for the
+    /// bodies of the blocks.
+    ///
+    /// Returns `None` if the headers are not of the batch or parent lookup requested.
+    pub fn on_beacon_block_headers_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockHeadersResponse,
+    ) -> Option<BeaconBlockBodiesRequest> {
+        if self.backfill.is_downloading_from(peer_id) {
+            return match self.backfill.on_headers_response(peer_id, response) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.on_backfill_failed(peer_id, e);
+                    None
+                }
+            };
+        }
+        if self.state == SyncState::Idle {
+            return match self.parent_lookups.on_headers_response(peer_id, response) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.on_lookup_failed(peer_id, e);
+                    None
+                }
+            };
+        }
+        if self.state != SyncState::Downloading {
+            return None;
+        }
+        match self.range_sync.on_headers_response(peer_id, response) {
+            Ok(Some(block_roots)) => {
+                debug!(
+                    self.log,
+                    "Requesting block bodies. Peer: {:?}", peer_id;
+                    "count" => block_roots.len(),
+                );
+                Some(BeaconBlockBodiesRequest { block_roots })
+            }
+            Ok(None) => None,
+            Err(e) => {
+                self.on_batch_failed(peer_id, e);
+                None
+            }
+        }
+    }
+
+    /// Handles a BeaconBlockBodies response, completing the batch being downloaded from the peer,
+    /// then imports every batch which is next in slot order. Whilst idle, completes the parent
+    /// lookup of the peer instead, importing its blocks once they connect to our chain.
+    ///
+    /// Returns the score penalties for the peers which provided invalid blocks. Syncing is
+    /// stopped if the chain returns an error. Historical blocks being backfilled are stored
+    /// once verified.
+    pub fn on_beacon_block_bodies_response(
+        &mut self,
+        peer_id: &PeerId,
+        response: BeaconBlockBodiesResponse,
+        trace_id: TraceId,
+    ) -> Vec<(PeerId, i64)> {
+        if self.backfill.is_downloading_from(peer_id) {
+            self.import_backfill(peer_id, response);
+            return vec![];
+        }
+        if self.state == SyncState::Idle {
+            return match self.parent_lookups.on_bodies_response(peer_id, response) {
+                Ok(Some(segment)) => {
+                    let mut penalties = self.import_segment(peer_id, segment, trace_id);
+                    penalties.append(&mut self.import_pending_blocks(trace_id));
+                    penalties
+                }
+                Ok(None) => vec![],
+                Err(e) => {
+                    self.on_lookup_failed(peer_id, e);
+                    vec![]
+                }
+            };
+        }
+        if self.state != SyncState::Downloading {
+            return vec![];
+        }
+        match self.range_sync.on_bodies_response(peer_id, response) {
+            Ok(downloaded) => {
+                if let Some(info) = self.known_peers.get_mut(peer_id) {
+                    info.blocks_served += downloaded as u64;
+                }
+            }
+            Err(e) => {
+                self.on_batch_failed(peer_id, e);
+                return vec![];
+            }
+        }
+
+        let mut penalties = self.import_ready_batches(trace_id);
+        penalties.append(&mut self.import_pending_blocks(trace_id));
+
+        debug!(
+            self.log,
+            "Imported synced blocks. Peer: {:?}", peer_id;
+            "local_slot" => self.latest_slot.as_u64(),
+            "pending_batches" => self.range_sync.batch_count(),
+            "trace_id" => format!("{}", trace_id),
+        );
+        self.update_state();
+
+        penalties
+    }
+
+    /// Stores the historical blocks of the range being backfilled from `peer_id`, once verified to
+    /// lead to our oldest block.
+    fn import_backfill(&mut self, peer_id: &PeerId, response: BeaconBlockBodiesResponse) {
+        let oldest_parent = match self.chain.oldest_block_parent() {
+            Some((_, oldest_parent)) => oldest_parent,
+            None => return,
+        };
+        let blocks = match self
+            .backfill
+            .on_bodies_response(peer_id, oldest_parent, response)
+        {
+            Ok(Some(blocks)) => blocks,
+            Ok(None) => return,
+            Err(e) => {
+                self.on_backfill_failed(peer_id, e);
+                return;
+            }
+        };
+
+        match self.chain.import_historical_blocks(&blocks) {
+            Ok(stored) => {
+                metrics::SYNC_BLOCKS_BACKFILLED.inc_by(stored);
+                if let Some(info) = self.known_peers.get_mut(peer_id) {
+                    info.blocks_served += stored as u64;
+                }
+                match self.chain.oldest_block_parent() {
+                    Some((oldest_slot, _)) => debug!(
+                        self.log,
+                        "Stored historical blocks. Peer: {:?}", peer_id;
+                        "count" => stored,
+                        "oldest_slot" => oldest_slot.as_u64(),
+                    ),
+                    None => info!(self.log, "Historical blocks backfilled to genesis"),
+                }
+            }
+            Err(e) => warn!(
+                self.log,
+                "Unable to store historical blocks. Peer: {:?}", peer_id;
+                "error" => format!("{:?}", e),
+            ),
+        }
+    }
+
+    /// Imports the downloaded batches whose preceding batches have all been imported, returning
+    /// the score penalty of the peer of a batch which could not be imported.
+    ///
+    /// A batch with an invalid block is downloaded again before later batches are imported.
+    fn import_ready_batches(&mut self, trace_id: TraceId) -> Vec<(PeerId, i64)> {
+        while let Some(batch) = self.range_sync.pop_ready() {
+            if let Some((peer_id, penalty)) = self.import_batch(batch, trace_id) {
+                if penalty > 0 {
+                    return vec![(peer_id, penalty)];
+                }
+                break;
+            }
+        }
+        vec![]
+    }
+
+    /// Imports the blocks of a downloaded batch into the chain in slot order, as a chain segment
+    /// whose signatures are verified together.
+    ///
+    /// If the batch cannot be imported it is downloaded again from another peer, and the peer
+    /// which provided it is returned with its score penalty, see `SyncError::peer_action`. A
+    /// peer which provided invalid blocks is never asked for the batch again, and once the batch
+    /// has held invalid blocks `max_batch_import_failures` times the chain being synced to is
+    /// considered invalid. Blocks which merely do not connect to our chain are not counted, as
+    /// the peer may be on another fork. If the first block does not connect, the slots between
+    /// our head and the batch are downloaded again too, as the peer of the batch before it may
+    /// have withheld the blocks at its end. Syncing is stopped if the chain returns an error.
+    fn import_batch(&mut self, mut batch: ReadyBatch, trace_id: TraceId) -> Option<(PeerId, i64)> {
+        let blocks = std::mem::replace(&mut batch.blocks, vec![]);
+        let first_slot = blocks.first().map(|block| block.slot);
+        let last_slot = blocks.last().map(|block| block.slot);
+        let imported = match self.process_segment(blocks, trace_id) {
+            BatchProcessingResult::Success { imported } => imported,
+            BatchProcessingResult::Failed { error, .. } => {
+                let invalid_blocks = match error {
+                    SyncError::InvalidBatch { .. } => true,
+                    _ => false,
+                };
+                let unconnected = match error {
+                    SyncError::WrongParent { slot } => Some(slot) == first_slot,
+                    _ => false,
+                };
+                let action = self.on_segment_failed(&batch.peer_id, error, trace_id);
+                if action == SyncPeerAction::None {
+                    // stopping abandons the remaining batches
+                    return None;
+                }
+                metrics::SYNC_BATCHES_FAILED.inc();
+                let peer_id = batch.peer_id.clone();
+                let start_slot = batch.start_slot;
+                let invalid = action != SyncPeerAction::RetryElsewhere;
+                let failures = self.range_sync.retry(batch, invalid, invalid_blocks);
+                if unconnected {
+                    self.range_sync.redownload(self.latest_slot + 1, start_slot);
+                }
+                if failures >= self.config.max_batch_import_failures {
+                    self.invalidate_target(start_slot, failures);
+                }
+                return Some((peer_id, action.penalty()));
+            }
+        };
+
+        metrics::SYNC_BATCHES_IMPORTED.inc();
+        self.blocks_downloaded += imported as u64;
+        // the slots of the batch after its last block were skipped, unless still to come
+        let end_slot = batch.start_slot + batch.count - 1;
+        let clock_slot = self.chain.read_slot_clock().unwrap_or(self.latest_slot);
+        self.batch_end_slot =
+            std::cmp::max(self.batch_end_slot, std::cmp::min(end_slot, clock_slot));
+        if let (Some(info), Some(slot)) = (self.known_peers.get_mut(&batch.peer_id), last_slot) {
+            info.see_slot(slot);
+        }
+        trace!(
+            self.log,
+            "Imported batch. Peer: {:?}", batch.peer_id;
+            "start_slot" => batch.start_slot.as_u64(),
+            "imported" => imported,
+        );
+        None
     }
+
+    /// Stops syncing to the current target chain, as the batch at `start_slot` has held invalid
+    /// blocks `failures` times. The peers which served them are not synced to for
+    /// `INVALID_CHAIN_TIMEOUT`, and downloading begins again towards the best chain of the rest,
+    /// if any.
+    fn invalidate_target(&mut self, start_slot: Slot, failures: u32) {
+        let invalid_peers = self.range_sync.invalid_peers(start_slot);
+        warn!(
+            self.log,
+            "Sync target chain is invalid";
+            "peers" => format!("{:?}", invalid_peers),
+            "start_slot" => start_slot.as_u64(),
+            "failures" => failures,
+        );
+        metrics::SYNC_CHAINS_INVALIDATED.inc();
+        let now = Instant::now();
+        self.invalid_chain_peers.retain(|_, until| *until > now);
+        for peer_id in invalid_peers {
+            self.invalid_chain_peers
+                .insert(peer_id, now + INVALID_CHAIN_TIMEOUT);
+        }
+        // stopping abandons the batches of the chain, and `update_state` restarts downloading
+        self.set_state(SyncState::Idle);
+    }
+
+    /// Imports the blocks of a completed parent lookup, oldest first.
+    ///
+    /// If the segment cannot be imported the rest of it is discarded and the score penalty of
+    /// the peer which provided it is returned, see `SyncError::peer_action`. Syncing is stopped if
+    /// the chain returns an error.
+    fn import_segment(
+        &mut self,
+        peer_id: &PeerId,
+        segment: Vec<BeaconBlock>,
+        trace_id: TraceId,
+    ) -> Vec<(PeerId, i64)> {
+        let length = segment.len();
+        if let BatchProcessingResult::Failed { error, .. } = self.process_segment(segment, trace_id)
+        {
+            let penalty = self.on_segment_failed(peer_id, error, trace_id).penalty();
+            if penalty > 0 {
+                return vec![(peer_id.clone(), penalty)];
+            }
+            return vec![];
+        }
+
+        debug!(
+            self.log,
+            "Imported looked up blocks. Peer: {:?}", peer_id;
+            "blocks" => length,
+            "local_slot" => self.latest_slot.as_u64(),
+            "trace_id" => format!("{}", trace_id),
+        );
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.blocks_served += length as u64;
+        }
+        vec![]
+    }
+
+    /// Imports a chain segment provided by a peer, oldest first, rejecting it before any block is
+    /// processed if its blocks are not linked.
+    fn process_segment(
+        &mut self,
+        blocks: Vec<BeaconBlock>,
+        trace_id: TraceId,
+    ) -> BatchProcessingResult {
+        if let Err(error) = batch_processing::verify_linear(&blocks) {
+            return BatchProcessingResult::Failed { imported: 0, error };
+        }
+        let slots: Vec<Slot> = blocks.iter().map(|block| block.slot).collect();
+        let outcomes = self.chain.process_chain_segment(blocks, trace_id);
+        let mut imported = 0;
+        for (slot, outcome) in slots.into_iter().zip(outcomes) {
+            let valid = match outcome {
+                Ok(BlockProcessingOutcome::ValidBlock(_)) => true,
+                _ => false,
+            };
+            if let Some(error) = batch_processing::classify_outcome(slot, outcome) {
+                return BatchProcessingResult::Failed { imported, error };
+            }
+            if valid {
+                imported += 1;
+                if slot > self.latest_slot {
+                    self.latest_slot = slot;
+                }
+            }
+        }
+        BatchProcessingResult::Success { imported }
+    }
+
+    /// Handles a chain segment from a peer which could not be imported, returning the action to
+    /// take against the peer. Syncing is stopped if the chain returned an error.
+    fn on_segment_failed(
+        &mut self,
+        peer_id: &PeerId,
+        error: SyncError,
+        trace_id: TraceId,
+    ) -> SyncPeerAction {
+        let action = error.peer_action();
+        if let SyncError::ExecutionError { slot, error } = error {
+            warn!(
+                self.log,
+                "Beacon chain error importing synced blocks";
+                "slot" => slot.as_u64(),
+                "trace_id" => format!("{}", trace_id),
+                "error" => format!("{:?}", error),
+            );
+            self.stop(StopReason::FatalChainError);
+            return action;
+        }
+
+        debug!(
+            self.log,
+            "Synced blocks not imported. Peer: {:?}", peer_id;
+            "slot" => error.slot().as_u64(),
+            "trace_id" => format!("{}", trace_id),
+            "error" => format!("{:?}", error),
+            "action" => format!("{:?}", action),
+        );
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.errors += 1;
+        }
+        action
+    }
+
+    /// Handles a block received over gossip, importing it into the chain.
+    ///
+    /// A block whose parent is unknown starts a parent lookup from the peer whilst idle, see
+    /// `ParentLookups`. Otherwise, as when downloading, it is held until the parent is imported.
+    /// A block slightly ahead of our present slot is held until its slot starts. See
+    /// `PendingBlocks`.
+    ///
+    /// Returns the score penalties for the peers which provided invalid blocks, the peer that
+    /// propagated a block being responsible for it. Held blocks made importable by this block
+    /// are imported with it. Syncing is stopped if the chain returns an error.
+    pub fn on_gossip_block(
+        &mut self,
+        peer_id: &PeerId,
+        block: BeaconBlock,
+        trace_id: TraceId,
+    ) -> Vec<(PeerId, i64)> {
+        let slot = block.slot;
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.blocks_served += 1;
+        }
+        let parent_root = block.previous_block_root;
+        if !self.chain.is_block_known(&parent_root) {
+            // a parent which is itself held will be imported without a lookup, and responses are
+            // matched by peer, so a peer being backfilled from is not also asked for ancestors
+            if self.state == SyncState::Idle
+                && !self.pending_blocks.is_pending(&parent_root)
+                && !self.backfill.is_downloading_from(peer_id)
+                && self.parent_lookups.can_start(peer_id, &block)
+            {
+                self.parent_lookups.start(peer_id.clone(), block);
+                debug!(
+                    self.log,
+                    "Looking up parent of gossip block. Peer: {:?}", peer_id;
+                    "slot" => slot.as_u64(),
+                    "trace_id" => format!("{}", trace_id),
+                );
+            } else if self.pending_blocks.queue_for_parent(peer_id.clone(), block) {
+                metrics::SYNC_BLOCKS_PENDING.inc();
+                debug!(
+                    self.log,
+                    "Holding gossip block until its parent is imported. Peer: {:?}", peer_id;
+                    "slot" => slot.as_u64(),
+                    "parent_root" => format!("{:?}", parent_root),
+                    "trace_id" => format!("{}", trace_id),
+                );
+            }
+            return vec![];
+        }
+
+        match self.import_block(peer_id, block, trace_id) {
+            Some(penalty) => vec![(peer_id.clone(), penalty)],
+            None => self.import_pending_blocks(trace_id),
+        }
+    }
+
+    /// Imports the held gossip blocks whose slot has started and discards those held too long.
+    /// Also imports the downloaded batches which completed without a blocks response, as those
+    /// of skipped slots do.
+    ///
+    /// Should be called periodically, so held blocks are imported once the slot ticks over.
+    /// Returns the score penalties for the peers which provided invalid blocks.
+    pub fn process_pending_blocks(&mut self, trace_id: TraceId) -> Vec<(PeerId, i64)> {
+        let mut penalties = self.import_ready_batches(trace_id);
+        self.update_state();
+        let expired = self.pending_blocks.expire();
+        if expired > 0 {
+            metrics::SYNC_PENDING_BLOCKS_EXPIRED.inc_by(expired);
+            debug!(
+                self.log,
+                "Discarded held gossip blocks";
+                "expired" => expired,
+                "remaining" => self.pending_blocks.len(),
+            );
+        }
+        penalties.append(&mut self.import_pending_blocks(trace_id));
+        penalties
+    }
+
+    /// Imports the held gossip blocks whose slot has started or whose parent is now known,
+    /// repeating whilst blocks are imported, as they may be the parents of others.
+    ///
+    /// Returns the score penalties for the peers which provided invalid blocks.
+    fn import_pending_blocks(&mut self, trace_id: TraceId) -> Vec<(PeerId, i64)> {
+        let mut penalties = vec![];
+        loop {
+            if self.state == SyncState::Stopped(StopReason::FatalChainError) {
+                return penalties;
+            }
+            let chain = &self.chain;
+            let mut ready = self.pending_blocks.take_ready(chain.present_slot());
+            ready.append(
+                &mut self
+                    .pending_blocks
+                    .take_connected(|root| chain.is_block_known(root)),
+            );
+            if ready.is_empty() {
+                return penalties;
+            }
+
+            for PendingBlock { peer_id, block, .. } in ready {
+                if !self.chain.is_block_known(&block.previous_block_root) {
+                    self.pending_blocks.queue_for_parent(peer_id, block);
+                    continue;
+                }
+                if let Some(penalty) = self.import_block(&peer_id, block, trace_id) {
+                    penalties.push((peer_id, penalty));
+                }
+            }
+        }
+    }
+
+    /// Imports a gossip block whose parent is known, holding it if its slot has not yet started.
+    ///
+    /// Returns the score penalty for the block if it is invalid. Syncing is stopped if the chain
+    /// returns an error.
+    fn import_block(
+        &mut self,
+        peer_id: &PeerId,
+        block: BeaconBlock,
+        trace_id: TraceId,
+    ) -> Option<i64> {
+        let slot = block.slot;
+        // a block ahead of the present slot is kept, in case it must be held
+        let future_block = if slot > self.chain.present_slot() {
+            Some(block.clone())
+        } else {
+            None
+        };
+        match self.chain.process_block(block, trace_id) {
+            Ok(BlockProcessingOutcome::ValidBlock(_)) => {
+                debug!(
+                    self.log,
+                    "Imported gossip block. Peer: {:?}", peer_id;
+                    "slot" => slot.as_u64(),
+                    "trace_id" => format!("{}", trace_id),
+                );
+                if slot > self.latest_slot {
+                    self.latest_slot = slot;
+                }
+                if let Some(info) = self.known_peers.get_mut(peer_id) {
+                    info.see_slot(slot);
+                }
+                None
+            }
+            Ok(BlockProcessingOutcome::InvalidBlock(InvalidBlock::FutureSlot)) => {
+                let present_slot = self.chain.present_slot();
+                let held = future_block.map_or(false, |block| {
+                    self.pending_blocks
+                        .queue_for_slot(peer_id.clone(), block, present_slot)
+                });
+                if held {
+                    metrics::SYNC_BLOCKS_PENDING.inc();
+                    debug!(
+                        self.log,
+                        "Holding gossip block until its slot. Peer: {:?}", peer_id;
+                        "slot" => slot.as_u64(),
+                        "present_slot" => present_slot.as_u64(),
+                        "trace_id" => format!("{}", trace_id),
+                    );
+                } else {
+                    debug!(
+                        self.log,
+                        "Gossip block too far ahead to hold. Peer: {:?}", peer_id;
+                        "slot" => slot.as_u64(),
+                        "present_slot" => present_slot.as_u64(),
+                        "trace_id" => format!("{}", trace_id),
+                    );
+                }
+                None
+            }
+            Ok(outcome) => {
+                debug!(
+                    self.log,
+                    "Gossip block not imported. Peer: {:?}", peer_id;
+                    "slot" => slot.as_u64(),
+                    "trace_id" => format!("{}", trace_id),
+                    "outcome" => format!("{:?}", outcome),
+                );
+                let penalty = penalty(&outcome);
+                if penalty == 0 {
+                    return None;
+                }
+                if let Some(info) = self.known_peers.get_mut(peer_id) {
+                    info.errors += 1;
+                }
+                Some(penalty)
+            }
+            Err(e) => {
+                warn!(
+                    self.log,
+                    "Beacon chain error importing gossip block";
+                    "slot" => slot.as_u64(),
+                    "trace_id" => format!("{}", trace_id),
+                    "error" => format!("{:?}", e),
+                );
+                self.stop(StopReason::FatalChainError);
+                None
+            }
+        }
+    }
+
+    /// Forgets a peer, for instance once it has been disconnected.
+    ///
+    /// A batch being downloaded from the peer is abandoned, to be downloaded from another peer.
+    /// Downloading stops if no known peers remain.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        if self.known_peers.remove(peer_id).is_none() {
+            return;
+        }
+        self.range_sync.remove_peer(peer_id);
+        self.parent_lookups.remove_peer(peer_id);
+        self.backfill.on_request_failed(peer_id);
+        self.attestation_requests.remove(peer_id);
+
+        if self.known_peers.is_empty() {
+            if self.state == SyncState::Downloading {
+                self.set_state(SyncState::Idle);
+            }
+        } else {
+            self.update_state();
+        }
+    }
+
+    /// Handles an error response from a peer, abandoning the batch being downloaded from it. The
+    /// batch is then downloaded again, preferring another peer.
+    pub fn on_request_failed(&mut self, peer_id: &PeerId) {
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.errors += 1;
+        }
+        if self.range_sync.on_request_failed(peer_id) {
+            debug!(self.log, "Batch request failed. Peer: {:?}", peer_id);
+            metrics::SYNC_BATCHES_FAILED.inc();
+        }
+        if self.parent_lookups.remove_peer(peer_id) {
+            debug!(
+                self.log,
+                "Parent lookup request failed. Peer: {:?}", peer_id
+            );
+        }
+        if self.backfill.on_request_failed(peer_id) {
+            debug!(self.log, "Backfill request failed. Peer: {:?}", peer_id);
+        }
+    }
+
+    /// Records a parent lookup abandoned as the peer could not provide the ancestors.
+    fn on_lookup_failed(&mut self, peer_id: &PeerId, error: LookupError) {
+        debug!(
+            self.log,
+            "Parent lookup failed. Peer: {:?}", peer_id;
+            "error" => format!("{:?}", error),
+        );
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.errors += 1;
+        }
+    }
+
+    /// Records a backfill range abandoned as the peer's response to it was invalid.
+    fn on_backfill_failed(&mut self, peer_id: &PeerId, error: BackfillError) {
+        debug!(
+            self.log,
+            "Invalid backfill response. Peer: {:?}", peer_id;
+            "error" => format!("{:?}", error),
+        );
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.errors += 1;
+        }
+    }
+
+    /// Records a batch abandoned as the peer's response to it was invalid.
+    fn on_batch_failed(&mut self, peer_id: &PeerId, error: BatchError) {
+        debug!(
+            self.log,
+            "Invalid batch response. Peer: {:?}", peer_id;
+            "error" => format!("{:?}", error),
+        );
+        metrics::SYNC_BATCHES_FAILED.inc();
+        if let Some(info) = self.known_peers.get_mut(peer_id) {
+            info.errors += 1;
+        }
+    }
+
+    /// Returns the current state of the syncing protocol.
+    pub fn state(&self) -> SyncState {
+        self.state
+    }
+
+    /// Stops syncing until `resume` is called.
+    pub fn stop(&mut self, reason: StopReason) {
+        self.set_state(SyncState::Stopped(reason));
+    }
+
+    /// Resumes syncing if it was stopped, returning to `Idle` and then downloading if a peer is
+    /// far enough ahead.
+    pub fn resume(&mut self) {
+        if let SyncState::Stopped(_) = self.state {
+            self.set_state(SyncState::Idle);
+            self.update_state();
+        }
+    }
+
+    /// Moves between `Idle` and `Downloading` based upon the quorum slot of the sync target, see
+    /// `sync_target`, and the slot we have synced up to, see `synced_slot`.
+    ///
+    /// Downloading starts when the target is more than `slot_import_tolerance` slots ahead of us
+    /// and only stops once it is within `slot_import_tolerance - slot_import_hysteresis` slots.
+    /// Whilst we are behind the wall clock, see `check_clock`, downloading starts and continues
+    /// whilst the target is any distance ahead. Has no effect whilst syncing is stopped.
+    fn update_state(&mut self) {
+        let best_peer_slot = match self.sync_target() {
+            Some(target) => target.quorum_slot,
+            None => return,
+        };
+        // slot subtraction saturates at zero
+        let distance = (best_peer_slot - self.synced_slot()).as_u64();
+        let catching_up = self.behind_clock && distance > 0;
+
+        match self.state {
+            SyncState::Idle if distance > self.config.slot_import_tolerance || catching_up => {
+                // blocks are requested in batches, see `next_batches`
+                self.set_state(SyncState::Downloading);
+            }
+            SyncState::Downloading
+                if !catching_up
+                    && distance
+                        <= self
+                            .config
+                            .slot_import_tolerance
+                            .saturating_sub(self.config.slot_import_hysteresis) =>
+            {
+                self.set_state(SyncState::Idle);
+            }
+            _ => {}
+        }
+    }
+
+    /// Transitions to a new sync state, logging and counting the transition.
+    fn set_state(&mut self, new_state: SyncState) {
+        if self.state == new_state {
+            return;
+        }
+
+        info!(
+            self.log,
+            "Sync state changed";
+            "from" => format!("{:?}", self.state),
+            "to" => format!("{:?}", new_state),
+            "local_slot" => self.latest_slot.as_u64(),
+        );
+
+        match new_state {
+            SyncState::Idle => metrics::SYNC_STATE_IDLE_TRANSITIONS.inc(),
+            SyncState::Downloading => metrics::SYNC_STATE_DOWNLOADING_TRANSITIONS.inc(),
+            SyncState::Stopped(_) => metrics::SYNC_STATE_STOPPED_TRANSITIONS.inc(),
+        }
+
+        // downloading continues from our head, and batches are abandoned when it stops
+        if new_state == SyncState::Downloading {
+            self.range_sync.start(self.latest_slot + 1);
+            self.download_started = Some((Instant::now(), self.latest_slot));
+            self.blocks_downloaded = 0;
+            // the attestations held will be stale by the time downloading finishes
+            self.attestations_requested = false;
+        } else {
+            self.range_sync.stop();
+            self.download_started = None;
+        }
+        // parent lookups and backfill share the block requests of range sync, so only run whilst
+        // idle
+        if new_state != SyncState::Idle {
+            self.parent_lookups.clear();
+            self.backfill.cancel();
+        }
+
+        self.state = new_state;
+    }
+
+    /// Returns the root to advertise as finalized.
+    ///
+    /// The state stores a zero hash as the finalized root until the first epoch is finalized, in
+    /// which case the genesis block root is substituted so peers can identify our chain.
+    fn finalized_root(&self, finalized_epoch: Epoch, finalized_root: Hash256) -> Hash256 {
+        if finalized_epoch == self.genesis_epoch && finalized_root.is_zero() {
+            self.genesis_block_root
+        } else {
+            finalized_root
+        }
+    }
+}
+
+/// Returns the highest of `slots` reached by more than half of them.
+///
+/// `slots` must not be empty.
+fn quorum_slot(slots: &mut [Slot]) -> Slot {
+    slots.sort();
+    slots[(slots.len() - 1) / 2]
 }
@@ -0,0 +1,1367 @@
+#![cfg(test)]
+use super::import_queue::{ImportQueue, MAX_QUEUED_HEADERS};
+use super::pending_blocks::PendingBlocks;
+use super::range_sync::{BatchError, RangeSync};
+use super::{SimpleSync, StopReason, SyncConfig, SyncState};
+use crate::beacon_chain::BeaconChainRead;
+use crate::peer_manager::INVALID_BLOCK_PENALTY;
+use beacon_chain::parking_lot::{RwLock, RwLockReadGuard};
+use beacon_chain::{
+    BeaconChainError, BlockProcessingOutcome, HeadInfo, InvalidBlock, TraceId, ValidBlock,
+};
+use eth2_libp2p::rpc::{
+    BeaconAttestationsResponse, BeaconBlockBodiesRequest, BeaconBlockBodiesResponse,
+    BeaconBlockHeadersRequest, BeaconBlockHeadersResponse, BeaconBlockRootsRequest,
+    BeaconBlockRootsResponse, BlockRootSlot, HelloMessage, RPCRequest,
+};
+use eth2_libp2p::PeerId;
+use slog::o;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use types::test_utils::{SeedableRng, TestRandom, TestingBeaconStateBuilder, XorShiftRng};
+use types::{
+    Attestation, BeaconBlock, BeaconBlockBody, BeaconBlockHeader, BeaconState, ChainSpec, Hash256,
+    Slot,
+};
+
+/// A chain of blocks held in memory, importing any block whose parent it holds.
+struct MockChain {
+    spec: ChainSpec,
+    state: RwLock<BeaconState>,
+    genesis_block_root: Hash256,
+    blocks: RwLock<HashMap<Hash256, BeaconBlock>>,
+    head: RwLock<(Hash256, Slot)>,
+    present_slot: Slot,
+    /// Blocks which are imported as invalid.
+    invalid_blocks: RwLock<HashSet<Hash256>>,
+    /// Whether importing returns an error, as when the database fails.
+    fail_imports: RwLock<bool>,
+    /// The slot and parent of the oldest block held, if started from a checkpoint.
+    oldest_block_parent: RwLock<Option<(Slot, Hash256)>>,
+}
+
+impl MockChain {
+    fn new(spec: ChainSpec, present_slot: Slot) -> Self {
+        let (state, _keypairs) =
+            TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec).build();
+        let genesis_block = BeaconBlock::empty(&spec);
+        let genesis_block_root = genesis_block.canonical_root();
+        let mut blocks = HashMap::new();
+        blocks.insert(genesis_block_root, genesis_block);
+
+        MockChain {
+            head: RwLock::new((genesis_block_root, spec.genesis_slot)),
+            spec,
+            state: RwLock::new(state),
+            genesis_block_root,
+            blocks: RwLock::new(blocks),
+            present_slot,
+            invalid_blocks: RwLock::new(HashSet::new()),
+            fail_imports: RwLock::new(false),
+            oldest_block_parent: RwLock::new(None),
+        }
+    }
+
+    /// Starts the chain from `anchor`, as from a checkpoint, without the blocks before it.
+    fn start_from(&self, anchor: &BeaconBlock) {
+        let anchor_root = anchor.canonical_root();
+        self.blocks.write().insert(anchor_root, anchor.clone());
+        *self.head.write() = (anchor_root, anchor.slot);
+        *self.oldest_block_parent.write() = Some((anchor.slot, anchor.previous_block_root));
+    }
+
+    fn head_slot(&self) -> Slot {
+        self.head.read().1
+    }
+
+    fn import(&self, block: BeaconBlock) -> Result<BlockProcessingOutcome, BeaconChainError> {
+        if *self.fail_imports.read() {
+            return Err(BeaconChainError::DBError("mock failure".to_string()));
+        }
+        let block_root = block.canonical_root();
+        if self.invalid_blocks.read().contains(&block_root) {
+            return Ok(BlockProcessingOutcome::InvalidBlock(
+                InvalidBlock::StateRootMismatch,
+            ));
+        }
+        if !self.is_block_known(&block.previous_block_root) {
+            return Ok(BlockProcessingOutcome::InvalidBlock(
+                InvalidBlock::ParentUnknown,
+            ));
+        }
+        let mut head = self.head.write();
+        if block.slot > head.1 {
+            *head = (block_root, block.slot);
+        }
+        self.blocks.write().insert(block_root, block);
+        Ok(BlockProcessingOutcome::ValidBlock(ValidBlock::Processed))
+    }
+}
+
+impl BeaconChainRead for MockChain {
+    fn get_spec(&self) -> &ChainSpec {
+        &self.spec
+    }
+
+    fn get_state(&self) -> RwLockReadGuard<BeaconState> {
+        self.state.read()
+    }
+
+    fn head_info(&self) -> HeadInfo {
+        let (block_root, slot) = *self.head.read();
+        let state = self.state.read();
+        HeadInfo {
+            slot,
+            block_root,
+            finalized_epoch: self.spec.genesis_epoch,
+            finalized_root: Hash256::zero(),
+            present_slot: self.present_slot,
+            fork_version: state.fork.get_fork_version(state.current_epoch(&self.spec)),
+        }
+    }
+
+    fn genesis_block_root(&self) -> Hash256 {
+        self.genesis_block_root
+    }
+
+    fn genesis_validators_root(&self) -> Hash256 {
+        Hash256::zero()
+    }
+
+    fn is_block_known(&self, block_root: &Hash256) -> bool {
+        self.blocks.read().contains_key(block_root)
+    }
+
+    fn present_slot(&self) -> Slot {
+        self.present_slot
+    }
+
+    fn read_slot_clock(&self) -> Option<Slot> {
+        Some(self.present_slot)
+    }
+
+    fn block_root_at_slot(&self, slot: Slot) -> Option<Hash256> {
+        self.blocks
+            .read()
+            .iter()
+            .filter(|(_, block)| block.slot <= slot)
+            .max_by_key(|(_, block)| block.slot)
+            .map(|(root, _)| *root)
+    }
+
+    fn process_block(
+        &self,
+        block: BeaconBlock,
+        _trace_id: TraceId,
+    ) -> Result<BlockProcessingOutcome, BeaconChainError> {
+        self.import(block)
+    }
+
+    fn process_chain_segment(
+        &self,
+        blocks: Vec<BeaconBlock>,
+        _trace_id: TraceId,
+    ) -> Vec<Result<BlockProcessingOutcome, BeaconChainError>> {
+        let mut outcomes = vec![];
+        for block in blocks {
+            let outcome = self.import(block);
+            let imported = match outcome {
+                Ok(BlockProcessingOutcome::ValidBlock(_)) => true,
+                _ => false,
+            };
+            outcomes.push(outcome);
+            if !imported {
+                break;
+            }
+        }
+        outcomes
+    }
+
+    fn oldest_block_parent(&self) -> Option<(Slot, Hash256)> {
+        *self.oldest_block_parent.read()
+    }
+
+    fn import_historical_blocks(&self, blocks: &[BeaconBlock]) -> Result<usize, BeaconChainError> {
+        for block in blocks {
+            self.blocks
+                .write()
+                .insert(block.canonical_root(), block.clone());
+        }
+        if let Some(oldest) = blocks.first() {
+            *self.oldest_block_parent.write() = if oldest.slot == self.spec.genesis_slot {
+                None
+            } else {
+                Some((oldest.slot, oldest.previous_block_root))
+            };
+        }
+        Ok(blocks.len())
+    }
+}
+
+/// Returns a chain of a block at each slot after genesis up to and including `best_slot`, built
+/// upon the genesis block of `chain`.
+fn build_blocks(chain: &MockChain, best_slot: Slot) -> Vec<BeaconBlock> {
+    let mut parent_root = chain.genesis_block_root;
+    let mut blocks = vec![];
+    for slot in chain.spec.genesis_slot.as_u64() + 1..=best_slot.as_u64() {
+        let mut block = BeaconBlock::empty(&chain.spec);
+        block.slot = Slot::new(slot);
+        block.previous_block_root = parent_root;
+        // distinct bodies, so that each is matched to its own header
+        block.body.eth1_data.block_hash = Hash256::from_low_u64_be(slot);
+        parent_root = block.canonical_root();
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Returns the HELLO of a peer on our chain whose head is the last of `blocks`.
+fn peer_hello(sync: &SimpleSync<MockChain>, blocks: &[BeaconBlock]) -> HelloMessage {
+    let mut hello = sync.generate_hello();
+    let head = blocks.last().expect("peer has blocks");
+    hello.best_root = head.canonical_root();
+    hello.best_slot = head.slot;
+    hello
+}
+
+/// Answers a batch request with `blocks`, as a peer holding them would, returning the score
+/// penalties from importing them.
+fn serve_batch(
+    sync: &mut SimpleSync<MockChain>,
+    peer_id: &PeerId,
+    blocks: &[BeaconBlock],
+    request: &BeaconBlockRootsRequest,
+) -> Vec<(PeerId, i64)> {
+    let end_slot = request.start_slot + request.count;
+    let batch: Vec<&BeaconBlock> = blocks
+        .iter()
+        .filter(|block| block.slot >= request.start_slot && block.slot < end_slot)
+        .collect();
+    let roots = batch
+        .iter()
+        .map(|block| BlockRootSlot {
+            block_root: block.canonical_root(),
+            slot: block.slot,
+        })
+        .collect();
+
+    let headers_request =
+        match sync.on_beacon_block_roots_response(peer_id, BeaconBlockRootsResponse { roots }) {
+            Some(request) => request,
+            None => return vec![],
+        };
+    assert_eq!(headers_request.max_headers, batch.len() as u64);
+    let headers = batch.iter().map(|block| block.block_header()).collect();
+
+    let BeaconBlockBodiesRequest { block_roots } = sync
+        .on_beacon_block_headers_response(peer_id, BeaconBlockHeadersResponse { headers })
+        .expect("bodies are requested");
+    assert_eq!(block_roots.len(), batch.len());
+    let block_bodies = batch.iter().map(|block| block.body.clone()).collect();
+
+    sync.on_beacon_block_bodies_response(
+        peer_id,
+        BeaconBlockBodiesResponse { block_bodies },
+        TraceId::next(),
+    )
+}
+
+/// A sync which downloads once a peer is more than 4 slots ahead, of a chain whose present slot
+/// is `present_slot` slots after genesis.
+fn new_sync(present_slot: u64) -> (Arc<MockChain>, SimpleSync<MockChain>) {
+    let spec = ChainSpec::few_validators();
+    let chain = Arc::new(MockChain::new(
+        spec.clone(),
+        spec.genesis_slot + present_slot,
+    ));
+    let sync = sync_of(&chain);
+    (chain, sync)
+}
+
+/// As `new_sync`, with the chain started from the block `anchor_slot` slots after genesis of the
+/// returned chain of blocks, up to the present slot.
+fn new_checkpoint_sync(
+    present_slot: u64,
+    anchor_slot: u64,
+) -> (Arc<MockChain>, SimpleSync<MockChain>, Vec<BeaconBlock>) {
+    let spec = ChainSpec::few_validators();
+    let chain = Arc::new(MockChain::new(
+        spec.clone(),
+        spec.genesis_slot + present_slot,
+    ));
+    let blocks = build_blocks(&chain, chain.present_slot);
+    chain.start_from(&blocks[anchor_slot as usize - 1]);
+    let sync = sync_of(&chain);
+    (chain, sync, blocks)
+}
+
+/// A sync of `chain` which downloads once a peer is more than 4 slots ahead.
+fn sync_of(chain: &Arc<MockChain>) -> SimpleSync<MockChain> {
+    let mut config = SyncConfig::default();
+    config.slot_import_tolerance = 4;
+    config.slot_import_hysteresis = 2;
+    let log = slog::Logger::root(slog::Discard, o!());
+    SimpleSync::new(chain.clone(), &config, &log)
+}
+
+#[test]
+fn rejects_peers_on_another_network_or_fork() {
+    let (chain, mut sync) = new_sync(2);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 2);
+
+    let mut hello = peer_hello(&sync, &blocks);
+    hello.network_id += 1;
+    assert!(!sync.validate_peer(PeerId::random(), hello));
+
+    let mut hello = peer_hello(&sync, &blocks);
+    hello.fork_digest[0] ^= 1;
+    assert!(!sync.validate_peer(PeerId::random(), hello));
+
+    let mut hello = peer_hello(&sync, &blocks);
+    hello.latest_finalized_root = Hash256::from_low_u64_be(1);
+    assert!(!sync.validate_peer(PeerId::random(), hello));
+
+    assert!(sync.known_peers().is_empty());
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn stays_idle_with_peers_within_tolerance() {
+    let (chain, mut sync) = new_sync(2);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 2);
+
+    let hello = peer_hello(&sync, &blocks);
+    assert!(sync.validate_peer(PeerId::random(), hello));
+
+    assert_eq!(sync.known_peers().len(), 1);
+    assert_eq!(sync.state(), SyncState::Idle);
+    assert!(sync.next_batches().is_empty());
+}
+
+#[test]
+fn leaves_and_enters_idle_with_hysteresis() {
+    // a tolerance of 4 slots and a hysteresis of 2, see `sync_of`
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    let peer_id = PeerId::random();
+    let hello_at = |sync: &mut SimpleSync<MockChain>, distance: usize| {
+        let hello = peer_hello(sync, &blocks[..distance]);
+        assert!(sync.validate_peer(peer_id.clone(), hello));
+        sync.state()
+    };
+
+    // downloading starts beyond the tolerance
+    assert_eq!(hello_at(&mut sync, 4), SyncState::Idle);
+    assert_eq!(hello_at(&mut sync, 5), SyncState::Downloading);
+    // and stops within the tolerance less the hysteresis
+    assert_eq!(hello_at(&mut sync, 4), SyncState::Downloading);
+    assert_eq!(hello_at(&mut sync, 3), SyncState::Downloading);
+    assert_eq!(hello_at(&mut sync, 2), SyncState::Idle);
+    assert_eq!(hello_at(&mut sync, 4), SyncState::Idle);
+    assert_eq!(hello_at(&mut sync, 6), SyncState::Downloading);
+}
+
+#[test]
+fn downloads_batches_until_synced() {
+    let (chain, mut sync) = new_sync(20);
+    let best_slot = chain.spec.genesis_slot + 20;
+    let blocks = build_blocks(&chain, best_slot);
+    let peer_id = PeerId::random();
+
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    assert_eq!(sync.state(), SyncState::Downloading);
+
+    let mut batches = 0;
+    while let Some((batch_peer, request)) = sync.next_batches().pop() {
+        assert_eq!(batch_peer, peer_id);
+        // batches start on epoch boundaries
+        assert_eq!(request.start_slot.as_u64() % chain.spec.slots_per_epoch, 0);
+        assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+        batches += 1;
+        assert!(batches <= 4, "too many batches requested");
+    }
+
+    assert_eq!(chain.head_slot(), best_slot);
+    assert_eq!(sync.state(), SyncState::Idle);
+    assert!(sync.is_synced());
+    assert_eq!(
+        sync.peer_summary(&peer_id).unwrap().blocks_served,
+        blocks.len() as u64
+    );
+}
+
+#[test]
+fn penalises_peer_serving_invalid_blocks() {
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    chain
+        .invalid_blocks
+        .write()
+        .insert(blocks[2].canonical_root());
+    let peer_id = PeerId::random();
+
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    let (_, request) = sync.next_batches().pop().expect("a batch is requested");
+
+    let penalties = serve_batch(&mut sync, &peer_id, &blocks, &request);
+    assert_eq!(penalties, vec![(peer_id.clone(), INVALID_BLOCK_PENALTY)]);
+    assert_eq!(sync.peer_summary(&peer_id).unwrap().errors, 1);
+    // the blocks before the invalid block are imported
+    assert_eq!(chain.head_slot(), blocks[1].slot);
+    // the batch is never downloaded from the same peer again
+    assert!(sync.next_batches().is_empty());
+    assert_eq!(sync.state(), SyncState::Downloading);
+}
+
+#[test]
+fn retries_failed_request_with_another_peer() {
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    let peers = vec![PeerId::random(), PeerId::random()];
+    for peer_id in &peers {
+        assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    }
+
+    let mut requests = sync.next_batches();
+    assert_eq!(requests.len(), 1, "the range fits in a single batch");
+    let (failed_peer, request) = requests.pop().unwrap();
+    sync.on_request_failed(&failed_peer);
+
+    let (retry_peer, retry) = sync.next_batches().pop().expect("the batch is retried");
+    assert_ne!(retry_peer, failed_peer);
+    assert_eq!(retry.start_slot, request.start_slot);
+    assert_eq!(sync.peer_summary(&failed_peer).unwrap().errors, 1);
+
+    assert!(serve_batch(&mut sync, &retry_peer, &blocks, &retry).is_empty());
+    assert_eq!(chain.head_slot(), blocks.last().unwrap().slot);
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn rejects_roots_outside_the_batch() {
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    let peer_id = PeerId::random();
+
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    let (_, request) = sync.next_batches().pop().expect("a batch is requested");
+
+    let roots = vec![BlockRootSlot {
+        block_root: Hash256::from_low_u64_be(1),
+        slot: request.start_slot + request.count,
+    }];
+    assert!(sync
+        .on_beacon_block_roots_response(&peer_id, BeaconBlockRootsResponse { roots })
+        .is_none());
+    assert_eq!(sync.peer_summary(&peer_id).unwrap().errors, 1);
+
+    // the abandoned batch is requested again
+    let (_, retry) = sync.next_batches().pop().expect("the batch is retried");
+    assert_eq!(retry.start_slot, request.start_slot);
+}
+
+#[test]
+fn stops_on_chain_error_until_resumed() {
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    *chain.fail_imports.write() = true;
+    let peer_id = PeerId::random();
+
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    let (_, request) = sync.next_batches().pop().expect("a batch is requested");
+
+    // the peer is not at fault for our chain failing
+    assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+    assert_eq!(
+        sync.state(),
+        SyncState::Stopped(StopReason::FatalChainError)
+    );
+    assert!(sync.next_batches().is_empty());
+
+    *chain.fail_imports.write() = false;
+    sync.resume();
+    assert_eq!(sync.state(), SyncState::Downloading);
+    let (_, request) = sync.next_batches().pop().expect("downloading resumes");
+    assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+    assert_eq!(chain.head_slot(), blocks.last().unwrap().slot);
+}
+
+#[test]
+fn synced_despite_skipped_slots() {
+    let (_chain, mut sync) = new_sync(20);
+
+    // without peers there is nothing to sync from
+    assert!(sync.is_synced());
+
+    // no peer has a block after genesis, so the slots since were skipped by the whole network
+    for _ in 0..2 {
+        let hello = sync.generate_hello();
+        assert!(sync.validate_peer(PeerId::random(), hello));
+    }
+    assert!(sync.is_synced());
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn synced_measured_against_majority_of_peers() {
+    let (chain, mut sync) = new_sync(20);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 20);
+
+    // a single peer claiming a later head cannot outvote the peers at our head
+    for _ in 0..2 {
+        let hello = sync.generate_hello();
+        assert!(sync.validate_peer(PeerId::random(), hello));
+    }
+    assert!(sync.validate_peer(PeerId::random(), peer_hello(&sync, &blocks)));
+    assert!(sync.is_synced());
+
+    // once most peers are ahead we are behind
+    assert!(sync.validate_peer(PeerId::random(), peer_hello(&sync, &blocks)));
+    assert!(sync.validate_peer(PeerId::random(), peer_hello(&sync, &blocks)));
+    assert!(!sync.is_synced());
+    assert!(!sync.sync_status().is_synced);
+}
+
+#[test]
+fn target_is_the_slot_reached_by_a_majority_of_peers() {
+    let (chain, mut sync) = new_sync(20);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 20);
+
+    // the highest peer does not decide how far we download
+    for best_slot in &[10, 12, 20] {
+        let hello = peer_hello(&sync, &blocks[..*best_slot]);
+        assert!(sync.validate_peer(PeerId::random(), hello));
+    }
+    assert_eq!(
+        sync.sync_status().target_slot,
+        Some(chain.spec.genesis_slot + 12)
+    );
+    assert_eq!(sync.state(), SyncState::Downloading);
+
+    while let Some((peer_id, request)) = sync.next_batches().pop() {
+        assert!(request.start_slot < chain.spec.genesis_slot + 12);
+        assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+    }
+    assert_eq!(chain.head_slot(), chain.spec.genesis_slot + 12);
+    assert!(sync.is_synced());
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn peers_finalizing_a_new_checkpoint_are_not_split() {
+    let (chain, mut sync) = new_sync(20);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 20);
+    let finalized_epoch = sync.generate_hello().latest_finalized_epoch;
+
+    // two peers have yet to learn of the checkpoint the other two have finalized
+    for _ in 0..2 {
+        assert!(sync.validate_peer(PeerId::random(), peer_hello(&sync, &blocks)));
+    }
+    for _ in 0..2 {
+        let mut hello = peer_hello(&sync, &blocks);
+        hello.latest_finalized_epoch = finalized_epoch + 1;
+        hello.latest_finalized_root = Hash256::from_low_u64_be(1);
+        assert!(sync.validate_peer(PeerId::random(), hello));
+    }
+    // a peer which has finalized far beyond us is not counted with them
+    let mut hello = peer_hello(&sync, &blocks);
+    hello.latest_finalized_epoch = finalized_epoch + 4;
+    hello.latest_finalized_root = Hash256::from_low_u64_be(2);
+    assert!(sync.validate_peer(PeerId::random(), hello));
+
+    assert_eq!(sync.sync_status().sync_peers, 4);
+    assert_eq!(sync.state(), SyncState::Downloading);
+}
+
+#[test]
+fn peer_heads_after_the_clock_are_not_trusted() {
+    let (chain, mut sync) = new_sync(2);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 2);
+
+    // the peer claims a head far beyond the clock, so is only as far ahead as the clock
+    let mut hello = peer_hello(&sync, &blocks);
+    hello.best_slot = chain.spec.genesis_slot + 1_000;
+    assert!(sync.validate_peer(PeerId::random(), hello));
+    assert!(sync.is_synced());
+}
+
+#[test]
+fn downloads_peers_within_tolerance_whilst_behind_the_clock() {
+    let (chain, mut sync) = new_sync(20);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 3);
+    let peer_id = PeerId::random();
+
+    // the peer is within tolerance, but our head is far behind the clock
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    assert_eq!(sync.state(), SyncState::Downloading);
+
+    let (_, request) = sync.next_batches().pop().expect("a batch is requested");
+    assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+    assert_eq!(chain.head_slot(), blocks.last().unwrap().slot);
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn skipped_slots_before_the_clock_do_not_keep_downloading() {
+    let (chain, mut sync) = new_sync(20);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 10);
+    let peer_id = PeerId::random();
+
+    // the peer has processed the slots skipped since its latest block
+    let mut hello = peer_hello(&sync, &blocks);
+    hello.best_slot = chain.spec.genesis_slot + 20;
+    assert!(sync.validate_peer(peer_id.clone(), hello));
+    assert_eq!(sync.state(), SyncState::Downloading);
+
+    let mut requests = 0;
+    while let Some((batch_peer, request)) = sync.next_batches().pop() {
+        assert!(serve_batch(&mut sync, &batch_peer, &blocks, &request).is_empty());
+        requests += 1;
+        assert!(requests <= 8, "too many batches requested");
+    }
+    assert_eq!(chain.head_slot(), blocks.last().unwrap().slot);
+
+    // the batch of skipped slots completes without blocks, so is imported periodically
+    assert!(sync.process_pending_blocks(TraceId::next()).is_empty());
+    assert_eq!(sync.state(), SyncState::Idle);
+    sync.check_clock();
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn backfills_blocks_before_checkpoint() {
+    let (chain, mut sync, blocks) = new_checkpoint_sync(10, 10);
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    assert_eq!(sync.state(), SyncState::Idle);
+
+    let genesis_block = chain.blocks.read()[&chain.genesis_block_root].clone();
+    let mut history = vec![genesis_block];
+    history.extend_from_slice(&blocks[..9]);
+
+    let mut requests = 0;
+    while let Some((request_peer, request)) = sync.next_backfill() {
+        assert_eq!(request_peer, peer_id);
+        assert!(serve_batch(&mut sync, &peer_id, &history, &request).is_empty());
+        requests += 1;
+        assert!(requests <= 2, "too many ranges requested");
+    }
+    assert_eq!(chain.oldest_block_parent(), None);
+    assert_eq!(
+        sync.peer_summary(&peer_id).unwrap().blocks_served,
+        history.len() as u64
+    );
+}
+
+#[test]
+fn restarts_backfill_when_parent_hidden_by_skipped_slots() {
+    let (chain, mut sync, blocks) = new_checkpoint_sync(10, 10);
+    let liar = PeerId::random();
+    assert!(sync.validate_peer(liar.clone(), peer_hello(&sync, &blocks)));
+
+    // the peer claims every slot back to genesis was skipped
+    for _ in 0..2 {
+        let (request_peer, _) = sync.next_backfill().expect("a range is requested");
+        assert_eq!(request_peer, liar);
+        let roots = vec![];
+        assert!(sync
+            .on_beacon_block_roots_response(&liar, BeaconBlockRootsResponse { roots })
+            .is_none());
+    }
+
+    // the search restarts from the oldest block, preferring another peer
+    let honest = PeerId::random();
+    assert!(sync.validate_peer(honest.clone(), peer_hello(&sync, &blocks)));
+    let (request_peer, request) = sync.next_backfill().expect("the search restarts");
+    assert_eq!(request_peer, honest);
+    assert_eq!(request.start_slot + request.count, blocks[9].slot);
+
+    assert!(serve_batch(&mut sync, &honest, &blocks, &request).is_empty());
+    assert_eq!(
+        chain.oldest_block_parent(),
+        Some((request.start_slot, blocks[0].canonical_root()))
+    );
+}
+
+#[test]
+fn backfill_cancelled_when_downloading_starts() {
+    let (chain, mut sync, blocks) = new_checkpoint_sync(30, 10);
+    let backfill_peer = PeerId::random();
+    assert!(sync.validate_peer(backfill_peer.clone(), peer_hello(&sync, &blocks[..10])));
+    let (request_peer, _) = sync.next_backfill().expect("a range is requested");
+    assert_eq!(request_peer, backfill_peer);
+
+    // most peers are ahead of us, so downloading starts and the range is abandoned
+    for _ in 0..2 {
+        assert!(sync.validate_peer(PeerId::random(), peer_hello(&sync, &blocks)));
+    }
+    assert_eq!(sync.state(), SyncState::Downloading);
+    assert!(sync.next_backfill().is_none());
+
+    // the responses of the peer are of range sync once it is asked for a batch
+    let (_, request) = sync
+        .next_batches()
+        .into_iter()
+        .find(|(peer_id, _)| *peer_id == backfill_peer)
+        .expect("a batch is requested from the peer");
+    assert!(serve_batch(&mut sync, &backfill_peer, &blocks, &request).is_empty());
+    let summary = sync.peer_summary(&backfill_peer).unwrap();
+    assert_eq!(summary.errors, 0);
+    assert!(summary.blocks_served > 0);
+    assert_eq!(
+        chain.oldest_block_parent(),
+        Some((blocks[9].slot, blocks[8].canonical_root()))
+    );
+}
+
+/// Answers a batch request of `range_sync` with `blocks`, as a peer holding them would, returning
+/// the number of blocks downloaded.
+fn serve_range(
+    range_sync: &mut RangeSync,
+    peer_id: &PeerId,
+    blocks: &[BeaconBlock],
+    request: &BeaconBlockRootsRequest,
+) -> usize {
+    let end_slot = request.start_slot + request.count;
+    let batch: Vec<&BeaconBlock> = blocks
+        .iter()
+        .filter(|block| block.slot >= request.start_slot && block.slot < end_slot)
+        .collect();
+    let roots = batch
+        .iter()
+        .map(|block| BlockRootSlot {
+            block_root: block.canonical_root(),
+            slot: block.slot,
+        })
+        .collect();
+    range_sync
+        .on_roots_response(peer_id, BeaconBlockRootsResponse { roots })
+        .unwrap()
+        .expect("headers are requested");
+    let headers = batch.iter().map(|block| block.block_header()).collect();
+    range_sync
+        .on_headers_response(peer_id, BeaconBlockHeadersResponse { headers })
+        .unwrap()
+        .expect("bodies are requested");
+    let block_bodies = batch.iter().map(|block| block.body.clone()).collect();
+    range_sync
+        .on_bodies_response(peer_id, BeaconBlockBodiesResponse { block_bodies })
+        .unwrap()
+}
+
+/// A range sync of epoch sized batches from the slot after genesis up to `target_slot` slots
+/// after genesis, with a chain of a block at each of those slots.
+fn new_range_sync(target_slot: u64) -> (RangeSync, Vec<BeaconBlock>) {
+    let (chain, _) = new_sync(target_slot);
+    let blocks = build_blocks(&chain, chain.present_slot);
+    let mut config = SyncConfig::default();
+    config.epochs_per_batch = 1;
+    let mut range_sync = RangeSync::new(chain.spec.slots_per_epoch, &config);
+    range_sync.start(chain.spec.genesis_slot + 1);
+    range_sync.set_target(chain.present_slot);
+    (range_sync, blocks)
+}
+
+#[test]
+fn range_sync_downloads_epoch_batches_in_parallel() {
+    let (mut range_sync, blocks) = new_range_sync(20);
+    let best_slot = blocks.last().unwrap().slot;
+    let peers: Vec<(PeerId, Slot)> = (0..4).map(|_| (PeerId::random(), best_slot)).collect();
+
+    let requests = range_sync.next_requests(&peers);
+    assert_eq!(requests.len(), 3, "the range spans three epochs");
+    let mut end_slot = blocks[0].slot;
+    for (_, request) in &requests {
+        assert_eq!(request.start_slot, end_slot);
+        end_slot = request.start_slot + request.count;
+    }
+    assert_eq!(end_slot, best_slot + 1);
+    let requested_peers: HashSet<&PeerId> = requests.iter().map(|(peer_id, _)| peer_id).collect();
+    assert_eq!(
+        requested_peers.len(),
+        3,
+        "each peer downloads a single batch"
+    );
+
+    // batches are only released for import in slot order
+    let (last_peer, last_request) = requests.last().unwrap();
+    let downloaded = serve_range(&mut range_sync, last_peer, &blocks, last_request);
+    assert_eq!(downloaded as u64, last_request.count);
+    assert!(range_sync.pop_ready().is_none());
+
+    for (peer_id, request) in &requests[..2] {
+        serve_range(&mut range_sync, peer_id, &blocks, request);
+    }
+    let mut imported = vec![];
+    while let Some(batch) = range_sync.pop_ready() {
+        imported.extend(batch.blocks);
+    }
+    assert_eq!(imported, blocks);
+    assert_eq!(range_sync.batch_count(), 0);
+}
+
+#[test]
+fn range_sync_confirms_skipped_batch_with_another_peer() {
+    let (mut range_sync, blocks) = new_range_sync(6);
+    let best_slot = blocks.last().unwrap().slot;
+    let peers = vec![(PeerId::random(), best_slot), (PeerId::random(), best_slot)];
+
+    let (first_peer, request) = range_sync.next_requests(&peers).pop().unwrap();
+    let roots = vec![];
+    assert!(range_sync
+        .on_roots_response(&first_peer, BeaconBlockRootsResponse { roots })
+        .unwrap()
+        .is_none());
+    // a single report of skipped slots may hide blocks
+    assert!(range_sync.pop_ready().is_none());
+
+    let (second_peer, retry) = range_sync.next_requests(&peers).pop().unwrap();
+    assert_ne!(second_peer, first_peer);
+    assert_eq!(retry.start_slot, request.start_slot);
+    let roots = vec![];
+    assert!(range_sync
+        .on_roots_response(&second_peer, BeaconBlockRootsResponse { roots })
+        .unwrap()
+        .is_none());
+    let batch = range_sync
+        .pop_ready()
+        .expect("the skipped slots are confirmed");
+    assert!(batch.blocks.is_empty());
+}
+
+#[test]
+fn range_sync_retries_failed_batch_with_another_peer() {
+    let (mut range_sync, blocks) = new_range_sync(6);
+    let best_slot = blocks.last().unwrap().slot;
+    let peers = vec![(PeerId::random(), best_slot), (PeerId::random(), best_slot)];
+
+    let (failed_peer, request) = range_sync.next_requests(&peers).pop().unwrap();
+    assert!(range_sync.on_request_failed(&failed_peer));
+    assert!(!range_sync.on_request_failed(&failed_peer));
+
+    let (retry_peer, retry) = range_sync.next_requests(&peers).pop().unwrap();
+    assert_ne!(retry_peer, failed_peer);
+    assert_eq!(retry.start_slot, request.start_slot);
+    serve_range(&mut range_sync, &retry_peer, &blocks, &retry);
+    assert_eq!(range_sync.pop_ready().unwrap().blocks, blocks);
+}
+
+#[test]
+fn range_sync_reassigns_timed_out_batch_to_another_peer() {
+    let spec = ChainSpec::few_validators();
+    let mut config = SyncConfig::default();
+    config.batch_timeout = Duration::from_secs(0);
+    let mut range_sync = RangeSync::new(spec.slots_per_epoch, &config);
+    let target_slot = spec.genesis_slot + 2;
+    range_sync.start(spec.genesis_slot + 1);
+    range_sync.set_target(target_slot);
+    let (slow_peer, other_peer) = (PeerId::random(), PeerId::random());
+    let peers = vec![
+        (slow_peer.clone(), target_slot),
+        (other_peer.clone(), target_slot),
+    ];
+
+    let requests = range_sync.next_requests(&peers);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, slow_peer);
+
+    // the batch times out without any response
+    let requests = range_sync.next_requests(&peers);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, other_peer);
+    assert_eq!(requests[0].1.start_slot, spec.genesis_slot + 1);
+
+    // a peer whose request is outstanding is not downloaded from again
+    assert!(range_sync.next_requests(&peers[..1]).is_empty());
+
+    // until it answers, the late response being ignored
+    let late_response = BeaconBlockRootsResponse { roots: vec![] };
+    assert!(range_sync
+        .on_roots_response(&slow_peer, late_response)
+        .unwrap()
+        .is_none());
+    let requests = range_sync.next_requests(&peers[..1]);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, slow_peer);
+}
+
+#[test]
+fn range_sync_rejects_unlinked_blocks() {
+    let (mut range_sync, mut blocks) = new_range_sync(6);
+    let best_slot = blocks.last().unwrap().slot;
+    let peer_id = PeerId::random();
+    blocks.remove(2);
+    blocks[3].previous_block_root = Hash256::from_low_u64_be(1);
+
+    let (_, request) = range_sync
+        .next_requests(&[(peer_id.clone(), best_slot)])
+        .pop()
+        .unwrap();
+    let roots = blocks
+        .iter()
+        .map(|block| BlockRootSlot {
+            block_root: block.canonical_root(),
+            slot: block.slot,
+        })
+        .collect();
+    range_sync
+        .on_roots_response(&peer_id, BeaconBlockRootsResponse { roots })
+        .unwrap();
+    let headers = blocks.iter().map(|block| block.block_header()).collect();
+    range_sync
+        .on_headers_response(&peer_id, BeaconBlockHeadersResponse { headers })
+        .unwrap();
+    let block_bodies = blocks.iter().map(|block| block.body.clone()).collect();
+    assert_eq!(
+        range_sync.on_bodies_response(&peer_id, BeaconBlockBodiesResponse { block_bodies }),
+        Err(BatchError::NonLinearBlocks)
+    );
+
+    // the batch is downloaded again, from the same peer as no other can serve it
+    let (retry_peer, retry) = range_sync
+        .next_requests(&[(peer_id.clone(), best_slot)])
+        .pop()
+        .unwrap();
+    assert_eq!(retry_peer, peer_id);
+    assert_eq!(retry.start_slot, request.start_slot);
+}
+
+#[test]
+fn ignores_only_peers_serving_repeatedly_invalid_batches() {
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    chain
+        .invalid_blocks
+        .write()
+        .insert(blocks[0].canonical_root());
+    let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+    for peer_id in &peers {
+        assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    }
+
+    // each peer serves the invalid batch once, the last failure invalidating the chain
+    for _ in 0..3 {
+        let (peer_id, request) = sync.next_batches().pop().expect("the batch is retried");
+        let penalties = serve_batch(&mut sync, &peer_id, &blocks, &request);
+        assert_eq!(penalties, vec![(peer_id, INVALID_BLOCK_PENALTY)]);
+    }
+    assert_eq!(sync.state(), SyncState::Idle);
+    assert!(sync.next_batches().is_empty());
+
+    // a peer sharing their finalized checkpoint is still synced to
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    assert_eq!(sync.state(), SyncState::Downloading);
+    let (request_peer, _) = sync.next_batches().pop().expect("a batch is requested");
+    assert_eq!(request_peer, peer_id);
+}
+
+#[test]
+fn unconnected_batches_do_not_invalidate_chain() {
+    let (chain, mut sync) = new_sync(6);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 6);
+    let peers: Vec<PeerId> = (0..2).map(|_| PeerId::random()).collect();
+    for peer_id in &peers {
+        assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    }
+
+    // the batch omits its first block, so does not connect to our chain, as from another fork
+    for _ in 0..4 {
+        let (peer_id, request) = sync.next_batches().pop().expect("the batch is retried");
+        assert!(serve_batch(&mut sync, &peer_id, &blocks[1..], &request).is_empty());
+        assert_eq!(sync.state(), SyncState::Downloading);
+    }
+
+    let (peer_id, request) = sync.next_batches().pop().expect("the batch is retried");
+    assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+    assert_eq!(chain.head_slot(), blocks.last().unwrap().slot);
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn blocks_withheld_before_an_unconnected_batch_are_downloaded_again() {
+    let (chain, mut sync) = new_sync(20);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 20);
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+
+    // the first batch withholds its last two blocks, as though their slots were skipped
+    let (_, first) = sync.next_batches().pop().expect("a batch is requested");
+    let end_slot = first.start_slot + first.count;
+    let withheld: Vec<BeaconBlock> = blocks
+        .iter()
+        .filter(|block| block.slot < end_slot - 2)
+        .cloned()
+        .collect();
+    assert!(serve_batch(&mut sync, &peer_id, &withheld, &first).is_empty());
+    assert_eq!(chain.head_slot(), end_slot - 3);
+
+    // so the next batch does not connect, and the withheld slots are downloaded before it again
+    let (_, second) = sync.next_batches().pop().expect("a batch is requested");
+    assert_eq!(second.start_slot, end_slot);
+    assert!(serve_batch(&mut sync, &peer_id, &blocks, &second).is_empty());
+    let (_, gap) = sync
+        .next_batches()
+        .pop()
+        .expect("the withheld slots are requested");
+    assert_eq!(gap.start_slot, end_slot - 2);
+    assert_eq!(gap.count, 2);
+    assert!(serve_batch(&mut sync, &peer_id, &blocks, &gap).is_empty());
+    assert_eq!(chain.head_slot(), end_slot - 1);
+
+    while let Some((_, request)) = sync.next_batches().pop() {
+        assert!(serve_batch(&mut sync, &peer_id, &blocks, &request).is_empty());
+    }
+    assert_eq!(chain.head_slot(), blocks.last().unwrap().slot);
+    assert_eq!(sync.state(), SyncState::Idle);
+}
+
+#[test]
+fn attestations_are_requested_from_peers_on_more_subnets() {
+    let (_chain, mut sync) = new_sync(20);
+    let peers = vec![PeerId::random(), PeerId::random(), PeerId::random()];
+    for peer_id in &peers {
+        assert!(sync.validate_peer(peer_id.clone(), sync.generate_hello()));
+    }
+    assert!(sync.wants_attestations());
+
+    let mut subnet_counts = HashMap::new();
+    subnet_counts.insert(peers[1].clone(), 1);
+    subnet_counts.insert(peers[2].clone(), 64);
+    let requested: Vec<PeerId> = sync
+        .next_attestation_requests(&subnet_counts)
+        .into_iter()
+        .map(|(peer_id, _)| peer_id)
+        .collect();
+    assert_eq!(requested, vec![peers[2].clone(), peers[1].clone()]);
+    assert!(!sync.wants_attestations());
+}
+
+#[test]
+fn only_requested_attestations_are_processed() {
+    let (chain, mut sync) = new_sync(20);
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), sync.generate_hello()));
+
+    let requests = sync.next_attestation_requests(&HashMap::new());
+    assert_eq!(requests.len(), 1);
+    let (_, request) = &requests[0];
+    assert_eq!(
+        request.start_slot,
+        chain.present_slot - chain.spec.slots_per_epoch
+    );
+    // requested once each time we become idle
+    assert!(sync.next_attestation_requests(&HashMap::new()).is_empty());
+
+    let mut rng = XorShiftRng::from_seed([42; 16]);
+    let mut attestation = Attestation::random_for_test(&mut rng);
+    attestation.data.slot = request.start_slot;
+    let mut stale = attestation.clone();
+    stale.data.slot = request.start_slot - 1;
+    let mut attestations = vec![stale];
+    attestations.extend(vec![attestation; request.max_attestations as usize + 10]);
+    let response = || BeaconAttestationsResponse {
+        attestations: attestations.clone(),
+    };
+
+    // a peer which was not asked is ignored
+    assert!(sync
+        .on_beacon_attestations_response(&PeerId::random(), response())
+        .is_empty());
+
+    // the response is truncated to the attestations requested
+    let accepted = sync.on_beacon_attestations_response(&peer_id, response());
+    assert_eq!(accepted.len() as u64, request.max_attestations);
+    assert!(accepted
+        .iter()
+        .all(|attestation| attestation.data.slot >= request.start_slot));
+
+    // and only one response is accepted per request
+    assert!(sync
+        .on_beacon_attestations_response(&peer_id, response())
+        .is_empty());
+}
+
+/// Answers the block roots request of a parent lookup with the roots of `blocks`, and of the
+/// genesis block, in the window requested.
+fn serve_lookup_roots(
+    sync: &mut SimpleSync<MockChain>,
+    chain: &MockChain,
+    peer_id: &PeerId,
+    blocks: &[BeaconBlock],
+    request: &RPCRequest,
+) -> Option<BeaconBlockHeadersRequest> {
+    let request = match request {
+        RPCRequest::BeaconBlockRoots(request) => request,
+        other => panic!("unexpected lookup request {:?}", other),
+    };
+    let end_slot = request.start_slot + request.count;
+    let mut roots = vec![BlockRootSlot {
+        block_root: chain.genesis_block_root,
+        slot: chain.spec.genesis_slot,
+    }];
+    roots.extend(blocks.iter().map(|block| BlockRootSlot {
+        block_root: block.canonical_root(),
+        slot: block.slot,
+    }));
+    roots.retain(|root_slot| root_slot.slot >= request.start_slot && root_slot.slot < end_slot);
+    sync.on_beacon_block_roots_response(peer_id, BeaconBlockRootsResponse { roots })
+}
+
+#[test]
+fn parent_lookup_recovers_ancestors_of_gossip_block() {
+    let (chain, mut sync) = new_sync(3);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 3);
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    assert_eq!(sync.state(), SyncState::Idle);
+
+    assert!(sync
+        .on_gossip_block(&peer_id, blocks[2].clone(), TraceId::next())
+        .is_empty());
+    let requests = sync.next_parent_lookups();
+    assert_eq!(requests.len(), 1);
+
+    let headers_request = serve_lookup_roots(&mut sync, &chain, &peer_id, &blocks, &requests[0].1)
+        .expect("headers of the ancestors are requested");
+    assert_eq!(headers_request.start_root, blocks[0].canonical_root());
+    assert_eq!(headers_request.max_headers, 2);
+
+    let headers = blocks[..2]
+        .iter()
+        .map(|block| block.block_header())
+        .collect();
+    let BeaconBlockBodiesRequest { block_roots } = sync
+        .on_beacon_block_headers_response(&peer_id, BeaconBlockHeadersResponse { headers })
+        .expect("bodies are requested");
+    assert_eq!(block_roots.len(), 2);
+    let block_bodies = blocks[..2].iter().map(|block| block.body.clone()).collect();
+    let penalties = sync.on_beacon_block_bodies_response(
+        &peer_id,
+        BeaconBlockBodiesResponse { block_bodies },
+        TraceId::next(),
+    );
+
+    assert!(penalties.is_empty());
+    assert_eq!(chain.head_slot(), blocks[2].slot);
+}
+
+#[test]
+fn only_imported_blocks_are_seen_from_a_peer() {
+    let (chain, mut sync) = new_sync(3);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 3);
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    // the best slot claimed by the HELLO is not seen
+    assert_eq!(sync.peer_summary(&peer_id).unwrap().last_seen_slot, None);
+
+    // a block whose parent is unknown is not imported
+    sync.on_gossip_block(&peer_id, blocks[1].clone(), TraceId::next());
+    assert_eq!(sync.peer_summary(&peer_id).unwrap().last_seen_slot, None);
+
+    sync.on_gossip_block(&peer_id, blocks[0].clone(), TraceId::next());
+    let summary = sync.peer_summary(&peer_id).unwrap();
+    assert_eq!(summary.last_seen_slot, Some(blocks[0].slot));
+    assert_eq!(summary.blocks_served, 2);
+}
+
+#[test]
+fn parent_lookup_requeues_block_whose_parent_arrives() {
+    let (chain, mut sync) = new_sync(3);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 3);
+    let peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+
+    sync.on_gossip_block(&peer_id, blocks[2].clone(), TraceId::next());
+    let requests = sync.next_parent_lookups();
+    assert_eq!(requests.len(), 1);
+
+    // the parent arrives by other means before the peer responds
+    for block in &blocks[..2] {
+        chain.import(block.clone()).unwrap();
+    }
+    assert!(serve_lookup_roots(&mut sync, &chain, &peer_id, &blocks, &requests[0].1).is_none());
+
+    // the gossip block is held rather than dropped, and imported with the held blocks
+    assert!(sync.next_parent_lookups().is_empty());
+    assert!(sync.process_pending_blocks(TraceId::next()).is_empty());
+    assert_eq!(chain.head_slot(), blocks[2].slot);
+}
+
+#[test]
+fn one_parent_lookup_per_peer() {
+    let (chain, mut sync) = new_sync(3);
+    let blocks = build_blocks(&chain, chain.spec.genesis_slot + 3);
+    let peer_id = PeerId::random();
+    let other_peer_id = PeerId::random();
+    assert!(sync.validate_peer(peer_id.clone(), peer_hello(&sync, &blocks)));
+    assert!(sync.validate_peer(other_peer_id.clone(), peer_hello(&sync, &blocks)));
+
+    // blocks of other forks, whose parents are unknown
+    let orphan = |parent: u64| {
+        let mut block = blocks[2].clone();
+        block.previous_block_root = Hash256::from_low_u64_be(parent);
+        block
+    };
+
+    // a peer with a lookup in progress cannot start another, so cannot take every lookup
+    sync.on_gossip_block(&peer_id, blocks[2].clone(), TraceId::next());
+    for parent in 1..5 {
+        sync.on_gossip_block(&peer_id, orphan(parent), TraceId::next());
+    }
+    sync.on_gossip_block(&other_peer_id, orphan(5), TraceId::next());
+
+    let mut lookup_peers: Vec<PeerId> = sync
+        .next_parent_lookups()
+        .into_iter()
+        .map(|(peer_id, _)| peer_id)
+        .collect();
+    lookup_peers.sort_by_key(|peer_id| peer_id.to_base58());
+    let mut expected = vec![peer_id, other_peer_id];
+    expected.sort_by_key(|peer_id| peer_id.to_base58());
+    assert_eq!(lookup_peers, expected);
+}
+
+/// Returns `count` distinct blocks at `slot` whose parents are unknown.
+fn orphan_blocks(spec: &ChainSpec, slot: Slot, count: usize) -> Vec<BeaconBlock> {
+    (0..count)
+        .map(|i| {
+            let mut block = BeaconBlock::empty(spec);
+            block.slot = slot;
+            block.previous_block_root = Hash256::from_low_u64_be(i as u64 + 1);
+            block
+        })
+        .collect()
+}
+
+#[test]
+fn pending_blocks_limits_the_share_of_each_peer() {
+    let spec = ChainSpec::few_validators();
+    let mut config = SyncConfig::default();
+    config.max_pending_blocks_per_peer = 3;
+    let mut pending = PendingBlocks::new(&config);
+    let peer_id = PeerId::random();
+    let blocks = orphan_blocks(&spec, spec.genesis_slot + 1, 5);
+    let (held, extra) = blocks.split_at(3);
+
+    for block in held {
+        assert!(pending.queue_for_parent(peer_id.clone(), block.clone()));
+    }
+    assert!(!pending.queue_for_parent(peer_id.clone(), extra[0].clone()));
+    assert!(!pending.queue_for_slot(peer_id.clone(), extra[0].clone(), spec.genesis_slot));
+
+    // other peers still have their share
+    assert!(pending.queue_for_parent(PeerId::random(), extra[0].clone()));
+    assert_eq!(pending.len(), 4);
+
+    // released blocks no longer count against the peer
+    let released = pending.take_connected(|root| *root == held[0].previous_block_root);
+    assert_eq!(released.len(), 1);
+    assert!(pending.queue_for_parent(peer_id, extra[1].clone()));
+}
+
+#[test]
+fn pending_blocks_are_released_by_slot() {
+    let spec = ChainSpec::few_validators();
+    let config = SyncConfig::default();
+    let mut pending = PendingBlocks::new(&config);
+    let peer_id = PeerId::random();
+    let present_slot = spec.genesis_slot;
+    let blocks: Vec<BeaconBlock> = [3, 1, 2]
+        .iter()
+        .map(|&slot| orphan_blocks(&spec, present_slot + slot, 1).remove(0))
+        .collect();
+
+    for block in &blocks {
+        assert!(pending.queue_for_slot(peer_id.clone(), block.clone(), present_slot));
+    }
+    // a block is held once, and only for a slot starting soon
+    assert!(!pending.queue_for_slot(peer_id.clone(), blocks[0].clone(), present_slot));
+    let far_block = orphan_blocks(&spec, present_slot + config.max_future_slots + 1, 1).remove(0);
+    assert!(!pending.queue_for_slot(peer_id, far_block, present_slot));
+
+    let ready: Vec<Slot> = pending
+        .take_ready(present_slot + 2)
+        .iter()
+        .map(|pending| pending.block.slot)
+        .collect();
+    assert_eq!(ready, vec![present_slot + 1, present_slot + 2]);
+    assert_eq!(pending.len(), 1);
+    assert!(pending.is_pending(&blocks[0].canonical_root()));
+}
+
+#[test]
+fn import_queue_refuses_headers_beyond_its_capacity() {
+    let spec = ChainSpec::few_validators();
+    let mut queue = ImportQueue::default();
+    let headers: Vec<BeaconBlockHeader> =
+        orphan_blocks(&spec, spec.genesis_slot + 1, MAX_QUEUED_HEADERS + 1)
+            .iter()
+            .map(BeaconBlock::block_header)
+            .collect();
+    let (held, extra) = headers.split_at(MAX_QUEUED_HEADERS - 1);
+
+    let roots = queue.insert_headers(held.to_vec()).unwrap();
+    assert_eq!(roots.len(), MAX_QUEUED_HEADERS - 1);
+    // headers already queued take no more room
+    assert_eq!(queue.insert_headers(held[..2].to_vec()), Some(vec![]));
+
+    // headers which do not all fit are not queued at all
+    assert_eq!(queue.insert_headers(extra.to_vec()), None);
+    assert_eq!(queue.len(), MAX_QUEUED_HEADERS - 1);
+    assert_eq!(
+        queue.insert_headers(extra[..1].to_vec()),
+        Some(vec![extra[0].canonical_root()])
+    );
+    assert_eq!(queue.len(), MAX_QUEUED_HEADERS);
+}
+
+#[test]
+fn import_queue_completes_blocks_in_slot_order() {
+    let spec = ChainSpec::few_validators();
+    let mut queue = ImportQueue::default();
+    let blocks: Vec<BeaconBlock> = [3, 1, 2]
+        .iter()
+        .map(|&slot| {
+            let mut block = orphan_blocks(&spec, spec.genesis_slot + slot, 1).remove(0);
+            block.body.eth1_data.block_hash = Hash256::from_low_u64_be(slot);
+            block
+        })
+        .collect();
+    queue.insert_headers(blocks.iter().map(BeaconBlock::block_header).collect());
+
+    // a body matching no header is discarded
+    let mut unknown_body = blocks[0].body.clone();
+    unknown_body.eth1_data.block_hash = Hash256::repeat_byte(0xff);
+    let mut bodies: Vec<BeaconBlockBody> = blocks.iter().map(|block| block.body.clone()).collect();
+    bodies.push(unknown_body);
+
+    let completed = queue.complete_blocks(bodies);
+    let slots: Vec<Slot> = completed.iter().map(|block| block.slot).collect();
+    assert_eq!(
+        slots,
+        vec![
+            spec.genesis_slot + 1,
+            spec.genesis_slot + 2,
+            spec.genesis_slot + 3
+        ]
+    );
+    assert_eq!(completed[2], blocks[0]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn range_sync_batches_fit_the_import_queue() {
+    let spec = ChainSpec::few_validators();
+    let mut config = SyncConfig::default();
+    config.epochs_per_batch = MAX_QUEUED_HEADERS as u64;
+    let mut range_sync = RangeSync::new(spec.slots_per_epoch, &config);
+    let target_slot = spec.genesis_slot + 2 * MAX_QUEUED_HEADERS as u64;
+    range_sync.start(spec.genesis_slot + 1);
+    range_sync.set_target(target_slot);
+
+    let requests = range_sync.next_requests(&[(PeerId::random(), target_slot)]);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].1.count, MAX_QUEUED_HEADERS as u64);
+}
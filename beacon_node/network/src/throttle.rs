@@ -0,0 +1,55 @@
+use std::time::Instant;
+
+/// Limits the rate of bytes uploaded by the network service.
+///
+/// Implemented as a token bucket holding at most one second worth of bytes. A message may be sent
+/// whenever the bucket is not in debt, so messages larger than the bucket are still sent
+/// (eventually) and the debt is repaid before anything else is sent.
+pub struct UploadThrottle {
+    /// The number of bytes that may be uploaded each second.
+    bytes_per_second: u64,
+    /// The bytes currently available to send. Negative if in debt.
+    available: i64,
+    /// The time the bucket was last refilled.
+    last_refill: Instant,
+}
+
+impl UploadThrottle {
+    pub fn new(bytes_per_second: u64) -> Self {
+        UploadThrottle {
+            bytes_per_second,
+            available: bytes_per_second as i64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Returns `true` and consumes `bytes` if the throttle currently permits sending.
+    pub fn try_consume(&mut self, bytes: u64) -> bool {
+        self.refill();
+        if self.available < 0 {
+            return false;
+        }
+        self.available -= bytes as i64;
+        true
+    }
+
+    /// Consumes `bytes` regardless of the bytes available, for messages that must be sent
+    /// immediately. Lower priority messages are held back until the debt is repaid.
+    pub fn force_consume(&mut self, bytes: u64) {
+        self.refill();
+        self.available -= bytes as i64;
+    }
+
+    /// Adds the bytes allowed since the last refill, up to one second worth.
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed_millis = elapsed.as_secs() * 1_000 + u64::from(elapsed.subsec_millis());
+        let refill = self.bytes_per_second.saturating_mul(elapsed_millis) / 1_000;
+
+        if refill > 0 {
+            self.available = (self.available + refill as i64).min(self.bytes_per_second as i64);
+            self.last_refill = now;
+        }
+    }
+}
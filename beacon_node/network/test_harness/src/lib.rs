@@ -0,0 +1,348 @@
+//! Runs several beacon nodes in-process, each with its own `BeaconChainHarness` and the
+//! `MessageHandler` of the network crate, connected by an in-memory router in place of libp2p.
+//!
+//! The router carries RPC messages between connected nodes and floods gossip across the links
+//! between them, each node forwarding only what its message handler has validated, so the sync
+//! and gossip handling of the real network code is exercised without
+//! any sockets. Tests connect and disconnect nodes, produce blocks on one of them and wait for the
+//! others to converge. Messages may be lost and delayed at random, and nodes restarted from
+//! genesis, to test that the network recovers from faults.
+//!
+//! Example:
+//! ```no_run
+//! use network_test_harness::{SimulatedNetwork, SimulationConfig};
+//! use std::time::Duration;
+//!
+//! let mut network = SimulatedNetwork::new(SimulationConfig::default());
+//! network.connect(0, 1);
+//!
+//! network.produce_block(0);
+//!
+//! assert!(network.wait_for_convergence(Duration::from_secs(10)));
+//! ```
+
+mod router;
+
+use beacon_chain::parking_lot::{Mutex, RwLock};
+use beacon_chain::BlockProcessingOutcome;
+use eth2_libp2p::{PeerId, PubsubMessage};
+use futures::prelude::*;
+use futures::sync::{mpsc, oneshot};
+use network::beacon_chain::BeaconChain as NetworkBeaconChain;
+use network::peer_manager::PeerManager;
+use network::{HandlerMessage, MessageHandler, NetworkConfig, OutboundQueue, SyncConfig};
+use router::Router;
+use slog::o;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use test_harness::BeaconChainHarness;
+use tokio::runtime::Runtime;
+use types::{BeaconBlock, ChainSpec, Hash256, Slot};
+
+pub use router::{MessageFaults, RouterStats};
+
+/// How often `wait_until` sends a heartbeat to the message handlers, as the network service would.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// How often `wait_until` checks its condition.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The parameters of a simulated network.
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub node_count: usize,
+    pub validator_count: usize,
+    pub spec: ChainSpec,
+    pub network_config: NetworkConfig,
+    pub sync_config: SyncConfig,
+}
+
+impl Default for SimulationConfig {
+    /// Two nodes, which begin downloading blocks when a peer is a few slots ahead, so that sync
+    /// is exercised by short chains. Batches are of a single epoch, 8 slots with this spec.
+    fn default() -> Self {
+        let mut sync_config = SyncConfig::default();
+        sync_config.slot_import_tolerance = 4;
+        sync_config.slot_import_hysteresis = 2;
+        sync_config.epochs_per_batch = 1;
+
+        Self {
+            node_count: 2,
+            validator_count: 8,
+            spec: ChainSpec::few_validators(),
+            network_config: NetworkConfig::default(),
+            sync_config,
+        }
+    }
+}
+
+/// A beacon node of the simulated network.
+pub struct SimulatedNode {
+    pub harness: BeaconChainHarness,
+    pub peer_id: PeerId,
+    pub peer_manager: Arc<RwLock<PeerManager>>,
+    /// Completes once the message handler of the node has stopped.
+    handler_stopped: Option<oneshot::Receiver<()>>,
+}
+
+impl SimulatedNode {
+    /// Returns the root and slot of the head block of the node.
+    pub fn head(&self) -> (Hash256, Slot) {
+        let head = self.harness.beacon_chain.head();
+        (head.beacon_block_root, head.beacon_block.slot)
+    }
+}
+
+/// A set of nodes connected by an in-memory router.
+pub struct SimulatedNetwork {
+    pub nodes: Vec<SimulatedNode>,
+    config: SimulationConfig,
+    router: Arc<Mutex<Router>>,
+    runtime: Runtime,
+    log: slog::Logger,
+}
+
+impl SimulatedNetwork {
+    /// Starts `config.node_count` nodes, all sharing the same genesis and with no connections
+    /// between them.
+    pub fn new(config: SimulationConfig) -> Self {
+        Self::with_logger(config, slog::Logger::root(slog::Discard, o!()))
+    }
+
+    /// As `new`, logging the message handlers of the nodes to `log`.
+    pub fn with_logger(config: SimulationConfig, log: slog::Logger) -> Self {
+        let mut network = Self {
+            nodes: vec![],
+            config,
+            router: Arc::new(Mutex::new(Router::default())),
+            runtime: Runtime::new().expect("Unable to start a runtime for the simulation"),
+            log,
+        };
+        for index in 0..network.config.node_count {
+            let harness = BeaconChainHarness::new(
+                network.config.spec.clone(),
+                network.config.validator_count,
+            );
+            let (node, handler_send) = network.start_node(index, PeerId::random(), harness);
+            network
+                .router
+                .lock()
+                .add_node(node.peer_id.clone(), handler_send);
+            network.nodes.push(node);
+        }
+        network
+    }
+
+    /// Starts the message handler of node `index` on the chain of `harness`, with its messages
+    /// to the network carried by the router. Returns the node and the sender of its handler.
+    fn start_node(
+        &self,
+        index: usize,
+        peer_id: PeerId,
+        harness: BeaconChainHarness,
+    ) -> (SimulatedNode, mpsc::Sender<HandlerMessage>) {
+        let node_log = self.log.new(o!("node" => index));
+        let peer_manager = Arc::new(RwLock::new(PeerManager::new(
+            self.config.network_config.peer_ban_duration,
+            node_log.new(o!("Service" => "PeerManager")),
+        )));
+
+        let (network_send, network_recv) = mpsc::unbounded();
+        let chain: Arc<NetworkBeaconChain> = harness.beacon_chain.clone();
+        let (handler_send, handler_stopped) = MessageHandler::spawn(
+            chain,
+            network_send,
+            peer_manager.clone(),
+            OutboundQueue::default().backlog(),
+            &self.config.network_config,
+            &self.config.sync_config,
+            node_log.new(o!("Service" => "MessageHandler")),
+        );
+
+        // carries the messages the handler sends to the network, in place of the network
+        // service
+        let router = self.router.clone();
+        self.runtime
+            .executor()
+            .spawn(network_recv.for_each(move |message| {
+                router.lock().route(index, message);
+                Ok(())
+            }));
+
+        let node = SimulatedNode {
+            harness,
+            peer_id,
+            peer_manager,
+            handler_stopped: Some(handler_stopped),
+        };
+        (node, handler_send)
+    }
+
+    /// Stops node `index` and starts it again from genesis with the same identity, as if its
+    /// process had been killed and its database lost. The node is disconnected from its peers,
+    /// and its clock is kept at the present slot.
+    pub fn restart(&mut self, index: usize) {
+        for peer in 0..self.nodes.len() {
+            self.disconnect(index, peer);
+        }
+        self.router.lock().stop(index);
+        if let Some(handler_stopped) = self.nodes[index].handler_stopped.take() {
+            let _ = handler_stopped.wait();
+        }
+
+        let present_slot = self.nodes[index].harness.beacon_chain.present_slot();
+        let harness =
+            BeaconChainHarness::new(self.config.spec.clone(), self.config.validator_count);
+        harness
+            .beacon_chain
+            .slot_clock
+            .set_slot(present_slot.as_u64());
+        harness
+            .beacon_chain
+            .advance_state(present_slot)
+            .expect("Unable to advance the state of a restarted node");
+
+        let peer_id = self.nodes[index].peer_id.clone();
+        let (node, handler_send) = self.start_node(index, peer_id, harness);
+        self.router.lock().replace_node(index, handler_send);
+        self.nodes[index] = node;
+    }
+
+    /// Loses and delays the RPC and gossip messages carried between nodes from now on, choosing
+    /// which at random from `seed`.
+    pub fn set_faults(&self, faults: MessageFaults, seed: u64) {
+        self.router.lock().set_faults(faults, seed);
+    }
+
+    /// Connects node `dialer` to node `listener`, as if `dialer` had dialed it. Has no effect if
+    /// either has banned the other.
+    pub fn connect(&self, dialer: usize, listener: usize) {
+        self.router.lock().connect(dialer, listener);
+    }
+
+    /// Connects every pair of nodes.
+    pub fn connect_all(&self) {
+        for dialer in 0..self.nodes.len() {
+            for listener in dialer + 1..self.nodes.len() {
+                self.connect(dialer, listener);
+            }
+        }
+    }
+
+    /// Drops the connection between two nodes, as if it had failed.
+    pub fn disconnect(&self, a: usize, b: usize) {
+        self.router.lock().disconnect(a, b);
+    }
+
+    /// Returns `true` if the two nodes are connected.
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.router.lock().is_connected(a, b)
+    }
+
+    /// Returns the counts of the messages carried and dropped by the router.
+    pub fn stats(&self) -> RouterStats {
+        self.router.lock().stats()
+    }
+
+    /// Advances the slot clocks of every node by one slot.
+    pub fn advance_slot(&mut self) -> Slot {
+        let mut slot = Slot::new(0);
+        for node in &mut self.nodes {
+            slot = node.harness.increment_beacon_chain_slot();
+        }
+        slot
+    }
+
+    /// Advances the slot of every node, then produces and imports a block on node `producer`,
+    /// publishing it on gossip.
+    pub fn produce_block(&mut self, producer: usize) -> BeaconBlock {
+        let block = self.produce_unpublished_block(producer);
+        self.router
+            .lock()
+            .publish(producer, PubsubMessage::Block(block.clone()).to_bytes());
+        block
+    }
+
+    /// Publishes a gossip message from node `publisher` to its peers, without processing it on
+    /// `publisher`.
+    pub fn publish(&self, publisher: usize, message: PubsubMessage) {
+        self.router.lock().publish(publisher, message.to_bytes());
+    }
+
+    /// Advances the slot of every node, then produces and imports a block on node `producer`
+    /// without publishing it, so the other nodes may only learn of it by syncing.
+    pub fn produce_unpublished_block(&mut self, producer: usize) -> BeaconBlock {
+        self.advance_slot();
+        let harness = &mut self.nodes[producer].harness;
+        let block = harness.produce_block();
+        match harness.beacon_chain.process_block(block.clone()) {
+            Ok(BlockProcessingOutcome::ValidBlock(_)) => {}
+            other => panic!("block processing failed with {:?}", other),
+        }
+        block
+    }
+
+    /// Produces the attestations of the validators due to attest at the present slot on node
+    /// `index`, which are included in the next blocks it produces. Every node holds the keys of
+    /// every validator.
+    pub fn attest(&mut self, index: usize) {
+        let harness = &mut self.nodes[index].harness;
+        for attestation in harness.gather_free_attesations() {
+            // an attestation to a slot the node has not reached is refused
+            let _ = harness.beacon_chain.process_free_attestation(attestation);
+        }
+    }
+
+    /// Sends a heartbeat to the message handler of every node.
+    pub fn heartbeat(&self) {
+        self.router.lock().heartbeat();
+    }
+
+    /// Returns the head of every node.
+    pub fn heads(&self) -> Vec<(Hash256, Slot)> {
+        self.nodes.iter().map(SimulatedNode::head).collect()
+    }
+
+    /// Waits until `condition` holds, sending heartbeats meanwhile. Returns `false` if it does
+    /// not hold within `timeout`.
+    pub fn wait_until<F>(&self, timeout: Duration, condition: F) -> bool
+    where
+        F: Fn(&Self) -> bool,
+    {
+        let start = Instant::now();
+        let mut last_heartbeat = start;
+        while start.elapsed() < timeout {
+            if condition(self) {
+                return true;
+            }
+            self.router.lock().release_delayed();
+            if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+                self.heartbeat();
+                last_heartbeat = Instant::now();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        condition(self)
+    }
+
+    /// Waits until every node has the same head. Returns `false` if they do not converge within
+    /// `timeout`.
+    pub fn wait_for_convergence(&self, timeout: Duration) -> bool {
+        self.wait_until(timeout, |network| {
+            let heads = network.heads();
+            heads.iter().all(|head| *head == heads[0])
+        })
+    }
+
+    /// Shuts down the message handler of every node, waiting for each to stop, then stops the
+    /// runtime.
+    pub fn shutdown(mut self) {
+        self.router.lock().shutdown();
+        for node in &mut self.nodes {
+            if let Some(handler_stopped) = node.handler_stopped.take() {
+                let _ = handler_stopped.wait();
+            }
+        }
+        let _ = self.runtime.shutdown_now().wait();
+    }
+}
@@ -0,0 +1,332 @@
+use beacon_chain::TraceId;
+use eth2_libp2p::{PeerId, PubsubMessage};
+use futures::sync::mpsc;
+use network::{HandlerMessage, NetworkMessage, OutgoingMessage};
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use ssz::Decodable;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// The counts of the messages carried by a `Router`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RouterStats {
+    /// RPC messages delivered to a connected node.
+    pub rpc_delivered: u64,
+    /// Messages dropped, as their destination was not connected or its handler was full.
+    pub dropped: u64,
+    /// Gossip messages delivered to a node other than the publisher.
+    pub gossip_delivered: u64,
+    /// Gossip messages a node did not forward, as its handler did not find them valid.
+    pub gossip_discarded: u64,
+    /// Connections closed by a node, by disconnecting or banning a peer.
+    pub disconnects: u64,
+    /// RPC and gossip messages lost to the injected faults.
+    pub lost: u64,
+    /// RPC and gossip messages delayed by the injected faults.
+    pub delayed: u64,
+}
+
+/// The faults injected into the RPC and gossip messages carried by a `Router`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MessageFaults {
+    /// The probability that a message is lost.
+    pub drop_probability: f64,
+    /// The probability that a message which is not lost is delayed.
+    pub delay_probability: f64,
+    /// The longest a message is delayed for.
+    pub max_delay: Duration,
+}
+
+/// A message held back by the injected faults, which is lost if its sender and recipient
+/// disconnect before it is due.
+struct DelayedMessage {
+    from: usize,
+    to: usize,
+    due: Instant,
+    message: HandlerMessage,
+}
+
+/// A node, as seen by the router.
+struct Node {
+    peer_id: PeerId,
+    handler_send: mpsc::Sender<HandlerMessage>,
+    /// The gossip messages the node has received or published, so each is handled and forwarded
+    /// once only.
+    seen_gossip: HashSet<Vec<u8>>,
+}
+
+/// Carries messages between the message handlers of simulated nodes, in place of libp2p.
+///
+/// RPC messages are delivered only between connected nodes. Gossip is flooded across
+/// connections, each node forwarding a message it has not seen before to its other peers once
+/// its handler has validated it, as gossipsub would if every node subscribed to every topic.
+#[derive(Default)]
+pub struct Router {
+    nodes: Vec<Node>,
+    /// Gossip messages awaiting validation by the handler of the node they were delivered to,
+    /// by the trace id they were delivered with.
+    unvalidated_gossip: HashMap<TraceId, (usize, Vec<u8>, PubsubMessage)>,
+    indices: HashMap<PeerId, usize>,
+    /// Connected pairs of nodes, the lower index first.
+    links: HashSet<(usize, usize)>,
+    /// Pairs of nodes which may not connect, as one has banned the other.
+    banned: HashSet<(usize, usize)>,
+    faults: MessageFaults,
+    rng: Option<XorShiftRng>,
+    delayed: Vec<DelayedMessage>,
+    stats: RouterStats,
+}
+
+impl Router {
+    /// Adds a node, which receives messages on `handler_send`.
+    pub fn add_node(&mut self, peer_id: PeerId, handler_send: mpsc::Sender<HandlerMessage>) {
+        self.indices.insert(peer_id.clone(), self.nodes.len());
+        self.nodes.push(Node {
+            peer_id,
+            handler_send,
+            seen_gossip: HashSet::new(),
+        });
+    }
+
+    /// Replaces the handler of a restarted node, which has seen no gossip.
+    pub fn replace_node(&mut self, index: usize, handler_send: mpsc::Sender<HandlerMessage>) {
+        let node = &mut self.nodes[index];
+        node.handler_send = handler_send;
+        node.seen_gossip.clear();
+        self.unvalidated_gossip.retain(|_, (to, _, _)| *to != index);
+        self.delayed.retain(|delayed| delayed.to != index);
+    }
+
+    /// Injects `faults` into the messages carried from now on, choosing which at random from
+    /// `seed`.
+    pub fn set_faults(&mut self, faults: MessageFaults, seed: u64) {
+        let mut bytes = [0; 16];
+        bytes[0..8].copy_from_slice(&seed.to_le_bytes());
+        bytes[8..16].copy_from_slice(&(!seed).to_le_bytes());
+        self.faults = faults;
+        self.rng = Some(XorShiftRng::from_seed(bytes));
+    }
+
+    /// Delivers the delayed messages which are due, losing those between nodes which have since
+    /// disconnected.
+    pub fn release_delayed(&mut self) {
+        let now = Instant::now();
+        let (due, delayed) = self
+            .delayed
+            .drain(..)
+            .partition(|delayed: &DelayedMessage| delayed.due <= now);
+        self.delayed = delayed;
+        for delayed in due {
+            if self.is_connected(delayed.from, delayed.to) {
+                self.deliver(delayed.to, delayed.message);
+            } else {
+                self.stats.dropped += 1;
+            }
+        }
+    }
+
+    /// Connects two nodes, informing the dialer, which begins the HELLO handshake.
+    pub fn connect(&mut self, dialer: usize, listener: usize) {
+        let link = link(dialer, listener);
+        if dialer == listener || self.banned.contains(&link) || !self.links.insert(link) {
+            return;
+        }
+        let peer_id = self.nodes[listener].peer_id.clone();
+        self.deliver(dialer, HandlerMessage::PeerDialed(peer_id));
+    }
+
+    /// Closes the connection between two nodes, informing both.
+    pub fn disconnect(&mut self, a: usize, b: usize) {
+        if !self.links.remove(&link(a, b)) {
+            return;
+        }
+        let (peer_a, peer_b) = (self.nodes[a].peer_id.clone(), self.nodes[b].peer_id.clone());
+        self.deliver(a, HandlerMessage::PeerDisconnected(peer_b));
+        self.deliver(b, HandlerMessage::PeerDisconnected(peer_a));
+    }
+
+    /// Returns `true` if the two nodes are connected.
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.links.contains(&link(a, b))
+    }
+
+    /// Returns the counts of the messages carried so far.
+    pub fn stats(&self) -> RouterStats {
+        self.stats
+    }
+
+    /// Handles a message sent by the handler of node `from` to the network.
+    pub fn route(&mut self, from: usize, message: NetworkMessage) {
+        match message {
+            NetworkMessage::Send(peer_id, OutgoingMessage::RPC(rpc_event, len)) => {
+                match self.indices.get(&peer_id).cloned() {
+                    Some(to) if self.is_connected(from, to) => {
+                        let source = self.nodes[from].peer_id.clone();
+                        let message =
+                            HandlerMessage::RPC(source, rpc_event, len as usize, TraceId::next());
+                        if self.carry(from, to, message) {
+                            self.stats.rpc_delivered += 1;
+                        }
+                    }
+                    _ => self.stats.dropped += 1,
+                }
+            }
+            NetworkMessage::Send(_, OutgoingMessage::NotifierTest) => {}
+            NetworkMessage::Disconnect(peer_id, _reason) => {
+                if let Some(to) = self.indices.get(&peer_id).cloned() {
+                    self.stats.disconnects += 1;
+                    self.disconnect(from, to);
+                }
+            }
+            NetworkMessage::Ban(peer_id, ..) => {
+                if let Some(to) = self.indices.get(&peer_id).cloned() {
+                    self.stats.disconnects += 1;
+                    self.banned.insert(link(from, to));
+                    self.disconnect(from, to);
+                }
+            }
+            NetworkMessage::Publish { message, .. } => self.publish(from, message),
+            NetworkMessage::Propagate(trace_id) => {
+                if let Some((to, bytes, message)) = self.unvalidated_gossip.remove(&trace_id) {
+                    if to == from {
+                        self.forward(from, &bytes, &message);
+                    }
+                }
+            }
+            NetworkMessage::Discard(trace_id) => {
+                if self.unvalidated_gossip.remove(&trace_id).is_some() {
+                    self.stats.gossip_discarded += 1;
+                }
+            }
+            NetworkMessage::HandlerRestarted => {
+                let peers = self
+                    .peers_of(from)
+                    .into_iter()
+                    .map(|to| self.nodes[to].peer_id.clone())
+                    .collect();
+                self.deliver(from, HandlerMessage::ConnectedPeers(peers));
+            }
+            // queries are answered by the network service only, so the reply channel is dropped
+            NetworkMessage::GetPeerInfo(..)
+            | NetworkMessage::GetConnectedPeers(..)
+            | NetworkMessage::GetKnownPeers(..) => {}
+        }
+    }
+
+    /// Sends a gossip message published by node `publisher` to its peers, which forward it in
+    /// turn once validated.
+    pub fn publish(&mut self, publisher: usize, bytes: Vec<u8>) {
+        let message = match PubsubMessage::ssz_decode(&bytes, 0) {
+            Ok((message, _)) => message,
+            Err(_) => {
+                self.stats.dropped += 1;
+                return;
+            }
+        };
+        self.nodes[publisher].seen_gossip.insert(bytes.clone());
+        self.forward(publisher, &bytes, &message);
+    }
+
+    /// Delivers a gossip message from node `forwarder` to each of its peers which has not seen it.
+    fn forward(&mut self, forwarder: usize, bytes: &[u8], message: &PubsubMessage) {
+        let source = self.nodes[forwarder].peer_id.clone();
+        for to in self.peers_of(forwarder) {
+            if !self.nodes[to].seen_gossip.insert(bytes.to_vec()) {
+                continue;
+            }
+            let trace_id = TraceId::next();
+            self.unvalidated_gossip
+                .insert(trace_id, (to, bytes.to_vec(), message.clone()));
+            let delivered = self.carry(
+                forwarder,
+                to,
+                HandlerMessage::PubsubMessage(source.clone(), message.clone(), trace_id),
+            );
+            if delivered {
+                self.stats.gossip_delivered += 1;
+            } else {
+                self.unvalidated_gossip.remove(&trace_id);
+            }
+        }
+    }
+
+    /// Sends a heartbeat to every node.
+    pub fn heartbeat(&mut self) {
+        for index in 0..self.nodes.len() {
+            self.deliver(index, HandlerMessage::Heartbeat);
+        }
+    }
+
+    /// Tells every node to shut down.
+    pub fn shutdown(&mut self) {
+        for index in 0..self.nodes.len() {
+            self.stop(index);
+        }
+    }
+
+    /// Tells node `index` to shut down.
+    pub fn stop(&mut self, index: usize) {
+        self.deliver(index, HandlerMessage::Shutdown);
+    }
+
+    /// Returns the nodes connected to node `index`.
+    fn peers_of(&self, index: usize) -> Vec<usize> {
+        self.links
+            .iter()
+            .filter_map(|&(a, b)| {
+                if a == index {
+                    Some(b)
+                } else if b == index {
+                    Some(a)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Carries an RPC or gossip message from node `from` to node `to`, subject to the injected
+    /// faults. Returns `false` if it was lost or dropped.
+    fn carry(&mut self, from: usize, to: usize, message: HandlerMessage) -> bool {
+        let faults = self.faults;
+        if let Some(rng) = self.rng.as_mut() {
+            if rng.gen_bool(faults.drop_probability) {
+                self.stats.lost += 1;
+                return false;
+            }
+            if rng.gen_bool(faults.delay_probability) {
+                let max_millis = faults.max_delay.as_millis() as u64;
+                let delay = Duration::from_millis(rng.gen_range(0, max_millis + 1));
+                self.stats.delayed += 1;
+                self.delayed.push(DelayedMessage {
+                    from,
+                    to,
+                    due: Instant::now() + delay,
+                    message,
+                });
+                return true;
+            }
+        }
+        self.deliver(to, message)
+    }
+
+    /// Queues a message for the handler of node `to`, returning `false` if it was dropped as the
+    /// queue is full or the handler has stopped.
+    fn deliver(&mut self, to: usize, message: HandlerMessage) -> bool {
+        if self.nodes[to].handler_send.try_send(message).is_err() {
+            self.stats.dropped += 1;
+            return false;
+        }
+        true
+    }
+}
+
+/// Returns the key of the connection between two nodes.
+fn link(a: usize, b: usize) -> (usize, usize) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
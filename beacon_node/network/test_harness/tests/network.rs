@@ -0,0 +1,125 @@
+use eth2_libp2p::PubsubMessage;
+use network_test_harness::{SimulatedNetwork, SimulationConfig};
+use std::time::Duration;
+use types::Hash256;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[test]
+fn it_propagates_gossip_blocks_across_hops() {
+    let mut config = SimulationConfig::default();
+    config.node_count = 3;
+    let mut network = SimulatedNetwork::new(config);
+
+    // a line, so node 2 only receives blocks forwarded by node 1
+    network.connect(0, 1);
+    network.connect(1, 2);
+
+    for _ in 0..3 {
+        network.produce_block(0);
+    }
+
+    assert!(network.wait_for_convergence(TIMEOUT));
+    assert_eq!(network.nodes[2].head(), network.nodes[0].head());
+    assert!(network.stats().gossip_delivered >= 6);
+
+    network.shutdown();
+}
+
+#[test]
+fn it_forwards_only_valid_gossip() {
+    let mut config = SimulationConfig::default();
+    config.node_count = 3;
+    let mut network = SimulatedNetwork::new(config);
+    network.connect(0, 1);
+    network.connect(1, 2);
+
+    // node 1 cannot import the block, so does not forward it to node 2
+    let mut block = network.produce_unpublished_block(0);
+    block.state_root = Hash256::from_low_u64_be(1);
+    network.publish(0, PubsubMessage::Block(block));
+
+    assert!(network.wait_until(TIMEOUT, |network| network.stats().gossip_discarded == 1));
+    assert_eq!(network.stats().gossip_delivered, 1);
+    network.shutdown();
+}
+
+#[test]
+fn it_syncs_a_node_which_joins_late() {
+    let mut network = SimulatedNetwork::new(SimulationConfig::default());
+
+    for _ in 0..12 {
+        network.produce_unpublished_block(0);
+    }
+    assert_ne!(network.nodes[1].head(), network.nodes[0].head());
+
+    network.connect(1, 0);
+
+    assert!(network.wait_for_convergence(TIMEOUT));
+
+    network.shutdown();
+}
+
+#[test]
+fn it_resyncs_after_a_disconnection() {
+    let mut network = SimulatedNetwork::new(SimulationConfig::default());
+    network.connect(0, 1);
+
+    for _ in 0..2 {
+        network.produce_block(0);
+    }
+    assert!(network.wait_for_convergence(TIMEOUT));
+
+    // blocks produced whilst disconnected are only learned of by syncing
+    network.disconnect(0, 1);
+    for _ in 0..12 {
+        network.produce_block(0);
+    }
+    assert_ne!(network.nodes[1].head(), network.nodes[0].head());
+
+    network.connect(1, 0);
+
+    assert!(network.wait_for_convergence(TIMEOUT));
+
+    network.shutdown();
+}
+
+#[test]
+fn it_syncs_batches_from_several_peers() {
+    let mut config = SimulationConfig::default();
+    config.node_count = 3;
+    let mut network = SimulatedNetwork::new(config);
+    network.connect(0, 1);
+
+    // several epochs, so batches are downloaded from both synced nodes
+    for _ in 0..32 {
+        network.produce_block(0);
+    }
+    assert!(network.wait_until(TIMEOUT, |network| {
+        network.nodes[1].head() == network.nodes[0].head()
+    }));
+
+    network.connect(2, 0);
+    network.connect(2, 1);
+
+    assert!(network.wait_for_convergence(TIMEOUT));
+
+    network.shutdown();
+}
+
+#[test]
+fn it_looks_up_the_parents_of_a_gossip_block() {
+    let mut network = SimulatedNetwork::new(SimulationConfig::default());
+    network.connect(0, 1);
+
+    // fewer blocks than the sync tolerance, so node 1 remains idle and only learns of them by
+    // looking up the parents of the published block
+    for _ in 0..2 {
+        network.produce_unpublished_block(0);
+    }
+    network.produce_block(0);
+
+    assert!(network.wait_for_convergence(TIMEOUT));
+
+    network.shutdown();
+}
@@ -0,0 +1,185 @@
+use network_test_harness::{MessageFaults, SimulatedNetwork, SimulationConfig};
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use types::Epoch;
+
+const NODE_COUNT: usize = 4;
+/// How long the nodes are given to exchange the messages of a slot before the next.
+const SLOT_DURATION: Duration = Duration::from_millis(100);
+/// How long the nodes are given to converge once faults have stopped.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The probability, each slot, that a node is killed, if none is down already.
+const KILL_PROBABILITY: f64 = 0.03;
+/// The probability, each slot, that the connection between two nodes is dropped.
+const DISCONNECT_PROBABILITY: f64 = 0.05;
+
+/// Runs four nodes, which sync and gossip through their message handlers, whilst killing and
+/// restarting nodes, dropping connections and losing and delaying messages. Then checks that all
+/// nodes re-converge and finalize once the faults stop.
+///
+/// Set `SOAK_SLOTS` to run for longer, e.g. thousands of slots, and `SOAK_SEED` to change the
+/// faults.
+#[test]
+fn soak_network_reconverges_and_finalizes() {
+    let mut config = SimulationConfig::default();
+    config.node_count = NODE_COUNT;
+    config.validator_count = 16;
+    let slots_per_epoch = config.spec.slots_per_epoch;
+    let slots = env::var("SOAK_SLOTS").map_or(slots_per_epoch * 12, |slots| {
+        slots.parse().expect("SOAK_SLOTS must be a number")
+    });
+    let seed =
+        env::var("SOAK_SEED").map_or(42, |seed| seed.parse().expect("SOAK_SEED must be a number"));
+
+    let mut network = SimulatedNetwork::new(config);
+    network.connect_all();
+    network.set_faults(
+        MessageFaults {
+            drop_probability: 0.05,
+            delay_probability: 0.2,
+            max_delay: SLOT_DURATION * 2,
+        },
+        seed,
+    );
+
+    let mut rng = XorShiftRng::from_seed(seed_bytes(seed));
+    // the slot until which a killed node stays down, and a dropped connection stays closed
+    let mut down: HashMap<usize, u64> = HashMap::new();
+    let mut disconnected: HashMap<(usize, usize), u64> = HashMap::new();
+    let (mut kills, mut disconnects) = (0, 0);
+
+    for slot in 0..slots {
+        if down.is_empty() && rng.gen_bool(KILL_PROBABILITY) {
+            let node = rng.gen_range(0, NODE_COUNT);
+            // a killed node is cut off until it restarts
+            for peer in 0..NODE_COUNT {
+                network.disconnect(node, peer);
+            }
+            down.insert(node, slot + rng.gen_range(1, slots_per_epoch * 4 + 1));
+            kills += 1;
+        }
+        if rng.gen_bool(DISCONNECT_PROBABILITY) {
+            let a = rng.gen_range(0, NODE_COUNT);
+            let b = (a + rng.gen_range(1, NODE_COUNT)) % NODE_COUNT;
+            network.disconnect(a, b);
+            disconnected.insert(link(a, b), slot + rng.gen_range(1, slots_per_epoch * 2 + 1));
+            disconnects += 1;
+        }
+
+        let restarted: Vec<usize> = down
+            .iter()
+            .filter(|(_, until)| **until <= slot)
+            .map(|(node, _)| *node)
+            .collect();
+        for node in restarted {
+            down.remove(&node);
+            network.restart(node);
+            for peer in 0..NODE_COUNT {
+                if !down.contains_key(&peer) && !disconnected.contains_key(&link(node, peer)) {
+                    network.connect(node, peer);
+                }
+            }
+        }
+        let reconnected: Vec<(usize, usize)> = disconnected
+            .iter()
+            .filter(|(_, until)| **until <= slot)
+            .map(|(link, _)| *link)
+            .collect();
+        for (a, b) in reconnected {
+            disconnected.remove(&(a, b));
+            if !down.contains_key(&a) && !down.contains_key(&b) {
+                network.connect(a, b);
+            }
+        }
+
+        let up: Vec<usize> = (0..NODE_COUNT).filter(|i| !down.contains_key(i)).collect();
+        run_slot(&mut network, slot, &up);
+        // the nodes which are up are given the slot to converge, which they may not whilst
+        // disconnected
+        network.wait_until(SLOT_DURATION, |network| {
+            up.iter()
+                .all(|i| network.nodes[*i].head() == network.nodes[up[0]].head())
+        });
+    }
+
+    // with the faults stopped, each block is produced once the nodes agree on the head
+    network.set_faults(MessageFaults::default(), seed);
+    for node in down.keys() {
+        network.restart(*node);
+    }
+    network.connect_all();
+    let settle_slots = slots_per_epoch * 6;
+    let all: Vec<usize> = (0..NODE_COUNT).collect();
+    for slot in slots..slots + settle_slots {
+        assert!(
+            network.wait_for_convergence(SETTLE_TIMEOUT),
+            "nodes did not converge at slot {} after {} kill(s) and {} disconnect(s): {:?}",
+            slot,
+            kills,
+            disconnects,
+            network.heads()
+        );
+        run_slot(&mut network, slot, &all);
+    }
+    assert!(network.wait_for_convergence(SETTLE_TIMEOUT));
+
+    // with all nodes up for the settling slots, finality is at most a few epochs behind
+    let end_epoch = Epoch::new((slots + settle_slots) / slots_per_epoch);
+    for (i, node) in network.nodes.iter().enumerate() {
+        let finalized_epoch = node
+            .harness
+            .beacon_chain
+            .head()
+            .beacon_state
+            .finalized_epoch;
+        assert!(
+            finalized_epoch >= end_epoch - 4,
+            "node {} has finalized epoch {} at epoch {}",
+            i,
+            finalized_epoch,
+            end_epoch
+        );
+    }
+    println!(
+        "Soak test of {} slots with seed {}: {} kill(s), {} disconnect(s), {:?}",
+        slots,
+        seed,
+        kills,
+        disconnects,
+        network.stats()
+    );
+
+    network.shutdown();
+}
+
+/// Produces the block of a slot on one of the nodes which are `up`, taking turns, then the
+/// attestations of the slot on each of them.
+fn run_slot(network: &mut SimulatedNetwork, slot: u64, up: &[usize]) {
+    if up.is_empty() {
+        network.advance_slot();
+        return;
+    }
+    network.produce_block(up[slot as usize % up.len()]);
+    for node in up {
+        network.attest(*node);
+    }
+}
+
+fn seed_bytes(seed: u64) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[0..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..16].copy_from_slice(&(!seed).to_le_bytes());
+    bytes
+}
+
+/// Returns the key of the connection between two nodes.
+fn link(a: usize, b: usize) -> (usize, usize) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
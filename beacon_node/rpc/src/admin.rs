@@ -0,0 +1,177 @@
+use crate::beacon_chain::BeaconChain;
+use beacon_chain::era::ExportConfig;
+use futures::sync::oneshot;
+use futures::Future;
+use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
+use network::Service as NetworkService;
+use protos::services::{Empty, ExportEraRequest, ExportEraResponse};
+use protos::services_grpc::AdminService;
+use slog::{info, warn};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Clone)]
+pub struct AdminServiceInstance {
+    pub chain: Arc<BeaconChain>,
+    pub network: Arc<NetworkService>,
+    /// The directory under which exports are written, if permitted.
+    pub export_dir: Option<PathBuf>,
+    /// Whether an export is being written, as only one is written at a time.
+    pub exporting: Arc<AtomicBool>,
+    pub log: slog::Logger,
+}
+
+impl AdminService for AdminServiceInstance {
+    /// Stops requesting new blocks from peers until syncing is resumed.
+    fn pause_sync(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<Empty>) {
+        info!(self.log, "Sync paused by operator");
+        self.network.pause_sync();
+
+        let log_clone = self.log.clone();
+        let f = sink
+            .success(Empty::new())
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Resumes syncing after it was paused or stopped due to an error.
+    fn resume_sync(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<Empty>) {
+        info!(self.log, "Sync resumed by operator");
+        self.network.resume_sync();
+
+        let log_clone = self.log.clone();
+        let f = sink
+            .success(Empty::new())
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Writes the canonical chain to flat files on the beacon node, for distribution to nodes
+    /// which import them rather than syncing from peers.
+    ///
+    /// The files are written to the requested directory within the configured export directory,
+    /// on a thread of their own, replying once they are written. One export is written at a
+    /// time.
+    fn export_era(
+        &mut self,
+        ctx: RpcContext,
+        req: ExportEraRequest,
+        sink: UnarySink<ExportEraResponse>,
+    ) {
+        info!(self.log, "Chain export requested by operator"; "directory" => req.get_directory());
+
+        let log_clone = self.log.clone();
+        let dir = match export_path(self.export_dir.as_ref(), req.get_directory()) {
+            Ok(dir) => dir,
+            Err(message) => {
+                let f = sink
+                    .fail(RpcStatus::new(
+                        RpcStatusCode::InvalidArgument,
+                        Some(message),
+                    ))
+                    .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+                return ctx.spawn(f);
+            }
+        };
+        if self.exporting.swap(true, Ordering::SeqCst) {
+            let f = sink
+                .fail(RpcStatus::new(
+                    RpcStatusCode::ResourceExhausted,
+                    Some("An export is already being written".to_string()),
+                ))
+                .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+            return ctx.spawn(f);
+        }
+
+        let mut config = ExportConfig::default();
+        if req.get_epochs_per_file() > 0 {
+            config.epochs_per_file = req.get_epochs_per_file();
+        }
+        config.states_every = req.get_states_every();
+
+        let (result_send, result_recv) = oneshot::channel();
+        let chain = self.chain.clone();
+        let exporting = ExportInProgress(self.exporting.clone());
+        thread::spawn(move || {
+            let result = chain.export_era(&dir, &config);
+            drop(exporting);
+            // the request may have been cancelled
+            let _ = result_send.send(result);
+        });
+
+        let f = result_recv
+            .then(move |result| match result {
+                Ok(Ok(paths)) => {
+                    let mut resp = ExportEraResponse::new();
+                    resp.set_files(
+                        paths
+                            .iter()
+                            .map(|path| path.to_string_lossy().into_owned())
+                            .collect(),
+                    );
+                    sink.success(resp)
+                }
+                Ok(Err(e)) => sink.fail(RpcStatus::new(
+                    RpcStatusCode::Internal,
+                    Some(format!("Export failed: {:?}", e)),
+                )),
+                Err(_) => sink.fail(RpcStatus::new(
+                    RpcStatusCode::Internal,
+                    Some("Export ended unexpectedly".to_string()),
+                )),
+            })
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+}
+
+/// Marks the export as complete when dropped, even if writing it panicked.
+struct ExportInProgress(Arc<AtomicBool>);
+
+impl Drop for ExportInProgress {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Returns the directory named by an export request within `export_dir`, refusing any which is
+/// not a relative path without parent components, so that exports cannot be written elsewhere.
+fn export_path(export_dir: Option<&PathBuf>, directory: &str) -> Result<PathBuf, String> {
+    let export_dir = export_dir.ok_or_else(|| "Exports are not enabled".to_string())?;
+    let relative = Path::new(directory);
+    let contained = relative.components().all(|component| match component {
+        Component::Normal(_) => true,
+        _ => false,
+    });
+    if directory.is_empty() || !contained {
+        return Err(
+            "The directory must be a relative path within the export directory".to_string(),
+        );
+    }
+    Ok(export_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_are_written_within_the_export_directory() {
+        let export_dir = PathBuf::from("/var/lib/lighthouse/exports");
+
+        assert_eq!(
+            export_path(Some(&export_dir), "mainnet/2019"),
+            Ok(export_dir.join("mainnet/2019"))
+        );
+        assert!(export_path(None, "mainnet").is_err());
+        for directory in &["", "/tmp", "../outside", "mainnet/../../outside", "."] {
+            assert!(
+                export_path(Some(&export_dir), directory).is_err(),
+                "{}",
+                directory
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::beacon_chain::BeaconChain;
+use crate::slashable_cache::SlashableMessageCache;
+use beacon_chain::parking_lot::Mutex;
+use futures::sync::mpsc;
+use futures::{Future, Sink, Stream};
+use grpcio::{RpcContext, RpcStatus, RpcStatusCode, ServerStreamingSink, UnarySink, WriteFlags};
+use network::Service as NetworkService;
+use protos::services::{
+    AggregatedAttestation, AttestationSubscriptionRequest, PublishAttestationRequest,
+    PublishAttestationResponse,
+};
+use protos::services_grpc::AttestationService;
+use slog::{debug, trace, warn};
+use ssz::{ssz_encode, Decodable};
+use std::sync::Arc;
+use types::{Attestation, BeaconState, ChainSpec};
+
+/// The number of attestations buffered for a subscriber, beyond which attestations are not sent
+/// to it until it has caught up.
+const SUBSCRIPTION_BUFFER: usize = 64;
+
+#[derive(Clone)]
+pub struct AttestationServiceInstance {
+    pub chain: Arc<BeaconChain>,
+    pub network: Arc<NetworkService>,
+    /// The attestations recently published by each local validator.
+    pub slashable_cache: Arc<Mutex<SlashableMessageCache>>,
+    /// Whether attestations which cannot be validated against our chain are published whilst
+    /// syncing.
+    pub publish_while_syncing: bool,
+    pub log: slog::Logger,
+}
+
+impl AttestationService for AttestationServiceInstance {
+    /// Streams each aggregated attestation matching the request to the subscriber as free
+    /// attestations are added to it.
+    fn subscribe_aggregated_attestations(
+        &mut self,
+        ctx: RpcContext,
+        req: AttestationSubscriptionRequest,
+        sink: ServerStreamingSink<AggregatedAttestation>,
+    ) {
+        debug!(self.log, "RPC request"; "endpoint" => "SubscribeAggregatedAttestations", "shards" => format!("{:?}", req.get_shards()), "from_slot" => req.get_from_slot());
+
+        // The chain sends each attestation into a bounded channel without blocking, and the
+        // stream is forwarded on the executor. Once the subscriber goes away the stream is
+        // dropped, and the chain drops the subscription on its next attestation.
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let sender = Mutex::new(sender);
+        self.chain
+            .subscribe_attestations(Box::new(move |attestation: &Attestation| {
+                match sender.lock().try_send(attestation.clone()) {
+                    Ok(()) => true,
+                    // a slow subscriber misses attestations rather than delaying the chain
+                    Err(e) => e.is_full(),
+                }
+            }));
+
+        let filter = req.clone();
+        let log_clone = self.log.clone();
+        let f = sink
+            .send_all(
+                receiver
+                    .filter(move |attestation| matches_subscription(&filter, attestation))
+                    .map(|attestation| (to_proto(&attestation), WriteFlags::default()))
+                    .map_err(|_| grpcio::Error::RemoteStopped),
+            )
+            .map(|_| ())
+            .map_err(move |e| trace!(log_clone, "Attestation subscription ended: {:?}", e));
+        ctx.spawn(f)
+    }
+
+    /// Validates a locally produced attestation and gossips it.
+    ///
+    /// The attestation must be signed by the requesting validator alone, so that it is checked
+    /// against the attestations that validator has published.
+    ///
+    /// Whilst syncing, an attestation which cannot be validated against our chain is gossiped
+    /// regardless if `publish_while_syncing` is set, as our chain may merely lag the network's.
+    /// Malformed or badly signed attestations are never gossiped.
+    ///
+    /// An attestation which conflicts with one the validator has recently published through this
+    /// beacon node is refused, as it is likely produced by a duplicated validator client.
+    fn publish_attestation(
+        &mut self,
+        ctx: RpcContext,
+        req: PublishAttestationRequest,
+        sink: UnarySink<PublishAttestationResponse>,
+    ) {
+        debug!(self.log, "RPC request"; "endpoint" => "PublishAttestation", "validator_index" => req.get_validator_index());
+
+        let log_clone = self.log.clone();
+        let attestation = match Attestation::ssz_decode(req.get_attestation(), 0) {
+            Ok((attestation, _)) => attestation,
+            Err(_) => {
+                let f = sink
+                    .fail(RpcStatus::new(
+                        RpcStatusCode::InvalidArgument,
+                        Some("Invalid attestation".to_string()),
+                    ))
+                    .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+                return ctx.spawn(f);
+            }
+        };
+
+        let mut resp = PublishAttestationResponse::new();
+        match self.publish(req.get_validator_index(), attestation) {
+            Ok(()) => resp.set_success(true),
+            Err(message) => {
+                warn!(self.log, "Attestation not published"; "validator_index" => req.get_validator_index(), "reason" => &message);
+                resp.set_success(false);
+                resp.set_msg(message.into_bytes());
+            }
+        }
+
+        let f = sink
+            .success(resp)
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+}
+
+impl AttestationServiceInstance {
+    /// Checks the attestation against those recently published by the validator, then
+    /// processes and gossips it. See `publish_attestation`.
+    fn publish(&self, validator_index: u64, attestation: Attestation) -> Result<(), String> {
+        let spec = self.chain.get_spec();
+        let slots_per_epoch = spec.slots_per_epoch;
+        let current_epoch = {
+            let state = self.chain.get_state();
+            verify_signer(&state, validator_index, &attestation, spec)?;
+            state.slot.epoch(slots_per_epoch)
+        };
+
+        // the cache is held until the attestation is recorded, so concurrent conflicting
+        // requests cannot both be published
+        let mut cache = self.slashable_cache.lock();
+        cache.prune(current_epoch);
+        cache
+            .check(validator_index, &attestation.data, slots_per_epoch)
+            .map_err(|e| format!("Slashable attestation: {:?}", e))?;
+
+        if let Err(e) = self.chain.process_attestation(attestation.clone()) {
+            if !self.publish_while_syncing || !e.is_state_dependent() || self.network.is_synced() {
+                return Err(format!("Invalid attestation: {:?}", e));
+            }
+            warn!(
+                self.log,
+                "Publishing unvalidated attestation whilst syncing";
+                "validator_index" => validator_index,
+                "error" => format!("{:?}", e),
+            );
+        }
+        cache.insert(validator_index, &attestation.data, slots_per_epoch);
+
+        self.network.publish_attestation(attestation);
+        Ok(())
+    }
+}
+
+/// Checks that the only participant of the attestation is the validator with `validator_index`,
+/// a member of the committee of its slot and shard in `state`.
+fn verify_signer(
+    state: &BeaconState,
+    validator_index: u64,
+    attestation: &Attestation,
+    spec: &ChainSpec,
+) -> Result<(), String> {
+    let data = &attestation.data;
+    let committee = state
+        .get_crosslink_committees_at_slot(data.slot, spec)
+        .map_err(|e| format!("Unable to find committee: {:?}", e))?
+        .iter()
+        .find(|committee| committee.shard == data.shard)
+        .ok_or_else(|| {
+            format!(
+                "No committee for shard {} at slot {}",
+                data.shard, data.slot
+            )
+        })?;
+    let position = committee
+        .committee
+        .iter()
+        .position(|&index| index as u64 == validator_index)
+        .ok_or_else(|| "Validator is not in the committee of the attestation".to_string())?;
+
+    let bitfield = &attestation.aggregation_bitfield;
+    if bitfield.num_set_bits() != 1 || bitfield.get(position) != Ok(true) {
+        return Err("Attestation is not signed by the validator alone".to_string());
+    }
+    Ok(())
+}
+
+/// Returns `true` if the subscriber has asked to receive the attestation.
+fn matches_subscription(req: &AttestationSubscriptionRequest, attestation: &Attestation) -> bool {
+    let data = &attestation.data;
+    let shards = req.get_shards();
+
+    data.slot.as_u64() >= req.get_from_slot() && (shards.is_empty() || shards.contains(&data.shard))
+}
+
+fn to_proto(attestation: &Attestation) -> AggregatedAttestation {
+    let data = &attestation.data;
+
+    let mut proto = AggregatedAttestation::new();
+    proto.set_slot(data.slot.as_u64());
+    proto.set_shard(data.shard);
+    proto.set_beacon_block_root(data.beacon_block_root.as_bytes().to_vec());
+    proto.set_participants(attestation.aggregation_bitfield.num_set_bits() as u64);
+    proto.set_ssz(ssz_encode(attestation));
+    proto
+}
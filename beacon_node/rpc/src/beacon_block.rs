@@ -1,14 +1,24 @@
+use crate::beacon_chain::BeaconChain;
+use beacon_chain::{BlockProcessingOutcome, InvalidBlock};
 use futures::Future;
-use grpcio::{RpcContext, UnarySink};
+use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
+use network::Service as NetworkService;
 use protos::services::{
     BeaconBlock as BeaconBlockProto, ProduceBeaconBlockRequest, ProduceBeaconBlockResponse,
     PublishBeaconBlockRequest, PublishBeaconBlockResponse,
 };
 use protos::services_grpc::BeaconBlockService;
-use slog::Logger;
+use slog::{debug, warn, Logger};
+use ssz::Decodable;
+use std::sync::Arc;
+use types::BeaconBlock;
 
 #[derive(Clone)]
 pub struct BeaconBlockServiceInstance {
+    pub chain: Arc<BeaconChain>,
+    pub network: Arc<NetworkService>,
+    /// Whether blocks are produced, and those whose parent is unknown published, whilst syncing.
+    pub publish_while_syncing: bool,
     pub log: Logger,
 }
 
@@ -22,6 +32,18 @@ impl BeaconBlockService for BeaconBlockServiceInstance {
     ) {
         println!("producing at slot {}", req.get_slot());
 
+        // a block built on a stale head may be orphaned, but missing the slot is worse unless
+        // sync gated
+        if !self.publish_while_syncing && !self.network.is_synced() {
+            let f = sink
+                .fail(RpcStatus::new(
+                    RpcStatusCode::Unavailable,
+                    Some("Beacon node is syncing".to_string()),
+                ))
+                .map_err(move |e| println!("failed to reply {:?}: {:?}", req, e));
+            return ctx.spawn(f);
+        }
+
         // TODO: build a legit block.
         let mut block = BeaconBlockProto::new();
         block.set_slot(req.get_slot());
@@ -37,21 +59,68 @@ impl BeaconBlockService for BeaconBlockServiceInstance {
     }
 
     /// Accept some fully-formed `BeaconBlock`, process and publish it.
+    ///
+    /// Whilst syncing, a block whose parent is unknown is published regardless if
+    /// `publish_while_syncing` is set, as our chain may merely lag the network's.
     fn publish_beacon_block(
         &mut self,
         ctx: RpcContext,
         req: PublishBeaconBlockRequest,
         sink: UnarySink<PublishBeaconBlockResponse>,
     ) {
-        println!("publishing {:?}", req.get_block());
+        debug!(self.log, "RPC request"; "endpoint" => "PublishBeaconBlock", "slot" => req.get_block().get_slot());
+
+        let log_clone = self.log.clone();
+        let block = match BeaconBlock::ssz_decode(req.get_block().get_ssz(), 0) {
+            Ok((block, _)) => block,
+            Err(_) => {
+                let f = sink
+                    .fail(RpcStatus::new(
+                        RpcStatusCode::InvalidArgument,
+                        Some("Invalid block".to_string()),
+                    ))
+                    .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+                return ctx.spawn(f);
+            }
+        };
 
-        // TODO: actually process the block.
         let mut resp = PublishBeaconBlockResponse::new();
-        resp.set_success(true);
+        match self.publish(block) {
+            Ok(()) => resp.set_success(true),
+            Err(message) => {
+                warn!(self.log, "Block not published"; "slot" => req.get_block().get_slot(), "reason" => &message);
+                resp.set_success(false);
+                resp.set_msg(message.into_bytes());
+            }
+        }
 
         let f = sink
             .success(resp)
-            .map_err(move |e| println!("failed to reply {:?}: {:?}", req, e));
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
         ctx.spawn(f)
     }
 }
+
+impl BeaconBlockServiceInstance {
+    /// Processes the block, then gossips it. See `publish_beacon_block`.
+    fn publish(&self, block: BeaconBlock) -> Result<(), String> {
+        match self.chain.process_block(block.clone()) {
+            Ok(BlockProcessingOutcome::ValidBlock(_))
+            | Ok(BlockProcessingOutcome::QueuedFutureBlock) => {}
+            Ok(BlockProcessingOutcome::InvalidBlock(InvalidBlock::ParentUnknown))
+                if self.publish_while_syncing && !self.network.is_synced() =>
+            {
+                warn!(
+                    self.log,
+                    "Publishing unvalidated block whilst syncing";
+                    "slot" => block.slot.as_u64(),
+                );
+            }
+            Ok(outcome) => return Err(format!("Invalid block: {:?}", outcome)),
+            Err(e) => return Err(format!("Unable to process block: {:?}", e)),
+        }
+
+        self.network.publish_block(block);
+        Ok(())
+    }
+}
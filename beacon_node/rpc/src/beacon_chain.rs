@@ -0,0 +1,205 @@
+use beacon_chain::era::{self, EraError, ExportConfig};
+use beacon_chain::validator_performance::{self, EpochPerformance};
+use beacon_chain::BeaconChain as RawBeaconChain;
+use beacon_chain::{
+    db::{ClientDB, DBError},
+    fork_choice::ForkChoice,
+    parking_lot::RwLockReadGuard,
+    slot_clock::SlotClock,
+    types::{
+        Attestation, BeaconBlock, BeaconState, BeaconStateError, ChainSpec, Epoch, Hash256,
+        PublicKey, Slot, Validator,
+    },
+    AttestationSubscriber, AttestationValidationError, BeaconChainError, BlockProcessingOutcome,
+    CheckPoint, DutiesReader, HeadInfo, IncrementalMerkleTree, ProposerDuties,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The RPC's API to the beacon chain.
+pub trait BeaconChain: Send + Sync {
+    fn get_spec(&self) -> &ChainSpec;
+
+    fn get_state(&self) -> RwLockReadGuard<BeaconState>;
+
+    fn head(&self) -> RwLockReadGuard<CheckPoint>;
+
+    fn finalized_head(&self) -> RwLockReadGuard<CheckPoint>;
+
+    /// Returns the values of the head most often read, without waiting for block imports.
+    fn head_info(&self) -> HeadInfo;
+
+    /// Returns the roots and slots of the canonical blocks with a slot in
+    /// `[start_slot, start_slot + count)`, found with the slot index.
+    fn get_block_roots(&self, start_slot: Slot, count: u64) -> Vec<(Hash256, Slot)>;
+
+    /// Returns the root of the canonical block at `slot`, or of the latest canonical block before
+    /// it if the slot was skipped, without walking the chain from the head.
+    fn block_root_at_slot(&self, slot: Slot) -> Option<Hash256>;
+
+    fn genesis_block_root(&self) -> Hash256;
+
+    fn genesis_validators_root(&self) -> Hash256;
+
+    /// Returns the genesis time of the chain, without waiting for block imports.
+    fn genesis_time(&self) -> u64;
+
+    /// Returns the index of the validator with `pubkey` in `registry`, which must be the registry
+    /// of some state of the chain.
+    fn validator_index_in_registry(
+        &self,
+        registry: &[Validator],
+        pubkey: &PublicKey,
+    ) -> Option<usize>;
+
+    /// Returns the duties of the current epoch, which may be read without locking the state.
+    fn duties_reader(&self) -> Result<Arc<DutiesReader>, BeaconStateError>;
+
+    /// Returns the proposers of `epoch`, the previous or current epoch of the head state.
+    fn proposer_duties(&self, epoch: Epoch) -> Result<ProposerDuties, BeaconStateError>;
+
+    fn get_block(&self, block_root: Hash256) -> Result<Option<BeaconBlock>, DBError>;
+
+    /// Returns the SSZ encoding of a block as stored, without decoding it.
+    fn block_ssz(&self, block_root: Hash256) -> Result<Option<Vec<u8>>, DBError>;
+
+    /// Returns the SSZ encoding of a state as stored, without decoding it.
+    fn state_ssz(&self, state_root: Hash256) -> Result<Option<Vec<u8>>, DBError>;
+
+    /// Calls `subscriber` with each aggregated attestation as it is updated, until it returns
+    /// `false`.
+    fn subscribe_attestations(&self, subscriber: AttestationSubscriber);
+
+    /// Validates an attestation, adding it to the pool for block inclusion.
+    fn process_attestation(
+        &self,
+        attestation: Attestation,
+    ) -> Result<bool, AttestationValidationError>;
+
+    /// Validates and imports a block, such as one produced by a local validator.
+    fn process_block(&self, block: BeaconBlock)
+        -> Result<BlockProcessingOutcome, BeaconChainError>;
+
+    /// Writes the canonical chain to flat files in `dir`, returning their paths.
+    fn export_era(&self, dir: &Path, config: &ExportConfig) -> Result<Vec<PathBuf>, EraError>;
+
+    /// Returns the deposit tree as of the finalized state, if all of its deposits are known.
+    fn finalized_deposit_tree(&self) -> Option<IncrementalMerkleTree>;
+
+    /// Returns the attestation performance of a validator in up to `epochs` recent epochs,
+    /// newest first.
+    fn validator_performance(
+        &self,
+        validator_index: usize,
+        epochs: u64,
+    ) -> Result<Vec<EpochPerformance>, BeaconChainError>;
+}
+
+impl<T, U, F> BeaconChain for RawBeaconChain<T, U, F>
+where
+    T: ClientDB + Sized + 'static,
+    U: SlotClock,
+    F: ForkChoice,
+{
+    fn get_spec(&self) -> &ChainSpec {
+        &self.spec
+    }
+
+    fn get_state(&self) -> RwLockReadGuard<BeaconState> {
+        self.state.read()
+    }
+
+    fn head(&self) -> RwLockReadGuard<CheckPoint> {
+        self.head()
+    }
+
+    fn finalized_head(&self) -> RwLockReadGuard<CheckPoint> {
+        self.finalized_head()
+    }
+
+    fn head_info(&self) -> HeadInfo {
+        self.head_info()
+    }
+
+    fn get_block_roots(&self, start_slot: Slot, count: u64) -> Vec<(Hash256, Slot)> {
+        self.get_block_roots(start_slot, count)
+    }
+
+    fn block_root_at_slot(&self, slot: Slot) -> Option<Hash256> {
+        self.block_root_at_slot(slot)
+    }
+
+    fn genesis_block_root(&self) -> Hash256 {
+        self.genesis_block_root
+    }
+
+    fn genesis_validators_root(&self) -> Hash256 {
+        self.genesis_validators_root
+    }
+
+    fn genesis_time(&self) -> u64 {
+        self.genesis_time
+    }
+
+    fn validator_index_in_registry(
+        &self,
+        registry: &[Validator],
+        pubkey: &PublicKey,
+    ) -> Option<usize> {
+        self.validator_index_in_registry(registry, pubkey)
+    }
+
+    fn duties_reader(&self) -> Result<Arc<DutiesReader>, BeaconStateError> {
+        self.duties_reader()
+    }
+
+    fn proposer_duties(&self, epoch: Epoch) -> Result<ProposerDuties, BeaconStateError> {
+        self.proposer_duties(epoch)
+    }
+
+    fn get_block(&self, block_root: Hash256) -> Result<Option<BeaconBlock>, DBError> {
+        self.block_store.get_deserialized(&block_root)
+    }
+
+    fn block_ssz(&self, block_root: Hash256) -> Result<Option<Vec<u8>>, DBError> {
+        self.block_store.get(&block_root)
+    }
+
+    fn state_ssz(&self, state_root: Hash256) -> Result<Option<Vec<u8>>, DBError> {
+        self.state_store.get(&state_root)
+    }
+
+    fn subscribe_attestations(&self, subscriber: AttestationSubscriber) {
+        self.subscribe_attestations(subscriber)
+    }
+
+    fn process_attestation(
+        &self,
+        attestation: Attestation,
+    ) -> Result<bool, AttestationValidationError> {
+        self.process_attestation(attestation)
+    }
+
+    fn process_block(
+        &self,
+        block: BeaconBlock,
+    ) -> Result<BlockProcessingOutcome, BeaconChainError> {
+        self.process_block(block)
+    }
+
+    fn export_era(&self, dir: &Path, config: &ExportConfig) -> Result<Vec<PathBuf>, EraError> {
+        era::export(self, dir, config)
+    }
+
+    fn finalized_deposit_tree(&self) -> Option<IncrementalMerkleTree> {
+        self.finalized_deposit_tree()
+    }
+
+    fn validator_performance(
+        &self,
+        validator_index: usize,
+        epochs: u64,
+    ) -> Result<Vec<EpochPerformance>, BeaconChainError> {
+        validator_performance::validator_performance(self, validator_index, epochs)
+    }
+}
@@ -0,0 +1,463 @@
+use crate::beacon_chain::BeaconChain;
+use eth2_libp2p::{HelloMessage, PeerId};
+use futures::Future;
+use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
+use logging::LogBuffer;
+use network::sync::SyncState;
+use network::Service as NetworkService;
+use protos::services::{
+    BlockRequest, Checkpoint, ComparePeerRequest, ComparePeerResponse, ConnectedPeer,
+    ConnectedPeersResponse, DecodeErrorCount, DepositTreeSnapshotResponse, Empty, GenesisResponse,
+    HelloRecord as HelloRecordProto, LogRecord, LogsResponse, Metric, MetricsResponse,
+    PeerDebugInfo, PeerDebugResponse, SszResponse, SyncStatusResponse,
+};
+use protos::services_grpc::BeaconNodeService;
+use slog::{trace, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use types::{Hash256, Slot};
+
+#[derive(Clone)]
+pub struct BeaconNodeServiceInstance {
+    pub chain: Arc<BeaconChain>,
+    pub network: Arc<NetworkService>,
+    /// The most recent warning and error log records.
+    pub error_logs: LogBuffer,
+    pub log: slog::Logger,
+}
+
+impl BeaconNodeService for BeaconNodeServiceInstance {
+    /// Provides the genesis parameters which identify the chain this node is following.
+    fn genesis(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<GenesisResponse>) {
+        trace!(self.log, "RPC request"; "endpoint" => "Genesis");
+
+        let spec = self.chain.get_spec();
+
+        let mut resp = GenesisResponse::new();
+        resp.set_genesis_time(self.chain.genesis_time());
+        resp.set_genesis_slot(spec.genesis_slot.as_u64());
+        resp.set_genesis_block_root(self.chain.genesis_block_root().as_bytes().to_vec());
+        resp.set_genesis_validators_root(self.chain.genesis_validators_root().as_bytes().to_vec());
+
+        let log_clone = self.log.clone();
+        let f = sink
+            .success(resp)
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Provides the progress of syncing and whether the node considers itself synced, as reported
+    /// by the network service.
+    fn sync_status(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<SyncStatusResponse>) {
+        trace!(self.log, "RPC request"; "endpoint" => "SyncStatus");
+
+        let f = match self.network.sync_status() {
+            Some(status) => {
+                let mut resp = SyncStatusResponse::new();
+                let state = match status.state {
+                    SyncState::Idle => "Idle",
+                    SyncState::Downloading => "Downloading",
+                    SyncState::Stopped(_) => "Stopped",
+                };
+                resp.set_state(state.to_string());
+                resp.set_head_slot(status.current_slot.as_u64());
+                resp.set_clock_slot(status.clock_slot.as_u64());
+                resp.set_network_head_slot(status.target_slot.map_or(0, |slot| slot.as_u64()));
+                resp.set_sync_peers(status.sync_peers as u64);
+                resp.set_is_synced(status.is_synced);
+                sink.success(resp)
+            }
+            None => sink.fail(RpcStatus::new(
+                RpcStatusCode::Unavailable,
+                Some("Network service has stopped".to_string()),
+            )),
+        };
+
+        let log_clone = self.log.clone();
+        ctx.spawn(f.map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e)))
+    }
+
+    /// Provides the counters of the beacon chain and the network service.
+    fn metrics(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<MetricsResponse>) {
+        trace!(self.log, "RPC request"; "endpoint" => "Metrics");
+
+        let mut resp = MetricsResponse::new();
+        let counters = beacon_chain::metrics::gather()
+            .into_iter()
+            .chain(network::metrics::gather());
+        for (name, value) in counters {
+            let mut metric = Metric::new();
+            metric.set_name(name.to_string());
+            metric.set_value(value as u64);
+            resp.mut_metrics().push(metric);
+        }
+
+        let log_clone = self.log.clone();
+        let f = sink
+            .success(resp)
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Provides the peers known to this node, including their score and recent HELLO messages,
+    /// as reported by the network service.
+    fn peer_debug(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<PeerDebugResponse>) {
+        trace!(self.log, "RPC request"; "endpoint" => "PeerDebug");
+
+        let f = match self.network.known_peers() {
+            Some(snapshots) => {
+                let mut resp = PeerDebugResponse::new();
+                for snapshot in snapshots {
+                    let mut peer = PeerDebugInfo::new();
+                    peer.set_peer_id(snapshot.peer_id.to_base58());
+                    peer.set_score(snapshot.score);
+                    if let Some(remaining) = snapshot.ban_remaining {
+                        peer.set_ban_remaining_secs(remaining.as_secs());
+                    }
+
+                    for (message, age) in snapshot.hello_history.iter() {
+                        peer.mut_hello_history().push(hello_record(message, *age));
+                    }
+
+                    for (message_type, error_kind, count) in snapshot.decode_errors {
+                        let mut decode_error = DecodeErrorCount::new();
+                        decode_error.set_message_type(message_type);
+                        decode_error.set_error_kind(error_kind);
+                        decode_error.set_count(count);
+                        peer.mut_decode_errors().push(decode_error);
+                    }
+
+                    resp.mut_peers().push(peer);
+                }
+                sink.success(resp)
+            }
+            None => sink.fail(RpcStatus::new(
+                RpcStatusCode::Unavailable,
+                Some("Network service has stopped".to_string()),
+            )),
+        };
+
+        let log_clone = self.log.clone();
+        ctx.spawn(f.map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e)))
+    }
+
+    /// Provides the connected peers, as reported by the network service.
+    fn connected_peers(
+        &mut self,
+        ctx: RpcContext,
+        req: Empty,
+        sink: UnarySink<ConnectedPeersResponse>,
+    ) {
+        trace!(self.log, "RPC request"; "endpoint" => "ConnectedPeers");
+
+        let f = match self.network.connected_peers() {
+            Some(snapshots) => {
+                let mut resp = ConnectedPeersResponse::new();
+                for snapshot in snapshots {
+                    let mut peer = ConnectedPeer::new();
+                    peer.set_peer_id(snapshot.peer_id.to_base58());
+                    peer.set_trusted(snapshot.trusted);
+                    peer.set_score(snapshot.score);
+                    if let Some(latency) = snapshot.latency {
+                        peer.set_latency_ms(millis(latency));
+                    }
+                    if let Some((message, age)) = snapshot.hello_history.last() {
+                        peer.set_last_hello(hello_record(message, *age));
+                    }
+                    for address in snapshot.addresses {
+                        peer.mut_addresses().push(format!("{}", address));
+                    }
+                    resp.mut_peers().push(peer);
+                }
+                sink.success(resp)
+            }
+            None => sink.fail(RpcStatus::new(
+                RpcStatusCode::Unavailable,
+                Some("Network service has stopped".to_string()),
+            )),
+        };
+
+        let log_clone = self.log.clone();
+        ctx.spawn(f.map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e)))
+    }
+
+    /// Provides the most recent warning and error log records, so problems can be diagnosed
+    /// without access to the log output.
+    fn error_logs(&mut self, ctx: RpcContext, req: Empty, sink: UnarySink<LogsResponse>) {
+        trace!(self.log, "RPC request"; "endpoint" => "ErrorLogs");
+
+        let mut resp = LogsResponse::new();
+        for entry in self.error_logs.entries() {
+            let mut record = LogRecord::new();
+            record.set_timestamp(entry.timestamp);
+            record.set_level(entry.level.as_str().to_string());
+            record.set_message(entry.message);
+            resp.mut_records().push(record);
+        }
+
+        let log_clone = self.log.clone();
+        let f = sink
+            .success(resp)
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Compares our finalized and head checkpoints against those from the last HELLO of a peer,
+    /// reporting the most recent block known to be shared by both chains.
+    fn compare_peer(
+        &mut self,
+        ctx: RpcContext,
+        req: ComparePeerRequest,
+        sink: UnarySink<ComparePeerResponse>,
+    ) {
+        trace!(self.log, "RPC request"; "endpoint" => "ComparePeer", "peer" => req.get_peer_id());
+
+        // a malformed peer id cannot be known
+        let hello = req
+            .get_peer_id()
+            .parse::<PeerId>()
+            .ok()
+            .and_then(|peer_id| self.network.peer_info(peer_id))
+            .and_then(|mut snapshot| snapshot.hello_history.pop())
+            .map(|(message, _)| message);
+
+        let mut resp = ComparePeerResponse::new();
+        if let Some(hello) = hello {
+            let head_info = self.chain.head_info();
+            let (head_root, head_slot) = (head_info.block_root, head_info.slot);
+            let (finalized_root, finalized_slot) = {
+                let finalized = self.chain.finalized_head();
+                (finalized.beacon_block_root, finalized.beacon_block.slot)
+            };
+
+            // Peers which have not finalized beyond genesis may advertise a zero finalized root.
+            let peer_finalized_root = if hello.latest_finalized_root.is_zero() {
+                self.chain.genesis_block_root()
+            } else {
+                hello.latest_finalized_root
+            };
+            let peer_finalized_slot = hello
+                .latest_finalized_epoch
+                .start_slot(self.chain.get_spec().slots_per_epoch);
+
+            // each block is in our chain if it is our canonical block at or before its slot,
+            // which is found without walking the chain
+            let head_found =
+                self.chain.block_root_at_slot(hello.best_slot) == Some(hello.best_root);
+            let finalized_found =
+                self.chain.block_root_at_slot(peer_finalized_slot) == Some(peer_finalized_root);
+            let common_ancestor = if head_found {
+                Some((hello.best_root, hello.best_slot))
+            } else if finalized_found {
+                // the finalized block may precede the first slot of its epoch
+                let slot = self
+                    .chain
+                    .get_block(peer_finalized_root)
+                    .ok()
+                    .and_then(|block| block)
+                    .map_or(peer_finalized_slot, |block| block.slot);
+                Some((peer_finalized_root, slot))
+            } else {
+                None
+            };
+
+            resp.set_peer_known(true);
+            resp.set_our_finalized(checkpoint(finalized_root, finalized_slot));
+            resp.set_our_head(checkpoint(head_root, head_slot));
+            resp.set_peer_finalized(checkpoint(peer_finalized_root, peer_finalized_slot));
+            resp.set_peer_head(checkpoint(hello.best_root, hello.best_slot));
+            resp.set_peer_finalized_in_our_chain(finalized_found);
+            resp.set_peer_head_in_our_chain(head_found);
+            if let Some((root, slot)) = common_ancestor {
+                resp.set_common_ancestor(checkpoint(root, slot));
+            }
+        }
+
+        let log_clone = self.log.clone();
+        let f = sink
+            .success(resp)
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Provides a snapshot of the deposit tree as of the finalized state, allowing a node which
+    /// did not sync from genesis to validate later deposits.
+    fn deposit_tree_snapshot(
+        &mut self,
+        ctx: RpcContext,
+        req: Empty,
+        sink: UnarySink<DepositTreeSnapshotResponse>,
+    ) {
+        trace!(self.log, "RPC request"; "endpoint" => "DepositTreeSnapshot");
+
+        let (finalized_root, finalized_slot) = {
+            let finalized = self.chain.finalized_head();
+            (finalized.beacon_block_root, finalized.beacon_block.slot)
+        };
+
+        let log_clone = self.log.clone();
+        let f = match self.chain.finalized_deposit_tree() {
+            Some(tree) => {
+                let mut resp = DepositTreeSnapshotResponse::new();
+                resp.set_branch(
+                    tree.branch()
+                        .iter()
+                        .map(|node| node.as_bytes().to_vec())
+                        .collect(),
+                );
+                resp.set_deposit_count(tree.len());
+                resp.set_deposit_root(tree.root().as_bytes().to_vec());
+                resp.set_finalized(checkpoint(finalized_root, finalized_slot));
+                sink.success(resp)
+            }
+            None => sink.fail(RpcStatus::new(
+                RpcStatusCode::Unavailable,
+                Some("Deposits of the finalized state are unknown".to_string()),
+            )),
+        }
+        .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Provides the SSZ encoding of a block, as stored.
+    fn beacon_block_ssz(
+        &mut self,
+        ctx: RpcContext,
+        req: BlockRequest,
+        sink: UnarySink<SszResponse>,
+    ) {
+        trace!(self.log, "RPC request"; "endpoint" => "BeaconBlockSsz");
+
+        let result = self
+            .block_root(&req)
+            .and_then(|block_root| match block_root {
+                Some(root) => self
+                    .chain
+                    .block_ssz(root)
+                    .map(|ssz| ssz.map(|ssz| (root, ssz)))
+                    .map_err(|e| internal_error(e.message)),
+                None => Ok(None),
+            });
+
+        let log_clone = self.log.clone();
+        let f = reply_ssz(sink, result, "Block")
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Provides the SSZ encoding of the state following a block, as stored.
+    fn beacon_state_ssz(
+        &mut self,
+        ctx: RpcContext,
+        req: BlockRequest,
+        sink: UnarySink<SszResponse>,
+    ) {
+        trace!(self.log, "RPC request"; "endpoint" => "BeaconStateSsz");
+
+        let result = self.block_root(&req).and_then(|block_root| {
+            let block = match block_root {
+                Some(root) => self
+                    .chain
+                    .get_block(root)
+                    .map_err(|e| internal_error(e.message))?,
+                None => None,
+            };
+            match block {
+                Some(block) => self
+                    .chain
+                    .state_ssz(block.state_root)
+                    .map(|ssz| ssz.map(|ssz| (block.state_root, ssz)))
+                    .map_err(|e| internal_error(e.message)),
+                None => Ok(None),
+            }
+        });
+
+        let log_clone = self.log.clone();
+        let f = reply_ssz(sink, result, "State")
+            .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+}
+
+impl BeaconNodeServiceInstance {
+    /// Returns the root of the requested block, or `None` if there is no canonical block at the
+    /// requested slot. A request with neither a root nor a slot of the chain is rejected.
+    fn block_root(&self, req: &BlockRequest) -> Result<Option<Hash256>, RpcStatus> {
+        let root = req.get_root();
+        if !root.is_empty() {
+            if root.len() != 32 {
+                return Err(RpcStatus::new(
+                    RpcStatusCode::InvalidArgument,
+                    Some("Block root must be 32 bytes".to_string()),
+                ));
+            }
+            return Ok(Some(Hash256::from_slice(root)));
+        }
+
+        // an unset slot is zero, which is before genesis
+        let slot = Slot::new(req.get_slot());
+        if slot < self.chain.get_spec().genesis_slot {
+            return Err(RpcStatus::new(
+                RpcStatusCode::InvalidArgument,
+                Some("Either a block root or a slot from genesis is required".to_string()),
+            ));
+        }
+        Ok(self
+            .chain
+            .get_block_roots(slot, 1)
+            .into_iter()
+            .next()
+            .map(|(root, _)| root))
+    }
+}
+
+/// Replies with the root and SSZ encoding of an object, or a `NotFound` status if it is unknown.
+fn reply_ssz(
+    sink: UnarySink<SszResponse>,
+    result: Result<Option<(Hash256, Vec<u8>)>, RpcStatus>,
+    object: &str,
+) -> grpcio::UnarySinkResult {
+    match result {
+        Ok(Some((root, ssz))) => {
+            let mut resp = SszResponse::new();
+            resp.set_root(root.as_bytes().to_vec());
+            resp.set_ssz(ssz);
+            sink.success(resp)
+        }
+        Ok(None) => sink.fail(RpcStatus::new(
+            RpcStatusCode::NotFound,
+            Some(format!("{} not found", object)),
+        )),
+        Err(status) => sink.fail(status),
+    }
+}
+
+fn internal_error(message: String) -> RpcStatus {
+    RpcStatus::new(RpcStatusCode::Internal, Some(message))
+}
+
+/// Builds a `Checkpoint` protobuf message.
+fn checkpoint(root: Hash256, slot: Slot) -> Checkpoint {
+    let mut checkpoint = Checkpoint::new();
+    checkpoint.set_slot(slot.as_u64());
+    checkpoint.set_root(root.as_bytes().to_vec());
+    checkpoint
+}
+
+/// Converts a HELLO message received `age` ago to its protobuf form.
+fn hello_record(message: &HelloMessage, age: Duration) -> HelloRecordProto {
+    let mut hello = HelloRecordProto::new();
+    hello.set_received_ms_ago(millis(age));
+    hello.set_network_id(u32::from(message.network_id));
+    hello.set_fork_digest(message.fork_digest.to_vec());
+    hello.set_latest_finalized_root(message.latest_finalized_root.as_bytes().to_vec());
+    hello.set_latest_finalized_epoch(message.latest_finalized_epoch.as_u64());
+    hello.set_best_root(message.best_root.as_bytes().to_vec());
+    hello.set_best_slot(message.best_slot.as_u64());
+    hello
+}
+
+/// Returns a duration in whole milliseconds.
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000 + u64::from(duration.subsec_millis())
+}
@@ -1,4 +1,5 @@
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 
 /// RPC Configuration
 #[derive(Debug, Clone)]
@@ -9,6 +10,13 @@ pub struct Config {
     pub listen_address: Ipv4Addr,
     /// The port the RPC will listen on.
     pub port: u16,
+    /// Whether local validators are served duties and their blocks and attestations are published
+    /// even if they cannot be validated whilst the node is syncing, as our chain may merely lag
+    /// the network's.
+    pub publish_while_syncing: bool,
+    /// The directory under which chain exports requested through the admin service are written.
+    /// Exports are refused if `None`.
+    pub export_dir: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -17,6 +25,8 @@ impl Default for Config {
             enabled: false, // rpc disabled by default
             listen_address: Ipv4Addr::new(127, 0, 0, 1),
             port: 5051,
+            publish_while_syncing: true,
+            export_dir: None,
         }
     }
 }
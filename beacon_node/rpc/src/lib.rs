@@ -1,31 +1,96 @@
+mod admin;
+mod attestation;
 mod beacon_block;
+pub mod beacon_chain;
+mod beacon_node;
 pub mod config;
+mod slashable_cache;
 mod validator;
 
+use self::admin::AdminServiceInstance;
+use self::attestation::AttestationServiceInstance;
 use self::beacon_block::BeaconBlockServiceInstance;
+use self::beacon_chain::BeaconChain;
+use self::beacon_node::BeaconNodeServiceInstance;
+use self::slashable_cache::SlashableMessageCache;
 use self::validator::ValidatorServiceInstance;
+use beacon_chain::parking_lot::Mutex;
 pub use config::Config as RPCConfig;
 use grpcio::{Environment, Server, ServerBuilder};
-use protos::services_grpc::{create_beacon_block_service, create_validator_service};
+use logging::LogBuffer;
+use network::Service as NetworkService;
+use protos::services_grpc::{
+    create_admin_service, create_attestation_service, create_beacon_block_service,
+    create_beacon_node_service, create_validator_service,
+};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 use slog::{info, o};
 
-pub fn start_server(config: &RPCConfig, log: &slog::Logger) -> Server {
+pub fn start_server(
+    config: &RPCConfig,
+    beacon_chain: Arc<BeaconChain>,
+    network: Arc<NetworkService>,
+    error_logs: LogBuffer,
+    log: &slog::Logger,
+) -> Server {
     let log = log.new(o!("Service"=>"RPC"));
     let env = Arc::new(Environment::new(1));
 
+    let beacon_node_service = {
+        let instance = BeaconNodeServiceInstance {
+            chain: beacon_chain.clone(),
+            network: network.clone(),
+            error_logs,
+            log: log.clone(),
+        };
+        create_beacon_node_service(instance)
+    };
+    let admin_service = {
+        let instance = AdminServiceInstance {
+            chain: beacon_chain.clone(),
+            network: network.clone(),
+            export_dir: config.export_dir.clone(),
+            exporting: Arc::new(AtomicBool::new(false)),
+            log: log.clone(),
+        };
+        create_admin_service(instance)
+    };
     let beacon_block_service = {
-        let instance = BeaconBlockServiceInstance { log: log.clone() };
+        let instance = BeaconBlockServiceInstance {
+            chain: beacon_chain.clone(),
+            network: network.clone(),
+            publish_while_syncing: config.publish_while_syncing,
+            log: log.clone(),
+        };
         create_beacon_block_service(instance)
     };
+    let attestation_service = {
+        let instance = AttestationServiceInstance {
+            chain: beacon_chain.clone(),
+            network: network.clone(),
+            slashable_cache: Arc::new(Mutex::new(SlashableMessageCache::default())),
+            publish_while_syncing: config.publish_while_syncing,
+            log: log.clone(),
+        };
+        create_attestation_service(instance)
+    };
     let validator_service = {
-        let instance = ValidatorServiceInstance { log: log.clone() };
+        let instance = ValidatorServiceInstance {
+            chain: beacon_chain.clone(),
+            network,
+            publish_while_syncing: config.publish_while_syncing,
+            log: log.clone(),
+        };
         create_validator_service(instance)
     };
 
     let mut server = ServerBuilder::new(env)
+        .register_service(beacon_node_service)
+        .register_service(admin_service)
         .register_service(beacon_block_service)
+        .register_service(attestation_service)
         .register_service(validator_service)
         .bind(config.listen_address.to_string(), config.port)
         .build()
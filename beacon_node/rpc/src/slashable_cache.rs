@@ -0,0 +1,206 @@
+use ssz::TreeHash;
+use std::collections::HashMap;
+use types::{AttestationData, Epoch, Hash256};
+
+/// The number of epochs before the current epoch for which broadcast attestations are remembered.
+const RETAINED_EPOCHS: u64 = 1;
+
+/// The reason a locally produced attestation was refused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlashableMessage {
+    /// The validator has already broadcast a different attestation with the same target epoch.
+    DoubleVote { target_epoch: Epoch },
+    /// The attestation surrounds, or is surrounded by, one the validator has already broadcast.
+    SurroundVote {
+        source_epoch: Epoch,
+        target_epoch: Epoch,
+    },
+}
+
+/// An attestation broadcast by a local validator.
+struct Broadcast {
+    source_epoch: Epoch,
+    target_epoch: Epoch,
+    data_root: Hash256,
+}
+
+/// Remembers the attestations each local validator has recently broadcast, so a conflicting
+/// attestation (e.g. from a duplicated validator client) is refused rather than gossiped.
+///
+/// This only protects against conflicts seen by this beacon node within the last
+/// `RETAINED_EPOCHS` epochs and is not a substitute for slashing protection in the validator
+/// client.
+#[derive(Default)]
+pub struct SlashableMessageCache {
+    broadcasts: HashMap<u64, Vec<Broadcast>>,
+}
+
+impl SlashableMessageCache {
+    /// Returns an error if broadcasting `data` would make the validator slashable.
+    ///
+    /// Re-broadcasting an identical attestation is permitted.
+    pub fn check(
+        &self,
+        validator_index: u64,
+        data: &AttestationData,
+        slots_per_epoch: u64,
+    ) -> Result<(), SlashableMessage> {
+        let source_epoch = data.source_epoch;
+        let target_epoch = data.slot.epoch(slots_per_epoch);
+        let data_root = Hash256::from_slice(&data.hash_tree_root()[..]);
+
+        let broadcasts = match self.broadcasts.get(&validator_index) {
+            Some(broadcasts) => broadcasts,
+            None => return Ok(()),
+        };
+
+        for broadcast in broadcasts {
+            if broadcast.data_root == data_root {
+                continue;
+            }
+            if broadcast.target_epoch == target_epoch {
+                return Err(SlashableMessage::DoubleVote { target_epoch });
+            }
+            let surrounds =
+                source_epoch < broadcast.source_epoch && target_epoch > broadcast.target_epoch;
+            let surrounded =
+                source_epoch > broadcast.source_epoch && target_epoch < broadcast.target_epoch;
+            if surrounds || surrounded {
+                return Err(SlashableMessage::SurroundVote {
+                    source_epoch,
+                    target_epoch,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records that the validator has broadcast an attestation for `data`.
+    pub fn insert(&mut self, validator_index: u64, data: &AttestationData, slots_per_epoch: u64) {
+        let broadcast = Broadcast {
+            source_epoch: data.source_epoch,
+            target_epoch: data.slot.epoch(slots_per_epoch),
+            data_root: Hash256::from_slice(&data.hash_tree_root()[..]),
+        };
+        self.broadcasts
+            .entry(validator_index)
+            .or_insert_with(Vec::new)
+            .push(broadcast);
+    }
+
+    /// Forgets attestations targeting epochs more than `RETAINED_EPOCHS` before `current_epoch`.
+    pub fn prune(&mut self, current_epoch: Epoch) {
+        let oldest = current_epoch - RETAINED_EPOCHS;
+
+        for broadcasts in self.broadcasts.values_mut() {
+            broadcasts.retain(|broadcast| broadcast.target_epoch >= oldest);
+        }
+        self.broadcasts
+            .retain(|_, broadcasts| !broadcasts.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+    use types::Slot;
+
+    const SLOTS_PER_EPOCH: u64 = 8;
+
+    /// Returns attestation data with the given source epoch, targeting the epoch of `slot`.
+    fn data(source_epoch: u64, slot: u64) -> AttestationData {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let mut data = AttestationData::random_for_test(&mut rng);
+        data.source_epoch = Epoch::new(source_epoch);
+        data.slot = Slot::new(slot);
+        data
+    }
+
+    /// Returns a cache in which validator 0 has broadcast `data`.
+    fn cache_of(data: &AttestationData) -> SlashableMessageCache {
+        let mut cache = SlashableMessageCache::default();
+        cache.insert(0, data, SLOTS_PER_EPOCH);
+        cache
+    }
+
+    #[test]
+    fn identical_and_unrelated_attestations_are_permitted() {
+        let broadcast = data(2, 3 * SLOTS_PER_EPOCH);
+        let cache = cache_of(&broadcast);
+
+        assert_eq!(cache.check(0, &broadcast, SLOTS_PER_EPOCH), Ok(()));
+        // the next epoch, from the same source
+        assert_eq!(
+            cache.check(0, &data(2, 4 * SLOTS_PER_EPOCH), SLOTS_PER_EPOCH),
+            Ok(())
+        );
+        // another validator may vote differently
+        let mut other = broadcast.clone();
+        other.beacon_block_root = Hash256::from_low_u64_be(1);
+        assert_eq!(cache.check(1, &other, SLOTS_PER_EPOCH), Ok(()));
+    }
+
+    #[test]
+    fn double_votes_are_refused() {
+        let broadcast = data(2, 3 * SLOTS_PER_EPOCH);
+        let cache = cache_of(&broadcast);
+
+        // another block in the same target epoch
+        let mut other = broadcast.clone();
+        other.beacon_block_root = Hash256::from_low_u64_be(1);
+        assert_eq!(
+            cache.check(0, &other, SLOTS_PER_EPOCH),
+            Err(SlashableMessage::DoubleVote {
+                target_epoch: Epoch::new(3)
+            })
+        );
+
+        // another slot of the same target epoch
+        let later = data(2, 3 * SLOTS_PER_EPOCH + 1);
+        assert_eq!(
+            cache.check(0, &later, SLOTS_PER_EPOCH),
+            Err(SlashableMessage::DoubleVote {
+                target_epoch: Epoch::new(3)
+            })
+        );
+    }
+
+    #[test]
+    fn surround_votes_are_refused() {
+        let cache = cache_of(&data(2, 4 * SLOTS_PER_EPOCH));
+
+        // surrounds the broadcast
+        assert_eq!(
+            cache.check(0, &data(1, 5 * SLOTS_PER_EPOCH), SLOTS_PER_EPOCH),
+            Err(SlashableMessage::SurroundVote {
+                source_epoch: Epoch::new(1),
+                target_epoch: Epoch::new(5),
+            })
+        );
+        // surrounded by the broadcast
+        assert_eq!(
+            cache.check(0, &data(3, 3 * SLOTS_PER_EPOCH), SLOTS_PER_EPOCH),
+            Err(SlashableMessage::SurroundVote {
+                source_epoch: Epoch::new(3),
+                target_epoch: Epoch::new(3),
+            })
+        );
+    }
+
+    #[test]
+    fn old_broadcasts_are_forgotten() {
+        let broadcast = data(2, 3 * SLOTS_PER_EPOCH);
+        let mut cache = cache_of(&broadcast);
+        let mut other = broadcast.clone();
+        other.beacon_block_root = Hash256::from_low_u64_be(1);
+
+        cache.prune(Epoch::new(3 + RETAINED_EPOCHS));
+        assert!(cache.check(0, &other, SLOTS_PER_EPOCH).is_err());
+
+        cache.prune(Epoch::new(4 + RETAINED_EPOCHS));
+        assert_eq!(cache.check(0, &other, SLOTS_PER_EPOCH), Ok(()));
+        assert!(cache.broadcasts.is_empty());
+    }
+}
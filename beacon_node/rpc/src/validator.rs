@@ -1,15 +1,31 @@
+use crate::beacon_chain::BeaconChain;
 use bls::PublicKey;
 use futures::Future;
 use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
+use network::Service as NetworkService;
 use protos::services::{
-    IndexResponse, ProposeBlockSlotRequest, ProposeBlockSlotResponse, PublicKey as PublicKeyRequest,
+    EpochPerformance, ExitStatusRequest, ExitStatusRequest_oneof_validator_oneof as ValidatorId,
+    ExitStatusResponse, ExitStatusResponse_Stage as Stage,
+    ExitStatusResponse_WithdrawalCredentialsType as WithdrawalCredentialsType, IndexResponse,
+    PerformanceRequest, PerformanceResponse, ProposeBlockSlotRequest, ProposeBlockSlotResponse,
+    ProposerDutiesRequest, ProposerDutiesResponse, ProposerDuty, PublicKey as PublicKeyRequest,
 };
 use protos::services_grpc::ValidatorService;
-use slog::{debug, Logger};
-use ssz::Decodable;
+use slog::{debug, warn, Logger};
+use ssz::{ssz_encode, Decodable};
+use std::sync::Arc;
+use types::{BeaconState, BeaconStateError, ChainSpec, Epoch, Validator};
+
+/// The maximum number of epochs of a single performance report, each of which requires a stored
+/// state to be loaded and advanced.
+const MAX_PERFORMANCE_EPOCHS: u64 = 64;
 
 #[derive(Clone)]
 pub struct ValidatorServiceInstance {
+    pub chain: Arc<BeaconChain>,
+    pub network: Arc<NetworkService>,
+    /// Whether duties are served whilst syncing, rather than refused.
+    pub publish_while_syncing: bool,
     pub log: Logger,
 }
 
@@ -23,14 +39,24 @@ impl ValidatorService for ValidatorServiceInstance {
         if let Ok((public_key, _)) = PublicKey::ssz_decode(req.get_public_key(), 0) {
             debug!(self.log, "RPC request"; "endpoint" => "ValidatorIndex", "public_key" => public_key.concatenated_hex_id());
 
-            let mut resp = IndexResponse::new();
-
-            // TODO: return a legit value.
-            resp.set_index(1);
+            let index = {
+                let head = self.chain.head();
+                self.chain
+                    .validator_index_in_registry(&head.beacon_state.validator_registry, &public_key)
+            };
 
-            let f = sink
-                .success(resp)
-                .map_err(move |e| println!("failed to reply {:?}: {:?}", req, e));
+            let f = match index {
+                Some(index) => {
+                    let mut resp = IndexResponse::new();
+                    resp.set_index(index as u64);
+                    sink.success(resp)
+                }
+                None => sink.fail(RpcStatus::new(
+                    RpcStatusCode::NotFound,
+                    Some("Unknown validator".to_string()),
+                )),
+            }
+            .map_err(move |e| println!("failed to reply {:?}: {:?}", req, e));
             ctx.spawn(f)
         } else {
             let f = sink
@@ -51,14 +77,337 @@ impl ValidatorService for ValidatorServiceInstance {
     ) {
         debug!(self.log, "RPC request"; "endpoint" => "ProposeBlockSlot", "epoch" => req.get_epoch(), "validator_index" => req.get_validator_index());
 
-        let mut resp = ProposeBlockSlotResponse::new();
+        // duties computed from a stale head may not be those of the network's chain, but missing
+        // them is worse unless sync gated
+        let result = if self.publish_while_syncing || self.network.is_synced() {
+            self.chain.duties_reader().map_err(|e| {
+                RpcStatus::new(
+                    RpcStatusCode::Internal,
+                    Some(format!("Unable to read duties: {:?}", e)),
+                )
+            })
+        } else {
+            Err(RpcStatus::new(
+                RpcStatusCode::Unavailable,
+                Some("Beacon node is syncing".to_string()),
+            ))
+        };
 
-        // TODO: return a legit value.
-        resp.set_slot(1);
+        let f = match result {
+            Ok(ref duties) if duties.epoch() != req.get_epoch() => sink.fail(RpcStatus::new(
+                RpcStatusCode::OutOfRange,
+                Some(format!(
+                    "Duties are only known for epoch {}",
+                    duties.epoch()
+                )),
+            )),
+            Ok(duties) => {
+                let mut resp = ProposeBlockSlotResponse::new();
+                match duties.proposal_slot(req.get_validator_index() as usize) {
+                    Some(slot) => resp.set_slot(slot.as_u64()),
+                    None => resp.set_none(true),
+                }
+                sink.success(resp)
+            }
+            Err(status) => sink.fail(status),
+        }
+        .map_err(move |e| println!("failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
 
-        let f = sink
-            .success(resp)
-            .map_err(move |e| println!("failed to reply {:?}: {:?}", req, e));
+    /// Reports the progress of a validator through the exit and withdrawal queues, as of the
+    /// head state.
+    fn exit_status(
+        &mut self,
+        ctx: RpcContext,
+        req: ExitStatusRequest,
+        sink: UnarySink<ExitStatusResponse>,
+    ) {
+        debug!(self.log, "RPC request"; "endpoint" => "ExitStatus");
+
+        let result = {
+            let head = self.chain.head();
+            find_validator(&*self.chain, &head.beacon_state, &req)
+                .map(|index| exit_status(&head.beacon_state, index, self.chain.get_spec()))
+        };
+
+        let log_clone = self.log.clone();
+        let f = match result {
+            Ok(resp) => sink.success(resp),
+            Err((code, message)) => sink.fail(RpcStatus::new(code, Some(message.to_string()))),
+        }
+        .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
         ctx.spawn(f)
     }
+
+    /// Reports how well a validator attested in recent epochs, so that stakers may audit their
+    /// setup.
+    fn performance(
+        &mut self,
+        ctx: RpcContext,
+        req: PerformanceRequest,
+        sink: UnarySink<PerformanceResponse>,
+    ) {
+        debug!(self.log, "RPC request"; "endpoint" => "Performance", "epochs" => req.get_epochs());
+
+        let epochs = std::cmp::min(std::cmp::max(req.get_epochs(), 1), MAX_PERFORMANCE_EPOCHS);
+        let result = PublicKey::ssz_decode(req.get_public_key(), 0)
+            .map_err(|_| {
+                (
+                    RpcStatusCode::InvalidArgument,
+                    "Invalid public_key".to_string(),
+                )
+            })
+            .and_then(|(public_key, _)| {
+                let head = self.chain.head();
+                self.chain
+                    .validator_index_in_registry(&head.beacon_state.validator_registry, &public_key)
+                    .ok_or((RpcStatusCode::NotFound, "Unknown validator".to_string()))
+            })
+            .and_then(|index| {
+                self.chain
+                    .validator_performance(index, epochs)
+                    .map(|performance| (index, performance))
+                    .map_err(|e| {
+                        (
+                            RpcStatusCode::Internal,
+                            format!("Unable to compute performance: {:?}", e),
+                        )
+                    })
+            });
+
+        let log_clone = self.log.clone();
+        let f = match result {
+            Ok((index, performance)) => {
+                let mut resp = PerformanceResponse::new();
+                resp.set_validator_index(index as u64);
+                for epoch in performance {
+                    let mut record = EpochPerformance::new();
+                    record.set_epoch(epoch.epoch.as_u64());
+                    record.set_active(epoch.active);
+                    record.set_source_correct(epoch.source_correct);
+                    record.set_target_correct(epoch.target_correct);
+                    record.set_head_correct(epoch.head_correct);
+                    record.set_inclusion_delay(epoch.inclusion_delay.unwrap_or(0));
+                    record.set_balance_change(epoch.balance_change);
+                    resp.mut_epochs().push(record);
+                }
+                sink.success(resp)
+            }
+            Err((code, message)) => sink.fail(RpcStatus::new(code, Some(message))),
+        }
+        .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
+    /// Provides the proposer of each slot of an epoch, so that monitoring tools may predict
+    /// upcoming proposals. Only the previous and current epochs of the head state are known.
+    fn proposer_duties(
+        &mut self,
+        ctx: RpcContext,
+        req: ProposerDutiesRequest,
+        sink: UnarySink<ProposerDutiesResponse>,
+    ) {
+        debug!(self.log, "RPC request"; "endpoint" => "ProposerDuties", "epoch" => req.get_epoch());
+
+        let log_clone = self.log.clone();
+        let f = match self.chain.proposer_duties(Epoch::new(req.get_epoch())) {
+            Ok(duties) => {
+                let mut resp = ProposerDutiesResponse::new();
+                resp.set_epoch(duties.epoch.as_u64());
+                resp.set_dependent_root(duties.dependent_root.as_bytes().to_vec());
+                for (slot, index, public_key) in duties.proposers {
+                    let mut duty = ProposerDuty::new();
+                    duty.set_slot(slot.as_u64());
+                    duty.set_validator_index(index as u64);
+                    duty.set_public_key(ssz_encode(&public_key));
+                    resp.mut_duties().push(duty);
+                }
+                sink.success(resp)
+            }
+            Err(BeaconStateError::RelativeEpochError(e)) => sink.fail(RpcStatus::new(
+                RpcStatusCode::OutOfRange,
+                Some(format!("Proposers are not known for the epoch: {:?}", e)),
+            )),
+            Err(e) => sink.fail(RpcStatus::new(
+                RpcStatusCode::Internal,
+                Some(format!("Unable to read proposers: {:?}", e)),
+            )),
+        }
+        .map_err(move |e| warn!(log_clone, "failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+}
+
+/// Returns the index of the validator identified by the request in the validator registry.
+fn find_validator(
+    chain: &BeaconChain,
+    state: &BeaconState,
+    req: &ExitStatusRequest,
+) -> Result<usize, (RpcStatusCode, &'static str)> {
+    let index = match &req.validator_oneof {
+        Some(ValidatorId::validator_index(index)) => Some(*index as usize),
+        Some(ValidatorId::public_key(bytes)) => {
+            let (public_key, _) = PublicKey::ssz_decode(bytes, 0)
+                .map_err(|_| (RpcStatusCode::InvalidArgument, "Invalid public_key"))?;
+            chain.validator_index_in_registry(&state.validator_registry, &public_key)
+        }
+        None => {
+            return Err((
+                RpcStatusCode::InvalidArgument,
+                "Missing validator_index or public_key",
+            ))
+        }
+    };
+
+    index
+        .filter(|index| *index < state.validator_registry.len())
+        .ok_or((RpcStatusCode::NotFound, "Unknown validator"))
+}
+
+/// Computes the exit status of the validator at `index`, which must be in the registry.
+fn exit_status(state: &BeaconState, index: usize, spec: &ChainSpec) -> ExitStatusResponse {
+    let registry = &state.validator_registry;
+    let validator = &registry[index];
+    let current_epoch = state.current_epoch(spec);
+
+    let exit_queued = |v: &Validator| v.initiated_exit && v.exit_epoch == spec.far_future_epoch;
+    let awaiting_withdrawal = |v: &Validator| {
+        v.exit_epoch != spec.far_future_epoch && v.withdrawable_epoch == spec.far_future_epoch
+    };
+
+    let (stage, queue_position) = if exit_queued(validator) {
+        // validators are exited in order of their index
+        let ahead = registry[..index].iter().filter(|v| exit_queued(v)).count();
+        (Stage::EXIT_QUEUED, ahead)
+    } else if awaiting_withdrawal(validator) {
+        // validators are made withdrawable in order of their exit epoch
+        let ahead = registry
+            .iter()
+            .enumerate()
+            .filter(|(i, v)| {
+                awaiting_withdrawal(v) && (v.exit_epoch, *i) < (validator.exit_epoch, index)
+            })
+            .count();
+        (Stage::EXITED, ahead)
+    } else if validator.withdrawable_epoch <= current_epoch {
+        (Stage::WITHDRAWABLE, 0)
+    } else if validator.exit_epoch != spec.far_future_epoch {
+        // scheduled to become withdrawable in a future epoch
+        (Stage::EXITED, 0)
+    } else {
+        (Stage::NOT_EXITING, 0)
+    };
+
+    let credentials = validator.withdrawal_credentials.as_bytes();
+    let credentials_type = if credentials[0] == spec.bls_withdrawal_prefix_byte {
+        WithdrawalCredentialsType::BLS
+    } else {
+        WithdrawalCredentialsType::UNKNOWN
+    };
+
+    let mut resp = ExitStatusResponse::new();
+    resp.set_validator_index(index as u64);
+    resp.set_stage(stage);
+    if validator.exit_epoch != spec.far_future_epoch {
+        resp.set_exit_epoch(validator.exit_epoch.as_u64());
+    }
+    if validator.withdrawable_epoch != spec.far_future_epoch {
+        resp.set_withdrawable_epoch(validator.withdrawable_epoch.as_u64());
+    }
+    resp.set_queue_position(queue_position as u64);
+    resp.set_withdrawal_credentials(credentials.to_vec());
+    resp.set_withdrawal_credentials_type(credentials_type);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::TestingBeaconStateBuilder;
+    use types::Hash256;
+
+    /// Returns the stage and queue position of each validator in `state`.
+    fn stages(state: &BeaconState, spec: &ChainSpec) -> Vec<(Stage, u64)> {
+        (0..state.validator_registry.len())
+            .map(|index| {
+                let status = exit_status(state, index, spec);
+                (status.get_stage(), status.get_queue_position())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exit_status_follows_the_exit_and_withdrawal_queues() {
+        let spec = ChainSpec::few_validators();
+        let (mut state, _) =
+            TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec).build();
+        let current_epoch = state.current_epoch(&spec);
+        {
+            let registry = &mut state.validator_registry;
+            // initiated exits awaiting an exit epoch
+            registry[1].initiated_exit = true;
+            registry[3].initiated_exit = true;
+            // exited, awaiting a withdrawable epoch, queued by exit epoch
+            registry[2].exit_epoch = current_epoch + 2;
+            registry[4].exit_epoch = current_epoch + 1;
+            // withdrawable
+            registry[5].exit_epoch = current_epoch;
+            registry[5].withdrawable_epoch = current_epoch;
+            // exited, to become withdrawable
+            registry[6].exit_epoch = current_epoch;
+            registry[6].withdrawable_epoch = current_epoch + 5;
+        }
+
+        assert_eq!(
+            stages(&state, &spec),
+            vec![
+                (Stage::NOT_EXITING, 0),
+                (Stage::EXIT_QUEUED, 0),
+                (Stage::EXITED, 1),
+                (Stage::EXIT_QUEUED, 1),
+                (Stage::EXITED, 0),
+                (Stage::WITHDRAWABLE, 0),
+                (Stage::EXITED, 0),
+                (Stage::NOT_EXITING, 0),
+            ]
+        );
+
+        let status = exit_status(&state, 6, &spec);
+        assert_eq!(status.get_validator_index(), 6);
+        assert_eq!(status.get_exit_epoch(), current_epoch.as_u64());
+        assert_eq!(
+            status.get_withdrawable_epoch(),
+            (current_epoch + 5).as_u64()
+        );
+        // epochs in the far future are left unset
+        let status = exit_status(&state, 0, &spec);
+        assert_eq!(status.get_exit_epoch(), 0);
+        assert_eq!(status.get_withdrawable_epoch(), 0);
+    }
+
+    #[test]
+    fn exit_status_reports_the_withdrawal_credentials_type() {
+        let spec = ChainSpec::few_validators();
+        let (mut state, _) =
+            TestingBeaconStateBuilder::from_deterministic_keypairs(2, &spec).build();
+        let mut bls_credentials = [0x11; 32];
+        bls_credentials[0] = spec.bls_withdrawal_prefix_byte;
+        let mut other_credentials = bls_credentials;
+        other_credentials[0] = spec.bls_withdrawal_prefix_byte.wrapping_add(1);
+        state.validator_registry[0].withdrawal_credentials = Hash256::from_slice(&bls_credentials);
+        state.validator_registry[1].withdrawal_credentials =
+            Hash256::from_slice(&other_credentials);
+
+        let status = exit_status(&state, 0, &spec);
+        assert_eq!(
+            status.get_withdrawal_credentials_type(),
+            WithdrawalCredentialsType::BLS
+        );
+        assert_eq!(status.get_withdrawal_credentials(), &bls_credentials[..]);
+        assert_eq!(
+            exit_status(&state, 1, &spec).get_withdrawal_credentials_type(),
+            WithdrawalCredentialsType::UNKNOWN
+        );
+    }
 }
@@ -2,13 +2,23 @@ extern crate slog;
 
 mod run;
 
-use clap::{App, Arg};
-use client::ClientConfig;
-use slog::{error, o, Drain};
+use clap::{App, Arg, SubCommand};
+use client::crash_report::CRASH_REPORT_LOG_RECORDS;
+use client::{resolve_genesis_time, ClientConfig, CrashReporter};
+use logging::LogBuffer;
+use slog::{error, o, Drain, Level};
+
+/// The number of recent warning and error log records served by the RPC API.
+const ERROR_LOG_RECORDS: usize = 1024;
 
 fn main() {
+    // recent records are kept for crash reports, and warnings and errors for the RPC API
+    let log_buffer = LogBuffer::new(CRASH_REPORT_LOG_RECORDS, Level::Info);
+    let error_logs = LogBuffer::new(ERROR_LOG_RECORDS, Level::Warning);
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::CompactFormat::new(decorator).build().fuse();
+    let drain = slog::Duplicate::new(drain, log_buffer.clone()).fuse();
+    let drain = slog::Duplicate::new(drain, error_logs.clone()).fuse();
     let drain = slog_async::Async::new(drain).build().fuse();
     let logger = slog::Logger::root(drain, o!());
 
@@ -23,6 +33,92 @@ fn main() {
                 .help("Data directory for keys and databases.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("discovery-address")
+                .long("discovery-address")
+                .value_name("IP")
+                .help("The IP address advertised to peers, e.g. the public address of a router this node is behind.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("upnp")
+                .long("upnp")
+                .help("Map the listen port on a UPnP gateway, or a NAT-PMP gateway if none is found, and advertise the external address.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("mdns")
+                .long("mdns")
+                .help("Discover peers on the local network with mDNS. For LAN devnets only.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("trusted-peers")
+                .long("trusted-peers")
+                .value_name("MULTIADDRS")
+                .help("Comma-separated addresses of peers to always stay connected to and never ban, each ending in /p2p/<peer id>.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("node-key")
+                .long("node-key")
+                .value_name("FILE")
+                .help("The file holding the private key which identifies this node to its peers. Defaults to node_key in the datadir.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("new-identity")
+                .long("new-identity")
+                .help("Replace the node key with a newly generated one, changing the PeerId of this node.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("peer-audit-log")
+                .long("peer-audit-log")
+                .value_name("FILE")
+                .help("Append each peer connection, disconnection and ban to FILE as a line of JSON, with the peer's score at the time.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("db-backend")
+                .long("db-backend")
+                .value_name("BACKEND")
+                .help("The database backend. The redb backend requires building with the `redb` feature.")
+                .possible_values(&["memory", "rocksdb", "redb"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("migrate-db-from")
+                .long("migrate-db-from")
+                .value_name("BACKEND")
+                .help("Copy the existing database of this backend in the datadir into the database of --db-backend before starting, unless already copied.")
+                .possible_values(&["rocksdb", "redb"])
+                .requires("db-backend")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("import-era")
+                .long("import-era")
+                .value_name("DIR")
+                .help("Import the chain from files exported by another node before joining the network.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("checkpoint-state")
+                .long("checkpoint-state")
+                .value_name("FILE_OR_URL")
+                .help("Start from this SSZ encoded finalized state, trusted to be canonical, rather than genesis. Downloaded if an http(s) URL.")
+                .requires("checkpoint-block")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("checkpoint-block")
+                .long("checkpoint-block")
+                .value_name("FILE_OR_URL")
+                .help("The SSZ encoded block of the checkpoint state. Downloaded if an http(s) URL.")
+                .requires("checkpoint-state")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("listen_address")
                 .long("listen-address")
@@ -37,6 +133,145 @@ fn main() {
                 .help("Network listen port for p2p connections.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("boot-nodes")
+                .long("boot-nodes")
+                .value_name("MULTIADDRS")
+                .help("Comma separated addresses of nodes to connect to on startup, from which further peers are discovered.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("peer-hourly-upload-limit")
+                .long("peer-hourly-upload-limit")
+                .value_name("MB")
+                .help("Maximum megabytes of blocks served to a single peer per hour.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("peer-daily-upload-limit")
+                .long("peer-daily-upload-limit")
+                .value_name("MB")
+                .help("Maximum megabytes of blocks served to a single peer per day.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("peer-ban-duration")
+                .long("peer-ban-duration")
+                .value_name("SECONDS")
+                .help("The number of seconds a misbehaving peer is banned for.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("target-peers")
+                .long("target-peers")
+                .value_name("COUNT")
+                .help("The number of peers to maintain connections to.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-requests-per-peer")
+                .long("max-requests-per-peer")
+                .value_name("COUNT")
+                .help("The maximum number of RPC requests awaiting a response from a single peer.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("snappy-compression")
+                .long("snappy-compression")
+                .help("Compress RPC and gossip messages with snappy. Gossip is also published and received uncompressed, for nodes which do not compress."),
+        )
+        .arg(
+            Arg::with_name("max-gossip-size")
+                .long("max-gossip-size")
+                .value_name("BYTES")
+                .help("The maximum size of a decompressed gossip message. Larger compressed messages are rejected before decompression and their sender penalized.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-rpc-size")
+                .long("max-rpc-size")
+                .value_name("BYTES")
+                .help("The maximum size of an RPC message, as received and once decompressed. Larger compressed messages are rejected before decompression and their sender penalized.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-upload-mbps")
+                .long("max-upload-mbps")
+                .value_name("MBPS")
+                .help("Maximum upload rate in megabits per second. Locally produced blocks and attestations take priority.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("disable-gossip-topic")
+                .long("disable-gossip-topic")
+                .value_name("TOPIC")
+                .help("Do not subscribe to, and so neither receive nor forward, a gossip topic, e.g. beacon_attestation.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("subscribe-all-subnets")
+                .long("subscribe-all-subnets")
+                .help("Stay subscribed to every attestation subnet, e.g. for aggregator, research or bootstrap nodes. Uses more memory and bandwidth.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("sync-config")
+                .long("sync-config")
+                .value_name("FILE")
+                .help("A JSON file of sync parameters, e.g. the limits on held gossip blocks and parent lookups. Durations are given in seconds.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("slot-import-tolerance")
+                .long("slot-import-tolerance")
+                .value_name("SLOTS")
+                .help("Start syncing when a peer is more than this many slots ahead.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("slot-import-hysteresis")
+                .long("slot-import-hysteresis")
+                .value_name("SLOTS")
+                .help("Stop syncing only when all peers are within the tolerance minus this many slots.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("epochs-per-batch")
+                .long("epochs-per-batch")
+                .value_name("EPOCHS")
+                .help("The number of epochs of blocks requested from a peer at once whilst syncing.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-pending-batches")
+                .long("max-pending-batches")
+                .value_name("BATCHES")
+                .help("The maximum number of batches downloading or awaiting import at once whilst syncing.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("batch-timeout")
+                .long("batch-timeout")
+                .value_name("SECONDS")
+                .help("Request a batch of blocks again if it is not answered within this many seconds.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("synced-tolerance")
+                .long("synced-tolerance")
+                .value_name("SLOTS")
+                .help("Consider the node synced whilst its head is within this many slots of the network.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-batch-import-failures")
+                .long("max-batch-import-failures")
+                .value_name("FAILURES")
+                .help("Sync to another chain once a batch has failed to import this many times.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("rpc")
                 .long("rpc")
@@ -58,12 +293,111 @@ fn main() {
                 .help("Listen port for RPC endpoint.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("rpc-export-dir")
+                .long("rpc-export-dir")
+                .value_name("DIR")
+                .help("Directory under which chain exports requested through the RPC are written. Exports are refused if not given.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sync-gated-publishing")
+                .long("sync-gated-publishing")
+                .help("Whilst syncing, refuse to serve duties and produce blocks for local validators, and to publish their blocks and attestations which cannot be validated, rather than doing so regardless.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("spec-override")
+                .long("spec-override")
+                .value_name("NAME=VALUE")
+                .help("Override a chain spec constant, e.g. SECONDS_PER_SLOT=3. Requires --unsafe-experimental.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("genesis-time")
+                .long("genesis-time")
+                .value_name("TIME")
+                .help("Override the genesis time with a unix timestamp, `now` or `now+SECONDS`. A relative time is resolved when the node first starts with its datadir, so give the nodes of a devnet the timestamp printed by the genesis-time subcommand.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-skip-slots")
+                .long("max-skip-slots")
+                .value_name("SLOTS")
+                .help("Refuse blocks which skip more than SLOTS slots after their parent, or `none` for no limit. Defaults to 700.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("proposer-re-org")
+                .long("proposer-re-org")
+                .help("When proposing, build on the parent of a head block which arrived late and has few votes, re-orging it out."),
+        )
+        .arg(
+            Arg::with_name("proposer-re-org-threshold")
+                .long("proposer-re-org-threshold")
+                .value_name("PERCENT")
+                .help("Re-org out late head blocks with the votes of less than PERCENT of a slot's committee balance, below 40. Defaults to 20.")
+                .requires("proposer-re-org")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("epoch-processing-budget")
+                .long("epoch-processing-budget")
+                .value_name("MILLIS")
+                .help("Log a breakdown of any epoch transition taking longer than MILLIS milliseconds. Defaults to 1000.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("slow-epoch-dump-dir")
+                .long("slow-epoch-dump-dir")
+                .value_name("DIR")
+                .help("Write the pre-state of the first few epoch transitions exceeding the budget to DIR for profiling. Copies the state before every epoch transition until then.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("unsafe-experimental")
+                .long("unsafe-experimental")
+                .help("Permit options which make the node incompatible with the network. For local experiments only.")
+                .takes_value(false),
+        )
+        .subcommand(
+            SubCommand::with_name("genesis-time")
+                .about("Print the unix timestamp of a genesis time, for starting every node of a devnet with the same --genesis-time.")
+                .arg(
+                    Arg::with_name("TIME")
+                        .help("A unix timestamp, `now` or `now+SECONDS`.")
+                        .required(true)
+                        .index(1),
+                ),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("genesis-time") {
+        let genesis_time = matches.value_of("TIME").expect("TIME is required");
+        match resolve_genesis_time(genesis_time) {
+            Some(genesis_time) => println!("{}", genesis_time),
+            None => {
+                eprintln!("Invalid genesis time: {}", genesis_time);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     // invalid arguments, panic
     let config = ClientConfig::parse_args(matches, &logger).unwrap();
 
-    match run::run_beacon_node(config, &logger) {
+    let crash_reporter = CrashReporter::new(
+        config.data_dir.join("crash_reports"),
+        config.spec.clone(),
+        log_buffer,
+        error_logs.clone(),
+    );
+    crash_reporter.install();
+
+    match run::run_beacon_node(config, &crash_reporter, error_logs, &logger) {
         Ok(_) => {}
         Err(e) => error!(logger, "Beacon node failed because {:?}", e),
     }
@@ -1,14 +1,23 @@
-use client::client_types::TestingClientType;
+#[cfg(feature = "redb")]
+use client::client_types::RedbClientType;
+use client::client_types::{StandardClientType, TestingClientType};
 use client::error;
-use client::{notifier, Client, ClientConfig};
+use client::{notifier, Client, ClientConfig, ClientTypes, CrashReporter};
+use db::DBType;
 use futures::sync::oneshot;
 use futures::Future;
+use logging::LogBuffer;
 use slog::info;
 use std::cell::RefCell;
-use tokio::runtime::Builder;
+use tokio::runtime::{Builder, Runtime};
 
-pub fn run_beacon_node(config: ClientConfig, log: &slog::Logger) -> error::Result<()> {
-    let mut runtime = Builder::new()
+pub fn run_beacon_node(
+    config: ClientConfig,
+    crash_reporter: &CrashReporter,
+    error_logs: LogBuffer,
+    log: &slog::Logger,
+) -> error::Result<()> {
+    let runtime = Builder::new()
         .name_prefix("main-")
         .build()
         .map_err(|e| format!("{:?}", e))?;
@@ -28,12 +37,37 @@ pub fn run_beacon_node(config: ClientConfig, log: &slog::Logger) -> error::Resul
     })
     .map_err(|e| format!("Could not set ctrlc hander: {:?}", e))?;
 
+    match config.db_type {
+        DBType::Memory => {
+            run::<TestingClientType>(config, runtime, ctrlc, crash_reporter, error_logs, log)
+        }
+        DBType::RocksDB => {
+            run::<StandardClientType>(config, runtime, ctrlc, crash_reporter, error_logs, log)
+        }
+        #[cfg(feature = "redb")]
+        DBType::Redb => {
+            run::<RedbClientType>(config, runtime, ctrlc, crash_reporter, error_logs, log)
+        }
+        #[cfg(not(feature = "redb"))]
+        DBType::Redb => Err("The redb database backend is not supported by this build".into()),
+    }
+}
+
+/// Runs a client storing the chain in the database of `T` until ctrl-c is received.
+fn run<T: ClientTypes>(
+    config: ClientConfig,
+    mut runtime: Runtime,
+    ctrlc: oneshot::Receiver<()>,
+    crash_reporter: &CrashReporter,
+    error_logs: LogBuffer,
+    log: &slog::Logger,
+) -> error::Result<()> {
     let (exit_signal, exit) = exit_future::signal();
 
     let executor = runtime.executor();
 
-    // currently testing - using TestingClientType
-    let client: Client<TestingClientType> = Client::new(config, log.clone(), &executor)?;
+    let client: Client<T> = Client::new(config, error_logs, log.clone(), &executor)?;
+    crash_reporter.watch_chain(client.beacon_chain());
     notifier::run(&client, executor, exit);
 
     runtime
@@ -42,6 +76,7 @@ pub fn run_beacon_node(config: ClientConfig, log: &slog::Logger) -> error::Resul
 
     // perform global shutdown operations.
     info!(log, "Shutting down..");
+    client.network.shutdown();
     exit_signal.fire();
     // shutdown the client
     //    client.exit_signal.fire();
@@ -2,15 +2,14 @@ pub mod test_utils;
 mod traits;
 
 use slot_clock::SlotClock;
-use ssz::TreeHash;
 use std::sync::Arc;
-use types::{AttestationData, AttestationDataAndCustodyBit, FreeAttestation, Signature, Slot};
+use types::custody;
+use types::{AttestationData, FreeAttestation, Signature, Slot};
 
 pub use self::traits::{
     BeaconNode, BeaconNodeError, DutiesReader, DutiesReaderError, PublishOutcome, Signer,
 };
 
-const PHASE_0_CUSTODY_BIT: bool = false;
 const DOMAIN_ATTESTATION: u64 = 1;
 
 #[derive(Debug, PartialEq)]
@@ -138,11 +137,8 @@ impl<T: SlotClock, U: BeaconNode, V: DutiesReader, W: Signer> Attester<T, U, V,
     fn sign_attestation_data(&mut self, attestation_data: &AttestationData) -> Option<Signature> {
         self.store_produce(attestation_data);
 
-        let message = AttestationDataAndCustodyBit {
-            data: attestation_data.clone(),
-            custody_bit: PHASE_0_CUSTODY_BIT,
-        }
-        .hash_tree_root();
+        let custody_bit = custody::attester_custody_bit(attestation_data);
+        let message = custody::signing_message(attestation_data, custody_bit);
 
         self.signer
             .sign_attestation_message(&message[..], DOMAIN_ATTESTATION)
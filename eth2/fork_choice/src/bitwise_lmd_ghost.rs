@@ -1,7 +1,9 @@
 //! The optimised bitwise LMD-GHOST fork choice rule.
 extern crate bit_vec;
 
-use crate::{ForkChoice, ForkChoiceError};
+use crate::{
+    apply_proposer_boost, latest_voters, seed_anchor_ancestors, ForkChoice, ForkChoiceError,
+};
 use bit_vec::BitVec;
 use db::{
     stores::{BeaconBlockStore, BeaconStateStore},
@@ -10,7 +12,7 @@ use db::{
 use log::{debug, trace};
 use std::collections::HashMap;
 use std::sync::Arc;
-use types::{BeaconBlock, ChainSpec, Hash256, Slot, SlotHeight};
+use types::{BeaconBlock, BeaconState, ChainSpec, Hash256, Slot, SlotHeight};
 
 //TODO: Pruning - Children
 //TODO: Handle Syncing
@@ -45,6 +47,8 @@ pub struct BitwiseLMDGhost<T: ClientDB + Sized> {
     /// The latest attestation targets as a map of validator index to block hash.
     //TODO: Could this be a fixed size vec
     latest_attestation_targets: HashMap<u64, Hash256>,
+    /// The timely block of the present slot whose branch is boosted, if any.
+    proposer_boost: Option<Hash256>,
     /// Block storage access.
     block_store: Arc<BeaconBlockStore<T>>,
     /// State storage access.
@@ -64,6 +68,7 @@ where
             cache: HashMap::new(),
             ancestors: vec![HashMap::new(); 16],
             latest_attestation_targets: HashMap::new(),
+            proposer_boost: None,
             children: HashMap::new(),
             max_known_height: SlotHeight::new(0),
             block_store,
@@ -93,17 +98,20 @@ where
         let active_validator_indices =
             current_state.get_active_validator_indices(block_slot.epoch(spec.slots_per_epoch));
 
+        let mut total_weight = 0;
         for index in active_validator_indices {
             let balance = std::cmp::min(
                 current_state.validator_balances[index],
                 spec.max_deposit_amount,
             ) / spec.fork_choice_balance_increment;
+            total_weight += balance;
             if balance > 0 {
                 if let Some(target) = self.latest_attestation_targets.get(&(index as u64)) {
                     *latest_votes.entry(*target).or_insert_with(|| 0) += balance;
                 }
             }
         }
+        apply_proposer_boost(&mut latest_votes, self.proposer_boost, total_weight, spec);
         trace!("Latest votes: {:?}", latest_votes);
         Ok(latest_votes)
     }
@@ -145,9 +153,8 @@ where
         if let Some(ancestor) = {
             let ancestor_lookup = self.ancestors
                 [log2_int((block_height - target_height - 1u64).as_u64()) as usize]
-                .get(&block_hash)
-                //TODO: Panic if we can't lookup and fork choice fails
-                .expect("All blocks should be added to the ancestor log lookup table");
+                // ancestors before the history of the anchor are unknown, see `add_anchor`
+                .get(&block_hash)?;
             self.get_ancestor(*ancestor_lookup, target_height, &spec)
         } {
             // add the result to the cache
@@ -268,17 +275,29 @@ impl<T: ClientDB + Sized> ForkChoice for BitwiseLMDGhost<T> {
         for index in 0..16 {
             if parent_height % (1 << index) == 0 {
                 self.ancestors[index].insert(*block_hash, *parent_hash);
-            } else {
-                // TODO: This is unsafe. Will panic if parent_hash doesn't exist. Using it for debugging
-                let parent_ancestor = self.ancestors[index][parent_hash];
+            } else if let Some(parent_ancestor) = self.ancestors[index].get(parent_hash).cloned() {
                 self.ancestors[index].insert(*block_hash, parent_ancestor);
             }
+            // otherwise the ancestor is before the history of the anchor, see `add_anchor`
         }
         // update the max height
         self.max_known_height = std::cmp::max(self.max_known_height, parent_height + 1);
         Ok(())
     }
 
+    fn add_anchor(
+        &mut self,
+        block: &BeaconBlock,
+        block_hash: &Hash256,
+        state: &BeaconState,
+        spec: &ChainSpec,
+    ) -> Result<(), ForkChoiceError> {
+        seed_anchor_ancestors(&mut self.ancestors, block_hash, block.slot, state, spec);
+        self.max_known_height =
+            std::cmp::max(self.max_known_height, block.slot.height(spec.genesis_slot));
+        Ok(())
+    }
+
     fn add_attestation(
         &mut self,
         validator_index: u64,
@@ -323,6 +342,14 @@ impl<T: ClientDB + Sized> ForkChoice for BitwiseLMDGhost<T> {
         Ok(())
     }
 
+    fn latest_voters(&self, block_hash: &Hash256) -> Option<Vec<u64>> {
+        Some(latest_voters(&self.latest_attestation_targets, block_hash))
+    }
+
+    fn set_proposer_boost(&mut self, block_hash: Option<Hash256>) {
+        self.proposer_boost = block_hash;
+    }
+
     /// Perform lmd_ghost on the current chain to find the head.
     fn find_head(
         &mut self,
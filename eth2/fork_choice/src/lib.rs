@@ -27,7 +27,8 @@ pub mod slow_lmd_ghost;
 
 use db::stores::BeaconBlockAtSlotError;
 use db::DBError;
-use types::{BeaconBlock, ChainSpec, Hash256};
+use std::collections::HashMap;
+use types::{BeaconBlock, BeaconState, ChainSpec, Hash256, Slot, SlotHeight};
 
 pub use bitwise_lmd_ghost::BitwiseLMDGhost;
 pub use longest_chain::LongestChain;
@@ -46,6 +47,20 @@ pub trait ForkChoice: Send + Sync {
         block_hash: &Hash256,
         spec: &ChainSpec,
     ) -> Result<(), ForkChoiceError>;
+    /// Called with the block the chain was started from and its state, such as a trusted
+    /// checkpoint, before any other block is added.
+    ///
+    /// The blocks before it are never added, so fork choices which index the ancestors of blocks
+    /// learn them from the block roots held in the state.
+    fn add_anchor(
+        &mut self,
+        _block: &BeaconBlock,
+        _block_hash: &Hash256,
+        _state: &BeaconState,
+        _spec: &ChainSpec,
+    ) -> Result<(), ForkChoiceError> {
+        Ok(())
+    }
     /// Called when an attestation has been added. Allows generic attestation-level data structures to be built for a given fork choice.
     // This can be generalised to a full attestation if required later.
     fn add_attestation(
@@ -54,6 +69,16 @@ pub trait ForkChoice: Send + Sync {
         target_block_hash: &Hash256,
         spec: &ChainSpec,
     ) -> Result<(), ForkChoiceError>;
+    /// Returns the indices of the validators whose latest attestation is for `block_hash`, or
+    /// `None` if the fork choice does not track attestations.
+    fn latest_voters(&self, block_hash: &Hash256) -> Option<Vec<u64>>;
+    /// Sets the block of the present slot, received in time, whose branch is given
+    /// `PROPOSER_SCORE_BOOST` percent of a slot's committee weight, or clears it with `None` once
+    /// the slot has ended.
+    ///
+    /// The boost lets a block built on the parent of a late head, which has no votes yet, win
+    /// over the late head if it has few votes itself.
+    fn set_proposer_boost(&mut self, _block_hash: Option<Hash256>) {}
     /// The fork-choice algorithm to find the current canonical head of the chain.
     // TODO: Remove the justified_start_block parameter and make it internal
     fn find_head(
@@ -63,6 +88,92 @@ pub trait ForkChoice: Send + Sync {
     ) -> Result<Hash256, ForkChoiceError>;
 }
 
+/// The percentage of the weight of a slot's committees given to the block set by
+/// `ForkChoice::set_proposer_boost`.
+pub const PROPOSER_SCORE_BOOST: u64 = 40;
+
+/// Returns the indices of the validators whose latest attestation target is `block_hash`.
+pub(crate) fn latest_voters(
+    latest_attestation_targets: &HashMap<u64, Hash256>,
+    block_hash: &Hash256,
+) -> Vec<u64> {
+    latest_attestation_targets
+        .iter()
+        .filter(|(_, target)| *target == block_hash)
+        .map(|(validator_index, _)| *validator_index)
+        .collect()
+}
+
+/// Adds the proposer boost of `boost_root`, if any, to the weighted `latest_votes`, given the
+/// `total_weight` of the votes of all active validators.
+pub(crate) fn apply_proposer_boost(
+    latest_votes: &mut HashMap<Hash256, u64>,
+    boost_root: Option<Hash256>,
+    total_weight: u64,
+    spec: &ChainSpec,
+) {
+    if let Some(boost_root) = boost_root {
+        let boost = total_weight / spec.slots_per_epoch * PROPOSER_SCORE_BOOST / 100;
+        *latest_votes.entry(boost_root).or_insert(0) += boost;
+    }
+}
+
+/// Returns the roots and heights of the blocks before `anchor_slot` whose roots are held in
+/// `anchor_state`, the state at that slot, newest first.
+///
+/// A slot held a block if its root differs from that of the slot before it, so the block at the
+/// earliest slot held cannot be placed and is omitted.
+fn anchor_ancestors(
+    anchor_slot: Slot,
+    anchor_state: &BeaconState,
+    spec: &ChainSpec,
+) -> Vec<(Hash256, SlotHeight)> {
+    let mut ancestors = vec![];
+    let mut slot = anchor_slot;
+    while slot > spec.genesis_slot {
+        slot = slot - 1;
+        let root = match anchor_state.get_block_root(slot, spec) {
+            Ok(root) => *root,
+            Err(_) => break,
+        };
+        let is_block = if slot == spec.genesis_slot {
+            true
+        } else {
+            match anchor_state.get_block_root(slot - 1, spec) {
+                Ok(previous_root) => *previous_root != root,
+                Err(_) => break,
+            }
+        };
+        if is_block {
+            ancestors.push((root, slot.height(spec.genesis_slot)));
+        }
+    }
+    ancestors
+}
+
+/// Seeds the log lookup table of ancestors of LMD-GHOST with those of `anchor_hash`, the block at
+/// `anchor_slot` the chain was started from, as if every block back to genesis had been added.
+///
+/// `ancestors[i]` maps a block to its latest ancestor at a height which is a multiple of `2^i`.
+/// Ancestors before the block roots held in `anchor_state` are left unknown.
+pub(crate) fn seed_anchor_ancestors(
+    ancestors: &mut [HashMap<Hash256, Hash256>],
+    anchor_hash: &Hash256,
+    anchor_slot: Slot,
+    anchor_state: &BeaconState,
+    spec: &ChainSpec,
+) {
+    let anchor_ancestors = anchor_ancestors(anchor_slot, anchor_state, spec);
+    for (index, table) in ancestors.iter_mut().enumerate() {
+        if let Some((root, _)) = anchor_ancestors
+            .iter()
+            .find(|(_, height)| *height % (1 << index) == 0)
+        {
+            table.insert(*anchor_hash, *root);
+        }
+    }
+}
+
 /// Possible fork choice errors that can occur.
 #[derive(Debug, PartialEq)]
 pub enum ForkChoiceError {
@@ -107,3 +218,39 @@ pub enum ForkChoiceAlgorithm {
     /// An optimised implementation of LMD ghost.
     OptimizedLMDGhost,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::test_utils::TestingBeaconStateBuilder;
+
+    #[test]
+    fn seeds_ancestors_of_anchor_from_block_roots() {
+        let spec = ChainSpec::few_validators();
+        let anchor_slot = spec.genesis_slot + 8;
+        let mut builder = TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec);
+        builder.teleport_to_slot(anchor_slot, &spec);
+        let (mut state, _keypairs) = builder.build();
+
+        // blocks at heights 0, 1, 2, 5 and 6, the other slots skipped
+        let root = |height: u64| Hash256::from_low_u64_be(height + 1);
+        for (height, block_height) in [0, 1, 2, 2, 2, 5, 6, 6].iter().enumerate() {
+            state
+                .set_block_root(
+                    spec.genesis_slot + height as u64,
+                    root(*block_height),
+                    &spec,
+                )
+                .unwrap();
+        }
+
+        let anchor_hash = Hash256::from_low_u64_be(100);
+        let mut ancestors = vec![HashMap::new(); 4];
+        seed_anchor_ancestors(&mut ancestors, &anchor_hash, anchor_slot, &state, &spec);
+
+        assert_eq!(ancestors[0].get(&anchor_hash), Some(&root(6)));
+        assert_eq!(ancestors[1].get(&anchor_hash), Some(&root(6)));
+        assert_eq!(ancestors[2].get(&anchor_hash), Some(&root(0)));
+        assert_eq!(ancestors[3].get(&anchor_hash), Some(&root(0)));
+    }
+}
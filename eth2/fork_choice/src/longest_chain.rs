@@ -49,6 +49,11 @@ impl<T: ClientDB + Sized> ForkChoice for LongestChain<T> {
         Ok(())
     }
 
+    fn latest_voters(&self, _: &Hash256) -> Option<Vec<u64>> {
+        // votes are not tracked
+        None
+    }
+
     fn find_head(&mut self, _: &Hash256, _: &ChainSpec) -> Result<Hash256, ForkChoiceError> {
         let mut head_blocks: Vec<(usize, BeaconBlock)> = vec![];
         /*
@@ -1,6 +1,6 @@
 extern crate db;
 
-use crate::{ForkChoice, ForkChoiceError};
+use crate::{apply_proposer_boost, latest_voters, ForkChoice, ForkChoiceError};
 use db::{
     stores::{BeaconBlockStore, BeaconStateStore},
     ClientDB,
@@ -16,6 +16,8 @@ pub struct SlowLMDGhost<T: ClientDB + Sized> {
     /// The latest attestation targets as a map of validator index to block hash.
     //TODO: Could this be a fixed size vec
     latest_attestation_targets: HashMap<u64, Hash256>,
+    /// The timely block of the present slot whose branch is boosted, if any.
+    proposer_boost: Option<Hash256>,
     /// Stores the children for any given parent.
     children: HashMap<Hash256, Vec<Hash256>>,
     /// Block storage access.
@@ -34,6 +36,7 @@ where
     ) -> Self {
         SlowLMDGhost {
             latest_attestation_targets: HashMap::new(),
+            proposer_boost: None,
             children: HashMap::new(),
             block_store,
             state_store,
@@ -62,17 +65,20 @@ where
         let active_validator_indices =
             current_state.get_active_validator_indices(block_slot.epoch(spec.slots_per_epoch));
 
+        let mut total_weight = 0;
         for index in active_validator_indices {
             let balance = std::cmp::min(
                 current_state.validator_balances[index],
                 spec.max_deposit_amount,
             ) / spec.fork_choice_balance_increment;
+            total_weight += balance;
             if balance > 0 {
                 if let Some(target) = self.latest_attestation_targets.get(&(index as u64)) {
                     *latest_votes.entry(*target).or_insert_with(|| 0) += balance;
                 }
             }
         }
+        apply_proposer_boost(&mut latest_votes, self.proposer_boost, total_weight, spec);
         trace!("Latest votes: {:?}", latest_votes);
         Ok(latest_votes)
     }
@@ -169,6 +175,14 @@ impl<T: ClientDB + Sized> ForkChoice for SlowLMDGhost<T> {
         Ok(())
     }
 
+    fn latest_voters(&self, block_hash: &Hash256) -> Option<Vec<u64>> {
+        Some(latest_voters(&self.latest_attestation_targets, block_hash))
+    }
+
+    fn set_proposer_boost(&mut self, block_hash: Option<Hash256>) {
+        self.proposer_boost = block_hash;
+    }
+
     /// A very inefficient implementation of LMD ghost.
     fn find_head(
         &mut self,
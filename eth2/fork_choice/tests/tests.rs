@@ -14,7 +14,7 @@ extern crate yaml_rust;
 pub use beacon_chain::BeaconChain;
 use bls::Signature;
 use db::stores::{BeaconBlockStore, BeaconStateStore};
-use db::MemoryDB;
+use db::MemoryStore;
 // use env_logger::{Builder, Env};
 use fork_choice::{
     BitwiseLMDGhost, ForkChoice, ForkChoiceAlgorithm, LongestChain, OptimizedLMDGhost, SlowLMDGhost,
@@ -181,6 +181,11 @@ fn test_yaml_vectors(
             }
         }
 
+        // boost a timely block, if given
+        if let Some(id) = test_case["boost"].as_str() {
+            fork_choice.set_proposer_boost(Some(id_to_hash(&id.to_string())));
+        }
+
         // everything is set up, run the fork choice, using genesis as the head
         let head = fork_choice
             .find_head(&genesis_hash.unwrap(), &spec)
@@ -219,8 +224,8 @@ fn load_test_cases_from_yaml(file_path: &str) -> Vec<yaml_rust::Yaml> {
 fn setup_inital_state(
     fork_choice_algo: &ForkChoiceAlgorithm,
     num_validators: usize,
-) -> (Box<ForkChoice>, Arc<BeaconBlockStore<MemoryDB>>, Hash256) {
-    let db = Arc::new(MemoryDB::open());
+) -> (Box<ForkChoice>, Arc<BeaconBlockStore<MemoryStore>>, Hash256) {
+    let db = Arc::new(MemoryStore::open());
     let block_store = Arc::new(BeaconBlockStore::new(db.clone()));
     let state_store = Arc::new(BeaconStateStore::new(db.clone()));
 
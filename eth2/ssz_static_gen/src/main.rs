@@ -0,0 +1,229 @@
+use clap::{App, Arg, SubCommand};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize, Serialize};
+use ssz::{ssz_encode, Decodable, Encodable, TreeHash};
+use std::fs;
+use std::path::Path;
+use std::process;
+use types::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+use types::*;
+
+/// A file of test cases for a single type, in the layout of the `ssz_static` spec tests.
+#[derive(Serialize, Deserialize)]
+struct TestSuite<T> {
+    title: String,
+    summary: String,
+    test_suite: String,
+    test_cases: Vec<TestCase<T>>,
+}
+
+/// A value, its SSZ serialization and its tree hash root, both `0x`-prefixed hex.
+#[derive(Serialize, Deserialize)]
+struct TestCase<T> {
+    #[serde(rename = "type")]
+    type_name: String,
+    value: T,
+    serialized: String,
+    root: String,
+}
+
+/// Calls `$function::<T>(name, args..)` for each type that fixtures are generated for.
+macro_rules! for_each_type {
+    ($function: ident, $($arg: expr),*) => {
+        $function::<Attestation>("Attestation", $($arg),*)?;
+        $function::<AttestationData>("AttestationData", $($arg),*)?;
+        $function::<AttestationDataAndCustodyBit>("AttestationDataAndCustodyBit", $($arg),*)?;
+        $function::<AttesterSlashing>("AttesterSlashing", $($arg),*)?;
+        $function::<BeaconBlock>("BeaconBlock", $($arg),*)?;
+        $function::<BeaconBlockBody>("BeaconBlockBody", $($arg),*)?;
+        $function::<BeaconBlockHeader>("BeaconBlockHeader", $($arg),*)?;
+        $function::<BeaconState>("BeaconState", $($arg),*)?;
+        $function::<Crosslink>("Crosslink", $($arg),*)?;
+        $function::<Deposit>("Deposit", $($arg),*)?;
+        $function::<DepositData>("DepositData", $($arg),*)?;
+        $function::<DepositInput>("DepositInput", $($arg),*)?;
+        $function::<Eth1Data>("Eth1Data", $($arg),*)?;
+        $function::<Eth1DataVote>("Eth1DataVote", $($arg),*)?;
+        $function::<Fork>("Fork", $($arg),*)?;
+        $function::<HistoricalBatch>("HistoricalBatch", $($arg),*)?;
+        $function::<PendingAttestation>("PendingAttestation", $($arg),*)?;
+        $function::<ProposerSlashing>("ProposerSlashing", $($arg),*)?;
+        $function::<SlashableAttestation>("SlashableAttestation", $($arg),*)?;
+        $function::<Transfer>("Transfer", $($arg),*)?;
+        $function::<Validator>("Validator", $($arg),*)?;
+        $function::<VoluntaryExit>("VoluntaryExit", $($arg),*)?;
+    };
+}
+
+fn main() {
+    let matches = App::new("Lighthouse SSZ Static Fixture Generator")
+        .version("0.0.1")
+        .author("Sigma Prime <contact@sigmaprime.io>")
+        .about("Generates and checks ssz_static test fixtures for the eth2 types.")
+        .subcommand(
+            SubCommand::with_name("generate")
+                .about("Writes a YAML file of test cases for each type.")
+                .arg(
+                    Arg::with_name("output_dir")
+                        .long("output-dir")
+                        .short("o")
+                        .value_name("DIR")
+                        .help("The directory to write the YAML files to.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .long("count")
+                        .short("n")
+                        .value_name("COUNT")
+                        .help("The number of test cases generated for each type.")
+                        .default_value("8"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks the serialization and root of each test case against ours.")
+                .arg(
+                    Arg::with_name("input_dir")
+                        .long("input-dir")
+                        .short("i")
+                        .value_name("DIR")
+                        .help("A directory of YAML files, as written by `generate`.")
+                        .required(true),
+                ),
+        )
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("generate", Some(matches)) => {
+            let dir = Path::new(matches.value_of("output_dir").unwrap());
+            matches
+                .value_of("count")
+                .unwrap()
+                .parse::<u64>()
+                .map_err(|e| format!("Invalid count: {:?}", e))
+                .and_then(|count| generate_all(dir, count))
+        }
+        ("check", Some(matches)) => check_all(Path::new(matches.value_of("input_dir").unwrap())),
+        _ => Err("No subcommand given, see --help".to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn generate_all(dir: &Path, count: u64) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Unable to create {:?}: {:?}", dir, e))?;
+    for_each_type!(generate, dir, count);
+    Ok(())
+}
+
+fn check_all(dir: &Path) -> Result<(), String> {
+    let mut failures = vec![];
+    for_each_type!(check, dir, &mut failures);
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n"))
+    }
+}
+
+/// Writes `count` test cases for `T` to `dir/<name>.yaml`.
+///
+/// Values are generated from fixed seeds, so the same cases are written by each run.
+fn generate<T>(name: &str, dir: &Path, count: u64) -> Result<(), String>
+where
+    T: TestRandom<XorShiftRng> + Encodable + TreeHash + Serialize,
+{
+    let test_cases = (0..count)
+        .map(|seed| {
+            let value: T = random_from_seed(seed);
+            TestCase {
+                type_name: name.to_string(),
+                serialized: to_hex(&ssz_encode(&value)),
+                root: to_hex(&value.hash_tree_root()),
+                value,
+            }
+        })
+        .collect();
+    let suite = TestSuite {
+        title: format!("ssz_static {}", name),
+        summary: format!("Serialization and tree hash root of {}", name),
+        test_suite: "ssz_static".to_string(),
+        test_cases,
+    };
+
+    let path = dir.join(format!("{}.yaml", name));
+    let yaml = serde_yaml::to_string(&suite)
+        .map_err(|e| format!("Unable to serialize {}: {:?}", name, e))?;
+    fs::write(&path, yaml).map_err(|e| format!("Unable to write {:?}: {:?}", path, e))?;
+    println!("Wrote {} test cases to {}", count, path.display());
+    Ok(())
+}
+
+/// Checks that each test case of `dir/<name>.yaml` decodes as `T`, that it re-encodes to the
+/// same bytes and that its root matches ours, adding a message to `failures` for each which does
+/// not. A missing file is skipped.
+fn check<T>(name: &str, dir: &Path, failures: &mut Vec<String>) -> Result<(), String>
+where
+    T: Encodable + TreeHash + Decodable,
+{
+    let path = dir.join(format!("{}.yaml", name));
+    if !path.exists() {
+        println!("Skipping {}: no test cases", name);
+        return Ok(());
+    }
+    let suite: TestSuite<serde_yaml::Value> = read_yaml(&path)?;
+
+    for (i, test_case) in suite.test_cases.iter().enumerate() {
+        let serialized = from_hex(&test_case.serialized)?;
+        let failure = match T::ssz_decode(&serialized, 0) {
+            Ok((value, index)) => {
+                let root = to_hex(&value.hash_tree_root());
+                if index != serialized.len() {
+                    Some(format!("{} trailing bytes", serialized.len() - index))
+                } else if ssz_encode(&value) != serialized {
+                    Some("re-encoding differs".to_string())
+                } else if root != test_case.root {
+                    Some(format!("root is {}, expected {}", root, test_case.root))
+                } else {
+                    None
+                }
+            }
+            Err(e) => Some(format!("unable to decode: {:?}", e)),
+        };
+        if let Some(failure) = failure {
+            failures.push(format!("{} case {}: {}", name, i, failure));
+        }
+    }
+
+    println!("Checked {} test cases of {}", suite.test_cases.len(), name);
+    Ok(())
+}
+
+fn read_yaml<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Unable to open {:?}: {:?}", path, e))?;
+    serde_yaml::from_reader(file).map_err(|e| format!("Unable to parse {:?}: {:?}", path, e))
+}
+
+/// Returns a random value, generated from `seed` as by the `ssz_tests!` property tests.
+fn random_from_seed<T: TestRandom<XorShiftRng>>(seed: u64) -> T {
+    let mut seed_bytes = [0; 16];
+    seed_bytes[0..8].copy_from_slice(&seed.to_le_bytes());
+    seed_bytes[8..16].copy_from_slice(&(!seed).to_le_bytes());
+
+    let mut rng = XorShiftRng::from_seed(seed_bytes);
+    T::random_for_test(&mut rng)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn from_hex(string: &str) -> Result<Vec<u8>, String> {
+    let string = string.trim_start_matches("0x");
+    hex::decode(string).map_err(|e| format!("Invalid hex {}: {:?}", string, e))
+}
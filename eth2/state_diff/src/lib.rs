@@ -0,0 +1,155 @@
+/// Compares two values field-by-field, e.g. the post-states of a block computed by two clients,
+/// to find the fields in which they diverge.
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+/// A field whose value differs between the two values compared.
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    /// The path of the field, e.g. `validator_registry[3].slashed`.
+    pub path: String,
+    /// The value of the field in the left value, or `None` if it is absent.
+    pub left: Option<String>,
+    /// The value of the field in the right value, or `None` if it is absent.
+    pub right: Option<String>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let absent = "<absent>".to_string();
+        write!(
+            f,
+            "{}: {} != {}",
+            self.path,
+            self.left.as_ref().unwrap_or(&absent),
+            self.right.as_ref().unwrap_or(&absent)
+        )
+    }
+}
+
+/// Returns the differences between each field of `left` and `right`, in the order the fields are
+/// declared.
+///
+/// Structs are compared field-by-field and lists element-by-element, so only the innermost
+/// differing fields are returned.
+pub fn diff<T: Serialize>(left: &T, right: &T) -> Result<Vec<Difference>, String> {
+    let left = serde_json::to_value(left).map_err(|e| format!("{:?}", e))?;
+    let right = serde_json::to_value(right).map_err(|e| format!("{:?}", e))?;
+
+    let mut differences = vec![];
+    diff_values("", Some(&left), Some(&right), &mut differences);
+    Ok(differences)
+}
+
+fn diff_values(
+    path: &str,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    differences: &mut Vec<Difference>,
+) {
+    match (left, right) {
+        (Some(Value::Object(left)), Some(Value::Object(right))) => {
+            for (key, left_value) in left {
+                diff_values(
+                    &field_path(path, key),
+                    Some(left_value),
+                    right.get(key),
+                    differences,
+                );
+            }
+            for (key, right_value) in right {
+                if !left.contains_key(key) {
+                    diff_values(&field_path(path, key), None, Some(right_value), differences);
+                }
+            }
+        }
+        (Some(Value::Array(left)), Some(Value::Array(right))) => {
+            if left.len() != right.len() {
+                differences.push(Difference {
+                    path: format!("{}.len()", path),
+                    left: Some(left.len().to_string()),
+                    right: Some(right.len().to_string()),
+                });
+            }
+            for i in 0..std::cmp::max(left.len(), right.len()) {
+                diff_values(
+                    &format!("{}[{}]", path, i),
+                    left.get(i),
+                    right.get(i),
+                    differences,
+                );
+            }
+        }
+        (left, right) => {
+            if left != right {
+                differences.push(Difference {
+                    path: path.to_string(),
+                    left: left.map(Value::to_string),
+                    right: right.map(Value::to_string),
+                });
+            }
+        }
+    }
+}
+
+fn field_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Serialize;
+
+    #[derive(Serialize)]
+    struct Validator {
+        balance: u64,
+        slashed: bool,
+    }
+
+    #[derive(Serialize)]
+    struct State {
+        slot: u64,
+        validators: Vec<Validator>,
+    }
+
+    fn state(slot: u64, balances: &[u64]) -> State {
+        State {
+            slot,
+            validators: balances
+                .iter()
+                .map(|&balance| Validator {
+                    balance,
+                    slashed: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn identical_values_have_no_differences() {
+        assert_eq!(diff(&state(1, &[32, 32]), &state(1, &[32, 32])), Ok(vec![]));
+    }
+
+    #[test]
+    fn reports_innermost_differing_fields() {
+        let differences = diff(&state(1, &[32, 31]), &state(2, &[32, 32, 16])).unwrap();
+        let paths: Vec<&str> = differences.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "slot",
+                "validators.len()",
+                "validators[1].balance",
+                "validators[2]"
+            ]
+        );
+        assert_eq!(differences[2].left, Some("31".to_string()));
+        assert_eq!(differences[3].left, None);
+    }
+}
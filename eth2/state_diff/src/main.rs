@@ -0,0 +1,99 @@
+use clap::{App, Arg};
+use ssz::{Decodable, TreeHash};
+use state_diff::diff;
+use std::fs;
+use std::process;
+use types::{BeaconState, Hash256};
+
+fn main() {
+    let matches = App::new("Lighthouse State Diff")
+        .version("0.0.1")
+        .author("Sigma Prime <contact@sigmaprime.io>")
+        .about("Prints the fields in which two SSZ-encoded BeaconStates differ.")
+        .arg(
+            Arg::with_name("left")
+                .value_name("LEFT")
+                .help("The first BeaconState SSZ file.")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("right")
+                .value_name("RIGHT")
+                .help("The second BeaconState SSZ file.")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .short("n")
+                .value_name("COUNT")
+                .help("The maximum number of differences printed.")
+                .default_value("100"),
+        )
+        .get_matches();
+
+    let result = matches
+        .value_of("limit")
+        .unwrap()
+        .parse::<usize>()
+        .map_err(|e| format!("Invalid limit: {:?}", e))
+        .and_then(|limit| {
+            run(
+                matches.value_of("left").unwrap(),
+                matches.value_of("right").unwrap(),
+                limit,
+            )
+        });
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Prints the roots of both states and the fields in which they differ, returning `true` if they
+/// are identical.
+fn run(left_path: &str, right_path: &str, limit: usize) -> Result<bool, String> {
+    let left = read_state(left_path)?;
+    let right = read_state(right_path)?;
+
+    println!("{}: {:?}", left_path, state_root(&left));
+    println!("{}: {:?}", right_path, state_root(&right));
+
+    let differences = diff(&left, &right)?;
+    if differences.is_empty() {
+        println!("States are identical");
+        return Ok(true);
+    }
+
+    println!("{} fields differ:", differences.len());
+    for difference in differences.iter().take(limit) {
+        println!("  {}", difference);
+    }
+    if differences.len() > limit {
+        println!("  ... and {} more", differences.len() - limit);
+    }
+    Ok(false)
+}
+
+fn read_state(path: &str) -> Result<BeaconState, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Unable to read {}: {:?}", path, e))?;
+    let (state, index) = BeaconState::ssz_decode(&bytes, 0)
+        .map_err(|e| format!("Unable to decode {}: {:?}", path, e))?;
+    if index != bytes.len() {
+        return Err(format!(
+            "{} has {} trailing bytes",
+            path,
+            bytes.len() - index
+        ));
+    }
+    Ok(state)
+}
+
+fn state_root(state: &BeaconState) -> Hash256 {
+    Hash256::from_slice(&state.hash_tree_root())
+}
@@ -10,7 +10,15 @@ pub mod per_slot_processing;
 pub use get_genesis_state::get_genesis_state;
 pub use per_block_processing::{
     errors::{BlockInvalid, BlockProcessingError},
-    per_block_processing, per_block_processing_without_verifying_block_signature,
+    per_block_processing, per_block_processing_with_verified_block_signature,
+    per_block_processing_with_verified_deposits,
+    per_block_processing_without_verifying_block_signature, verify_block_signatures,
+    VerifiedDeposits,
+};
+pub use per_epoch_processing::{
+    errors::EpochProcessingError, per_epoch_processing, per_epoch_processing_with_timings,
+    timings::EpochProcessingTimings,
+};
+pub use per_slot_processing::{
+    per_slot_processing, per_slot_processing_with_timings, Error as SlotProcessingError,
 };
-pub use per_epoch_processing::{errors::EpochProcessingError, per_epoch_processing};
-pub use per_slot_processing::{per_slot_processing, Error as SlotProcessingError};
@@ -1,6 +1,8 @@
 use self::verify_proposer_slashing::verify_proposer_slashing;
 use crate::common::slash_validator;
-use errors::{BlockInvalid as Invalid, BlockProcessingError as Error, IntoWithIndex};
+use errors::{
+    BlockInvalid as Invalid, BlockProcessingError as Error, DepositInvalid, IntoWithIndex,
+};
 use rayon::prelude::*;
 use ssz::{SignedRoot, TreeHash};
 use types::*;
@@ -8,14 +10,22 @@ use types::*;
 pub use self::verify_attester_slashing::{
     gather_attester_slashing_indices, verify_attester_slashing,
 };
-pub use validate_attestation::{validate_attestation, validate_attestation_without_signature};
-pub use verify_deposit::{get_existing_validator_index, verify_deposit, verify_deposit_index};
+pub use validate_attestation::{
+    validate_attestation, validate_attestation_for_gossip, validate_attestation_without_signature,
+};
+pub use verified_deposits::VerifiedDeposits;
+pub use verify_deposit::{
+    get_deposit_leaf, get_existing_validator_index, verify_deposit, verify_deposit_index,
+    verify_deposit_without_proof_of_possession,
+};
 pub use verify_exit::verify_exit;
 pub use verify_slashable_attestation::verify_slashable_attestation;
 pub use verify_transfer::{execute_transfer, verify_transfer};
 
 pub mod errors;
+pub mod tests;
 mod validate_attestation;
+mod verified_deposits;
 mod verify_attester_slashing;
 mod verify_deposit;
 mod verify_exit;
@@ -39,7 +49,7 @@ pub fn per_block_processing(
     block: &BeaconBlock,
     spec: &ChainSpec,
 ) -> Result<(), Error> {
-    per_block_processing_signature_optional(state, block, true, spec)
+    per_block_processing_signature_optional(state, block, true, None, spec)
 }
 
 /// Updates the state for a new block, whilst validating that the block is valid, without actually
@@ -54,11 +64,45 @@ pub fn per_block_processing_without_verifying_block_signature(
     block: &BeaconBlock,
     spec: &ChainSpec,
 ) -> Result<(), Error> {
-    per_block_processing_signature_optional(state, block, false, spec)
+    per_block_processing_signature_optional(state, block, false, None, spec)
+}
+
+/// Updates the state for a new block, whilst validating that the block is valid, skipping the
+/// proof-of-possession check for any deposit already present in `verified_deposits`.
+///
+/// Returns `Ok(())` if the block is valid and the state was successfully updated. Otherwise
+/// returns an error describing why the block was invalid or how the function failed to execute.
+///
+/// Spec v0.5.0
+pub fn per_block_processing_with_verified_deposits(
+    state: &mut BeaconState,
+    block: &BeaconBlock,
+    verified_deposits: &VerifiedDeposits,
+    spec: &ChainSpec,
+) -> Result<(), Error> {
+    per_block_processing_signature_optional(state, block, true, Some(verified_deposits), spec)
+}
+
+/// Updates the state for a new block whose proposer signature has already been verified, e.g. by
+/// `verify_block_signatures`, skipping the proof-of-possession check for any deposit already
+/// present in `verified_deposits`.
+///
+/// Returns `Ok(())` if the block is valid and the state was successfully updated. Otherwise
+/// returns an error describing why the block was invalid or how the function failed to execute.
+///
+/// Spec v0.5.0
+pub fn per_block_processing_with_verified_block_signature(
+    state: &mut BeaconState,
+    block: &BeaconBlock,
+    verified_deposits: &VerifiedDeposits,
+    spec: &ChainSpec,
+) -> Result<(), Error> {
+    per_block_processing_signature_optional(state, block, false, Some(verified_deposits), spec)
 }
 
 /// Updates the state for a new block, whilst validating that the block is valid, optionally
-/// checking the block proposer signature.
+/// checking the block proposer signature and optionally skipping the proof-of-possession check
+/// for pre-verified deposits.
 ///
 /// Returns `Ok(())` if the block is valid and the state was successfully updated. Otherwise
 /// returns an error describing why the block was invalid or how the function failed to execute.
@@ -68,6 +112,7 @@ fn per_block_processing_signature_optional(
     mut state: &mut BeaconState,
     block: &BeaconBlock,
     should_verify_block_signature: bool,
+    verified_deposits: Option<&VerifiedDeposits>,
     spec: &ChainSpec,
 ) -> Result<(), Error> {
     process_block_header(state, block, spec)?;
@@ -84,7 +129,12 @@ fn per_block_processing_signature_optional(
     process_proposer_slashings(&mut state, &block.body.proposer_slashings, spec)?;
     process_attester_slashings(&mut state, &block.body.attester_slashings, spec)?;
     process_attestations(&mut state, &block.body.attestations, spec)?;
-    process_deposits(&mut state, &block.body.deposits, spec)?;
+    process_deposits_with_verified_deposits(
+        &mut state,
+        &block.body.deposits,
+        verified_deposits,
+        spec,
+    )?;
     process_exits(&mut state, &block.body.voluntary_exits, spec)?;
     process_transfers(&mut state, &block.body.transfers, spec)?;
 
@@ -141,6 +191,29 @@ pub fn verify_block_signature(
     Ok(())
 }
 
+/// Verifies the signatures of several blocks of the current epoch of `state` in parallel, each
+/// against the proposer of its slot.
+///
+/// The current epoch cache of `state` must be built. Returns the first error found, which may not
+/// be that of the earliest invalid block.
+pub fn verify_block_signatures(
+    state: &BeaconState,
+    blocks: &[BeaconBlock],
+    spec: &ChainSpec,
+) -> Result<(), Error> {
+    let current_epoch = state.current_epoch(spec);
+    verify!(
+        blocks
+            .iter()
+            .all(|block| block.slot.epoch(spec.slots_per_epoch) == current_epoch),
+        Invalid::StateSlotMismatch
+    );
+
+    blocks
+        .par_iter()
+        .try_for_each(|block| verify_block_signature(state, block, spec))
+}
+
 /// Verifies the `randao_reveal` against the block's proposer pubkey and updates
 /// `state.latest_randao_mixes`.
 ///
@@ -345,20 +418,65 @@ pub fn process_deposits(
     state: &mut BeaconState,
     deposits: &[Deposit],
     spec: &ChainSpec,
+) -> Result<(), Error> {
+    process_deposits_with_verified_deposits(state, deposits, None, spec)
+}
+
+/// Validates each `Deposit` and updates the state, short-circuiting on an invalid object.
+///
+/// A deposit to a public key already in the registry tops up the balance of that validator. The
+/// proof-of-possession of a top-up is not checked, so it is credited even if its signature is
+/// invalid. A deposit for a new validator must have a valid proof-of-possession, which is not
+/// checked again if the deposit is present in `verified_deposits`.
+///
+/// Spec v0.5.0
+fn process_deposits_with_verified_deposits(
+    state: &mut BeaconState,
+    deposits: &[Deposit],
+    verified_deposits: Option<&VerifiedDeposits>,
+    spec: &ChainSpec,
 ) -> Result<(), Error> {
     verify!(
         deposits.len() as u64 <= spec.max_deposits,
         Invalid::MaxDepositsExceeded
     );
 
-    // Verify deposits in parallel.
-    deposits
+    let epoch = state.slot.epoch(spec.slots_per_epoch);
+
+    // The pubkey cache is used to find the deposits which top up an existing validator.
+    state.update_pubkey_cache()?;
+
+    // Verify deposits in parallel, collecting whether each has a valid proof-of-possession.
+    //
+    // Deposits to validators added earlier in this block are found to be top-ups only whilst
+    // updating the state, so their proofs-of-possession are checked here but ignored there.
+    let proofs_of_possession_valid = deposits
         .par_iter()
         .enumerate()
-        .try_for_each(|(i, deposit)| {
-            verify_deposit(state, deposit, VERIFY_DEPOSIT_MERKLE_PROOFS, spec)
-                .map_err(|e| e.into_with_index(i))
-        })?;
+        .map(|(i, deposit)| -> Result<bool, Error> {
+            verify_deposit_without_proof_of_possession(
+                state,
+                deposit,
+                VERIFY_DEPOSIT_MERKLE_PROOFS,
+                spec,
+            )
+            .map_err(|e| e.into_with_index(i))?;
+
+            let is_top_up = state
+                .get_validator_index(&deposit.deposit_data.deposit_input.pubkey)?
+                .is_some();
+            let already_verified = verified_deposits.map_or(false, |verified| {
+                verified.is_verified(deposit, epoch, &state.fork, spec)
+            });
+
+            Ok(is_top_up
+                || already_verified
+                || deposit
+                    .deposit_data
+                    .deposit_input
+                    .validate_proof_of_possession(epoch, &state.fork, spec))
+        })
+        .collect::<Result<Vec<bool>, Error>>()?;
 
     // Check `state.deposit_index` and update the state in series.
     for (i, deposit) in deposits.iter().enumerate() {
@@ -385,6 +503,11 @@ pub fn process_deposits(
                 deposit_data.amount
             );
         } else {
+            verify!(
+                proofs_of_possession_valid[i],
+                Invalid::DepositInvalid(i, DepositInvalid::BadProofOfPossession)
+            );
+
             // Create a new validator.
             let validator = Validator {
                 pubkey: deposit_input.pubkey.clone(),
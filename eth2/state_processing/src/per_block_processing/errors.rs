@@ -122,6 +122,8 @@ pub enum AttestationInvalid {
     },
     /// Attestation slot is too far in the past to be included in a block.
     IncludedTooLate { state: Slot, attestation: Slot },
+    /// Attestation slot is after the slot of the state.
+    FutureSlot { state: Slot, attestation: Slot },
     /// Attestation justified epoch does not match the states current or previous justified epoch.
     ///
     /// `is_current` is `true` if the attestation was compared to the
@@ -171,6 +173,27 @@ pub enum AttestationInvalid {
 impl_from_beacon_state_error!(AttestationValidationError);
 impl_into_with_index_with_beacon_error!(AttestationValidationError, AttestationInvalid);
 
+impl AttestationValidationError {
+    /// Returns `true` if the attestation may be valid against the state of another chain, failing
+    /// only because this state lacks the blocks, committees or validators it references, as when
+    /// our chain lags the network's.
+    pub fn is_state_dependent(&self) -> bool {
+        match self {
+            AttestationValidationError::BeaconStateError(_) => true,
+            AttestationValidationError::Invalid(invalid) => match invalid {
+                AttestationInvalid::IncludedTooEarly { .. }
+                | AttestationInvalid::FutureSlot { .. }
+                | AttestationInvalid::WrongJustifiedEpoch { .. }
+                | AttestationInvalid::WrongJustifiedRoot { .. }
+                | AttestationInvalid::BadPreviousCrosslink
+                | AttestationInvalid::NoCommitteeForShard { .. }
+                | AttestationInvalid::UnknownValidator(_) => true,
+                _ => false,
+            },
+        }
+    }
+}
+
 /*
  * `AttesterSlashing` Validation
  */
@@ -0,0 +1,308 @@
+#![cfg(test)]
+use super::errors::{
+    AttestationInvalid, AttestationValidationError, BlockInvalid, BlockProcessingError,
+    DepositInvalid,
+};
+use super::{
+    process_deposits, validate_attestation, validate_attestation_for_gossip,
+    verify_block_signatures,
+};
+use types::test_utils::{
+    TestingAttestationBuilder, TestingBeaconBlockBuilder, TestingBeaconStateBuilder,
+    TestingDepositBuilder,
+};
+use types::*;
+
+const VALIDATOR_COUNT: usize = 8;
+
+fn state(spec: &ChainSpec) -> (BeaconState, Vec<Keypair>) {
+    let builder = TestingBeaconStateBuilder::from_deterministic_keypairs(VALIDATOR_COUNT, spec);
+    builder.build()
+}
+
+fn deposit(state: &BeaconState, keypair: &Keypair, amount: u64, spec: &ChainSpec) -> Deposit {
+    let mut builder = TestingDepositBuilder::new(keypair.pk.clone(), amount);
+    builder.set_index(state.deposit_index);
+    builder.sign(keypair, state.current_epoch(spec), &state.fork, spec);
+    builder.build()
+}
+
+/// Returns a block at `slot` of the current epoch of `state`, signed by `keypairs[signer]`, or by
+/// its proposer if `signer` is `None`.
+fn block(
+    state: &BeaconState,
+    keypairs: &[Keypair],
+    slot: Slot,
+    signer: Option<usize>,
+    spec: &ChainSpec,
+) -> BeaconBlock {
+    let proposer = state
+        .get_beacon_proposer_index(slot, RelativeEpoch::Current, spec)
+        .unwrap();
+    let signer = signer.unwrap_or(proposer);
+    let mut builder = TestingBeaconBlockBuilder::new(spec);
+    builder.set_slot(slot);
+    builder.build(&keypairs[signer].sk, &state.fork, spec)
+}
+
+#[test]
+fn verifies_the_signatures_of_several_blocks() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, keypairs) = state(&spec);
+    state
+        .build_epoch_cache(RelativeEpoch::Current, &spec)
+        .unwrap();
+    let blocks: Vec<BeaconBlock> = (1..4)
+        .map(|i| block(&state, &keypairs, state.slot + i, None, &spec))
+        .collect();
+
+    assert_eq!(verify_block_signatures(&state, &blocks, &spec), Ok(()));
+}
+
+#[test]
+fn rejects_blocks_with_a_bad_signature() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, keypairs) = state(&spec);
+    state
+        .build_epoch_cache(RelativeEpoch::Current, &spec)
+        .unwrap();
+    let slot = state.slot + 2;
+    let proposer = state
+        .get_beacon_proposer_index(slot, RelativeEpoch::Current, &spec)
+        .unwrap();
+    let blocks = vec![
+        block(&state, &keypairs, state.slot + 1, None, &spec),
+        block(
+            &state,
+            &keypairs,
+            slot,
+            Some((proposer + 1) % VALIDATOR_COUNT),
+            &spec,
+        ),
+    ];
+
+    assert_eq!(
+        verify_block_signatures(&state, &blocks, &spec),
+        Err(BlockProcessingError::Invalid(BlockInvalid::BadSignature))
+    );
+}
+
+#[test]
+fn rejects_blocks_of_another_epoch() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, keypairs) = state(&spec);
+    state
+        .build_epoch_cache(RelativeEpoch::Current, &spec)
+        .unwrap();
+    let mut blocks = vec![block(&state, &keypairs, state.slot + 1, None, &spec)];
+    blocks[0].slot += spec.slots_per_epoch;
+
+    assert_eq!(
+        verify_block_signatures(&state, &blocks, &spec),
+        Err(BlockProcessingError::Invalid(
+            BlockInvalid::StateSlotMismatch
+        ))
+    );
+}
+
+#[test]
+fn tops_up_an_existing_validator() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, keypairs) = state(&spec);
+    let balance = state.validator_balances[0];
+    let deposit = deposit(&state, &keypairs[0], 1_000, &spec);
+
+    process_deposits(&mut state, &[deposit], &spec).unwrap();
+
+    assert_eq!(state.validator_registry.len(), VALIDATOR_COUNT);
+    assert_eq!(state.validator_balances[0], balance + 1_000);
+}
+
+#[test]
+fn credits_a_top_up_with_an_invalid_proof_of_possession() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, keypairs) = state(&spec);
+    let balance = state.validator_balances[0];
+    let mut deposit = deposit(&state, &keypairs[0], 1_000, &spec);
+    deposit.deposit_data.deposit_input.proof_of_possession = Signature::empty_signature();
+
+    process_deposits(&mut state, &[deposit], &spec).unwrap();
+
+    assert_eq!(state.validator_registry.len(), VALIDATOR_COUNT);
+    assert_eq!(state.validator_balances[0], balance + 1_000);
+}
+
+#[test]
+fn credits_a_top_up_of_a_validator_added_in_the_same_block() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, _keypairs) = state(&spec);
+    let keypair = Keypair::random();
+    let new_validator = deposit(&state, &keypair, spec.max_deposit_amount, &spec);
+    let mut top_up = deposit(&state, &keypair, 1_000, &spec);
+    top_up.index = state.deposit_index + 1;
+    top_up.deposit_data.deposit_input.proof_of_possession = Signature::empty_signature();
+
+    process_deposits(&mut state, &[new_validator, top_up], &spec).unwrap();
+
+    assert_eq!(state.validator_registry.len(), VALIDATOR_COUNT + 1);
+    assert_eq!(
+        state.validator_balances[VALIDATOR_COUNT],
+        spec.max_deposit_amount + 1_000
+    );
+}
+
+#[test]
+fn adds_a_new_validator() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, _keypairs) = state(&spec);
+    let deposit = deposit(&state, &Keypair::random(), spec.max_deposit_amount, &spec);
+
+    process_deposits(&mut state, &[deposit], &spec).unwrap();
+
+    assert_eq!(state.validator_registry.len(), VALIDATOR_COUNT + 1);
+    assert_eq!(
+        state.validator_balances[VALIDATOR_COUNT],
+        spec.max_deposit_amount
+    );
+}
+
+#[test]
+fn rejects_a_new_validator_with_an_invalid_proof_of_possession() {
+    let spec = ChainSpec::few_validators();
+    let (mut state, _keypairs) = state(&spec);
+    let mut deposit = deposit(&state, &Keypair::random(), spec.max_deposit_amount, &spec);
+    deposit.deposit_data.deposit_input.proof_of_possession = Signature::empty_signature();
+
+    assert_eq!(
+        process_deposits(&mut state, &[deposit], &spec),
+        Err(BlockProcessingError::Invalid(BlockInvalid::DepositInvalid(
+            0,
+            DepositInvalid::BadProofOfPossession
+        )))
+    );
+    assert_eq!(state.validator_registry.len(), VALIDATOR_COUNT);
+}
+
+/// Returns an attestation at `slot`, before the slot of `state`, signed by its whole committee.
+fn attestation(
+    state: &BeaconState,
+    keypairs: &[Keypair],
+    slot: Slot,
+    spec: &ChainSpec,
+) -> Attestation {
+    let committee = state.get_crosslink_committees_at_slot(slot, spec).unwrap()[0].clone();
+    let mut builder =
+        TestingAttestationBuilder::new(state, &committee.committee, slot, committee.shard, spec);
+    let secret_keys: Vec<&SecretKey> = committee
+        .committee
+        .iter()
+        .map(|&index| &keypairs[index].sk)
+        .collect();
+    builder.sign(&committee.committee, &secret_keys, &state.fork, spec);
+    builder.build()
+}
+
+#[test]
+fn gossip_attestations_are_valid_before_they_may_be_included() {
+    let spec = ChainSpec::few_validators();
+    let mut builder =
+        TestingBeaconStateBuilder::from_deterministic_keypairs(VALIDATOR_COUNT, &spec);
+    builder.teleport_to_slot(spec.genesis_slot + spec.slots_per_epoch * 4 + 2, &spec);
+    let (mut state, keypairs) = builder.build();
+    state
+        .build_epoch_cache(RelativeEpoch::Previous, &spec)
+        .unwrap();
+    state
+        .build_epoch_cache(RelativeEpoch::Current, &spec)
+        .unwrap();
+
+    // an attestation of the last slot is propagated, but is not yet includable
+    let fresh = attestation(&state, &keypairs, state.slot - 1, &spec);
+    assert_eq!(
+        validate_attestation_for_gossip(&state, &fresh, &spec),
+        Ok(())
+    );
+    assert_eq!(
+        validate_attestation(&state, &fresh, &spec),
+        Err(AttestationValidationError::Invalid(
+            AttestationInvalid::IncludedTooEarly {
+                state: state.slot,
+                delay: spec.min_attestation_inclusion_delay,
+                attestation: fresh.data.slot,
+            }
+        ))
+    );
+
+    // the committee and signature are checked
+    let mut unsigned = fresh.clone();
+    unsigned.aggregate_signature = AggregateSignature::new();
+    assert_eq!(
+        validate_attestation_for_gossip(&state, &unsigned, &spec),
+        Err(AttestationValidationError::Invalid(
+            AttestationInvalid::BadSignature
+        ))
+    );
+
+    let mut future = fresh.clone();
+    future.data.slot = state.slot + 1;
+    assert_eq!(
+        validate_attestation_for_gossip(&state, &future, &spec),
+        Err(AttestationValidationError::Invalid(
+            AttestationInvalid::FutureSlot {
+                state: state.slot,
+                attestation: future.data.slot,
+            }
+        ))
+    );
+
+    let mut stale = fresh.clone();
+    stale.data.slot = state.slot - spec.slots_per_epoch - 1;
+    assert_eq!(
+        validate_attestation_for_gossip(&state, &stale, &spec),
+        Err(AttestationValidationError::Invalid(
+            AttestationInvalid::IncludedTooLate {
+                state: state.slot,
+                attestation: stale.data.slot,
+            }
+        ))
+    );
+}
+
+#[test]
+fn attestation_errors_of_a_lagging_state_are_state_dependent() {
+    let state_dependent = vec![
+        AttestationValidationError::BeaconStateError(BeaconStateError::EpochOutOfBounds),
+        AttestationValidationError::Invalid(AttestationInvalid::IncludedTooEarly {
+            state: Slot::new(8),
+            delay: 4,
+            attestation: Slot::new(9),
+        }),
+        AttestationValidationError::Invalid(AttestationInvalid::FutureSlot {
+            state: Slot::new(8),
+            attestation: Slot::new(9),
+        }),
+        AttestationValidationError::Invalid(AttestationInvalid::WrongJustifiedEpoch {
+            state: Epoch::new(1),
+            attestation: Epoch::new(2),
+            is_current: true,
+        }),
+        AttestationValidationError::Invalid(AttestationInvalid::UnknownValidator(7)),
+    ];
+    for e in &state_dependent {
+        assert!(e.is_state_dependent(), "{:?}", e);
+    }
+
+    let invalid = vec![
+        AttestationValidationError::Invalid(AttestationInvalid::BadSignature),
+        AttestationValidationError::Invalid(AttestationInvalid::AggregationBitfieldIsEmpty),
+        AttestationValidationError::Invalid(AttestationInvalid::CustodyBitfieldHasSetBits),
+        AttestationValidationError::Invalid(AttestationInvalid::ShardBlockRootNotZero),
+        AttestationValidationError::Invalid(AttestationInvalid::IncludedTooLate {
+            state: Slot::new(80),
+            attestation: Slot::new(9),
+        }),
+    ];
+    for e in &invalid {
+        assert!(!e.is_state_dependent(), "{:?}", e);
+    }
+}
@@ -1,6 +1,6 @@
 use super::errors::{AttestationInvalid as Invalid, AttestationValidationError as Error};
 use crate::common::verify_bitfield_length;
-use ssz::TreeHash;
+use types::custody::{self, CustodySignatureSet};
 use types::*;
 
 /// Indicates if an `Attestation` is valid to be included in a block in the current epoch of the
@@ -31,6 +31,43 @@ pub fn validate_attestation_without_signature(
     validate_attestation_signature_optional(state, attestation, spec, false)
 }
 
+/// Indicates if an `Attestation` received from the network is valid to be propagated and held for
+/// inclusion in a later block, given a state at the present slot.
+///
+/// Only the conditions which hold wherever the attestation is included are checked: it must be
+/// from the last epoch of slots up to the present, and be signed by members of its committee. The
+/// inclusion delay, justified checkpoint and crosslink depend on the block which includes it, so
+/// are only checked by `validate_attestation` when it is included.
+pub fn validate_attestation_for_gossip(
+    state: &BeaconState,
+    attestation: &Attestation,
+    spec: &ChainSpec,
+) -> Result<(), Error> {
+    verify!(
+        attestation.data.slot >= spec.genesis_slot,
+        Invalid::PreGenesis {
+            genesis: spec.genesis_slot,
+            attestation: attestation.data.slot
+        }
+    );
+    verify!(
+        attestation.data.slot <= state.slot,
+        Invalid::FutureSlot {
+            state: state.slot,
+            attestation: attestation.data.slot
+        }
+    );
+    verify!(
+        state.slot <= attestation.data.slot + spec.slots_per_epoch,
+        Invalid::IncludedTooLate {
+            state: state.slot,
+            attestation: attestation.data.slot
+        }
+    );
+
+    verify_committee_and_signature(state, attestation, spec, true)
+}
+
 /// Indicates if an `Attestation` is valid to be included in a block in the current epoch of the
 /// given state, optionally validating the aggregate signature.
 ///
@@ -130,14 +167,25 @@ fn validate_attestation_signature_optional(
         Invalid::BadPreviousCrosslink
     );
 
+    verify_committee_and_signature(state, attestation, spec, verify_signature)
+}
+
+/// Indicates if the participants of an `Attestation` are members of its committee, optionally
+/// validating the aggregate signature, and if it does not reference a shard block.
+fn verify_committee_and_signature(
+    state: &BeaconState,
+    attestation: &Attestation,
+    spec: &ChainSpec,
+    verify_signature: bool,
+) -> Result<(), Error> {
     // Attestation must be non-empty!
     verify!(
         attestation.aggregation_bitfield.num_set_bits() != 0,
         Invalid::AggregationBitfieldIsEmpty
     );
-    // Custody bitfield must be empty (be be removed in phase 1)
+    // Custody bitfield must be empty in phase 0
     verify!(
-        attestation.custody_bitfield.num_set_bits() == 0,
+        custody::is_custody_bitfield_permitted(&attestation.custody_bitfield),
         Invalid::CustodyBitfieldHasSetBits
     );
 
@@ -203,8 +251,7 @@ fn verify_attestation_signature(
     a: &Attestation,
     spec: &ChainSpec,
 ) -> Result<(), Error> {
-    let mut aggregate_pubs = vec![AggregatePublicKey::new(); 2];
-    let mut message_exists = vec![false; 2];
+    let mut signature_set = CustodySignatureSet::default();
     let attestation_epoch = a.data.slot.epoch(spec.slots_per_epoch);
 
     for (i, v) in committee.iter().enumerate() {
@@ -227,51 +274,18 @@ fn verify_attestation_signature(
                 }
             };
 
-            message_exists[custody_bit as usize] = true;
-
             match state.validator_registry.get(*v as usize) {
-                Some(validator) => {
-                    aggregate_pubs[custody_bit as usize].add(&validator.pubkey);
-                }
+                Some(validator) => signature_set.add(custody_bit, &validator.pubkey),
                 // Return error if validator index is unknown.
                 None => return Err(Error::BeaconStateError(BeaconStateError::UnknownValidator)),
             };
         }
     }
 
-    // Message when custody bitfield is `false`
-    let message_0 = AttestationDataAndCustodyBit {
-        data: a.data.clone(),
-        custody_bit: false,
-    }
-    .hash_tree_root();
-
-    // Message when custody bitfield is `true`
-    let message_1 = AttestationDataAndCustodyBit {
-        data: a.data.clone(),
-        custody_bit: true,
-    }
-    .hash_tree_root();
-
-    let mut messages = vec![];
-    let mut keys = vec![];
-
-    // If any validator signed a message with a `false` custody bit.
-    if message_exists[0] {
-        messages.push(&message_0[..]);
-        keys.push(&aggregate_pubs[0]);
-    }
-    // If any validator signed a message with a `true` custody bit.
-    if message_exists[1] {
-        messages.push(&message_1[..]);
-        keys.push(&aggregate_pubs[1]);
-    }
-
     let domain = spec.get_domain(attestation_epoch, Domain::Attestation, &state.fork);
 
     verify!(
-        a.aggregate_signature
-            .verify_multiple(&messages[..], domain, &keys[..]),
+        signature_set.verify(&a.data, &a.aggregate_signature, domain),
         Invalid::BadSignature
     );
 
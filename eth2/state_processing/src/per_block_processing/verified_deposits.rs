@@ -0,0 +1,131 @@
+use ssz::TreeHash;
+use std::collections::HashMap;
+use types::*;
+
+/// The deposits whose proof-of-possession has already been verified, allowing block processing to
+/// skip the BLS signature check when the deposit is included in a block.
+///
+/// Deposits are keyed by their `hash_tree_root`. The signature domain each deposit was verified
+/// against is stored alongside it, as a proof-of-possession is only valid for a particular fork.
+#[derive(Debug, Default, Clone)]
+pub struct VerifiedDeposits {
+    deposits: HashMap<Hash256, u64>,
+}
+
+impl VerifiedDeposits {
+    /// Verifies the proof-of-possession of a deposit at `epoch` of `fork`, remembering the
+    /// deposit if it is valid.
+    ///
+    /// Returns `true` if the proof-of-possession is valid.
+    pub fn verify(
+        &mut self,
+        deposit: &Deposit,
+        epoch: Epoch,
+        fork: &Fork,
+        spec: &ChainSpec,
+    ) -> bool {
+        if self.is_verified(deposit, epoch, fork, spec) {
+            return true;
+        }
+
+        let valid = deposit
+            .deposit_data
+            .deposit_input
+            .validate_proof_of_possession(epoch, fork, spec);
+
+        if valid {
+            let domain = spec.get_domain(epoch, Domain::Deposit, fork);
+            self.deposits.insert(deposit_root(deposit), domain);
+        }
+
+        valid
+    }
+
+    /// Returns `true` if the proof-of-possession of the deposit has been verified for `epoch` of
+    /// `fork`.
+    pub fn is_verified(
+        &self,
+        deposit: &Deposit,
+        epoch: Epoch,
+        fork: &Fork,
+        spec: &ChainSpec,
+    ) -> bool {
+        let domain = spec.get_domain(epoch, Domain::Deposit, fork);
+        self.deposits.get(&deposit_root(deposit)) == Some(&domain)
+    }
+
+    /// Forgets a deposit, e.g., once it has been included in a finalized block.
+    pub fn remove(&mut self, deposit: &Deposit) {
+        self.deposits.remove(&deposit_root(deposit));
+    }
+
+    /// Returns the number of verified deposits.
+    pub fn len(&self) -> usize {
+        self.deposits.len()
+    }
+
+    /// Returns `true` if there are no verified deposits.
+    pub fn is_empty(&self) -> bool {
+        self.deposits.is_empty()
+    }
+}
+
+fn deposit_root(deposit: &Deposit) -> Hash256 {
+    Hash256::from_slice(&deposit.hash_tree_root()[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bls::{Keypair, Signature};
+
+    fn deposit(spec: &ChainSpec, fork: &Fork, epoch: Epoch) -> Deposit {
+        let keypair = Keypair::random();
+        let mut deposit_input = DepositInput {
+            pubkey: keypair.pk.clone(),
+            withdrawal_credentials: Hash256::zero(),
+            proof_of_possession: Signature::empty_signature(),
+        };
+        deposit_input.proof_of_possession =
+            deposit_input.create_proof_of_possession(&keypair.sk, epoch, fork, spec);
+
+        Deposit {
+            proof: vec![],
+            index: 0,
+            deposit_data: DepositData {
+                amount: spec.max_deposit_amount,
+                timestamp: 0,
+                deposit_input,
+            },
+        }
+    }
+
+    #[test]
+    fn remembers_valid_deposits() {
+        let spec = ChainSpec::foundation();
+        let fork = Fork::genesis(&spec);
+        let epoch = Epoch::new(0);
+        let deposit = deposit(&spec, &fork, epoch);
+
+        let mut verified = VerifiedDeposits::default();
+        assert!(!verified.is_verified(&deposit, epoch, &fork, &spec));
+        assert!(verified.verify(&deposit, epoch, &fork, &spec));
+        assert!(verified.is_verified(&deposit, epoch, &fork, &spec));
+
+        verified.remove(&deposit);
+        assert!(verified.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_deposits() {
+        let spec = ChainSpec::foundation();
+        let fork = Fork::genesis(&spec);
+        let epoch = Epoch::new(0);
+        let mut deposit = deposit(&spec, &fork, epoch);
+        deposit.deposit_data.deposit_input.withdrawal_credentials = Hash256::from([1; 32]);
+
+        let mut verified = VerifiedDeposits::default();
+        assert!(!verified.verify(&deposit, epoch, &fork, &spec));
+        assert!(verified.is_empty());
+    }
+}
@@ -34,6 +34,22 @@ pub fn verify_deposit(
         Invalid::BadProofOfPossession
     );
 
+    verify_deposit_without_proof_of_possession(state, deposit, verify_merkle_branch, spec)
+}
+
+/// Indicates if a `Deposit` is valid to be included in a block in the current epoch of the given
+/// state, assuming its proof-of-possession has already been verified (e.g., when the deposit was
+/// first seen in the eth1 chain).
+///
+/// Returns `Ok(())` if the `Deposit` is valid, otherwise indicates the reason for invalidity.
+///
+/// Spec v0.5.0
+pub fn verify_deposit_without_proof_of_possession(
+    state: &BeaconState,
+    deposit: &Deposit,
+    verify_merkle_branch: bool,
+    spec: &ChainSpec,
+) -> Result<(), Error> {
     if verify_merkle_branch {
         verify!(
             verify_deposit_merkle_proof(state, deposit, spec),
@@ -90,9 +106,8 @@ pub fn get_existing_validator_index(
 ///
 /// Spec v0.5.0
 fn verify_deposit_merkle_proof(state: &BeaconState, deposit: &Deposit, spec: &ChainSpec) -> bool {
-    let leaf = hash(&get_serialized_deposit_data(deposit));
     verify_merkle_proof(
-        Hash256::from_slice(&leaf),
+        get_deposit_leaf(deposit),
         &deposit.proof,
         spec.deposit_contract_tree_depth as usize,
         deposit.index as usize,
@@ -100,6 +115,13 @@ fn verify_deposit_merkle_proof(state: &BeaconState, deposit: &Deposit, spec: &Ch
     )
 }
 
+/// Returns the leaf of the deposit in the deposit contract's Merkle tree.
+///
+/// Spec v0.5.0
+pub fn get_deposit_leaf(deposit: &Deposit) -> Hash256 {
+    Hash256::from_slice(&hash(&get_serialized_deposit_data(deposit)))
+}
+
 /// Helper struct for easily getting the serialized data generated by the deposit contract.
 ///
 /// Spec v0.5.0
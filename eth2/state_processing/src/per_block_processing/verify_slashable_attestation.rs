@@ -2,7 +2,7 @@ use super::errors::{
     SlashableAttestationInvalid as Invalid, SlashableAttestationValidationError as Error,
 };
 use crate::common::verify_bitfield_length;
-use ssz::TreeHash;
+use types::custody::{self, CustodySignatureSet};
 use types::*;
 
 /// Indicates if a `SlashableAttestation` is valid to be included in a block in the current epoch of the given
@@ -16,7 +16,7 @@ pub fn verify_slashable_attestation(
     slashable_attestation: &SlashableAttestation,
     spec: &ChainSpec,
 ) -> Result<(), Error> {
-    if slashable_attestation.custody_bitfield.num_set_bits() > 0 {
+    if !custody::is_custody_bitfield_permitted(&slashable_attestation.custody_bitfield) {
         invalid!(Invalid::CustodyBitfieldHasSetBits);
     }
 
@@ -50,12 +50,7 @@ pub fn verify_slashable_attestation(
         ));
     }
 
-    // TODO: this signature verification could likely be replaced with:
-    //
-    // super::validate_attestation::validate_attestation_signature(..)
-
-    let mut aggregate_pubs = vec![AggregatePublicKey::new(); 2];
-    let mut message_exists = vec![false; 2];
+    let mut signature_set = CustodySignatureSet::default();
 
     for (i, v) in slashable_attestation.validator_indices.iter().enumerate() {
         let custody_bit = match slashable_attestation.custody_bitfield.get(i) {
@@ -63,48 +58,23 @@ pub fn verify_slashable_attestation(
             Err(_) => unreachable!(),
         };
 
-        message_exists[custody_bit as usize] = true;
-
         match state.validator_registry.get(*v as usize) {
-            Some(validator) => {
-                aggregate_pubs[custody_bit as usize].add(&validator.pubkey);
-            }
+            Some(validator) => signature_set.add(custody_bit, &validator.pubkey),
             None => invalid!(Invalid::UnknownValidator(*v)),
         };
     }
 
-    let message_0 = AttestationDataAndCustodyBit {
-        data: slashable_attestation.data.clone(),
-        custody_bit: false,
-    }
-    .hash_tree_root();
-    let message_1 = AttestationDataAndCustodyBit {
-        data: slashable_attestation.data.clone(),
-        custody_bit: true,
-    }
-    .hash_tree_root();
-
-    let mut messages = vec![];
-    let mut keys = vec![];
-
-    if message_exists[0] {
-        messages.push(&message_0[..]);
-        keys.push(&aggregate_pubs[0]);
-    }
-    if message_exists[1] {
-        messages.push(&message_1[..]);
-        keys.push(&aggregate_pubs[1]);
-    }
-
     let domain = {
         let epoch = slashable_attestation.data.slot.epoch(spec.slots_per_epoch);
         spec.get_domain(epoch, Domain::Attestation, &state.fork)
     };
 
     verify!(
-        slashable_attestation
-            .aggregate_signature
-            .verify_multiple(&messages[..], domain, &keys[..]),
+        signature_set.verify(
+            &slashable_attestation.data,
+            &slashable_attestation.aggregate_signature,
+            domain
+        ),
         Invalid::BadSignature
     );
 
@@ -5,6 +5,7 @@ use process_exit_queue::process_exit_queue;
 use process_slashings::process_slashings;
 use ssz::TreeHash;
 use std::collections::HashMap;
+use timings::{EpochProcessingTimings, StageTimer};
 use types::*;
 use update_registry_and_shuffling_data::update_registry_and_shuffling_data;
 use validator_statuses::{TotalBalances, ValidatorStatuses};
@@ -12,12 +13,14 @@ use winning_root::{winning_root, WinningRoot};
 
 pub mod apply_rewards;
 pub mod errors;
+pub mod finality_tests;
 pub mod get_attestation_participants;
 pub mod inclusion_distance;
 pub mod process_ejections;
 pub mod process_exit_queue;
 pub mod process_slashings;
 pub mod tests;
+pub mod timings;
 pub mod update_registry_and_shuffling_data;
 pub mod validator_statuses;
 pub mod winning_root;
@@ -34,21 +37,39 @@ pub type WinningRootHashSet = HashMap<u64, WinningRoot>;
 ///
 /// Spec v0.5.0
 pub fn per_epoch_processing(state: &mut BeaconState, spec: &ChainSpec) -> Result<(), Error> {
+    per_epoch_processing_with_timings(state, spec).map(|_| ())
+}
+
+/// Performs per-epoch processing as `per_epoch_processing`, returning the time spent in each of
+/// its stages.
+///
+/// Spec v0.5.0
+pub fn per_epoch_processing_with_timings(
+    state: &mut BeaconState,
+    spec: &ChainSpec,
+) -> Result<EpochProcessingTimings, Error> {
+    let mut timings = EpochProcessingTimings::default();
+    let mut timer = StageTimer::start();
+
     // Ensure the previous and next epoch caches are built.
     state.build_epoch_cache(RelativeEpoch::Previous, spec)?;
     state.build_epoch_cache(RelativeEpoch::Current, spec)?;
+    timings.caches = timer.lap();
 
     // Load the struct we use to assign validators into sets based on their participation.
     //
     // E.g., attestation in the previous epoch, attested to the head, etc.
     let mut validator_statuses = ValidatorStatuses::new(state, spec)?;
     validator_statuses.process_attestations(&state, spec)?;
+    timings.validator_statuses = timer.lap();
 
     // Justification.
     update_justification_and_finalization(state, &validator_statuses.total_balances, spec)?;
+    timings.justification = timer.lap();
 
     // Crosslinks.
     let winning_root_for_shards = process_crosslinks(state, spec)?;
+    timings.crosslinks = timer.lap();
 
     // Eth1 data.
     maybe_reset_eth1_period(state, spec);
@@ -60,6 +81,7 @@ pub fn per_epoch_processing(state: &mut BeaconState, spec: &ChainSpec) -> Result
         &winning_root_for_shards,
         spec,
     )?;
+    timings.rewards = timer.lap();
 
     // Ejections.
     process_ejections(state, spec)?;
@@ -70,18 +92,21 @@ pub fn per_epoch_processing(state: &mut BeaconState, spec: &ChainSpec) -> Result
         validator_statuses.total_balances.current_epoch,
         spec,
     )?;
+    timings.registry = timer.lap();
 
     // Slashings and exit queue.
     process_slashings(state, validator_statuses.total_balances.current_epoch, spec)?;
     process_exit_queue(state, spec);
+    timings.slashings_and_exits = timer.lap();
 
     // Final updates.
     finish_epoch_update(state, spec)?;
 
     // Rotate the epoch caches to suit the epoch transition.
     state.advance_caches();
+    timings.final_updates = timer.lap();
 
-    Ok(())
+    Ok(timings)
 }
 
 /// Maybe resets the eth1 period.
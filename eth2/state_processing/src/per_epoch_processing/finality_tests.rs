@@ -0,0 +1,279 @@
+#![cfg(test)]
+//! Exercises each rule of `update_justification_and_finalization` with hand-built justification
+//! bitfields, as the Casper FFG edge cases are where clients have historically diverged.
+//!
+//! Epochs are given relative to the current epoch `C` of the state, e.g. `2` is `C - 2`. In the
+//! bitfield, bit `n` is set if epoch `C - n` is justified once the bitfield is rotated. The test
+//! names follow the rules of the spec, which count `C` as the first most recent epoch, so the
+//! "second" epoch is `1`.
+use super::update_justification_and_finalization;
+use super::validator_statuses::TotalBalances;
+use types::test_utils::TestingBeaconStateBuilder;
+use types::*;
+
+/// The total balance of the active validators in each epoch.
+const TOTAL_BALANCE: u64 = 300;
+/// The smallest balance which justifies an epoch.
+const SUPERMAJORITY: u64 = 200;
+
+/// The justification of a state before `update_justification_and_finalization`.
+struct Case {
+    /// The bitfield before it is rotated for the new epoch.
+    bitfield: u64,
+    /// `C - previous_justified` is the previous justified epoch.
+    previous_justified: u64,
+    /// `C - current_justified` is the current justified epoch.
+    current_justified: u64,
+    /// The balance of the boundary attesters of the previous epoch.
+    previous_attesters: u64,
+    /// The balance of the boundary attesters of the current epoch.
+    current_attesters: u64,
+}
+
+/// The justification of a state after `update_justification_and_finalization`.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    bitfield: u64,
+    /// `C - previous_justified` is the previous justified epoch.
+    previous_justified: u64,
+    /// `C - current_justified` is the current justified epoch.
+    current_justified: u64,
+    /// `C - finalized` is the finalized epoch, `None` if it was not updated.
+    finalized: Option<u64>,
+}
+
+/// The epoch the state was finalized at before each case.
+const PRIOR_FINALIZED: u64 = 8;
+
+fn run(case: Case) -> Outcome {
+    let spec = ChainSpec::few_validators();
+    let current_epoch = spec.genesis_epoch + 16;
+
+    let mut builder = TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec);
+    builder.teleport_to_slot(current_epoch.end_slot(spec.slots_per_epoch), &spec);
+    let (mut state, _keypairs) = builder.build();
+
+    // a distinct root at the start of each epoch, so the justified and finalized roots may be
+    // checked against their epochs
+    for distance in 0..=PRIOR_FINALIZED {
+        let epoch = current_epoch - distance;
+        state
+            .set_block_root(epoch.start_slot(spec.slots_per_epoch), root(epoch), &spec)
+            .unwrap();
+    }
+
+    state.justification_bitfield = case.bitfield;
+    state.previous_justified_epoch = current_epoch - case.previous_justified;
+    state.current_justified_epoch = current_epoch - case.current_justified;
+    state.current_justified_root = root(state.current_justified_epoch);
+    state.finalized_epoch = current_epoch - PRIOR_FINALIZED;
+    state.finalized_root = root(state.finalized_epoch);
+
+    let total_balances = TotalBalances {
+        current_epoch: TOTAL_BALANCE,
+        previous_epoch: TOTAL_BALANCE,
+        current_epoch_boundary_attesters: case.current_attesters,
+        previous_epoch_boundary_attesters: case.previous_attesters,
+        ..TotalBalances::default()
+    };
+
+    update_justification_and_finalization(&mut state, &total_balances, &spec).unwrap();
+
+    assert_eq!(
+        state.current_justified_root,
+        root(state.current_justified_epoch)
+    );
+    assert_eq!(state.finalized_root, root(state.finalized_epoch));
+
+    let finalized = (current_epoch - state.finalized_epoch).as_u64();
+    Outcome {
+        bitfield: state.justification_bitfield,
+        previous_justified: (current_epoch - state.previous_justified_epoch).as_u64(),
+        current_justified: (current_epoch - state.current_justified_epoch).as_u64(),
+        finalized: if finalized == PRIOR_FINALIZED {
+            None
+        } else {
+            Some(finalized)
+        },
+    }
+}
+
+fn root(epoch: Epoch) -> Hash256 {
+    Hash256::from_low_u64_le(epoch.as_u64())
+}
+
+#[test]
+fn justifies_at_exactly_two_thirds() {
+    let outcome = run(Case {
+        bitfield: 0,
+        previous_justified: 4,
+        current_justified: 4,
+        previous_attesters: 0,
+        current_attesters: SUPERMAJORITY,
+    });
+    assert_eq!(outcome.bitfield, 0b1);
+    assert_eq!(outcome.current_justified, 0);
+    assert_eq!(outcome.previous_justified, 4);
+}
+
+#[test]
+fn does_not_justify_below_two_thirds() {
+    let outcome = run(Case {
+        bitfield: 0b1,
+        previous_justified: 4,
+        current_justified: 3,
+        previous_attesters: SUPERMAJORITY - 1,
+        current_attesters: SUPERMAJORITY - 1,
+    });
+    assert_eq!(
+        outcome,
+        Outcome {
+            bitfield: 0b10,
+            previous_justified: 3,
+            current_justified: 3,
+            finalized: None,
+        }
+    );
+}
+
+#[test]
+fn justifies_the_previous_epoch_late() {
+    let outcome = run(Case {
+        bitfield: 0,
+        previous_justified: 4,
+        current_justified: 4,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: 0,
+    });
+    assert_eq!(outcome.bitfield, 0b10);
+    assert_eq!(outcome.current_justified, 1);
+    assert_eq!(outcome.finalized, None);
+}
+
+/// Epochs 1, 2 and 3 justified, 1 using 3 as source: 3 is finalized.
+#[test]
+fn finalizes_by_the_second_third_and_fourth_epochs() {
+    let outcome = run(Case {
+        bitfield: 0b110,
+        previous_justified: 3,
+        current_justified: 2,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: 0,
+    });
+    assert_eq!(outcome.bitfield, 0b1110);
+    assert_eq!(outcome.finalized, Some(3));
+}
+
+/// Epochs 1 and 2 justified, 1 using 2 as source: 2 is finalized.
+#[test]
+fn finalizes_by_the_second_and_third_epochs() {
+    let outcome = run(Case {
+        bitfield: 0b10,
+        previous_justified: 2,
+        current_justified: 2,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: 0,
+    });
+    assert_eq!(outcome.bitfield, 0b110);
+    assert_eq!(outcome.finalized, Some(2));
+}
+
+/// Epochs 0, 1 and 2 justified, 0 using 2 as source: 2 is finalized.
+#[test]
+fn finalizes_by_the_first_second_and_third_epochs() {
+    let outcome = run(Case {
+        bitfield: 0b11,
+        previous_justified: 3,
+        current_justified: 2,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: SUPERMAJORITY,
+    });
+    assert_eq!(outcome.bitfield, 0b111);
+    assert_eq!(outcome.current_justified, 0);
+    assert_eq!(outcome.finalized, Some(2));
+}
+
+/// Epochs 0 and 1 justified, 0 using 1 as source: 1 is finalized.
+#[test]
+fn finalizes_by_the_first_and_second_epochs() {
+    let outcome = run(Case {
+        bitfield: 0b1,
+        previous_justified: 2,
+        current_justified: 1,
+        previous_attesters: 0,
+        current_attesters: SUPERMAJORITY,
+    });
+    assert_eq!(outcome.bitfield, 0b11);
+    assert_eq!(outcome.finalized, Some(1));
+}
+
+/// When several rules apply, the most recent finalized epoch is used.
+#[test]
+fn prefers_the_latest_rule_which_applies() {
+    let outcome = run(Case {
+        bitfield: 0b11,
+        previous_justified: 2,
+        current_justified: 1,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: SUPERMAJORITY,
+    });
+    assert_eq!(outcome.bitfield, 0b111);
+    assert_eq!(outcome.finalized, Some(1));
+}
+
+/// Epochs 1, 2 and 3 justified, but 1 used the older epoch 5 as source.
+#[test]
+fn does_not_finalize_with_a_skipped_source() {
+    let outcome = run(Case {
+        bitfield: 0b110,
+        previous_justified: 5,
+        current_justified: 2,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: 0,
+    });
+    assert_eq!(outcome.bitfield, 0b1110);
+    assert_eq!(outcome.finalized, None);
+}
+
+/// Epoch 2 was not justified, so the justifications of 1 and 3 do not finalize.
+#[test]
+fn does_not_finalize_across_an_unjustified_epoch() {
+    let outcome = run(Case {
+        bitfield: 0b100,
+        previous_justified: 3,
+        current_justified: 3,
+        previous_attesters: SUPERMAJORITY,
+        current_attesters: 0,
+    });
+    assert_eq!(outcome.bitfield, 0b1010);
+    assert_eq!(outcome.finalized, None);
+}
+
+/// Epoch 0 justified using 1 as source, but 1 was not justified.
+#[test]
+fn does_not_finalize_an_unjustified_source() {
+    let outcome = run(Case {
+        bitfield: 0b10,
+        previous_justified: 3,
+        current_justified: 1,
+        previous_attesters: 0,
+        current_attesters: SUPERMAJORITY,
+    });
+    assert_eq!(outcome.bitfield, 0b101);
+    assert_eq!(outcome.finalized, None);
+}
+
+/// Epochs justified long ago do not finalize once the intervening epochs are not justified.
+#[test]
+fn does_not_finalize_without_recent_justification() {
+    let outcome = run(Case {
+        bitfield: u64::max_value() << 4,
+        previous_justified: 5,
+        current_justified: 4,
+        previous_attesters: 0,
+        current_attesters: 0,
+    });
+    assert_eq!(outcome.bitfield % 32, 0);
+    assert_eq!(outcome.current_justified, 4);
+    assert_eq!(outcome.finalized, None);
+}
@@ -1,6 +1,7 @@
 #![cfg(test)]
-use crate::per_epoch_processing;
+use crate::{per_epoch_processing, per_epoch_processing_with_timings};
 use env_logger::{Builder, Env};
+use std::time::{Duration, Instant};
 use types::test_utils::TestingBeaconStateBuilder;
 use types::*;
 
@@ -19,3 +20,26 @@ fn runs_without_error() {
 
     per_epoch_processing(&mut state, &spec).unwrap();
 }
+
+#[test]
+fn records_stage_timings() {
+    let spec = ChainSpec::few_validators();
+
+    let mut builder = TestingBeaconStateBuilder::from_deterministic_keypairs(8, &spec);
+
+    let target_slot = (spec.genesis_epoch + 4).end_slot(spec.slots_per_epoch);
+    builder.teleport_to_slot(target_slot, &spec);
+
+    let (mut state, _keypairs) = builder.build();
+
+    let start = Instant::now();
+    let timings = per_epoch_processing_with_timings(&mut state, &spec).unwrap();
+    let elapsed = start.elapsed();
+
+    // the stages cover all of epoch processing, each timed from the end of the last
+    assert!(timings.caches > Duration::from_secs(0));
+    assert!(timings.validator_statuses > Duration::from_secs(0));
+    assert!(timings.crosslinks > Duration::from_secs(0));
+    assert!(timings.total() <= elapsed);
+    assert!(timings.total() * 2 >= elapsed);
+}
@@ -0,0 +1,53 @@
+use std::time::{Duration, Instant};
+
+/// The time spent in each stage of per-epoch processing, recorded to diagnose slow epochs.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EpochProcessingTimings {
+    /// Building the previous and current epoch caches.
+    pub caches: Duration,
+    /// Sorting the validators by their participation in the previous and current epochs.
+    pub validator_statuses: Duration,
+    /// Updating justification and finalization.
+    pub justification: Duration,
+    /// Finding the winning crosslink of each shard.
+    pub crosslinks: Duration,
+    /// Resetting the eth1 voting period and applying rewards and penalties.
+    pub rewards: Duration,
+    /// Ejecting validators and updating the validator registry and shuffling.
+    pub registry: Duration,
+    /// Processing slashings and the exit queue.
+    pub slashings_and_exits: Duration,
+    /// The final updates and rotation of the epoch caches.
+    pub final_updates: Duration,
+}
+
+impl EpochProcessingTimings {
+    /// Returns the time spent in every stage.
+    pub fn total(&self) -> Duration {
+        self.caches
+            + self.validator_statuses
+            + self.justification
+            + self.crosslinks
+            + self.rewards
+            + self.registry
+            + self.slashings_and_exits
+            + self.final_updates
+    }
+}
+
+/// Measures consecutive stages, each lasting from the end of the last.
+pub(crate) struct StageTimer(Instant);
+
+impl StageTimer {
+    pub fn start() -> Self {
+        StageTimer(Instant::now())
+    }
+
+    /// Returns the time since the end of the last stage, starting the next.
+    pub fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.0);
+        self.0 = now;
+        elapsed
+    }
+}
@@ -16,16 +16,31 @@ pub fn per_slot_processing(
     latest_block_header: &BeaconBlockHeader,
     spec: &ChainSpec,
 ) -> Result<(), Error> {
+    per_slot_processing_with_timings(state, latest_block_header, spec).map(|_| ())
+}
+
+/// Advances a state forward by one slot as `per_slot_processing`, returning the time spent in each
+/// stage of per-epoch processing, if it was performed.
+///
+/// Spec v0.5.0
+pub fn per_slot_processing_with_timings(
+    state: &mut BeaconState,
+    latest_block_header: &BeaconBlockHeader,
+    spec: &ChainSpec,
+) -> Result<Option<EpochProcessingTimings>, Error> {
     cache_state(state, latest_block_header, spec)?;
 
-    if (state.slot + 1) % spec.slots_per_epoch == 0 {
-        per_epoch_processing(state, spec)?;
+    let timings = if (state.slot + 1) % spec.slots_per_epoch == 0 {
+        let timings = per_epoch_processing_with_timings(state, spec)?;
         state.advance_caches();
-    }
+        Some(timings)
+    } else {
+        None
+    };
 
     state.slot += 1;
 
-    Ok(())
+    Ok(timings)
 }
 
 fn cache_state(
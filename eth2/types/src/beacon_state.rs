@@ -44,6 +44,9 @@ pub enum Error {
 
 /// The state of the `BeaconChain` at some slot.
 ///
+/// The `test_random` sizes keep randomly generated states within realistic list lengths, with the
+/// validator registry and balances kept the same length.
+///
 /// Spec v0.5.0
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, TestRandom, Encode, Decode, TreeHash)]
 pub struct BeaconState {
@@ -53,11 +56,14 @@ pub struct BeaconState {
     pub fork: Fork,
 
     // Validator registry
+    #[test_random(size = 16)]
     pub validator_registry: Vec<Validator>,
+    #[test_random(size = 16)]
     pub validator_balances: Vec<u64>,
     pub validator_registry_update_epoch: Epoch,
 
     // Randomness and committees
+    #[test_random(size = 64)]
     pub latest_randao_mixes: Vec<Hash256>,
     pub previous_shuffling_start_shard: u64,
     pub current_shuffling_start_shard: u64,
@@ -67,7 +73,9 @@ pub struct BeaconState {
     pub current_shuffling_seed: Hash256,
 
     // Finality
+    #[test_random(size = 4)]
     pub previous_epoch_attestations: Vec<PendingAttestation>,
+    #[test_random(size = 4)]
     pub current_epoch_attestations: Vec<PendingAttestation>,
     pub previous_justified_epoch: Epoch,
     pub current_justified_epoch: Epoch,
@@ -78,16 +86,23 @@ pub struct BeaconState {
     pub finalized_root: Hash256,
 
     // Recent state
+    #[test_random(size = 8)]
     pub latest_crosslinks: Vec<Crosslink>,
+    #[test_random(size = 64)]
     latest_block_roots: Vec<Hash256>,
+    #[test_random(size = 64)]
     latest_state_roots: Vec<Hash256>,
+    #[test_random(size = 64)]
     latest_active_index_roots: Vec<Hash256>,
+    #[test_random(size = 64)]
     latest_slashed_balances: Vec<u64>,
     pub latest_block_header: BeaconBlockHeader,
+    #[test_random(size = 4)]
     pub historical_roots: Vec<Hash256>,
 
     // Ethereum 1.0 chain data
     pub latest_eth1_data: Eth1Data,
+    #[test_random(size = 4)]
     pub eth1_data_votes: Vec<Eth1DataVote>,
     pub deposit_index: u64,
 
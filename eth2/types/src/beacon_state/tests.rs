@@ -55,3 +55,18 @@ fn cache_initialization() {
     test_cache_initialization(&mut state, RelativeEpoch::NextWithRegistryChange, &spec);
     test_cache_initialization(&mut state, RelativeEpoch::NextWithoutRegistryChange, &spec);
 }
+
+#[test]
+fn random_for_test_respects_collection_sizes() {
+    let mut rng = XorShiftRng::from_seed([42; 16]);
+    let state = BeaconState::random_for_test(&mut rng);
+
+    assert_eq!(state.validator_registry.len(), 16);
+    assert_eq!(
+        state.validator_balances.len(),
+        state.validator_registry.len()
+    );
+    assert_eq!(state.latest_randao_mixes.len(), 64);
+    assert_eq!(state.latest_block_roots.len(), 64);
+    assert_eq!(state.eth1_data_votes.len(), 4);
+}
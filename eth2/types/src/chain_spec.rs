@@ -296,6 +296,93 @@ impl ChainSpec {
     }
 }
 
+/// Sets the named field of a `ChainSpec` to `$value`, parsed as the type of the field.
+///
+/// Fields of type `Slot` or `Epoch` are listed separately and parsed from a `u64`.
+macro_rules! override_field {
+    ($spec: expr, $name: expr, $value: expr, [$($field: ident),*], [$($wrapped: ident: $type: ident),*]) => {
+        match $name {
+            $(
+                stringify!($field) => {
+                    $spec.$field = $value
+                        .parse()
+                        .map_err(|_| format!("Invalid value for {}: {}", $name, $value))?;
+                }
+            )*
+            $(
+                stringify!($wrapped) => {
+                    $spec.$wrapped = $type::new(
+                        $value
+                            .parse()
+                            .map_err(|_| format!("Invalid value for {}: {}", $name, $value))?,
+                    );
+                }
+            )*
+            _ => return Err(format!("Unknown or unsupported spec constant: {}", $name)),
+        }
+    };
+}
+
+impl ChainSpec {
+    /// Overrides a numeric constant, named as in the specification (e.g., `SECONDS_PER_SLOT`).
+    ///
+    /// Intended only for local experiments; the resulting spec is unlikely to be compatible with
+    /// any other node.
+    pub fn apply_override(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let name = name.to_lowercase();
+        override_field!(
+            self,
+            name.as_str(),
+            value,
+            [
+                shard_count,
+                target_committee_size,
+                max_balance_churn_quotient,
+                max_indices_per_slashable_vote,
+                max_exit_dequeues_per_epoch,
+                shuffle_round_count,
+                deposit_contract_tree_depth,
+                min_deposit_amount,
+                max_deposit_amount,
+                fork_choice_balance_increment,
+                ejection_balance,
+                genesis_fork_version,
+                genesis_start_shard,
+                seconds_per_slot,
+                min_attestation_inclusion_delay,
+                slots_per_epoch,
+                activation_exit_delay,
+                epochs_per_eth1_voting_period,
+                slots_per_historical_root,
+                persistent_committee_period,
+                latest_randao_mixes_length,
+                latest_active_index_roots_length,
+                latest_slashed_exit_length,
+                base_reward_quotient,
+                whistleblower_reward_quotient,
+                attestation_inclusion_reward_quotient,
+                inactivity_penalty_quotient,
+                min_penalty_quotient,
+                max_proposer_slashings,
+                max_attester_slashings,
+                max_attestations,
+                max_deposits,
+                max_voluntary_exits,
+                max_transfers,
+                network_id
+            ],
+            [
+                genesis_slot: Slot,
+                genesis_epoch: Epoch,
+                far_future_epoch: Epoch,
+                min_seed_lookahead: Epoch,
+                min_validator_withdrawability_delay: Epoch
+            ]
+        );
+        Ok(())
+    }
+}
+
 impl Default for ChainSpec {
     fn default() -> Self {
         Self::foundation()
@@ -324,6 +411,21 @@ mod tests {
         assert_eq!(int_to_bytes8(domain), expected);
     }
 
+    #[test]
+    fn test_apply_override() {
+        let mut spec = ChainSpec::foundation();
+
+        spec.apply_override("SECONDS_PER_SLOT", "3").unwrap();
+        assert_eq!(spec.seconds_per_slot, 3);
+
+        spec.apply_override("genesis_epoch", "7").unwrap();
+        assert_eq!(spec.genesis_epoch, Epoch::new(7));
+
+        assert!(spec.apply_override("SECONDS_PER_SLOT", "three").is_err());
+        assert!(spec.apply_override("NOT_A_CONSTANT", "1").is_err());
+        assert!(spec.apply_override("DOMAIN_DEPOSIT", "1").is_err());
+    }
+
     #[test]
     fn test_get_domain() {
         let spec = ChainSpec::foundation();
@@ -0,0 +1,148 @@
+//! The custody bits of attestations, by which validators will prove custody of the shard data
+//! they attest to.
+//!
+//! Phase 0 has no proofs of custody, so every validator signs `PHASE_0_CUSTODY_BIT` and blocks may
+//! only include attestations whose custody bitfields have no bits set. Attestation production and
+//! verification go through this module, so that phase 1 custody need only change it.
+//!
+//! The `proof-of-custody` feature exposes the interfaces through which phase 1 will decide the
+//! custody bit of each attester. It does not change which attestations are valid, as every other
+//! phase 0 client rejects set custody bits.
+
+use crate::{AttestationData, AttestationDataAndCustodyBit, Bitfield};
+use bls::{AggregatePublicKey, AggregateSignature, PublicKey};
+use ssz::TreeHash;
+
+/// The custody bit signed by every attester in phase 0.
+pub const PHASE_0_CUSTODY_BIT: bool = false;
+
+/// Returns the custody bit a validator signs when attesting to `data`.
+pub fn attester_custody_bit(_data: &AttestationData) -> bool {
+    PHASE_0_CUSTODY_BIT
+}
+
+/// Returns the message signed by a validator attesting to `data` with `custody_bit`.
+pub fn signing_message(data: &AttestationData, custody_bit: bool) -> Vec<u8> {
+    AttestationDataAndCustodyBit {
+        data: data.clone(),
+        custody_bit,
+    }
+    .hash_tree_root()
+}
+
+/// Returns `true` if an attestation with `custody_bitfield` may be included in a block.
+///
+/// Phase 0 permits no set bits.
+pub fn is_custody_bitfield_permitted(custody_bitfield: &Bitfield) -> bool {
+    custody_bitfield.num_set_bits() == 0
+}
+
+/// Decides the custody bit an attester signs, from its proof of custody of the shard data it
+/// attests to.
+///
+/// A phase 1 interface: attesters sign `PHASE_0_CUSTODY_BIT` until phase 1 is specified.
+#[cfg(feature = "proof-of-custody")]
+pub trait CustodyBitSource {
+    /// Returns the custody bit of the validator with `validator_index` attesting to `data`.
+    fn custody_bit(&self, data: &AttestationData, validator_index: u64) -> bool;
+}
+
+/// The phase 0 custody bits, the same for every attester.
+#[cfg(feature = "proof-of-custody")]
+pub struct Phase0Custody;
+
+#[cfg(feature = "proof-of-custody")]
+impl CustodyBitSource for Phase0Custody {
+    fn custody_bit(&self, data: &AttestationData, _validator_index: u64) -> bool {
+        attester_custody_bit(data)
+    }
+}
+
+/// The public keys of the validators whose signatures form an aggregate signature, grouped by the
+/// custody bit each signed, so that the aggregate may be verified.
+pub struct CustodySignatureSet {
+    /// The aggregate public key of the signers of each custody bit, indexed by the bit.
+    pubkeys: [AggregatePublicKey; 2],
+    /// Whether any validator signed each custody bit, indexed by the bit.
+    signed: [bool; 2],
+}
+
+impl Default for CustodySignatureSet {
+    fn default() -> Self {
+        CustodySignatureSet {
+            pubkeys: [AggregatePublicKey::new(), AggregatePublicKey::new()],
+            signed: [false; 2],
+        }
+    }
+}
+
+impl CustodySignatureSet {
+    /// Adds the public key of a validator which signed `custody_bit`.
+    pub fn add(&mut self, custody_bit: bool, pubkey: &PublicKey) {
+        self.pubkeys[custody_bit as usize].add(pubkey);
+        self.signed[custody_bit as usize] = true;
+    }
+
+    /// Returns `true` if `signature` aggregates a signature of `data` and their custody bit by each
+    /// validator added, in the given `domain`.
+    pub fn verify(
+        &self,
+        data: &AttestationData,
+        signature: &AggregateSignature,
+        domain: u64,
+    ) -> bool {
+        let messages: Vec<Vec<u8>> = [false, true]
+            .iter()
+            .filter(|&&custody_bit| self.signed[custody_bit as usize])
+            .map(|&custody_bit| signing_message(data, custody_bit))
+            .collect();
+        let messages: Vec<&[u8]> = messages.iter().map(|message| &message[..]).collect();
+        let keys: Vec<&AggregatePublicKey> = [false, true]
+            .iter()
+            .filter(|&&custody_bit| self.signed[custody_bit as usize])
+            .map(|&custody_bit| &self.pubkeys[custody_bit as usize])
+            .collect();
+
+        signature.verify_multiple(&messages[..], domain, &keys[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Keypair;
+    use bls::Signature;
+
+    #[test]
+    fn verifies_signatures_of_each_custody_bit() {
+        let data = AttestationData::default();
+        let domain = 1;
+        let keypairs: Vec<Keypair> = (0..3).map(|_| Keypair::random()).collect();
+        let custody_bits = [false, true, false];
+
+        let mut signature = AggregateSignature::new();
+        let mut set = CustodySignatureSet::default();
+        for (keypair, &custody_bit) in keypairs.iter().zip(custody_bits.iter()) {
+            let message = signing_message(&data, custody_bit);
+            signature.add(&Signature::new(&message, domain, &keypair.sk));
+            set.add(custody_bit, &keypair.pk);
+        }
+        assert!(set.verify(&data, &signature, domain));
+
+        // a signer claiming the other custody bit invalidates the aggregate
+        let mut wrong_set = CustodySignatureSet::default();
+        for (keypair, &custody_bit) in keypairs.iter().zip(custody_bits.iter()) {
+            wrong_set.add(!custody_bit, &keypair.pk);
+        }
+        assert!(!wrong_set.verify(&data, &signature, domain));
+    }
+
+    #[test]
+    fn phase_0_permits_only_empty_custody_bitfields() {
+        let mut bitfield = Bitfield::with_capacity(8);
+        assert!(is_custody_bitfield_permitted(&bitfield));
+
+        bitfield.set(3, true);
+        assert!(!is_custody_bitfield_permitted(&bitfield));
+    }
+}
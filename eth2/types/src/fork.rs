@@ -1,10 +1,11 @@
 use crate::{
     test_utils::{fork_from_hex_str, TestRandom},
-    ChainSpec, Epoch,
+    ChainSpec, Epoch, Hash256,
 };
 use int_to_bytes::int_to_bytes4;
 use rand::RngCore;
 use serde_derive::{Deserialize, Serialize};
+use ssz::hash;
 use ssz_derive::{Decode, Encode, TreeHash};
 use test_random_derive::TestRandom;
 
@@ -46,6 +47,19 @@ impl Fork {
         }
         self.current_version
     }
+
+    /// Returns the digest identifying the chain of `genesis_validators_root` at fork `version`,
+    /// used by peers to reject each other if they are on different chains or forks.
+    ///
+    /// The digest is the first four bytes of the hash of `version` followed by the root.
+    pub fn compute_digest(version: [u8; 4], genesis_validators_root: Hash256) -> [u8; 4] {
+        let mut preimage = version.to_vec();
+        preimage.extend_from_slice(genesis_validators_root.as_bytes());
+
+        let mut digest = [0; 4];
+        digest.copy_from_slice(&hash(&preimage)[0..4]);
+        digest
+    }
 }
 
 #[cfg(test)]
@@ -98,4 +112,18 @@ mod tests {
         assert_eq!(fork.get_fork_version(epoch), current_version);
         assert_eq!(fork.get_fork_version(epoch + 1), current_version);
     }
+
+    #[test]
+    fn compute_digest() {
+        let version = [1; 4];
+        let root = Hash256::from_low_u64_be(1);
+        let digest = Fork::compute_digest(version, root);
+
+        assert_eq!(Fork::compute_digest(version, root), digest);
+        assert_ne!(Fork::compute_digest([2; 4], root), digest);
+        assert_ne!(
+            Fork::compute_digest(version, Hash256::from_low_u64_be(2)),
+            digest
+        );
+    }
 }
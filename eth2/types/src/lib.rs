@@ -15,6 +15,7 @@ pub mod beacon_state;
 pub mod chain_spec;
 pub mod crosslink;
 pub mod crosslink_committee;
+pub mod custody;
 pub mod deposit;
 pub mod deposit_data;
 pub mod deposit_input;
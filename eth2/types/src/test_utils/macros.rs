@@ -1,34 +1,108 @@
+/// Generates property tests for the SSZ encoding and tree hashing of some type.
+///
+/// Each property is checked against many values, each generated by `TestRandom` from a
+/// `quickcheck`-provided seed.
 #[cfg(test)]
 #[macro_export]
 macro_rules! ssz_tests {
     ($type: ident) => {
+        /// Returns a random instance of the type, generated from the given `seed`.
+        fn random_from_seed(seed: u64) -> $type {
+            use crate::test_utils::{SeedableRng, TestRandom, XorShiftRng};
+
+            let mut seed_bytes = [0; 16];
+            seed_bytes[0..8].copy_from_slice(&seed.to_le_bytes());
+            seed_bytes[8..16].copy_from_slice(&(!seed).to_le_bytes());
+
+            let mut rng = XorShiftRng::from_seed(seed_bytes);
+            $type::random_for_test(&mut rng)
+        }
+
+        /// Runs the property against a modest number of seeds, some types are expensive to build.
+        fn quickcheck_seeds<P: quickcheck::Testable>(property: P) {
+            quickcheck::QuickCheck::new().tests(32).quickcheck(property)
+        }
+
         #[test]
         pub fn test_ssz_round_trip() {
-            use crate::test_utils::{SeedableRng, TestRandom, XorShiftRng};
             use ssz::{ssz_encode, Decodable};
 
-            let mut rng = XorShiftRng::from_seed([42; 16]);
-            let original = $type::random_for_test(&mut rng);
+            fn property(seed: u64) -> bool {
+                let original = random_from_seed(seed);
+
+                let bytes = ssz_encode(&original);
+                match $type::ssz_decode(&bytes, 0) {
+                    Ok((decoded, i)) => decoded == original && i == bytes.len(),
+                    Err(_) => false,
+                }
+            }
+
+            quickcheck_seeds(property as fn(u64) -> bool);
+        }
+
+        #[test]
+        pub fn test_ssz_decode_rejects_truncation() {
+            use ssz::{ssz_encode, Decodable};
+
+            fn property(seed: u64, cut: usize) -> bool {
+                let bytes = ssz_encode(&random_from_seed(seed));
+                if bytes.is_empty() {
+                    return true;
+                }
+                let truncated = &bytes[0..cut % bytes.len()];
+
+                $type::ssz_decode(truncated, 0).is_err()
+            }
+
+            quickcheck_seeds(property as fn(u64, usize) -> bool);
+        }
+
+        #[test]
+        pub fn test_ssz_decode_rejects_mutation() {
+            use ssz::{ssz_encode, Decodable};
+
+            fn property(seed: u64, position: usize, flip: u8) -> bool {
+                let original = random_from_seed(seed);
+                let mut bytes = ssz_encode(&original);
+                if bytes.is_empty() {
+                    return true;
+                }
+                let position = position % bytes.len();
+                bytes[position] ^= flip.max(1);
 
-            let bytes = ssz_encode(&original);
-            let (decoded, _) = $type::ssz_decode(&bytes, 0).unwrap();
+                // Flipping a byte of a field may give the encoding of another valid value, but
+                // never of the original one. Anything which is not a canonical encoding must be
+                // rejected, rather than decoded to a value which encodes to other bytes.
+                match $type::ssz_decode(&bytes, 0) {
+                    Ok((decoded, i)) => {
+                        i <= bytes.len()
+                            && decoded != original
+                            && ssz_encode(&decoded)[..] == bytes[..i]
+                    }
+                    Err(_) => true,
+                }
+            }
 
-            assert_eq!(original, decoded);
+            quickcheck_seeds(property as fn(u64, usize, u8) -> bool);
         }
 
         #[test]
         pub fn test_hash_tree_root() {
-            use crate::test_utils::{SeedableRng, TestRandom, XorShiftRng};
-            use ssz::TreeHash;
+            use ssz::{ssz_encode, Decodable, TreeHash};
+
+            fn property(seed: u64) -> bool {
+                let original = random_from_seed(seed);
+                let root = original.hash_tree_root();
 
-            let mut rng = XorShiftRng::from_seed([42; 16]);
-            let original = $type::random_for_test(&mut rng);
+                let bytes = ssz_encode(&original);
+                let (decoded, _) = $type::ssz_decode(&bytes, 0).unwrap();
 
-            let result = original.hash_tree_root();
+                root.len() == 32
+                    && root == random_from_seed(seed).hash_tree_root()
+                    && root == decoded.hash_tree_root()
+            }
 
-            assert_eq!(result.len(), 32);
-            // TODO: Add further tests
-            // https://github.com/sigp/lighthouse/issues/170
+            quickcheck_seeds(property as fn(u64) -> bool);
         }
     };
 }
@@ -1,6 +1,5 @@
 use crate::test_utils::TestingAttestationDataBuilder;
 use crate::*;
-use ssz::TreeHash;
 
 /// Builds an attestation to be used for testing purposes.
 ///
@@ -70,11 +69,8 @@ impl TestingAttestationBuilder {
                 .aggregation_bitfield
                 .set(committee_index, true);
 
-            let message = AttestationDataAndCustodyBit {
-                data: self.attestation.data.clone(),
-                custody_bit: false,
-            }
-            .hash_tree_root();
+            let message =
+                custody::signing_message(&self.attestation.data, custody::PHASE_0_CUSTODY_BIT);
 
             let domain = spec.get_domain(
                 self.attestation.data.slot.epoch(spec.slots_per_epoch),
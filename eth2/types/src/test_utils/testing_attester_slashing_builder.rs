@@ -1,5 +1,4 @@
 use crate::*;
-use ssz::TreeHash;
 
 /// Builds an `AttesterSlashing`.
 ///
@@ -61,12 +60,8 @@ impl TestingAttesterSlashingBuilder {
         };
 
         let add_signatures = |attestation: &mut SlashableAttestation| {
-            // All validators sign with a `false` custody bit.
-            let attestation_data_and_custody_bit = AttestationDataAndCustodyBit {
-                data: attestation.data.clone(),
-                custody_bit: false,
-            };
-            let message = attestation_data_and_custody_bit.hash_tree_root();
+            // All validators sign with the phase 0 custody bit.
+            let message = custody::signing_message(&attestation.data, custody::PHASE_0_CUSTODY_BIT);
 
             for (i, validator_index) in validator_indices.iter().enumerate() {
                 attestation.custody_bitfield.set(i, false);
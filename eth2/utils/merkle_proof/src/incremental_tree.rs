@@ -0,0 +1,169 @@
+use ethereum_types::H256;
+use hashing::hash;
+
+/// An append-only Merkle tree of fixed depth, as maintained by the deposit contract.
+///
+/// Only the left-most branch of the filled part of the tree is stored, which is sufficient to
+/// append leaves and compute the root. The branch and leaf count form a compact snapshot from
+/// which the tree can be resumed without its leaves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalMerkleTree {
+    depth: usize,
+    /// The root of the last filled subtree at each height, bottom-up.
+    branch: Vec<H256>,
+    /// The root of an empty subtree at each height, bottom-up.
+    zero_hashes: Vec<H256>,
+    len: u64,
+}
+
+impl IncrementalMerkleTree {
+    /// Creates an empty tree of the given depth.
+    pub fn new(depth: usize) -> Self {
+        let mut zero_hashes = vec![H256::zero()];
+        for i in 0..depth {
+            zero_hashes.push(hash_concat(zero_hashes[i], zero_hashes[i]));
+        }
+
+        IncrementalMerkleTree {
+            depth,
+            branch: vec![H256::zero(); depth],
+            zero_hashes,
+            len: 0,
+        }
+    }
+
+    /// Resumes a tree from the branch and leaf count of a snapshot.
+    ///
+    /// Returns `None` if the branch length does not match `depth` or the tree cannot hold `len`
+    /// leaves.
+    pub fn from_snapshot(depth: usize, branch: Vec<H256>, len: u64) -> Option<Self> {
+        if branch.len() != depth || depth < 64 && len > 1 << depth {
+            return None;
+        }
+
+        Some(IncrementalMerkleTree {
+            branch,
+            len,
+            ..IncrementalMerkleTree::new(depth)
+        })
+    }
+
+    /// Appends a leaf, returning `false` if the tree is full.
+    pub fn push(&mut self, leaf: H256) -> bool {
+        if self.depth < 64 && self.len >= 1 << self.depth {
+            return false;
+        }
+
+        self.len += 1;
+        let mut size = self.len;
+        let mut node = leaf;
+        for height in 0..self.depth {
+            if size & 1 == 1 {
+                self.branch[height] = node;
+                return true;
+            }
+            node = hash_concat(self.branch[height], node);
+            size >>= 1;
+        }
+        true
+    }
+
+    /// Returns the root of the tree, with unfilled leaves taken to be zero.
+    pub fn root(&self) -> H256 {
+        let mut size = self.len;
+        let mut node = H256::zero();
+        for height in 0..self.depth {
+            node = if size & 1 == 1 {
+                hash_concat(self.branch[height], node)
+            } else {
+                hash_concat(node, self.zero_hashes[height])
+            };
+            size >>= 1;
+        }
+        node
+    }
+
+    /// Returns the branch of the snapshot of this tree.
+    pub fn branch(&self) -> &[H256] {
+        &self.branch
+    }
+
+    /// Returns the number of leaves in the tree.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the tree has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+fn hash_concat(h1: H256, h2: H256) -> H256 {
+    let mut input = h1.as_bytes().to_vec();
+    input.extend_from_slice(h2.as_bytes());
+    H256::from_slice(&hash(&input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Computes the root of a tree of `depth` from all of its leaves.
+    fn naive_root(leaves: &[H256], depth: usize) -> H256 {
+        let mut nodes = leaves.to_vec();
+        nodes.resize(1 << depth, H256::zero());
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| hash_concat(pair[0], pair[1]))
+                .collect();
+        }
+        nodes[0]
+    }
+
+    fn leaf(i: u8) -> H256 {
+        H256::from([i + 1; 32])
+    }
+
+    #[test]
+    fn root_matches_naive_root() {
+        let depth = 4;
+        let mut tree = IncrementalMerkleTree::new(depth);
+        let mut leaves = vec![];
+        assert_eq!(tree.root(), naive_root(&leaves, depth));
+
+        for i in 0..16 {
+            assert!(tree.push(leaf(i)));
+            leaves.push(leaf(i));
+            assert_eq!(tree.root(), naive_root(&leaves, depth));
+        }
+        assert!(!tree.push(leaf(16)));
+    }
+
+    #[test]
+    fn resumes_from_snapshot() {
+        let depth = 8;
+        let mut tree = IncrementalMerkleTree::new(depth);
+        for i in 0..5 {
+            tree.push(leaf(i));
+        }
+
+        let mut resumed =
+            IncrementalMerkleTree::from_snapshot(depth, tree.branch().to_vec(), tree.len())
+                .unwrap();
+        assert_eq!(resumed.root(), tree.root());
+
+        for i in 5..9 {
+            tree.push(leaf(i));
+            resumed.push(leaf(i));
+        }
+        assert_eq!(resumed.root(), tree.root());
+    }
+
+    #[test]
+    fn rejects_invalid_snapshot() {
+        assert!(IncrementalMerkleTree::from_snapshot(4, vec![H256::zero(); 3], 1).is_none());
+        assert!(IncrementalMerkleTree::from_snapshot(4, vec![H256::zero(); 4], 17).is_none());
+    }
+}
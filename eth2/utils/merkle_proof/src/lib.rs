@@ -1,6 +1,10 @@
+mod incremental_tree;
+
 use ethereum_types::H256;
 use hashing::hash;
 
+pub use incremental_tree::IncrementalMerkleTree;
+
 /// Verify a proof that `leaf` exists at `index` in a Merkle tree rooted at `root`.
 ///
 /// The `branch` argument is the main component of the proof: it should be a list of internal
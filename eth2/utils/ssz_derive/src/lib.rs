@@ -8,7 +8,8 @@
 //! order they are defined.
 //!
 //! Presently, only `structs` with named fields are supported. `enum`s and tuple-structs are
-//! unsupported.
+//! unsupported, with the exception of `#[derive(TreeHash)]` which also supports `enum`s with unit
+//! or named-field variants.
 //!
 //! Example:
 //! ```
@@ -189,30 +190,6 @@ pub fn ssz_decode_derive(input: TokenStream) -> TokenStream {
     output.into()
 }
 
-/// Returns a Vec of `syn::Ident` for each named field in the struct, whilst filtering out fields
-/// that should not be tree hashed.
-///
-/// # Panics
-/// Any unnamed struct field (like in a tuple struct) will raise a panic at compile time.
-fn get_tree_hashable_named_field_idents<'a>(
-    struct_data: &'a syn::DataStruct,
-) -> Vec<&'a syn::Ident> {
-    struct_data
-        .fields
-        .iter()
-        .filter_map(|f| {
-            if should_skip_tree_hash(&f) {
-                None
-            } else {
-                Some(match &f.ident {
-                    Some(ref ident) => ident,
-                    _ => panic!("ssz_derive only supports named struct fields."),
-                })
-            }
-        })
-        .collect()
-}
-
 /// Returns true if some field has an attribute declaring it should not be tree-hashed.
 ///
 /// The field attribute is: `#[tree_hash(skip_hashing)]`
@@ -225,31 +202,190 @@ fn should_skip_tree_hash(field: &syn::Field) -> bool {
     false
 }
 
-/// Implements `ssz::TreeHash` for some `struct`.
+/// Returns the `hash_tree_root` expressions for each tree-hashable field of some named fields,
+/// where `accessor` produces an expression referencing each field.
+fn tree_hash_field_roots<F>(fields: &syn::FieldsNamed, accessor: F) -> Vec<proc_macro2::TokenStream>
+where
+    F: Fn(&syn::Ident) -> proc_macro2::TokenStream,
+{
+    fields
+        .named
+        .iter()
+        .filter(|f| !should_skip_tree_hash(f))
+        .map(|f| {
+            let field = accessor(f.ident.as_ref().expect("named fields have idents"));
+            quote! { ssz::TreeHash::hash_tree_root(#field) }
+        })
+        .collect()
+}
+
+/// Returns the generics of the item with a `ssz::TreeHash` bound added to each type parameter.
+fn add_tree_hash_bounds(mut generics: syn::Generics) -> syn::Generics {
+    for param in &mut generics.params {
+        if let syn::GenericParam::Type(ref mut type_param) = *param {
+            type_param.bounds.push(syn::parse_quote!(ssz::TreeHash));
+        }
+    }
+    generics
+}
+
+/// Builds the body of `hash_tree_root` for a `struct`.
 ///
-/// Fields are processed in the order they are defined.
+/// Only structs with named fields are supported.
+fn tree_hash_struct_body(
+    item: &DeriveInput,
+    struct_data: &syn::DataStruct,
+) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let fields = match &struct_data.fields {
+        syn::Fields::Named(fields) => fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &item.ident,
+                "TreeHash can only be derived for structs with named fields.",
+            ));
+        }
+    };
+
+    let roots = tree_hash_field_roots(fields, |ident| quote! { &self.#ident });
+
+    Ok(quote! {
+        let mut list: Vec<Vec<u8>> = Vec::new();
+        #(
+            list.push(#roots);
+        )*
+
+        ssz::merkle_hash(&mut list)
+    })
+}
+
+/// Builds the body of `hash_tree_root` for an `enum`.
+///
+/// Each variant is hashed as a container whose first element is the `u32` index of the variant,
+/// followed by the tree hash of each of the variant's fields. Only unit variants and variants with
+/// named fields are supported.
+fn tree_hash_enum_body(
+    item: &DeriveInput,
+    enum_data: &syn::DataEnum,
+) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let name = &item.ident;
+
+    if enum_data.variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            name,
+            "TreeHash cannot be derived for an enum without variants.",
+        ));
+    }
+
+    let mut arms = vec![];
+    for (index, variant) in enum_data.variants.iter().enumerate() {
+        let variant_ident = &variant.ident;
+        let index = index as u32;
+
+        let arm = match &variant.fields {
+            syn::Fields::Unit => quote! {
+                #name::#variant_ident => {
+                    let mut list: Vec<Vec<u8>> = vec![ssz::TreeHash::hash_tree_root(&#index)];
+                    ssz::merkle_hash(&mut list)
+                }
+            },
+            syn::Fields::Named(fields) => {
+                let idents: Vec<&syn::Ident> = fields
+                    .named
+                    .iter()
+                    .filter(|f| !should_skip_tree_hash(f))
+                    .map(|f| f.ident.as_ref().expect("named fields have idents"))
+                    .collect();
+                let roots = tree_hash_field_roots(fields, |ident| quote! { #ident });
+
+                quote! {
+                    #name::#variant_ident { #( ref #idents, )* .. } => {
+                        let mut list: Vec<Vec<u8>> = vec![ssz::TreeHash::hash_tree_root(&#index)];
+                        #(
+                            list.push(#roots);
+                        )*
+                        ssz::merkle_hash(&mut list)
+                    }
+                }
+            }
+            syn::Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "TreeHash can only be derived for enum variants that are units or have named \
+                     fields.",
+                ));
+            }
+        };
+        arms.push(arm);
+    }
+
+    Ok(quote! {
+        match *self {
+            #(
+                #arms
+            )*
+        }
+    })
+}
+
+/// Builds the body of `hash_tree_root` for some `struct` or `enum`.
+fn tree_hash_body(item: &DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    match &item.data {
+        syn::Data::Struct(s) => tree_hash_struct_body(item, s),
+        syn::Data::Enum(e) => tree_hash_enum_body(item, e),
+        syn::Data::Union(_) => Err(syn::Error::new_spanned(
+            &item.ident,
+            "TreeHash cannot be derived for unions.",
+        )),
+    }
+}
+
+/// Implements `ssz::TreeHash` for some `struct` or `enum`.
+///
+/// Struct fields are processed in the order they are defined. See `tree_hash_enum_body` for the
+/// handling of `enum`s. Any type parameters are required to implement `ssz::TreeHash`.
+///
+/// Tuple-structs, unions and enum variants with unnamed fields are rejected with a compile error.
+/// The messages of these errors are checked by the unit tests of `tree_hash_body`.
+///
+/// ```compile_fail
+/// use ssz_derive::TreeHash;
+///
+/// #[derive(TreeHash)]
+/// struct Tuple(u64, u64);
+/// ```
+///
+/// ```compile_fail
+/// use ssz_derive::TreeHash;
+///
+/// #[derive(TreeHash)]
+/// enum Unnamed {
+///     A(u64),
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use ssz_derive::TreeHash;
+///
+/// #[derive(TreeHash)]
+/// enum Empty {}
+/// ```
 #[proc_macro_derive(TreeHash, attributes(tree_hash))]
 pub fn ssz_tree_hash_derive(input: TokenStream) -> TokenStream {
     let item = parse_macro_input!(input as DeriveInput);
 
-    let name = &item.ident;
-
-    let struct_data = match &item.data {
-        syn::Data::Struct(s) => s,
-        _ => panic!("ssz_derive only supports structs."),
+    let body = match tree_hash_body(&item) {
+        Ok(body) => body,
+        Err(e) => return e.to_compile_error().into(),
     };
 
-    let field_idents = get_tree_hashable_named_field_idents(&struct_data);
+    let name = &item.ident;
+    let generics = add_tree_hash_bounds(item.generics.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let output = quote! {
-        impl ssz::TreeHash for #name {
+        impl #impl_generics ssz::TreeHash for #name #ty_generics #where_clause {
             fn hash_tree_root(&self) -> Vec<u8> {
-                let mut list: Vec<Vec<u8>> = Vec::new();
-                #(
-                    list.push(self.#field_idents.hash_tree_root());
-                )*
-
-                ssz::merkle_hash(&mut list)
+                #body
             }
         }
     };
@@ -330,3 +466,72 @@ pub fn ssz_signed_root_derive(input: TokenStream) -> TokenStream {
     };
     output.into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_hash_error(item: DeriveInput) -> String {
+        match tree_hash_body(&item) {
+            Ok(_) => panic!("TreeHash derived for {}", item.ident),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn rejects_tuple_structs() {
+        assert_eq!(
+            tree_hash_error(syn::parse_quote! {
+                struct Tuple(u64, u64);
+            }),
+            "TreeHash can only be derived for structs with named fields."
+        );
+    }
+
+    #[test]
+    fn rejects_unions() {
+        assert_eq!(
+            tree_hash_error(syn::parse_quote! {
+                union Union {
+                    a: u64,
+                    b: u32,
+                }
+            }),
+            "TreeHash cannot be derived for unions."
+        );
+    }
+
+    #[test]
+    fn rejects_enum_variants_with_unnamed_fields() {
+        assert_eq!(
+            tree_hash_error(syn::parse_quote! {
+                enum Unnamed {
+                    A { a: u64 },
+                    B(u64),
+                }
+            }),
+            "TreeHash can only be derived for enum variants that are units or have named fields."
+        );
+    }
+
+    #[test]
+    fn rejects_enums_without_variants() {
+        assert_eq!(
+            tree_hash_error(syn::parse_quote! {
+                enum Empty {}
+            }),
+            "TreeHash cannot be derived for an enum without variants."
+        );
+    }
+
+    #[test]
+    fn accepts_unit_and_named_variants() {
+        let item: DeriveInput = syn::parse_quote! {
+            enum Operation<T> {
+                Empty,
+                Transfer { amount: u64, inner: Vec<T> },
+            }
+        };
+        assert!(tree_hash_body(&item).is_ok());
+    }
+}
@@ -0,0 +1,83 @@
+use ssz::{merkle_hash, TreeHash};
+use ssz_derive::TreeHash;
+
+#[derive(TreeHash)]
+struct Inner {
+    a: u64,
+    b: bool,
+}
+
+#[derive(TreeHash)]
+struct Nested<T> {
+    items: Vec<Vec<T>>,
+    inner: Inner,
+}
+
+#[derive(TreeHash)]
+enum Operation {
+    Empty,
+    Transfer {
+        amount: u64,
+        #[tree_hash(skip_hashing)]
+        memo: u64,
+    },
+    Wrapped {
+        inner: Nested<u32>,
+    },
+}
+
+fn inner() -> Inner {
+    Inner { a: 42, b: true }
+}
+
+#[test]
+fn struct_matches_manual_merkle_hash() {
+    let inner = inner();
+
+    let mut list = vec![inner.a.hash_tree_root(), inner.b.hash_tree_root()];
+
+    assert_eq!(inner.hash_tree_root(), merkle_hash(&mut list));
+}
+
+#[test]
+fn nested_generic_container() {
+    let nested = Nested {
+        items: vec![vec![1_u32, 2], vec![3]],
+        inner: inner(),
+    };
+
+    let mut list = vec![nested.items.hash_tree_root(), nested.inner.hash_tree_root()];
+
+    assert_eq!(nested.hash_tree_root(), merkle_hash(&mut list));
+}
+
+#[test]
+fn enum_variants_include_index() {
+    let mut list = vec![0_u32.hash_tree_root()];
+    assert_eq!(Operation::Empty.hash_tree_root(), merkle_hash(&mut list));
+
+    let transfer = Operation::Transfer { amount: 7, memo: 1 };
+    let mut list = vec![1_u32.hash_tree_root(), 7_u64.hash_tree_root()];
+    assert_eq!(transfer.hash_tree_root(), merkle_hash(&mut list));
+}
+
+#[test]
+fn enum_skips_fields() {
+    let a = Operation::Transfer { amount: 7, memo: 1 };
+    let b = Operation::Transfer { amount: 7, memo: 2 };
+
+    assert_eq!(a.hash_tree_root(), b.hash_tree_root());
+}
+
+#[test]
+fn enum_variants_differ() {
+    let empty = Operation::Empty;
+    let wrapped = Operation::Wrapped {
+        inner: Nested {
+            items: vec![],
+            inner: inner(),
+        },
+    };
+
+    assert_ne!(empty.hash_tree_root(), wrapped.hash_tree_root());
+}
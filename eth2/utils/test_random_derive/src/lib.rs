@@ -17,6 +17,34 @@ fn should_use_default(field: &syn::Field) -> bool {
     false
 }
 
+/// Returns the number of elements that should be generated for some collection field, if the field
+/// has an attribute declaring it.
+///
+/// The field attribute is: `#[test_random(size = N)]`
+///
+/// # Panics
+/// If the `size` is not an integer literal.
+fn collection_size(field: &syn::Field) -> Option<u64> {
+    for attr in &field.attrs {
+        if !attr.path.is_ident("test_random") {
+            continue;
+        }
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested.iter() {
+                if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested {
+                    if name_value.ident == "size" {
+                        match &name_value.lit {
+                            syn::Lit::Int(int) => return Some(int.value()),
+                            _ => panic!("test_random size must be an integer literal."),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
 #[proc_macro_derive(TestRandom, attributes(test_random))]
 pub fn test_random_derive(input: TokenStream) -> TokenStream {
     let derived_input = parse_macro_input!(input as DeriveInput);
@@ -37,6 +65,11 @@ pub fn test_random_derive(input: TokenStream) -> TokenStream {
                     quotes.push(quote! {
                         #ident: <_>::default(),
                     });
+                } else if let Some(size) = collection_size(field) {
+                    let size = size as usize;
+                    quotes.push(quote! {
+                        #ident: (0..#size).map(|_| <_>::random_for_test(rng)).collect(),
+                    });
                 } else {
                     quotes.push(quote! {
                         #ident: <_>::random_for_test(rng),
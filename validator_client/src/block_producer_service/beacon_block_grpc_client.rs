@@ -85,6 +85,7 @@ impl BeaconNode for BeaconBlockGrpcClient {
         grpc_block.set_block_root(vec![0]);
         grpc_block.set_randao_reveal(ssz_encode(&block.body.randao_reveal));
         grpc_block.set_signature(ssz_encode(&block.signature));
+        grpc_block.set_ssz(ssz_encode(&block));
 
         req.set_block(grpc_block);
 
@@ -6,7 +6,12 @@ use types::ChainSpec;
 #[derive(Clone)]
 pub struct ClientConfig {
     pub data_dir: PathBuf,
+    /// The beacon node used for all requests, unless overridden below.
     pub server: String,
+    /// The beacon node blocks are produced by and published to, if not `server`.
+    pub proposer_server: Option<String>,
+    /// The beacon node duties are queried from and attestations published to, if not `server`.
+    pub attester_server: Option<String>,
     pub spec: ChainSpec,
 }
 
@@ -26,7 +31,19 @@ impl ClientConfig {
         Self {
             data_dir,
             server,
+            proposer_server: None,
+            attester_server: None,
             spec,
         }
     }
+
+    /// The beacon node to produce and publish blocks with.
+    pub fn proposer_server(&self) -> &str {
+        self.proposer_server.as_ref().unwrap_or(&self.server)
+    }
+
+    /// The beacon node to query duties from and publish attestations to.
+    pub fn attester_server(&self) -> &str {
+        self.attester_server.as_ref().unwrap_or(&self.server)
+    }
 }
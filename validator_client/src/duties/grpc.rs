@@ -1,5 +1,6 @@
 use super::traits::{BeaconNode, BeaconNodeError};
 use super::EpochDuties;
+use grpcio::{Error as GrpcError, RpcStatusCode};
 use protos::services::{ProposeBlockSlotRequest, PublicKey as IndexRequest};
 use protos::services_grpc::ValidatorServiceClient;
 use ssz::ssz_encode;
@@ -21,10 +22,16 @@ impl BeaconNode for ValidatorServiceClient {
         let validator_index = {
             let mut req = IndexRequest::new();
             req.set_public_key(ssz_encode(public_key).to_vec());
-            let resp = self
-                .validator_index(&req)
-                .map_err(|err| BeaconNodeError::RemoteFailure(format!("{:?}", err)))?;
-            resp.get_index()
+            match self.validator_index(&req) {
+                Ok(resp) => resp.get_index(),
+                // the validator is not yet in the registry
+                Err(GrpcError::RpcFailure(ref status))
+                    if status.status == RpcStatusCode::NotFound =>
+                {
+                    return Ok(None)
+                }
+                Err(err) => return Err(BeaconNodeError::RemoteFailure(format!("{:?}", err))),
+            }
         };
 
         let mut req = ProposeBlockSlotRequest::new();
@@ -43,6 +43,20 @@ fn main() {
                 .help("Address to connect to BeaconNode.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("proposer-server")
+                .long("proposer-server")
+                .value_name("HOST:PORT")
+                .help("Beacon node to produce and publish blocks with, if not the --server.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("attester-server")
+                .long("attester-server")
+                .value_name("HOST:PORT")
+                .help("Beacon node to query duties from and publish attestations to, if not the --server.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("spec")
                 .long("spec")
@@ -72,6 +86,14 @@ fn main() {
         }
     }
 
+    // Beacon nodes for each role
+    if let Some(server_str) = matches.value_of("proposer-server") {
+        config.proposer_server = Some(server_str.to_string());
+    }
+    if let Some(server_str) = matches.value_of("attester-server") {
+        config.attester_server = Some(server_str.to_string());
+    }
+
     // TODO: Permit loading a custom spec from file.
     // Custom spec
     if let Some(spec_str) = matches.value_of("spec") {
@@ -86,19 +108,20 @@ fn main() {
     // Log configuration
     info!(log, "";
           "data_dir" => &config.data_dir.to_str(),
-          "server" => &config.server);
+          "proposer_server" => config.proposer_server(),
+          "attester_server" => config.attester_server());
 
     // Beacon node gRPC beacon block endpoints.
     let beacon_block_grpc_client = {
         let env = Arc::new(EnvBuilder::new().build());
-        let ch = ChannelBuilder::new(env).connect(&config.server);
+        let ch = ChannelBuilder::new(env).connect(config.proposer_server());
         Arc::new(BeaconBlockServiceClient::new(ch))
     };
 
     // Beacon node gRPC validator endpoints.
     let validator_grpc_client = {
         let env = Arc::new(EnvBuilder::new().build());
-        let ch = ChannelBuilder::new(env).connect(&config.server);
+        let ch = ChannelBuilder::new(env).connect(config.attester_server());
         Arc::new(ValidatorServiceClient::new(ch))
     };
 